@@ -0,0 +1,108 @@
+//Redraw costs at 1280x720, 1920x1080 and 3840x2160, into a mapped file like the shm buffer.
+//Plain timing, no bench framework: cargo bench --bench canvas
+//
+//  write()    the gradient the way draw() first did it: a BufWriter over the file, one write per
+//             pixel, four divisions per pixel.
+//  per-pixel  the same math through Canvas::put_pixel.
+//  gradient   Canvas::gradient, with its per-row and per-column tables.
+//  clear      Canvas::clear, a full-window fill.
+
+use std::{
+    cmp::min,
+    fs::File,
+    hint::black_box,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    ptr, slice,
+    time::{Duration, Instant},
+};
+
+use rustix::mm::{MapFlags, ProtFlags, mmap, munmap};
+use simple_wayland_window::{Canvas, Color, GradientView};
+
+const SIZES: [(u32, u32); 3] = [(1280, 720), (1920, 1080), (3840, 2160)];
+const RUNS: u32 = 20;
+
+fn gradient(x: u32, y: u32, (width, height): (u32, u32)) -> Color {
+    let r = min(((width - x) * 0xFF) / width, ((height - y) * 0xFF) / height);
+    let g = min((x * 0xFF) / width, ((height - y) * 0xFF) / height);
+    let b = min(((width - x) * 0xFF) / width, (y * 0xFF) / height);
+    Color::opaque(r as u8, g as u8, b as u8)
+}
+
+fn write_path(file: &mut File, (width, height): (u32, u32)) {
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut buf = BufWriter::new(file);
+    for y in 0..height {
+        for x in 0..width {
+            buf.write_all(&gradient(x, y, (width, height)).to_argb8888_bytes())
+                .unwrap();
+        }
+    }
+    buf.flush().unwrap();
+}
+
+fn canvas(pixels: &mut [u8], (width, height): (u32, u32)) -> Canvas<'_> {
+    Canvas::from_bytes(pixels, width, height, width as usize * 4).unwrap()
+}
+
+fn per_pixel(pixels: &mut [u8], (width, height): (u32, u32)) {
+    let mut canvas = canvas(pixels, (width, height));
+    for y in 0..height {
+        for x in 0..width {
+            canvas.put_pixel(x, y, gradient(x, y, (width, height)));
+        }
+    }
+}
+
+fn time(name: &str, (width, height): (u32, u32), mut run: impl FnMut()) {
+    run();
+    let mut best = Duration::MAX;
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        run();
+        let elapsed = start.elapsed();
+        best = best.min(elapsed);
+        total += elapsed;
+    }
+    println!(
+        "{name:>9} {width}x{height}: best {best:.2?}, mean {:.2?}",
+        total / RUNS
+    );
+}
+
+fn main() {
+    for size in SIZES {
+        let len = (size.0 * size.1 * 4) as usize;
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.set_len(len as u64).unwrap();
+        time("write()", size, || write_path(black_box(&mut file), size));
+
+        let mapped = tempfile::tempfile().unwrap();
+        mapped.set_len(len as u64).unwrap();
+        //SAFETY: a shared mapping of a file sized to `len`, unmapped at the end.
+        unsafe {
+            let data = mmap(
+                ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED,
+                &mapped,
+                0,
+            )
+            .unwrap();
+            let pixels = slice::from_raw_parts_mut(data.cast::<u8>(), len);
+
+            time("per-pixel", size, || per_pixel(black_box(pixels), size));
+            time("gradient", size, || {
+                canvas(black_box(pixels), size).gradient(&GradientView::default())
+            });
+            time("clear", size, || {
+                canvas(black_box(pixels), size).clear(Color::opaque(0x20, 0x40, 0x60))
+            });
+            munmap(data, len).unwrap();
+        }
+        println!();
+    }
+}
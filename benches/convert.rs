@@ -0,0 +1,64 @@
+//RGBA to wl_shm conversion of a 1920x1080 picture, per format, straight and premultiplied.
+//Plain timing, no bench framework: cargo bench --bench convert
+//
+//  per-byte  the shuffle done a byte at a time, for comparison.
+//  <format>  convert_rgba_to, a u32 word per pixel.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use simple_wayland_window::convert_rgba_to;
+use wayland_client::protocol::wl_shm::Format;
+
+const SIZE: (usize, usize) = (1920, 1080);
+const RUNS: u32 = 20;
+const FORMATS: [(&str, Format, usize); 4] = [
+    ("argb8888", Format::Argb8888, 4),
+    ("xrgb8888", Format::Xrgb8888, 4),
+    ("abgr8888", Format::Abgr8888, 4),
+    ("rgb565", Format::Rgb565, 2),
+];
+
+fn per_byte(src: &[u8], dst: &mut [u8]) {
+    for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        dst[0] = src[2];
+        dst[1] = src[1];
+        dst[2] = src[0];
+        dst[3] = src[3];
+    }
+}
+
+fn time(name: &str, mut run: impl FnMut()) {
+    run();
+    let mut best = Duration::MAX;
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        run();
+        let elapsed = start.elapsed();
+        best = best.min(elapsed);
+        total += elapsed;
+    }
+    println!("{name:>21}: best {best:.2?}, mean {:.2?}", total / RUNS);
+}
+
+fn main() {
+    let pixels = SIZE.0 * SIZE.1;
+    let src: Vec<u8> = (0..pixels * 4).map(|i| (i * 7) as u8).collect();
+    let mut dst = vec![0; pixels * 4];
+
+    time("per-byte", || {
+        per_byte(black_box(&src), black_box(&mut dst))
+    });
+    for (name, format, bytes_per_pixel) in FORMATS {
+        let dst = &mut dst[..pixels * bytes_per_pixel];
+        for premultiply in [false, true] {
+            let label = format!("{name}{}", if premultiply { " premultiplied" } else { "" });
+            time(&label, || {
+                convert_rgba_to(format, black_box(&src), black_box(dst), premultiply).unwrap()
+            });
+        }
+    }
+}
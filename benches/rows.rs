@@ -0,0 +1,119 @@
+//A frame where 5% of the rows change, at 3840x2160 and 7680x4320, with two buffers taking turns
+//like the swapchain's. Plain timing, no bench framework: cargo bench --bench rows
+//
+//  full  what Window::draw costs: the whole buffer cleared, every row generated again.
+//  rows  what Window::draw_rows costs: the rows the buffer missed (the other buffer's frame)
+//        copied over from the buffer on screen, and the changed rows generated.
+//
+//Bytes touched per frame are printed with each, the time is mostly them.
+
+use std::{
+    hint::black_box,
+    ptr, slice,
+    time::{Duration, Instant},
+};
+
+use rustix::mm::{MapFlags, ProtFlags, mmap, munmap};
+use simple_wayland_window::{Canvas, Color};
+
+const SIZES: [(u32, u32); 2] = [(3840, 2160), (7680, 4320)];
+const RUNS: u32 = 20;
+//One row in twenty.
+const CHANGED_EVERY: u32 = 20;
+
+//Stands in for the application's content: a row of one color.
+fn generate(y: u32, row: &mut [u8]) {
+    let value = (y % 0xFF) as u8;
+    for pixel in row.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&[value, value, value, 0xFF]);
+    }
+}
+
+//Rows changed in `frame`, a different set each time.
+fn changed(frame: u32, height: u32) -> impl Iterator<Item = u32> {
+    (frame % CHANGED_EVERY..height).step_by(CHANGED_EVERY as usize)
+}
+
+fn full(pixels: &mut [u8], (width, height): (u32, u32)) {
+    let mut canvas = Canvas::from_bytes(pixels, width, height, width as usize * 4).unwrap();
+    canvas.clear(Color::TRANSPARENT);
+    for y in 0..height {
+        generate(y, canvas.row_mut(y).unwrap());
+    }
+}
+
+//`back` is two frames behind, `front` on screen holds the frame before this one.
+fn rows(back: &mut [u8], front: &[u8], frame: u32, (width, height): (u32, u32)) {
+    let row_len = width as usize * 4;
+    for y in changed(frame.wrapping_sub(1), height) {
+        let start = y as usize * row_len;
+        back[start..start + row_len].copy_from_slice(&front[start..start + row_len]);
+    }
+    for y in changed(frame, height) {
+        let start = y as usize * row_len;
+        generate(y, &mut back[start..start + row_len]);
+    }
+}
+
+fn time(name: &str, (width, height): (u32, u32), bytes: usize, mut run: impl FnMut(u32)) {
+    run(0);
+    let mut best = Duration::MAX;
+    let mut total = Duration::ZERO;
+    for frame in 1..=RUNS {
+        let start = Instant::now();
+        run(frame);
+        let elapsed = start.elapsed();
+        best = best.min(elapsed);
+        total += elapsed;
+    }
+    println!(
+        "{name:>4} {width}x{height}: best {best:.2?}, mean {:.2?}, {:.1} MB touched",
+        total / RUNS,
+        bytes as f64 / 1e6
+    );
+}
+
+fn main() {
+    for size in SIZES {
+        let len = (size.0 * size.1 * 4) as usize;
+        let row_len = size.0 as usize * 4;
+        let changed_rows = changed(0, size.1).count();
+
+        let files = [tempfile::tempfile().unwrap(), tempfile::tempfile().unwrap()];
+        //SAFETY: shared mappings of files sized to `len`, unmapped at the end.
+        unsafe {
+            let mut data = Vec::new();
+            for file in &files {
+                file.set_len(len as u64).unwrap();
+                data.push(
+                    mmap(
+                        ptr::null_mut(),
+                        len,
+                        ProtFlags::READ | ProtFlags::WRITE,
+                        MapFlags::SHARED,
+                        file,
+                        0,
+                    )
+                    .unwrap(),
+                );
+            }
+            //Each frame takes the two apart, never two slices of one mapping at once.
+            let buffer =
+                |index: u32| slice::from_raw_parts_mut(data[index as usize].cast::<u8>(), len);
+
+            //Cleared, then written.
+            time("full", size, len * 2, |frame| {
+                full(black_box(buffer(frame % 2)), size)
+            });
+            //Read and written for the missed rows, written for the changed ones.
+            time("rows", size, changed_rows * row_len * 3, |frame| {
+                let (back, front) = (buffer(frame % 2), buffer(1 - frame % 2));
+                rows(black_box(back), front, frame, size)
+            });
+            for data in data {
+                munmap(data, len).unwrap();
+            }
+        }
+        println!();
+    }
+}
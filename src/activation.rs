@@ -0,0 +1,264 @@
+//xdg-activation, the launcher's half: a token naming our surface and the input event that asked
+//for a program, handed to that program in XDG_ACTIVATION_TOKEN. The program passes it back to the
+//compositor with its first window, which then gets the focus instead of opening behind ours.
+//Terminals and launchers built on this want it for everything they start.
+//
+//Quoting documentation: "Some compositors might refuse to activate toplevels when the token
+//doesn't have a valid and recent enough event serial." and the same about the requesting
+//surface, so both go in when we have them.
+//
+//The token comes with a Done event after the commit, so spawn_with_activation waits for it
+//(bounded, the child starts without it when it's late) and spawn_with_activation_then doesn't,
+//its callback runs when the token is there.
+
+use std::{
+    process::{Child, Command},
+    time::{Duration, Instant},
+};
+
+use rustix::event::{PollFd, Timespec, poll};
+use wayland_client::{
+    Connection, Dispatch, QueueHandle, delegate_noop, protocol::wl_surface::WlSurface,
+};
+use wayland_protocols::xdg::activation::v1::client::{
+    xdg_activation_token_v1::{self, XdgActivationTokenV1},
+    xdg_activation_v1::XdgActivationV1,
+};
+
+use crate::{AppState, SerialKind, Window, WindowError};
+
+//How long spawn_with_activation waits for the token. Compositors answer right away, this is for
+//one that doesn't.
+const TOKEN_TIMEOUT: Duration = Duration::from_millis(500);
+
+const TOKEN_VARIABLE: &str = "XDG_ACTIVATION_TOKEN";
+
+//A program started with spawn_with_activation. `token` is what went into its
+//XDG_ACTIVATION_TOKEN, or why it started without one (the compositor lacking xdg_activation_v1,
+//or not answering in time).
+#[derive(Debug)]
+pub struct Spawned {
+    pub child: Child,
+    pub token: Result<String, WindowError>,
+}
+
+type SpawnCallback = Box<dyn FnOnce(&mut Window, Result<Spawned, WindowError>) + Send>;
+
+//A token asked for and not handed out yet.
+struct PendingToken {
+    object: XdgActivationTokenV1,
+    token: Option<String>,
+    //spawn_with_activation_then's, None for spawn_with_activation, which waits on the spot.
+    spawn: Option<(Command, SpawnCallback)>,
+    //Utility windows to raise with it, see utility.rs.
+    raise: Vec<WlSurface>,
+}
+
+#[derive(Default)]
+pub(crate) struct ActivationState {
+    pub(crate) manager: Option<XdgActivationV1>,
+    pending: Vec<PendingToken>,
+}
+
+impl AppState {
+    //Asks for a token with the newest input serial and our surface. None without the global.
+    fn request_activation_token(
+        &mut self,
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Option<XdgActivationTokenV1> {
+        let manager = self.activation.manager.as_ref()?;
+        let object = manager.get_activation_token(queue_handle, ());
+        //Quoting documentation: "The serial can come from an input or focus event."
+        let kinds = [
+            SerialKind::KeyPress,
+            SerialKind::PointerButton,
+            SerialKind::KeyboardEnter,
+            SerialKind::PointerEnter,
+        ];
+        if let Some((seat, serial)) = self.latest_serial(&kinds, "xdg_activation_token_v1") {
+            object.set_serial(serial, &seat);
+        }
+        if let Some(ref surface) = self.base_surface {
+            object.set_surface(surface);
+        }
+        object.commit();
+        Some(object)
+    }
+
+    fn take_activation_token(&mut self, object: &XdgActivationTokenV1) -> Option<String> {
+        let pending = &mut self.activation.pending;
+        let index = pending
+            .iter()
+            .position(|p| &p.object == object && p.token.is_some())?;
+        pending.remove(index).token
+    }
+
+    //Asks for a token to activate `surfaces` with, our own windows' (utility.rs). False without
+    //xdg_activation_v1.
+    pub(crate) fn activate_surfaces(
+        &mut self,
+        surfaces: Vec<WlSurface>,
+        queue_handle: &QueueHandle<AppState>,
+    ) -> bool {
+        let Some(object) = self.request_activation_token(queue_handle) else {
+            return false;
+        };
+        self.activation.pending.push(PendingToken {
+            object,
+            token: None,
+            spawn: None,
+            raise: surfaces,
+        });
+        true
+    }
+
+    //The window is going, see cleanup.rs. Callbacks still waiting never run. Those with a token
+    //destroyed their object at Done.
+    pub(crate) fn release_activation(&mut self) {
+        for pending in self.activation.pending.drain(..) {
+            if pending.token.is_none() {
+                pending.object.destroy();
+            }
+        }
+        if let Some(manager) = self.activation.manager.take() {
+            manager.destroy();
+        }
+    }
+}
+
+//Starts `command` with the token, or without one. A token we got when we were started ourselves
+//is spent, it never goes down to the child.
+fn spawn(mut command: Command, token: Result<String, WindowError>) -> Result<Spawned, WindowError> {
+    match token {
+        Ok(ref token) => command.env(TOKEN_VARIABLE, token),
+        Err(_) => command.env_remove(TOKEN_VARIABLE),
+    };
+    let child = command
+        .spawn()
+        .map_err(|err| WindowError::Spawn(format!("{:?}: {err}", command.get_program())))?;
+    Ok(Spawned { child, token })
+}
+
+impl Window {
+    //Starts `command` with a fresh activation token in XDG_ACTIVATION_TOKEN, so the program's
+    //window comes up focused. Waits for the token a little (half a second at most); the program
+    //starts without it when there's none, see Spawned::token. Err only when it couldn't be
+    //started.
+    pub fn spawn_with_activation(&mut self, command: Command) -> Result<Spawned, WindowError> {
+        let queue_handle = self.event_queue.handle();
+        let Some(object) = self.state.request_activation_token(&queue_handle) else {
+            return spawn(command, Err(WindowError::Unsupported("xdg_activation_v1")));
+        };
+        self.state.activation.pending.push(PendingToken {
+            object: object.clone(),
+            token: None,
+            spawn: None,
+            raise: Vec::new(),
+        });
+
+        self.cancel_read();
+        let deadline = Instant::now() + TOKEN_TIMEOUT;
+        let token = loop {
+            self.dispatch_queued();
+            if let Some(token) = self.state.take_activation_token(&object) {
+                break Ok(token);
+            }
+            if self.state.connection_lost {
+                break Err(WindowError::Disconnected);
+            }
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                break Err(WindowError::Connection(
+                    "no activation token in time".into(),
+                ));
+            };
+            self.send_requests();
+            if let Some(guard) = self.event_queue.prepare_read() {
+                let mut fds = [PollFd::from_borrowed_fd(
+                    guard.connection_fd(),
+                    self.connection_interest(),
+                )];
+                let timeout = Timespec::try_from(left).ok();
+                //EINTR (a signal) is just an early wakeup.
+                let _ = poll(&mut fds, timeout.as_ref());
+                if !fds[0].revents().is_empty() {
+                    let _ = guard.read();
+                }
+            }
+        };
+        //Too late, the Done that may still come finds nobody waiting.
+        if token.is_err() {
+            self.state.activation.pending.retain(|p| p.object != object);
+            object.destroy();
+        }
+        spawn(command, token)
+    }
+
+    //The same without waiting: asks for the token and returns. `callback` gets the outcome from
+    //the dispatch that brings the token (pump_events and friends), or right away when the
+    //compositor lacks xdg_activation_v1, the program then started without one.
+    pub fn spawn_with_activation_then(
+        &mut self,
+        command: Command,
+        callback: impl FnOnce(&mut Window, Result<Spawned, WindowError>) + Send + 'static,
+    ) {
+        let queue_handle = self.event_queue.handle();
+        let Some(object) = self.state.request_activation_token(&queue_handle) else {
+            let spawned = spawn(command, Err(WindowError::Unsupported("xdg_activation_v1")));
+            callback(self, spawned);
+            return;
+        };
+        self.state.activation.pending.push(PendingToken {
+            object,
+            token: None,
+            spawn: Some((command, Box::new(callback))),
+            raise: Vec::new(),
+        });
+        let _ = self.flush();
+    }
+
+    //Once per dispatch, like run_idle_callbacks: spawns what got its token.
+    pub(crate) fn run_activation_callbacks(&mut self) {
+        let pending = &mut self.state.activation.pending;
+        let ready: Vec<_> = pending
+            .extract_if(.., |p| p.token.is_some() && p.spawn.is_some())
+            .collect();
+        for pending in ready {
+            let (command, callback) = pending.spawn.unwrap();
+            let spawned = spawn(command, Ok(pending.token.unwrap()));
+            callback(self, spawned);
+        }
+    }
+}
+
+impl Dispatch<XdgActivationTokenV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        object: &XdgActivationTokenV1,
+        event: xdg_activation_token_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_activation_token_v1::Event::Done { token } = event {
+            //Quoting documentation: "The received token stays valid."
+            object.destroy();
+            let activation = &mut state.activation;
+            let Some(index) = activation.pending.iter().position(|p| &p.object == object) else {
+                return;
+            };
+            if activation.pending[index].raise.is_empty() {
+                activation.pending[index].token = Some(token);
+                return;
+            }
+            let pending = activation.pending.remove(index);
+            if let Some(ref manager) = activation.manager {
+                for surface in pending.raise {
+                    manager.activate(token.clone(), &surface);
+                }
+            }
+        }
+    }
+}
+
+//xdg_activation_v1 has no events.
+delegate_noop!(AppState: ignore XdgActivationV1);
@@ -0,0 +1,174 @@
+use std::ops::Range;
+
+use wayland_client::{QueueHandle, delegate_noop};
+use wayland_protocols::wp::alpha_modifier::v1::client::{
+    wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1, wp_alpha_modifier_v1::WpAlphaModifierV1,
+};
+
+use crate::{AppState, BufferFormat, Window};
+
+//Whole-window opacity. With wp_alpha_modifier_v1 the compositor multiplies the surface's alpha
+//when it blends it, the buffer stays as drawn and changing the opacity costs a commit. Without
+//it, the slow path: the shm buffer's pixels get multiplied when they're drawn.
+pub(crate) struct AlphaModifierState {
+    pub(crate) manager: Option<WpAlphaModifierV1>,
+    //Per-surface object, only created the first time the opacity isn't 1.
+    object: Option<WpAlphaModifierSurfaceV1>,
+    opacity: f32,
+}
+
+impl Default for AlphaModifierState {
+    fn default() -> Self {
+        AlphaModifierState {
+            manager: None,
+            object: None,
+            opacity: 1.0,
+        }
+    }
+}
+
+//0.0 to 1.0 onto the protocol's range. Quoting documentation: "Zero means completely
+//transparent, UINT32_MAX means completely opaque."
+fn multiplier(opacity: f32) -> u32 {
+    (f64::from(opacity) * f64::from(u32::MAX)).round() as u32
+}
+
+//Premultiplied pixels fade by scaling all four channels alike, so they stay premultiplied.
+fn fade(pixels: &mut [u8], opacity: f32) {
+    let factor = (opacity * 256.0).round() as u16;
+    for byte in pixels {
+        *byte = ((u16::from(*byte) * factor + 128) >> 8) as u8;
+    }
+}
+
+impl AppState {
+    //Sends the opacity, creating the per-surface object if needed. Also called when the surface
+    //gets (re)created so the setting survives it.
+    pub(crate) fn apply_alpha_modifier(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let modifier = &mut self.alpha_modifier;
+        let (Some(manager), Some(surface)) =
+            (modifier.manager.as_ref(), self.base_surface.as_ref())
+        else {
+            return;
+        };
+        //Fully opaque is the default, no need for an object for it.
+        if modifier.object.is_none() && modifier.opacity == 1.0 {
+            return;
+        }
+        //Quoting documentation: "If there is already such an object associated with the
+        //wl_surface, the already_constructed error will be raised."
+        let object = modifier
+            .object
+            .get_or_insert_with(|| manager.get_surface(surface, queue_handle, ()));
+        object.set_multiplier(multiplier(modifier.opacity));
+    }
+
+    //Quoting documentation: "This object has to be destroyed before the associated wl_surface."
+    pub(crate) fn forget_alpha_modifier_object(&mut self) {
+        if let Some(object) = self.alpha_modifier.object.take() {
+            object.destroy();
+        }
+    }
+
+    //The slow path, after every draw into the shm buffer. Xrgb8888 has no alpha to fade.
+    pub(crate) fn fade_shm_buffer(&mut self) {
+        if !self.fades_shm_buffer() {
+            return;
+        }
+        let opacity = self.alpha_modifier.opacity;
+        if let Some((pixels, _)) = self.shm_pixels.as_mut()
+            && let Some(mut bytes) = pixels.whole_mut()
+        {
+            fade(&mut bytes, opacity);
+        }
+    }
+
+    //The same for some rows only, those draw_rows drew.
+    pub(crate) fn fade_shm_rows(&mut self, rows: &[Range<u32>]) {
+        if !self.fades_shm_buffer() {
+            return;
+        }
+        let opacity = self.alpha_modifier.opacity;
+        if let Some((pixels, layout)) = self.shm_pixels.as_mut()
+            && let Some(mut bytes) = pixels.whole_mut()
+        {
+            let stride = layout.stride as usize;
+            for rows in rows {
+                let end = rows.end.min(layout.height) as usize * stride;
+                fade(&mut bytes[rows.start as usize * stride..end], opacity);
+            }
+        }
+    }
+
+    //Whether fade_shm_buffer changes the pixels.
+    pub(crate) fn fades_shm_buffer(&self) -> bool {
+        let modifier = &self.alpha_modifier;
+        modifier.manager.is_none()
+            && modifier.opacity != 1.0
+            && self.format != BufferFormat::Xrgb8888
+    }
+}
+
+impl Window {
+    //Opacity of the whole window, 0.0 (invisible) to 1.0 (as drawn). Out of range values are
+    //clamped, NaN is ignored. Takes effect with a commit, no redraw, when the compositor has
+    //wp_alpha_modifier_v1 (see opacity_supported).
+    //
+    //Without it, the slow path: the shm buffer's pixels are multiplied by the opacity when
+    //drawn. The gradient is redrawn right away, a picture from draw() fades at the next draw().
+    //Nothing fades with Xrgb8888 buffers, EGL or present_buffer then.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        if opacity.is_nan() {
+            log::warn!("set_opacity(NaN) ignored");
+            return;
+        }
+        self.state.alpha_modifier.opacity = opacity.clamp(0.0, 1.0);
+
+        if self.state.alpha_modifier.manager.is_some() {
+            let queue_handle = self.event_queue.handle();
+            self.state.apply_alpha_modifier(&queue_handle);
+            //The multiplier is double-buffered state, it takes effect on the next commit.
+            self.state.commit_state(&queue_handle);
+        } else if !self.state.drawn_by_app {
+            self.redraw_background();
+        }
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.state.alpha_modifier.opacity
+    }
+
+    //Whether set_opacity is the compositor's job rather than the slow path.
+    pub fn opacity_supported(&self) -> bool {
+        self.state.alpha_modifier.manager.is_some()
+    }
+}
+
+delegate_noop!(AppState: WpAlphaModifierV1);
+//wp_alpha_modifier_surface_v1 has no events.
+delegate_noop!(AppState: ignore WpAlphaModifierSurfaceV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opacity_maps_onto_the_whole_range() {
+        assert_eq!(multiplier(0.0), 0);
+        assert_eq!(multiplier(1.0), u32::MAX);
+        assert_eq!(multiplier(0.5), 1 << 31);
+    }
+
+    #[test]
+    fn fading_keeps_pixels_premultiplied() {
+        let mut pixels = [0xFF, 0x80, 0x00, 0xFF, 0x40, 0x40, 0x40, 0x80];
+        fade(&mut pixels, 0.5);
+        assert_eq!(pixels, [0x80, 0x40, 0x00, 0x80, 0x20, 0x20, 0x20, 0x40]);
+
+        let mut opaque = [0xFF; 4];
+        fade(&mut opaque, 1.0);
+        assert_eq!(opaque, [0xFF; 4]);
+        fade(&mut opaque, 0.0);
+        assert_eq!(opaque, [0; 4]);
+    }
+}
@@ -0,0 +1,223 @@
+//The app id only does its job when it's the name of an installed .desktop file: that's where the
+//compositor finds the icon and the application name, and what windows are grouped by. A window
+//whose app id matches nothing gets a generic icon (GNOME's gear), with nothing telling why.
+//
+//The desktop entry specification wants desktop file ids in the form of D-Bus well-known names:
+//reverse DNS, two or more elements separated by dots, each made of ASCII letters, digits, `_`
+//and `-`, not starting with a digit, at most 255 characters. It recommends `_` over `-`.
+//set_app_id sends whatever it gets (compositors take any string), an id breaking those rules is
+//logged as a warning.
+//
+//default_app_id makes one from the executable's name, the WindowOptions default. With the
+//desktop-file-check feature, a new window and set_app_id also look for the .desktop file where
+//desktop environments do, $XDG_DATA_HOME/applications and $XDG_DATA_DIRS/applications, and log
+//where it should be when there's none. Files in subdirectories (applications/vendor/app.desktop,
+//id vendor-app) aren't looked for.
+
+use std::path::Path;
+#[cfg(any(test, feature = "desktop-file-check"))]
+use std::path::PathBuf;
+
+use crate::WindowError;
+
+//D-Bus names are at most 255 characters.
+const MAX_LEN: usize = 255;
+
+//For executable names without a dot, which are one element.
+const DEFAULT_PREFIX: &str = "local";
+
+//Checks `id` against the desktop entry specification's rules for desktop file ids.
+pub fn validate_app_id(id: &str) -> Result<(), WindowError> {
+    if id.is_empty() {
+        return Err(WindowError::InvalidArgument("the app id is empty"));
+    }
+    if id.len() > MAX_LEN {
+        return Err(WindowError::InvalidArgument(
+            "the app id is longer than 255 characters",
+        ));
+    }
+    let elements: Vec<&str> = id.split('.').collect();
+    if elements.len() < 2 {
+        return Err(WindowError::InvalidArgument(
+            "the app id needs at least two elements separated by dots (reverse DNS)",
+        ));
+    }
+    for element in elements {
+        if element.is_empty() {
+            return Err(WindowError::InvalidArgument(
+                "the app id has an empty element",
+            ));
+        }
+        if element.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(WindowError::InvalidArgument(
+                "an app id element starts with a digit",
+            ));
+        }
+        if !element.chars().all(valid_char) {
+            return Err(WindowError::InvalidArgument(
+                "the app id has characters other than ASCII letters, digits, _ and -",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn valid_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+//A valid app id made from `name`: every other character becomes `_` (`-` too, as recommended),
+//elements starting with a digit get a `_` in front, and a single element gets DEFAULT_PREFIX.
+pub(crate) fn app_id_from(name: &str) -> String {
+    let mut elements: Vec<String> = name
+        .split('.')
+        .filter(|element| !element.is_empty())
+        .map(|element| {
+            let mut element: String = element
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            if element.starts_with(|c: char| c.is_ascii_digit()) {
+                element.insert(0, '_');
+            }
+            element
+        })
+        .collect();
+    match elements.len() {
+        0 => elements = vec![DEFAULT_PREFIX.into(), "app".into()],
+        1 => elements.insert(0, DEFAULT_PREFIX.into()),
+        _ => {}
+    }
+    let mut id = elements.join(".");
+    if id.len() > MAX_LEN {
+        //All ASCII by now.
+        id.truncate(MAX_LEN);
+        id = id.trim_end_matches('.').to_string();
+    }
+    id
+}
+
+//From the executable's file name, `my-app` becomes `local.my_app`. Names that are reverse DNS
+//already (`org.example.App`) are kept. Install the .desktop file under that name, or set an id
+//of your own.
+pub fn default_app_id() -> String {
+    let name = std::env::current_exe()
+        .ok()
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .or_else(|| {
+            let arg = std::env::args_os().next()?;
+            Some(Path::new(&arg).file_name()?.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    app_id_from(&name)
+}
+
+//The warning set_app_id logs for an id breaking the rules.
+pub(crate) fn warn_invalid(id: &str) {
+    if let Err(err) = validate_app_id(id) {
+        log::warn!("app id {id:?}: {err}, desktop environments may not find its .desktop file");
+    }
+}
+
+//$XDG_DATA_HOME first, then $XDG_DATA_DIRS, each with applications/ appended. Their defaults
+//are ~/.local/share and /usr/local/share:/usr/share.
+#[cfg(feature = "desktop-file-check")]
+fn application_dirs() -> Vec<PathBuf> {
+    use std::env;
+
+    let non_empty = |name| env::var_os(name).filter(|value| !value.is_empty());
+    let data_home = non_empty("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(non_empty("HOME")?).join(".local/share")));
+    let data_dirs =
+        non_empty("XDG_DATA_DIRS").unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    data_home
+        .into_iter()
+        .chain(env::split_paths(&data_dirs))
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+//Where the .desktop file for `app_id` is, if it's installed.
+#[cfg(feature = "desktop-file-check")]
+pub fn find_desktop_file(app_id: &str) -> Option<PathBuf> {
+    find_in(&application_dirs(), app_id)
+}
+
+#[cfg(any(test, feature = "desktop-file-check"))]
+fn find_in(dirs: &[PathBuf], app_id: &str) -> Option<PathBuf> {
+    let name = format!("{app_id}.desktop");
+    dirs.iter()
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
+
+//Logs where the .desktop file should be when there's none.
+#[cfg(feature = "desktop-file-check")]
+pub(crate) fn check_desktop_file(app_id: &str) {
+    let dirs = application_dirs();
+    if app_id.is_empty() || find_in(&dirs, app_id).is_some() {
+        return;
+    }
+    let searched: Vec<_> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+    let install = dirs.first().map_or_else(
+        || format!("{app_id}.desktop"),
+        |dir| dir.join(format!("{app_id}.desktop")).display().to_string(),
+    );
+    log::warn!(
+        "no {app_id}.desktop in {}: the compositor can't find the window's icon and name. \
+         Install one as {install}, or set the app id of an installed one",
+        searched.join(", ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_follow_the_desktop_entry_rules() {
+        assert!(validate_app_id("org.example.App").is_ok());
+        assert!(validate_app_id("io.github.some_one.my-app2").is_ok());
+        for invalid in [
+            "",
+            "app",
+            "org..App",
+            "org.example.",
+            "org.2048.Game",
+            "org.example.My App",
+            "org.exämple.App",
+        ] {
+            assert!(validate_app_id(invalid).is_err(), "{invalid:?}");
+        }
+        assert!(validate_app_id(&format!("org.{}", "a".repeat(251))).is_ok());
+        assert!(validate_app_id(&format!("org.{}", "a".repeat(252))).is_err());
+    }
+
+    #[test]
+    fn names_become_valid_ids() {
+        assert_eq!(app_id_from("my-app"), "local.my_app");
+        assert_eq!(app_id_from("org.example.App"), "org.example.App");
+        assert_eq!(app_id_from("2048"), "local._2048");
+        assert_eq!(app_id_from("über tool"), "local._ber_tool");
+        assert_eq!(app_id_from(""), "local.app");
+        assert_eq!(app_id_from(&"x".repeat(300)).len(), MAX_LEN);
+        for name in ["my-app", "a..b", "-", ".x.", &"y.".repeat(200)] {
+            let id = app_id_from(name);
+            assert!(validate_app_id(&id).is_ok(), "{name:?} gave {id:?}");
+        }
+    }
+
+    #[test]
+    fn desktop_files_are_found_by_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = [dir.path().join("missing"), dir.path().to_path_buf()];
+        std::fs::write(dir.path().join("org.example.App.desktop"), "").unwrap();
+        assert_eq!(
+            find_in(&dirs, "org.example.App"),
+            Some(dir.path().join("org.example.App.desktop"))
+        );
+        assert_eq!(find_in(&dirs, "org.example.Other"), None);
+    }
+}
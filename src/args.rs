@@ -0,0 +1,212 @@
+#[cfg(feature = "image")]
+use std::path::PathBuf;
+
+use simple_wayland_window::{
+    Background, BufferFormat, Color, Decorations, Margins, SideDispatch, WindowOptions,
+};
+
+pub const USAGE: &str = "\
+Usage: simple-wayland-window [OPTIONS] [MODE]
+
+Options:
+  --width N, --height N          buffer size in pixels (default 320x240)
+  --title TITLE, --app-id ID     toplevel title and app id
+  --format argb8888|xrgb8888     shm buffer format (default argb8888)
+  --maximized, --fullscreen      ask for that state before the first commit
+  --fullscreen-on NAME           fullscreen on the output of that name (DP-1...)
+  --shadow N                     client side decorations, the outer N pixels being shadow
+  --background B                 gradient (default), xor, checkerboard[:N] (N physical pixel
+                                 checks, 8 by default) or an RRGGBB color
+  --frames N                     exit after N frame callbacks, printing how long they took
+  --side-queue after|thread      relative motion added up on its own event queue, dispatched
+                                 after the window's or on a thread (demo only)
+
+Modes (default: the interactive demo):
+  --loop         timers and key repeat through the EventLoop (feature calloop)
+  --epoll        the window driven from our own epoll loop
+  --transparent  a half transparent rounded rectangle, to check alpha blending
+  --dialog       Q asks for confirmation in a modal dialog before quitting
+  --transform    an F under each of the eight buffer transforms, T for the next one
+  --overlay      a click-through window with one clickable button, C toggles the button
+  --threaded     a slow animation drawn on its own thread, keys still answer right away
+  --animate      hues turning on every frame callback, printing the frame rate
+  --blink        a blinking text cursor redrawn with damage only, keys move it
+  --fractional   the preferred fractional scale, from a protocol bound on our own queue
+  --async        the async event stream (feature async)
+  --egl          OpenGL ES clear loop (feature egl)
+  --handles      print the raw window handles (feature raw-window-handle)
+  --image PATH   show a PNG or JPEG, scaled to fit (feature image)
+  --hdr          a PQ encoded BT.2020 ramp, tagged as such (feature color-management)
+  --probe        print what the compositor supports and exit, without a window
+  --probe-startup
+                 show a window, print how long its first frame took to show and exit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Demo,
+    #[cfg(feature = "calloop")]
+    Loop,
+    Epoll,
+    Transparent,
+    Dialog,
+    Transform,
+    Overlay,
+    Threaded,
+    Animate,
+    Blink,
+    Fractional,
+    #[cfg(feature = "async")]
+    Async,
+    #[cfg(feature = "egl")]
+    Egl,
+    #[cfg(feature = "raw-window-handle")]
+    Handles,
+    #[cfg(feature = "image")]
+    Image,
+    #[cfg(feature = "color-management")]
+    Hdr,
+    Probe,
+    ProbeStartup,
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub options: WindowOptions,
+    pub frames: Option<u32>,
+    pub side_queue: Option<SideDispatch>,
+    pub mode: Mode,
+    //--image: what to show.
+    #[cfg(feature = "image")]
+    pub image: Option<PathBuf>,
+}
+
+impl Args {
+    //Everything is checked here, so a bad value is an argument error and never reaches the
+    //compositor as a protocol error (a 0 wide buffer, for one).
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--width" => parsed.options.size.0 = positive(&arg, &value()?)?,
+                "--height" => parsed.options.size.1 = positive(&arg, &value()?)?,
+                "--title" => parsed.options.title = value()?,
+                "--app-id" => parsed.options.app_id = value()?,
+                "--format" => {
+                    parsed.options.format = match value()?.as_str() {
+                        "argb8888" => BufferFormat::Argb8888,
+                        "xrgb8888" => BufferFormat::Xrgb8888,
+                        other => return Err(format!("unknown format {other}")),
+                    }
+                }
+                "--maximized" => parsed.options.maximized = true,
+                "--fullscreen" => parsed.options.fullscreen = true,
+                "--fullscreen-on" => {
+                    parsed.options.fullscreen = true;
+                    parsed.options.fullscreen_output = Some(value()?);
+                }
+                "--shadow" => {
+                    parsed.options.decorations = Decorations::ClientSide {
+                        shadow: Margins::uniform(positive(&arg, &value()?)?),
+                    }
+                }
+                "--background" => parsed.options.background = background(&value()?)?,
+                "--frames" => parsed.frames = Some(positive(&arg, &value()?)?),
+                "--side-queue" => {
+                    parsed.side_queue = Some(match value()?.as_str() {
+                        "after" => SideDispatch::AfterMainQueue,
+                        "thread" => SideDispatch::Thread,
+                        other => return Err(format!("unknown side queue dispatch {other}")),
+                    })
+                }
+                #[cfg(feature = "calloop")]
+                "--loop" => parsed.mode = Mode::Loop,
+                "--epoll" => parsed.mode = Mode::Epoll,
+                "--transparent" => parsed.mode = Mode::Transparent,
+                "--dialog" => parsed.mode = Mode::Dialog,
+                "--transform" => parsed.mode = Mode::Transform,
+                "--overlay" => parsed.mode = Mode::Overlay,
+                "--threaded" => parsed.mode = Mode::Threaded,
+                "--animate" => parsed.mode = Mode::Animate,
+                "--blink" => parsed.mode = Mode::Blink,
+                "--fractional" => parsed.mode = Mode::Fractional,
+                #[cfg(feature = "async")]
+                "--async" => parsed.mode = Mode::Async,
+                #[cfg(feature = "egl")]
+                "--egl" => parsed.mode = Mode::Egl,
+                #[cfg(feature = "raw-window-handle")]
+                "--handles" => parsed.mode = Mode::Handles,
+                #[cfg(feature = "image")]
+                "--image" => {
+                    parsed.image = Some(value()?.into());
+                    parsed.mode = Mode::Image;
+                }
+                #[cfg(feature = "color-management")]
+                "--hdr" => parsed.mode = Mode::Hdr,
+                "--probe" => parsed.mode = Mode::Probe,
+                "--probe-startup" => parsed.mode = Mode::ProbeStartup,
+                #[cfg(not(feature = "calloop"))]
+                "--loop" => return Err(not_built(&arg)),
+                #[cfg(not(feature = "async"))]
+                "--async" => return Err(not_built(&arg)),
+                #[cfg(not(feature = "egl"))]
+                "--egl" => return Err(not_built(&arg)),
+                #[cfg(not(feature = "raw-window-handle"))]
+                "--handles" => return Err(not_built(&arg)),
+                #[cfg(not(feature = "image"))]
+                "--image" => return Err(not_built(&arg)),
+                #[cfg(not(feature = "color-management"))]
+                "--hdr" => return Err(not_built(&arg)),
+                "--help" => return Err(String::new()),
+                other => return Err(format!("unknown argument {other}")),
+            }
+        }
+
+        if parsed.frames.is_some() && parsed.mode != Mode::Demo {
+            return Err("--frames only works with the default demo".into());
+        }
+        if parsed.side_queue.is_some() && parsed.mode != Mode::Demo {
+            return Err("--side-queue only works with the default demo".into());
+        }
+        Ok(parsed)
+    }
+}
+
+fn positive(arg: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(0) | Err(_) => Err(format!("{arg} must be a positive number, got {value}")),
+        Ok(number) => Ok(number),
+    }
+}
+
+fn background(value: &str) -> Result<Background, String> {
+    let bad = || format!("unknown background {value}");
+    match value {
+        "gradient" => Ok(Background::default()),
+        "xor" => Ok(Background::Xor),
+        "checkerboard" => Ok(Background::Checkerboard(8)),
+        _ => match value.strip_prefix("checkerboard:") {
+            Some(size) => Ok(Background::Checkerboard(positive("--background", size)?)),
+            None if value.len() == 6 => {
+                let color = u32::from_str_radix(value, 16).map_err(|_| bad())?;
+                let [_, r, g, b] = color.to_be_bytes();
+                Ok(Background::Solid(Color::opaque(r, g, b)))
+            }
+            None => Err(bad()),
+        },
+    }
+}
+
+#[cfg(not(all(
+    feature = "calloop",
+    feature = "async",
+    feature = "egl",
+    feature = "raw-window-handle",
+    feature = "image",
+    feature = "color-management"
+)))]
+fn not_built(arg: &str) -> String {
+    format!("{arg} needs the crate built with its feature")
+}
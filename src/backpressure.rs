@@ -0,0 +1,238 @@
+//A bound on the WindowEvents waiting for the application. They pile up when it dispatches without
+//taking them (an external loop calling read_and_dispatch but not take_events, an EventStream
+//nobody polls) while the user keeps moving the mouse: a 1000 Hz mouse is a thousand events a
+//second, for as long as the application is stuck.
+//
+//Past `capacity`, the oldest events that only matter as the latest of a series go: pointer and
+//relative motion, scrolling, key repeats and SlowFrame. PointerMoved positions are absolute and
+//their deltas are computed later from the previous position delivered (see motion.rs), so those
+//stay right; RelativeMotion and Scroll deltas of dropped events are lost. Everything else is
+//kept whatever the count, Resized, FocusLost or a key release missing would leave the
+//application wrong for good: with nothing left to drop, the queue goes over capacity.
+//
+//A single WindowEvent::EventsDropped where the first dropped event was says how many went. Until
+//it's taken, more drops add to its count instead of making another one.
+
+use crate::{AppState, Window, WindowEvent};
+
+//A few seconds of a fast mouse.
+const DEFAULT_CAPACITY: usize = 4096;
+
+pub(crate) struct BackpressureState {
+    capacity: usize,
+    //All dropped since the window was made.
+    pub(crate) dropped: u64,
+}
+
+impl Default for BackpressureState {
+    fn default() -> Self {
+        BackpressureState {
+            capacity: DEFAULT_CAPACITY,
+            dropped: 0,
+        }
+    }
+}
+
+impl BackpressureState {
+    pub(crate) fn for_reconnect(&self) -> BackpressureState {
+        BackpressureState {
+            capacity: self.capacity,
+            ..BackpressureState::default()
+        }
+    }
+}
+
+fn droppable(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::PointerMoved { .. }
+            | WindowEvent::RelativeMotion { .. }
+            | WindowEvent::Scroll { .. }
+            | WindowEvent::KeyRepeat { .. }
+            | WindowEvent::PointerButtonRepeat { .. }
+            | WindowEvent::SlowFrame { .. }
+    )
+}
+
+//Drops the oldest droppable events until `events` fits in `capacity`, the marker included.
+//Returns how many went.
+fn bound(events: &mut Vec<WindowEvent>, capacity: usize) -> usize {
+    if events.len() <= capacity {
+        return 0;
+    }
+    let marker = events
+        .iter()
+        .position(|event| matches!(event, WindowEvent::EventsDropped { .. }));
+    //Room for a new marker.
+    let excess = events.len() - capacity + usize::from(marker.is_none());
+    let mut dropped = 0;
+    let mut first = None;
+    let mut index = 0;
+    events.retain(|event| {
+        let keep = dropped == excess || !droppable(event);
+        if !keep {
+            first.get_or_insert(index - dropped);
+            dropped += 1;
+        }
+        index += 1;
+        keep
+    });
+    if dropped == 0 {
+        return 0;
+    }
+    match marker {
+        Some(marker) => {
+            //Dropped events were before it or after it, it moved by those before.
+            let marker = events
+                .iter_mut()
+                .skip(marker.saturating_sub(dropped))
+                .find(|event| matches!(event, WindowEvent::EventsDropped { .. }));
+            if let Some(WindowEvent::EventsDropped { count }) = marker {
+                *count += dropped as u64;
+            }
+        }
+        None => events.insert(
+            first.unwrap(),
+            WindowEvent::EventsDropped {
+                count: dropped as u64,
+            },
+        ),
+    }
+    dropped
+}
+
+impl AppState {
+    //After every dispatch, see backpressure.rs.
+    pub(crate) fn bound_events(&mut self) {
+        let dropped = bound(&mut self.events, self.backpressure.capacity);
+        if dropped > 0 {
+            self.backpressure.dropped += dropped as u64;
+            log::debug!("{dropped} events dropped, the application isn't taking them");
+        }
+    }
+}
+
+impl Window {
+    //How many WindowEvents may wait for the application, 4096 by default. At least 1, the
+    //EventsDropped marker.
+    pub fn set_event_capacity(&mut self, capacity: usize) {
+        self.state.backpressure.capacity = capacity.max(1);
+        self.state.bound_events();
+    }
+
+    pub fn event_capacity(&self) -> usize {
+        self.state.backpressure.capacity
+    }
+
+    //Events dropped over the window's life, see backpressure.rs.
+    pub fn events_dropped(&self) -> u64 {
+        self.state.backpressure.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moved(x: f64) -> WindowEvent {
+        WindowEvent::PointerMoved {
+            seat: "seat0".into(),
+            x,
+            y: 0.0,
+            dx: 0.0,
+            dy: 0.0,
+            samples: 1,
+        }
+    }
+
+    #[test]
+    fn oldest_motion_goes_first() {
+        let mut events = vec![
+            moved(1.0),
+            WindowEvent::Activated,
+            moved(2.0),
+            moved(3.0),
+            moved(4.0),
+        ];
+        assert_eq!(bound(&mut events, 4), 2);
+        assert_eq!(
+            events,
+            [
+                WindowEvent::EventsDropped { count: 2 },
+                WindowEvent::Activated,
+                moved(3.0),
+                moved(4.0),
+            ]
+        );
+
+        //Another drop adds to the marker.
+        events.push(moved(5.0));
+        assert_eq!(bound(&mut events, 4), 1);
+        assert_eq!(
+            events,
+            [
+                WindowEvent::EventsDropped { count: 3 },
+                WindowEvent::Activated,
+                moved(4.0),
+                moved(5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn critical_events_stay_over_capacity() {
+        let mut events = vec![
+            WindowEvent::Activated,
+            WindowEvent::Resized {
+                width: 1,
+                height: 1,
+            },
+            moved(1.0),
+            WindowEvent::Deactivated,
+        ];
+        assert_eq!(bound(&mut events, 2), 1);
+        assert_eq!(
+            events,
+            [
+                WindowEvent::Activated,
+                WindowEvent::Resized {
+                    width: 1,
+                    height: 1,
+                },
+                WindowEvent::EventsDropped { count: 1 },
+                WindowEvent::Deactivated,
+            ]
+        );
+        assert_eq!(bound(&mut events, 2), 0);
+    }
+
+    //A stuck application and a very fast mouse: the queue stays at capacity, the rest survives.
+    #[test]
+    fn a_flood_of_motion_stays_bounded() {
+        let mut events = Vec::new();
+        let mut dropped = 0;
+        for i in 0..100_000 {
+            events.push(moved(f64::from(i)));
+            if i % 1000 == 0 {
+                events.push(WindowEvent::Activated);
+            }
+            dropped += bound(&mut events, 512);
+            assert!(events.len() <= 512);
+        }
+        let activated = events
+            .iter()
+            .filter(|event| **event == WindowEvent::Activated)
+            .count();
+        assert_eq!(activated, 100);
+        let markers: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                WindowEvent::EventsDropped { count } => Some(*count),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(markers, [dropped as u64]);
+        assert_eq!(events.len() - 100 - 1 + dropped, 100_000);
+        assert_eq!(events.last(), Some(&moved(99_999.0)));
+    }
+}
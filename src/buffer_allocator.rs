@@ -0,0 +1,699 @@
+//One wl_shm_pool for the small, short lived buffers (icons, the 1x1 solid color fallback, the
+//diagnostic overlay) instead of a file and a pool for each, shared by every window on the
+//connection: a window of its own and its dialogs and utility windows all take slices of it.
+//
+//The window's own buffer and its spares (swapchain.rs) stay out of it, on purpose. A wl_shm_pool
+//grows but never shrinks: a window maximized once would keep three maximized buffers' worth of
+//pool until the connection goes, where a buffer with its own file gives its memory back when
+//it's unmapped (a resize, hidden_buffers.rs, a spare trimmed). Those are also drawn into for as
+//long as the window lives, so they'd pin most of the pool against compaction.
+//
+//Quoting documentation: "Reusing the mapped memory avoids the setup/teardown overhead and is
+//useful when interactively resizing a surface or for many small buffers."
+//
+//Slices of the pool are handed out by SliceAllocator, which knows nothing of Wayland so it can be
+//tested on its own. A buffer's offset in the pool is fixed when the wl_buffer is made, so moving
+//one (compaction) means a new wl_buffer: only done for buffers nobody can be reading.
+//
+//The memory itself is a MappedPool (mapped_pool.rs), which knows which slices the compositor may
+//be reading and won't hand those out for writing.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    os::fd::AsFd,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use wayland_client::{
+    Connection, QueueHandle,
+    backend::WeakBackend,
+    protocol::{wl_buffer::WlBuffer, wl_shm, wl_shm_pool::WlShmPool},
+};
+
+use crate::{AppState, buffer_layout::BufferLayout, canvas::MappedFile, mapped_pool::MappedPool};
+
+//Slices start on cache line boundaries. wl_shm itself only needs 4 bytes.
+const ALIGN: usize = 64;
+//Size of a new pool, at least doubled whenever it's full.
+const INITIAL_SIZE: usize = 64 * 1024;
+//Share of the free bytes outside the largest free range above which a failed allocation tries
+//compacting before growing the pool.
+const COMPACT_THRESHOLD: f32 = 0.5;
+
+//A slice compaction moved: its bytes go from `from` to `to`, which can overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Move {
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    pub(crate) len: usize,
+}
+
+//First fit over a sorted list of free ranges, merged with their neighbours when a slice is
+//freed.
+#[derive(Debug)]
+pub(crate) struct SliceAllocator {
+    size: usize,
+    //(offset, len), sorted by offset, no two touching.
+    free: Vec<(usize, usize)>,
+    //Offset to length of the slices handed out.
+    used: BTreeMap<usize, usize>,
+}
+
+impl SliceAllocator {
+    pub(crate) fn new(size: usize) -> SliceAllocator {
+        SliceAllocator {
+            size,
+            free: if size > 0 {
+                vec![(0, size)]
+            } else {
+                Vec::new()
+            },
+            used: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    //Offset of a slice of at least `len` bytes, None when no free range is big enough.
+    pub(crate) fn allocate(&mut self, len: usize) -> Option<usize> {
+        let len = len.max(1).checked_next_multiple_of(ALIGN)?;
+        let index = self.free.iter().position(|&(_, free)| free >= len)?;
+        let (offset, free) = self.free[index];
+        if free == len {
+            self.free.remove(index);
+        } else {
+            self.free[index] = (offset + len, free - len);
+        }
+        self.used.insert(offset, len);
+        Some(offset)
+    }
+
+    //Gives the slice at `offset` back. False when there's none.
+    pub(crate) fn free(&mut self, offset: usize) -> bool {
+        let Some(len) = self.used.remove(&offset) else {
+            return false;
+        };
+        let index = self.free.partition_point(|&(free, _)| free < offset);
+        self.free.insert(index, (offset, len));
+        if let Some(&(next, next_len)) = self.free.get(index + 1)
+            && offset + len == next
+        {
+            self.free[index].1 += next_len;
+            self.free.remove(index + 1);
+        }
+        if index > 0 {
+            let (previous, previous_len) = self.free[index - 1];
+            if previous + previous_len == offset {
+                self.free[index - 1].1 += self.free[index].1;
+                self.free.remove(index);
+            }
+        }
+        true
+    }
+
+    //More room at the end. Pools only grow.
+    pub(crate) fn grow(&mut self, size: usize) {
+        if size <= self.size {
+            return;
+        }
+        match self.free.last_mut() {
+            Some((offset, len)) if *offset + *len == self.size => *len += size - self.size,
+            _ => self.free.push((self.size, size - self.size)),
+        }
+        self.size = size;
+    }
+
+    pub(crate) fn free_bytes(&self) -> usize {
+        self.free.iter().map(|&(_, len)| len).sum()
+    }
+
+    pub(crate) fn largest_free(&self) -> usize {
+        self.free.iter().map(|&(_, len)| len).max().unwrap_or(0)
+    }
+
+    //0 when the free bytes are all in one range, close to 1 when they're scattered in small
+    //holes between slices.
+    pub(crate) fn fragmentation(&self) -> f32 {
+        let free = self.free_bytes();
+        if free == 0 {
+            return 0.0;
+        }
+        1.0 - self.largest_free() as f32 / free as f32
+    }
+
+    //Slides the slices `movable` says can move towards the start, in order, around the ones
+    //that can't. The moves are to be applied in the order returned, each copy can overlap its
+    //own source but never a slice not moved yet.
+    pub(crate) fn compact(&mut self, movable: impl Fn(usize) -> bool) -> Vec<Move> {
+        let pinned: Vec<(usize, usize)> = self
+            .used
+            .iter()
+            .filter(|&(&offset, _)| !movable(offset))
+            .map(|(&offset, &len)| (offset, len))
+            .collect();
+
+        let mut moves = Vec::new();
+        let mut placed = BTreeMap::new();
+        let mut cursor = 0;
+        for (&offset, &len) in &self.used {
+            if !movable(offset) {
+                placed.insert(offset, len);
+                cursor = cursor.max(offset + len);
+                continue;
+            }
+            //The first hole after the cursor that fits, past the pinned slices in the way.
+            let mut to = cursor;
+            for &(pinned, pinned_len) in &pinned {
+                if pinned + pinned_len <= to {
+                    continue;
+                }
+                if pinned >= to + len {
+                    break;
+                }
+                to = pinned + pinned_len;
+            }
+            if to < offset {
+                moves.push(Move {
+                    from: offset,
+                    to,
+                    len,
+                });
+            } else {
+                to = offset;
+            }
+            placed.insert(to, len);
+            cursor = to + len;
+        }
+
+        //The free list is whatever is between the slices now.
+        self.free.clear();
+        let mut end = 0;
+        for (&offset, &len) in &placed {
+            if offset > end {
+                self.free.push((end, offset - end));
+            }
+            end = offset + len;
+        }
+        if self.size > end {
+            self.free.push((end, self.size - end));
+        }
+        self.used = placed;
+        moves
+    }
+}
+
+struct Slot {
+    buffer: WlBuffer,
+    layout: BufferLayout,
+    //SharedAllocator::owner of the window that made it.
+    owner: u64,
+    //The compositor may read it whenever (icons): it never moves.
+    pinned: bool,
+    //Given back while busy (see MappedPool::is_busy), freed on release.
+    retired: bool,
+}
+
+pub(crate) struct BufferAllocator {
+    memory: MappedPool,
+    pool: WlShmPool,
+    slices: SliceAllocator,
+    slots: BTreeMap<usize, Slot>,
+}
+
+impl BufferAllocator {
+    fn new(shm: &wl_shm::WlShm, queue_handle: &QueueHandle<AppState>) -> io::Result<Self> {
+        let memory = MappedPool::new(MappedFile::new(INITIAL_SIZE)?);
+        let pool = shm.create_pool(memory.file().as_fd(), INITIAL_SIZE as i32, queue_handle, ());
+        Ok(BufferAllocator {
+            memory,
+            pool,
+            slices: SliceAllocator::new(INITIAL_SIZE),
+            slots: BTreeMap::new(),
+        })
+    }
+
+    fn offset_of(&self, buffer: &WlBuffer) -> Option<usize> {
+        self.slots
+            .iter()
+            .find(|(_, slot)| slot.buffer == *buffer)
+            .map(|(&offset, _)| offset)
+    }
+
+    fn free(&mut self, offset: usize) {
+        if let Some(slot) = self.slots.remove(&offset) {
+            slot.buffer.destroy();
+        }
+        self.memory.released(offset);
+        self.slices.free(offset);
+    }
+
+    //Moves the idle buffers of `owner` that may move, each getting a new wl_buffer on its
+    //queue. Other windows' buffers stay put, only they know who holds them. Returns the (old,
+    //new) pairs, the old ones are destroyed.
+    fn compact(
+        &mut self,
+        owner: u64,
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Vec<(WlBuffer, WlBuffer)> {
+        let (slots, memory) = (&self.slots, &self.memory);
+        let moves = self.slices.compact(|offset| {
+            slots
+                .get(&offset)
+                .is_some_and(|slot| slot.owner == owner && !slot.pinned && !memory.is_busy(offset))
+        });
+
+        let mut replaced = Vec::new();
+        for slice_move in moves {
+            let Move { from, to, .. } = slice_move;
+            //Neither side is busy: compact only moved idle slices, into free space.
+            let moved = self.memory.move_slice(slice_move);
+            debug_assert!(moved, "compaction moved a busy slice");
+            let mut slot = self.slots.remove(&from).unwrap();
+            let (width, height, stride) = slot.layout.protocol_size();
+            let buffer = self.pool.create_buffer(
+                to as i32,
+                width,
+                height,
+                stride,
+                slot.layout.format,
+                queue_handle,
+                (),
+            );
+            let old = std::mem::replace(&mut slot.buffer, buffer.clone());
+            old.destroy();
+            self.slots.insert(to, slot);
+            replaced.push((old, buffer));
+        }
+        replaced
+    }
+
+    //Room for `len` more bytes: the file, the compositor's mapping and the free list.
+    fn grow(&mut self, len: usize) -> io::Result<()> {
+        let size = self.slices.size();
+        let len = len.next_multiple_of(ALIGN);
+        //Pool sizes are i32 on the wire.
+        let new_size = (size * 2).max(size + len).min(i32::MAX as usize);
+        if new_size - size < len {
+            return Err(io::ErrorKind::OutOfMemory.into());
+        }
+        //Quoting documentation: "It is the client's responsibility to ensure that the file is
+        //at least as big as the new pool size."
+        self.memory.grow(new_size)?;
+        self.pool.resize(new_size as i32);
+        self.slices.grow(new_size);
+        Ok(())
+    }
+}
+
+//The last window of the connection went: whatever it left behind, and the pool.
+impl Drop for BufferAllocator {
+    fn drop(&mut self) {
+        for slot in std::mem::take(&mut self.slots).into_values() {
+            slot.buffer.destroy();
+        }
+        self.pool.destroy();
+    }
+}
+
+type Pool = Arc<Mutex<Option<BufferAllocator>>>;
+type WeakPool = Weak<Mutex<Option<BufferAllocator>>>;
+
+//The pool of every connection with a window on it, so windows on the same connection share it:
+//a dialog and its parent, or windows given clones of one Connection. An entry goes with the last
+//window of its connection.
+static POOLS: Mutex<Vec<(WeakBackend, WeakPool)>> = Mutex::new(Vec::new());
+static NEXT_OWNER: AtomicU64 = AtomicU64::new(0);
+
+//A window's hold on its connection's pool. Each buffer belongs to the window that made it: its
+//release comes on that window's queue, and only that window knows who holds it when compaction
+//moves it.
+pub(crate) struct SharedAllocator {
+    pool: Pool,
+    owner: u64,
+}
+
+impl SharedAllocator {
+    pub(crate) fn for_connection(connection: &Connection) -> SharedAllocator {
+        let backend = connection.backend();
+        let mut pools = POOLS.lock().unwrap();
+        pools.retain(|(_, pool)| pool.strong_count() > 0);
+        let shared = pools
+            .iter()
+            .find(|(weak, _)| weak.upgrade().as_ref() == Some(&backend))
+            .and_then(|(_, pool)| pool.upgrade());
+        let pool = shared.unwrap_or_else(|| {
+            let pool = Pool::default();
+            pools.push((backend.downgrade(), Arc::downgrade(&pool)));
+            pool
+        });
+        SharedAllocator {
+            pool,
+            owner: NEXT_OWNER.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl AppState {
+    //A buffer laid out as `layout` holding `pixels` (rows of width * 4 bytes, no padding), from
+    //the connection's shared pool. `pinned` for buffers the compositor may read at any time,
+    //which compaction then never moves. Give it back with drop_buffer.
+    pub(crate) fn create_pool_buffer(
+        &mut self,
+        layout: BufferLayout,
+        pixels: &[u8],
+        pinned: bool,
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Option<WlBuffer> {
+        let len = layout.len();
+        debug_assert_eq!(pixels.len(), layout.row_len() * layout.height as usize);
+        let owner = self.buffer_allocator.owner;
+        let pool = self.buffer_allocator.pool.clone();
+        let mut pool = pool.lock().unwrap();
+        if pool.is_none() {
+            let allocator = BufferAllocator::new(self.shm.as_ref()?, queue_handle);
+            *pool = Some(allocator.map_err(|err| log::warn!("{err}")).ok()?);
+        }
+        let allocator = pool.as_mut().unwrap();
+
+        let offset = match allocator.slices.allocate(len) {
+            Some(offset) => offset,
+            None => {
+                let mut offset = None;
+                if allocator.slices.fragmentation() > COMPACT_THRESHOLD {
+                    let replaced = allocator.compact(owner, queue_handle);
+                    offset = allocator.slices.allocate(len);
+                    for (old, new) in replaced {
+                        self.replace_moved_buffer(&old, new);
+                    }
+                }
+                match offset {
+                    Some(offset) => offset,
+                    None => {
+                        if let Err(err) = allocator.grow(len) {
+                            log::warn!("Couldn't grow the shm pool: {err}");
+                            return None;
+                        }
+                        allocator.slices.allocate(len)?
+                    }
+                }
+            }
+        };
+
+        //A slice just allocated, nobody reads it.
+        match allocator.memory.slice_mut(offset, len) {
+            Some(mut slice) => layout.copy_rows_in(pixels, &mut slice),
+            None => {
+                allocator.slices.free(offset);
+                return None;
+            }
+        }
+        let (width, height, stride) = layout.protocol_size();
+        let buffer = allocator.pool.create_buffer(
+            offset as i32,
+            width,
+            height,
+            stride,
+            layout.format,
+            queue_handle,
+            (),
+        );
+        allocator.slots.insert(
+            offset,
+            Slot {
+                buffer: buffer.clone(),
+                layout,
+                owner,
+                pinned,
+                retired: false,
+            },
+        );
+        Some(buffer)
+    }
+
+    //To be called when attaching a buffer: a pool buffer's slice stays taken until it's
+    //released.
+    pub(crate) fn pool_buffer_attached(&mut self, buffer: &WlBuffer) {
+        let mut pool = self.buffer_allocator.pool.lock().unwrap();
+        if let Some(allocator) = pool.as_mut()
+            && let Some(offset) = allocator.offset_of(buffer)
+        {
+            let len = allocator.slots[&offset].layout.len();
+            allocator.memory.attached(offset, len);
+        }
+    }
+
+    //Done with a buffer. Others are destroyed, pool ones go back to the pool, once the
+    //compositor released them if they're attached.
+    pub(crate) fn drop_buffer(&mut self, buffer: WlBuffer) {
+        let mut pool = self.buffer_allocator.pool.lock().unwrap();
+        let Some(allocator) = pool.as_mut() else {
+            buffer.destroy();
+            return;
+        };
+        let Some(offset) = allocator.offset_of(&buffer) else {
+            buffer.destroy();
+            return;
+        };
+        if allocator.memory.is_busy(offset) {
+            //Quoting documentation: "Destroying the wl_buffer before wl_buffer.release is
+            //allowed as long as the underlying buffer storage isn't re-used". Keeping it is
+            //simpler, and the release tells when the slice is free.
+            allocator.slots.get_mut(&offset).unwrap().retired = true;
+        } else {
+            allocator.free(offset);
+        }
+    }
+
+    //wl_buffer.release, for any buffer. A pool buffer's comes to the window that made it.
+    pub(crate) fn buffer_released(&mut self, buffer: &WlBuffer) {
+        if self.swapchain_released(buffer) {
+            return;
+        }
+        let mut pool = self.buffer_allocator.pool.lock().unwrap();
+        let Some(allocator) = pool.as_mut() else {
+            return;
+        };
+        let Some(offset) = allocator.offset_of(buffer) else {
+            return;
+        };
+        allocator.memory.released(offset);
+        if allocator.slots[&offset].retired {
+            allocator.free(offset);
+        }
+    }
+
+    //The window is going, see cleanup.rs: every buffer it made, busy or not, and its hold on the
+    //pool, which goes with the last window of the connection.
+    pub(crate) fn release_buffer_allocator(&mut self) {
+        let owner = self.buffer_allocator.owner;
+        let pool = std::mem::take(&mut self.buffer_allocator.pool);
+        if let Some(allocator) = pool.lock().unwrap().as_mut() {
+            let owned: Vec<usize> = allocator
+                .slots
+                .iter()
+                .filter(|(_, slot)| slot.owner == owner)
+                .map(|(&offset, _)| offset)
+                .collect();
+            for offset in owned {
+                allocator.free(offset);
+            }
+        }
+    }
+
+    //Compaction moved a buffer of this window: whoever held the old one gets the new one. Icons
+    //are pinned, the solid color fallback's and the diagnostic overlay's buffers can move.
+    fn replace_moved_buffer(&mut self, old: &WlBuffer, new: WlBuffer) {
+        #[cfg(feature = "diagnostic-overlay")]
+        self.diagnostic_overlay.replace_buffer(old, new.clone());
+        self.solid_color.replace_buffer(old, new);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //No overlaps, everything aligned, and used + free covering the whole pool.
+    fn check(allocator: &SliceAllocator) {
+        let mut ranges: Vec<(usize, usize)> = allocator
+            .used
+            .iter()
+            .map(|(&offset, &len)| (offset, len))
+            .chain(allocator.free.iter().copied())
+            .collect();
+        ranges.sort();
+        let mut end = 0;
+        for (offset, len) in ranges {
+            assert_eq!(offset, end, "{allocator:?}");
+            assert_eq!(offset % ALIGN, 0);
+            end = offset + len;
+        }
+        assert_eq!(end, allocator.size);
+        for pair in allocator.free.windows(2) {
+            assert!(pair[0].0 + pair[0].1 < pair[1].0, "touching free ranges");
+        }
+    }
+
+    #[test]
+    fn slices_are_aligned_and_reused() {
+        let mut allocator = SliceAllocator::new(1024);
+        assert_eq!(allocator.allocate(4), Some(0));
+        assert_eq!(allocator.allocate(65), Some(64));
+        assert_eq!(allocator.allocate(64), Some(192));
+        check(&allocator);
+
+        assert!(allocator.free(64));
+        assert!(!allocator.free(64));
+        //First fit: the hole is big enough.
+        assert_eq!(allocator.allocate(100), Some(64));
+        assert_eq!(allocator.allocate(1024), None);
+        check(&allocator);
+
+        for offset in [0, 64, 192] {
+            assert!(allocator.free(offset));
+        }
+        assert_eq!(allocator.free, [(0, 1024)]);
+    }
+
+    #[test]
+    fn growing_extends_the_last_free_range() {
+        let mut allocator = SliceAllocator::new(128);
+        assert_eq!(allocator.allocate(128), Some(0));
+        assert_eq!(allocator.allocate(64), None);
+        allocator.grow(256);
+        assert_eq!(allocator.free, [(128, 128)]);
+        allocator.grow(512);
+        assert_eq!(allocator.free, [(128, 384)]);
+        assert_eq!(allocator.allocate(384), Some(128));
+        check(&allocator);
+    }
+
+    #[test]
+    fn compaction_undoes_a_checkerboard() {
+        //Every other slice freed: half the pool is free, and no two free slices are adjacent.
+        let mut allocator = SliceAllocator::new(64 * 100);
+        let offsets: Vec<usize> = (0..100).map(|_| allocator.allocate(64).unwrap()).collect();
+        for offset in offsets.iter().step_by(2) {
+            allocator.free(*offset);
+        }
+        assert_eq!(allocator.free_bytes(), 64 * 50);
+        assert_eq!(allocator.largest_free(), 64);
+        assert!(allocator.fragmentation() > 0.9);
+        assert_eq!(allocator.allocate(128), None);
+
+        let moves = allocator.compact(|_| true);
+        assert_eq!(moves.len(), 50);
+        assert_eq!(
+            moves[0],
+            Move {
+                from: 64,
+                to: 0,
+                len: 64
+            }
+        );
+        assert_eq!(allocator.free, [(64 * 50, 64 * 50)]);
+        assert_eq!(allocator.fragmentation(), 0.0);
+        assert_eq!(allocator.allocate(64 * 50), Some(64 * 50));
+        check(&allocator);
+    }
+
+    #[test]
+    fn compaction_goes_around_pinned_slices() {
+        //Slices of 1, 2 and 3 units, every third one pinned, the 1 unit ones freed.
+        let unit = ALIGN;
+        let mut allocator = SliceAllocator::new(unit * 60);
+        let mut pinned = Vec::new();
+        for i in 0..10 {
+            let small = allocator.allocate(unit).unwrap();
+            let medium = allocator.allocate(2 * unit).unwrap();
+            allocator.allocate(3 * unit).unwrap();
+            allocator.free(small);
+            if i % 3 == 0 {
+                pinned.push(medium);
+            }
+        }
+        check(&allocator);
+        let before: Vec<(usize, usize)> = allocator.used.iter().map(|(&o, &l)| (o, l)).collect();
+
+        let moves = allocator.compact(|offset| !pinned.contains(&offset));
+        check(&allocator);
+        for offset in &pinned {
+            assert!(allocator.used.contains_key(offset), "pinned slice moved");
+        }
+        //Nothing moves up, nothing pinned moves, every length survives.
+        for Move { from, to, len } in &moves {
+            assert!(to < from);
+            assert!(!pinned.contains(from));
+            assert!(before.contains(&(*from, *len)));
+        }
+        assert_eq!(allocator.used.len(), before.len());
+        assert_eq!(allocator.free_bytes(), 10 * unit);
+        assert!(allocator.largest_free() >= 6 * unit);
+
+        //Applying the moves in order to real bytes keeps every slice's content.
+        let mut bytes = vec![0u8; allocator.size];
+        for &(offset, len) in &before {
+            bytes[offset..offset + len].fill((offset / unit) as u8);
+        }
+        for Move { from, to, len } in &moves {
+            bytes.copy_within(*from..*from + *len, *to);
+        }
+        let mut placed = before.clone();
+        for Move { from, to, .. } in &moves {
+            let slice = placed.iter_mut().find(|(o, _)| o == from).unwrap();
+            slice.0 = *to;
+        }
+        for (&(original, len), &(offset, _)) in before.iter().zip(&placed) {
+            assert!(
+                bytes[offset..offset + len]
+                    .iter()
+                    .all(|&b| b == (original / unit) as u8)
+            );
+        }
+    }
+
+    #[test]
+    fn random_sequences_keep_the_invariants() {
+        //A small LCG, the same sequence on every run.
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let mut next = |bound: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % bound
+        };
+
+        let mut allocator = SliceAllocator::new(64 * 1024);
+        let mut live = Vec::new();
+        for step in 0..5000 {
+            match next(10) {
+                0..=5 => match allocator.allocate(1 + next(4096)) {
+                    Some(offset) => live.push(offset),
+                    None => allocator.grow(allocator.size() * 2),
+                },
+                6..=8 if !live.is_empty() => {
+                    let offset = live.swap_remove(next(live.len()));
+                    assert!(allocator.free(offset));
+                }
+                _ => {
+                    let moves = allocator.compact(|offset| offset % 3 != 0);
+                    for Move { from, to, .. } in moves {
+                        *live.iter_mut().find(|offset| **offset == from).unwrap() = to;
+                    }
+                }
+            }
+            if step % 50 == 0 {
+                check(&allocator);
+            }
+        }
+        check(&allocator);
+        live.sort();
+        assert_eq!(live, allocator.used.keys().copied().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,206 @@
+//The geometry of an shm buffer, worked out once with checked arithmetic. Sizes come from options,
+//configures, icons and images, any of which can be huge: width * height * 4 in u32 (or in the
+//i32 the protocol wants) silently wraps, and a wrapped pool size or stride is a protocol error at
+//best, a buffer smaller than what we draw into at worst. Every pool and buffer we make goes
+//through here.
+
+use wayland_client::protocol::wl_shm;
+
+use crate::WindowError;
+
+//Largest width or height. What GPUs take as a texture, which is what compositors upload shm
+//buffers into. A 16384 x 16384 buffer is 1 GiB, still well within the protocol's i32 sizes.
+pub(crate) const MAX_DIMENSION: u32 = 16384;
+//Rows start on 64 byte boundaries, what GPU uploads (and SIMD copies in pixman) go fastest with.
+//wl_shm itself only needs whole pixels.
+pub(crate) const STRIDE_ALIGN: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BufferLayout {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    //Bytes from one row to the next, at least width * 4.
+    pub(crate) stride: u32,
+    pub(crate) format: wl_shm::Format,
+}
+
+impl BufferLayout {
+    pub(crate) fn new(
+        width: u32,
+        height: u32,
+        format: wl_shm::Format,
+    ) -> Result<BufferLayout, WindowError> {
+        if width == 0 || height == 0 {
+            return Err(WindowError::InvalidArgument("empty buffer"));
+        }
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(WindowError::InvalidArgument("buffer too large"));
+        }
+        let bytes_per_pixel = match format {
+            wl_shm::Format::Argb8888
+            | wl_shm::Format::Xrgb8888
+            | wl_shm::Format::Abgr8888
+            | wl_shm::Format::Xbgr8888 => 4,
+            _ => return Err(WindowError::InvalidArgument("unsupported shm format")),
+        };
+        let stride = width
+            .checked_mul(bytes_per_pixel)
+            .and_then(|row| row.checked_next_multiple_of(STRIDE_ALIGN))
+            .filter(|&stride| i32::try_from(stride).is_ok())
+            .ok_or(WindowError::InvalidArgument("buffer too large"))?;
+        //The pool size is an i32 too.
+        stride
+            .checked_mul(height)
+            .filter(|&len| i32::try_from(len).is_ok())
+            .ok_or(WindowError::InvalidArgument("buffer too large"))?;
+        Ok(BufferLayout {
+            width,
+            height,
+            stride,
+            format,
+        })
+    }
+
+    //A size every layout takes: 0 becomes 1, anything above MAX_DIMENSION becomes that.
+    pub(crate) fn clamp_size((width, height): (u32, u32)) -> (u32, u32) {
+        (
+            width.clamp(1, MAX_DIMENSION),
+            height.clamp(1, MAX_DIMENSION),
+        )
+    }
+
+    //Bytes the buffer takes in its pool. Checked in new to fit an i32.
+    pub(crate) fn len(&self) -> usize {
+        self.stride as usize * self.height as usize
+    }
+
+    //Bytes of one row's pixels, without the padding up to the stride.
+    pub(crate) fn row_len(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    //wl_shm_pool.create_buffer's width, height and stride. Checked in new to fit.
+    pub(crate) fn protocol_size(&self) -> (i32, i32, i32) {
+        (self.width as i32, self.height as i32, self.stride as i32)
+    }
+
+    //Copies rows of width * 4 bytes into memory laid out with this stride.
+    pub(crate) fn copy_rows_in(&self, tight: &[u8], memory: &mut [u8]) {
+        for (row, target) in tight
+            .chunks_exact(self.row_len())
+            .zip(memory.chunks_mut(self.stride as usize))
+        {
+            target[..row.len()].copy_from_slice(row);
+        }
+    }
+
+    //The other way around: the pixels of memory laid out with this stride, without the padding.
+    pub(crate) fn copy_rows_out(&self, memory: &[u8]) -> Vec<u8> {
+        let mut tight = Vec::with_capacity(self.row_len() * self.height as usize);
+        for row in memory[..self.len()].chunks_exact(self.stride as usize) {
+            tight.extend_from_slice(&row[..self.row_len()]);
+        }
+        tight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: wl_shm::Format = wl_shm::Format::Argb8888;
+
+    //What must hold for any size, valid or not.
+    fn check(width: u32, height: u32) {
+        let valid = (1..=MAX_DIMENSION).contains(&width) && (1..=MAX_DIMENSION).contains(&height);
+        match BufferLayout::new(width, height, FORMAT) {
+            Ok(layout) => {
+                assert!(valid, "{width}x{height} accepted");
+                assert_eq!((layout.width, layout.height), (width, height));
+                assert!(u64::from(layout.stride) >= u64::from(width) * 4);
+                assert!(u64::from(layout.stride) < u64::from(width) * 4 + u64::from(STRIDE_ALIGN));
+                assert_eq!(layout.stride % STRIDE_ALIGN, 0);
+                let len = u64::from(layout.stride) * u64::from(height);
+                assert_eq!(layout.len() as u64, len);
+                assert!(len <= i32::MAX as u64);
+                let (w, h, stride) = layout.protocol_size();
+                assert!(w > 0 && h > 0 && stride > 0);
+            }
+            Err(_) => assert!(!valid, "{width}x{height} refused"),
+        }
+    }
+
+    #[test]
+    fn edges_of_the_range() {
+        let edges = [
+            0,
+            1,
+            2,
+            15,
+            16,
+            17,
+            MAX_DIMENSION - 1,
+            MAX_DIMENSION,
+            MAX_DIMENSION + 1,
+            1 << 29,
+            1 << 30,
+            (1 << 30) + 1,
+            i32::MAX as u32,
+            i32::MAX as u32 + 1,
+            u32::MAX / 4,
+            u32::MAX / 4 + 1,
+            u32::MAX - 1,
+            u32::MAX,
+        ];
+        for width in edges {
+            for height in edges {
+                check(width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn random_sizes_across_the_u32_range() {
+        //A small LCG, the same sizes on every run. Half of them small enough to be valid.
+        let mut seed: u64 = 0x9E3779B97F4A7C15;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 32) as u32
+        };
+        for _ in 0..100_000 {
+            let (a, b) = (next(), next());
+            let (width, height) = if a & 1 == 0 {
+                (a % (MAX_DIMENSION + 2), b % (MAX_DIMENSION + 2))
+            } else {
+                (a, b)
+            };
+            check(width, height);
+        }
+    }
+
+    #[test]
+    fn clamped_sizes_always_make_a_layout() {
+        for size in [(0, 0), (u32::MAX, 1), (1, u32::MAX), (320, 240)] {
+            let (width, height) = BufferLayout::clamp_size(size);
+            assert!(BufferLayout::new(width, height, FORMAT).is_ok());
+        }
+        assert_eq!(BufferLayout::clamp_size((320, 240)), (320, 240));
+    }
+
+    #[test]
+    fn rows_are_padded_to_the_stride() {
+        let layout = BufferLayout::new(3, 2, FORMAT).unwrap();
+        assert_eq!((layout.stride, layout.len()), (64, 128));
+        assert!(BufferLayout::new(3, 2, wl_shm::Format::Rgb565).is_err());
+
+        let tight: Vec<u8> = (0..24).collect();
+        let mut memory = vec![0xAA; layout.len()];
+        layout.copy_rows_in(&tight, &mut memory);
+        assert_eq!(memory[..12], tight[..12]);
+        assert_eq!(memory[12..64], [0xAA; 52]);
+        assert_eq!(memory[64..76], tight[12..]);
+        assert_eq!(layout.copy_rows_out(&memory), tight);
+    }
+}
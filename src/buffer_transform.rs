@@ -0,0 +1,254 @@
+use wayland_client::{Proxy, protocol::wl_output};
+
+use crate::{AppState, Rect, RenderMode, ScaleMath, Window, WindowError};
+
+//How the content was already rotated or flipped in the buffer, so the compositor undoes it when
+//showing it (e.g. camera frames that arrive sideways). The flipped ones are a flip around the
+//vertical axis first, then the rotation. Rotations are counter-clockwise, as wl_output has them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Transform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl From<Transform> for wl_output::Transform {
+    fn from(transform: Transform) -> Self {
+        match transform {
+            Transform::Normal => wl_output::Transform::Normal,
+            Transform::Rotate90 => wl_output::Transform::_90,
+            Transform::Rotate180 => wl_output::Transform::_180,
+            Transform::Rotate270 => wl_output::Transform::_270,
+            Transform::Flipped => wl_output::Transform::Flipped,
+            Transform::Flipped90 => wl_output::Transform::Flipped90,
+            Transform::Flipped180 => wl_output::Transform::Flipped180,
+            Transform::Flipped270 => wl_output::Transform::Flipped270,
+        }
+    }
+}
+
+//Unknown values can't come here, wayland-client keeps them as WEnum::Unknown.
+impl From<wl_output::Transform> for Transform {
+    fn from(transform: wl_output::Transform) -> Self {
+        match transform {
+            wl_output::Transform::_90 => Transform::Rotate90,
+            wl_output::Transform::_180 => Transform::Rotate180,
+            wl_output::Transform::_270 => Transform::Rotate270,
+            wl_output::Transform::Flipped => Transform::Flipped,
+            wl_output::Transform::Flipped90 => Transform::Flipped90,
+            wl_output::Transform::Flipped180 => Transform::Flipped180,
+            wl_output::Transform::Flipped270 => Transform::Flipped270,
+            _ => Transform::Normal,
+        }
+    }
+}
+
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Transform::Normal,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    //Quoting documentation: "Note that if the transform value includes 90 or 270 degree
+    //rotation, the width of the buffer will become the surface height and the height of the
+    //buffer will become the surface width."
+    pub fn swaps_axes(self) -> bool {
+        matches!(
+            self,
+            Transform::Rotate90
+                | Transform::Rotate270
+                | Transform::Flipped90
+                | Transform::Flipped270
+        )
+    }
+
+    //Surface size from buffer size, or the other way around: the same swap.
+    pub fn apply_to_size(self, (width, height): (u32, u32)) -> (u32, u32) {
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    //What undoes it. Flips are their own inverse, and so are the flipped rotations.
+    pub fn inverse(self) -> Transform {
+        match self {
+            Transform::Rotate90 => Transform::Rotate270,
+            Transform::Rotate270 => Transform::Rotate90,
+            transform => transform,
+        }
+    }
+
+    //The buffer pixel shown at pixel (x, y) of a surface of `surface_size`. The same mapping
+    //Weston (weston_transformed_coord) and wlroots use, on pixel indices instead of edges.
+    pub fn surface_to_buffer(self, (x, y): (u32, u32), surface_size: (u32, u32)) -> (u32, u32) {
+        let (last_x, last_y) = (surface_size.0 - 1, surface_size.1 - 1);
+        match self {
+            Transform::Normal => (x, y),
+            Transform::Rotate90 => (y, last_x - x),
+            Transform::Rotate180 => (last_x - x, last_y - y),
+            Transform::Rotate270 => (last_y - y, x),
+            Transform::Flipped => (last_x - x, y),
+            Transform::Flipped90 => (y, x),
+            Transform::Flipped180 => (x, last_y - y),
+            Transform::Flipped270 => (last_y - y, last_x - x),
+        }
+    }
+
+    //Where buffer pixel (x, y) of a buffer of `buffer_size` shows on the surface: what to draw
+    //where, for content to come out upright.
+    pub fn buffer_to_surface(self, point: (u32, u32), buffer_size: (u32, u32)) -> (u32, u32) {
+        self.inverse().surface_to_buffer(point, buffer_size)
+    }
+}
+
+impl AppState {
+    //The surface in surface coordinates: the buffer, swapped for 90 and 270 degree transforms.
+    //Window geometry, viewport destinations and input all live here, the buffer size is only
+    //what gets drawn. At a buffer scale it's that many times smaller, see preferred.rs. While a
+    //resize preview stretches the buffer, the size it's stretched to (resize_preview.rs).
+    pub(crate) fn surface_size(&self) -> (u32, u32) {
+        if let Some(size) = self.resize_preview.surface {
+            return size;
+        }
+        let size = self.buffer_transform.apply_to_size(self.buffer_size);
+        self.scale_math().size_to_logical(size)
+    }
+
+    //Damages a whole buffer of `size`. damage_buffer (wl_surface v4) takes buffer coordinates
+    //as they are, plain damage wants them in surface coordinates.
+    pub(crate) fn damage_buffer(&self, (width, height): (i32, i32)) {
+        let surface = self.base_surface.as_ref().unwrap();
+        if surface.version() >= 4 {
+            surface.damage_buffer(0, 0, width, height);
+        } else {
+            let size = self
+                .buffer_transform
+                .apply_to_size((width as u32, height as u32));
+            let (width, height) =
+                ScaleMath::integer(self.preferred.sent_scale).size_to_logical(size);
+            surface.damage(0, 0, width as i32, height as i32);
+        }
+    }
+
+    //Only those parts of a buffer of `size`, in buffer pixels. Before version 4 damage is in
+    //surface coordinates, all of it is damaged then.
+    pub(crate) fn damage_buffer_rects(&self, rects: &[Rect], size: (i32, i32)) {
+        let surface = self.base_surface.as_ref().unwrap();
+        if surface.version() < 4 {
+            self.damage_buffer(size);
+            return;
+        }
+        for rect in rects {
+            surface.damage_buffer(rect.x, rect.y, rect.width, rect.height);
+        }
+    }
+}
+
+impl Window {
+    //Tells the compositor how the buffer content is rotated or flipped. With 90 and 270 degree
+    //transforms the window's size becomes the buffer's height x width: the shm buffer keeps its
+    //size and the window turns around it, EGL and external renderers get a Resized and swap
+    //their buffer instead (buffer_size() has the new one). Canvas and capture_to_vec stay in
+    //buffer coordinates, see Transform::buffer_to_surface for drawing into them.
+    pub fn set_buffer_transform(&mut self, transform: Transform) -> Result<(), WindowError> {
+        let Some(surface) = self.state.base_surface.as_ref() else {
+            return Err(WindowError::NotConfigured);
+        };
+        if transform == self.state.buffer_transform {
+            return Ok(());
+        }
+        if surface.version() < 2 {
+            return Err(WindowError::Unsupported("wl_surface.set_buffer_transform"));
+        }
+
+        //Quoting documentation: "Buffer transform is double-buffered state, see
+        //wl_surface.commit."
+        surface.set_buffer_transform(transform.into());
+        let size = self.size();
+        let swap = transform.swaps_axes() != self.state.buffer_transform.swaps_axes();
+        self.state.buffer_transform = transform;
+
+        if self.state.render_mode != RenderMode::Shm {
+            //Same window, the buffer turns. The transform goes out with the renderer's next
+            //commit, together with the geometry.
+            if swap {
+                self.state.resize_client_rendered(size, true);
+            }
+            return Ok(());
+        }
+        if self.state.configured() {
+            //The geometry turns with the surface, and goes out in the same commit.
+            let queue_handle = self.event_queue.handle();
+            self.state.apply_window_geometry();
+            self.state.apply_input_region(&queue_handle);
+            if self.state.main_buffer_shown() {
+                self.state.present_gradient(&queue_handle);
+            } else {
+                self.state.commit_state(&queue_handle);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn buffer_transform(&self) -> Transform {
+        self.state.buffer_transform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_land_where_the_rotation_puts_them() {
+        //A 3x2 surface. Its top left corner goes to the buffer's...
+        let corner = |transform: Transform| transform.surface_to_buffer((0, 0), (3, 2));
+        assert_eq!(corner(Transform::Normal), (0, 0));
+        //...bottom left when the content was turned counter-clockwise (buffer 2x3),
+        assert_eq!(corner(Transform::Rotate90), (0, 2));
+        assert_eq!(corner(Transform::Rotate180), (2, 1));
+        //...top right when it was turned clockwise,
+        assert_eq!(corner(Transform::Rotate270), (1, 0));
+        //...top right when it was mirrored.
+        assert_eq!(corner(Transform::Flipped), (2, 0));
+        assert_eq!(corner(Transform::Flipped90), (0, 0));
+        assert_eq!(corner(Transform::Flipped180), (0, 1));
+        assert_eq!(corner(Transform::Flipped270), (1, 2));
+    }
+
+    #[test]
+    fn mappings_are_bijections_and_invert() {
+        let surface_size = (5, 3);
+        for transform in Transform::ALL {
+            let buffer_size = transform.apply_to_size(surface_size);
+            let mut seen = [false; 15];
+            for y in 0..surface_size.1 {
+                for x in 0..surface_size.0 {
+                    let (bx, by) = transform.surface_to_buffer((x, y), surface_size);
+                    assert!(bx < buffer_size.0 && by < buffer_size.1, "{transform:?}");
+                    assert!(!seen[(by * buffer_size.0 + bx) as usize], "{transform:?}");
+                    seen[(by * buffer_size.0 + bx) as usize] = true;
+                    assert_eq!(
+                        transform.buffer_to_surface((bx, by), buffer_size),
+                        (x, y),
+                        "{transform:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//Pointer buttons repeating while held, like keys: the arrows of a scrollbar, the buttons of a spin
+//box. Off until Window::pointer_button_repeat, and then only in the regions the application
+//registered with set_repeat_regions, each for one button.
+//
+//Like key repeats, PointerButtonRepeat events come from the EventLoop's calloop timers (the calloop
+//feature), pump_events has none to drive them. A press in a region repeats until the
+//button is released, the pointer leaves the region or the surface, or the compositor takes the
+//pointer for a move or resize: it then gets no release, the Leave may come after the grab ends.
+
+use std::{sync::Arc, time::Duration};
+
+use wayland_client::protocol::wl_pointer::WlPointer;
+
+use crate::{AppState, Rect, Window};
+
+//The press repeating.
+pub(crate) struct HeldButton {
+    pointer: WlPointer,
+    #[cfg_attr(not(feature = "calloop"), allow(dead_code))]
+    pub(crate) seat: Arc<str>,
+    pub(crate) button: u32,
+    //Told apart from the previous press of the same button by it.
+    #[cfg_attr(not(feature = "calloop"), allow(dead_code))]
+    pub(crate) serial: u32,
+    pub(crate) position: (f64, f64),
+    region: Rect,
+}
+
+#[derive(Default)]
+pub(crate) struct ButtonRepeatState {
+    //Delay and interval, None while off.
+    pub(crate) timing: Option<(Duration, Duration)>,
+    regions: Vec<(u32, Rect)>,
+    pub(crate) held: Option<HeldButton>,
+}
+
+impl ButtonRepeatState {
+    //The setting and regions, for Window::reconnect. What was held went with the old seats.
+    pub(crate) fn for_reconnect(&mut self) -> ButtonRepeatState {
+        ButtonRepeatState {
+            timing: self.timing,
+            regions: std::mem::take(&mut self.regions),
+            held: None,
+        }
+    }
+}
+
+fn contains(rect: &Rect, (x, y): (f64, f64)) -> bool {
+    x >= rect.x as f64
+        && y >= rect.y as f64
+        && x < rect.x as f64 + rect.width as f64
+        && y < rect.y as f64 + rect.height as f64
+}
+
+impl AppState {
+    //A button the application got (not one the decorations took). A press inside a region for
+    //that button starts repeating, its release stops.
+    pub(crate) fn track_button_repeat(
+        &mut self,
+        pointer: &WlPointer,
+        seat: Arc<str>,
+        serial: u32,
+        button: u32,
+        pressed: bool,
+    ) {
+        let repeat = &mut self.button_repeat;
+        if !pressed {
+            if repeat
+                .held
+                .as_ref()
+                .is_some_and(|held| &held.pointer == pointer && held.button == button)
+            {
+                repeat.held = None;
+            }
+            return;
+        }
+        if repeat.timing.is_none() {
+            return;
+        }
+        let Some(position) = self.pointer_position(pointer) else {
+            return;
+        };
+        let repeat = &mut self.button_repeat;
+        //The last press wins, like keys.
+        if let Some(&(_, region)) = repeat
+            .regions
+            .iter()
+            .find(|&&(b, ref region)| b == button && contains(region, position))
+        {
+            repeat.held = Some(HeldButton {
+                pointer: pointer.clone(),
+                seat,
+                button,
+                serial,
+                position,
+                region,
+            });
+        }
+    }
+
+    //Motion: the repeats follow the pointer, and stop when it leaves the region.
+    pub(crate) fn button_repeat_moved(&mut self, pointer: &WlPointer, position: (f64, f64)) {
+        let repeat = &mut self.button_repeat;
+        let Some(ref mut held) = repeat.held else {
+            return;
+        };
+        if &held.pointer != pointer {
+            return;
+        }
+        if contains(&held.region, position) {
+            held.position = position;
+        } else {
+            repeat.held = None;
+        }
+    }
+
+    //The pointer left the surface or went away, or a grab took it: None for any pointer.
+    pub(crate) fn stop_button_repeat(&mut self, pointer: Option<&WlPointer>) {
+        let repeat = &mut self.button_repeat;
+        if repeat
+            .held
+            .as_ref()
+            .is_some_and(|held| pointer.is_none_or(|pointer| &held.pointer == pointer))
+        {
+            repeat.held = None;
+        }
+    }
+}
+
+impl Window {
+    //Turns button repeats on: a button held in one of its regions (set_repeat_regions) sends
+    //PointerButtonRepeat `delay` after the press, then every `interval`. Through the EventLoop
+    //only, like key repeats. A zero interval is taken as 1 ms.
+    pub fn pointer_button_repeat(&mut self, delay: Duration, interval: Duration) {
+        let interval = interval.max(Duration::from_millis(1));
+        self.state.button_repeat.timing = Some((delay, interval));
+    }
+
+    //Turns them off again, a held button stops repeating.
+    pub fn disable_pointer_button_repeat(&mut self) {
+        self.state.button_repeat.timing = None;
+        self.state.button_repeat.held = None;
+    }
+
+    //Where buttons repeat: each rectangle (surface-local) with the button that repeats in it,
+    //0x110 (BTN_LEFT) for the usual scrollbar arrow. Replaces the previous ones; a button held in
+    //a region that's gone stops.
+    pub fn set_repeat_regions(&mut self, regions: &[(u32, Rect)]) {
+        let repeat = &mut self.state.button_repeat;
+        repeat.regions = regions.to_vec();
+        if let Some(ref held) = repeat.held
+            && !repeat
+                .regions
+                .iter()
+                .any(|&(button, region)| button == held.button && region == held.region)
+        {
+            repeat.held = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_include_their_top_left_edge_only() {
+        let rect = Rect::new(10, 20, 16, 16);
+        assert!(contains(&rect, (10.0, 20.0)));
+        assert!(contains(&rect, (25.5, 35.5)));
+        assert!(!contains(&rect, (26.0, 30.0)));
+        assert!(!contains(&rect, (15.0, 19.9)));
+    }
+}
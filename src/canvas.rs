@@ -0,0 +1,426 @@
+use std::{fs::File, io, ptr, ptr::NonNull, slice};
+
+use rustix::mm::{MapFlags, ProtFlags, mmap, munmap};
+use tempfile::tempfile;
+
+use crate::{GradientView, Rect, Window};
+
+//A pixel as our Argb8888 buffers store it. Wayland's Argb8888 is *premultiplied*: the color
+//channels are already scaled by alpha, so 50% transparent white is (128, 128, 128, 128), not
+//(255, 255, 255, 128). Writing straight alpha there makes the compositor add the full color on
+//top of what's behind, which shows as bright fringes around every soft edge.
+//
+//So no channel can be bigger than alpha. from_unpremultiplied does the multiply for colors as
+//humans (and image files) write them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Color {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+impl Color {
+    pub const TRANSPARENT: Color = Color::premultiplied(0, 0, 0, 0);
+
+    pub const fn opaque(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 0xFF }
+    }
+
+    //Channels already multiplied by alpha. With the alpha-checks feature, anything brighter than
+    //its alpha is caught in debug builds.
+    pub const fn premultiplied(r: u8, g: u8, b: u8, a: u8) -> Color {
+        #[cfg(feature = "alpha-checks")]
+        debug_assert!(
+            r <= a && g <= a && b <= a,
+            "premultiplied color with a channel above alpha"
+        );
+        Color { r, g, b, a }
+    }
+
+    pub const fn from_unpremultiplied(r: u8, g: u8, b: u8, a: u8) -> Color {
+        //Rounded x * a / 255.
+        const fn multiply(channel: u8, a: u8) -> u8 {
+            ((channel as u16 * a as u16 + 127) / 255) as u8
+        }
+        Color {
+            r: multiply(r, a),
+            g: multiply(g, a),
+            b: multiply(b, a),
+            a,
+        }
+    }
+
+    //Argb8888 is little endian, so the bytes are B, G, R, A.
+    pub const fn to_argb8888_bytes(self) -> [u8; 4] {
+        [self.b, self.g, self.r, self.a]
+    }
+
+    //The same as one u32 in memory, whatever the CPU's byte order, for writing pixels a word at a
+    //time.
+    const fn to_word(self) -> u32 {
+        u32::from_ne_bytes(self.to_argb8888_bytes())
+    }
+}
+
+//Pixel bytes as u32 words, when they're aligned for it (mapped memory and Vecs of pixels always
+//are). Any bit pattern is a valid u32, so the cast itself can't go wrong.
+fn as_words(bytes: &mut [u8]) -> Option<&mut [u32]> {
+    //SAFETY: u32 has no invalid values, align_to_mut only hands out the aligned middle.
+    let (head, words, tail) = unsafe { bytes.align_to_mut::<u32>() };
+    (head.is_empty() && tail.is_empty()).then_some(words)
+}
+
+//Sets Argb8888 bytes to one color: slice::fill on words, byte chunks if they're not aligned.
+fn fill_pixels(bytes: &mut [u8], color: Color) {
+    match as_words(bytes) {
+        Some(words) => words.fill(color.to_word()),
+        None => {
+            for pixel in bytes.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color.to_argb8888_bytes());
+            }
+        }
+    }
+}
+
+//The shm buffer's memory, mapped once when the buffer is made. Drawing writes straight into it:
+//no write() per redraw, and any pixel can be reached in any order.
+pub(crate) struct MappedFile {
+    file: File,
+    data: NonNull<u8>,
+    len: usize,
+}
+
+//The mapping is ours alone (the compositor has its own), owned like a Vec's memory.
+unsafe impl Send for MappedFile {}
+
+impl MappedFile {
+    pub(crate) fn new(len: usize) -> io::Result<MappedFile> {
+        let file = tempfile()?;
+        file.set_len(len as u64)?;
+        //SAFETY: a fresh shared mapping of a file just sized to `len`, unmapped on drop.
+        let data = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED,
+                &file,
+                0,
+            )?
+        };
+        Ok(MappedFile {
+            file,
+            data: NonNull::new(data.cast()).unwrap(),
+            len,
+        })
+    }
+
+    //Makes the file (and the mapping) bigger, keeping the content. The mapping moves, nothing
+    //may hold on to the old one.
+    pub(crate) fn grow(&mut self, len: usize) -> io::Result<()> {
+        if len <= self.len {
+            return Ok(());
+        }
+        self.file.set_len(len as u64)?;
+        //SAFETY: the same kind of mapping as in new, of the file just grown to `len`.
+        let data = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED,
+                &self.file,
+                0,
+            )?
+        };
+        //SAFETY: the old mapping, &mut self means nothing borrows it.
+        let _ = unsafe { munmap(self.data.as_ptr().cast(), self.len) };
+        self.data = NonNull::new(data.cast()).unwrap();
+        self.len = len;
+        Ok(())
+    }
+
+    pub(crate) fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub(crate) fn bytes(&self) -> &[u8] {
+        //SAFETY: mapped for `len` bytes until drop, writes need &mut self.
+        unsafe { slice::from_raw_parts(self.data.as_ptr(), self.len) }
+    }
+
+    pub(crate) fn bytes_mut(&mut self) -> &mut [u8] {
+        //SAFETY: mapped for `len` bytes until drop, and &mut self keeps it exclusive.
+        unsafe { slice::from_raw_parts_mut(self.data.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        //SAFETY: the mapping made in new, nothing borrows it past this point.
+        let _ = unsafe { munmap(self.data.as_ptr().cast(), self.len) };
+    }
+}
+
+//Pixels to draw into, one Argb8888 picture of width x height, rows `stride` bytes apart.
+//Everything is bounds checked against the picture, so nothing draws outside it, whatever the
+//coordinates; the checks are per call (per row for the bulk ones), not per byte.
+pub struct Canvas<'a> {
+    width: u32,
+    height: u32,
+    stride: usize,
+    scale: i32,
+    pixels: &'a mut [u8],
+}
+
+impl<'a> Canvas<'a> {
+    //A canvas over memory of your own, e.g. a mapped buffer for present_buffer. None if `pixels`
+    //is too small for height rows of `stride` bytes, or a row doesn't fit in `stride`.
+    pub fn from_bytes(
+        pixels: &'a mut [u8],
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Option<Canvas<'a>> {
+        let row = width as usize * 4;
+        let needed = match height {
+            0 => 0,
+            height => stride.checked_mul(height as usize - 1)?.checked_add(row)?,
+        };
+        (row <= stride && needed <= pixels.len()).then_some(Canvas {
+            width,
+            height,
+            stride,
+            scale: 1,
+            pixels,
+        })
+    }
+
+    //The same canvas, shown at `scale` physical pixels per canvas pixel.
+    pub fn with_scale(self, scale: i32) -> Canvas<'a> {
+        Canvas { scale, ..self }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    //Bytes from one row to the next.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    //How many physical pixels a canvas pixel ends up as, each way. For the window's canvases
+    //that's the scale of the outputs it is on (see WindowStateSnapshot::scale_factor) over the
+    //buffer scale (Window::buffer_scale): 1 once the preferred scale is applied. 1 for canvases
+    //from from_bytes.
+    pub fn scale(&self) -> i32 {
+        self.scale
+    }
+
+    //Row y as bytes, B, G, R, A per pixel. None out of bounds.
+    pub fn row_mut(&mut self, y: u32) -> Option<&mut [u8]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = y as usize * self.stride;
+        Some(&mut self.pixels[start..start + self.width as usize * 4])
+    }
+
+    //Out of bounds pixels are ignored.
+    pub fn put_pixel(&mut self, x: u32, y: u32, color: Color) {
+        if x >= self.width {
+            return;
+        }
+        if let Some(row) = self.row_mut(y) {
+            let offset = x as usize * 4;
+            row[offset..offset + 4].copy_from_slice(&color.to_argb8888_bytes());
+        }
+    }
+
+    //For straight alpha colors, premultiplied on the way in.
+    pub fn put_pixel_unpremultiplied(&mut self, x: u32, y: u32, r: u8, g: u8, b: u8, a: u8) {
+        self.put_pixel(x, y, Color::from_unpremultiplied(r, g, b, a));
+    }
+
+    //The part of `rect` inside the canvas.
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let clamp = |value: i64, max: u32| value.clamp(0, i64::from(max)) as usize;
+        let (x0, x1) = (
+            clamp(i64::from(rect.x), self.width),
+            clamp(i64::from(rect.x) + i64::from(rect.width), self.width),
+        );
+        let (y0, y1) = (
+            clamp(i64::from(rect.y), self.height),
+            clamp(i64::from(rect.y) + i64::from(rect.height), self.height),
+        );
+        for y in y0..y1 {
+            let row = self.row_mut(y as u32).unwrap();
+            fill_pixels(&mut row[x0 * 4..x1 * 4], color);
+        }
+    }
+
+    //The whole canvas in one color. Rows without padding between them are one fill.
+    pub fn clear(&mut self, color: Color) {
+        let row = self.width as usize * 4;
+        if self.stride == row {
+            let len = row * self.height as usize;
+            fill_pixels(&mut self.pixels[..len], color);
+        } else {
+            for y in 0..self.height {
+                fill_pixels(self.row_mut(y).unwrap(), color);
+            }
+        }
+    }
+
+    //The window's own content: a gradient, red from the top left, green from the top right and
+    //blue from the bottom left. Every pixel is first mapped through the view (zoom around the
+    //center, then pan) to the point of the gradient it shows, clamped so zooming out just
+    //stretches the edges.
+    //
+    //Both the mapping and the channels split by axis: a channel is the smaller of a falloff that
+    //only depends on the column and one that only depends on the row. Those are worked out once
+    //per column and once per row, and a pixel is table reads, mins and one word store. No
+    //division per pixel, which is what made a 4K redraw stall configure handling.
+    pub fn gradient(&mut self, view: &GradientView) {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return;
+        }
+        let sample = |pos: u32, len: u32, pan: f64| {
+            let center = f64::from(len) / 2.0;
+            let pos = (f64::from(pos) - center) / view.zoom + center - pan;
+            (pos.max(0.0) as u32).min(len - 1)
+        };
+
+        //Per column: the falloff from the left edge (red, blue) and from the right one (green).
+        let columns: Vec<(u32, u32)> = (0..width)
+            .map(|x| {
+                let x = sample(x, width, view.pan.0);
+                (((width - x) * 0xFF) / width, (x * 0xFF) / width)
+            })
+            .collect();
+
+        for y in 0..height {
+            let sy = sample(y, height, view.pan.1);
+            let (top, bottom) = (((height - sy) * 0xFF) / height, (sy * 0xFF) / height);
+            //Fully opaque, so premultiplying changes nothing.
+            let color = |&(left, right): &(u32, u32)| {
+                Color::opaque(
+                    left.min(top) as u8,
+                    right.min(top) as u8,
+                    left.min(bottom) as u8,
+                )
+            };
+
+            let row = self.row_mut(y).unwrap();
+            match as_words(row) {
+                Some(words) => {
+                    for (word, column) in words.iter_mut().zip(&columns) {
+                        *word = color(column).to_word();
+                    }
+                }
+                None => {
+                    for (pixel, column) in row.chunks_exact_mut(4).zip(&columns) {
+                        pixel.copy_from_slice(&color(column).to_argb8888_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    //Copies Argb8888 bytes to the start of a row, as many as fit in it.
+    pub fn copy_from_slice(&mut self, row: u32, bytes: &[u8]) {
+        if let Some(row) = self.row_mut(row) {
+            let len = row.len().min(bytes.len());
+            row[..len].copy_from_slice(&bytes[..len]);
+        }
+    }
+}
+
+impl Window {
+    //Replaces the window content with whatever `draw` paints, until the next draw (or
+    //redraw_background, which brings the renderer's back, see renderer.rs). The canvas starts
+    //fully transparent. The buffer only exists from the first configure on and is made anew for
+    //every new size: draw on WindowEvent::Resized, before the first one this does nothing.
+    //With BufferFormat::Xrgb8888 the alpha channel is ignored and the window stays opaque.
+    //With set_skip_identical_frames, a picture identical to the one on screen isn't committed.
+    pub fn draw(&mut self, draw: impl FnOnce(&mut Canvas)) {
+        if self.state.shm_pixels.is_none() {
+            return;
+        }
+        self.wait_for_free_buffer();
+        self.state.paint(|canvas| {
+            canvas.clear(Color::TRANSPARENT);
+            draw(canvas);
+        });
+        self.state.drawn_by_app = true;
+        self.state.fade_shm_buffer();
+
+        if self.state.configured() {
+            let queue_handle = self.event_queue.handle();
+            self.state.present_drawn_frame(&queue_handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::min;
+
+    use super::*;
+
+    //The gradient as it was computed before the tables, pixel by pixel.
+    fn reference(x: u32, y: u32, (width, height): (u32, u32), view: &GradientView) -> [u8; 4] {
+        let sample = |pos: u32, len: u32, pan: f64| {
+            let center = f64::from(len) / 2.0;
+            let pos = (f64::from(pos) - center) / view.zoom + center - pan;
+            (pos.max(0.0) as u32).min(len - 1)
+        };
+        let (x, y) = (sample(x, width, view.pan.0), sample(y, height, view.pan.1));
+        let r = min(((width - x) * 0xFF) / width, ((height - y) * 0xFF) / height);
+        let g = min((x * 0xFF) / width, ((height - y) * 0xFF) / height);
+        let b = min(((width - x) * 0xFF) / width, (y * 0xFF) / height);
+        Color::opaque(r as u8, g as u8, b as u8).to_argb8888_bytes()
+    }
+
+    #[test]
+    fn gradient_matches_the_per_pixel_one() {
+        let (width, height) = (37, 23);
+        let view = GradientView {
+            zoom: 1.7,
+            pan: (3.5, -2.0),
+        };
+        //One byte in, so the rows aren't aligned and the byte path runs too.
+        for offset in [0, 1] {
+            let mut bytes = vec![0; width as usize * 4 * height as usize + offset];
+            let mut canvas =
+                Canvas::from_bytes(&mut bytes[offset..], width, height, width as usize * 4)
+                    .unwrap();
+            canvas.gradient(&view);
+            for y in 0..height {
+                let row = canvas.row_mut(y).unwrap().to_vec();
+                for x in 0..width {
+                    let at = x as usize * 4;
+                    assert_eq!(row[at..at + 4], reference(x, y, (width, height), &view));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clear_leaves_the_padding_alone() {
+        let mut bytes = vec![0xAA; 3 * 12];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 2, 3, 12).unwrap();
+        canvas.clear(Color::opaque(1, 2, 3));
+        for row in bytes.chunks(12) {
+            assert_eq!(row[..8], [3, 2, 1, 0xFF, 3, 2, 1, 0xFF]);
+            assert_eq!(row[8..], [0xAA; 4]);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//What the compositor supports, for applications to decide before making any UI: decorations of
+//their own or the compositor's, fractional scaling or not, cursor shapes or cursor images...
+//Window::capabilities reads the globals the window saw, Capabilities::probe connects just for
+//that and makes no window (the example's --probe).
+//
+//Each field is the version the compositor advertises, None when it doesn't. Versions are the
+//compositor's, the window binds at most the versions it knows. Some are protocols the window
+//doesn't use itself (layer shell, text input...), listed for applications binding them on a
+//queue of their own. Anything else the compositor has is in `others`.
+
+use std::fmt;
+
+use wayland_client::{
+    Connection, Dispatch, QueueHandle,
+    protocol::wl_registry::{self, WlRegistry},
+};
+
+use crate::{ConnectOptions, Window, WindowError};
+
+macro_rules! capabilities {
+    ($($field:ident: $interface:literal,)+) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Default)]
+        pub struct Capabilities {
+            $(pub $field: Option<u32>,)+
+            //Advertised, and none of the above.
+            pub others: Vec<(String, u32)>,
+        }
+
+        impl Capabilities {
+            //From (interface, version) pairs, as Window::advertised_globals has them. The highest
+            //version wins when an interface comes more than once.
+            pub fn from_globals(globals: &[(String, u32)]) -> Capabilities {
+                let mut capabilities = Capabilities::default();
+                for (interface, version) in globals {
+                    let field = match interface.as_str() {
+                        $($interface => &mut capabilities.$field,)+
+                        _ => {
+                            capabilities.others.push((interface.clone(), *version));
+                            continue;
+                        }
+                    };
+                    *field = (*field).max(Some(*version));
+                }
+                capabilities
+            }
+
+            //(interface, version) for every field, in order.
+            pub fn known(&self) -> Vec<(&'static str, Option<u32>)> {
+                vec![$(($interface, self.$field),)+]
+            }
+        }
+    };
+}
+
+capabilities! {
+    //What a window can't do without.
+    compositor: "wl_compositor",
+    shm: "wl_shm",
+    xdg_wm_base: "xdg_wm_base",
+    //Destroying the registry when the window goes, see cleanup.rs.
+    fixes: "wl_fixes",
+    //Subsurfaces, for the diagnostic overlay.
+    subcompositor: "wl_subcompositor",
+    //Input and outputs.
+    seat: "wl_seat",
+    output: "wl_output",
+    data_device_manager: "wl_data_device_manager",
+    //Window management.
+    xdg_decoration: "zxdg_decoration_manager_v1",
+    xdg_dialog: "xdg_wm_dialog_v1",
+    xdg_activation: "xdg_activation_v1",
+    xdg_exporter: "zxdg_exporter_v2",
+    xdg_importer: "zxdg_importer_v2",
+    toplevel_icon: "xdg_toplevel_icon_manager_v1",
+    layer_shell: "zwlr_layer_shell_v1",
+    //Scaling and presentation.
+    fractional_scale: "wp_fractional_scale_manager_v1",
+    viewporter: "wp_viewporter",
+    presentation: "wp_presentation",
+    single_pixel_buffer: "wp_single_pixel_buffer_manager_v1",
+    alpha_modifier: "wp_alpha_modifier_v1",
+    content_type: "wp_content_type_manager_v1",
+    tearing_control: "wp_tearing_control_manager_v1",
+    color_manager: "wp_color_manager_v1",
+    //Buffers.
+    linux_dmabuf: "zwp_linux_dmabuf_v1",
+    drm_syncobj: "wp_linux_drm_syncobj_manager_v1",
+    //Pointer, keyboard and text.
+    cursor_shape: "wp_cursor_shape_manager_v1",
+    pointer_constraints: "zwp_pointer_constraints_v1",
+    relative_pointer: "zwp_relative_pointer_manager_v1",
+    pointer_gestures: "zwp_pointer_gestures_v1",
+    keyboard_shortcuts_inhibit: "zwp_keyboard_shortcuts_inhibit_manager_v1",
+    text_input: "zwp_text_input_manager_v3",
+    idle_notifier: "ext_idle_notifier_v1",
+    idle_inhibit: "zwp_idle_inhibit_manager_v1",
+}
+
+impl Capabilities {
+    //Connects, lists the globals and disconnects, without a window.
+    pub fn probe(options: ConnectOptions) -> Result<Capabilities, WindowError> {
+        let connection = options.connect()?;
+        let mut event_queue = connection.new_event_queue();
+        connection.display().get_registry(&event_queue.handle(), ());
+        let mut probe = Probe::default();
+        event_queue
+            .roundtrip(&mut probe)
+            .map_err(|err| WindowError::Connection(err.to_string()))?;
+        Ok(Capabilities::from_globals(&probe.globals))
+    }
+
+    //Whether a window can be made: wl_shm is only needed for buffers of ours.
+    pub fn has_required(&self) -> bool {
+        self.compositor.is_some() && self.xdg_wm_base.is_some()
+    }
+}
+
+//A table, one protocol per line, "-" for the missing ones.
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let known = self.known();
+        let width = known
+            .iter()
+            .map(|(interface, _)| interface.len())
+            .chain(self.others.iter().map(|(interface, _)| interface.len()))
+            .max()
+            .unwrap_or(0);
+        writeln!(f, "{:width$}  version", "protocol")?;
+        for (interface, version) in known {
+            match version {
+                Some(version) => writeln!(f, "{interface:width$}  {version}")?,
+                None => writeln!(f, "{interface:width$}  -")?,
+            }
+        }
+        if !self.others.is_empty() {
+            writeln!(f, "\nalso advertised:")?;
+            for (interface, version) in &self.others {
+                writeln!(f, "{interface:width$}  {version}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Probe {
+    globals: Vec<(String, u32)>,
+}
+
+impl Dispatch<WlRegistry, ()> for Probe {
+    fn event(
+        probe: &mut Self,
+        _: &WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            interface, version, ..
+        } = event
+        {
+            probe.globals.push((interface, version));
+        }
+    }
+}
+
+impl Window {
+    //From the globals the window saw, see capabilities.rs.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_globals(&self.advertised_globals())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globals_go_to_their_field() {
+        let globals = [
+            ("wl_compositor".to_string(), 6),
+            ("xdg_wm_base".to_string(), 6),
+            ("wl_output".to_string(), 2),
+            ("wl_output".to_string(), 4),
+            ("zwlr_layer_shell_v1".to_string(), 4),
+            ("org_kde_kwin_blur_manager".to_string(), 1),
+        ];
+        let capabilities = Capabilities::from_globals(&globals);
+        assert_eq!(capabilities.compositor, Some(6));
+        assert_eq!(capabilities.output, Some(4));
+        assert_eq!(capabilities.layer_shell, Some(4));
+        assert_eq!(capabilities.shm, None);
+        assert!(capabilities.has_required());
+        assert_eq!(
+            capabilities.others,
+            [("org_kde_kwin_blur_manager".to_string(), 1)]
+        );
+
+        let table = capabilities.to_string();
+        let line = |interface: &str| {
+            table
+                .lines()
+                .find(|line| line.split_whitespace().next() == Some(interface))
+                .map(|line| line.split_whitespace().nth(1).unwrap().to_string())
+        };
+        assert_eq!(line("wl_compositor").as_deref(), Some("6"));
+        assert_eq!(line("wl_shm").as_deref(), Some("-"));
+        assert_eq!(line("org_kde_kwin_blur_manager").as_deref(), Some("1"));
+    }
+}
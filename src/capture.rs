@@ -0,0 +1,99 @@
+//Reading back what the window shows, for debugging and tests. The shm memory is ours, so this
+//needs no protocol: it's a copy of the mapping.
+//
+//What's in the mapping is what the compositor shows as long as the last attach was the shm
+//buffer: every draw into it is committed right away once configured. Anything attached since (a
+//solid color, present_buffer, EGL's buffers) means the mapping is stale, so `attached` is checked
+//first. A Release of the buffer doesn't change that, it only means the compositor made its own
+//copy and keeps showing that.
+
+#[cfg(feature = "image")]
+use std::path::Path;
+
+use crate::{AppState, BufferFormat, Window, WindowError};
+
+//Argb8888 (premultiplied, B G R A in memory) or Xrgb8888 to straight alpha R G B A.
+pub(crate) fn to_rgba(pixels: &[u8], format: BufferFormat) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(pixels.len());
+    for pixel in pixels.chunks_exact(4) {
+        let [b, g, r, a] = pixel.try_into().unwrap();
+        match format {
+            //The alpha byte is padding, the compositor shows the pixel opaque.
+            BufferFormat::Xrgb8888 => rgba.extend([r, g, b, 0xFF]),
+            //Nothing to recover under a fully transparent pixel.
+            BufferFormat::Argb8888 if a == 0 => rgba.extend([0; 4]),
+            BufferFormat::Argb8888 => {
+                //Rounded channel * 255 / a. Channels above alpha (broken premultiplication)
+                //saturate.
+                let divide = |channel: u8| {
+                    ((u32::from(channel) * 255 + u32::from(a) / 2) / u32::from(a)).min(255) as u8
+                };
+                rgba.extend([divide(r), divide(g), divide(b), a]);
+            }
+        }
+    }
+    rgba
+}
+
+impl AppState {
+    fn capture(&self) -> Result<Vec<u8>, WindowError> {
+        if !self.configured() {
+            return Err(WindowError::NotConfigured);
+        }
+        //The window's buffer or one of its spares, see swapchain.rs.
+        let (Some(pixels), Some((_, layout))) = (self.shown_pixels(), &self.shm_pixels) else {
+            return Err(WindowError::NoContent);
+        };
+        //Rows in the buffer are padded to the stride, see buffer_layout.rs.
+        Ok(to_rgba(&layout.copy_rows_out(pixels.bytes()), self.format))
+    }
+}
+
+impl Window {
+    //The window content as last committed: buffer_size() pixels, row by row, R G B A with
+    //straight (not premultiplied) alpha. Fails with NoContent when the window shows something
+    //else than our shm buffer.
+    pub fn capture_to_vec(&self) -> Result<Vec<u8>, WindowError> {
+        self.state.capture()
+    }
+
+    //The same as a PNG file.
+    #[cfg(feature = "image")]
+    pub fn capture_to_png(&self, path: impl AsRef<Path>) -> Result<(), WindowError> {
+        let path = path.as_ref();
+        let (width, height) = self.state.buffer_size;
+        image::save_buffer_with_format(
+            path,
+            &self.state.capture()?,
+            width,
+            height,
+            image::ExtendedColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+        .map_err(|err| WindowError::Image(format!("{}: {err}", path.display())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_are_unpremultiplied() {
+        #[rustfmt::skip]
+        let pixels = [
+            0x00, 0x80, 0xFF, 0xFF,     0x40, 0x40, 0x40, 0x80,
+            0x12, 0x34, 0x56, 0x00,     0x10, 0x20, 0x30, 0x40,
+        ];
+        #[rustfmt::skip]
+        assert_eq!(to_rgba(&pixels, BufferFormat::Argb8888), [
+            0xFF, 0x80, 0x00, 0xFF,     0x80, 0x80, 0x80, 0x80,
+            0x00, 0x00, 0x00, 0x00,     0xBF, 0x80, 0x40, 0x40,
+        ]);
+        #[rustfmt::skip]
+        assert_eq!(to_rgba(&pixels, BufferFormat::Xrgb8888), [
+            0xFF, 0x80, 0x00, 0xFF,     0x40, 0x40, 0x40, 0xFF,
+            0x56, 0x34, 0x12, 0xFF,     0x30, 0x20, 0x10, 0xFF,
+        ]);
+    }
+}
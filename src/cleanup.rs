@@ -0,0 +1,123 @@
+//What the window leaves behind when it's dropped. Its connection can outlive it (with_connection,
+//the application making windows one after the other on its own connection), and every object it
+//doesn't destroy stays alive in the compositor until the connection goes, some of which cap how
+//many objects a client may have. So the drop takes down, after the surface and its roles: the
+//buffers (the pool ones with their pool), the seats with their keyboards and pointers (and what
+//follows the pointer), the outputs, xdg_wm_base and wl_shm, each with its destructor when the
+//bound version has one (wl_keyboard.release and wl_pointer.release came with version 3,
+//wl_seat.release with 5, wl_output.release with 3, wl_shm.release with 2).
+//
+//The tests check it for every window they start: in debug builds, what a window leaves behind
+//on its connection has to be no more than the registry and the globals left below
+//(TEARDOWN_BASELINE in tests/compositor).
+//
+//The registry has no destructor in the core protocol. wl_fixes (Wayland 1.23) adds one, from
+//the outside: quoting documentation, "This request destroys a wl_registry object." Without the
+//global the registry stays around, still told about every global coming and going. wl_fixes is
+//newer than the wayland-client this builds with, its XML is in protocols/ and its code made with
+//wayland-scanner. wayland-client can't drop the registry's proxy without a destructor request of
+//its own, so that one object stays on our side until the connection goes, the compositor's is
+//what counts.
+//
+//Left for the connection to take: the other globals, they're one per window and there's no
+//replacing them, and wl_compositor has no destructor anyway.
+
+use wayland_client::Proxy;
+
+use crate::AppState;
+
+pub(crate) mod protocol {
+    //The generated code looks for it as super::wayland_client.
+    #[allow(clippy::single_component_path_imports)]
+    use wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/wl-fixes.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/wl-fixes.xml");
+}
+
+//Each an Option of an object with a destroy request, taken and destroyed.
+macro_rules! destroy {
+    ($($object:expr),+ $(,)?) => {
+        $(
+            if let Some(object) = $object.take() {
+                object.destroy();
+            }
+        )+
+    };
+}
+
+impl AppState {
+    //From Window's drop, before the surface goes: the objects made for it. Some have to go
+    //first, wp_alpha_modifier_surface_v1 for one. Quoting documentation: "This object has to be
+    //destroyed before the associated wl_surface."
+    pub(crate) fn release_surface_objects(&mut self) {
+        #[cfg(feature = "diagnostic-overlay")]
+        self.hide_diagnostic_overlay();
+        self.forget_alpha_modifier_object();
+        self.forget_content_type_object();
+        self.forget_viewport();
+        #[cfg(feature = "color-management")]
+        self.forget_color_objects();
+        #[cfg(feature = "explicit-sync")]
+        self.end_explicit_sync();
+    }
+
+    //From Window's drop, once the surface and its roles are gone.
+    pub(crate) fn release_objects(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            buffer.destroy();
+        }
+        self.drop_spare_buffers();
+        self.release_buffer_allocator();
+        self.release_seats();
+        self.release_outputs();
+        self.release_idle_notifications();
+        self.release_activation();
+        #[cfg(feature = "color-management")]
+        self.release_color();
+        #[cfg(feature = "dmabuf")]
+        self.release_dmabuf();
+        destroy!(
+            self.dialog.manager,
+            self.foreign.exporter,
+            self.foreign.importer,
+            self.pointer.constraints,
+            self.relative_pointer.manager,
+            self.content_type.manager,
+            self.alpha_modifier.manager,
+            self.icon.manager,
+            self.idle.notifier,
+            self.hit_test.cursor_shape,
+            self.refresh.presentation,
+            self.viewport.viewporter,
+            self.solid_color.manager,
+            self.wm_base,
+        );
+        #[cfg(feature = "explicit-sync")]
+        destroy!(self.explicit_sync.manager);
+        #[cfg(feature = "diagnostic-overlay")]
+        destroy!(self.diagnostic_overlay.subcompositor);
+        if let Some(gestures) = self.gestures.manager.take()
+            && gestures.version() >= 2
+        {
+            gestures.release();
+        }
+        if let Some(shm) = self.shm.take()
+            && shm.version() >= 2
+        {
+            shm.release();
+        }
+        if let Some(fixes) = self.fixes.take() {
+            if let Some(registry) = self.registry.take() {
+                fixes.destroy_registry(&registry);
+            }
+            fixes.destroy();
+        }
+    }
+}
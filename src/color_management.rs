@@ -0,0 +1,717 @@
+//Color management through wp_color_manager_v1: what color spaces the compositor understands, the
+//image description of our own content (sRGB said out loud, or PQ/BT.2020 for HDR), and the one
+//the compositor would like best for the surface.
+//
+//Image descriptions are built with the parametric creator only, out of named primaries and
+//transfer functions. ICC profiles and raw chromaticities are left out.
+//
+//Everything is asynchronous: the supported features come after the bind, and a description we
+//create is only usable once the compositor sent ready. Quoting documentation: "Image descriptions
+//which are not ready (see wp_image_description_v1) are forbidden in this request, and in such case
+//the protocol error image_description is raised." So set_color_description only stores the wish,
+//it reaches the surface with the ready event.
+
+use std::mem;
+
+use wayland_client::{Connection, Dispatch, QueueHandle, WEnum, delegate_noop};
+use wayland_protocols::wp::color_management::v1::client::{
+    wp_color_management_surface_feedback_v1::{self, WpColorManagementSurfaceFeedbackV1},
+    wp_color_management_surface_v1::WpColorManagementSurfaceV1,
+    wp_color_manager_v1::{self, WpColorManagerV1},
+    wp_image_description_creator_params_v1::WpImageDescriptionCreatorParamsV1,
+    wp_image_description_info_v1::{self, WpImageDescriptionInfoV1},
+    wp_image_description_v1::{self, WpImageDescriptionV1},
+};
+
+use crate::{AppState, Window, WindowError, WindowEvent, protocol_log::protocol_log};
+
+//Our enum for one of the protocol's, both directions of the conversion from one list. Unknown
+//values from a newer compositor are dropped.
+macro_rules! named {
+    ($name:ident = $protocol:ident { $($variant:ident,)+ }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)+
+        }
+
+        impl From<$name> for wp_color_manager_v1::$protocol {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => wp_color_manager_v1::$protocol::$variant,)+
+                }
+            }
+        }
+
+        impl $name {
+            fn from_protocol(value: WEnum<wp_color_manager_v1::$protocol>) -> Option<$name> {
+                match value {
+                    $(WEnum::Value(wp_color_manager_v1::$protocol::$variant) => {
+                        Some($name::$variant)
+                    })+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+named!(
+    ColorFeature = Feature {
+        IccV2V4,
+        Parametric,
+        SetPrimaries,
+        SetTfPower,
+        SetLuminances,
+        SetMasteringDisplayPrimaries,
+        ExtendedTargetVolume,
+        WindowsScrgb,
+    }
+);
+
+named!(
+    Primaries = Primaries {
+        Srgb,
+        PalM,
+        Pal,
+        Ntsc,
+        GenericFilm,
+        Bt2020,
+        Cie1931Xyz,
+        DciP3,
+        DisplayP3,
+        AdobeRgb,
+    }
+);
+
+named!(
+    TransferFunction = TransferFunction {
+        Bt1886,
+        Gamma22,
+        Gamma28,
+        St240,
+        ExtLinear,
+        Log100,
+        Log316,
+        Xvycc,
+        Srgb,
+        ExtSrgb,
+        St2084Pq,
+        St428,
+        Hlg,
+    }
+);
+
+named!(
+    RenderIntent = RenderIntent {
+        Perceptual,
+        Relative,
+        Saturation,
+        Absolute,
+        RelativeBpc,
+    }
+);
+
+//What the compositor said it supports, all of it sent once after the bind.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorCapabilities {
+    pub features: Vec<ColorFeature>,
+    pub primaries: Vec<Primaries>,
+    pub transfer_functions: Vec<TransferFunction>,
+    pub render_intents: Vec<RenderIntent>,
+}
+
+//In cd/m². The minimum goes over the wire with 4 decimals, the others as whole numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Luminances {
+    pub min: f64,
+    pub max: u32,
+    pub reference: u32,
+}
+
+//How the pixels we draw are to be read. Named primaries and transfer function are all a
+//description needs, the rest falls back to what the transfer function implies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorDescription {
+    pub primaries: Primaries,
+    pub transfer_function: TransferFunction,
+    //Needs ColorFeature::SetLuminances.
+    pub luminances: Option<Luminances>,
+    //Min and max cd/m² of the display the content was mastered on (SMPTE ST 2086). Needs
+    //ColorFeature::SetMasteringDisplayPrimaries.
+    pub mastering_luminance: Option<(f64, u32)>,
+    //Maximum content and frame-average light level in cd/m², HDR metadata the compositor can
+    //tone map with.
+    pub max_cll: Option<u32>,
+    pub max_fall: Option<u32>,
+}
+
+impl ColorDescription {
+    //What compositors assume for surfaces without a description anyway, said explicitly.
+    pub const SRGB: ColorDescription =
+        ColorDescription::named(Primaries::Srgb, TransferFunction::Srgb);
+
+    //HDR10: BT.2020 primaries, PQ encoded. Quoting documentation: "This TF implies these default
+    //luminances - primary color volume minimum: 0.005 cd/m² - primary color volume maximum: 10000
+    //cd/m² - reference white: 203 cd/m²".
+    pub const BT2100_PQ: ColorDescription =
+        ColorDescription::named(Primaries::Bt2020, TransferFunction::St2084Pq);
+
+    pub const fn named(primaries: Primaries, transfer_function: TransferFunction) -> Self {
+        ColorDescription {
+            primaries,
+            transfer_function,
+            luminances: None,
+            mastering_luminance: None,
+            max_cll: None,
+            max_fall: None,
+        }
+    }
+
+    //Anything the compositor didn't advertise is a protocol error once sent, so it's checked
+    //before.
+    fn check(
+        &self,
+        intent: RenderIntent,
+        capabilities: &ColorCapabilities,
+    ) -> Result<(), WindowError> {
+        let feature = |feature| capabilities.features.contains(&feature);
+        if !feature(ColorFeature::Parametric) {
+            return Err(WindowError::Unsupported("parametric image descriptions"));
+        }
+        if !capabilities.primaries.contains(&self.primaries) {
+            return Err(WindowError::Unsupported("these named primaries"));
+        }
+        if !capabilities
+            .transfer_functions
+            .contains(&self.transfer_function)
+        {
+            return Err(WindowError::Unsupported("this transfer function"));
+        }
+        if !capabilities.render_intents.contains(&intent) {
+            return Err(WindowError::Unsupported("this render intent"));
+        }
+        if self.luminances.is_some() && !feature(ColorFeature::SetLuminances) {
+            return Err(WindowError::Unsupported("set_luminances"));
+        }
+        if self.mastering_luminance.is_some()
+            && !feature(ColorFeature::SetMasteringDisplayPrimaries)
+        {
+            return Err(WindowError::Unsupported("mastering luminances"));
+        }
+        //Quoting documentation: "If 'max_lum' or 'reference_lum' are less than or equal to
+        //'min_lum', the protocol error invalid_luminance is raised."
+        if let Some(luminances) = self.luminances
+            && (f64::from(luminances.max) <= luminances.min
+                || f64::from(luminances.reference) <= luminances.min)
+        {
+            return Err(WindowError::InvalidArgument(
+                "max and reference luminance must be above the minimum",
+            ));
+        }
+        if let Some((min, max)) = self.mastering_luminance
+            && f64::from(max) <= min
+        {
+            return Err(WindowError::InvalidArgument(
+                "max mastering luminance must be above the minimum",
+            ));
+        }
+        //Quoting documentation: "When both max_cll and max_fall are set, max_fall must be less or
+        //equal to max_cll."
+        if let (Some(max_cll), Some(max_fall)) = (self.max_cll, self.max_fall)
+            && max_fall > max_cll
+        {
+            return Err(WindowError::InvalidArgument(
+                "max_fall must not exceed max_cll",
+            ));
+        }
+        Ok(())
+    }
+}
+
+//The compositor's preferred image description for the surface, as wp_image_description_info_v1
+//tells it. Chromaticities are CIE 1931 xy of red, green, blue and white.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColorInfo {
+    pub primaries: [(f64, f64); 4],
+    pub named_primaries: Option<Primaries>,
+    pub transfer_function: Option<TransferFunction>,
+    //The exponent, when the transfer function is a pure power curve.
+    pub tf_power: Option<f64>,
+    pub luminances: Option<Luminances>,
+    //What the target display can actually show, None when it's the primaries above.
+    pub target_primaries: Option<[(f64, f64); 4]>,
+    pub target_luminance: Option<(f64, u32)>,
+    pub max_cll: Option<u32>,
+    pub max_fall: Option<u32>,
+}
+
+//Quoting documentation: "The minimum luminance is multiplied by 10000 to get the argument
+//'min_lum' value and carries precision of 4 decimals."
+fn min_lum_to_wire(min: f64) -> u32 {
+    (min * 10000.0).round() as u32
+}
+
+fn min_lum_from_wire(min_lum: u32) -> f64 {
+    f64::from(min_lum) / 10000.0
+}
+
+//Quoting documentation: "Each coordinate value is multiplied by 1 million to get the argument
+//value to carry precision of 6 decimals."
+fn chromaticities(values: [i32; 8]) -> [(f64, f64); 4] {
+    let xy = |i: usize| {
+        (
+            f64::from(values[i]) / 1_000_000.0,
+            f64::from(values[i + 1]) / 1_000_000.0,
+        )
+    };
+    [xy(0), xy(2), xy(4), xy(6)]
+}
+
+#[derive(Default)]
+pub(crate) struct ColorState {
+    pub(crate) manager: Option<WpColorManagerV1>,
+    //The supported_* events collect here, capabilities is set from it with done.
+    incoming: ColorCapabilities,
+    capabilities: Option<ColorCapabilities>,
+    //Per-surface objects: the first made when a description is ready to be set, the second as
+    //soon as the capabilities are known.
+    surface: Option<WpColorManagementSurfaceV1>,
+    feedback: Option<WpColorManagementSurfaceFeedbackV1>,
+    wanted: Option<(ColorDescription, RenderIntent)>,
+    //Made from `wanted`, waiting for its ready event.
+    pending: Option<WpImageDescriptionV1>,
+    //The preferred description asked for, waiting for its ready event too.
+    pending_preferred: Option<WpImageDescriptionV1>,
+    preferred: Option<ColorInfo>,
+    incoming_info: ColorInfo,
+}
+
+impl ColorState {
+    //The wanted description without the objects of the old connection, for Window::reconnect.
+    pub(crate) fn for_reconnect(&self) -> ColorState {
+        ColorState {
+            wanted: self.wanted,
+            ..ColorState::default()
+        }
+    }
+}
+
+//What an image description was made for, to know what to do with it once ready.
+pub(crate) enum DescriptionFor {
+    Surface,
+    Preferred,
+}
+
+impl AppState {
+    //Creates the description of the wanted one, set on the surface when ready. Also called when
+    //the capabilities come in and when the surface gets (re)created.
+    pub(crate) fn apply_color_description(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let color = &mut self.color;
+        let (Some(manager), Some(capabilities)) =
+            (color.manager.as_ref(), color.capabilities.as_ref())
+        else {
+            return;
+        };
+        //A description still on its way is stale now. Quoting documentation: "It is safe to
+        //destroy an object which is not ready."
+        if let Some(pending) = color.pending.take() {
+            pending.destroy();
+        }
+        let Some((description, intent)) = color.wanted else {
+            return;
+        };
+        if let Err(err) = description.check(intent, capabilities) {
+            log::warn!("color description not set: {err}");
+            return;
+        }
+
+        let creator = manager.create_parametric_creator(queue_handle, ());
+        creator.set_tf_named(description.transfer_function.into());
+        creator.set_primaries_named(description.primaries.into());
+        if let Some(luminances) = description.luminances {
+            creator.set_luminances(
+                min_lum_to_wire(luminances.min),
+                luminances.max,
+                luminances.reference,
+            );
+        }
+        if let Some((min, max)) = description.mastering_luminance {
+            creator.set_mastering_luminance(min_lum_to_wire(min), max);
+        }
+        if let Some(max_cll) = description.max_cll {
+            creator.set_max_cll(max_cll);
+        }
+        if let Some(max_fall) = description.max_fall {
+            creator.set_max_fall(max_fall);
+        }
+        color.pending = Some(creator.create(queue_handle, DescriptionFor::Surface));
+    }
+
+    //Starts following the preferred description, once there are a surface and capabilities.
+    pub(crate) fn init_color_feedback(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let color = &mut self.color;
+        let (Some(manager), Some(_), Some(surface), None) = (
+            color.manager.as_ref(),
+            color.capabilities.as_ref(),
+            self.base_surface.as_ref(),
+            color.feedback.as_ref(),
+        ) else {
+            return;
+        };
+        let feedback = manager.get_surface_feedback(surface, queue_handle, ());
+        //Quoting documentation: "This request is usually sent as a reaction to the
+        //preferred_changed event or when creating a wp_color_management_surface_feedback_v1
+        //object if the client is capable of adapting to image descriptions."
+        color.request_preferred(&feedback, queue_handle);
+        color.feedback = Some(feedback);
+    }
+
+    //The surface went away, its color objects are inert without it.
+    pub(crate) fn forget_color_objects(&mut self) {
+        if let Some(surface) = self.color.surface.take() {
+            surface.destroy();
+        }
+        if let Some(feedback) = self.color.feedback.take() {
+            feedback.destroy();
+        }
+    }
+
+    //The window is going, see cleanup.rs. A description waiting for ready goes too.
+    pub(crate) fn release_color(&mut self) {
+        self.forget_color_objects();
+        for pending in [
+            self.color.pending.take(),
+            self.color.pending_preferred.take(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            pending.destroy();
+        }
+        if let Some(manager) = self.color.manager.take() {
+            manager.destroy();
+        }
+    }
+}
+
+impl ColorState {
+    //get_preferred could hand out an ICC profile, which we have no use for. One asked for before
+    //and still not ready is stale.
+    fn request_preferred(
+        &mut self,
+        feedback: &WpColorManagementSurfaceFeedbackV1,
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        if self
+            .capabilities
+            .as_ref()
+            .is_some_and(|capabilities| capabilities.features.contains(&ColorFeature::Parametric))
+        {
+            if let Some(stale) = self.pending_preferred.take() {
+                stale.destroy();
+            }
+            self.pending_preferred =
+                Some(feedback.get_preferred_parametric(queue_handle, DescriptionFor::Preferred));
+        }
+    }
+}
+
+impl Window {
+    //Whether the compositor has wp_color_manager_v1 at all.
+    pub fn color_management_supported(&self) -> bool {
+        self.state.color.manager.is_some()
+    }
+
+    //None until the compositor listed them, a dispatch or two after the window was created.
+    pub fn color_capabilities(&self) -> Option<&ColorCapabilities> {
+        self.state.color.capabilities.as_ref()
+    }
+
+    //Tags the surface's content with a color description, None to go back to the compositor's
+    //default (usually sRGB). The content has to be drawn in it: nothing is converted on our
+    //side. Takes effect once the compositor made the description, with a commit of its own.
+    //
+    //Errors right away when the compositor is known not to support it. While its capabilities
+    //aren't known yet the description waits for them, and is dropped with a warning if it turns
+    //out unsupported. Survives reconnect.
+    pub fn set_color_description(
+        &mut self,
+        description: Option<ColorDescription>,
+        intent: RenderIntent,
+    ) -> Result<(), WindowError> {
+        //The registry's globals all come in the same batch as wl_compositor.
+        if self.state.color.manager.is_none() && self.state.compositor.is_some() {
+            return Err(WindowError::Unsupported("wp_color_manager_v1"));
+        }
+        if let (Some(description), Some(capabilities)) =
+            (description, self.state.color.capabilities.as_ref())
+        {
+            description.check(intent, capabilities)?;
+        }
+
+        let queue_handle = self.event_queue.handle();
+        self.state.color.wanted = description.map(|description| (description, intent));
+        self.state.apply_color_description(&queue_handle);
+        if description.is_none()
+            && let Some(ref surface) = self.state.color.surface
+        {
+            surface.unset_image_description();
+            self.state.commit_state(&queue_handle);
+        }
+        Ok(())
+    }
+
+    pub fn color_description(&self) -> Option<ColorDescription> {
+        self.state.color.wanted.map(|(description, _)| description)
+    }
+
+    //What the compositor would like the content in, for the output the surface is on. None until
+    //it told (and always without the parametric feature). Changes come as
+    //WindowEvent::PreferredColorChanged.
+    pub fn preferred_color(&self) -> Option<&ColorInfo> {
+        self.state.color.preferred.as_ref()
+    }
+}
+
+impl Dispatch<WpColorManagerV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &WpColorManagerV1,
+        event: wp_color_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+    ) {
+        let incoming = &mut state.color.incoming;
+        match event {
+            wp_color_manager_v1::Event::SupportedIntent { render_intent } => {
+                incoming
+                    .render_intents
+                    .extend(RenderIntent::from_protocol(render_intent));
+            }
+            wp_color_manager_v1::Event::SupportedFeature { feature } => {
+                incoming
+                    .features
+                    .extend(ColorFeature::from_protocol(feature));
+            }
+            wp_color_manager_v1::Event::SupportedTfNamed { tf } => {
+                incoming
+                    .transfer_functions
+                    .extend(TransferFunction::from_protocol(tf));
+            }
+            wp_color_manager_v1::Event::SupportedPrimariesNamed { primaries } => {
+                incoming
+                    .primaries
+                    .extend(Primaries::from_protocol(primaries));
+            }
+            wp_color_manager_v1::Event::Done => {
+                let capabilities = mem::take(incoming);
+                protocol_log!("color capabilities {capabilities:?}");
+                state.color.capabilities = Some(capabilities);
+                state.init_color_feedback(queue_handle);
+                state.apply_color_description(queue_handle);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WpImageDescriptionV1, DescriptionFor> for AppState {
+    fn event(
+        state: &mut Self,
+        description: &WpImageDescriptionV1,
+        event: wp_image_description_v1::Event,
+        made_for: &DescriptionFor,
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+    ) {
+        match (event, made_for) {
+            (wp_image_description_v1::Event::Ready { .. }, DescriptionFor::Surface) => {
+                state.color.pending = None;
+                let color = &mut state.color;
+                if let (Some(manager), Some((_, intent)), Some(surface)) = (
+                    color.manager.as_ref(),
+                    color.wanted,
+                    state.base_surface.as_ref(),
+                ) {
+                    //Quoting documentation: "If a wp_color_management_surface_v1 object already
+                    //exists for the given wl_surface, the protocol error surface_exists is
+                    //raised."
+                    let object = color
+                        .surface
+                        .get_or_insert_with(|| manager.get_surface(surface, queue_handle, ()));
+                    object.set_image_description(description, intent.into());
+                    //Double-buffered, like the rest of the surface state.
+                    state.commit_state(queue_handle);
+                    protocol_log!("color description set");
+                }
+                //Quoting documentation: "Setting the image description has copy semantics; after
+                //this request, the image description can be immediately destroyed without
+                //affecting the pending state of the surface."
+                description.destroy();
+            }
+            (wp_image_description_v1::Event::Ready { .. }, DescriptionFor::Preferred) => {
+                state.color.pending_preferred = None;
+                description.get_information(queue_handle, ());
+                description.destroy();
+            }
+            (wp_image_description_v1::Event::Failed { msg, .. }, made_for) => {
+                match made_for {
+                    DescriptionFor::Surface => state.color.pending = None,
+                    DescriptionFor::Preferred => state.color.pending_preferred = None,
+                }
+                log::warn!("the compositor couldn't make an image description: {msg}");
+                description.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WpColorManagementSurfaceFeedbackV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        feedback: &WpColorManagementSurfaceFeedbackV1,
+        event: wp_color_management_surface_feedback_v1::Event,
+        _: &(),
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+    ) {
+        if let wp_color_management_surface_feedback_v1::Event::PreferredChanged { .. } = event {
+            state.color.request_preferred(feedback, queue_handle);
+        }
+    }
+}
+
+impl Dispatch<WpImageDescriptionInfoV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &WpImageDescriptionInfoV1,
+        event: wp_image_description_info_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use wp_image_description_info_v1::Event;
+
+        let info = &mut state.color.incoming_info;
+        match event {
+            Event::Primaries {
+                r_x,
+                r_y,
+                g_x,
+                g_y,
+                b_x,
+                b_y,
+                w_x,
+                w_y,
+            } => info.primaries = chromaticities([r_x, r_y, g_x, g_y, b_x, b_y, w_x, w_y]),
+            Event::PrimariesNamed { primaries } => {
+                info.named_primaries = Primaries::from_protocol(primaries);
+            }
+            //Quoting documentation: "The curve exponent has been multiplied by 10000 to get the
+            //argument eexp value to carry the precision of 4 decimals."
+            Event::TfPower { eexp } => info.tf_power = Some(f64::from(eexp) / 10000.0),
+            Event::TfNamed { tf } => info.transfer_function = TransferFunction::from_protocol(tf),
+            Event::Luminances {
+                min_lum,
+                max_lum,
+                reference_lum,
+            } => {
+                info.luminances = Some(Luminances {
+                    min: min_lum_from_wire(min_lum),
+                    max: max_lum,
+                    reference: reference_lum,
+                });
+            }
+            Event::TargetPrimaries {
+                r_x,
+                r_y,
+                g_x,
+                g_y,
+                b_x,
+                b_y,
+                w_x,
+                w_y,
+            } => {
+                info.target_primaries =
+                    Some(chromaticities([r_x, r_y, g_x, g_y, b_x, b_y, w_x, w_y]));
+            }
+            Event::TargetLuminance { min_lum, max_lum } => {
+                info.target_luminance = Some((min_lum_from_wire(min_lum), max_lum));
+            }
+            Event::TargetMaxCll { max_cll } => info.max_cll = Some(max_cll),
+            Event::TargetMaxFall { max_fall } => info.max_fall = Some(max_fall),
+            //Only with ICC descriptions, and get_preferred_parametric never hands those out.
+            Event::IccFile { .. } => {}
+            Event::Done => {
+                let info = mem::take(info);
+                protocol_log!("preferred color {info:?}");
+                state.color.preferred = Some(info.clone());
+                state.events.push(WindowEvent::PreferredColorChanged(info));
+            }
+            _ => {}
+        }
+    }
+}
+
+//The creator has no events, wp_color_management_surface_v1 neither.
+delegate_noop!(AppState: ignore WpImageDescriptionCreatorParamsV1);
+delegate_noop!(AppState: ignore WpColorManagementSurfaceV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities() -> ColorCapabilities {
+        ColorCapabilities {
+            features: vec![ColorFeature::Parametric],
+            primaries: vec![Primaries::Srgb, Primaries::Bt2020],
+            transfer_functions: vec![TransferFunction::Srgb, TransferFunction::St2084Pq],
+            render_intents: vec![RenderIntent::Perceptual],
+        }
+    }
+
+    #[test]
+    fn descriptions_are_checked_against_the_capabilities() {
+        let capabilities = capabilities();
+        let perceptual = RenderIntent::Perceptual;
+        assert_eq!(
+            ColorDescription::BT2100_PQ.check(perceptual, &capabilities),
+            Ok(())
+        );
+        assert!(
+            ColorDescription::named(Primaries::DisplayP3, TransferFunction::Srgb)
+                .check(perceptual, &capabilities)
+                .is_err()
+        );
+        assert!(
+            ColorDescription::SRGB
+                .check(RenderIntent::Absolute, &capabilities)
+                .is_err()
+        );
+        let with_luminances = ColorDescription {
+            luminances: Some(Luminances {
+                min: 0.2,
+                max: 80,
+                reference: 80,
+            }),
+            ..ColorDescription::SRGB
+        };
+        assert_eq!(
+            with_luminances.check(perceptual, &capabilities),
+            Err(WindowError::Unsupported("set_luminances"))
+        );
+    }
+
+    #[test]
+    fn luminances_are_sent_as_the_protocol_scales_them() {
+        assert_eq!(min_lum_to_wire(0.005), 50);
+        assert_eq!(min_lum_from_wire(2000), 0.2);
+        let srgb = chromaticities([
+            640_000, 330_000, 300_000, 600_000, 150_000, 60_000, 312_700, 329_000,
+        ]);
+        assert_eq!(srgb[0], (0.64, 0.33));
+        assert_eq!(srgb[3], (0.3127, 0.329));
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    env,
+    os::{fd::OwnedFd, unix::net::UnixStream},
+    path::{Path, PathBuf},
+};
+
+use wayland_client::Connection;
+
+use crate::{Window, WindowError, WindowOptions};
+
+//Which compositor to talk to. The default does what every Wayland client does (WAYLAND_SOCKET,
+//then WAYLAND_DISPLAY), the others skip the environment: a nested compositor next to the session
+//one, or a socket a parent process opened for us.
+#[derive(Debug, Default)]
+pub struct ConnectOptions {
+    target: Target,
+}
+
+#[derive(Debug, Default)]
+enum Target {
+    #[default]
+    Env,
+    Path(PathBuf),
+    Fd(OwnedFd),
+}
+
+impl ConnectOptions {
+    //Same as WAYLAND_DISPLAY=name: relative to XDG_RUNTIME_DIR ("wayland-2"), or an absolute
+    //path as it is.
+    pub fn socket_name(name: &str) -> Result<ConnectOptions, WindowError> {
+        if Path::new(name).is_absolute() {
+            return Ok(Self::socket_path(name));
+        }
+        let Some(runtime_dir) = env::var_os("XDG_RUNTIME_DIR") else {
+            return Err(WindowError::Connection(format!(
+                "socket {name}: XDG_RUNTIME_DIR is not set"
+            )));
+        };
+        Ok(Self::socket_path(Path::new(&runtime_dir).join(name)))
+    }
+
+    pub fn socket_path(path: impl Into<PathBuf>) -> ConnectOptions {
+        ConnectOptions {
+            target: Target::Path(path.into()),
+        }
+    }
+
+    //A connected socket, WAYLAND_SOCKET style: compositors spawning a client directly hand it
+    //one end of a socketpair. The connection takes the fd over.
+    pub fn socket_fd(fd: OwnedFd) -> ConnectOptions {
+        ConnectOptions {
+            target: Target::Fd(fd),
+        }
+    }
+
+    //Errors name the socket that was tried.
+    pub fn connect(self) -> Result<Connection, WindowError> {
+        match self.target {
+            Target::Env => Connection::connect_to_env().map_err(|err| {
+                let display = env::var("WAYLAND_SOCKET")
+                    .map(|fd| format!("WAYLAND_SOCKET={fd}"))
+                    .or_else(|_| env::var("WAYLAND_DISPLAY"))
+                    .unwrap_or_else(|_| "wayland-0".into());
+                WindowError::Connection(format!("{display}: {err}"))
+            }),
+            Target::Path(path) => UnixStream::connect(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|stream| Connection::from_socket(stream).map_err(|err| err.to_string()))
+                .map_err(|err| WindowError::Connection(format!("{}: {err}", path.display()))),
+            Target::Fd(fd) => Connection::from_socket(UnixStream::from(fd))
+                .map_err(|err| WindowError::Connection(format!("socket fd: {err}"))),
+        }
+    }
+
+    //Where reconnect can go again. A passed fd is used up, reconnecting goes through the
+    //environment then.
+    fn socket(&self) -> Option<PathBuf> {
+        match self.target {
+            Target::Path(ref path) => Some(path.clone()),
+            Target::Env | Target::Fd(_) => None,
+        }
+    }
+}
+
+impl Window {
+    //Like with_options, connecting as `connect` says. Fails instead of panicking when there's no
+    //compositor, or when it lacks what a window needs (WindowError::MissingGlobals).
+    pub fn connect(connect: ConnectOptions, options: WindowOptions) -> Result<Window, WindowError> {
+        let socket = connect.socket();
+        let mut window = Window::with_connection(connect.connect()?, options);
+        window.socket = socket;
+        window.wait_for_globals()?;
+        Ok(window)
+    }
+
+    //Connects again the way this window first did, for reconnect.
+    pub(crate) fn connect_again(&self) -> Result<Connection, WindowError> {
+        match self.socket {
+            Some(ref path) => ConnectOptions::socket_path(path).connect(),
+            None => ConnectOptions::default().connect(),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use wayland_client::{QueueHandle, delegate_noop};
+use wayland_protocols::wp::content_type::v1::client::{
+    wp_content_type_manager_v1::WpContentTypeManagerV1,
+    wp_content_type_v1::{self, WpContentTypeV1},
+};
+
+use crate::{AppState, Window};
+
+//What kind of content the surface shows, so the compositor can pick its trade-offs
+//(e.g. skip animations or allow tearing for games, keep quality for photos).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContentType {
+    #[default]
+    None,
+    Photo,
+    Video,
+    Game,
+}
+
+impl From<ContentType> for wp_content_type_v1::Type {
+    fn from(content_type: ContentType) -> Self {
+        match content_type {
+            ContentType::None => wp_content_type_v1::Type::None,
+            ContentType::Photo => wp_content_type_v1::Type::Photo,
+            ContentType::Video => wp_content_type_v1::Type::Video,
+            ContentType::Game => wp_content_type_v1::Type::Game,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ContentTypeState {
+    pub(crate) manager: Option<WpContentTypeManagerV1>,
+    //Per-surface object, only created the first time the application sets a type.
+    object: Option<WpContentTypeV1>,
+    wanted: Option<ContentType>,
+}
+
+impl ContentTypeState {
+    //The wanted type without the objects of the old connection, for Window::reconnect.
+    pub(crate) fn for_reconnect(&self) -> ContentTypeState {
+        ContentTypeState {
+            wanted: self.wanted,
+            ..ContentTypeState::default()
+        }
+    }
+}
+
+impl AppState {
+    //Sends the wanted content type, creating the per-surface object if needed.
+    //Also called when the surface gets (re)created so the setting survives it.
+    pub(crate) fn apply_content_type(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let (Some(wanted), Some(manager), Some(surface)) = (
+            self.content_type.wanted,
+            self.content_type.manager.as_ref(),
+            self.base_surface.as_ref(),
+        ) else {
+            return;
+        };
+
+        //Quoting documentation: "If the wl_surface already has a wp_content_type_v1 object
+        //associated, the already_constructed protocol error is raised."
+        let object = self
+            .content_type
+            .object
+            .get_or_insert_with(|| manager.get_surface_content_type(surface, queue_handle, ()));
+        object.set_content_type(wanted.into());
+    }
+
+    //The surface went away, and its content type object is useless without it.
+    pub(crate) fn forget_content_type_object(&mut self) {
+        if let Some(object) = self.content_type.object.take() {
+            object.destroy();
+        }
+    }
+}
+
+impl Window {
+    //Silently does nothing when the compositor doesn't know the protocol; check
+    //content_type_supported() if that matters.
+    pub fn set_content_type(&mut self, content_type: ContentType) {
+        let queue_handle = self.event_queue.handle();
+        self.state.content_type.wanted = Some(content_type);
+        self.state.apply_content_type(&queue_handle);
+
+        //The content type is double-buffered state, it takes effect on the next commit.
+        self.state.commit_state(&queue_handle);
+    }
+
+    pub fn content_type_supported(&self) -> bool {
+        self.state.content_type.manager.is_some()
+    }
+}
+
+//wp_content_type_v1 has no events.
+delegate_noop!(AppState: ignore WpContentTypeV1);
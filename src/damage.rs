@@ -0,0 +1,267 @@
+//Drawing only what changed. Window::draw repaints the whole buffer every time; draw_damaged
+//takes the rectangles the application changes and keeps the rest of the buffer as it is. With
+//more than one buffer (swapchain.rs) the buffer drawn into isn't the one on screen: it holds a
+//frame from one, two or more presents ago, and everything damaged since then has to be repainted
+//too, or the old content comes back as trails (a blinking cursor left on where it blinked off).
+//
+//That's EGL_EXT_buffer_age's model. The draw gets the buffer's age: 1 when it holds the latest
+//frame, 2 the one before, and so on; 0 when its content is unknown (a new buffer, a new size, a
+//draw that wasn't presented), then the whole buffer is to be repainted. And the rectangles to
+//repaint: the damage given plus that of the frames the buffer missed. Those are cleared to
+//transparent before the draw, like draw clears the whole canvas.
+//
+//Only the damage given goes to the compositor, it already has the frames in between. Rectangles
+//are in buffer pixels, like the canvas. Compositors without wl_surface.damage_buffer (version 4)
+//get the whole buffer damaged. So does a window faded without wp_alpha_modifier_v1, whose pixels
+//are faded in place: those draws always repaint everything.
+
+use crate::{AppState, Canvas, Color, Rect, Window};
+
+//Frames of damage kept. Buffers further behind repaint everything.
+const HISTORY: usize = 8;
+
+//Past this many rectangles their bounding box is repainted instead.
+const MAX_RECTS: usize = 32;
+
+//`rects` within a width x height buffer, the empty ones gone.
+fn clip(rects: &[Rect], (width, height): (u32, u32)) -> Vec<Rect> {
+    let (width, height) = (width as i64, height as i64);
+    rects
+        .iter()
+        .filter_map(|rect| {
+            let x0 = i64::from(rect.x).clamp(0, width);
+            let y0 = i64::from(rect.y).clamp(0, height);
+            let x1 = (i64::from(rect.x) + i64::from(rect.width)).clamp(0, width);
+            let y1 = (i64::from(rect.y) + i64::from(rect.height)).clamp(0, height);
+            (x1 > x0 && y1 > y0)
+                .then(|| Rect::new(x0 as i32, y0 as i32, (x1 - x0) as i32, (y1 - y0) as i32))
+        })
+        .collect()
+}
+
+fn bounding_box(rects: &[Rect]) -> Rect {
+    let x0 = rects.iter().map(|r| r.x).min().unwrap_or(0);
+    let y0 = rects.iter().map(|r| r.y).min().unwrap_or(0);
+    let x1 = rects.iter().map(|r| r.x + r.width).max().unwrap_or(0);
+    let y1 = rects.iter().map(|r| r.y + r.height).max().unwrap_or(0);
+    Rect::new(x0, y0, x1 - x0, y1 - y0)
+}
+
+//What a buffer of `age` has to repaint for `damage`: that plus the damage of the age - 1 latest
+//frames of `history` (newest last, None for a whole frame). The whole buffer when the age is
+//unknown or older than the history.
+fn repaint(
+    age: usize,
+    history: &[Option<Vec<Rect>>],
+    damage: &[Rect],
+    size: (u32, u32),
+) -> Vec<Rect> {
+    let full = vec![Rect::new(0, 0, size.0 as i32, size.1 as i32)];
+    if age == 0 || age - 1 > history.len() {
+        return full;
+    }
+    let mut rects = clip(damage, size);
+    for frame in &history[history.len() - (age - 1)..] {
+        let Some(frame) = frame else {
+            return full;
+        };
+        for rect in clip(frame, size) {
+            if !rects.contains(&rect) {
+                rects.push(rect);
+            }
+        }
+    }
+    if rects.len() > MAX_RECTS {
+        rects = vec![bounding_box(&rects)];
+    }
+    rects
+}
+
+impl AppState {
+    //The age of the window's buffer, see damage.rs.
+    fn buffer_age(&self) -> usize {
+        match self.swapchain.drawn_at {
+            Some(drawn_at) if !self.fades_shm_buffer() => {
+                (self.swapchain.frame + 1 - drawn_at) as usize
+            }
+            _ => 0,
+        }
+    }
+
+    //What the window's buffer lacks of the latest frame: the damage of the frames it missed, all
+    //of it when its age is unknown. See rows.rs.
+    pub(crate) fn missed_damage(&self) -> Vec<Rect> {
+        let Some((_, layout)) = self.shm_pixels.as_ref() else {
+            return Vec::new();
+        };
+        let history: Vec<_> = self.swapchain.history.iter().cloned().collect();
+        repaint(
+            self.buffer_age(),
+            &history,
+            &[],
+            (layout.width, layout.height),
+        )
+    }
+
+    //present_gradient: the window's buffer goes on screen with what frame_damage says. Returns
+    //the rectangles to damage, None for all of it. A frame drawn row by row ends with it.
+    pub(crate) fn main_buffer_presented(&mut self) -> Option<Vec<Rect>> {
+        self.rows.frame = None;
+        #[cfg(feature = "diagnostic-overlay")]
+        self.diagnostic_overlay.frame_presented();
+        let swapchain = &mut self.swapchain;
+        let damage = swapchain.frame_damage.take();
+        swapchain.frame += 1;
+        swapchain.drawn_at = Some(swapchain.frame);
+        swapchain.history.push_back(damage.clone());
+        if swapchain.history.len() > HISTORY {
+            swapchain.history.pop_front();
+        }
+        damage
+    }
+
+    //The window's buffer was drawn into and not presented, as identical to the frame on screen.
+    pub(crate) fn main_buffer_matches_shown(&mut self) {
+        self.swapchain.drawn_at = Some(self.swapchain.frame);
+    }
+}
+
+impl Window {
+    //Like draw, for a picture that only changed in `damage` (buffer pixels). `draw` gets the
+    //canvas with the previous content of the buffer, the buffer's age and the rectangles to
+    //repaint, cleared to transparent; it may paint anywhere but only those rectangles are sure to
+    //be shown as painted. An age of 0 means all of the buffer, see damage.rs.
+    //
+    //Not skipped when identical (set_skip_identical_frames), a damaged frame is a changed one.
+    pub fn draw_damaged(
+        &mut self,
+        damage: &[Rect],
+        draw: impl FnOnce(&mut Canvas, usize, &[Rect]),
+    ) {
+        let Some((_, layout)) = self.state.shm_pixels.as_ref() else {
+            return;
+        };
+        let size = (layout.width, layout.height);
+        self.wait_for_free_buffer();
+        let age = self.state.buffer_age();
+        let history: Vec<_> = self.state.swapchain.history.iter().cloned().collect();
+        let repaint = repaint(age, &history, damage, size);
+        self.state.paint(|canvas| {
+            for rect in &repaint {
+                canvas.fill_rect(*rect, Color::TRANSPARENT);
+            }
+            draw(canvas, age, &repaint);
+        });
+        self.state.drawn_by_app = true;
+        self.state.fade_shm_buffer();
+
+        let frame = self.state.swapchain.frame;
+        if self.state.configured() {
+            self.state.swapchain.frame_damage = (age != 0).then(|| clip(damage, size));
+            let queue_handle = self.event_queue.handle();
+            self.state.present_gradient(&queue_handle);
+        }
+        if self.state.swapchain.frame == frame {
+            //Not presented (not configured yet, or the surface isn't ours to attach to): whatever
+            //presents it next damages everything.
+            self.state.swapchain.frame_damage = None;
+            self.state.swapchain.drawn_at = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (u32, u32) = (100, 50);
+
+    fn rect(x: i32) -> Rect {
+        Rect::new(x, 0, 10, 10)
+    }
+
+    #[test]
+    fn older_buffers_repaint_what_they_missed() {
+        let history = [
+            Some(vec![rect(0)]),
+            None,
+            Some(vec![rect(20)]),
+            Some(vec![rect(40)]),
+        ];
+        let full = [Rect::new(0, 0, 100, 50)];
+        assert_eq!(repaint(1, &history, &[rect(60)], SIZE), [rect(60)]);
+        assert_eq!(
+            repaint(2, &history, &[rect(60)], SIZE),
+            [rect(60), rect(40)]
+        );
+        assert_eq!(
+            repaint(3, &history, &[rect(40)], SIZE),
+            [rect(40), rect(20)]
+        );
+        //Through a whole frame, or unknown.
+        assert_eq!(repaint(4, &history, &[rect(60)], SIZE), full);
+        assert_eq!(repaint(0, &history, &[rect(60)], SIZE), full);
+        assert_eq!(repaint(6, &history, &[rect(60)], SIZE), full);
+    }
+
+    //A blinking cursor moving along, each frame damaging where it was and where it is. With two
+    //buffers taking turns, a buffer two frames behind must not bring the cursor of two frames ago
+    //back.
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn a_blinking_cursor_leaves_no_trails() {
+        use crate::{TestWindow, WindowOptions};
+
+        const BACKGROUND: Color = Color::opaque(0x20, 0x20, 0x20);
+        const CURSOR: Color = Color::opaque(0xFF, 0xFF, 0xFF);
+        let cursor = |step: i32| Rect::new(4 + step * 6, 4, 2, 12);
+
+        let mut window = TestWindow::new(WindowOptions::default());
+        window.inject_configure(60, 20, &[]);
+        window.dispatch();
+        window.take_frames();
+        let mut ages = Vec::new();
+        for step in 0..8 {
+            let visible = step % 2 == 0;
+            let damage = [cursor(step - 1), cursor(step)];
+            window.draw_damaged(&damage, |canvas, age, repaint| {
+                ages.push(age);
+                for rect in repaint {
+                    canvas.fill_rect(*rect, BACKGROUND);
+                }
+                if visible {
+                    canvas.fill_rect(cursor(step), CURSOR);
+                }
+            });
+            window.dispatch();
+
+            let frame = window.take_frames().pop().unwrap();
+            for (index, pixel) in frame.rgba.chunks(4).enumerate() {
+                let (x, y) = ((index % 60) as i32, (index / 60) as i32);
+                let at = cursor(step);
+                let on_cursor = visible
+                    && (at.x..at.x + at.width).contains(&x)
+                    && (at.y..at.y + at.height).contains(&y);
+                let expected = if on_cursor { 0xFF } else { 0x20 };
+                assert_eq!(
+                    pixel,
+                    [expected, expected, expected, 0xFF],
+                    "step {step}, {x},{y}"
+                );
+            }
+        }
+        //The first one into a new spare, then the configure's buffer and it taking turns.
+        assert_eq!(ages[0], 0);
+        assert!(ages[1..].iter().all(|&age| age == 2));
+    }
+
+    #[test]
+    fn damage_is_clipped_and_bounded() {
+        assert_eq!(
+            clip(&[Rect::new(-5, 45, 10, 10), Rect::new(200, 0, 5, 5)], SIZE),
+            [Rect::new(0, 45, 5, 5)]
+        );
+        let many: Vec<_> = (0..40).map(|i| Rect::new(i * 2, i, 1, 1)).collect();
+        assert_eq!(repaint(1, &[], &many, SIZE), [Rect::new(0, 0, 79, 40)]);
+    }
+}
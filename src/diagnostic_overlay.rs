@@ -0,0 +1,357 @@
+//A box in the window's top left corner with what's useful when "it feels slow on my compositor":
+//frames per second and frame time percentiles (of the window's shm buffers), buffers and stalls
+//(see swapchain.rs), the scale, the size, configures received and applied, and the last few
+//input events. Ctrl+Shift+F12 shows and hides it, a default key binding like Escape's
+//(Action::ToggleDiagnosticOverlay), or Window::set_diagnostic_overlay.
+//
+//It's a subsurface with a buffer of its own, so the window's buffers, and everything looking at
+//them (captures, frame skipping, damage), never see it. Desynchronized, it commits on its own,
+//redrawn 4 times a second whatever the window's content does (but not while it's suspended):
+//pump_events and the EventLoop wake up for it, poll_events and external loops (take_events)
+//redraw it when they come by. Its input region is empty, clicks go through to the window.
+//
+//Nothing is measured while it's hidden, the numbers start when it's shown. The diagnostic-overlay
+//feature, which brings text along.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use wayland_client::{
+    Connection, Dispatch, QueueHandle, delegate_noop,
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_shm,
+        wl_subcompositor::WlSubcompositor,
+        wl_subsurface::WlSubsurface,
+        wl_surface::{self, WlSurface},
+    },
+};
+
+use crate::{
+    AppState, Canvas, Color, ScaleMath, Window, WindowError, WindowEvent,
+    buffer_layout::BufferLayout, region::create_region,
+};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+//Presents kept for the frame times.
+const FRAME_WINDOW: Duration = Duration::from_secs(2);
+const INPUTS: usize = 4;
+
+//Surface pixels.
+const WIDTH: u32 = 260;
+const MARGIN: i32 = 8;
+const PADDING: u32 = 6;
+const LINE_HEIGHT: u32 = 15;
+const TEXT_SIZE: f32 = 12.0;
+const BACKGROUND: Color = Color::premultiplied(0, 0, 0, 0xC0);
+const TEXT: Color = Color::opaque(0xE0, 0xE0, 0xE0);
+
+struct Overlay {
+    surface: WlSurface,
+    subsurface: WlSubsurface,
+    //On screen, or None before the first redraw.
+    buffer: Option<WlBuffer>,
+    position: (i32, i32),
+    next_redraw: Instant,
+    //When the window's frames went on screen, oldest first.
+    presents: VecDeque<Instant>,
+    inputs: VecDeque<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct DiagnosticOverlayState {
+    pub(crate) subcompositor: Option<WlSubcompositor>,
+    shown: Option<Overlay>,
+}
+
+impl DiagnosticOverlayState {
+    //When it wants to be redrawn, None while hidden. The waits take it as a deadline.
+    pub(crate) fn next_redraw(&self) -> Option<Instant> {
+        self.shown.as_ref().map(|overlay| overlay.next_redraw)
+    }
+
+    //From main_buffer_presented.
+    pub(crate) fn frame_presented(&mut self) {
+        let Some(ref mut overlay) = self.shown else {
+            return;
+        };
+        let now = Instant::now();
+        overlay.presents.push_back(now);
+        while overlay
+            .presents
+            .front()
+            .is_some_and(|&at| now - at > FRAME_WINDOW)
+        {
+            overlay.presents.pop_front();
+        }
+    }
+
+    //Compaction recreated the overlay's buffer elsewhere in the pool.
+    pub(crate) fn replace_buffer(&mut self, old: &WlBuffer, new: WlBuffer) {
+        if let Some(ref mut overlay) = self.shown
+            && overlay.buffer.as_ref() == Some(old)
+        {
+            overlay.buffer = Some(new);
+        }
+    }
+
+    //The input events of a dispatch batch.
+    pub(crate) fn note_inputs(&mut self, events: &[WindowEvent]) {
+        let Some(ref mut overlay) = self.shown else {
+            return;
+        };
+        for event in events.iter().filter_map(describe) {
+            if overlay.inputs.len() == INPUTS {
+                overlay.inputs.pop_front();
+            }
+            overlay.inputs.push_back(event);
+        }
+    }
+}
+
+//One line about an input event, None for motion (too many to see anything else) and what isn't
+//input.
+fn describe(event: &WindowEvent) -> Option<String> {
+    let pressed = |pressed: bool| if pressed { "pressed" } else { "released" };
+    Some(match *event {
+        WindowEvent::Key { key, state, .. } => format!("{key:?} {state:?}"),
+        WindowEvent::KeyRepeat { key, .. } => format!("{key:?} repeated"),
+        WindowEvent::PointerButton {
+            button, pressed: p, ..
+        } => format!("button {button:#x} {}", pressed(p)),
+        WindowEvent::PointerButtonRepeat { button, .. } => format!("button {button:#x} repeated"),
+        WindowEvent::Scroll { dx, dy, .. } => format!("scroll {dx:.0},{dy:.0}"),
+        WindowEvent::PointerEntered { .. } => "pointer entered".into(),
+        WindowEvent::PointerLeft { .. } => "pointer left".into(),
+        _ => return None,
+    })
+}
+
+//The value `percent` of the way through `sorted`, nearest rank.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl AppState {
+    fn show_diagnostic_overlay(
+        &mut self,
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Result<(), WindowError> {
+        if self.diagnostic_overlay.shown.is_some() {
+            return Ok(());
+        }
+        let Some(ref subcompositor) = self.diagnostic_overlay.subcompositor else {
+            return Err(WindowError::Unsupported("wl_subcompositor"));
+        };
+        let (Some(compositor), Some(parent)) = (&self.compositor, &self.base_surface) else {
+            return Err(WindowError::Unsupported("wl_compositor"));
+        };
+        let surface = compositor.create_surface(queue_handle, OverlaySurface);
+        let subsurface = subcompositor.get_subsurface(&surface, parent, queue_handle, ());
+        //Quoting documentation: "In desynchronized mode, a commit on the sub-surface will
+        //apply the pending state directly".
+        subsurface.set_desync();
+        let nothing = create_region(compositor, &[], queue_handle);
+        surface.set_input_region(Some(&nothing));
+        nothing.destroy();
+        self.set_role(&surface, "diagnostic overlay surface");
+
+        self.diagnostic_overlay.shown = Some(Overlay {
+            surface,
+            subsurface,
+            buffer: None,
+            position: (0, 0),
+            next_redraw: Instant::now(),
+            presents: VecDeque::new(),
+            inputs: VecDeque::new(),
+        });
+        self.redraw_diagnostic_overlay(queue_handle);
+        Ok(())
+    }
+
+    //Also from Window's drop, see cleanup.rs.
+    pub(crate) fn hide_diagnostic_overlay(&mut self) {
+        let Some(overlay) = self.diagnostic_overlay.shown.take() else {
+            return;
+        };
+        overlay.subsurface.destroy();
+        overlay.surface.destroy();
+        if let Some(buffer) = overlay.buffer {
+            self.drop_buffer(buffer);
+        }
+    }
+
+    fn diagnostic_lines(&self, now: Instant) -> Vec<String> {
+        let Some(ref overlay) = self.diagnostic_overlay.shown else {
+            return Vec::new();
+        };
+        let presents = &overlay.presents;
+        let fps = presents
+            .iter()
+            .filter(|&&at| now - at <= Duration::from_secs(1))
+            .count();
+        let mut frame_times: Vec<_> = presents
+            .iter()
+            .zip(presents.iter().skip(1))
+            .map(|(earlier, later)| *later - *earlier)
+            .collect();
+        frame_times.sort();
+        let frame_times = if frame_times.is_empty() {
+            "frame times: none yet".to_owned()
+        } else {
+            format!(
+                "frame p50 {:.1} p90 {:.1} p99 {:.1} ms",
+                millis(percentile(&frame_times, 50)),
+                millis(percentile(&frame_times, 90)),
+                millis(percentile(&frame_times, 99)),
+            )
+        };
+        let (width, height) = self.surface_size();
+        let configures = self.configure_stats;
+
+        let mut lines = vec![
+            format!("{fps} fps"),
+            frame_times,
+            format!(
+                "{} buffers, {} stalls",
+                self.swapchain.buffers(),
+                self.swapchain.stalls
+            ),
+            format!("scale {}, {width}x{height}", self.preferred.sent_scale),
+            format!(
+                "{} configures, {} applied",
+                configures.received, configures.applied
+            ),
+        ];
+        lines.extend(overlay.inputs.iter().cloned());
+        lines
+    }
+
+    //After every dispatch: a new picture when it's time. None while suspended, the waits don't
+    //wake up for it then either.
+    pub(crate) fn redraw_diagnostic_overlay(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let now = Instant::now();
+        if self.suspended
+            || self
+                .diagnostic_overlay
+                .next_redraw()
+                .is_none_or(|next| next > now)
+        {
+            return;
+        }
+        let lines = self.diagnostic_lines(now);
+        let buffer_scale = self.preferred.sent_scale.max(1);
+        let scale = ScaleMath::integer(buffer_scale);
+        let (width, height) =
+            scale.size_to_physical((WIDTH, PADDING * 2 + LINE_HEIGHT * lines.len() as u32));
+        let Ok(layout) = BufferLayout::new(width, height, wl_shm::Format::Argb8888) else {
+            return;
+        };
+        let mut pixels = vec![0; layout.row_len() * height as usize];
+        if let Some(mut canvas) = Canvas::from_bytes(&mut pixels, width, height, width as usize * 4)
+        {
+            canvas.clear(BACKGROUND);
+            for (index, line) in lines.iter().enumerate() {
+                let y = PADDING + LINE_HEIGHT * index as u32;
+                canvas.draw_text(
+                    line,
+                    scale.to_physical(PADDING) as i32,
+                    scale.to_physical(y) as i32,
+                    TEXT_SIZE * scale.factor() as f32,
+                    TEXT,
+                );
+            }
+        }
+        let buffer = self.create_pool_buffer(layout, &pixels, false, queue_handle);
+        let geometry = self.window_geometry();
+        let position = (geometry.x + MARGIN, geometry.y + MARGIN);
+
+        let Some(ref mut overlay) = self.diagnostic_overlay.shown else {
+            return;
+        };
+        overlay.next_redraw = now + REDRAW_INTERVAL;
+        let Some(buffer) = buffer else {
+            return;
+        };
+        //Quoting documentation: "The scheduled coordinates will take effect whenever the state
+        //of the parent surface is applied."
+        if overlay.position != position {
+            overlay.subsurface.set_position(position.0, position.1);
+            overlay.position = position;
+        }
+        overlay.surface.attach(Some(&buffer), 0, 0);
+        overlay.surface.set_buffer_scale(buffer_scale);
+        overlay
+            .surface
+            .damage_buffer(0, 0, width as i32, height as i32);
+        overlay.surface.commit();
+        let previous = overlay.buffer.replace(buffer.clone());
+        self.pool_buffer_attached(&buffer);
+        if let Some(previous) = previous {
+            self.drop_buffer(previous);
+        }
+    }
+}
+
+impl Window {
+    //Shows or hides the overlay, see diagnostic_overlay.rs. Unsupported without wl_subcompositor.
+    pub fn set_diagnostic_overlay(&mut self, shown: bool) -> Result<(), WindowError> {
+        if !shown {
+            self.state.hide_diagnostic_overlay();
+            return Ok(());
+        }
+        let queue_handle = self.event_queue.handle();
+        self.state.show_diagnostic_overlay(&queue_handle)?;
+        let _ = self.flush();
+        Ok(())
+    }
+
+    pub fn diagnostic_overlay_shown(&self) -> bool {
+        self.state.diagnostic_overlay.shown.is_some()
+    }
+}
+
+//The overlay's surface, told apart from the window's: its outputs and preferred scale are the
+//window's business (output.rs), not ours.
+pub(crate) struct OverlaySurface;
+
+impl Dispatch<WlSurface, OverlaySurface> for AppState {
+    fn event(
+        _: &mut Self,
+        _: &WlSurface,
+        _: wl_surface::Event,
+        _: &OverlaySurface,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+//Neither has events.
+delegate_noop!(AppState: ignore WlSubcompositor);
+delegate_noop!(AppState: ignore WlSubsurface);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_take_the_nearest_rank() {
+        let sorted: Vec<_> = (1..=20).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 50), Duration::from_millis(10));
+        assert_eq!(percentile(&sorted, 90), Duration::from_millis(18));
+        assert_eq!(percentile(&sorted, 99), Duration::from_millis(20));
+        assert_eq!(
+            percentile(&sorted[..1], 50),
+            Duration::from_millis(1),
+            "a single frame time is every percentile"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//What a fatal protocol error was about. The compositor posts it on one of our objects and drops
+//the connection; wayland-client hands back "Protocol error 3 on object xdg_surface@5" and that's
+//all. Quoting documentation: "The error event is sent out when a fatal (non-recoverable) error
+//has occurred. The object_id argument is the object where the error occurred, most often in
+//response to a request to that object."
+//
+//So the window keeps, for the objects it makes, what they're for ("main window surface") and
+//the last few requests it sent on them. When the connection is lost over a protocol error, the
+//code is looked up in the error enum of the object's interface (wl_display, wl_shm, wl_surface,
+//xdg_wm_base, xdg_surface, xdg_toplevel, the summaries of the protocol XMLs) and everything goes
+//into a ProtocolErrorInfo: logged with the connection loss, returned by Window::protocol_error,
+//and as WindowError::Protocol from read_and_dispatch.
+//
+//wayland-client has no hook on outgoing requests, so only the requests noted here are in the
+//history: the window's surface, role objects, shm buffers and pongs. Objects of other modules
+//(popups, dmabuf, the buffer allocator's pools) still get the decoded message, without a role.
+
+use std::{collections::HashMap, collections::VecDeque, fmt};
+
+use wayland_client::{Proxy, backend::protocol::ProtocolError};
+
+use crate::{AppState, Window, WindowError};
+
+//Requests kept per object.
+const RECENT: usize = 8;
+
+//A fatal protocol error, decoded, see diagnostics.rs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolErrorInfo {
+    pub interface: String,
+    pub object_id: u32,
+    pub code: u32,
+    //What the compositor wrote along with it. Empty with the system libwayland (wayland-client's
+    //default), which prints it on stderr and keeps it to itself.
+    pub message: String,
+    //The summary of the code in the interface's error enum, None for interfaces or codes we
+    //don't know.
+    pub meaning: Option<&'static str>,
+    //What the object was for, None when it isn't one we keep track of.
+    pub role: Option<String>,
+    //The last requests we sent on the object, oldest first.
+    pub recent_requests: Vec<String>,
+}
+
+impl fmt::Display for ProtocolErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error {}", self.interface, self.code)?;
+        if let Some(meaning) = self.meaning {
+            write!(f, ": {meaning}")?;
+        }
+        let object = format!("{}@{}", self.interface, self.object_id);
+        match self.role {
+            Some(ref role) => write!(f, " (object: {role}, {object})")?,
+            None => write!(f, " (object: {object})")?,
+        }
+        if !self.message.is_empty() {
+            write!(f, ", the compositor says {:?}", self.message)?;
+        }
+        if !self.recent_requests.is_empty() {
+            write!(
+                f,
+                "; last requests on it: {}",
+                self.recent_requests.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+//The summary of an error code, as the protocol XMLs have it.
+fn meaning(interface: &str, code: u32) -> Option<&'static str> {
+    let summaries: &[&str] = match interface {
+        "wl_display" => &[
+            "server couldn't find object",
+            "method doesn't exist on the specified interface or malformed request",
+            "server is out of memory",
+            "implementation error in compositor",
+        ],
+        //Pools have no enum of their own, they're posted wl_shm's.
+        "wl_shm" | "wl_shm_pool" => &[
+            "buffer format is not known",
+            "invalid size or stride during pool or buffer creation",
+            "mmapping the file descriptor failed",
+        ],
+        "wl_surface" => &[
+            "buffer scale value is invalid",
+            "buffer transform value is invalid",
+            "buffer size is invalid",
+            "buffer offset is invalid",
+            "surface was destroyed before its role object",
+        ],
+        "xdg_wm_base" => &[
+            "given wl_surface has another role",
+            "xdg_wm_base was destroyed before children",
+            "the client tried to map or destroy a non-topmost popup",
+            "the client specified an invalid popup parent surface",
+            "the client provided an invalid surface state",
+            "the client provided an invalid positioner",
+            "the client didn’t respond to a ping event in time",
+        ],
+        //Starts at 1.
+        "xdg_surface" => &[
+            "",
+            "Surface was not fully constructed",
+            "Surface was already constructed",
+            "Attaching a buffer to an unconfigured surface",
+            "Invalid serial number when acking a configure event",
+            "Width or height was zero or negative",
+            "Surface was destroyed before its role object",
+        ],
+        "xdg_toplevel" => &[
+            "provided value is not a valid variant of the resize_edge enum",
+            "invalid parent toplevel",
+            "client provided an invalid min or max size",
+        ],
+        _ => &[],
+    };
+    summaries
+        .get(code as usize)
+        .copied()
+        .filter(|summary| !summary.is_empty())
+}
+
+#[derive(Default)]
+struct Tracked {
+    role: String,
+    recent: VecDeque<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct DiagnosticsState {
+    //By protocol id. Ids are reused once an object is gone, setting a role starts over.
+    objects: HashMap<u32, Tracked>,
+    //Once the connection was lost over one.
+    error: Option<ProtocolErrorInfo>,
+}
+
+impl AppState {
+    //`proxy` was just made, for `role`.
+    pub(crate) fn set_role(&mut self, proxy: &impl Proxy, role: impl Into<String>) {
+        self.diagnostics.objects.insert(
+            proxy.id().protocol_id(),
+            Tracked {
+                role: role.into(),
+                recent: VecDeque::new(),
+            },
+        );
+    }
+
+    //A request went out on `proxy`. Objects without a role aren't kept track of.
+    pub(crate) fn note_request(&mut self, proxy: &impl Proxy, request: impl Into<String>) {
+        self.request_sent();
+        if let Some(tracked) = self.diagnostics.objects.get_mut(&proxy.id().protocol_id()) {
+            if tracked.recent.len() == RECENT {
+                tracked.recent.pop_front();
+            }
+            tracked.recent.push_back(request.into());
+        }
+    }
+
+    fn decode_protocol_error(&self, error: ProtocolError) -> ProtocolErrorInfo {
+        let tracked = self.diagnostics.objects.get(&error.object_id);
+        ProtocolErrorInfo {
+            meaning: meaning(&error.object_interface, error.code),
+            role: tracked.map(|tracked| tracked.role.clone()),
+            recent_requests: tracked
+                .map_or_else(Vec::new, |tracked| tracked.recent.iter().cloned().collect()),
+            interface: error.object_interface,
+            object_id: error.object_id,
+            code: error.code,
+            message: error.message,
+        }
+    }
+}
+
+impl Window {
+    //A dispatch, read or flush failed: connection_lost, with the protocol error decoded when
+    //that's what it was.
+    pub(crate) fn connection_failed(&mut self, reason: impl fmt::Display) {
+        if self.state.connection_lost {
+            return;
+        }
+        match self.connection.protocol_error() {
+            Some(error) => {
+                let info = self.state.decode_protocol_error(error);
+                self.state.connection_lost(&info);
+                self.state.diagnostics.error = Some(info);
+            }
+            None => self.state.connection_lost(reason),
+        }
+    }
+
+    //What a request failing on the lost connection returns.
+    pub(crate) fn lost_connection_error(&self) -> WindowError {
+        match self.state.diagnostics.error {
+            Some(ref info) => WindowError::Protocol(info.clone()),
+            None => WindowError::Disconnected,
+        }
+    }
+
+    //The protocol error the connection was lost over, None while connected or when it was lost
+    //otherwise (the compositor quit). Gone with reconnect.
+    pub fn protocol_error(&self) -> Option<&ProtocolErrorInfo> {
+        self.state.diagnostics.error.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_looked_up_by_interface() {
+        assert_eq!(
+            meaning("xdg_surface", 3),
+            Some("Attaching a buffer to an unconfigured surface")
+        );
+        assert_eq!(
+            meaning("wl_shm_pool", 1),
+            Some("invalid size or stride during pool or buffer creation")
+        );
+        assert_eq!(meaning("xdg_surface", 0), None);
+        assert_eq!(meaning("xdg_wm_base", 7), None);
+        assert_eq!(meaning("wp_viewport", 0), None);
+    }
+
+    #[test]
+    fn the_message_says_what_and_where() {
+        let mut info = ProtocolErrorInfo {
+            interface: "xdg_surface".into(),
+            object_id: 5,
+            code: 3,
+            message: "buffer before configure".into(),
+            meaning: meaning("xdg_surface", 3),
+            role: Some("main window".into()),
+            recent_requests: vec!["get_toplevel".into(), "set_window_geometry".into()],
+        };
+        assert_eq!(
+            info.to_string(),
+            "xdg_surface error 3: Attaching a buffer to an unconfigured surface (object: main \
+             window, xdg_surface@5), the compositor says \"buffer before configure\"; last \
+             requests on it: get_toplevel, set_window_geometry"
+        );
+        info.role = None;
+        info.meaning = None;
+        info.message.clear();
+        info.recent_requests.clear();
+        assert_eq!(
+            info.to_string(),
+            "xdg_surface error 3 (object: xdg_surface@5)"
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::{Arc, Mutex};
+
+use wayland_client::{Proxy, QueueHandle, delegate_noop};
+use wayland_protocols::xdg::{
+    dialog::v1::client::{xdg_dialog_v1::XdgDialogV1, xdg_wm_dialog_v1::XdgWmDialogV1},
+    shell::client::xdg_toplevel::XdgToplevel,
+};
+
+use crate::{AppState, Window, WindowError, WindowOptions};
+
+//Toplevels of the dialogs a window has, shared with those dialogs so each side can let go of
+//the other whichever is dropped first.
+type Children = Arc<Mutex<Vec<XdgToplevel>>>;
+
+#[derive(Default)]
+pub(crate) struct DialogState {
+    pub(crate) manager: Option<XdgWmDialogV1>,
+    //The toplevel we are a dialog of, and its list of children we join once our toplevel exists.
+    parent: Option<(XdgToplevel, Children)>,
+    modal: bool,
+    //Only with xdg_wm_dialog_v1, set_parent alone already makes a dialog of sorts.
+    dialog: Option<XdgDialogV1>,
+    children: Children,
+}
+
+impl AppState {
+    //Called when the toplevel gets created, before its initial commit.
+    pub(crate) fn apply_parent(&mut self) {
+        let (Some((_, toplevel)), Some((parent, siblings))) =
+            (self.xdg_surface.as_ref(), self.dialog.parent.as_ref())
+        else {
+            return;
+        };
+
+        //The parent window may be gone already, there's nothing to be a dialog of then.
+        if parent.is_alive() {
+            toplevel.set_parent(Some(parent));
+            siblings.lock().unwrap().push(toplevel.clone());
+        }
+    }
+
+    //Creates the dialog object once both the toplevel and the manager are there, whichever comes
+    //last, and keeps its modal hint up to date.
+    pub(crate) fn apply_dialog(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let (Some(manager), Some((_, toplevel)), Some(_)) = (
+            self.dialog.manager.as_ref(),
+            self.xdg_surface.as_ref(),
+            self.dialog.parent.as_ref(),
+        ) else {
+            return;
+        };
+
+        //Quoting documentation: "Compositors must raise an already_used error if clients attempt
+        //to create multiple xdg_dialog_v1 objects for the same xdg_toplevel."
+        let dialog = self
+            .dialog
+            .dialog
+            .get_or_insert_with(|| manager.get_xdg_dialog(toplevel, queue_handle, ()));
+        if self.dialog.modal {
+            dialog.set_modal();
+        } else {
+            dialog.unset_modal();
+        }
+    }
+
+    //Part of dropping the window: our dialogs lose their parent (they stay open, their own Window
+    //owns them) and our parent forgets about us. The dialog object goes before the toplevel it
+    //belongs to.
+    pub(crate) fn detach_dialogs(&mut self) {
+        for child in self.dialog.children.lock().unwrap().drain(..) {
+            if child.is_alive() {
+                child.set_parent(None);
+            }
+        }
+
+        if let (Some((_, siblings)), Some((_, toplevel))) =
+            (self.dialog.parent.as_ref(), self.xdg_surface.as_ref())
+        {
+            siblings
+                .lock()
+                .unwrap()
+                .retain(|sibling| sibling != toplevel);
+        }
+        if let Some(dialog) = self.dialog.dialog.take() {
+            dialog.destroy();
+        }
+    }
+}
+
+impl Window {
+    //A second window, shown as a modal dialog of `parent` (kept above it, and on some compositors
+    //the parent is dimmed). A toplevel can only be the parent of one from the same client, so the
+    //dialog shares the parent's connection, with its own event queue: both windows need their
+    //events pumped.
+    //
+    //The parent is fixed at creation, so there can't be parent loops. If the parent goes first, the
+    //dialog just becomes a normal window.
+    //
+    //Quoting documentation: "Clients must implement the logic to filter events in the parent
+    //toplevel on their own." has_dialogs tells when to.
+    pub fn create_child_dialog(
+        parent: &Window,
+        options: WindowOptions,
+    ) -> Result<Window, WindowError> {
+        let Some((_, ref toplevel)) = parent.state.xdg_surface else {
+            return Err(WindowError::NotConfigured);
+        };
+
+        let mut dialog = Window::with_connection(parent.connection.clone(), options);
+        dialog.state.dialog.parent = Some((toplevel.clone(), parent.state.dialog.children.clone()));
+        dialog.state.dialog.modal = true;
+        Ok(dialog)
+    }
+
+    //Whether some dialog created from this window is still open.
+    pub fn has_dialogs(&self) -> bool {
+        !self.state.dialog.children.lock().unwrap().is_empty()
+    }
+
+    //Only a hint, and only with xdg_wm_dialog_v1. No-op for windows that aren't dialogs.
+    pub fn set_modal(&mut self, modal: bool) {
+        self.state.dialog.modal = modal;
+        let queue_handle = self.event_queue.handle();
+        self.state.apply_dialog(&queue_handle);
+        let _ = self.flush();
+    }
+}
+
+//Neither has events.
+delegate_noop!(AppState: ignore XdgWmDialogV1);
+delegate_noop!(AppState: ignore XdgDialogV1);
@@ -0,0 +1,249 @@
+use std::{
+    fs::File,
+    os::{
+        fd::{BorrowedFd, OwnedFd},
+        unix::fs::FileExt,
+    },
+};
+
+use wayland_client::{
+    Connection, Dispatch, Proxy, QueueHandle, delegate_noop, event_created_child,
+    protocol::wl_buffer::WlBuffer,
+};
+use wayland_protocols::wp::linux_dmabuf::zv1::client::{
+    zwp_linux_buffer_params_v1::{self, ZwpLinuxBufferParamsV1},
+    zwp_linux_dmabuf_feedback_v1::{self, ZwpLinuxDmabufFeedbackV1},
+    zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+};
+
+use crate::{AppState, Window, WindowError, WindowEvent};
+
+//One plane of a dmabuf, e.g. what a GPU allocator (gbm, vulkan...) hands back for an image.
+//Most RGB formats have a single plane; YUV formats can have two or three.
+#[derive(Debug, Clone, Copy)]
+pub struct DmabufPlane<'a> {
+    pub fd: BorrowedFd<'a>,
+    pub offset: u32,
+    pub stride: u32,
+}
+
+//Which create_dmabuf_buffer call a WindowEvent::DmabufBufferCreated answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DmabufRequest(u32);
+
+//A format/modifier pair the compositor can import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmabufFormat {
+    pub fourcc: u32,
+    pub modifier: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct DmabufState {
+    pub(crate) dmabuf: Option<ZwpLinuxDmabufV1>,
+    feedback: Option<ZwpLinuxDmabufFeedbackV1>,
+    //Whole table the compositor sent, tranches refer to it by index.
+    format_table: Vec<DmabufFormat>,
+    //Feedback currently being received, applied on Done.
+    pending_main_device: Option<u64>,
+    pending_formats: Vec<DmabufFormat>,
+    main_device: Option<u64>,
+    formats: Vec<DmabufFormat>,
+    //Of the last create_dmabuf_buffer.
+    last_request: u32,
+}
+
+impl AppState {
+    //Version 4 replaced the Format/Modifier events with feedback objects. The default feedback
+    //describes what works for any surface and is resent whenever it changes (e.g. the main device
+    //switched on a multi-GPU setup).
+    pub(crate) fn init_dmabuf_feedback(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let Some(ref dmabuf) = self.dmabuf.dmabuf else {
+            return;
+        };
+
+        if dmabuf.version() >= 4 {
+            self.dmabuf.feedback = Some(dmabuf.get_default_feedback(queue_handle, ()));
+        }
+    }
+
+    //The window is going, see cleanup.rs.
+    pub(crate) fn release_dmabuf(&mut self) {
+        if let Some(feedback) = self.dmabuf.feedback.take() {
+            feedback.destroy();
+        }
+        if let Some(dmabuf) = self.dmabuf.dmabuf.take() {
+            dmabuf.destroy();
+        }
+    }
+}
+
+//The table is an array of 16 byte entries: u32 format, 4 bytes padding, u64 modifier.
+fn read_format_table(fd: OwnedFd, size: u32) -> Vec<DmabufFormat> {
+    let file = File::from(fd);
+    let mut bytes = vec![0u8; size as usize];
+    if file.read_exact_at(&mut bytes, 0).is_err() {
+        return Vec::new();
+    }
+
+    bytes
+        .chunks_exact(16)
+        .map(|entry| DmabufFormat {
+            fourcc: u32::from_ne_bytes(entry[0..4].try_into().unwrap()),
+            modifier: u64::from_ne_bytes(entry[8..16].try_into().unwrap()),
+        })
+        .collect()
+}
+
+//dev_t comes as a native endian byte array.
+fn read_dev_t(device: &[u8]) -> Option<u64> {
+    Some(u64::from_ne_bytes(device.try_into().ok()?))
+}
+
+impl Window {
+    pub fn dmabuf_supported(&self) -> bool {
+        self.state.dmabuf.dmabuf.is_some()
+    }
+
+    //Format/modifier pairs the compositor said it can import (from the latest feedback).
+    pub fn dmabuf_formats(&self) -> &[DmabufFormat] {
+        &self.state.dmabuf.formats
+    }
+
+    //Device the compositor composites on. Allocating there avoids copies between GPUs.
+    pub fn dmabuf_main_device(&self) -> Option<u64> {
+        self.state.dmabuf.main_device
+    }
+
+    //Asks for the planes of a dmabuf wrapped in a wl_buffer. The answer comes later, as a
+    //WindowEvent::DmabufBufferCreated with the request returned here: the buffer, which can be
+    //attached like any other (see present_buffer), or BufferCreationFailed.
+    //
+    //Uses `create` instead of `create_immed`: with create_immed an import failure may be a fatal
+    //protocol error, with create the compositor answers Created or Failed. Nothing waits for the
+    //answer, it comes with the events like any other.
+    pub fn create_dmabuf_buffer(
+        &mut self,
+        planes: &[DmabufPlane],
+        width: i32,
+        height: i32,
+        fourcc: u32,
+        modifier: u64,
+    ) -> Result<DmabufRequest, WindowError> {
+        //Quoting documentation: "Any argument errors, including non-positive width or height,
+        //mismatch between the number of planes and the format, bad format, bad offset or stride,
+        //may be indicated by fatal protocol errors". Even with create, the size is ours to check.
+        if width <= 0 || height <= 0 {
+            return Err(WindowError::InvalidArgument("empty buffer"));
+        }
+        let queue_handle = self.event_queue.handle();
+        let Some(ref dmabuf) = self.state.dmabuf.dmabuf else {
+            return Err(WindowError::Unsupported("zwp_linux_dmabuf_v1"));
+        };
+
+        self.state.dmabuf.last_request += 1;
+        let request = DmabufRequest(self.state.dmabuf.last_request);
+        let params = dmabuf.create_params(&queue_handle, request);
+        for (index, plane) in planes.iter().enumerate() {
+            params.add(
+                plane.fd,
+                index as u32,
+                plane.offset,
+                plane.stride,
+                (modifier >> 32) as u32,
+                modifier as u32,
+            );
+        }
+        params.create(
+            width,
+            height,
+            fourcc,
+            zwp_linux_buffer_params_v1::Flags::empty(),
+        );
+
+        let _ = self.flush();
+        Ok(request)
+    }
+}
+
+//Only pre-v4 compositors send Format/Modifier, and we ask for the feedback object instead.
+delegate_noop!(AppState: ignore ZwpLinuxDmabufV1);
+
+impl Dispatch<ZwpLinuxDmabufFeedbackV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpLinuxDmabufFeedbackV1,
+        event: zwp_linux_dmabuf_feedback_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let dmabuf = &mut state.dmabuf;
+        match event {
+            zwp_linux_dmabuf_feedback_v1::Event::FormatTable { fd, size } => {
+                dmabuf.format_table = read_format_table(fd, size);
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::MainDevice { device } => {
+                dmabuf.pending_main_device = read_dev_t(&device);
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::TrancheFormats { indices } => {
+                //Quoting documentation: "a list of 16-bit indexes into the format table".
+                for index in indices.chunks_exact(2) {
+                    let index = u16::from_ne_bytes([index[0], index[1]]) as usize;
+                    if let Some(format) = dmabuf.format_table.get(index)
+                        && !dmabuf.pending_formats.contains(format)
+                    {
+                        dmabuf.pending_formats.push(*format);
+                    }
+                }
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::Done => {
+                let formats = std::mem::take(&mut dmabuf.pending_formats);
+                let main_device = dmabuf.pending_main_device;
+
+                //The first Done is the initial state. Later ones are changes the renderer has
+                //to react to by re-allocating its buffers.
+                let first = dmabuf.main_device.is_none() && dmabuf.formats.is_empty();
+                let changed = main_device != dmabuf.main_device || formats != dmabuf.formats;
+
+                dmabuf.main_device = main_device;
+                dmabuf.formats = formats;
+
+                if changed && !first {
+                    state
+                        .events
+                        .push(WindowEvent::DmabufFeedbackChanged { main_device });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+//The answer to create_dmabuf_buffer, either way the params object's last event.
+impl Dispatch<ZwpLinuxBufferParamsV1, DmabufRequest> for AppState {
+    fn event(
+        state: &mut Self,
+        params: &ZwpLinuxBufferParamsV1,
+        event: zwp_linux_buffer_params_v1::Event,
+        &request: &DmabufRequest,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let result = match event {
+            zwp_linux_buffer_params_v1::Event::Created { buffer } => Ok(buffer),
+            zwp_linux_buffer_params_v1::Event::Failed => Err(WindowError::BufferCreationFailed),
+            _ => return,
+        };
+        params.destroy();
+        state
+            .events
+            .push(WindowEvent::DmabufBufferCreated { request, result });
+    }
+
+    //Created carries a brand new wl_buffer, wayland-client needs to know which user data
+    //to give it.
+    event_created_child!(AppState, ZwpLinuxBufferParamsV1, [
+        zwp_linux_buffer_params_v1::EVT_CREATED_OPCODE => (WlBuffer, ()),
+    ]);
+}
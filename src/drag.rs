@@ -0,0 +1,204 @@
+//Click or drag: a press on a title bar only moves the window once the pointer went more than
+//`threshold` away from where it was pressed. Released before that it's a click, and two clicks
+//close enough in time and place are a double click (the built-in decorations maximize on it).
+//Moving on the press itself would make every click on the title bar a tiny move, and leave no
+//way to double-click it.
+//
+//The move is asked with the press's serial, not the motion's: xdg_toplevel.move wants the
+//serial of the press that started the grab. Quoting documentation: "The server may ignore move
+//requests depending on the state of the surface (e.g. fullscreen or maximized), or if the passed
+//serial is no longer valid."
+//
+//Double clicks are timed with Instant, not the events' `time`: those are milliseconds of an
+//unspecified base that wrap around every 49 days, and a wrap between two clicks would make them
+//a double click or never one.
+//
+//DragDetector is public for applications drawing their own title bar (see set_hit_test) or
+//other draggable things: feed it presses, motion and releases of one button.
+
+use std::time::{Duration, Instant};
+
+use crate::Window;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DragConfig {
+    //In surface pixels (logical), from the press, before a press becomes a drag.
+    pub threshold: f64,
+    //Longest time between two clicks of a double click. They also have to be within
+    //`threshold` of each other.
+    pub double_click: Duration,
+}
+
+impl Default for DragConfig {
+    //GTK's defaults for both.
+    fn default() -> Self {
+        DragConfig {
+            threshold: 8.0,
+            double_click: Duration::from_millis(400),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DragOutcome {
+    Click,
+    DoubleClick,
+    //With the press's serial, the one to start a move or resize with.
+    DragStart(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Press {
+    position: (f64, f64),
+    serial: u32,
+    //DragStart was given already, the release is the drag's end.
+    dragging: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DragDetector {
+    config: DragConfig,
+    press: Option<Press>,
+    //When and where the last click was, for the next one.
+    last_click: Option<(Instant, (f64, f64))>,
+}
+
+impl DragDetector {
+    pub fn new(config: DragConfig) -> DragDetector {
+        DragDetector {
+            config,
+            ..DragDetector::default()
+        }
+    }
+
+    pub fn config(&self) -> DragConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: DragConfig) {
+        self.config = config;
+    }
+
+    fn within_threshold(&self, (x, y): (f64, f64), (from_x, from_y): (f64, f64)) -> bool {
+        (x - from_x).hypot(y - from_y) <= self.config.threshold
+    }
+
+    //The button went down at x, y. A press while another is pending replaces it.
+    pub fn press(&mut self, x: f64, y: f64, serial: u32) {
+        self.press = Some(Press {
+            position: (x, y),
+            serial,
+            dragging: false,
+        });
+    }
+
+    //DragStart the first time the pointer is past the threshold while pressed, None otherwise.
+    pub fn motion(&mut self, x: f64, y: f64) -> Option<DragOutcome> {
+        let press = self.press?;
+        if press.dragging || self.within_threshold((x, y), press.position) {
+            return None;
+        }
+        self.press = Some(Press {
+            dragging: true,
+            ..press
+        });
+        //A drag in between makes the next click a first one.
+        self.last_click = None;
+        Some(DragOutcome::DragStart(press.serial))
+    }
+
+    //The button went up: Click or DoubleClick, None after a drag or without a press.
+    pub fn release(&mut self, now: Instant) -> Option<DragOutcome> {
+        let press = self.press.take()?;
+        if press.dragging {
+            return None;
+        }
+        let double = self.last_click.is_some_and(|(at, position)| {
+            now.saturating_duration_since(at) <= self.config.double_click
+                && self.within_threshold(press.position, position)
+        });
+        if double {
+            //A third click starts over.
+            self.last_click = None;
+            Some(DragOutcome::DoubleClick)
+        } else {
+            self.last_click = Some((now, press.position));
+            Some(DragOutcome::Click)
+        }
+    }
+
+    //The pointer left, or the press went to something else: no click nor drag from it.
+    pub fn cancel(&mut self) {
+        self.press = None;
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.press.is_some()
+    }
+}
+
+impl Window {
+    //For the built-in decorations' title bar, see drag.rs.
+    pub fn set_drag_config(&mut self, config: DragConfig) {
+        self.state.hit_test.drag_config = config;
+    }
+
+    pub fn drag_config(&self) -> DragConfig {
+        self.state.hit_test.drag_config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_motion_is_still_a_click() {
+        let mut detector = DragDetector::default();
+        let start = Instant::now();
+        detector.press(10.0, 10.0, 7);
+        assert_eq!(detector.motion(15.0, 14.0), None);
+        assert_eq!(detector.release(start), Some(DragOutcome::Click));
+
+        detector.press(10.0, 10.0, 8);
+        assert_eq!(detector.motion(20.0, 10.0), Some(DragOutcome::DragStart(8)));
+        //Once.
+        assert_eq!(detector.motion(40.0, 10.0), None);
+        assert_eq!(detector.release(start), None);
+        assert_eq!(detector.release(start), None);
+    }
+
+    #[test]
+    fn double_clicks_need_time_and_place() {
+        let mut detector = DragDetector::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut click = |x, ms| {
+            detector.press(x, 0.0, 0);
+            detector.release(at(ms))
+        };
+        assert_eq!(click(0.0, 0), Some(DragOutcome::Click));
+        assert_eq!(click(2.0, 300), Some(DragOutcome::DoubleClick));
+        //A third one is a first one again.
+        assert_eq!(click(2.0, 400), Some(DragOutcome::Click));
+        assert_eq!(click(2.0, 900), Some(DragOutcome::Click));
+        assert_eq!(click(50.0, 1000), Some(DragOutcome::Click));
+        assert_eq!(click(50.0, 1400), Some(DragOutcome::DoubleClick));
+    }
+
+    #[test]
+    fn a_drag_in_between_breaks_a_double_click() {
+        let mut detector = DragDetector::default();
+        let now = Instant::now();
+        detector.press(0.0, 0.0, 0);
+        assert_eq!(detector.release(now), Some(DragOutcome::Click));
+        detector.press(0.0, 0.0, 1);
+        detector.motion(30.0, 0.0);
+        detector.release(now);
+        detector.press(0.0, 0.0, 2);
+        assert_eq!(detector.release(now), Some(DragOutcome::Click));
+        detector.press(0.0, 0.0, 3);
+        detector.cancel();
+        assert_eq!(detector.release(now), None);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    ffi::{CString, c_char, c_void},
+    ptr,
+};
+
+use libloading::Library;
+use wayland_client::{Connection, Proxy, protocol::wl_surface::WlSurface};
+
+use crate::{Window, WindowError};
+
+//Just enough of EGL (and libwayland-egl) to get an OpenGL ES context on our surface. The libraries
+//are loaded at runtime, so a machine without them still runs the shm path fine.
+type EglDisplay = *mut c_void;
+type EglConfig = *mut c_void;
+type EglContext = *mut c_void;
+type EglSurface = *mut c_void;
+type EglInt = i32;
+
+const EGL_NONE: EglInt = 0x3038;
+const EGL_SURFACE_TYPE: EglInt = 0x3033;
+const EGL_WINDOW_BIT: EglInt = 0x0004;
+const EGL_RED_SIZE: EglInt = 0x3024;
+const EGL_GREEN_SIZE: EglInt = 0x3023;
+const EGL_BLUE_SIZE: EglInt = 0x3022;
+const EGL_ALPHA_SIZE: EglInt = 0x3021;
+const EGL_RENDERABLE_TYPE: EglInt = 0x3040;
+const EGL_OPENGL_ES2_BIT: EglInt = 0x0004;
+const EGL_CONTEXT_CLIENT_VERSION: EglInt = 0x3098;
+const EGL_OPENGL_ES_API: u32 = 0x30A0;
+const EGL_PLATFORM_WAYLAND_KHR: u32 = 0x31D8;
+
+//Function pointers pulled out of the libraries. Kept together with the libraries so they can't
+//outlive them.
+struct EglApi {
+    get_platform_display: unsafe extern "C" fn(u32, *mut c_void, *const isize) -> EglDisplay,
+    initialize: unsafe extern "C" fn(EglDisplay, *mut EglInt, *mut EglInt) -> u32,
+    bind_api: unsafe extern "C" fn(u32) -> u32,
+    choose_config:
+        unsafe extern "C" fn(EglDisplay, *const EglInt, *mut EglConfig, EglInt, *mut EglInt) -> u32,
+    create_context:
+        unsafe extern "C" fn(EglDisplay, EglConfig, EglContext, *const EglInt) -> EglContext,
+    create_window_surface:
+        unsafe extern "C" fn(EglDisplay, EglConfig, *mut c_void, *const EglInt) -> EglSurface,
+    make_current: unsafe extern "C" fn(EglDisplay, EglSurface, EglSurface, EglContext) -> u32,
+    swap_buffers: unsafe extern "C" fn(EglDisplay, EglSurface) -> u32,
+    swap_interval: unsafe extern "C" fn(EglDisplay, EglInt) -> u32,
+    get_proc_address: unsafe extern "C" fn(*const c_char) -> *const c_void,
+    destroy_surface: unsafe extern "C" fn(EglDisplay, EglSurface) -> u32,
+    destroy_context: unsafe extern "C" fn(EglDisplay, EglContext) -> u32,
+    terminate: unsafe extern "C" fn(EglDisplay) -> u32,
+    wl_egl_window_create: unsafe extern "C" fn(*mut c_void, i32, i32) -> *mut c_void,
+    wl_egl_window_resize: unsafe extern "C" fn(*mut c_void, i32, i32, i32, i32),
+    wl_egl_window_destroy: unsafe extern "C" fn(*mut c_void),
+    _egl: Library,
+    _wayland_egl: Library,
+}
+
+fn egl_error(what: &str) -> WindowError {
+    WindowError::Egl(what.to_string())
+}
+
+impl EglApi {
+    fn load() -> Result<EglApi, WindowError> {
+        //SAFETY: loading well known system libraries, their initializers have no preconditions.
+        let egl = unsafe { Library::new("libEGL.so.1") }
+            .map_err(|_| egl_error("libEGL.so.1 not found"))?;
+        let wayland_egl = unsafe { Library::new("libwayland-egl.so.1") }
+            .map_err(|_| egl_error("libwayland-egl.so.1 not found"))?;
+
+        macro_rules! load {
+            ($lib:expr, $name:literal) => {
+                //SAFETY: the signatures above match the EGL 1.5 / wayland-egl headers.
+                *unsafe { $lib.get(concat!($name, "\0").as_bytes()) }
+                    .map_err(|_| egl_error(concat!("missing symbol ", $name)))?
+            };
+        }
+
+        Ok(EglApi {
+            get_platform_display: load!(egl, "eglGetPlatformDisplay"),
+            initialize: load!(egl, "eglInitialize"),
+            bind_api: load!(egl, "eglBindAPI"),
+            choose_config: load!(egl, "eglChooseConfig"),
+            create_context: load!(egl, "eglCreateContext"),
+            create_window_surface: load!(egl, "eglCreateWindowSurface"),
+            make_current: load!(egl, "eglMakeCurrent"),
+            swap_buffers: load!(egl, "eglSwapBuffers"),
+            swap_interval: load!(egl, "eglSwapInterval"),
+            get_proc_address: load!(egl, "eglGetProcAddress"),
+            destroy_surface: load!(egl, "eglDestroySurface"),
+            destroy_context: load!(egl, "eglDestroyContext"),
+            terminate: load!(egl, "eglTerminate"),
+            wl_egl_window_create: load!(wayland_egl, "wl_egl_window_create"),
+            wl_egl_window_resize: load!(wayland_egl, "wl_egl_window_resize"),
+            wl_egl_window_destroy: load!(wayland_egl, "wl_egl_window_destroy"),
+            _egl: egl,
+            _wayland_egl: wayland_egl,
+        })
+    }
+}
+
+//EGL state for the base surface. wl_egl_window is what sits between EGL and the wl_surface:
+//EGL renders into buffers of its size and attaches them to the surface on every swap.
+pub(crate) struct EglState {
+    api: EglApi,
+    display: EglDisplay,
+    context: EglContext,
+    surface: EglSurface,
+    egl_window: *mut c_void,
+}
+
+impl EglState {
+    fn new(
+        connection: &Connection,
+        wl_surface: &WlSurface,
+        (width, height): (u32, u32),
+    ) -> Result<EglState, WindowError> {
+        let api = EglApi::load()?;
+        let display_ptr = connection.backend().display_ptr() as *mut c_void;
+        let surface_ptr = wl_surface.id().as_ptr() as *mut c_void;
+
+        //SAFETY: the pointers come straight from libwayland and live as long as the connection
+        //and the surface, which both outlive this state (it is dropped before them).
+        unsafe {
+            let display =
+                (api.get_platform_display)(EGL_PLATFORM_WAYLAND_KHR, display_ptr, ptr::null());
+            if display.is_null() || (api.initialize)(display, ptr::null_mut(), ptr::null_mut()) == 0
+            {
+                return Err(egl_error("eglInitialize failed"));
+            }
+            (api.bind_api)(EGL_OPENGL_ES_API);
+
+            let config_attribs = [
+                EGL_SURFACE_TYPE,
+                EGL_WINDOW_BIT,
+                EGL_RED_SIZE,
+                8,
+                EGL_GREEN_SIZE,
+                8,
+                EGL_BLUE_SIZE,
+                8,
+                EGL_ALPHA_SIZE,
+                8,
+                EGL_RENDERABLE_TYPE,
+                EGL_OPENGL_ES2_BIT,
+                EGL_NONE,
+            ];
+            let mut config = ptr::null_mut();
+            let mut count = 0;
+            if (api.choose_config)(display, config_attribs.as_ptr(), &mut config, 1, &mut count)
+                == 0
+                || count == 0
+            {
+                (api.terminate)(display);
+                return Err(egl_error("no suitable EGL config"));
+            }
+
+            let context_attribs = [EGL_CONTEXT_CLIENT_VERSION, 2, EGL_NONE];
+            let context =
+                (api.create_context)(display, config, ptr::null_mut(), context_attribs.as_ptr());
+            if context.is_null() {
+                (api.terminate)(display);
+                return Err(egl_error("eglCreateContext failed"));
+            }
+
+            let egl_window = (api.wl_egl_window_create)(surface_ptr, width as i32, height as i32);
+            if egl_window.is_null() {
+                (api.destroy_context)(display, context);
+                (api.terminate)(display);
+                return Err(egl_error("wl_egl_window_create failed"));
+            }
+            let surface = (api.create_window_surface)(display, config, egl_window, ptr::null());
+            if surface.is_null() {
+                (api.wl_egl_window_destroy)(egl_window);
+                (api.destroy_context)(display, context);
+                (api.terminate)(display);
+                return Err(egl_error("eglCreateWindowSurface failed"));
+            }
+
+            Ok(EglState {
+                api,
+                display,
+                context,
+                surface,
+                egl_window,
+            })
+        }
+    }
+
+    pub(crate) fn resize(&self, width: i32, height: i32) {
+        //SAFETY: egl_window is alive until drop.
+        unsafe { (self.api.wl_egl_window_resize)(self.egl_window, width, height, 0, 0) };
+    }
+}
+
+impl Drop for EglState {
+    fn drop(&mut self) {
+        //SAFETY: everything here was created in new() and is destroyed exactly once.
+        unsafe {
+            (self.api.make_current)(
+                self.display,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            (self.api.destroy_surface)(self.display, self.surface);
+            (self.api.wl_egl_window_destroy)(self.egl_window);
+            (self.api.destroy_context)(self.display, self.context);
+            (self.api.terminate)(self.display);
+        }
+    }
+}
+
+impl Window {
+    //Makes the window's GL context current on this thread, creating it the first time.
+    pub fn make_current(&mut self) -> Result<(), WindowError> {
+        if self.state.egl.is_none() {
+            let Some(ref surface) = self.state.base_surface else {
+                return Err(WindowError::NotConfigured);
+            };
+            let egl = EglState::new(&self.connection, surface, self.state.buffer_size)?;
+            self.state.egl = Some(egl);
+        }
+
+        let egl = self.state.egl.as_ref().unwrap();
+        //SAFETY: all handles are alive as long as egl is.
+        unsafe {
+            if (egl.api.make_current)(egl.display, egl.surface, egl.surface, egl.context) == 0 {
+                return Err(egl_error("eglMakeCurrent failed"));
+            }
+            //Swap interval 1: eglSwapBuffers waits for the frame callback of the previous frame,
+            //so rendering in a loop is paced by the compositor instead of spinning.
+            (egl.api.swap_interval)(egl.display, 1);
+        }
+        Ok(())
+    }
+
+    //Presents what was rendered. Like any buffer attach, this can't happen before the first
+    //configure is acked.
+    pub fn swap_buffers(&mut self) -> Result<(), WindowError> {
+        if !self.state.configured() {
+            return Err(WindowError::NotConfigured);
+        }
+        let Some(ref egl) = self.state.egl else {
+            return Err(egl_error("make_current was never called"));
+        };
+
+        //SAFETY: all handles are alive as long as egl is.
+        if unsafe { (egl.api.swap_buffers)(egl.display, egl.surface) } == 0 {
+            return Err(egl_error("eglSwapBuffers failed"));
+        }
+        //eglSwapBuffers attaches and commits for us.
+        self.state.startup_committed();
+        self.state.log_commit("EGL buffer");
+        Ok(())
+    }
+
+    //Looks up a GL function (glClear, glClearColor...), null if it doesn't exist.
+    pub fn gl_proc_address(&self, name: &str) -> *const c_void {
+        let (Some(egl), Ok(name)) = (self.state.egl.as_ref(), CString::new(name)) else {
+            return ptr::null();
+        };
+        //SAFETY: name is a valid NUL-terminated string.
+        unsafe { (egl.api.get_proc_address)(name.as_ptr()) }
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::{ProtocolErrorInfo, globals::missing_hint};
+
+//Errors returned by the requests the window exposes to the application.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowError {
+    //The compositor never advertised the global this request needs.
+    Unsupported(&'static str),
+    //The seat has no pointer (or it hasn't been announced yet).
+    NoPointer,
+    //The compositor refused to create the buffer (e.g. a dmabuf it couldn't import).
+    BufferCreationFailed,
+    //The request needs the surface to be configured first.
+    NotConfigured,
+    //Reading from or writing to the compositor failed (disconnect, protocol error...).
+    Connection(String),
+    //The connection is gone for good, see WindowEvent::ConnectionLost.
+    Disconnected,
+    //Same, over a protocol error of ours, decoded (see diagnostics.rs).
+    Protocol(ProtocolErrorInfo),
+    //An argument the request can't work with.
+    InvalidArgument(&'static str),
+    //The request is out of the order the protocol wants, see Lifecycle. Says what was wrong.
+    InvalidState(&'static str),
+    //The window this was meant for is gone.
+    Closed,
+    //Loading or talking to EGL failed.
+    Egl(String),
+    //An image couldn't be read or decoded.
+    Image(String),
+    //The window doesn't show anything drawn into our shm buffer (EGL, present_buffer, a solid
+    //color, or nothing committed yet), so there's nothing of ours to read back.
+    NoContent,
+    //A font file couldn't be read or isn't one we can draw with.
+    Font(String),
+    //A window recording couldn't be read back.
+    Recording(String),
+    //A program couldn't be started, see spawn_with_activation.
+    Spawn(String),
+    //The EventLoop couldn't be made, or couldn't take a source (an fd epoll can't watch).
+    EventLoop(String),
+    //The compositor doesn't advertise globals a window can't do without. `found` is what it
+    //does advertise.
+    MissingGlobals {
+        missing: Vec<&'static str>,
+        found: Vec<String>,
+    },
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowError::Unsupported(interface) => {
+                write!(f, "the compositor does not support {interface}")
+            }
+            WindowError::NoPointer => write!(f, "no pointer is available on the seat"),
+            WindowError::BufferCreationFailed => {
+                write!(f, "the compositor failed to create the buffer")
+            }
+            WindowError::NotConfigured => write!(f, "the window has not been configured yet"),
+            WindowError::Connection(reason) => {
+                write!(f, "talking to the compositor failed: {reason}")
+            }
+            WindowError::Disconnected => write!(f, "the compositor closed the connection"),
+            WindowError::Protocol(info) => write!(f, "protocol error: {info}"),
+            WindowError::InvalidArgument(reason) => write!(f, "invalid argument: {reason}"),
+            WindowError::InvalidState(reason) => write!(f, "out of order: {reason}"),
+            WindowError::Closed => write!(f, "the window was closed"),
+            WindowError::Egl(reason) => write!(f, "EGL error: {reason}"),
+            WindowError::Image(reason) => write!(f, "image error: {reason}"),
+            WindowError::NoContent => write!(f, "the window shows no content drawn by us"),
+            WindowError::Font(reason) => write!(f, "font error: {reason}"),
+            WindowError::Recording(reason) => write!(f, "recording error: {reason}"),
+            WindowError::Spawn(reason) => write!(f, "couldn't start {reason}"),
+            WindowError::EventLoop(reason) => write!(f, "event loop error: {reason}"),
+            WindowError::MissingGlobals { missing, found } => {
+                write!(
+                    f,
+                    "the compositor does not support {} (it has {})",
+                    missing.join(", "),
+                    found.join(", ")
+                )?;
+                match missing_hint(missing, found) {
+                    "" => Ok(()),
+                    hint => write!(f, ": {hint}"),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for WindowError {}
@@ -0,0 +1,626 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    os::fd::{AsFd, OwnedFd},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use calloop::{
+    Interest, Mode, PostAction, RegistrationToken,
+    generic::Generic,
+    timer::{self, Timer},
+};
+use calloop_wayland_source::WaylandSource;
+use wayland_client::Connection;
+
+use crate::{Key, Window, WindowError, WindowEvent};
+
+//The window on a calloop EventLoop (the calloop feature), for when the application needs to wait
+//on more than the compositor: timers, its own sockets, signals... LoopHandle::calloop takes any
+//calloop source, its other methods are the usual ones, with the Window in their callbacks.
+//
+//The plain `while window.is_running() { window.pump_events() }` loop works without it.
+//
+//The connection is a calloop_wayland_source::WaylandSource, which reads the socket when it's
+//readable. The queue it wraps stays empty: libwayland sorts the events into queues by object,
+//whoever reads, so they land in the window's, which its callback dispatches. Key and pointer
+//button repeats and the application's timers are calloop Timers, so each round sleeps until the
+//earliest of them (animation timers are put away while the window is suspended), the diagnostic
+//overlay's redraw or something to read, never on a fixed tick. Window::loop_stats counts the
+//wakeups.
+pub struct EventLoop {
+    window: Window,
+    inner: calloop::EventLoop<'static, Window>,
+    sources: Rc<RefCell<Sources>>,
+    //The WaylandSource, with the connection it reads: Window::reconnect makes a new one.
+    wayland: Option<(Connection, RegistrationToken)>,
+    //The proxies' pipe, from the first proxy on.
+    user_events: Option<RegistrationToken>,
+}
+
+//What a timer callback wants next: go away, or run again after the given time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutAction {
+    Drop,
+    ToDuration(Duration),
+    //Run again after one refresh of the screen the window is on (Window::current_refresh_interval,
+    //read anew every time, so it follows the window to a faster screen), 60 Hz while unknown. The
+    //tick for animation timers until frame callbacks pace them.
+    NextFrame,
+}
+
+//When the refresh is unknown.
+const DEFAULT_FRAME_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+//Identifies an inserted timer or fd source, to remove it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceToken(u64);
+
+type TimerCallback = Box<dyn FnMut(&mut Window) -> TimeoutAction>;
+
+struct TimerSource {
+    //Its calloop Timer, None while put away (an animation timer, the window being suspended).
+    registration: Option<RegistrationToken>,
+    //Animation timers wait while the window is suspended.
+    animation: bool,
+    //Taken out while the callback runs, so it can use the LoopHandle itself.
+    callback: Option<TimerCallback>,
+}
+
+//What the callbacks of one wait did.
+#[derive(Debug, Clone, Copy, Default)]
+struct Woke {
+    //Something was readable: the compositor, a proxy, a source.
+    read: bool,
+    //A timer was due.
+    timer: bool,
+    //Something ran for the application: the window's events, a timer, a source.
+    ran: bool,
+    //Something woke us for nothing: a delete_id (the backend takes care of those), an animation
+    //timer put away.
+    idle: bool,
+}
+
+struct Sources {
+    next_token: u64,
+    timers: HashMap<SourceToken, TimerSource>,
+    fds: HashMap<SourceToken, RegistrationToken>,
+    //Animation timer delays are multiplied by it while the user is idle, see set_idle_multiplier.
+    idle_multiplier: u32,
+    //The held key repeating and its timer.
+    key_repeat: Option<(u32, RegistrationToken)>,
+    //The same for a held pointer button, by the serial of its press, see button_repeat.rs.
+    button_repeat: Option<(u32, RegistrationToken)>,
+    woke: Woke,
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        Sources {
+            next_token: 0,
+            timers: HashMap::new(),
+            fds: HashMap::new(),
+            idle_multiplier: 1,
+            key_repeat: None,
+            button_repeat: None,
+            woke: Woke::default(),
+        }
+    }
+}
+
+impl Sources {
+    fn token(&mut self) -> SourceToken {
+        self.next_token += 1;
+        SourceToken(self.next_token)
+    }
+}
+
+//Cloneable handle to add and remove sources, also from inside callbacks.
+#[derive(Clone)]
+pub struct LoopHandle {
+    inner: calloop::LoopHandle<'static, Window>,
+    sources: Rc<RefCell<Sources>>,
+}
+
+impl LoopHandle {
+    //Calls `callback` once `delay` has passed, then again as it asks.
+    pub fn insert_timer(
+        &self,
+        delay: Duration,
+        callback: impl FnMut(&mut Window) -> TimeoutAction + 'static,
+    ) -> SourceToken {
+        self.push_timer(delay, false, Box::new(callback))
+    }
+
+    //A timer that redraws: same as insert_timer, but it doesn't run while the window is
+    //suspended (nobody would see the frames) and runs right away when it's resumed, so the
+    //content catches up.
+    pub fn insert_animation_timer(
+        &self,
+        delay: Duration,
+        callback: impl FnMut(&mut Window) -> TimeoutAction + 'static,
+    ) -> SourceToken {
+        self.push_timer(delay, true, Box::new(callback))
+    }
+
+    fn push_timer(&self, delay: Duration, animation: bool, callback: TimerCallback) -> SourceToken {
+        let token = {
+            let mut sources = self.sources.borrow_mut();
+            let token = sources.token();
+            sources.timers.insert(
+                token,
+                TimerSource {
+                    registration: None,
+                    animation,
+                    callback: Some(callback),
+                },
+            );
+            token
+        };
+        self.arm(token, Timer::from_duration(delay));
+        token
+    }
+
+    //Puts the timer `token` on calloop. Its callback holds the sources, not the handle: calloop
+    //keeps the callbacks, a handle in there would keep the loop alive.
+    fn arm(&self, token: SourceToken, timer: Timer) {
+        let sources = self.sources.clone();
+        let registration = self
+            .inner
+            .insert_source(timer, move |_, _, window| fire(&sources, token, window))
+            .ok();
+        if let Some(timer) = self.sources.borrow_mut().timers.get_mut(&token) {
+            timer.registration = registration;
+        }
+    }
+
+    //Resumed, or the user back: the animation timers run right away.
+    fn rearm_animations(&self) {
+        let animations: Vec<_> = self
+            .sources
+            .borrow_mut()
+            .timers
+            .iter_mut()
+            .filter(|(_, timer)| timer.animation)
+            .map(|(&token, timer)| (token, timer.registration.take()))
+            .collect();
+        for (token, registration) in animations {
+            if let Some(registration) = registration {
+                self.inner.remove(registration);
+            }
+            self.arm(token, Timer::immediate());
+        }
+    }
+
+    //Calls `callback` every time `fd` is readable. The loop keeps the fd until the source is
+    //removed. Fails for fds epoll can't watch, like regular files.
+    pub fn insert_source<F: AsFd + 'static>(
+        &self,
+        fd: F,
+        mut callback: impl FnMut(&F, &mut Window) + 'static,
+    ) -> Result<SourceToken, WindowError> {
+        let sources = self.sources.clone();
+        let registration = self
+            .inner
+            .insert_source(
+                Generic::new(fd, Interest::READ, Mode::Level),
+                move |_, fd, window| {
+                    woken(&sources, window, true);
+                    callback(fd, window);
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|err| WindowError::EventLoop(err.error.to_string()))?;
+        let mut sources = self.sources.borrow_mut();
+        let token = sources.token();
+        sources.fds.insert(token, registration);
+        Ok(token)
+    }
+
+    //The calloop handle itself, for any other calloop source: channels, signals, pings... Their
+    //callbacks get the Window too.
+    pub fn calloop(&self) -> &calloop::LoopHandle<'static, Window> {
+        &self.inner
+    }
+
+    //Stretches the delays animation timers ask for while the user is idle (Window::is_user_idle,
+    //so only with an on_idle timeout registered): 60 turns a 60 fps animation into 1 fps. They
+    //go back to their pace, with a run right away, when the user is back. 1, the default, turns
+    //it off.
+    pub fn set_idle_multiplier(&self, multiplier: u32) {
+        self.sources.borrow_mut().idle_multiplier = multiplier.max(1);
+    }
+
+    pub fn remove(&self, token: SourceToken) {
+        let registration = {
+            let mut sources = self.sources.borrow_mut();
+            match sources.timers.remove(&token) {
+                Some(timer) => timer.registration,
+                None => sources.fds.remove(&token),
+            }
+        };
+        //From inside its own callback too: calloop lets go of it once the callback returns.
+        if let Some(registration) = registration {
+            self.inner.remove(registration);
+        }
+    }
+}
+
+//One of our readable sources (the compositor, the proxies' pipe, the application's) is about to
+//run: the wait is over.
+fn woken(sources: &RefCell<Sources>, window: &mut Window, ran: bool) {
+    let woke = &mut sources.borrow_mut().woke;
+    woke.read = true;
+    woke.ran |= ran;
+    window.state.watchdog.wait_ended();
+}
+
+//The timer `token` is due.
+fn fire(
+    sources: &RefCell<Sources>,
+    token: SourceToken,
+    window: &mut Window,
+) -> timer::TimeoutAction {
+    let (callback, animation) = {
+        let mut sources = sources.borrow_mut();
+        sources.woke.timer = true;
+        //Removed meanwhile.
+        let Some(timer) = sources.timers.get_mut(&token) else {
+            return timer::TimeoutAction::Drop;
+        };
+        //Put away until the window is resumed, see rearm_animations.
+        if timer.animation && window.is_suspended() {
+            timer.registration = None;
+            sources.woke.idle = true;
+            return timer::TimeoutAction::Drop;
+        }
+        let animation = timer.animation;
+        let Some(callback) = timer.callback.take() else {
+            return timer::TimeoutAction::Drop;
+        };
+        sources.woke.ran = true;
+        (callback, animation)
+    };
+    window.state.watchdog.wait_ended();
+
+    let mut callback = callback;
+    let mut delay = match callback(window) {
+        TimeoutAction::Drop => None,
+        TimeoutAction::ToDuration(delay) => Some(delay),
+        TimeoutAction::NextFrame => Some(
+            window
+                .current_refresh_interval()
+                .unwrap_or(DEFAULT_FRAME_INTERVAL),
+        ),
+    };
+
+    let mut sources = sources.borrow_mut();
+    if animation && window.is_user_idle() {
+        let multiplier = sources.idle_multiplier;
+        delay = delay.map(|delay| delay.saturating_mul(multiplier));
+    }
+    //The callback may have removed its own timer meanwhile.
+    let Some(timer) = sources.timers.get_mut(&token) else {
+        return timer::TimeoutAction::Drop;
+    };
+    match delay {
+        Some(delay) => {
+            timer.callback = Some(callback);
+            timer::TimeoutAction::ToDuration(delay)
+        }
+        None => {
+            sources.timers.remove(&token);
+            timer::TimeoutAction::Drop
+        }
+    }
+}
+
+//The held key `key` repeats, if it's still held. The next one is `interval` after this one,
+//whenever this one ran: a loop that fell behind doesn't get a burst of them.
+fn repeat_key(sources: &RefCell<Sources>, key: u32, window: &mut Window) -> timer::TimeoutAction {
+    let repeat = &window.state.key_repeat;
+    let Some((_, seat)) = repeat.held.clone().filter(|&(held, _)| held == key) else {
+        sources.borrow_mut().key_repeat = None;
+        return timer::TimeoutAction::Drop;
+    };
+    let interval = Duration::from_secs(1) / repeat.rate.max(1) as u32;
+    {
+        let woke = &mut sources.borrow_mut().woke;
+        woke.timer = true;
+        woke.ran = true;
+    }
+    window.state.watchdog.wait_ended();
+
+    //A bound key's repeats are the binding's, not events.
+    if window.state.fire_key_binding(&seat, key, true) {
+        window.run_key_bindings();
+    } else {
+        let event = WindowEvent::KeyRepeat {
+            logical_key: window.state.held_logical_key(&seat, key),
+            seat,
+            key: Key::from_evdev(key),
+        };
+        window.state.events.push(event);
+    }
+    timer::TimeoutAction::ToDuration(interval)
+}
+
+//The same for the button pressed with `serial`.
+fn repeat_button(
+    sources: &RefCell<Sources>,
+    serial: u32,
+    window: &mut Window,
+) -> timer::TimeoutAction {
+    let repeat = &window.state.button_repeat;
+    let (Some((_, interval)), Some(held)) = (
+        repeat.timing,
+        repeat.held.as_ref().filter(|held| held.serial == serial),
+    ) else {
+        sources.borrow_mut().button_repeat = None;
+        return timer::TimeoutAction::Drop;
+    };
+    let (x, y) = held.position;
+    let event = WindowEvent::PointerButtonRepeat {
+        seat: held.seat.clone(),
+        button: held.button,
+        x,
+        y,
+    };
+    {
+        let woke = &mut sources.borrow_mut().woke;
+        woke.timer = true;
+        woke.ran = true;
+    }
+    window.state.watchdog.wait_ended();
+    window.state.events.push(event);
+    timer::TimeoutAction::ToDuration(interval)
+}
+
+//The compositor said something: the WaylandSource read it, into the window's queue if it was
+//for the window.
+fn read_wayland(sources: &RefCell<Sources>, window: &mut Window) {
+    woken(sources, window, false);
+    let events = window.state.events.len();
+    let dispatched = window.dispatch_queued();
+    window.state.dispatch_side_queue();
+    if dispatched > 0 || window.state.events.len() != events {
+        sources.borrow_mut().woke.ran = true;
+    } else {
+        sources.borrow_mut().woke.idle = true;
+        //Still waiting, as far as the watchdog is concerned.
+        window.state.wait_started();
+    }
+}
+
+impl Window {
+    pub fn into_event_loop(self) -> Result<EventLoop, WindowError> {
+        let inner =
+            calloop::EventLoop::try_new().map_err(|err| WindowError::EventLoop(err.to_string()))?;
+        Ok(EventLoop {
+            window: self,
+            inner,
+            sources: Rc::default(),
+            wayland: None,
+            user_events: None,
+        })
+    }
+}
+
+impl EventLoop {
+    pub fn handle(&self) -> LoopHandle {
+        LoopHandle {
+            inner: self.inner.handle(),
+            sources: self.sources.clone(),
+        }
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn window_mut(&mut self) -> &mut Window {
+        &mut self.window
+    }
+
+    //Runs until the window stops running, handing every event to `handler`.
+    pub fn run(&mut self, mut handler: impl FnMut(WindowEvent, &mut Window)) {
+        while self.window.is_running() {
+            self.dispatch(None, &mut handler);
+        }
+    }
+
+    //One round: wait (at most `timeout`, None meaning until something happens) for the
+    //compositor, a timer or a source, then run whatever became ready.
+    pub fn dispatch(
+        &mut self,
+        timeout: Option<Duration>,
+        handler: &mut impl FnMut(WindowEvent, &mut Window),
+    ) {
+        let window = &mut self.window;
+        window.cancel_read();
+        window.state.watchdog.round_started();
+
+        //Events already in the queue have to be handled first: calloop only wakes up for the
+        //socket, and they are no longer in it.
+        window.dispatch_queued();
+        window.send_requests();
+        self.follow_connection();
+        let writable = self.watch_writable();
+
+        let mut deadline = timeout.map(|timeout| Instant::now() + timeout);
+        if let Some(next) = self.window.wakeup_deadline() {
+            deadline = Some(deadline.map_or(next, |deadline| deadline.min(next)));
+        }
+        let wait = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+        self.window.state.wait_started();
+        let woke = loop {
+            self.sources.borrow_mut().woke = Woke::default();
+            let left = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if let Err(err) = self.inner.dispatch(left, &mut self.window) {
+                //The connection failing, which dispatch_queued below finds out about.
+                log::debug!("event loop: {err}");
+                break Woke {
+                    read: true,
+                    ran: true,
+                    ..Woke::default()
+                };
+            }
+            let woke = self.sources.borrow().woke;
+            //Woken for nothing (see Woke::idle): keep waiting, it isn't a round.
+            if !woke.idle || woke.ran || deadline.is_some_and(|deadline| deadline <= Instant::now())
+            {
+                break woke;
+            }
+        };
+        self.window.state.watchdog.wait_ended();
+        if let Some(writable) = writable {
+            self.inner.handle().remove(writable);
+        }
+        //Not sleeping at all isn't a wakeup. Neither a timer nor the deadline: a source of the
+        //application's, inserted through LoopHandle::calloop.
+        if wait != Some(Duration::ZERO) {
+            let timed_out = !woke.read
+                && (woke.timer || deadline.is_some_and(|deadline| deadline <= Instant::now()));
+            self.window.state.watchdog.woke(timed_out);
+        }
+
+        let handle = self.handle();
+        let window = &mut self.window;
+        window.dispatch_queued();
+        window.deliver_user_events();
+        window.apply_configure();
+
+        for event in std::mem::take(&mut window.state.events) {
+            if event == WindowEvent::Resumed || matches!(event, WindowEvent::UserActive { .. }) {
+                handle.rearm_animations();
+            }
+            handler(event, window);
+        }
+
+        self.follow_key_repeat();
+        self.follow_button_repeat();
+    }
+
+    //The WaylandSource on the window's connection, anew after a reconnect, and the proxies' pipe
+    //once there is one.
+    fn follow_connection(&mut self) {
+        let handle = self.inner.handle();
+        let connection = &self.window.connection;
+        if self
+            .wayland
+            .as_ref()
+            .is_none_or(|(current, _)| current != connection)
+        {
+            if let Some((_, registration)) = self.wayland.take() {
+                handle.remove(registration);
+            }
+            let source =
+                WaylandSource::<Window>::new(connection.clone(), connection.new_event_queue());
+            let sources = self.sources.clone();
+            self.wayland = handle
+                .insert_source(source, move |_, _, window: &mut Window| {
+                    read_wayland(&sources, window);
+                    Ok(0)
+                })
+                .map_err(|err| log::warn!("the event loop can't read the compositor: {err}"))
+                .ok()
+                .map(|registration| (connection.clone(), registration));
+        }
+
+        if self.user_events.is_some() {
+            return;
+        }
+        let Some(fd) = self.window.user_event_fd() else {
+            return;
+        };
+        let Ok(fd) = rustix::io::dup(fd) else {
+            return;
+        };
+        let sources = self.sources.clone();
+        self.user_events = handle
+            .insert_source(
+                Generic::new(fd, Interest::READ, Mode::Level),
+                move |_, _, window: &mut Window| {
+                    woken(&sources, window, true);
+                    window.deliver_user_events();
+                    Ok(PostAction::Continue)
+                },
+            )
+            .ok();
+    }
+
+    //A blocked flush (see flush.rs) goes on once the socket is writable: a source just for this
+    //round.
+    fn watch_writable(&mut self) -> Option<RegistrationToken> {
+        if !self.window.flush_blocked() {
+            return None;
+        }
+        let fd: OwnedFd = rustix::io::dup(self.window.connection.backend().poll_fd()).ok()?;
+        let sources = self.sources.clone();
+        self.inner
+            .handle()
+            .insert_source(
+                Generic::new(fd, Interest::WRITE, Mode::Level),
+                move |_, _, window: &mut Window| {
+                    woken(&sources, window, true);
+                    window.send_requests();
+                    Ok(PostAction::Disable)
+                },
+            )
+            .ok()
+    }
+
+    //Follows the held key: a new one starts repeating after the delay, a released one stops.
+    fn follow_key_repeat(&mut self) {
+        let repeat = &self.window.state.key_repeat;
+        let held = repeat.held.as_ref().map(|&(key, _)| key);
+        let delay = Duration::from_millis(repeat.delay.max(0) as u64);
+        let current = self.sources.borrow().key_repeat;
+        if current.map(|(key, _)| key) == held {
+            return;
+        }
+        let handle = self.inner.handle();
+        if let Some((_, registration)) = current {
+            handle.remove(registration);
+        }
+        let sources = self.sources.clone();
+        let key_repeat = held.and_then(|key| {
+            handle
+                .insert_source(Timer::from_duration(delay), move |_, _, window| {
+                    repeat_key(&sources, key, window)
+                })
+                .ok()
+                .map(|registration| (key, registration))
+        });
+        self.sources.borrow_mut().key_repeat = key_repeat;
+    }
+
+    //The same for a button held in a repeat region.
+    fn follow_button_repeat(&mut self) {
+        let repeat = &self.window.state.button_repeat;
+        let held = repeat
+            .timing
+            .zip(repeat.held.as_ref())
+            .map(|((delay, _), held)| (held.serial, delay));
+        let current = self.sources.borrow().button_repeat;
+        if current.map(|(serial, _)| serial) == held.map(|(serial, _)| serial) {
+            return;
+        }
+        let handle = self.inner.handle();
+        if let Some((_, registration)) = current {
+            handle.remove(registration);
+        }
+        let sources = self.sources.clone();
+        let button_repeat = held.and_then(|(serial, delay)| {
+            handle
+                .insert_source(Timer::from_duration(delay), move |_, _, window| {
+                    repeat_button(&sources, serial, window)
+                })
+                .ok()
+                .map(|registration| (serial, registration))
+        });
+        self.sources.borrow_mut().button_repeat = button_repeat;
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    future::Future,
+    os::fd::{AsFd, OwnedFd},
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread::JoinHandle,
+};
+
+use rustix::{
+    event::{PollFd, PollFlags, poll},
+    pipe::pipe,
+};
+
+use crate::{Window, WindowError, WindowEvent};
+
+//Window events as an async stream, usable from any executor: poll_next has the shape of
+//futures' Stream::poll_next and next_event() gives the usual
+//`while let Some(event) = events.next_event().await`.
+//
+//There's no reactor to register the connection fd with, so a small thread waits on it and wakes
+//the task when it becomes readable. Nothing is held across a Pending: reads are done without
+//blocking inside poll_next and unread events stay in the window, so dropping the stream (or the
+//future from next_event()) at any point loses nothing.
+pub struct EventStream<'a> {
+    window: &'a mut Window,
+    waiter: Waiter,
+}
+
+impl Window {
+    pub fn events(&mut self) -> Result<EventStream<'_>, WindowError> {
+        let waiter = Waiter::new(self)?;
+        Ok(EventStream {
+            window: self,
+            waiter,
+        })
+    }
+}
+
+impl EventStream<'_> {
+    //Ready(None) once the window stopped running (or the connection died) and everything before
+    //that was handed out.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<WindowEvent>> {
+        if let Some(event) = self.pop() {
+            return Poll::Ready(Some(event));
+        }
+        //A lost connection still hands out its ConnectionLost first.
+        if !self.window.is_running() || self.dispatch().is_err() {
+            return Poll::Ready(self.pop());
+        }
+        if let Some(event) = self.pop() {
+            return Poll::Ready(Some(event));
+        }
+
+        //Nothing yet. Registering after the read is fine: if data came in meanwhile, the fd is
+        //already readable and the waiter wakes us right away.
+        self.waiter.register(cx.waker().clone());
+        self.window.state.wait_started();
+        Poll::Pending
+    }
+
+    pub fn next_event(&mut self) -> impl Future<Output = Option<WindowEvent>> + '_ {
+        std::future::poll_fn(move |cx| self.poll_next(cx))
+    }
+
+    //The window, e.g. to react to an event. Requests made through it are flushed on the next
+    //poll_next.
+    pub fn window(&mut self) -> &mut Window {
+        self.window
+    }
+
+    fn pop(&mut self) -> Option<WindowEvent> {
+        let events = &mut self.window.state.events;
+        (!events.is_empty()).then(|| events.remove(0))
+    }
+
+    //Non-blocking round: send our requests, read what arrived, dispatch it.
+    fn dispatch(&mut self) -> Result<(), WindowError> {
+        self.window.prepare_read()?;
+        //A full socket just means the rest goes out with the next flush.
+        self.window.flush()?;
+        self.window.read_and_dispatch()?;
+        self.window.deliver_user_events();
+        self.window.apply_configure();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+//The thread waiting on the connection fd. It only polls while a task waits (a waker is
+//registered), so it doesn't spin on a socket that stays readable until we read it.
+struct Waiter {
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+    //Written to on drop to get the thread out of poll.
+    cancel: OwnedFd,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Waiter {
+    fn new(window: &Window) -> Result<Waiter, WindowError> {
+        let io_error = |err: std::io::Error| WindowError::Connection(err.to_string());
+        let connection_fd = window
+            .connection_fd()
+            .try_clone_to_owned()
+            .map_err(io_error)?;
+        let (cancel_read, cancel) = pipe().map_err(|err| io_error(err.into()))?;
+        //Proxies can't be created while the stream borrows the window, so this is the only one.
+        let user_event_fd = match window.user_event_fd() {
+            Some(fd) => Some(fd.try_clone_to_owned().map_err(io_error)?),
+            None => None,
+        };
+
+        let shared = Arc::new((Mutex::new(Shared::default()), Condvar::new()));
+        let thread_shared = shared.clone();
+        let thread = std::thread::spawn(move || {
+            let (lock, condvar) = &*thread_shared;
+            loop {
+                {
+                    let mut shared = lock.lock().unwrap();
+                    while shared.waker.is_none() && !shared.closed {
+                        shared = condvar.wait(shared).unwrap();
+                    }
+                    if shared.closed {
+                        return;
+                    }
+                }
+
+                let mut fds = vec![
+                    PollFd::new(&cancel_read, PollFlags::IN),
+                    PollFd::new(&connection_fd, PollFlags::IN),
+                ];
+                if let Some(ref fd) = user_event_fd {
+                    fds.push(PollFd::new(fd, PollFlags::IN));
+                }
+                if poll(&mut fds, None).is_err() {
+                    continue;
+                }
+                if !fds[0].revents().is_empty() {
+                    return;
+                }
+
+                //The latest waker, the task may have moved since it registered.
+                if let Some(waker) = lock.lock().unwrap().waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+
+        Ok(Waiter {
+            shared,
+            cancel,
+            thread: Some(thread),
+        })
+    }
+
+    fn register(&self, waker: Waker) {
+        let (lock, condvar) = &*self.shared;
+        lock.lock().unwrap().waker = Some(waker);
+        condvar.notify_one();
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.shared;
+        lock.lock().unwrap().closed = true;
+        condvar.notify_one();
+        let _ = rustix::io::write(self.cancel.as_fd(), &[0]);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//Explicit synchronization for dmabuf buffers, through wp_linux_drm_syncobj_manager_v1.
+//
+//A GPU renderer queues its drawing and returns long before the pixels are there. Without this
+//protocol, the kernel orders things by itself (implicit sync): the fences of the rendering are
+//attached to the dmabuf, and the compositor's reads wait on them. That's what present_buffer
+//relies on, and it's enough as long as the driver does implicit sync. The renderer has to have
+//submitted its work (glFlush, vkQueueSubmit) before present_buffer, and the buffer is free again
+//on wl_buffer.release.
+//
+//Drivers that don't (NVIDIA's, Vulkan without the implicit sync extensions) need the fences
+//spelled out: a DRM syncobj timeline the renderer signals when it's done (the acquire point),
+//and one the compositor signals when it's done reading (the release point). Both are syncobj fds
+//the renderer exports (drmSyncobjHandleToFD, vkGetSemaphoreFdKHR on a timeline semaphore), this
+//only imports them and sets the points around the commit.
+//
+//Quoting documentation: "As long as the wp_linux_drm_syncobj_surface_v1 object is alive, the
+//compositor may ignore implicit synchronization for buffers attached and committed to the
+//wl_surface. The delivery of wl_buffer.release events for buffers attached to the surface
+//becomes undefined."
+//And every attach needs both points, which shm and single pixel buffers may not support. So the
+//syncobj surface only exists while synced buffers are presented: the first present_synced
+//creates it, any other attach (the gradient, present_buffer, a solid color) destroys it first.
+//Shm windows never see any of it.
+
+use std::os::fd::BorrowedFd;
+
+use wayland_client::{delegate_noop, protocol::wl_buffer::WlBuffer};
+use wayland_protocols::wp::linux_drm_syncobj::v1::client::{
+    wp_linux_drm_syncobj_manager_v1::WpLinuxDrmSyncobjManagerV1,
+    wp_linux_drm_syncobj_surface_v1::WpLinuxDrmSyncobjSurfaceV1,
+    wp_linux_drm_syncobj_timeline_v1::WpLinuxDrmSyncobjTimelineV1,
+};
+
+#[cfg(feature = "egl")]
+use crate::RenderMode;
+use crate::{AppState, Window, WindowError, lifecycle::SurfaceRequest, transaction::StagedCommit};
+
+#[derive(Default)]
+pub(crate) struct ExplicitSyncState {
+    pub(crate) manager: Option<WpLinuxDrmSyncobjManagerV1>,
+    //While synced buffers are what's attached.
+    surface: Option<WpLinuxDrmSyncobjSurfaceV1>,
+}
+
+//An imported syncobj timeline. Destroyed when dropped: points already set stay set.
+//Only good on the connection it was imported on, import again after Window::reconnect.
+#[derive(Debug)]
+pub struct SyncTimeline {
+    timeline: WpLinuxDrmSyncobjTimelineV1,
+}
+
+impl PartialEq for SyncTimeline {
+    fn eq(&self, other: &Self) -> bool {
+        self.timeline == other.timeline
+    }
+}
+
+impl Drop for SyncTimeline {
+    fn drop(&mut self) {
+        self.timeline.destroy();
+    }
+}
+
+//A dmabuf buffer with a release timeline of its own. Quoting documentation: "it is strongly
+//recommended that each buffer should use a separate timeline for its release points": the
+//compositor may release buffers out of order, and signaling a point signals all before it.
+#[derive(Debug)]
+pub struct SyncedBuffer {
+    buffer: WlBuffer,
+    release: SyncTimeline,
+    //The last point handed to the compositor, 0 before the first present.
+    release_point: u64,
+}
+
+impl SyncedBuffer {
+    pub fn buffer(&self) -> &WlBuffer {
+        &self.buffer
+    }
+
+    pub fn release_timeline(&self) -> &SyncTimeline {
+        &self.release
+    }
+
+    //The point to wait on (on the renderer's own copy of the release syncobj) before drawing
+    //into the buffer again. 0, already signaled, when it was never presented.
+    pub fn release_point(&self) -> u64 {
+        self.release_point
+    }
+}
+
+//The protocol takes 64 bit points as two u32.
+fn split(point: u64) -> (u32, u32) {
+    ((point >> 32) as u32, point as u32)
+}
+
+//Quoting documentation: "If at surface commit time the acquire and release DRM syncobj timelines
+//are identical, the acquire point value must be strictly less than the release point value".
+//A protocol error otherwise, so it's checked before.
+fn points_conflict(same_timeline: bool, acquire: u64, release: u64) -> bool {
+    same_timeline && acquire >= release
+}
+
+impl AppState {
+    //Before attaching anything that isn't a synced buffer.
+    pub(crate) fn end_explicit_sync(&mut self) {
+        if let Some(surface) = self.explicit_sync.surface.take() {
+            surface.destroy();
+        }
+    }
+}
+
+impl Window {
+    pub fn explicit_sync_supported(&self) -> bool {
+        self.state.explicit_sync.manager.is_some()
+    }
+
+    //Imports a DRM syncobj, as a timeline for acquire or release points. Quoting documentation:
+    //"If the FD cannot be imported, the invalid_timeline error is raised.", which ends the
+    //connection: only hand it real syncobj fds.
+    pub fn import_sync_timeline(&mut self, fd: BorrowedFd) -> Result<SyncTimeline, WindowError> {
+        let Some(ref manager) = self.state.explicit_sync.manager else {
+            return Err(WindowError::Unsupported("wp_linux_drm_syncobj_manager_v1"));
+        };
+        let queue_handle = self.event_queue.handle();
+        Ok(SyncTimeline {
+            timeline: manager.import_timeline(fd, &queue_handle, ()),
+        })
+    }
+
+    //Pairs a dmabuf buffer (from WindowEvent::DmabufBufferCreated) with the syncobj its release
+    //points go on.
+    pub fn synced_buffer(
+        &mut self,
+        buffer: WlBuffer,
+        release_fd: BorrowedFd,
+    ) -> Result<SyncedBuffer, WindowError> {
+        Ok(SyncedBuffer {
+            buffer,
+            release: self.import_sync_timeline(release_fd)?,
+            release_point: 0,
+        })
+    }
+
+    //present_buffer, with the compositor waiting for `acquire_point` on `acquire` before reading
+    //the buffer. Returns the release point the buffer got, also in SyncedBuffer::release_point:
+    //the buffer is free again once it's signaled, wl_buffer.release means nothing here.
+    //
+    //Not for surfaces EGL presents on, Mesa uses the protocol itself there and a second syncobj
+    //surface is a protocol error. The same goes for an external Vulkan swapchain.
+    pub fn present_synced(
+        &mut self,
+        buffer: &mut SyncedBuffer,
+        width: i32,
+        height: i32,
+        acquire: &SyncTimeline,
+        acquire_point: u64,
+    ) -> Result<u64, WindowError> {
+        #[cfg(feature = "egl")]
+        if self.state.render_mode == RenderMode::Egl {
+            return Err(WindowError::InvalidArgument(
+                "the surface is presented by EGL",
+            ));
+        }
+        let Some(ref manager) = self.state.explicit_sync.manager else {
+            return Err(WindowError::Unsupported("wp_linux_drm_syncobj_manager_v1"));
+        };
+        let release_point = buffer.release_point + 1;
+        if points_conflict(*acquire == buffer.release, acquire_point, release_point) {
+            return Err(WindowError::InvalidArgument(
+                "acquire point not before the release point on the same timeline",
+            ));
+        }
+        self.state.lifecycle.transition(SurfaceRequest::Attach)?;
+
+        let queue_handle = self.event_queue.handle();
+        let surface = self.state.base_surface.as_ref().unwrap();
+        let sync_surface = self
+            .state
+            .explicit_sync
+            .surface
+            .get_or_insert_with(|| manager.get_surface(surface, &queue_handle, ()));
+        let (hi, lo) = split(acquire_point);
+        sync_surface.set_acquire_point(&acquire.timeline, hi, lo);
+        let (hi, lo) = split(release_point);
+        sync_surface.set_release_point(&buffer.release.timeline, hi, lo);
+
+        let staged = StagedCommit {
+            buffer: Some((buffer.buffer.clone(), width as u32, height as u32)),
+            explicitly_synced: true,
+            ..StagedCommit::default()
+        };
+        self.state
+            .commit_staged(staged, &queue_handle, "synced buffer");
+
+        buffer.release_point = release_point;
+        Ok(release_point)
+    }
+}
+
+//None of the three has events.
+delegate_noop!(AppState: ignore WpLinuxDrmSyncobjManagerV1);
+delegate_noop!(AppState: ignore WpLinuxDrmSyncobjSurfaceV1);
+delegate_noop!(AppState: ignore WpLinuxDrmSyncobjTimelineV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_split_in_halves() {
+        assert_eq!(split(0x1_0000_0002), (1, 2));
+        assert_eq!(split(u64::MAX), (u32::MAX, u32::MAX));
+    }
+
+    #[test]
+    fn acquire_comes_before_release_on_one_timeline() {
+        assert!(!points_conflict(true, 4, 5));
+        assert!(points_conflict(true, 5, 5));
+        assert!(!points_conflict(false, 9, 5));
+    }
+}
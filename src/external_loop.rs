@@ -0,0 +1,90 @@
+use std::{
+    io::ErrorKind,
+    os::fd::{AsFd, BorrowedFd},
+};
+
+use wayland_client::backend::WaylandError;
+
+use crate::{Window, WindowError, WindowEvent};
+
+//For applications that already have an event loop (mio, tokio, their own epoll): register
+//connection_fd() for readability there and drive the window with these, in this order:
+//
+//  window.prepare_read()?;        //before going to sleep
+//  window.flush()?;               //our requests have to reach the compositor too
+//  ...the loop waits until connection_fd() is readable...
+//  window.read_and_dispatch()?;
+//  for event in window.take_events() { ... }
+//
+//libwayland lets several readers share the socket, which is why reading is two steps: the
+//prepared read says "I'm about to read", and only then is it safe to sleep on the fd. Another
+//reader may read our events for us in between (they then sit in our queue and the fd doesn't
+//wake up for them), so prepare_read first dispatches whatever is already queued.
+//
+//When the compositor is slow to read, flush leaves the rest for later and flush_blocked says so:
+//register connection_fd() for writability too until then, and flush again once it is (see
+//flush.rs).
+impl Window {
+    pub fn connection_fd(&self) -> BorrowedFd<'_> {
+        self.connection.as_fd()
+    }
+
+    //Dispatches what's already queued and announces a read. Calling it again before
+    //read_and_dispatch (e.g. the loop woke up for another fd) keeps the read prepared.
+    pub fn prepare_read(&mut self) -> Result<(), WindowError> {
+        self.state.watchdog.round_started();
+        while self.read_guard.is_none() {
+            self.dispatch_or_disconnect()?;
+            //None means events arrived in the queue meanwhile: dispatch those and try again.
+            self.read_guard = self.event_queue.prepare_read();
+        }
+        //The application's poll comes next.
+        self.state.wait_started();
+        Ok(())
+    }
+
+    //Reads what's in the socket (if prepare_read was called) and dispatches everything queued.
+    //Returns how many protocol events were dispatched, the resulting WindowEvents are in
+    //take_events. A wakeup with nothing to read just returns 0.
+    pub fn read_and_dispatch(&mut self) -> Result<usize, WindowError> {
+        self.state.watchdog.wait_ended();
+        if let Some(guard) = self.read_guard.take() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(err)) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => {
+                    self.connection_failed(err);
+                    return Err(self.lost_connection_error());
+                }
+            }
+        }
+        self.dispatch_or_disconnect()
+    }
+
+    //Failures here are the connection going away, see WindowEvent::ConnectionLost.
+    fn dispatch_or_disconnect(&mut self) -> Result<usize, WindowError> {
+        let dispatched = match self.event_queue.dispatch_pending(&mut self.state) {
+            Ok(dispatched) => Ok(dispatched),
+            Err(err) => {
+                self.connection_failed(err);
+                Err(self.lost_connection_error())
+            }
+        };
+        //Nothing says take_events comes next, see backpressure.rs.
+        self.state.bound_events();
+        dispatched
+    }
+
+    //libwayland's read waits until every prepared reader has read or cancelled, so a read
+    //left prepared here would hang the other ways of dispatching (pump_events...) forever.
+    pub(crate) fn cancel_read(&mut self) {
+        self.read_guard = None;
+    }
+
+    //WindowEvents produced since the last call (or the last pump_events/poll_events).
+    pub fn take_events(&mut self) -> Vec<WindowEvent> {
+        self.deliver_user_events();
+        self.apply_configure();
+        std::mem::take(&mut self.state.events)
+    }
+}
@@ -0,0 +1,132 @@
+//When our requests leave for the compositor. wayland-client keeps them in a buffer until
+//Connection::flush, which the dispatching functions do before they sleep. A window that sets its
+//title, draws and then computes for 200 ms would leave all of it, the frame included, waiting
+//in that buffer until it dispatches again. So by default every commit of the window's surface is
+//flushed right away, and the FlushPolicy says what else.
+//
+//The socket may be full (the compositor is slow to read): Connection::flush then says
+//WouldBlock, having sent what fit. Retrying on the spot would spin, so the rest waits for the
+//socket to be writable. The waits (pump_events, the EventLoop, the bounded waits for buffers and
+//tokens) poll for it next to the events and flush again then; external loops see
+//Window::flush_blocked.
+
+use std::{cell::Cell, io::ErrorKind};
+
+use rustix::event::PollFlags;
+use wayland_client::{Connection, backend::WaylandError};
+
+use crate::{AppState, Window, WindowError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    //When dispatching and on Window::flush only. For applications that commit several times in a
+    //row and want them in one write.
+    OnDispatch,
+    //After every commit of the window's surface, so frames never sit in the buffer.
+    #[default]
+    OnCommit,
+    //Same, and after every `n` requests of those the window keeps track of (see diagnostics.rs:
+    //on the surface, its roles and the shm buffers). Requests sent without the window knowing
+    //(on the application's own protocol objects, by EGL) don't count towards `n`, they go with
+    //the next flush. 0 is taken as 1.
+    EveryRequests(u32),
+}
+
+pub(crate) struct FlushState {
+    //The window's, for flushing from AppState where the commits are.
+    connection: Connection,
+    pub(crate) policy: FlushPolicy,
+    //Requests since the last flush, for EveryRequests. Cells, so Window::flush can take &self.
+    unflushed: Cell<u32>,
+    //The socket was full at the last flush: the rest goes once it's writable.
+    pub(crate) blocked: Cell<bool>,
+}
+
+impl FlushState {
+    pub(crate) fn new(connection: Connection) -> FlushState {
+        FlushState {
+            connection,
+            policy: FlushPolicy::default(),
+            unflushed: Cell::new(0),
+            blocked: Cell::new(false),
+        }
+    }
+
+    //Sends what's buffered. Errors other than a full socket are the connection going away,
+    //which the next dispatch finds out about.
+    fn flush(&self) -> Result<(), WaylandError> {
+        self.unflushed.set(0);
+        match self.connection.flush() {
+            Err(WaylandError::Io(err)) if err.kind() == ErrorKind::WouldBlock => {
+                self.blocked.set(true);
+                Ok(())
+            }
+            result => {
+                self.blocked.set(false);
+                result
+            }
+        }
+    }
+}
+
+impl AppState {
+    //After every commit of the window's surface.
+    pub(crate) fn committed(&mut self) {
+        if self.flush.policy != FlushPolicy::OnDispatch {
+            let _ = self.flush.flush();
+        }
+    }
+
+    //From note_request.
+    pub(crate) fn request_sent(&mut self) {
+        let FlushPolicy::EveryRequests(n) = self.flush.policy else {
+            return;
+        };
+        let unflushed = self.flush.unflushed.get() + 1;
+        self.flush.unflushed.set(unflushed);
+        if unflushed >= n.max(1) {
+            let _ = self.flush.flush();
+        }
+    }
+}
+
+impl Window {
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.state.flush.policy = policy;
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.state.flush.policy
+    }
+
+    //Sends our buffered requests now. A full socket isn't an error: what didn't fit goes once
+    //it's writable, see flush_blocked.
+    pub fn flush(&self) -> Result<(), WindowError> {
+        self.state
+            .flush
+            .flush()
+            .map_err(|err| WindowError::Connection(err.to_string()))
+    }
+
+    //The last flush found the socket full. Dispatching takes care of the rest; an external loop
+    //(see external_loop.rs) polls connection_fd for writability too, and flushes when it is.
+    pub fn flush_blocked(&self) -> bool {
+        self.state.flush.blocked.get()
+    }
+
+    //For the dispatching functions, before they sleep.
+    pub(crate) fn send_requests(&mut self) {
+        if let Err(err) = self.state.flush.flush() {
+            self.connection_failed(err);
+        }
+    }
+
+    //What the waits poll the connection for: events, and room to write while blocked.
+    pub(crate) fn connection_interest(&self) -> PollFlags {
+        if self.state.flush.blocked.get() {
+            PollFlags::IN | PollFlags::OUT
+        } else {
+            PollFlags::IN
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use wayland_client::{Connection, Dispatch, QueueHandle, delegate_noop};
+use wayland_protocols::xdg::foreign::zv2::client::{
+    zxdg_exported_v2::{self, ZxdgExportedV2},
+    zxdg_exporter_v2::ZxdgExporterV2,
+    zxdg_imported_v2::{self, ZxdgImportedV2},
+    zxdg_importer_v2::ZxdgImporterV2,
+};
+
+use crate::{AppState, Window, WindowError};
+
+//xdg-foreign: our toplevel as a string handle another client can parent its window to (a portal
+//file chooser, say), and the other way around. Same idea as create_child_dialog, across processes.
+#[derive(Default)]
+pub(crate) struct ForeignState {
+    pub(crate) exporter: Option<ZxdgExporterV2>,
+    pub(crate) importer: Option<ZxdgImporterV2>,
+    //The handle stays valid as long as the exported object lives.
+    exported: Option<ZxdgExportedV2>,
+    handle: Option<String>,
+    //Another client's toplevel we are parented to.
+    imported: Option<ZxdgImportedV2>,
+}
+
+impl AppState {
+    //Part of dropping the window: handles to a surface that's going away shouldn't be handed out
+    //anymore, and the parent relationship ends with it.
+    pub(crate) fn revoke_foreign(&mut self) {
+        if let Some(exported) = self.foreign.exported.take() {
+            exported.destroy();
+        }
+        self.foreign.handle = None;
+        if let Some(imported) = self.foreign.imported.take() {
+            imported.destroy();
+        }
+    }
+}
+
+impl Window {
+    //A handle other clients can use to make their windows dialogs of ours, to be passed over IPC.
+    //Exported once, later calls give the same handle. Waits for the compositor's answer.
+    pub fn export_handle(&mut self) -> Result<String, WindowError> {
+        if let Some(ref handle) = self.state.foreign.handle {
+            return Ok(handle.clone());
+        }
+        let Some(ref exporter) = self.state.foreign.exporter else {
+            return Err(WindowError::Unsupported("zxdg_exporter_v2"));
+        };
+        //Quoting documentation: "Only xdg_toplevel equivalent surfaces may be exported, otherwise
+        //an invalid_surface protocol error is sent."
+        let (Some(surface), Some(_)) = (&self.state.base_surface, &self.state.xdg_surface) else {
+            return Err(WindowError::NotConfigured);
+        };
+
+        let queue_handle = self.event_queue.handle();
+        if self.state.foreign.exported.is_none() {
+            self.state.foreign.exported =
+                Some(exporter.export_toplevel(surface, &queue_handle, ()));
+        }
+
+        //The Handle event is sent right away, one roundtrip is enough.
+        self.cancel_read();
+        self.event_queue
+            .roundtrip(&mut self.state)
+            .map_err(|err| WindowError::Connection(err.to_string()))?;
+        self.state
+            .foreign
+            .handle
+            .clone()
+            .ok_or(WindowError::Connection(
+                "no handle for the exported toplevel".into(),
+            ))
+    }
+
+    //Parents our toplevel to another client's, given the handle it exported. Replaces any parent
+    //set this way before.
+    pub fn set_parent_from_handle(&mut self, handle: &str) -> Result<(), WindowError> {
+        let Some(ref importer) = self.state.foreign.importer else {
+            return Err(WindowError::Unsupported("zxdg_importer_v2"));
+        };
+        let (Some(surface), Some(_)) = (&self.state.base_surface, &self.state.xdg_surface) else {
+            return Err(WindowError::NotConfigured);
+        };
+
+        let queue_handle = self.event_queue.handle();
+        let imported = importer.import_toplevel(handle.to_owned(), &queue_handle, ());
+        imported.set_parent_of(surface);
+        if let Some(old) = self.state.foreign.imported.replace(imported) {
+            old.destroy();
+        }
+        let _ = self.flush();
+        Ok(())
+    }
+}
+
+impl Dispatch<ZxdgExportedV2, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ZxdgExportedV2,
+        event: zxdg_exported_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zxdg_exported_v2::Event::Handle { handle } = event {
+            state.foreign.handle = Some(handle);
+        }
+    }
+}
+
+impl Dispatch<ZxdgImportedV2, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        imported: &ZxdgImportedV2,
+        event: zxdg_imported_v2::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        //Quoting documentation: "The imported surface handle has been destroyed and any
+        //relationship set up has been invalidated. This may happen for various reasons, for
+        //example if the exported surface or the exported surface handle has been destroyed, if
+        //the handle used for importing was invalid."
+        if let zxdg_imported_v2::Event::Destroyed = event {
+            log::warn!("the foreign parent went away (or its handle was invalid)");
+            imported.destroy();
+            if state.foreign.imported.as_ref() == Some(imported) {
+                state.foreign.imported = None;
+            }
+        }
+    }
+}
+
+//The exporter and importer have no events.
+delegate_noop!(AppState: ignore ZxdgExporterV2);
+delegate_noop!(AppState: ignore ZxdgImporterV2);
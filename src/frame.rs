@@ -0,0 +1,51 @@
+use wayland_client::{Connection, Dispatch, QueueHandle, protocol::wl_callback};
+
+use crate::{AppState, Window, WindowEvent};
+
+//How many xdg_surface configures came in and how many of them were acked and drawn. Only the
+//newest of each dispatch batch is, so during an interactive resize `applied` should stay well
+//below `received`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConfigureStats {
+    pub received: u64,
+    pub applied: u64,
+}
+
+impl Window {
+    pub fn configure_stats(&self) -> ConfigureStats {
+        self.state.configure_stats
+    }
+
+    //Asks the compositor to say when it's a good time to draw the next frame, delivered as
+    //WindowEvent::Frame. Like most surface state it only counts from the next commit on (the next
+    //redraw), and the compositor may never answer for a hidden window.
+    pub fn request_frame(&mut self) {
+        let queue_handle = self.event_queue.handle();
+        if let Some(ref surface) = self.state.base_surface {
+            surface.frame(&queue_handle, ());
+            self.state.frame_skip.frame_requested = true;
+            self.state.motion.frames_pending += 1;
+        }
+    }
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        //Quoting documentation: "the callback_data passed in the callback is the current time, in
+        //milliseconds, with an undefined base."
+        if let wl_callback::Event::Done { callback_data } = event {
+            state.log_frame_done(callback_data);
+            state.motion.frames_pending = state.motion.frames_pending.saturating_sub(1);
+            state.events.push(WindowEvent::Frame {
+                time: callback_data,
+            });
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//Skipping frames that draw the same thing again. An application redrawing on a timer (a clock
+//that only changes once a second, an animation that stopped) would otherwise attach, damage and
+//commit a buffer the compositor then composites for nothing.
+//
+//With Window::set_skip_identical_frames, Window::draw hashes the buffer rows after the draw
+//closure, and when they hash like the frame it last committed, and that frame is still what's
+//attached, nothing is attached or damaged. Every commit damages the whole buffer (see
+//damage_buffer), so the damaged rows are all of them.
+//
+//Frame callbacks only come after a commit: an application that asked for one and skipped its
+//frame would wait forever. So a skipped frame still commits, without buffer or damage, when a
+//frame callback is pending. Quoting documentation: "The frame request will take effect on the
+//next wl_surface.commit."
+//
+//The hash is XXH64, written out here. Rows are hashed one at a time, each seeded with the
+//previous row's hash, the stride padding left out. Anything that presents the buffer some other
+//way (resize, redraw_background, another buffer attached) forgets the last hash, and so do a new
+//preferred scale and a Resumed: the next draw always commits.
+
+use wayland_client::QueueHandle;
+
+use crate::{AppState, Window, protocol_log::protocol_log, transaction::StagedCommit};
+
+const PRIME_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+fn round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME_1)
+}
+
+fn merge_round(acc: u64, value: u64) -> u64 {
+    (acc ^ round(0, value))
+        .wrapping_mul(PRIME_1)
+        .wrapping_add(PRIME_4)
+}
+
+//XXH64 of `input`, the same as the reference implementation's.
+fn xxh64(input: &[u8], seed: u64) -> u64 {
+    let mut stripes = input.chunks_exact(32);
+    let mut hash = if input.len() >= 32 {
+        let mut acc = [
+            seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+            seed.wrapping_add(PRIME_2),
+            seed,
+            seed.wrapping_sub(PRIME_1),
+        ];
+        for stripe in &mut stripes {
+            for (lane, acc) in acc.iter_mut().enumerate() {
+                *acc = round(*acc, read_u64(&stripe[lane * 8..]));
+            }
+        }
+        let hash = acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18));
+        acc.into_iter().fold(hash, merge_round)
+    } else {
+        seed.wrapping_add(PRIME_5)
+    };
+    hash = hash.wrapping_add(input.len() as u64);
+
+    let mut rest = stripes.remainder();
+    while rest.len() >= 8 {
+        hash = (hash ^ round(0, read_u64(rest)))
+            .rotate_left(27)
+            .wrapping_mul(PRIME_1)
+            .wrapping_add(PRIME_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let word = u32::from_le_bytes(rest[..4].try_into().unwrap());
+        hash = (hash ^ u64::from(word).wrapping_mul(PRIME_1))
+            .rotate_left(23)
+            .wrapping_mul(PRIME_2)
+            .wrapping_add(PRIME_3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash = (hash ^ u64::from(byte).wrapping_mul(PRIME_5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME_3);
+    hash ^ (hash >> 32)
+}
+
+//`rows` of `row_len` bytes every `stride`, chained. The size seeds the first row: the same
+//bytes at another size are another frame.
+fn hash_rows(bytes: &[u8], row_len: usize, stride: usize, rows: u32) -> u64 {
+    let seed = (row_len as u64) << 32 | u64::from(rows);
+    bytes
+        .chunks(stride.max(1))
+        .take(rows as usize)
+        .fold(seed, |hash, row| {
+            xxh64(&row[..row_len.min(row.len())], hash)
+        })
+}
+
+#[derive(Default)]
+pub(crate) struct FrameSkipState {
+    enabled: bool,
+    //Of the frame Window::draw last committed, None when something else was presented since.
+    last: Option<u64>,
+    //Window::request_frame since the last commit.
+    pub(crate) frame_requested: bool,
+    pub(crate) skipped: u64,
+}
+
+impl FrameSkipState {
+    //The hash was of the old connection's buffer.
+    pub(crate) fn for_reconnect(&self) -> FrameSkipState {
+        FrameSkipState {
+            enabled: self.enabled,
+            ..FrameSkipState::default()
+        }
+    }
+
+    //The next draw commits, whatever it draws.
+    pub(crate) fn force_next(&mut self) {
+        self.last = None;
+    }
+}
+
+impl AppState {
+    fn drawn_frame_hash(&self) -> Option<u64> {
+        let (pixels, layout) = self.shm_pixels.as_ref()?;
+        Some(hash_rows(
+            pixels.bytes(),
+            layout.row_len(),
+            layout.stride as usize,
+            layout.height,
+        ))
+    }
+
+    //Presents what Window::draw painted, unless it's the frame on screen already.
+    pub(crate) fn present_drawn_frame(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let hash = self
+            .frame_skip
+            .enabled
+            .then(|| self.drawn_frame_hash())
+            .flatten();
+        if hash.is_some() && hash == self.frame_skip.last && self.main_buffer_shown() {
+            self.frame_skip.skipped += 1;
+            self.main_buffer_matches_shown();
+            if std::mem::take(&mut self.frame_skip.frame_requested) {
+                self.commit_staged(StagedCommit::default(), queue_handle, "state");
+            }
+            protocol_log!("identical frame skipped");
+            return;
+        }
+        //present_gradient forgets the hash, like every other present.
+        self.present_gradient(queue_handle);
+        if self.main_buffer_shown() {
+            self.frame_skip.last = hash;
+        }
+    }
+}
+
+impl Window {
+    //Off by default. See frame_skip.rs: Window::draw stops committing frames identical to the
+    //one on screen, counted in FrameStats::skipped.
+    pub fn set_skip_identical_frames(&mut self, skip: bool) {
+        self.state.frame_skip.enabled = skip;
+        self.state.frame_skip.force_next();
+    }
+
+    pub fn skip_identical_frames(&self) -> bool {
+        self.state.frame_skip.enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xxh64_matches_the_reference() {
+        assert_eq!(xxh64(b"", 0), 0xEF46_DB37_51D8_E999);
+        assert_eq!(xxh64(b"a", 0), 0xD24E_C4F1_A98C_6E5B);
+        assert_eq!(xxh64(b"abc", 0), 0x44BC_2CF5_AD77_0999);
+        //Past 32 bytes, with 4 and 1 byte tails.
+        assert_eq!(
+            xxh64(b"Nobody inspects the spammish repetition", 0),
+            0xFBCE_A83C_8A37_8BF1
+        );
+    }
+
+    #[test]
+    fn padding_is_not_hashed_but_the_size_is() {
+        let mut bytes = [1, 2, 3, 4, 0, 0, 5, 6, 7, 8, 0, 0];
+        let hash = hash_rows(&bytes, 4, 6, 2);
+        bytes[4] = 9;
+        assert_eq!(hash_rows(&bytes, 4, 6, 2), hash);
+        bytes[6] = 9;
+        assert_ne!(hash_rows(&bytes, 4, 6, 2), hash);
+        assert_ne!(hash_rows(&bytes, 4, 6, 1), hash_rows(&bytes, 4, 6, 2));
+    }
+}
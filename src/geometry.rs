@@ -0,0 +1,225 @@
+use wayland_protocols::xdg::shell::client::xdg_toplevel;
+
+use crate::{AppState, Rect, RenderMode, Window, WindowError};
+
+//Who draws the window frame. With client side decorations the buffer usually also holds a drop
+//shadow around the frame: it's part of the surface, but not of the window the user sees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decorations {
+    #[default]
+    ServerSide,
+    ClientSide {
+        shadow: Margins,
+    },
+}
+
+//Pixels on each side of the buffer that are shadow, not window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Margins {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+impl Margins {
+    pub fn uniform(margin: u32) -> Margins {
+        Margins {
+            top: margin,
+            right: margin,
+            bottom: margin,
+            left: margin,
+        }
+    }
+}
+
+//The window's edges that butt up against something, another window or the screen's edge, from
+//the compositor's tiled states (xdg_toplevel v2). Quoting documentation: "The window is currently
+//in a tiled layout and the left edge is considered to be adjacent to another part of the
+//tiling grid." No shadow on those edges (see shadow_margins) and no resize border, and who
+//draws the frame should draw square corners there, or there'd be gaps between the windows.
+//Maximized windows report whatever the compositor says, often nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TiledEdges {
+    pub top: bool,
+    pub right: bool,
+    pub bottom: bool,
+    pub left: bool,
+}
+
+impl TiledEdges {
+    pub const NONE: TiledEdges = TiledEdges {
+        top: false,
+        right: false,
+        bottom: false,
+        left: false,
+    };
+    pub const ALL: TiledEdges = TiledEdges {
+        top: true,
+        right: true,
+        bottom: true,
+        left: true,
+    };
+
+    pub fn any(self) -> bool {
+        self != TiledEdges::NONE
+    }
+
+    pub(crate) fn from_states(states: &[xdg_toplevel::State]) -> TiledEdges {
+        TiledEdges {
+            top: states.contains(&xdg_toplevel::State::TiledTop),
+            right: states.contains(&xdg_toplevel::State::TiledRight),
+            bottom: states.contains(&xdg_toplevel::State::TiledBottom),
+            left: states.contains(&xdg_toplevel::State::TiledLeft),
+        }
+    }
+}
+
+pub(crate) struct GeometryState {
+    pub(crate) decorations: Decorations,
+    //What the compositor got last, so an unchanged geometry isn't sent again on every configure.
+    sent: Option<Rect>,
+}
+
+impl GeometryState {
+    pub(crate) fn new(decorations: Decorations) -> GeometryState {
+        GeometryState {
+            decorations,
+            sent: None,
+        }
+    }
+}
+
+impl AppState {
+    //The shadow that is actually drawn. Fullscreen windows have nothing around them, so their
+    //geometry is the whole buffer even with client side decorations, and tiled edges have none
+    //either. Back to floating, the next configure brings the margins back.
+    pub(crate) fn shadow_margins(&self) -> Margins {
+        match self.geometry.decorations {
+            Decorations::ClientSide { shadow }
+                if !self
+                    .configure_states
+                    .contains(&xdg_toplevel::State::Fullscreen) =>
+            {
+                let tiled = self.tiled_edges();
+                let unless = |tiled: bool, margin: u32| if tiled { 0 } else { margin };
+                Margins {
+                    top: unless(tiled.top, shadow.top),
+                    right: unless(tiled.right, shadow.right),
+                    bottom: unless(tiled.bottom, shadow.bottom),
+                    left: unless(tiled.left, shadow.left),
+                }
+            }
+            _ => Margins::default(),
+        }
+    }
+
+    pub(crate) fn tiled_edges(&self) -> TiledEdges {
+        TiledEdges::from_states(&self.configure_states)
+    }
+
+    //The window's size as the compositor knows it: the geometry it was sent last. Not the one
+    //the configure being handled would give the current buffer, its margins may differ.
+    pub(crate) fn sent_window_size(&self) -> (u32, u32) {
+        let geometry = self.geometry.sent.unwrap_or_else(|| self.window_geometry());
+        (geometry.width as u32, geometry.height as u32)
+    }
+
+    //Quoting documentation: "The window geometry of a surface is its "visible bounds" from the
+    //user's perspective. Client-side decorations often have invisible portions like drop-shadows
+    //which should be ignored for the purposes of aligning, placing and constraining windows."
+    //
+    //Never empty: margins that eat the whole buffer leave a 1x1 window.
+    pub(crate) fn window_geometry(&self) -> Rect {
+        let margins = self.shadow_margins();
+        let (width, height) = self.surface_size();
+        Rect::new(
+            margins.left.min(width.saturating_sub(1)) as i32,
+            margins.top.min(height.saturating_sub(1)) as i32,
+            width.saturating_sub(margins.left + margins.right).max(1) as i32,
+            height.saturating_sub(margins.top + margins.bottom).max(1) as i32,
+        )
+    }
+
+    //Double-buffered, like everything on the surface. Called before the commit that acks a
+    //configure, so the geometry always matches the buffer it comes with.
+    pub(crate) fn apply_window_geometry(&mut self) {
+        let Some((ref xdg_surface, _)) = self.xdg_surface else {
+            return;
+        };
+
+        let geometry = self.window_geometry();
+        if self.geometry.sent != Some(geometry) {
+            xdg_surface.set_window_geometry(
+                geometry.x,
+                geometry.y,
+                geometry.width,
+                geometry.height,
+            );
+            self.geometry.sent = Some(geometry);
+        }
+    }
+}
+
+impl Window {
+    //Size of the window itself, without the shadow. This is what Resized reports and what the
+    //compositor's configures talk about.
+    pub fn size(&self) -> (u32, u32) {
+        let geometry = self.state.window_geometry();
+        (geometry.width as u32, geometry.height as u32)
+    }
+
+    //Size of the buffers, shadow included: what a renderer allocates and draws.
+    pub fn buffer_size(&self) -> (u32, u32) {
+        self.state.buffer_size
+    }
+
+    //Size of the surface: the buffer's, divided by the buffer scale and turned by the buffer
+    //transform. Pointer positions and set_viewport_source are in it.
+    pub fn surface_size(&self) -> (u32, u32) {
+        self.state.surface_size()
+    }
+
+    pub fn decorations(&self) -> Decorations {
+        self.state.geometry.decorations
+    }
+
+    //The edges the compositor tiled the window on, see TiledEdges.
+    pub fn tiled_edges(&self) -> TiledEdges {
+        self.state.tiled_edges()
+    }
+
+    //With the shm renderer the buffer keeps its size and the window shrinks by the shadow, until
+    //the next configure makes a buffer for the window and its shadow. When
+    //EGL or an external renderer owns the buffers, the window keeps its size and the buffer grows
+    //instead, reported with a Resized.
+    pub fn set_decorations(&mut self, decorations: Decorations) -> Result<(), WindowError> {
+        if let Decorations::ClientSide { shadow } = decorations {
+            let (width, height) = self.state.surface_size();
+            if self.state.render_mode == RenderMode::Shm
+                && (shadow.left + shadow.right >= width || shadow.top + shadow.bottom >= height)
+            {
+                return Err(WindowError::InvalidArgument(
+                    "shadow margins leave no room for the window",
+                ));
+            }
+        }
+
+        let size = self.size();
+        self.state.geometry.decorations = decorations;
+        if self.state.render_mode != RenderMode::Shm {
+            self.state.resize_client_rendered(size, false);
+        }
+
+        //Client rendered buffers commit with their next frame, which picks the geometry up.
+        if self.state.configured() {
+            self.state.apply_window_geometry();
+            let queue_handle = self.event_queue.handle();
+            self.state.apply_input_region(&queue_handle);
+            if self.state.render_mode == RenderMode::Shm {
+                self.state.commit_state(&queue_handle);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, protocol::wl_seat::WlSeat};
+use wayland_protocols::wp::pointer_gestures::zv1::client::{
+    zwp_pointer_gesture_hold_v1::{self, ZwpPointerGestureHoldV1},
+    zwp_pointer_gesture_pinch_v1::{self, ZwpPointerGesturePinchV1},
+    zwp_pointer_gesture_swipe_v1::{self, ZwpPointerGestureSwipeV1},
+    zwp_pointer_gestures_v1::ZwpPointerGesturesV1,
+};
+
+use crate::{AppState, WindowEvent};
+
+//Touchpad gestures as the application sees them.
+//
+//Careful with pinches: the protocol's scale is already absolute (relative to the finger
+//positions at Begin, 1.0 meaning no change) while its rotation is a delta since the previous
+//update. To make both behave the same way, PinchUpdate carries the protocol scale untouched and
+//the rotation accumulated since Begin, so "zoom = zoom_at_begin * scale" and
+//"angle = angle_at_begin + rotation" are always right.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GestureEvent {
+    SwipeBegin {
+        fingers: u32,
+    },
+    SwipeUpdate {
+        dx: f64,
+        dy: f64,
+    },
+    SwipeEnd {
+        cancelled: bool,
+    },
+    PinchBegin {
+        fingers: u32,
+    },
+    PinchUpdate {
+        scale: f64,
+        rotation: f64,
+        dx: f64,
+        dy: f64,
+    },
+    PinchEnd {
+        cancelled: bool,
+    },
+    HoldBegin {
+        fingers: u32,
+    },
+    HoldEnd {
+        cancelled: bool,
+    },
+}
+
+#[derive(Default)]
+pub(crate) struct GestureState {
+    pub(crate) manager: Option<ZwpPointerGesturesV1>,
+    swipe: Option<ZwpPointerGestureSwipeV1>,
+    pinch: Option<ZwpPointerGesturePinchV1>,
+    hold: Option<ZwpPointerGestureHoldV1>,
+    pinch_rotation: f64,
+}
+
+impl GestureState {
+    pub(crate) fn forget(&mut self) {
+        if let Some(swipe) = self.swipe.take() {
+            swipe.destroy();
+        }
+        if let Some(pinch) = self.pinch.take() {
+            pinch.destroy();
+        }
+        if let Some(hold) = self.hold.take() {
+            hold.destroy();
+        }
+    }
+}
+
+impl AppState {
+    //Gesture objects hang off one wl_pointer, same as the relative pointer, so whichever of the
+    //manager and the pointer shows up last creates them.
+    pub(crate) fn init_gestures(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if self.gestures.swipe.is_some() {
+            return;
+        }
+
+        let (Some(manager), Some(pointer)) = (
+            self.gestures.manager.as_ref(),
+            self.pointer.pointer.as_ref(),
+        ) else {
+            return;
+        };
+
+        //Their events are tagged with the pointer's seat.
+        let seat = pointer.data::<WlSeat>().unwrap().clone();
+        self.gestures.swipe = Some(manager.get_swipe_gesture(pointer, queue_handle, seat.clone()));
+        self.gestures.pinch = Some(manager.get_pinch_gesture(pointer, queue_handle, seat.clone()));
+
+        //Hold gestures (fingers resting on the touchpad, e.g. to stop kinetic scrolling) were
+        //only added in version 3.
+        if manager.version() >= 3 {
+            self.gestures.hold = Some(manager.get_hold_gesture(pointer, queue_handle, seat));
+        }
+    }
+}
+
+impl Dispatch<ZwpPointerGestureSwipeV1, WlSeat> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpPointerGestureSwipeV1,
+        event: zwp_pointer_gesture_swipe_v1::Event,
+        seat: &WlSeat,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let gesture = match event {
+            zwp_pointer_gesture_swipe_v1::Event::Begin { fingers, .. } => {
+                GestureEvent::SwipeBegin { fingers }
+            }
+            zwp_pointer_gesture_swipe_v1::Event::Update { dx, dy, .. } => {
+                GestureEvent::SwipeUpdate { dx, dy }
+            }
+            zwp_pointer_gesture_swipe_v1::Event::End { cancelled, .. } => GestureEvent::SwipeEnd {
+                cancelled: cancelled != 0,
+            },
+            _ => return,
+        };
+        state.events.push(WindowEvent::Gesture {
+            seat: state.seat_name(seat),
+            gesture,
+        });
+    }
+}
+
+impl Dispatch<ZwpPointerGesturePinchV1, WlSeat> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpPointerGesturePinchV1,
+        event: zwp_pointer_gesture_pinch_v1::Event,
+        seat: &WlSeat,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let gesture = match event {
+            zwp_pointer_gesture_pinch_v1::Event::Begin { fingers, .. } => {
+                state.gestures.pinch_rotation = 0.0;
+                GestureEvent::PinchBegin { fingers }
+            }
+            zwp_pointer_gesture_pinch_v1::Event::Update {
+                dx,
+                dy,
+                scale,
+                rotation,
+                ..
+            } => {
+                state.gestures.pinch_rotation += rotation;
+                GestureEvent::PinchUpdate {
+                    scale,
+                    rotation: state.gestures.pinch_rotation,
+                    dx,
+                    dy,
+                }
+            }
+            zwp_pointer_gesture_pinch_v1::Event::End { cancelled, .. } => GestureEvent::PinchEnd {
+                cancelled: cancelled != 0,
+            },
+            _ => return,
+        };
+        state.events.push(WindowEvent::Gesture {
+            seat: state.seat_name(seat),
+            gesture,
+        });
+    }
+}
+
+impl Dispatch<ZwpPointerGestureHoldV1, WlSeat> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpPointerGestureHoldV1,
+        event: zwp_pointer_gesture_hold_v1::Event,
+        seat: &WlSeat,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let gesture = match event {
+            zwp_pointer_gesture_hold_v1::Event::Begin { fingers, .. } => {
+                GestureEvent::HoldBegin { fingers }
+            }
+            zwp_pointer_gesture_hold_v1::Event::End { cancelled, .. } => GestureEvent::HoldEnd {
+                cancelled: cancelled != 0,
+            },
+            _ => return,
+        };
+        state.events.push(WindowEvent::Gesture {
+            seat: state.seat_name(seat),
+            gesture,
+        });
+    }
+}
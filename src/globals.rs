@@ -0,0 +1,146 @@
+//Which globals the compositor advertised, to say what's missing when a window can't work on it
+//instead of waiting forever for a configure that never comes (no xdg_wm_base, no toplevel, no
+//configure).
+
+use std::{thread, time::Duration};
+
+use crate::{AppState, RenderMode, Window, WindowError};
+
+//Everything else the registry handler binds, nice to have. Reported at debug level when missing.
+const OPTIONAL: &[&str] = &[
+    "wl_seat",
+    "wl_output",
+    "xdg_wm_dialog_v1",
+    "zxdg_exporter_v2",
+    "zxdg_importer_v2",
+    "zwp_pointer_constraints_v1",
+    "zwp_relative_pointer_manager_v1",
+    "wp_content_type_manager_v1",
+    "wp_alpha_modifier_v1",
+    "xdg_toplevel_icon_manager_v1",
+    "wp_color_manager_v1",
+    "ext_idle_notifier_v1",
+    "wp_cursor_shape_manager_v1",
+    "wp_presentation",
+    "wp_viewporter",
+    "wp_single_pixel_buffer_manager_v1",
+    "zwp_linux_dmabuf_v1",
+    "wp_linux_drm_syncobj_manager_v1",
+    "zwp_pointer_gestures_v1",
+];
+
+//Roundtrips after the first one, waiting twice as long before each: 10 ms up to 160 ms, a third
+//of a second all in all. Globals are normally all there by the first roundtrip's end, this is for
+//compositors still starting up.
+const RETRIES: u32 = 5;
+const FIRST_WAIT: Duration = Duration::from_millis(10);
+
+struct Advertised {
+    name: u32,
+    interface: String,
+    version: u32,
+}
+
+#[derive(Default)]
+pub(crate) struct GlobalsState {
+    advertised: Vec<Advertised>,
+}
+
+impl GlobalsState {
+    pub(crate) fn added(&mut self, name: u32, interface: &str, version: u32) {
+        self.advertised.push(Advertised {
+            name,
+            interface: interface.into(),
+            version,
+        });
+    }
+
+    pub(crate) fn removed(&mut self, name: u32) {
+        self.advertised.retain(|global| global.name != name);
+    }
+
+    fn has(&self, interface: &str) -> bool {
+        self.advertised
+            .iter()
+            .any(|global| global.interface == interface)
+    }
+}
+
+impl AppState {
+    //What the window can't do without. wl_shm only matters when the buffers are ours.
+    fn missing_globals(&self) -> Vec<&'static str> {
+        let mut required = vec!["wl_compositor", "xdg_wm_base"];
+        if self.render_mode == RenderMode::Shm {
+            required.push("wl_shm");
+        }
+        required.retain(|interface| !self.globals.has(interface));
+        required
+    }
+}
+
+//The likely reason for the first missing global, for WindowError::MissingGlobals.
+pub(crate) fn missing_hint(missing: &[&str], found: &[String]) -> &'static str {
+    match missing.first() {
+        Some(&"wl_compositor") => {
+            "is WAYLAND_DISPLAY pointing at a Wayland compositor and not some other socket?"
+        }
+        Some(&"xdg_wm_base") if found.iter().any(|interface| interface == "wl_shell") => {
+            "this compositor only has wl_shell, the shell xdg-shell replaced; is it a bare \
+             wl_shell-only environment?"
+        }
+        Some(&"xdg_wm_base") => {
+            "this compositor does not support xdg-shell toplevels; is it a kiosk or embedded \
+             compositor with a shell of its own?"
+        }
+        Some(&"wl_shm") => "without shared memory buffers, render with EGL or an external renderer",
+        _ => "",
+    }
+}
+
+impl Window {
+    //Interfaces the compositor advertises and their versions, in the order they came.
+    pub fn advertised_globals(&self) -> Vec<(String, u32)> {
+        self.state
+            .globals
+            .advertised
+            .iter()
+            .map(|global| (global.interface.clone(), global.version))
+            .collect()
+    }
+
+    //Roundtrips until the globals the window needs are there, waiting longer after each, and
+    //names what's missing if they never come. Used by Window::connect.
+    pub(crate) fn wait_for_globals(&mut self) -> Result<(), WindowError> {
+        let mut wait = FIRST_WAIT;
+        for attempt in 0..=RETRIES {
+            if attempt > 0 {
+                thread::sleep(wait);
+                wait *= 2;
+            }
+            self.event_queue
+                .roundtrip(&mut self.state)
+                .map_err(|err| WindowError::Connection(err.to_string()))?;
+            if self.state.missing_globals().is_empty() {
+                break;
+            }
+        }
+
+        let missing = self.state.missing_globals();
+        let found: Vec<String> = self
+            .advertised_globals()
+            .into_iter()
+            .map(|(interface, _)| interface)
+            .collect();
+        if !missing.is_empty() {
+            return Err(WindowError::MissingGlobals { missing, found });
+        }
+        let optional: Vec<_> = OPTIONAL
+            .iter()
+            .filter(|interface| !self.state.globals.has(interface))
+            .collect();
+        if !optional.is_empty() {
+            log::debug!("optional globals not advertised: {optional:?}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//Giving the shm buffer's memory back while the window is hidden. A 4K Argb8888 buffer is 32 MiB
+//of shared memory, kept for nothing while the window sits minimized for hours.
+//
+//With Window::set_release_buffers_when_hidden, the first configure with the Suspended state
+//destroys the wl_buffer and unmaps the file behind it (a wl_shm_pool can grow but not shrink, so
+//there's nothing to keep: the next buffer gets a new file and pool). The window stays mapped with
+//the placeholder color, stretched by the viewport. Without wp_viewporter there's no placeholder:
+//the compositor keeps what it had on screen, and the memory with it if it didn't copy it.
+//
+//Configures while hidden only follow the size, the placeholder goes on at each. The first one
+//without Suspended makes the buffer again (resize_shm, as for the first configure) and presents
+//the renderer's content. A picture from Window::draw is lost by then, the application draws it
+//again on the Resumed that comes with it. Draws while hidden do nothing, like before the first
+//configure.
+
+use crate::{AppState, RenderMode, Window};
+
+#[derive(Default)]
+pub(crate) struct HiddenBuffersState {
+    enabled: bool,
+    //The main buffer is gone until the window shows again.
+    pub(crate) released: bool,
+}
+
+impl HiddenBuffersState {
+    //The new connection starts with buffers of its own.
+    pub(crate) fn for_reconnect(&self) -> HiddenBuffersState {
+        HiddenBuffersState {
+            enabled: self.enabled,
+            released: false,
+        }
+    }
+}
+
+impl AppState {
+    //From apply_pending_configure, once the states of the configure are in and before the shm
+    //buffer follows its size.
+    pub(crate) fn update_hidden_buffers(&mut self) {
+        let hide = self.hidden_buffers.enabled && self.suspended;
+        if hide == self.hidden_buffers.released || self.render_mode != RenderMode::Shm {
+            return;
+        }
+        self.hidden_buffers.released = hide;
+        if !hide {
+            //resize_shm makes a buffer when there's none.
+            return;
+        }
+        //Destroyed while still attached, which is fine: quoting documentation: "Destroying the
+        //wl_buffer before wl_buffer.release is allowed as long as the underlying buffer storage
+        //isn't re-used". It's unmapped, not re-used, and the placeholder replaces it right after.
+        if let Some(buffer) = self.buffer.take() {
+            buffer.destroy();
+        }
+        self.drop_spare_buffers();
+        self.shm_pixels = None;
+        self.drawn_by_app = false;
+    }
+}
+
+impl Window {
+    //Off by default. See hidden_buffers.rs: while the compositor says the window is Suspended
+    //(xdg_toplevel v6), its buffer memory is released. Turning it off while hidden brings the
+    //buffer back right away.
+    pub fn set_release_buffers_when_hidden(&mut self, release: bool) {
+        self.state.hidden_buffers.enabled = release;
+        if !release && self.state.hidden_buffers.released {
+            self.state.hidden_buffers.released = false;
+            let queue_handle = self.event_queue.handle();
+            self.state.create_main_buffer(&queue_handle);
+            self.state.render_thread_resized();
+            if self.state.configured() {
+                self.state.present_gradient(&queue_handle);
+            }
+        }
+    }
+
+    pub fn release_buffers_when_hidden(&self) -> bool {
+        self.state.hidden_buffers.enabled
+    }
+}
@@ -0,0 +1,582 @@
+//What a pointer press means on a window with client side decorations: the title bar moves the
+//window once dragged (drag.rs) and maximizes on a double click, the border around it resizes, the
+//close button closes, and only the rest reaches the application. Server side decorated windows
+//never see their frame's events, everything there is Client.
+//
+//The same regions pick the cursor, through wp_cursor_shape_v1 when the compositor has it.
+//Without it the cursor stays whatever the compositor shows, resizing still works.
+
+use std::time::Instant;
+
+use wayland_client::{
+    QueueHandle, delegate_noop,
+    protocol::{wl_pointer::WlPointer, wl_seat::WlSeat},
+};
+use wayland_protocols::{
+    wp::cursor_shape::v1::client::{
+        wp_cursor_shape_device_v1::{Shape, WpCursorShapeDeviceV1},
+        wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+    },
+    xdg::shell::client::xdg_toplevel,
+};
+
+use crate::{
+    AppState, Decorations, Margins, TiledEdges, Window,
+    drag::{DragConfig, DragDetector, DragOutcome},
+    protocol_log::protocol_log,
+};
+
+//linux/input-event-codes.h
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+
+//Which side or corner of the window a border press drags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl ResizeEdge {
+    fn from_sides(top: bool, bottom: bool, left: bool, right: bool) -> Option<ResizeEdge> {
+        let edge = match (top, bottom, left, right) {
+            (true, _, true, _) => ResizeEdge::TopLeft,
+            (true, _, _, true) => ResizeEdge::TopRight,
+            (_, true, true, _) => ResizeEdge::BottomLeft,
+            (_, true, _, true) => ResizeEdge::BottomRight,
+            (true, ..) => ResizeEdge::Top,
+            (_, true, ..) => ResizeEdge::Bottom,
+            (_, _, true, _) => ResizeEdge::Left,
+            (_, _, _, true) => ResizeEdge::Right,
+            _ => return None,
+        };
+        Some(edge)
+    }
+
+    fn cursor(self) -> Shape {
+        match self {
+            ResizeEdge::Top => Shape::NResize,
+            ResizeEdge::Bottom => Shape::SResize,
+            ResizeEdge::Left => Shape::WResize,
+            ResizeEdge::Right => Shape::EResize,
+            ResizeEdge::TopLeft => Shape::NwResize,
+            ResizeEdge::TopRight => Shape::NeResize,
+            ResizeEdge::BottomLeft => Shape::SwResize,
+            ResizeEdge::BottomRight => Shape::SeResize,
+        }
+    }
+}
+
+impl From<ResizeEdge> for xdg_toplevel::ResizeEdge {
+    fn from(edge: ResizeEdge) -> xdg_toplevel::ResizeEdge {
+        match edge {
+            ResizeEdge::Top => xdg_toplevel::ResizeEdge::Top,
+            ResizeEdge::Bottom => xdg_toplevel::ResizeEdge::Bottom,
+            ResizeEdge::Left => xdg_toplevel::ResizeEdge::Left,
+            ResizeEdge::Right => xdg_toplevel::ResizeEdge::Right,
+            ResizeEdge::TopLeft => xdg_toplevel::ResizeEdge::TopLeft,
+            ResizeEdge::TopRight => xdg_toplevel::ResizeEdge::TopRight,
+            ResizeEdge::BottomLeft => xdg_toplevel::ResizeEdge::BottomLeft,
+            ResizeEdge::BottomRight => xdg_toplevel::ResizeEdge::BottomRight,
+        }
+    }
+}
+
+//What is under the pointer. Only Client gets pointer events, presses anywhere else are taken
+//by the window itself (motion still goes through, for hover effects on a drawn title bar).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitRegion {
+    Client,
+    TitleBar,
+    CloseButton,
+    Border(ResizeEdge),
+}
+
+//Where the decorations are, for windows that draw the usual frame: a title bar along the top of
+//the window with the close button at its right end, and a resize border all around. Sizes in
+//surface pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecorationLayout {
+    pub title_bar_height: u32,
+    pub close_button_width: u32,
+    //How wide the resize border is. It sits in the shadow as far as there is one, and reaches
+    //into the window for what's missing, so it is this wide with or without shadow.
+    pub border: u32,
+}
+
+impl Default for DecorationLayout {
+    fn default() -> DecorationLayout {
+        DecorationLayout {
+            title_bar_height: 32,
+            close_button_width: 32,
+            border: 8,
+        }
+    }
+}
+
+impl DecorationLayout {
+    //x and y relative to the window geometry, negative in the shadow above and left of it.
+    //There's no border on the `fixed` edges: tiled ones, all of them for maximized windows.
+    fn hit(
+        &self,
+        x: f64,
+        y: f64,
+        (width, height): (u32, u32),
+        shadow: Margins,
+        fixed: TiledEdges,
+    ) -> HitRegion {
+        let (width, height) = (width as f64, height as f64);
+        //How far the border goes into the shadow, and into the window.
+        let band = |shadow: u32, fixed: bool| {
+            let border = if fixed { 0 } else { self.border };
+            let outside = shadow.min(border);
+            (outside as f64, (border - outside) as f64)
+        };
+        let (top_out, top_in) = band(shadow.top, fixed.top);
+        let (bottom_out, bottom_in) = band(shadow.bottom, fixed.bottom);
+        let (left_out, left_in) = band(shadow.left, fixed.left);
+        let (right_out, right_in) = band(shadow.right, fixed.right);
+
+        if x >= -left_out && x < width + right_out && y >= -top_out && y < height + bottom_out {
+            let edge = ResizeEdge::from_sides(
+                y < top_in,
+                y >= height - bottom_in,
+                x < left_in,
+                x >= width - right_in,
+            );
+            if let Some(edge) = edge {
+                return HitRegion::Border(edge);
+            }
+        }
+        //The shadow beyond the border is the application's, set_input_region can make it
+        //click-through.
+        if x < 0.0 || y < 0.0 || x >= width || y >= height {
+            return HitRegion::Client;
+        }
+        if y < self.title_bar_height as f64 {
+            if x >= width - self.close_button_width as f64 {
+                return HitRegion::CloseButton;
+            }
+            return HitRegion::TitleBar;
+        }
+        HitRegion::Client
+    }
+}
+
+type HitTestCallback = Box<dyn Fn(f64, f64, (u32, u32)) -> HitRegion + Send>;
+
+enum HitTester {
+    Layout(DecorationLayout),
+    Custom(HitTestCallback),
+}
+
+impl Default for HitTester {
+    fn default() -> HitTester {
+        HitTester::Layout(DecorationLayout::default())
+    }
+}
+
+//One per pointer that has been over the window, seats have a pointer each.
+struct PointerHit {
+    pointer: WlPointer,
+    //Made the first time the pointer needs a cursor.
+    shape_device: Option<WpCursorShapeDeviceV1>,
+    //set_shape only takes the latest Enter's serial.
+    enter_serial: u32,
+    //What was set since that Enter.
+    shape: Option<Shape>,
+    //None while the pointer is away.
+    region: Option<HitRegion>,
+    position: (f64, f64),
+    //Buttons pressed outside of Client and the region they were pressed on, their releases are
+    //taken too.
+    taken: Vec<(u32, HitRegion)>,
+    //A left press on the title bar, moving once it's a drag, and the seat to move with.
+    drag: DragDetector,
+    drag_seat: Option<WlSeat>,
+}
+
+#[derive(Default)]
+pub(crate) struct HitTestState {
+    pub(crate) cursor_shape: Option<WpCursorShapeManagerV1>,
+    tester: HitTester,
+    pointers: Vec<PointerHit>,
+    pub(crate) drag_config: DragConfig,
+}
+
+impl HitTestState {
+    //The layout or callback, for Window::reconnect.
+    pub(crate) fn for_reconnect(&mut self) -> HitTestState {
+        HitTestState {
+            tester: std::mem::take(&mut self.tester),
+            drag_config: self.drag_config,
+            ..HitTestState::default()
+        }
+    }
+}
+
+impl AppState {
+    fn client_side_decorated(&self) -> bool {
+        matches!(self.geometry.decorations, Decorations::ClientSide { .. })
+            && !self.has_state(xdg_toplevel::State::Fullscreen)
+    }
+
+    //x and y surface-local, like pointer events.
+    pub(crate) fn hit_region(&self, x: f64, y: f64) -> HitRegion {
+        if !self.client_side_decorated() {
+            return HitRegion::Client;
+        }
+        let geometry = self.window_geometry();
+        let (x, y) = (x - geometry.x as f64, y - geometry.y as f64);
+        let size = (geometry.width as u32, geometry.height as u32);
+        match self.hit_test.tester {
+            HitTester::Layout(ref layout) => {
+                let fixed = if self.has_state(xdg_toplevel::State::Maximized) {
+                    TiledEdges::ALL
+                } else {
+                    self.tiled_edges()
+                };
+                layout.hit(x, y, size, self.shadow_margins(), fixed)
+            }
+            HitTester::Custom(ref hit_test) => hit_test(x, y, size),
+        }
+    }
+
+    //Enter (with its serial) and Motion: the region under the pointer, and its cursor if that
+    //changed.
+    pub(crate) fn pointer_hit_moved(
+        &mut self,
+        pointer: &WlPointer,
+        (x, y): (f64, f64),
+        enter_serial: Option<u32>,
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        let region = self.hit_region(x, y);
+        let decorated = self.client_side_decorated();
+        let pointers = &mut self.hit_test.pointers;
+        let index = match pointers.iter().position(|hit| &hit.pointer == pointer) {
+            Some(index) => index,
+            None => {
+                pointers.push(PointerHit {
+                    pointer: pointer.clone(),
+                    shape_device: None,
+                    enter_serial: 0,
+                    shape: None,
+                    region: None,
+                    position: (x, y),
+                    taken: Vec::new(),
+                    drag: DragDetector::default(),
+                    drag_seat: None,
+                });
+                pointers.len() - 1
+            }
+        };
+        let hit = &mut pointers[index];
+        if let Some(serial) = enter_serial {
+            hit.enter_serial = serial;
+            hit.shape = None;
+        }
+        hit.position = (x, y);
+        hit.region = Some(region);
+        if let Some(DragOutcome::DragStart(serial)) = hit.drag.motion(x, y)
+            && let Some(seat) = hit.drag_seat.take()
+            && let Some((_, ref toplevel)) = self.xdg_surface
+        {
+            protocol_log!("title bar dragged, moving (serial {serial})");
+            toplevel._move(&seat, serial);
+            //The compositor has the pointer now, see input_routing.rs and button_repeat.rs.
+            self.routing.grab_started(&mut self.events);
+            self.button_repeat.held = None;
+        }
+
+        //Server side decorated windows leave the cursor alone, as before.
+        let Some(ref manager) = self.hit_test.cursor_shape else {
+            return;
+        };
+        if !decorated {
+            return;
+        }
+        let shape = match region {
+            HitRegion::Client | HitRegion::TitleBar => Shape::Default,
+            HitRegion::CloseButton => Shape::Pointer,
+            HitRegion::Border(edge) => edge.cursor(),
+        };
+        if hit.shape == Some(shape) {
+            return;
+        }
+        hit.shape = Some(shape);
+        let device = hit
+            .shape_device
+            .get_or_insert_with(|| manager.get_pointer(pointer, queue_handle, ()));
+        device.set_shape(hit.enter_serial, shape);
+    }
+
+    //Where the pointer was last seen on the surface, None while it's away.
+    pub(crate) fn pointer_position(&self, pointer: &WlPointer) -> Option<(f64, f64)> {
+        self.hit_test
+            .pointers
+            .iter()
+            .find(|hit| &hit.pointer == pointer && hit.region.is_some())
+            .map(|hit| hit.position)
+    }
+
+    pub(crate) fn pointer_hit_left(&mut self, pointer: &WlPointer) {
+        if let Some(hit) = self
+            .hit_test
+            .pointers
+            .iter_mut()
+            .find(|hit| &hit.pointer == pointer)
+        {
+            hit.region = None;
+            hit.taken.clear();
+            hit.drag.cancel();
+        }
+    }
+
+    //A move or resize took the pointer: whatever was pressed on the decorations never gets its
+    //release.
+    pub(crate) fn forget_decoration_presses(&mut self, pointer: &WlPointer) {
+        if let Some(hit) = self
+            .hit_test
+            .pointers
+            .iter_mut()
+            .find(|hit| &hit.pointer == pointer)
+        {
+            hit.taken.clear();
+            hit.drag.cancel();
+            hit.drag_seat = None;
+        }
+    }
+
+    //The pointer went away with its seat.
+    pub(crate) fn forget_hit_pointer(&mut self, pointer: &WlPointer) {
+        self.hit_test.pointers.retain(|hit| {
+            if &hit.pointer != pointer {
+                return true;
+            }
+            if let Some(ref device) = hit.shape_device {
+                device.destroy();
+            }
+            false
+        });
+    }
+
+    //A button on the decorations does what it's for there and is not the application's: true
+    //when the event was taken. Left on the title bar moves once dragged and maximizes on a
+    //double click, on the border resizes, and a click (press and release) on the close button
+    //closes. Right on the title bar opens the
+    //compositor's window menu.
+    pub(crate) fn decoration_button(
+        &mut self,
+        pointer: &WlPointer,
+        seat: &WlSeat,
+        serial: u32,
+        button: u32,
+        pressed: bool,
+    ) -> bool {
+        let Some(hit) = self
+            .hit_test
+            .pointers
+            .iter_mut()
+            .find(|hit| &hit.pointer == pointer)
+        else {
+            return false;
+        };
+
+        if !pressed {
+            let Some(index) = hit.taken.iter().position(|&(taken, _)| taken == button) else {
+                return false;
+            };
+            let (_, pressed_on) = hit.taken.swap_remove(index);
+            if button == BTN_LEFT
+                && pressed_on == HitRegion::CloseButton
+                && hit.region == Some(HitRegion::CloseButton)
+            {
+                protocol_log!("close button clicked");
+                self.running = false;
+            }
+            if button == BTN_LEFT && pressed_on == HitRegion::TitleBar {
+                hit.drag_seat = None;
+                if hit.drag.release(Instant::now()) == Some(DragOutcome::DoubleClick) {
+                    self.toggle_maximized();
+                }
+            }
+            return true;
+        }
+
+        let region = match hit.region {
+            None | Some(HitRegion::Client) => return false,
+            Some(region) => region,
+        };
+        hit.taken.push((button, region));
+        let (x, y) = hit.position;
+        if (region, button) == (HitRegion::TitleBar, BTN_LEFT) {
+            protocol_log!("title bar pressed (serial {serial})");
+            hit.drag.set_config(self.hit_test.drag_config);
+            hit.drag.press(x, y, serial);
+            hit.drag_seat = Some(seat.clone());
+            return true;
+        }
+
+        //Quoting documentation: "The server may ignore move requests depending on the state of
+        //the surface (e.g. fullscreen or maximized), or if the passed serial is no longer
+        //valid."
+        let geometry = self.window_geometry();
+        let Some((_, ref toplevel)) = self.xdg_surface else {
+            return true;
+        };
+        match (region, button) {
+            (HitRegion::Border(edge), BTN_LEFT) => {
+                protocol_log!("border pressed, resizing {edge:?} (serial {serial})");
+                toplevel.resize(seat, serial, edge.into());
+                self.routing.grab_started(&mut self.events);
+                self.button_repeat.held = None;
+            }
+            (HitRegion::TitleBar, BTN_RIGHT)
+                if self
+                    .wm_capabilities
+                    .as_ref()
+                    .is_none_or(|c| c.contains(&xdg_toplevel::WmCapabilities::WindowMenu)) =>
+            {
+                toplevel.show_window_menu(
+                    seat,
+                    serial,
+                    x as i32 - geometry.x,
+                    y as i32 - geometry.y,
+                );
+            }
+            _ => {}
+        }
+        true
+    }
+
+    //A double click on the title bar: maximized, or not anymore. Not when the compositor said
+    //it can't maximize.
+    fn toggle_maximized(&mut self) {
+        let Some((_, ref toplevel)) = self.xdg_surface else {
+            return;
+        };
+        if self
+            .wm_capabilities
+            .as_ref()
+            .is_some_and(|c| !c.contains(&xdg_toplevel::WmCapabilities::Maximize))
+        {
+            return;
+        }
+        if self.has_state(xdg_toplevel::State::Maximized) {
+            protocol_log!("title bar double clicked, unmaximizing");
+            toplevel.unset_maximized();
+        } else {
+            protocol_log!("title bar double clicked, maximizing");
+            toplevel.set_maximized();
+        }
+    }
+}
+
+impl Window {
+    //Where the title bar, close button and resize border are, for windows with client side
+    //decorations. Replaces a callback from set_hit_test.
+    pub fn set_decoration_layout(&mut self, layout: DecorationLayout) {
+        self.state.hit_test.tester = HitTester::Layout(layout);
+    }
+
+    //For an application drawing its own title bar: `hit_test` gets x and y relative to the
+    //window geometry (negative in the shadow above and left of it) and the window's size, and
+    //says what is there. Tabs in the title bar, say, are Client there, and get their clicks.
+    //Only asked with client side decorations, and not while fullscreen.
+    pub fn set_hit_test(
+        &mut self,
+        hit_test: impl Fn(f64, f64, (u32, u32)) -> HitRegion + Send + 'static,
+    ) {
+        self.state.hit_test.tester = HitTester::Custom(Box::new(hit_test));
+    }
+
+    //What is at x and y, surface-local like pointer events.
+    pub fn hit_test(&self, x: f64, y: f64) -> HitRegion {
+        self.state.hit_region(x, y)
+    }
+
+    pub fn cursor_shape_supported(&self) -> bool {
+        self.state.hit_test.cursor_shape.is_some()
+    }
+}
+
+delegate_noop!(AppState: WpCursorShapeManagerV1);
+delegate_noop!(AppState: WpCursorShapeDeviceV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (u32, u32) = (400, 300);
+
+    fn hit(x: f64, y: f64, shadow: Margins) -> HitRegion {
+        DecorationLayout::default().hit(x, y, SIZE, shadow, TiledEdges::NONE)
+    }
+
+    #[test]
+    fn title_bar_and_close_button() {
+        let shadow = Margins::uniform(20);
+        assert_eq!(hit(100.0, 10.0, shadow), HitRegion::TitleBar);
+        assert_eq!(hit(390.0, 20.0, shadow), HitRegion::CloseButton);
+        assert_eq!(hit(100.0, 100.0, shadow), HitRegion::Client);
+    }
+
+    #[test]
+    fn border_goes_into_the_shadow() {
+        let shadow = Margins::uniform(20);
+        assert_eq!(
+            hit(-4.0, 100.0, shadow),
+            HitRegion::Border(ResizeEdge::Left)
+        );
+        assert_eq!(
+            hit(404.0, 100.0, shadow),
+            HitRegion::Border(ResizeEdge::Right)
+        );
+        assert_eq!(
+            hit(-4.0, -4.0, shadow),
+            HitRegion::Border(ResizeEdge::TopLeft)
+        );
+        //Inside the window it's the window's again, and far out it's nobody's.
+        assert_eq!(hit(2.0, 100.0, shadow), HitRegion::Client);
+        assert_eq!(hit(-15.0, 100.0, shadow), HitRegion::Client);
+    }
+
+    #[test]
+    fn border_without_shadow_is_inside() {
+        let shadow = Margins::default();
+        assert_eq!(hit(2.0, 100.0, shadow), HitRegion::Border(ResizeEdge::Left));
+        assert_eq!(
+            hit(398.0, 298.0, shadow),
+            HitRegion::Border(ResizeEdge::BottomRight)
+        );
+        assert_eq!(hit(100.0, 4.0, shadow), HitRegion::Border(ResizeEdge::Top));
+        assert_eq!(hit(100.0, 12.0, shadow), HitRegion::TitleBar);
+    }
+
+    #[test]
+    fn maximized_has_no_border() {
+        let layout = DecorationLayout::default();
+        let hit = layout.hit(2.0, 4.0, SIZE, Margins::default(), TiledEdges::ALL);
+        assert_eq!(hit, HitRegion::TitleBar);
+    }
+
+    #[test]
+    fn tiled_edges_have_no_border() {
+        let layout = DecorationLayout::default();
+        let tiled = TiledEdges {
+            left: true,
+            ..TiledEdges::NONE
+        };
+        let hit = |x, y| layout.hit(x, y, SIZE, Margins::default(), tiled);
+        assert_eq!(hit(2.0, 100.0), HitRegion::Client);
+        assert_eq!(hit(2.0, 298.0), HitRegion::Border(ResizeEdge::Bottom));
+        assert_eq!(hit(398.0, 100.0), HitRegion::Border(ResizeEdge::Right));
+    }
+}
@@ -0,0 +1,221 @@
+use wayland_client::{
+    Connection, Dispatch, QueueHandle, delegate_noop,
+    protocol::{wl_buffer::WlBuffer, wl_shm},
+};
+use wayland_protocols::xdg::toplevel_icon::v1::client::{
+    xdg_toplevel_icon_manager_v1::{self, XdgToplevelIconManagerV1},
+    xdg_toplevel_icon_v1::XdgToplevelIconV1,
+};
+
+use crate::{AppState, Color, Window, WindowError, buffer_layout::BufferLayout};
+
+//Icon pixels, row by row, 4 bytes per pixel in R, G, B, A order with straight (not
+//premultiplied) alpha, which is what image decoders hand out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconData {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum IconSource {
+    Pixels(IconData),
+    //A themed icon name, looked up by the compositor (e.g. "utilities-terminal").
+    Name(String),
+}
+
+#[derive(Default)]
+pub(crate) struct IconState {
+    pub(crate) manager: Option<XdgToplevelIconManagerV1>,
+    //Edge sizes the compositor asked for, the pending list is replaced on Done.
+    pending_sizes: Vec<i32>,
+    sizes: Vec<i32>,
+    wanted: Option<IconSource>,
+    //The icon in use and the buffers it points to. The buffers have to outlive the icon object.
+    icon: Option<(XdgToplevelIconV1, Vec<WlBuffer>)>,
+}
+
+impl IconState {
+    //The wanted icon without the objects and buffers of the old connection, for
+    //Window::reconnect.
+    pub(crate) fn for_reconnect(&mut self) -> IconState {
+        IconState {
+            wanted: self.wanted.take(),
+            ..IconState::default()
+        }
+    }
+}
+
+impl AppState {
+    //Builds an icon object from the wanted source and sets it on the toplevel, replacing the
+    //previous one. Also called when the toplevel gets created (before its initial commit) and
+    //when the compositor changes its preferred sizes. It's double-buffered: the caller commits.
+    pub(crate) fn apply_icon(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let (Some(manager), Some((_, toplevel))) =
+            (self.icon.manager.clone(), self.xdg_surface.clone())
+        else {
+            return;
+        };
+        //Taken out while the buffers are made, they need all of self.
+        let Some(wanted) = self.icon.wanted.take() else {
+            return;
+        };
+
+        let icon = manager.create_icon(queue_handle, ());
+        let buffers = match &wanted {
+            IconSource::Name(name) => {
+                icon.set_name(name.clone());
+                Vec::new()
+            }
+            IconSource::Pixels(data) => {
+                //No preference from the compositor: the icon's own size will do.
+                let sizes = if self.icon.sizes.is_empty() {
+                    vec![data.width.max(data.height) as i32]
+                } else {
+                    self.icon.sizes.clone()
+                };
+                let buffers = self.create_icon_buffers(data, &sizes, queue_handle);
+                for buffer in &buffers {
+                    icon.add_buffer(buffer, 1);
+                }
+                buffers
+            }
+        };
+        self.icon.wanted = Some(wanted);
+
+        //Double-buffered, the commit applies it. The old icon object and its buffers can go as
+        //soon as the new one is set.
+        manager.set_icon(&toplevel, Some(&icon));
+        if let Some((old_icon, old_buffers)) = self.icon.icon.replace((icon, buffers)) {
+            old_icon.destroy();
+            for buffer in old_buffers {
+                self.drop_buffer(buffer);
+            }
+        }
+    }
+
+    //One square buffer per size, from the shared pool. The compositor requires squares, so the
+    //picture is scaled to fit and centered on a transparent background.
+    fn create_icon_buffers(
+        &mut self,
+        data: &IconData,
+        sizes: &[i32],
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Vec<WlBuffer> {
+        sizes
+            .iter()
+            .filter_map(|&size| {
+                //The sizes are the compositor's, a silly one is skipped rather than trusted.
+                let size = size.max(1) as u32;
+                let layout = match BufferLayout::new(size, size, wl_shm::Format::Argb8888) {
+                    Ok(layout) => layout,
+                    Err(err) => {
+                        log::warn!("{size}x{size} icon skipped: {err}");
+                        return None;
+                    }
+                };
+                let pixels = scale_to_square(data, size);
+                //Quoting documentation: "The buffer contents must not be modified after it was
+                //assigned to the icon." Pinned, so compaction never moves it.
+                self.create_pool_buffer(layout, &pixels, true, queue_handle)
+            })
+            .collect()
+    }
+}
+
+//Nearest-neighbour scale into a size x size Argb8888 (premultiplied, little endian B, G, R, A)
+//picture.
+fn scale_to_square(data: &IconData, size: u32) -> Vec<u8> {
+    let scale = f64::from(size) / f64::from(data.width.max(data.height));
+    let (scaled_width, scaled_height) = (
+        (f64::from(data.width) * scale).round() as u32,
+        (f64::from(data.height) * scale).round() as u32,
+    );
+    let (left, top) = (
+        (size - scaled_width.min(size)) / 2,
+        (size - scaled_height.min(size)) / 2,
+    );
+
+    let mut pixels = vec![0u8; (size * size * 4) as usize];
+    for y in 0..scaled_height.min(size) {
+        for x in 0..scaled_width.min(size) {
+            let source_x = ((f64::from(x) / scale) as u32).min(data.width - 1);
+            let source_y = ((f64::from(y) / scale) as u32).min(data.height - 1);
+            let source = ((source_y * data.width + source_x) * 4) as usize;
+            let [r, g, b, a] = data.rgba[source..source + 4] else {
+                continue;
+            };
+
+            let target = (((top + y) * size + left + x) * 4) as usize;
+            pixels[target..target + 4]
+                .copy_from_slice(&Color::from_unpremultiplied(r, g, b, a).to_argb8888_bytes());
+        }
+    }
+    pixels
+}
+
+impl Window {
+    //Task bar / switcher icon from pixels. Needs xdg_toplevel_icon_manager_v1 (KDE Plasma 6.1+
+    //among others).
+    pub fn set_icon(&mut self, icon: IconData) -> Result<(), WindowError> {
+        if icon.width == 0
+            || icon.height == 0
+            || Some(icon.rgba.len())
+                != (icon.width as usize)
+                    .checked_mul(icon.height as usize)
+                    .and_then(|pixels| pixels.checked_mul(4))
+        {
+            return Err(WindowError::InvalidArgument(
+                "icon pixels don't match its size",
+            ));
+        }
+        self.set_icon_source(IconSource::Pixels(icon))
+    }
+
+    //Themed icon, looked up by name in the icon theme.
+    pub fn set_icon_name(&mut self, name: &str) -> Result<(), WindowError> {
+        self.set_icon_source(IconSource::Name(crate::title::wire_string(name)))
+    }
+
+    fn set_icon_source(&mut self, source: IconSource) -> Result<(), WindowError> {
+        if self.state.icon.manager.is_none() {
+            return Err(WindowError::Unsupported("xdg_toplevel_icon_manager_v1"));
+        }
+
+        let queue_handle = self.event_queue.handle();
+        self.state.icon.wanted = Some(source);
+        self.state.apply_icon(&queue_handle);
+        self.state.commit_state(&queue_handle);
+        Ok(())
+    }
+}
+
+impl Dispatch<XdgToplevelIconManagerV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &XdgToplevelIconManagerV1,
+        event: xdg_toplevel_icon_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+    ) {
+        match event {
+            xdg_toplevel_icon_manager_v1::Event::IconSize { size } => {
+                state.icon.pending_sizes.push(size);
+            }
+            xdg_toplevel_icon_manager_v1::Event::Done => {
+                state.icon.sizes = std::mem::take(&mut state.icon.pending_sizes);
+                //Pixel icons were made for the old sizes, redo them.
+                if let Some(IconSource::Pixels(_)) = state.icon.wanted {
+                    state.apply_icon(queue_handle);
+                    state.commit_state(queue_handle);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+//xdg_toplevel_icon_v1 has no events.
+delegate_noop!(AppState: ignore XdgToplevelIconV1);
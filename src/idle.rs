@@ -0,0 +1,240 @@
+//User idle detection through ext_idle_notifier_v1: the compositor tells when nobody touched the
+//seat for a while, so dashboards and such can stop redrawing at full speed. Each timeout is a
+//notification object of its own, on the first seat (the one the pointer is usually on).
+//
+//Quoting documentation: "if an idle inhibitor is active (e.g. another client has created a
+//zwp_idle_inhibitor_v1 on a visible surface), the compositor must not make the notification
+//object idle." A video playing elsewhere keeps us active too, which is what get_idle_notification
+//is picked for: the screen stays on, so does what's on it.
+
+use std::time::Duration;
+
+use wayland_client::{Connection, Dispatch, QueueHandle, delegate_noop, protocol::wl_seat::WlSeat};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+
+use crate::{AppState, Window, WindowEvent, protocol_log::protocol_log};
+
+//Identifies a timeout registered with Window::on_idle, to cancel it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdleToken(u64);
+
+type IdleCallback = Box<dyn FnMut(&mut Window, bool) + Send>;
+
+struct IdleWatch {
+    token: IdleToken,
+    timeout: Duration,
+    notification: Option<ExtIdleNotificationV1>,
+    idle: bool,
+    //Taken out while it runs, it gets the window and could cancel itself meanwhile.
+    callback: Option<IdleCallback>,
+}
+
+#[derive(Default)]
+pub(crate) struct IdleState {
+    pub(crate) notifier: Option<ExtIdleNotifierV1>,
+    //Where the notifications are, to notice when it goes away.
+    seat: Option<WlSeat>,
+    watches: Vec<IdleWatch>,
+    next_token: u64,
+    //Idled (true) and Resumed (false) waiting for their callbacks to run.
+    fired: Vec<(IdleToken, bool)>,
+}
+
+impl IdleState {
+    //The timeouts and callbacks without the objects of the old connection, for Window::reconnect.
+    //Everything starts out active again.
+    pub(crate) fn for_reconnect(&mut self) -> IdleState {
+        IdleState {
+            watches: std::mem::take(&mut self.watches)
+                .into_iter()
+                .map(|watch| IdleWatch {
+                    notification: None,
+                    idle: false,
+                    ..watch
+                })
+                .collect(),
+            next_token: self.next_token,
+            ..IdleState::default()
+        }
+    }
+}
+
+//Quoting documentation: "minimum idle timeout in msec".
+fn timeout_millis(timeout: Duration) -> u32 {
+    timeout.as_millis().try_into().unwrap_or(u32::MAX)
+}
+
+impl AppState {
+    //Creates the missing notification objects. Called when the notifier or a seat comes, and
+    //when the seat they were on went away (they move to the next one).
+    pub(crate) fn init_idle_notifications(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let idle = &mut self.idle;
+        let Some(ref notifier) = idle.notifier else {
+            return;
+        };
+        if idle.seat.is_none() {
+            idle.seat = self.seats.first_seat().cloned();
+        }
+        let Some(ref seat) = idle.seat else {
+            return;
+        };
+        for watch in idle.watches.iter_mut().filter(|w| w.notification.is_none()) {
+            watch.notification = Some(notifier.get_idle_notification(
+                timeout_millis(watch.timeout),
+                seat,
+                queue_handle,
+                watch.token,
+            ));
+        }
+    }
+
+    //The window is going, see cleanup.rs. The watches stay as they are, nothing runs them anymore.
+    pub(crate) fn release_idle_notifications(&mut self) {
+        self.idle.seat = None;
+        for watch in &mut self.idle.watches {
+            if let Some(notification) = watch.notification.take() {
+                notification.destroy();
+            }
+        }
+    }
+
+    //GlobalRemove of a seat: if the notifications were on it, they start over on another one.
+    pub(crate) fn idle_seat_removed(
+        &mut self,
+        seat: &WlSeat,
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        if self.idle.seat.as_ref() != Some(seat) {
+            return;
+        }
+        self.idle.seat = None;
+        for watch in &mut self.idle.watches {
+            if let Some(notification) = watch.notification.take() {
+                notification.destroy();
+            }
+            if watch.idle {
+                watch.idle = false;
+                self.idle.fired.push((watch.token, false));
+                self.events.push(WindowEvent::UserActive {
+                    timeout: watch.timeout,
+                });
+            }
+        }
+        self.init_idle_notifications(queue_handle);
+    }
+}
+
+impl Window {
+    //Calls `callback` with true once the user has been idle for `timeout`, and with false when
+    //they're back. WindowEvent::UserIdle and UserActive come along with it. Several timeouts can
+    //be registered, each one goes idle on its own.
+    //
+    //Without ext_idle_notifier_v1 (see idle_notify_supported) nothing ever fires. Survives
+    //reconnect.
+    pub fn on_idle(
+        &mut self,
+        timeout: Duration,
+        callback: impl FnMut(&mut Window, bool) + Send + 'static,
+    ) -> IdleToken {
+        let idle = &mut self.state.idle;
+        idle.next_token += 1;
+        let token = IdleToken(idle.next_token);
+        idle.watches.push(IdleWatch {
+            token,
+            timeout,
+            notification: None,
+            idle: false,
+            callback: Some(Box::new(callback)),
+        });
+        let queue_handle = self.event_queue.handle();
+        self.state.init_idle_notifications(&queue_handle);
+        token
+    }
+
+    //The timeout stops being watched. No UserActive follows, even if it was idle.
+    pub fn cancel_idle(&mut self, token: IdleToken) {
+        let idle = &mut self.state.idle;
+        idle.fired.retain(|(fired, _)| *fired != token);
+        idle.watches.retain(|watch| {
+            if watch.token != token {
+                return true;
+            }
+            if let Some(ref notification) = watch.notification {
+                notification.destroy();
+            }
+            false
+        });
+    }
+
+    //Whether any registered timeout is idle right now.
+    pub fn is_user_idle(&self) -> bool {
+        self.state.idle.watches.iter().any(|watch| watch.idle)
+    }
+
+    pub fn idle_notify_supported(&self) -> bool {
+        self.state.idle.notifier.is_some()
+    }
+
+    pub(crate) fn run_idle_callbacks(&mut self) {
+        for (token, idle) in std::mem::take(&mut self.state.idle.fired) {
+            let watches = &mut self.state.idle.watches;
+            let Some(mut callback) = watches
+                .iter_mut()
+                .find(|watch| watch.token == token)
+                .and_then(|watch| watch.callback.take())
+            else {
+                continue;
+            };
+            callback(self, idle);
+            //Back in place, unless it cancelled itself.
+            if let Some(watch) = self
+                .state
+                .idle
+                .watches
+                .iter_mut()
+                .find(|watch| watch.token == token)
+            {
+                watch.callback = Some(callback);
+            }
+        }
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, IdleToken> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        token: &IdleToken,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let idle = match event {
+            ext_idle_notification_v1::Event::Idled => true,
+            ext_idle_notification_v1::Event::Resumed => false,
+            _ => return,
+        };
+        let Some(watch) = state
+            .idle
+            .watches
+            .iter_mut()
+            .find(|watch| watch.token == *token)
+        else {
+            return;
+        };
+        protocol_log!("idle {:?}: {idle}", watch.timeout);
+        watch.idle = idle;
+        let timeout = watch.timeout;
+        state.idle.fired.push((*token, idle));
+        state.events.push(if idle {
+            WindowEvent::UserIdle { timeout }
+        } else {
+            WindowEvent::UserActive { timeout }
+        });
+    }
+}
+
+delegate_noop!(AppState: ExtIdleNotifierV1);
@@ -0,0 +1,199 @@
+use std::path::Path;
+
+use image::RgbaImage;
+
+use crate::{Canvas, Color, Rect, Window, WindowError};
+
+//How draw_image_scaled picks the color of a pixel that falls between image pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    //The closest image pixel: blocky, but sharp edges stay sharp (pixel art).
+    Nearest,
+    //The four closest, weighted by distance. Smooth, for photos.
+    #[default]
+    Bilinear,
+}
+
+//Image pixels are RGBA with straight alpha, the buffer wants them premultiplied (see Color). For
+//Xrgb8888 buffers that's the image over black, which is what an opaque window can show anyway.
+fn pixel(image: &RgbaImage, x: u32, y: u32) -> Color {
+    let [r, g, b, a] = image.get_pixel(x, y).0;
+    Color::from_unpremultiplied(r, g, b, a)
+}
+
+//Premultiplied channels can be interpolated as they are, straight ones can't: a transparent
+//neighbour's color (often black) would bleed into the edge.
+fn bilinear(image: &RgbaImage, x: f32, y: f32) -> Color {
+    let (max_x, max_y) = (image.width() - 1, image.height() - 1);
+    let (x, y) = (x.clamp(0.0, max_x as f32), y.clamp(0.0, max_y as f32));
+    let (x0, y0) = (x as u32, y as u32);
+    let (x1, y1) = ((x0 + 1).min(max_x), (y0 + 1).min(max_y));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let corners = [
+        (pixel(image, x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (pixel(image, x1, y0), fx * (1.0 - fy)),
+        (pixel(image, x0, y1), (1.0 - fx) * fy),
+        (pixel(image, x1, y1), fx * fy),
+    ];
+    let mut sum = [0.0f32; 4];
+    for (color, weight) in corners {
+        for (sum, channel) in sum.iter_mut().zip(color.to_argb8888_bytes()) {
+            *sum += f32::from(channel) * weight;
+        }
+    }
+    let [b, g, r, a] = sum.map(|channel| channel.round() as u8);
+    Color::premultiplied(r, g, b, a)
+}
+
+//The largest rectangle with the image's aspect ratio that fits in width x height, centered.
+fn fit(image: &RgbaImage, width: u32, height: u32) -> Rect {
+    let scale = f64::min(
+        f64::from(width) / f64::from(image.width()),
+        f64::from(height) / f64::from(image.height()),
+    );
+    let (fit_width, fit_height) = (
+        (f64::from(image.width()) * scale).round() as i32,
+        (f64::from(image.height()) * scale).round() as i32,
+    );
+    Rect::new(
+        (width as i32 - fit_width) / 2,
+        (height as i32 - fit_height) / 2,
+        fit_width,
+        fit_height,
+    )
+}
+
+impl Canvas<'_> {
+    //The image at its own size, top left corner at (x, y). What falls outside the canvas is cut
+    //off. Pixels are replaced, not blended: a transparent image pixel makes a transparent window
+    //pixel.
+    pub fn draw_image(&mut self, image: &RgbaImage, x: i32, y: i32) {
+        for image_y in 0..image.height() {
+            let canvas_y = i64::from(y) + i64::from(image_y);
+            if canvas_y < 0 || canvas_y >= i64::from(self.height()) {
+                continue;
+            }
+            for image_x in 0..image.width() {
+                let canvas_x = i64::from(x) + i64::from(image_x);
+                if canvas_x >= 0 && canvas_x < i64::from(self.width()) {
+                    self.put_pixel(
+                        canvas_x as u32,
+                        canvas_y as u32,
+                        pixel(image, image_x, image_y),
+                    );
+                }
+            }
+        }
+    }
+
+    //The image stretched over `dest`, same clipping as draw_image.
+    pub fn draw_image_scaled(&mut self, image: &RgbaImage, dest: Rect, filter: Filter) {
+        if dest.width <= 0 || dest.height <= 0 || image.width() == 0 || image.height() == 0 {
+            return;
+        }
+        let scale_x = image.width() as f32 / dest.width as f32;
+        let scale_y = image.height() as f32 / dest.height as f32;
+
+        let rows = dest.y.max(0)..(dest.y + dest.height).min(self.height() as i32);
+        let columns = dest.x.max(0)..(dest.x + dest.width).min(self.width() as i32);
+        for y in rows {
+            //Pixel centers: the middle of a canvas pixel maps to the middle of an image area.
+            let image_y = ((y - dest.y) as f32 + 0.5) * scale_y - 0.5;
+            for x in columns.clone() {
+                let image_x = ((x - dest.x) as f32 + 0.5) * scale_x - 0.5;
+                let color = match filter {
+                    Filter::Nearest => pixel(
+                        image,
+                        (image_x.round().max(0.0) as u32).min(image.width() - 1),
+                        (image_y.round().max(0.0) as u32).min(image.height() - 1),
+                    ),
+                    Filter::Bilinear => bilinear(image, image_x, image_y),
+                };
+                self.put_pixel(x as u32, y as u32, color);
+            }
+        }
+    }
+}
+
+//Decodes a PNG, JPEG or whatever else the image crate knows from its contents.
+fn load(path: &Path) -> Result<RgbaImage, WindowError> {
+    image::open(path)
+        .map(|image| image.into_rgba8())
+        .map_err(|err| WindowError::Image(format!("{}: {err}", path.display())))
+}
+
+impl Window {
+    //Shows the image as the window content: scaled to fit the buffer with its aspect ratio kept,
+    //centered, transparent around it. Like draw, it stays until something else is drawn.
+    pub fn show_image(&mut self, path: impl AsRef<Path>) -> Result<(), WindowError> {
+        let image = load(path.as_ref())?;
+        self.draw(|canvas| {
+            let dest = fit(&image, canvas.width(), canvas.height());
+            //1:1 needs no filtering, and nearest is exact there.
+            let filter = if (dest.width as u32, dest.height as u32) == image.dimensions() {
+                Filter::Nearest
+            } else {
+                Filter::Bilinear
+            };
+            canvas.draw_image_scaled(&image, dest, filter);
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageFormat, Rgba};
+
+    use super::*;
+
+    //A 2x2 PNG: opaque red, green and blue, and white at half alpha. The buffer has to end up
+    //B, G, R, A with the last one premultiplied.
+    #[test]
+    fn png_lands_as_premultiplied_bgra() {
+        let mut png = Vec::new();
+        RgbaImage::from_fn(2, 2, |x, y| match (x, y) {
+            (0, 0) => Rgba([0xFF, 0, 0, 0xFF]),
+            (1, 0) => Rgba([0, 0xFF, 0, 0xFF]),
+            (0, 1) => Rgba([0, 0, 0xFF, 0xFF]),
+            _ => Rgba([0xFF, 0xFF, 0xFF, 0x80]),
+        })
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+        let image = image::load_from_memory(&png).unwrap().into_rgba8();
+
+        let mut bytes = vec![0; 2 * 2 * 4];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 2, 2, 8).unwrap();
+        canvas.draw_image(&image, 0, 0);
+        #[rustfmt::skip]
+        assert_eq!(bytes, [
+            0, 0, 0xFF, 0xFF,     0, 0xFF, 0, 0xFF,
+            0xFF, 0, 0, 0xFF,     0x80, 0x80, 0x80, 0x80,
+        ]);
+
+        //Scaled 1:1 it's the same bytes, whichever the filter.
+        for filter in [Filter::Nearest, Filter::Bilinear] {
+            let mut scaled = vec![0; 2 * 2 * 4];
+            Canvas::from_bytes(&mut scaled, 2, 2, 8)
+                .unwrap()
+                .draw_image_scaled(&image, Rect::new(0, 0, 2, 2), filter);
+            assert_eq!(scaled, bytes);
+        }
+    }
+
+    #[test]
+    fn images_are_clipped_and_fitted() {
+        let image = RgbaImage::from_pixel(4, 2, Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
+        let mut bytes = vec![0; 3 * 3 * 4];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 3, 3, 12).unwrap();
+        canvas.draw_image(&image, -2, 2);
+        assert_eq!(bytes[2 * 12..2 * 12 + 8], [0xFF; 8]);
+        assert_eq!(bytes[2 * 12 + 8..], [0; 4]);
+
+        //Twice as wide as high, in a square: full width, centered vertically.
+        assert_eq!(fit(&image, 100, 100), Rect::new(0, 25, 100, 50));
+    }
+}
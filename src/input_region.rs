@@ -0,0 +1,108 @@
+use wayland_client::QueueHandle;
+
+use crate::{AppState, Rect, Window, region::create_region};
+
+//Which part of the surface takes pointer and touch input. Outside of it, clicks go to whatever
+//is below the window: what overlays use to be click-through. The keyboard doesn't care, a window
+//that takes no clicks at all still gets keys while it has the focus, and the compositor can
+//still close it.
+#[derive(Default)]
+enum Wanted {
+    #[default]
+    Full,
+    Rects(Vec<Rect>),
+    //Worked out again from the surface size whenever it changes.
+    Relative(Box<dyn Fn(u32, u32) -> Vec<Rect> + Send>),
+}
+
+#[derive(Default)]
+pub(crate) struct InputRegionState {
+    wanted: Wanted,
+    //Surface size the region was last sent for, None when it has to be sent again.
+    sent_for: Option<(u32, u32)>,
+    //Whether the surface has a region other than the default, infinite one.
+    limited: bool,
+}
+
+impl InputRegionState {
+    //What set_input_region asks for, sent by the next apply_input_region.
+    pub(crate) fn want(&mut self, rects: Option<&[Rect]>) {
+        self.wanted = match rects {
+            Some(rects) => Wanted::Rects(rects.to_vec()),
+            None => Wanted::Full,
+        };
+        self.sent_for = None;
+    }
+
+    //The wanted region, to be sent again on the new connection's surface, for Window::reconnect.
+    pub(crate) fn for_reconnect(&mut self) -> InputRegionState {
+        InputRegionState {
+            wanted: std::mem::take(&mut self.wanted),
+            sent_for: None,
+            limited: false,
+        }
+    }
+}
+
+impl AppState {
+    //Sends the region if it changed, or if the surface size did. Called next to
+    //apply_window_geometry, so both go out in the same commit.
+    pub(crate) fn apply_input_region(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let size = self.surface_size();
+        let (Some(surface), Some(compositor)) = (&self.base_surface, &self.compositor) else {
+            return;
+        };
+        let state = &mut self.input_region;
+        let rects = match &state.wanted {
+            //Quoting documentation: "A NULL wl_region causes the input region to be set to
+            //infinite." Which is also what a new surface starts with, so nothing to send then.
+            Wanted::Full => {
+                if state.limited {
+                    surface.set_input_region(None);
+                    state.limited = false;
+                }
+                return;
+            }
+            _ if state.sent_for == Some(size) => return,
+            Wanted::Rects(rects) => rects.clone(),
+            Wanted::Relative(region) => region(size.0, size.1),
+        };
+        state.sent_for = Some(size);
+        state.limited = true;
+        //Quoting documentation: "Setting the pending input region has copy semantics, and the
+        //wl_region object can be destroyed immediately."
+        let region = create_region(compositor, &rects, queue_handle);
+        surface.set_input_region(Some(&region));
+        region.destroy();
+    }
+}
+
+impl Window {
+    //Rectangles in surface coordinates (the shadow included) that take pointer and touch input,
+    //the rest is click-through. Some(&[]) makes the whole window click-through, None goes back
+    //to all of it taking input.
+    pub fn set_input_region(&mut self, rects: Option<&[Rect]>) {
+        self.state.input_region.want(rects);
+        self.send_input_region();
+    }
+
+    //The same, with the rectangles worked out from the surface's width and height, now and after
+    //every resize: e.g. a strip along the bottom whatever the window's size.
+    pub fn set_input_region_with(
+        &mut self,
+        region: impl Fn(u32, u32) -> Vec<Rect> + Send + 'static,
+    ) {
+        let state = &mut self.state.input_region;
+        state.wanted = Wanted::Relative(Box::new(region));
+        state.sent_for = None;
+        self.send_input_region();
+    }
+
+    fn send_input_region(&mut self) {
+        let queue_handle = self.event_queue.handle();
+        self.state.apply_input_region(&queue_handle);
+        //Quoting documentation: "Input region is double-buffered state, see
+        //wl_surface.commit."
+        self.state.commit_state(&queue_handle);
+    }
+}
@@ -0,0 +1,188 @@
+//Where input goes while the compositor has a grab of its own. After an interactive move or
+//resize, the pointer is the compositor's until the button goes up, wherever that happens: the
+//release often never reaches us, a Leave may or may not come first, and some compositors send
+//the release once the pointer is back. With a popup's explicit grab, the keyboard focus goes to
+//the popup, and its keys come through our wl_keyboard all the same.
+//
+//Left alone, that's a button the application saw pressed and never released, a decoration
+//press whose stale release swallows the application's next one, or Escape quitting the window
+//while the user only meant to close a menu. So there's a mode:
+//
+//  Normal            everything as usual.
+//  MovingOrResizing  from our move or resize request (a dragged title bar, a border, begin_move)
+//                    to the next pointer event on our surface: Enter, Motion or a button, the
+//                    button being the grab's and swallowed. Buttons the application saw pressed
+//                    get their release when the grab starts.
+//  PopupGrab         from grab_popup to popup_grab_ended, or the keyboard coming back to our
+//                    surface. Keys still go to the application, for its popup.
+//
+//In both grabs, key bindings don't fire. And in any mode, a release only reaches the
+//application when it got the press.
+
+use std::sync::Arc;
+
+use wayland_client::{
+    Proxy,
+    protocol::{wl_pointer::WlPointer, wl_seat::WlSeat, wl_surface::WlSurface},
+};
+use wayland_protocols::xdg::shell::client::xdg_popup::XdgPopup;
+
+use crate::{
+    AppState, ResizeEdge, SerialKind, Window, WindowError, WindowEvent, protocol_log::protocol_log,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputRouting {
+    #[default]
+    Normal,
+    MovingOrResizing,
+    //The popup's protocol id.
+    PopupGrab(u32),
+}
+
+#[derive(Default)]
+pub(crate) struct InputRoutingState {
+    pub(crate) mode: InputRouting,
+    //Buttons the application got the press of and not the release yet.
+    pressed: Vec<(WlPointer, Arc<str>, u32)>,
+}
+
+impl InputRoutingState {
+    //We asked for a move or resize. Kept apart from AppState so it can be called while the
+    //decorations' state is borrowed, see hit_test.rs.
+    pub(crate) fn grab_started(&mut self, events: &mut Vec<WindowEvent>) {
+        self.mode = InputRouting::MovingOrResizing;
+        for (_, seat, button) in self.pressed.drain(..) {
+            events.push(WindowEvent::PointerButton {
+                seat,
+                button,
+                pressed: false,
+            });
+        }
+    }
+
+    //Whether the application gets this button event. Presses always, releases of its presses.
+    fn pass(&mut self, pointer: &WlPointer, seat: Arc<str>, button: u32, pressed: bool) -> bool {
+        if pressed {
+            self.pressed.push((pointer.clone(), seat, button));
+            return true;
+        }
+        match self
+            .pressed
+            .iter()
+            .position(|(p, _, b)| p == pointer && *b == button)
+        {
+            Some(index) => {
+                self.pressed.swap_remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl AppState {
+    //Pointer input on our surface (Enter, Motion, a button): a move or resize is over. True
+    //when it was, the event is then the grab's.
+    pub(crate) fn pointer_grab_ended(&mut self, pointer: &WlPointer) -> bool {
+        if self.routing.mode != InputRouting::MovingOrResizing {
+            return false;
+        }
+        protocol_log!("move or resize over");
+        self.routing.mode = InputRouting::Normal;
+        self.forget_decoration_presses(pointer);
+        true
+    }
+
+    //A button that got past the decorations: whether it's the application's.
+    pub(crate) fn route_button(
+        &mut self,
+        pointer: &WlPointer,
+        seat: Arc<str>,
+        button: u32,
+        pressed: bool,
+    ) -> bool {
+        self.routing.pass(pointer, seat, button, pressed)
+    }
+
+    //The keyboard focus came to `surface`: back from a popup when it's ours.
+    pub(crate) fn keyboard_entered(&mut self, surface: &WlSurface) {
+        if matches!(self.routing.mode, InputRouting::PopupGrab(_))
+            && self.base_surface.as_ref() == Some(surface)
+        {
+            self.routing.mode = InputRouting::Normal;
+        }
+    }
+
+    //The pointer went away with its seat.
+    pub(crate) fn forget_routed_pointer(&mut self, pointer: &WlPointer) {
+        self.routing.pressed.retain(|(p, ..)| p != pointer);
+        if self.routing.mode == InputRouting::MovingOrResizing {
+            self.routing.mode = InputRouting::Normal;
+        }
+    }
+}
+
+impl Window {
+    pub fn input_routing(&self) -> InputRouting {
+        self.state.routing.mode
+    }
+
+    //Moves the window with the pointer, for a title bar of the application's own (a Client
+    //region, see set_hit_test). Call it on the press, or on a drag from it: the latest button
+    //press is the one the compositor checks. Its release comes right away, see input_routing.rs.
+    pub fn begin_move(&mut self) -> Result<(), WindowError> {
+        let (seat, serial) = self.grab_trigger("xdg_toplevel.move")?;
+        let Some((_, ref toplevel)) = self.state.xdg_surface else {
+            return Err(WindowError::NotConfigured);
+        };
+        toplevel._move(&seat, serial);
+        self.grab_started()
+    }
+
+    //The same for resizing from `edge`.
+    pub fn begin_resize(&mut self, edge: ResizeEdge) -> Result<(), WindowError> {
+        let (seat, serial) = self.grab_trigger("xdg_toplevel.resize")?;
+        let Some((_, ref toplevel)) = self.state.xdg_surface else {
+            return Err(WindowError::NotConfigured);
+        };
+        toplevel.resize(&seat, serial, edge.into());
+        self.grab_started()
+    }
+
+    //Gives `popup` (made on the application's own queue, see protocol_objects.rs) an explicit
+    //grab with the latest press, before its initial commit. Key bindings stay off until
+    //popup_grab_ended, which is for its popup_done, or until the keyboard comes back.
+    pub fn grab_popup(&mut self, popup: &XdgPopup) -> Result<(), WindowError> {
+        let Some((seat, serial)) = self.state.latest_serial(
+            &[SerialKind::PointerButton, SerialKind::KeyPress],
+            "xdg_popup.grab",
+        ) else {
+            return Err(WindowError::NoPointer);
+        };
+        popup.grab(&seat, serial);
+        self.state.routing.mode = InputRouting::PopupGrab(popup.id().protocol_id());
+        Ok(())
+    }
+
+    pub fn popup_grab_ended(&mut self, popup: &XdgPopup) {
+        if self.state.routing.mode == InputRouting::PopupGrab(popup.id().protocol_id()) {
+            self.state.routing.mode = InputRouting::Normal;
+        }
+    }
+
+    fn grab_trigger(&self, request: &str) -> Result<(WlSeat, u32), WindowError> {
+        self.state
+            .latest_serial(&[SerialKind::PointerButton], request)
+            .ok_or(WindowError::NoPointer)
+    }
+
+    fn grab_started(&mut self) -> Result<(), WindowError> {
+        let state = &mut self.state;
+        state.routing.grab_started(&mut state.events);
+        state.button_repeat.held = None;
+        //The compositor should have the pointer before the button goes up.
+        let _ = self.flush();
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+//Keys by name instead of evdev keycodes (linux/input-event-codes.h), which is what wl_keyboard
+//sends with a keymap or without. These are physical keys: Key::Q is the key where Q is on a US
+//layout, whatever the layout in use says, and Key::Numpad7 is Numpad7 with NumLock on or off.
+//What the key means in the layout in use is LogicalKey's, from the keymap, see numpad.rs.
+
+//The enum and both directions of the conversion from one list.
+macro_rules! keys {
+    ($($name:ident = $code:literal,)+) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Key {
+            $($name,)+
+            //Anything not named above, by its evdev code.
+            Unknown(u32),
+        }
+
+        impl Key {
+            pub fn from_evdev(code: u32) -> Key {
+                match code {
+                    $($code => Key::$name,)+
+                    code => Key::Unknown(code),
+                }
+            }
+
+            pub fn evdev(self) -> u32 {
+                match self {
+                    $(Key::$name => $code,)+
+                    Key::Unknown(code) => code,
+                }
+            }
+        }
+    };
+}
+
+keys! {
+    Escape = 1,
+    Digit1 = 2,
+    Digit2 = 3,
+    Digit3 = 4,
+    Digit4 = 5,
+    Digit5 = 6,
+    Digit6 = 7,
+    Digit7 = 8,
+    Digit8 = 9,
+    Digit9 = 10,
+    Digit0 = 11,
+    Minus = 12,
+    Equal = 13,
+    Backspace = 14,
+    Tab = 15,
+    Q = 16,
+    W = 17,
+    E = 18,
+    R = 19,
+    T = 20,
+    Y = 21,
+    U = 22,
+    I = 23,
+    O = 24,
+    P = 25,
+    LeftBracket = 26,
+    RightBracket = 27,
+    Enter = 28,
+    LeftCtrl = 29,
+    A = 30,
+    S = 31,
+    D = 32,
+    F = 33,
+    G = 34,
+    H = 35,
+    J = 36,
+    K = 37,
+    L = 38,
+    Semicolon = 39,
+    Apostrophe = 40,
+    Grave = 41,
+    LeftShift = 42,
+    Backslash = 43,
+    Z = 44,
+    X = 45,
+    C = 46,
+    V = 47,
+    B = 48,
+    N = 49,
+    M = 50,
+    Comma = 51,
+    Dot = 52,
+    Slash = 53,
+    RightShift = 54,
+    NumpadMultiply = 55,
+    LeftAlt = 56,
+    Space = 57,
+    CapsLock = 58,
+    F1 = 59,
+    F2 = 60,
+    F3 = 61,
+    F4 = 62,
+    F5 = 63,
+    F6 = 64,
+    F7 = 65,
+    F8 = 66,
+    F9 = 67,
+    F10 = 68,
+    NumLock = 69,
+    ScrollLock = 70,
+    Numpad7 = 71,
+    Numpad8 = 72,
+    Numpad9 = 73,
+    NumpadSubtract = 74,
+    Numpad4 = 75,
+    Numpad5 = 76,
+    Numpad6 = 77,
+    NumpadAdd = 78,
+    Numpad1 = 79,
+    Numpad2 = 80,
+    Numpad3 = 81,
+    Numpad0 = 82,
+    NumpadDecimal = 83,
+    F11 = 87,
+    F12 = 88,
+    NumpadEnter = 96,
+    RightCtrl = 97,
+    NumpadDivide = 98,
+    PrintScreen = 99,
+    RightAlt = 100,
+    Home = 102,
+    Up = 103,
+    PageUp = 104,
+    Left = 105,
+    Right = 106,
+    End = 107,
+    Down = 108,
+    PageDown = 109,
+    Insert = 110,
+    Delete = 111,
+    Mute = 113,
+    VolumeDown = 114,
+    VolumeUp = 115,
+    Pause = 119,
+    LeftMeta = 125,
+    RightMeta = 126,
+    Menu = 127,
+    NextSong = 163,
+    PlayPause = 164,
+    PreviousSong = 165,
+    StopMedia = 166,
+}
+
+impl Key {
+    pub fn is_modifier(self) -> bool {
+        matches!(
+            self,
+            Key::LeftCtrl
+                | Key::RightCtrl
+                | Key::LeftShift
+                | Key::RightShift
+                | Key::LeftAlt
+                | Key::RightAlt
+                | Key::LeftMeta
+                | Key::RightMeta
+        )
+    }
+}
+
+//Whether a key went down or up. Repeats are a separate event, WindowEvent::KeyRepeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+impl KeyState {
+    pub fn is_pressed(self) -> bool {
+        self == KeyState::Pressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_keys_round_trip() {
+        for code in 0..256 {
+            assert_eq!(Key::from_evdev(code).evdev(), code);
+        }
+        assert_eq!(Key::from_evdev(1), Key::Escape);
+        assert_eq!(Key::from_evdev(16), Key::Q);
+    }
+
+    #[test]
+    fn unnamed_keys_keep_their_code() {
+        assert_eq!(Key::from_evdev(0x2ff), Key::Unknown(0x2ff));
+    }
+}
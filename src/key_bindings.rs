@@ -0,0 +1,283 @@
+use std::ops::BitOr;
+
+use crate::{AppState, InputRouting, Key, LogicalKey, Window, WindowRole};
+
+//Modifiers a binding wants held. Left and right count the same. They come from the keys held
+//on the seat, not from the keymap, so they're the physical keys: CTRL is the Ctrl keys even on a
+//layout that moved Ctrl elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Mods(u8);
+
+impl Mods {
+    pub const NONE: Mods = Mods(0);
+    pub const CTRL: Mods = Mods(1);
+    pub const SHIFT: Mods = Mods(1 << 1);
+    pub const ALT: Mods = Mods(1 << 2);
+    pub const SUPER: Mods = Mods(1 << 3);
+
+    pub fn contains(self, other: Mods) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn of_key(key: Key) -> Mods {
+        match key {
+            Key::LeftCtrl | Key::RightCtrl => Mods::CTRL,
+            Key::LeftShift | Key::RightShift => Mods::SHIFT,
+            Key::LeftAlt | Key::RightAlt => Mods::ALT,
+            Key::LeftMeta | Key::RightMeta => Mods::SUPER,
+            _ => Mods::NONE,
+        }
+    }
+
+    pub(crate) fn held(keys: impl Iterator<Item = u32>) -> Mods {
+        keys.map(|key| Mods::of_key(Key::from_evdev(key)))
+            .fold(Mods::NONE, |mods, key| mods | key)
+    }
+}
+
+impl BitOr for Mods {
+    type Output = Mods;
+
+    fn bitor(self, other: Mods) -> Mods {
+        Mods(self.0 | other.0)
+    }
+}
+
+//The key of a binding: a physical one (Numpad7, whatever NumLock says), or what a key means
+//(Named(Key::Home) is Home, and Numpad7 with NumLock off), see numpad.rs. A Key is a physical one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BindingKey {
+    Physical(Key),
+    Logical(LogicalKey),
+}
+
+impl From<Key> for BindingKey {
+    fn from(key: Key) -> BindingKey {
+        BindingKey::Physical(key)
+    }
+}
+
+impl From<LogicalKey> for BindingKey {
+    fn from(key: LogicalKey) -> BindingKey {
+        BindingKey::Logical(key)
+    }
+}
+
+impl BindingKey {
+    fn matches(self, key: Key, logical: LogicalKey) -> bool {
+        match self {
+            BindingKey::Physical(physical) => physical == key,
+            BindingKey::Logical(bound) => bound == logical,
+        }
+    }
+}
+
+//What a bound key does.
+pub enum Action {
+    //Same as Window::close.
+    Quit,
+    ToggleFullscreen,
+    //Same as Window::set_diagnostic_overlay, the other way around each time.
+    #[cfg(feature = "diagnostic-overlay")]
+    ToggleDiagnosticOverlay,
+    Custom(Box<dyn FnMut(&mut Window) + Send>),
+}
+
+struct Binding {
+    mods: Mods,
+    key: BindingKey,
+    //Taken out while it runs, a custom action gets the window and could rebind keys meanwhile.
+    action: Option<Action>,
+    repeat: bool,
+}
+
+//Bound keys are taken out of the event stream: their press runs the action (after the dispatch
+//batch, where there is a Window to give it) and neither it, its repeats nor its release show up
+//as WindowEvents. Everything else goes through as before.
+pub(crate) struct KeyBindings {
+    bindings: Vec<Binding>,
+    //Fired, waiting to run.
+    fired: Vec<(Mods, BindingKey)>,
+    //Evdev codes of bound keys held down, whose release is swallowed too.
+    held: Vec<u32>,
+}
+
+//Escape quits unless the application says otherwise, as it always did. With the
+//diagnostic-overlay feature, Ctrl+Shift+F12 shows the overlay, see diagnostic_overlay.rs.
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            bindings: vec![
+                Binding {
+                    mods: Mods::NONE,
+                    key: BindingKey::Physical(Key::Escape),
+                    action: Some(Action::Quit),
+                    repeat: false,
+                },
+                #[cfg(feature = "diagnostic-overlay")]
+                Binding {
+                    mods: Mods::CTRL | Mods::SHIFT,
+                    key: BindingKey::Physical(Key::F12),
+                    action: Some(Action::ToggleDiagnosticOverlay),
+                    repeat: false,
+                },
+            ],
+            fired: Vec::new(),
+            held: Vec::new(),
+        }
+    }
+}
+
+impl KeyBindings {
+    fn find(&self, mods: Mods, key: BindingKey) -> Option<usize> {
+        self.bindings
+            .iter()
+            .position(|binding| binding.mods == mods && binding.key == key)
+    }
+
+    //The binding a key pressed as `logical` fires, physical or logical, whichever was bound first.
+    fn matching(&self, mods: Mods, key: Key, logical: LogicalKey) -> Option<usize> {
+        self.bindings
+            .iter()
+            .position(|binding| binding.mods == mods && binding.key.matches(key, logical))
+    }
+
+    fn bind(&mut self, mods: Mods, key: BindingKey, action: Action, repeat: bool) {
+        let binding = Binding {
+            mods,
+            key,
+            action: Some(action),
+            repeat,
+        };
+        match self.find(mods, key) {
+            Some(index) => self.bindings[index] = binding,
+            None => self.bindings.push(binding),
+        }
+    }
+}
+
+impl AppState {
+    //A press (or, from the EventLoop, a repeat) of `code` on `seat`. True when it's bound, and
+    //so not an event. Logical bindings go by what the key was pressed as, repeats too.
+    pub(crate) fn fire_key_binding(&mut self, seat: &str, code: u32, repeat: bool) -> bool {
+        //Mid-drag, or for a popup's grab, keys aren't the window's to act on, see
+        //input_routing.rs.
+        if self.routing.mode != InputRouting::Normal {
+            return false;
+        }
+        let key = Key::from_evdev(code);
+        let logical = self.held_logical_key(seat, code);
+        let Some(entry) = self.seat_named(seat) else {
+            return false;
+        };
+        //A held key doesn't count as its own modifier.
+        let mods = Mods::held(
+            entry
+                .pressed_keys
+                .iter()
+                .copied()
+                .filter(|&held| held != code),
+        );
+
+        let bindings = &mut self.key_bindings;
+        let Some(index) = bindings.matching(mods, key, logical) else {
+            return false;
+        };
+        let bound = bindings.bindings[index].key;
+        if !repeat {
+            bindings.held.push(code);
+            bindings.fired.push((mods, bound));
+        } else if bindings.bindings[index].repeat {
+            bindings.fired.push((mods, bound));
+        }
+        true
+    }
+
+    //A press the window kept for itself, Escape releasing a pointer constraint: its release is
+    //swallowed like a binding's.
+    pub(crate) fn hold_key(&mut self, code: u32) {
+        self.key_bindings.held.push(code);
+    }
+
+    //A release. True when the press was a binding's.
+    pub(crate) fn release_key_binding(&mut self, code: u32) -> bool {
+        let held = &mut self.key_bindings.held;
+        match held.iter().position(|&key| key == code) {
+            Some(index) => {
+                held.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    //Focus left: the releases won't come.
+    pub(crate) fn forget_held_bindings(&mut self) {
+        self.key_bindings.held.clear();
+    }
+}
+
+impl Window {
+    //Runs `action` when `key` is pressed with no modifier held. Binding a key again replaces
+    //what it did. A Key binds the physical key, a LogicalKey what keys mean (see BindingKey).
+    pub fn bind_key(&mut self, key: impl Into<BindingKey>, action: Action) {
+        self.bind_key_with_mods(Mods::NONE, key, action);
+    }
+
+    //Same with modifiers, which have to be exactly these: CTRL+Q doesn't fire on Ctrl+Shift+Q.
+    pub fn bind_key_with_mods(&mut self, mods: Mods, key: impl Into<BindingKey>, action: Action) {
+        self.state
+            .key_bindings
+            .bind(mods, key.into(), action, false);
+    }
+
+    //Same, also running on every repeat of the key (with the EventLoop, the only one that makes
+    //repeats). Other bindings swallow the repeats.
+    pub fn bind_key_repeating(&mut self, mods: Mods, key: impl Into<BindingKey>, action: Action) {
+        self.state.key_bindings.bind(mods, key.into(), action, true);
+    }
+
+    //The key goes back to being a WindowEvent. unbind_key(Mods::NONE, Key::Escape) is how an
+    //application that has its own use for Escape stops it from quitting.
+    pub fn unbind_key(&mut self, mods: Mods, key: impl Into<BindingKey>) {
+        let bindings = &mut self.state.key_bindings;
+        if let Some(index) = bindings.find(mods, key.into()) {
+            bindings.bindings.remove(index);
+        }
+    }
+
+    pub(crate) fn run_key_bindings(&mut self) {
+        for (mods, key) in std::mem::take(&mut self.state.key_bindings.fired) {
+            let Some(index) = self.state.key_bindings.find(mods, key) else {
+                continue;
+            };
+            let Some(mut action) = self.state.key_bindings.bindings[index].action.take() else {
+                continue;
+            };
+            match action {
+                Action::Quit => self.close(),
+                //A tool palette is never fullscreen on purpose, see utility.rs.
+                Action::ToggleFullscreen if self.state.utility.role == WindowRole::Utility => {}
+                Action::ToggleFullscreen => {
+                    if let Err(err) = self.set_fullscreen(!self.is_fullscreen()) {
+                        log::warn!("{key:?} binding: {err}");
+                    }
+                }
+                #[cfg(feature = "diagnostic-overlay")]
+                Action::ToggleDiagnosticOverlay => {
+                    let shown = self.diagnostic_overlay_shown();
+                    if let Err(err) = self.set_diagnostic_overlay(!shown) {
+                        log::warn!("{key:?} binding: {err}");
+                    }
+                }
+                Action::Custom(ref mut action) => action(self),
+            }
+            //Back in place, unless the action unbound or rebound its own key.
+            if let Some(index) = self.state.key_bindings.find(mods, key)
+                && self.state.key_bindings.bindings[index].action.is_none()
+            {
+                self.state.key_bindings.bindings[index].action = Some(action);
+            }
+        }
+    }
+}
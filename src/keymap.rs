@@ -0,0 +1,238 @@
+use std::{
+    hash::{DefaultHasher, Hasher},
+    os::fd::{AsFd, OwnedFd},
+    ptr, slice,
+    sync::Arc,
+};
+
+use rustix::{
+    fs::fstat,
+    mm::{MapFlags, ProtFlags, mmap, munmap},
+};
+use wayland_client::{
+    WEnum,
+    protocol::{wl_keyboard::KeymapFormat, wl_seat::WlSeat},
+};
+
+use crate::{
+    AppState, Window, keysyms::Symbols, layout::layout_names, numpad::numlock_mask,
+    protocol_log::protocol_log,
+};
+
+//Real keymaps are some tens of kilobytes. A size way past that is a broken compositor, mapping it
+//would only waste address space.
+const MAX_KEYMAP_SIZE: u64 = 16 * 1024 * 1024;
+
+//A seat's keymap, as XKB text. Without one keys are raw evdev keycodes, their physical key as
+//their logical one.
+#[derive(Clone)]
+pub(crate) struct Keymap {
+    //Of the mapped bytes, to tell a keymap sent again apart from a new one.
+    hash: u64,
+    text: Arc<str>,
+    //Layout names by group, see layout.rs.
+    pub(crate) layouts: Vec<Option<Arc<str>>>,
+    //The locked modifier that is NumLock, see numpad.rs.
+    pub(crate) numlock: u32,
+    //What its keys mean, see keysyms.rs.
+    pub(crate) symbols: Arc<Symbols>,
+}
+
+//Copies the keymap out of the fd. Quoting documentation: "From version 7 onwards, the fd must be
+//mapped with MAP_PRIVATE by the recipient, as MAP_SHARED may fail." The size is the compositor's
+//word, the file can be shorter: reading a mapping past the end of its file is a SIGBUS, so the
+//mapping stops at the file's real size.
+fn read_keymap(fd: impl AsFd, size: u32) -> Result<Vec<u8>, String> {
+    let file_size = fstat(&fd).map_err(|err| err.to_string())?.st_size as u64;
+    let len = u64::from(size).min(file_size);
+    if len == 0 {
+        return Err(format!(
+            "empty keymap (size {size}, file {file_size} bytes)"
+        ));
+    }
+    if len > MAX_KEYMAP_SIZE {
+        return Err(format!("keymap of {len} bytes is too big"));
+    }
+
+    let len = len as usize;
+    //SAFETY: a fresh read-only private mapping, read within its length and unmapped right after.
+    unsafe {
+        let data = mmap(
+            ptr::null_mut(),
+            len,
+            ProtFlags::READ,
+            MapFlags::PRIVATE,
+            fd,
+            0,
+        )
+        .map_err(|err| err.to_string())?;
+        let bytes = slice::from_raw_parts(data.cast::<u8>(), len).to_vec();
+        let _ = munmap(data, len);
+        Ok(bytes)
+    }
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+//The text is meant to end with a NUL, but nothing checks that it does: it ends at the first NUL
+//or at the end of the mapping, whichever comes first. What isn't UTF-8 or doesn't start like an
+//XKB keymap isn't one.
+//
+//This is where the keymap gets compiled (xkb_keymap_new_from_string) once xkb is in, and why
+//identical keymaps skip it.
+fn compile(bytes: &[u8]) -> Option<Arc<str>> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let text = std::str::from_utf8(&bytes[..end]).ok()?;
+    text.trim_start()
+        .starts_with("xkb_keymap")
+        .then(|| text.into())
+}
+
+//What the Keymap event leaves: the new keymap, the same one again when the bytes didn't change
+//(compositors resend it on every focus change), or None for raw keycodes.
+pub(crate) fn load_keymap(
+    format: WEnum<KeymapFormat>,
+    fd: OwnedFd,
+    size: u32,
+    current: Option<&Keymap>,
+) -> Option<Keymap> {
+    //Quoting documentation: "no keymap; client must understand how to interpret the raw keycode"
+    if format != WEnum::Value(KeymapFormat::XkbV1) {
+        return None;
+    }
+    let bytes = match read_keymap(fd, size) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("unusable keymap, using raw keycodes: {err}");
+            return None;
+        }
+    };
+
+    let hash = hash(&bytes);
+    if let Some(current) = current.filter(|current| current.hash == hash) {
+        return Some(current.clone());
+    }
+    match compile(&bytes) {
+        Some(text) => Some(Keymap {
+            hash,
+            layouts: layout_names(&text),
+            numlock: numlock_mask(&text),
+            symbols: Arc::new(Symbols::parse(&text)),
+            text,
+        }),
+        None => {
+            log::warn!("keymap isn't XKB text, using raw keycodes");
+            None
+        }
+    }
+}
+
+impl AppState {
+    pub(crate) fn update_keymap(
+        &mut self,
+        seat: &WlSeat,
+        format: WEnum<KeymapFormat>,
+        fd: OwnedFd,
+        size: u32,
+    ) {
+        let name = self.seat_name(seat);
+        let before = self
+            .seat_named(&name)
+            .filter(|entry| entry.keymap.is_some())
+            .and_then(|_| self.seat_layout(&name));
+        let Some(entry) = self.seat_mut(seat) else {
+            return;
+        };
+        entry.keymap = load_keymap(format, fd, size, entry.keymap.as_ref());
+        protocol_log!(
+            "keymap of {size} bytes, {}",
+            if entry.keymap.is_some() {
+                "xkb"
+            } else {
+                "raw keycodes"
+            }
+        );
+        self.keymap_layout_changed(seat, before);
+    }
+}
+
+impl Window {
+    //The XKB keymap of a seat (see seats), for applications doing their own keysym translation.
+    //None when the seat has no usable one and keys are raw keycodes.
+    pub fn keymap(&self, seat: &str) -> Option<Arc<str>> {
+        self.state
+            .seat_named(seat)?
+            .keymap
+            .as_ref()
+            .map(|keymap| keymap.text.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const KEYMAP: &[u8] = b"xkb_keymap {\n\txkb_keycodes \"evdev\" { };\n};\n\0";
+
+    fn fd_with(bytes: &[u8]) -> OwnedFd {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(bytes).unwrap();
+        file.into()
+    }
+
+    fn load(bytes: &[u8], size: u32, current: Option<&Keymap>) -> Option<Keymap> {
+        load_keymap(
+            WEnum::Value(KeymapFormat::XkbV1),
+            fd_with(bytes),
+            size,
+            current,
+        )
+    }
+
+    #[test]
+    fn keymap_is_read_up_to_its_nul() {
+        let keymap = load(KEYMAP, KEYMAP.len() as u32, None).unwrap();
+        assert_eq!(keymap.text.as_bytes(), &KEYMAP[..KEYMAP.len() - 1]);
+    }
+
+    #[test]
+    fn keymap_without_nul_is_fine() {
+        let bytes = &KEYMAP[..KEYMAP.len() - 1];
+        assert!(load(bytes, bytes.len() as u32, None).is_some());
+    }
+
+    //A size past the end of the file would be a SIGBUS if it was trusted.
+    #[test]
+    fn truncated_or_garbage_keymaps_mean_raw_keycodes() {
+        assert!(load(&KEYMAP[..8], KEYMAP.len() as u32, None).is_none());
+        assert!(load(&[0xff, 0xfe, 0x00, 0x13], 4096, None).is_none());
+        assert!(load(b"", 4096, None).is_none());
+        assert!(load(KEYMAP, u32::MAX, None).is_some());
+        assert!(
+            load_keymap(
+                WEnum::Value(KeymapFormat::NoKeymap),
+                fd_with(KEYMAP),
+                KEYMAP.len() as u32,
+                None
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn same_keymap_is_reused() {
+        let first = load(KEYMAP, KEYMAP.len() as u32, None).unwrap();
+        let again = load(KEYMAP, KEYMAP.len() as u32, Some(&first)).unwrap();
+        assert!(Arc::ptr_eq(&first.text, &again.text));
+
+        let other = b"xkb_keymap { };\0";
+        let new = load(other, other.len() as u32, Some(&first)).unwrap();
+        assert!(!Arc::ptr_eq(&first.text, &new.text));
+    }
+}
@@ -0,0 +1,615 @@
+//What keys mean, read from the keymap: the keysyms of each key's levels in each group, and which
+//level the modifiers pick. xkbcommon would do all of it (and more: actions, the keymap's own key
+//types, levels past Shift); until it's in, this reads the keymap text for the parts written the
+//way xkbcommon writes them:
+//
+//  xkb_keycodes  <AD01> = 24;                 the XKB keycode, evdev + 8. Aliases too.
+//  xkb_symbols   key <AD01> { [ q, Q ] };     levels 1 and 2 of group 1.
+//                key <KP7> { type= "KEYPAD", symbols[Group1]= [ KP_Home, KP_7 ] };
+//
+//The key type picks the level: KEYPAD by NumLock and Shift (Level2 with one of them, not both),
+//ALPHABETIC by Shift and CapsLock the same way, ONE_LEVEL never, any other by Shift alone. A key
+//without a type gets the one XKB would give it: ONE_LEVEL for one keysym, ALPHABETIC for a lower
+//and upper case letter, KEYPAD when a keypad keysym is in there, TWO_LEVEL otherwise.
+//
+//Keysyms are known by name for ASCII, the keypad, navigation, editing, function and media keys,
+//and as Unicode (U20AC, 0x10020ac). Others (Cyrillic_ef, adiaeresis...) need xkbcommon's table
+//and mean their physical key, as keys do without a keymap.
+
+use std::collections::HashMap;
+
+use crate::{Key, LogicalKey};
+
+//evdev codes are XKB keycodes minus this.
+const EVDEV_OFFSET: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyType {
+    OneLevel,
+    TwoLevel,
+    Alphabetic,
+    Keypad,
+}
+
+//The state that picks a level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Levels {
+    pub(crate) shift: bool,
+    pub(crate) caps_lock: bool,
+    pub(crate) num_lock: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Group {
+    kind: KeyType,
+    //By level, None for keysyms without a meaning here (NoSymbol, unknown names).
+    levels: Vec<Option<LogicalKey>>,
+}
+
+//The keys of a keymap, by evdev code.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Symbols {
+    keys: HashMap<u32, Vec<Option<Group>>>,
+}
+
+impl Symbols {
+    pub(crate) fn parse(text: &str) -> Symbols {
+        let codes = keycodes(section(text, "xkb_keycodes").unwrap_or_default());
+        let mut keys = HashMap::new();
+        let Some(symbols) = section(text, "xkb_symbols") else {
+            return Symbols { keys };
+        };
+        let mut rest = symbols;
+        while let Some(at) = find_word(rest, "key") {
+            rest = rest[at + "key".len()..].trim_start();
+            let Some((name, after)) = rest.strip_prefix('<').and_then(|name| name.split_once('>'))
+            else {
+                continue;
+            };
+            let Some(body) = braced(after) else {
+                continue;
+            };
+            rest = &after[body.len()..];
+            if let Some(&code) = codes.get(name) {
+                let code = code.saturating_sub(EVDEV_OFFSET);
+                keys.insert(code, key_groups(body, Key::from_evdev(code)));
+            }
+        }
+        Symbols { keys }
+    }
+
+    //What the key of evdev `code` means in `group` with `levels`. Named(the physical key) for a
+    //key the keymap doesn't say anything about.
+    pub(crate) fn logical_key(&self, code: u32, group: u32, levels: Levels) -> LogicalKey {
+        let physical = LogicalKey::Named(Key::from_evdev(code));
+        let Some(groups) = self.keys.get(&code).filter(|groups| !groups.is_empty()) else {
+            return physical;
+        };
+        //A group the key doesn't have wraps around, XKB's default.
+        let Some(group) = &groups[group as usize % groups.len()] else {
+            return physical;
+        };
+        let second = match group.kind {
+            KeyType::OneLevel => false,
+            KeyType::TwoLevel => levels.shift,
+            KeyType::Alphabetic => levels.shift != levels.caps_lock,
+            KeyType::Keypad => levels.shift != levels.num_lock,
+        };
+        let level = if second && group.levels.len() > 1 {
+            1
+        } else {
+            0
+        };
+        group.levels[level].unwrap_or(physical)
+    }
+}
+
+//The body of `section "name" { ... }`.
+fn section<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let at = find_word(text, name)?;
+    let rest = &text[at + name.len()..];
+    let open = rest.find('{')?;
+    braced(&rest[open..])
+}
+
+//Where `word` is in `text` on its own, not as part of a longer name.
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut from = 0;
+    while let Some(at) = text[from..].find(word).map(|at| at + from) {
+        let end = at + word.len();
+        if !text[..at].ends_with(is_name) && !text[end..].starts_with(is_name) {
+            return Some(at);
+        }
+        from = end;
+    }
+    None
+}
+
+//From the `{` starting `text` (after spaces) to its matching `}`, both included.
+fn braced(text: &str) -> Option<&str> {
+    let start = text.len() - text.trim_start().len();
+    if !text[start..].starts_with('{') {
+        return None;
+    }
+    let mut depth = 0;
+    for (at, c) in text[start..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[..start + at + 1]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+//`<AD01> = 24;` and `alias <AB11> = <AC11>;`, by name.
+fn keycodes(section: &str) -> HashMap<&str, u32> {
+    let mut codes = HashMap::new();
+    let mut aliases = Vec::new();
+    for statement in section.split(';') {
+        let statement = statement.trim().trim_start_matches('{').trim();
+        let (alias, statement) = match statement.strip_prefix("alias") {
+            Some(rest) => (true, rest.trim()),
+            None => (false, statement),
+        };
+        let Some((name, value)) = statement.split_once('=') else {
+            continue;
+        };
+        let (Some(name), value) = (angled(name), value.trim()) else {
+            continue;
+        };
+        if alias {
+            if let Some(target) = angled(value) {
+                aliases.push((name, target));
+            }
+        } else if let Ok(code) = value.parse() {
+            codes.insert(name, code);
+        }
+    }
+    for (alias, target) in aliases {
+        if let Some(&code) = codes.get(target) {
+            codes.insert(alias, code);
+        }
+    }
+    codes
+}
+
+//`<AD01>` as AD01.
+fn angled(text: &str) -> Option<&str> {
+    text.trim().strip_prefix('<')?.strip_suffix('>')
+}
+
+//The entries of a key's `{ ... }`, split at the commas outside of brackets and parentheses.
+fn entries(body: &str) -> Vec<&str> {
+    let body = body.trim();
+    let body = body
+        .strip_prefix('{')
+        .and_then(|body| body.strip_suffix('}'))
+        .unwrap_or(body);
+    let mut entries = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (at, c) in body.char_indices() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                entries.push(body[start..at].trim());
+                start = at + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(body[start..].trim());
+    entries
+}
+
+//`[Group2]` (of `symbols[Group2]`) as 1, no brackets as the first group.
+fn group_of(name: &str) -> Option<usize> {
+    let Some(index) = name.split_once('[') else {
+        return Some(0);
+    };
+    let index = index.1.trim().strip_suffix(']')?.trim();
+    let number = index
+        .get(..5)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("group"))
+        .map_or(index, |_| &index[5..]);
+    //XKB has four groups at most.
+    number
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (1..=4).contains(n))
+        .map(|n| n - 1)
+}
+
+fn key_groups(body: &str, physical: Key) -> Vec<Option<Group>> {
+    fn set<T: Clone>(list: &mut Vec<Option<T>>, group: usize, value: T) {
+        if list.len() <= group {
+            list.resize(group + 1, None);
+        }
+        list[group] = Some(value);
+    }
+    let mut names: Vec<Option<Vec<&str>>> = Vec::new();
+    let mut types: Vec<Option<&str>> = Vec::new();
+    for entry in entries(body) {
+        if entry.starts_with('[') {
+            set(&mut names, 0, keysym_list(entry));
+            continue;
+        }
+        let Some((field, value)) = entry.split_once('=') else {
+            continue;
+        };
+        let field = field.trim();
+        let Some(group) = group_of(field) else {
+            continue;
+        };
+        let value = value.trim();
+        if field.starts_with("symbols") {
+            set(&mut names, group, keysym_list(value));
+        } else if field.starts_with("type") {
+            set(&mut types, group, value.trim_matches('"'));
+        }
+    }
+    //A type without a group is every group's.
+    let shared = types.first().copied().flatten();
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(group, names)| {
+            let names = names?;
+            let kind = match types.get(group).copied().flatten().or(shared) {
+                Some(name) => named_type(name),
+                None => inferred_type(&names),
+            };
+            Some(Group {
+                kind,
+                levels: names
+                    .iter()
+                    .map(|&name| logical_keysym(name, physical))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+//`[ KP_Home, KP_7 ]` as its names.
+fn keysym_list(list: &str) -> Vec<&str> {
+    list.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(str::trim)
+        .collect()
+}
+
+fn named_type(name: &str) -> KeyType {
+    if name.contains("KEYPAD") {
+        KeyType::Keypad
+    } else if name.contains("ALPHABETIC") {
+        KeyType::Alphabetic
+    } else if name == "ONE_LEVEL" {
+        KeyType::OneLevel
+    } else {
+        KeyType::TwoLevel
+    }
+}
+
+fn inferred_type(names: &[&str]) -> KeyType {
+    let letter = |name: &str| {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .filter(|c| c.is_alphabetic() && chars.next().is_none())
+    };
+    match names {
+        [_] => KeyType::OneLevel,
+        _ if names.iter().any(|name| name.starts_with("KP_")) => KeyType::Keypad,
+        [lower, upper, ..]
+            if letter(lower).is_some_and(char::is_lowercase)
+                && letter(upper).is_some_and(char::is_uppercase) =>
+        {
+            KeyType::Alphabetic
+        }
+        _ => KeyType::TwoLevel,
+    }
+}
+
+//What a keysym name means. `physical` is what KP_Begin means, no other key is where it is.
+fn logical_keysym(name: &str, physical: Key) -> Option<LogicalKey> {
+    let character = LogicalKey::Character;
+    let named = LogicalKey::Named;
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(character(c));
+    }
+    if let Some(c) = unicode(name) {
+        return Some(character(c));
+    }
+    if let Some(c) = name
+        .strip_prefix("KP_")
+        .and_then(|digit| digit.parse::<u32>().ok())
+        .and_then(|digit| char::from_digit(digit, 10))
+    {
+        return Some(character(c));
+    }
+    if let Some(&(_, c)) = PUNCTUATION.iter().find(|&&(keysym, _)| keysym == name) {
+        return Some(character(c));
+    }
+    if let Some(&(_, key)) = NAMED.iter().find(|&&(keysym, _)| keysym == name) {
+        return Some(named(key));
+    }
+    if let Some(number) = name.strip_prefix('F').and_then(|n| n.parse::<u32>().ok())
+        && (1..=12).contains(&number)
+    {
+        //F1 to F10 are evdev 59 to 68, F11 and F12 87 and 88.
+        let code = if number <= 10 {
+            58 + number
+        } else {
+            76 + number
+        };
+        return Some(named(Key::from_evdev(code)));
+    }
+    (name == "KP_Begin").then_some(named(physical))
+}
+
+//`U20AC`, and `0x10020ac` (Unicode keysyms are 0x1000000 + the code point).
+fn unicode(name: &str) -> Option<char> {
+    let code = if let Some(hex) = name.strip_prefix('U') {
+        u32::from_str_radix(hex, 16).ok()?
+    } else {
+        let keysym = u32::from_str_radix(name.strip_prefix("0x")?, 16).ok()?;
+        keysym.checked_sub(0x0100_0000)?
+    };
+    char::from_u32(code).filter(|c| !c.is_control())
+}
+
+const PUNCTUATION: [(&str, char); 44] = [
+    ("space", ' '),
+    ("exclam", '!'),
+    ("quotedbl", '"'),
+    ("numbersign", '#'),
+    ("dollar", '$'),
+    ("percent", '%'),
+    ("ampersand", '&'),
+    ("apostrophe", '\''),
+    ("parenleft", '('),
+    ("parenright", ')'),
+    ("asterisk", '*'),
+    ("plus", '+'),
+    ("comma", ','),
+    ("minus", '-'),
+    ("period", '.'),
+    ("slash", '/'),
+    ("colon", ':'),
+    ("semicolon", ';'),
+    ("less", '<'),
+    ("equal", '='),
+    ("greater", '>'),
+    ("question", '?'),
+    ("at", '@'),
+    ("bracketleft", '['),
+    ("backslash", '\\'),
+    ("bracketright", ']'),
+    ("asciicircum", '^'),
+    ("underscore", '_'),
+    ("grave", '`'),
+    ("braceleft", '{'),
+    ("bar", '|'),
+    ("braceright", '}'),
+    ("asciitilde", '~'),
+    ("KP_Space", ' '),
+    ("KP_Decimal", '.'),
+    ("KP_Separator", ','),
+    ("KP_Divide", '/'),
+    ("KP_Multiply", '*'),
+    ("KP_Subtract", '-'),
+    ("KP_Add", '+'),
+    ("KP_Equal", '='),
+    ("sterling", '£'),
+    ("section", '§'),
+    ("degree", '°'),
+];
+
+const NAMED: [(&str, Key); 49] = [
+    ("Escape", Key::Escape),
+    ("BackSpace", Key::Backspace),
+    ("Tab", Key::Tab),
+    ("ISO_Left_Tab", Key::Tab),
+    ("Return", Key::Enter),
+    ("KP_Enter", Key::Enter),
+    ("Home", Key::Home),
+    ("KP_Home", Key::Home),
+    ("End", Key::End),
+    ("KP_End", Key::End),
+    ("Up", Key::Up),
+    ("KP_Up", Key::Up),
+    ("Down", Key::Down),
+    ("KP_Down", Key::Down),
+    ("Left", Key::Left),
+    ("KP_Left", Key::Left),
+    ("Right", Key::Right),
+    ("KP_Right", Key::Right),
+    ("Prior", Key::PageUp),
+    ("KP_Prior", Key::PageUp),
+    ("Next", Key::PageDown),
+    ("KP_Next", Key::PageDown),
+    ("Insert", Key::Insert),
+    ("KP_Insert", Key::Insert),
+    ("Delete", Key::Delete),
+    ("KP_Delete", Key::Delete),
+    ("Shift_L", Key::LeftShift),
+    ("Shift_R", Key::RightShift),
+    ("Control_L", Key::LeftCtrl),
+    ("Control_R", Key::RightCtrl),
+    ("Alt_L", Key::LeftAlt),
+    ("Alt_R", Key::RightAlt),
+    ("ISO_Level3_Shift", Key::RightAlt),
+    ("Super_L", Key::LeftMeta),
+    ("Super_R", Key::RightMeta),
+    ("Menu", Key::Menu),
+    ("Caps_Lock", Key::CapsLock),
+    ("Num_Lock", Key::NumLock),
+    ("Scroll_Lock", Key::ScrollLock),
+    ("Print", Key::PrintScreen),
+    ("Pause", Key::Pause),
+    ("XF86AudioMute", Key::Mute),
+    ("XF86AudioLowerVolume", Key::VolumeDown),
+    ("XF86AudioRaiseVolume", Key::VolumeUp),
+    ("XF86AudioNext", Key::NextSong),
+    ("XF86AudioPlay", Key::PlayPause),
+    ("XF86AudioPause", Key::PlayPause),
+    ("XF86AudioPrev", Key::PreviousSong),
+    ("XF86AudioStop", Key::StopMedia),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Trimmed from what xkbcommon writes for "us,ru", the way it writes it.
+    const KEYMAP: &str = r#"xkb_keymap {
+xkb_keycodes "evdev+aliases(qwerty)" {
+	minimum = 8;
+	maximum = 255;
+	<ESC> = 9;
+	<AE01> = 10;
+	<AD01> = 24;
+	<RTRN> = 36;
+	<AC01> = 38;
+	<LFSH> = 50;
+	<KPMU> = 63;
+	<FK01> = 67;
+	<NMLK> = 77;
+	<KP7> = 79;
+	<KP5> = 84;
+	<KPEN> = 104;
+	<LVL3> = 92;
+	alias <MDSW> = <LVL3>;
+	indicator 2 = "Num Lock";
+};
+xkb_types "complete" {
+	type "KEYPAD" {
+		modifiers= Shift+NumLock;
+		map[Shift]= Level2;
+		map[NumLock]= Level2;
+	};
+};
+xkb_symbols "pc+us+ru:2+inet(evdev)" {
+	name[Group1]="English (US)";
+	name[Group2]="Russian";
+	key <ESC>                {	[          Escape ] };
+	key <AE01>               {	[               1,          exclam ],
+		symbols[Group2]= [ 1, exclam ] };
+	key <AD01>               {
+		symbols[Group1]= [               q,               Q ],
+		symbols[Group2]= [     Cyrillic_shorti,     Cyrillic_SHORTI ]
+	};
+	key <RTRN>               {	[          Return ] };
+	key <AC01>               {	[               a,               A ] };
+	key <LFSH>               {	[         Shift_L ] };
+	key <KPMU>               {
+		type= "CTRL+ALT",
+		symbols[Group1]= [     KP_Multiply,     KP_Multiply,     KP_Multiply,
+		                       KP_Multiply,   XF86ClearGrab ]
+	};
+	key <FK01>               {
+		type= "CTRL+ALT",
+		symbols[Group1]= [              F1,              F1,              F1,
+		                                F1, XF86Switch_VT_1 ]
+	};
+	key <NMLK>               {	[        Num_Lock ] };
+	key <KP7>                {	[         KP_Home,            KP_7 ] };
+	key <KP5>                {	[        KP_Begin,            KP_5 ] };
+	key <KPEN>               {	[        KP_Enter ] };
+	key <MDSW>               {	[     Mode_switch ] };
+	modifier_map Shift { <LFSH> };
+	modifier_map Mod2 { <NMLK> };
+};
+};
+"#;
+
+    fn key(symbols: &Symbols, key: Key, group: u32, levels: Levels) -> LogicalKey {
+        symbols.logical_key(key.evdev(), group, levels)
+    }
+
+    #[test]
+    fn keycodes_and_aliases() {
+        let codes = keycodes(section(KEYMAP, "xkb_keycodes").unwrap());
+        assert_eq!(codes["AD01"], 24);
+        assert_eq!(codes["MDSW"], 92);
+        assert!(!codes.contains_key("minimum"));
+    }
+
+    #[test]
+    fn levels_by_key_type() {
+        let symbols = Symbols::parse(KEYMAP);
+        let shift = Levels {
+            shift: true,
+            ..Levels::default()
+        };
+        let caps = Levels {
+            caps_lock: true,
+            ..Levels::default()
+        };
+        let none = Levels::default();
+        //ALPHABETIC, worked out from the letters.
+        assert_eq!(key(&symbols, Key::Q, 0, none), LogicalKey::Character('q'));
+        assert_eq!(key(&symbols, Key::Q, 0, shift), LogicalKey::Character('Q'));
+        assert_eq!(key(&symbols, Key::Q, 0, caps), LogicalKey::Character('Q'));
+        let both = Levels {
+            shift: true,
+            ..caps
+        };
+        assert_eq!(key(&symbols, Key::A, 0, both), LogicalKey::Character('a'));
+        //TWO_LEVEL: CapsLock doesn't count.
+        assert_eq!(
+            key(&symbols, Key::Digit1, 0, caps),
+            LogicalKey::Character('1')
+        );
+        assert_eq!(
+            key(&symbols, Key::Digit1, 0, shift),
+            LogicalKey::Character('!')
+        );
+        //ONE_LEVEL and named keysyms.
+        assert_eq!(
+            key(&symbols, Key::Enter, 0, shift),
+            LogicalKey::Named(Key::Enter)
+        );
+        assert_eq!(key(&symbols, Key::F1, 0, shift), LogicalKey::Named(Key::F1));
+        assert_eq!(
+            key(&symbols, Key::NumpadMultiply, 0, shift),
+            LogicalKey::Character('*')
+        );
+    }
+
+    #[test]
+    fn groups_and_unknown_keysyms() {
+        let symbols = Symbols::parse(KEYMAP);
+        let none = Levels::default();
+        //Cyrillic_shorti isn't known by name: the physical key, in the second group.
+        assert_eq!(key(&symbols, Key::Q, 1, none), LogicalKey::Named(Key::Q));
+        assert_eq!(
+            key(&symbols, Key::Digit1, 1, none),
+            LogicalKey::Character('1')
+        );
+        //One group only: group 2 wraps around to it.
+        assert_eq!(key(&symbols, Key::A, 1, none), LogicalKey::Character('a'));
+        //Not in the keymap at all.
+        assert_eq!(key(&symbols, Key::Z, 0, none), LogicalKey::Named(Key::Z));
+        assert_eq!(
+            logical_keysym("U20AC", Key::E),
+            Some(LogicalKey::Character('€'))
+        );
+        assert_eq!(
+            logical_keysym("0x10020ac", Key::E),
+            Some(LogicalKey::Character('€'))
+        );
+        assert_eq!(logical_keysym("NoSymbol", Key::E), None);
+        assert_eq!(Symbols::parse("xkb_keymap { };"), Symbols::default());
+    }
+}
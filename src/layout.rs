@@ -0,0 +1,201 @@
+//Keyboard layouts: an XKB keymap has up to four groups (us and ru, say), the compositor switches
+//between them (a shortcut, a panel menu) and says which one is active with wl_keyboard.modifiers.
+//Quoting documentation: "Notifies clients that the modifier and/or group state has changed, and
+//it should update its local state."
+//
+//The group is all the window takes from that event, per seat. A change is a
+//WindowEvent::KeyboardLayoutChanged; so is a new keymap changing the name of the active layout
+//(the layout list edited in the settings), but not the first keymap.
+//
+//Names are read from the keymap text, the `name[GroupN]="..."` lines of its xkb_symbols section,
+//which is how xkbcommon writes them. A keymap without them (or no keymap) has nameless layouts.
+//
+//Key events carry the group their key was pressed with: that's the group of the seat's keymap
+//their `code` translates with. A release always has its press's group, even when the layout
+//changed while the key was down (switching with a shortcut and releasing its last key), so the
+//pair never comes apart: an `a` pressed on us doesn't come up as `ф`.
+
+use std::sync::Arc;
+
+use wayland_client::protocol::wl_seat::WlSeat;
+
+use crate::{AppState, Window, WindowEvent, protocol_log::protocol_log};
+
+//A layout of a seat's keymap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutInfo {
+    //The XKB group, from 0.
+    pub index: u32,
+    //As the keymap names it ("English (US)"), None when it doesn't.
+    pub name: Option<Arc<str>>,
+}
+
+//Layout names by group, from the keymap's xkb_symbols section. Groups without a name are None.
+pub(crate) fn layout_names(text: &str) -> Vec<Option<Arc<str>>> {
+    let mut names = Vec::new();
+    let Some(start) = text.find("xkb_symbols") else {
+        return names;
+    };
+    let symbols = &text[start..];
+    let mut rest = symbols;
+    while let Some(at) = rest.find("name[") {
+        //Not level_name[...] of a key type.
+        let whole_word =
+            at == 0 || !rest[..at].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_');
+        rest = &rest[at + "name[".len()..];
+        if !whole_word {
+            continue;
+        }
+        if let Some((group, name)) = name_entry(rest) {
+            if names.len() < group {
+                names.resize(group, None);
+            }
+            names[group - 1] = Some(name.into());
+        }
+    }
+    names
+}
+
+//`Group2]="Russian"` (after `name[`) as (2, "Russian").
+fn name_entry(entry: &str) -> Option<(usize, &str)> {
+    let (group, rest) = entry.split_once(']')?;
+    let group = group.trim();
+    let number = group
+        .get(..5)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("group"))
+        .map_or(group, |_| &group[5..]);
+    //XKB has four groups at most.
+    let group: usize = number.parse().ok().filter(|n| (1..=4).contains(n))?;
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let name = rest.strip_prefix('"')?.split('"').next()?;
+    Some((group, name))
+}
+
+impl AppState {
+    //The active layout of a seat.
+    pub(crate) fn seat_layout(&self, seat: &str) -> Option<LayoutInfo> {
+        let entry = self.seat_named(seat)?;
+        let name = entry
+            .keymap
+            .as_ref()
+            .and_then(|keymap| keymap.layouts.get(entry.layout as usize).cloned())
+            .flatten();
+        Some(LayoutInfo {
+            index: entry.layout,
+            name,
+        })
+    }
+
+    //wl_keyboard.modifiers, for its group.
+    pub(crate) fn update_layout(&mut self, seat: &WlSeat, group: u32) {
+        let Some(entry) = self.seat_mut(seat) else {
+            return;
+        };
+        if entry.layout == group {
+            return;
+        }
+        entry.layout = group;
+        let name = self.seat_name(seat);
+        let layout = self.seat_layout(&name).unwrap();
+        protocol_log!("keyboard layout {group} ({:?})", layout.name);
+        self.events
+            .push(WindowEvent::KeyboardLayoutChanged { seat: name, layout });
+    }
+
+    //A new keymap came: same group, maybe another name. `before` is the layout with the old
+    //keymap, None when there was none.
+    pub(crate) fn keymap_layout_changed(&mut self, seat: &WlSeat, before: Option<LayoutInfo>) {
+        let name = self.seat_name(seat);
+        let (Some(before), Some(layout)) = (before, self.seat_layout(&name)) else {
+            return;
+        };
+        if before != layout {
+            self.events
+                .push(WindowEvent::KeyboardLayoutChanged { seat: name, layout });
+        }
+    }
+
+    //The group of a Key event: the active one for a press, the press's for a release.
+    pub(crate) fn key_layout(&mut self, seat: &WlSeat, key: u32, pressed: bool) -> u32 {
+        let Some(entry) = self.seat_mut(seat) else {
+            return 0;
+        };
+        let at_press = entry
+            .key_layouts
+            .iter()
+            .position(|&(held, _)| held == key)
+            .map(|index| entry.key_layouts.swap_remove(index).1);
+        if pressed {
+            entry.key_layouts.push((key, entry.layout));
+            entry.layout
+        } else {
+            at_press.unwrap_or(entry.layout)
+        }
+    }
+}
+
+impl Window {
+    //The active layout of a seat (see seats), None for a seat that isn't there. Index 0 until the
+    //compositor says otherwise.
+    pub fn current_layout(&self, seat: &str) -> Option<LayoutInfo> {
+        self.state.seat_layout(seat)
+    }
+
+    //All layouts of a seat's keymap, by group. Empty without a keymap or names in it.
+    pub fn layouts(&self, seat: &str) -> Vec<LayoutInfo> {
+        let Some(keymap) = self
+            .state
+            .seat_named(seat)
+            .and_then(|entry| entry.keymap.as_ref())
+        else {
+            return Vec::new();
+        };
+        keymap
+            .layouts
+            .iter()
+            .enumerate()
+            .map(|(index, name)| LayoutInfo {
+                index: index as u32,
+                name: name.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Trimmed from what xkbcommon writes for "us,ru".
+    const KEYMAP: &str = r#"xkb_keymap {
+xkb_keycodes "evdev+aliases(qwerty)" { minimum = 8; };
+xkb_types "complete" {
+	type "TWO_LEVEL" {
+		level_name[Level1]= "Base";
+		level_name[Level2]= "Shift";
+	};
+};
+xkb_symbols "pc+us+ru:2+inet(evdev)" {
+	name[Group1]="English (US)";
+	name[Group2]="Russian";
+	key <AC01> { [ a, A ], [ Cyrillic_ef, Cyrillic_EF ] };
+};
+};
+"#;
+
+    #[test]
+    fn names_come_from_the_symbols_section() {
+        assert_eq!(
+            layout_names(KEYMAP),
+            [Some("English (US)".into()), Some("Russian".into())]
+        );
+        assert_eq!(
+            layout_names("xkb_symbols { name[group3] = \"Greek\"; };"),
+            [None, None, Some("Greek".into())]
+        );
+        assert!(
+            layout_names("xkb_keymap { xkb_types { level_name[Level1]=\"Base\"; }; };").is_empty()
+        );
+        assert!(layout_names("xkb_symbols { name[Group9]=\"x\"; name[Group1]=; };").is_empty());
+    }
+}
@@ -0,0 +1,2096 @@
+use std::{os::fd::AsFd, path::PathBuf, sync::Arc, time::Duration};
+
+#[cfg(feature = "diagnostic-overlay")]
+use wayland_client::protocol::wl_subcompositor::WlSubcompositor;
+use wayland_client::{
+    Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+    backend::ReadEventsGuard,
+    delegate_noop,
+    protocol::{
+        wl_buffer, wl_compositor, wl_keyboard, wl_output, wl_region, wl_registry,
+        wl_seat::{self},
+        wl_shm, wl_shm_pool, wl_surface,
+    },
+};
+#[cfg(feature = "color-management")]
+use wayland_protocols::wp::color_management::v1::client::wp_color_manager_v1::WpColorManagerV1;
+#[cfg(feature = "dmabuf")]
+use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_dmabuf_v1;
+#[cfg(feature = "explicit-sync")]
+use wayland_protocols::wp::linux_drm_syncobj::v1::client::wp_linux_drm_syncobj_manager_v1;
+use wayland_protocols::{
+    ext::idle_notify::v1::client::ext_idle_notifier_v1::ExtIdleNotifierV1,
+    wp::{
+        alpha_modifier::v1::client::wp_alpha_modifier_v1,
+        content_type::v1::client::wp_content_type_manager_v1,
+        cursor_shape::v1::client::wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+        pointer_constraints::zv1::client::zwp_pointer_constraints_v1,
+        pointer_gestures::zv1::client::zwp_pointer_gestures_v1,
+        presentation_time::client::wp_presentation::WpPresentation,
+        relative_pointer::zv1::client::zwp_relative_pointer_manager_v1,
+        single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1,
+        viewporter::client::wp_viewporter,
+    },
+    xdg::{
+        activation::v1::client::xdg_activation_v1::XdgActivationV1,
+        dialog::v1::client::xdg_wm_dialog_v1::XdgWmDialogV1,
+        foreign::zv2::client::{
+            zxdg_exporter_v2::ZxdgExporterV2, zxdg_importer_v2::ZxdgImporterV2,
+        },
+        shell::client::{
+            xdg_surface,
+            xdg_toplevel::{self, XdgToplevel},
+            xdg_wm_base,
+        },
+        toplevel_icon::v1::client::xdg_toplevel_icon_manager_v1,
+    },
+};
+
+mod activation;
+mod alpha_modifier;
+mod app_id;
+mod backpressure;
+mod buffer_allocator;
+mod buffer_layout;
+mod buffer_transform;
+mod button_repeat;
+mod canvas;
+mod capabilities;
+mod capture;
+mod cleanup;
+#[cfg(feature = "color-management")]
+mod color_management;
+mod connect;
+mod content_type;
+mod damage;
+#[cfg(feature = "diagnostic-overlay")]
+mod diagnostic_overlay;
+mod diagnostics;
+mod dialog;
+#[cfg(feature = "dmabuf")]
+mod dmabuf;
+mod drag;
+#[cfg(feature = "egl")]
+mod egl;
+mod error;
+#[cfg(feature = "calloop")]
+mod event_loop;
+#[cfg(feature = "async")]
+mod event_stream;
+#[cfg(feature = "explicit-sync")]
+mod explicit_sync;
+mod external_loop;
+mod flush;
+mod foreign;
+mod frame;
+mod frame_skip;
+mod geometry;
+mod gestures;
+mod globals;
+mod hidden_buffers;
+mod hit_test;
+mod icon;
+mod idle;
+#[cfg(feature = "image")]
+mod image_content;
+mod input_region;
+mod input_routing;
+mod key;
+mod key_bindings;
+mod keymap;
+mod keysyms;
+mod layout;
+mod lifecycle;
+mod mapped_pool;
+mod motion;
+mod numpad;
+mod output;
+mod pixel_convert;
+mod pointer;
+mod preferred;
+mod protocol_log;
+mod protocol_objects;
+mod reconnect;
+#[cfg(feature = "record")]
+mod record;
+mod refresh;
+mod region;
+mod relative_pointer;
+mod render_thread;
+mod renderer;
+mod resize_content;
+mod resize_preview;
+mod rows;
+mod scale_math;
+mod scroll;
+mod seat;
+mod serials;
+mod side_queue;
+mod size_persistence;
+mod sizing;
+mod solid_color;
+mod startup;
+mod state_snapshot;
+mod swapchain;
+#[cfg(feature = "test-util")]
+mod test_window;
+#[cfg(feature = "text")]
+mod text;
+mod title;
+mod toplevel;
+mod transaction;
+mod user_data;
+mod user_events;
+mod utility;
+mod viewport;
+mod watchdog;
+#[cfg(feature = "raw-window-handle")]
+mod window_handle;
+
+pub use activation::Spawned;
+#[cfg(feature = "desktop-file-check")]
+pub use app_id::find_desktop_file;
+pub use app_id::{default_app_id, validate_app_id};
+pub use buffer_transform::Transform;
+pub use canvas::{Canvas, Color};
+pub use capabilities::Capabilities;
+#[cfg(feature = "color-management")]
+pub use color_management::{
+    ColorCapabilities, ColorDescription, ColorFeature, ColorInfo, Luminances, Primaries,
+    RenderIntent, TransferFunction,
+};
+pub use connect::ConnectOptions;
+pub use content_type::ContentType;
+pub use diagnostics::ProtocolErrorInfo;
+#[cfg(feature = "dmabuf")]
+pub use dmabuf::{DmabufFormat, DmabufPlane, DmabufRequest};
+pub use drag::{DragConfig, DragDetector, DragOutcome};
+pub use error::WindowError;
+#[cfg(feature = "calloop")]
+pub use event_loop::{EventLoop, LoopHandle, SourceToken, TimeoutAction};
+#[cfg(feature = "async")]
+pub use event_stream::EventStream;
+#[cfg(feature = "explicit-sync")]
+pub use explicit_sync::{SyncTimeline, SyncedBuffer};
+pub use flush::FlushPolicy;
+pub use frame::ConfigureStats;
+pub use geometry::{Decorations, Margins, TiledEdges};
+pub use gestures::GestureEvent;
+pub use hit_test::{DecorationLayout, HitRegion, ResizeEdge};
+pub use icon::IconData;
+pub use idle::IdleToken;
+#[cfg(feature = "image")]
+pub use image_content::Filter;
+pub use input_routing::InputRouting;
+pub use key::{Key, KeyState};
+pub use key_bindings::{Action, BindingKey, Mods};
+pub use layout::LayoutInfo;
+pub use lifecycle::Lifecycle;
+pub use motion::MotionCoalescing;
+pub use numpad::LogicalKey;
+pub use output::OutputInfo;
+pub use pixel_convert::convert_rgba_to;
+#[cfg(feature = "record")]
+pub use record::{Recorded, Recording};
+pub use refresh::RefreshSource;
+pub use region::Rect;
+pub use render_thread::{EventSide, FrameStats, PresentMode, RenderSide};
+pub use renderer::{Background, Renderer};
+pub use resize_content::ResizeContent;
+pub use scale_math::ScaleMath;
+pub use scroll::{ScrollConfig, ScrollSource};
+pub use serials::SerialKind;
+pub use side_queue::SideDispatch;
+pub use startup::StartupTiming;
+pub use state_snapshot::WindowStateSnapshot;
+pub use swapchain::SwapchainConfig;
+#[cfg(feature = "test-util")]
+pub use test_window::{CapturedFrame, TEST_SEAT, TestWindow};
+#[cfg(feature = "text")]
+pub use text::Font;
+pub use toplevel::WmCapabilities;
+pub use transaction::SurfaceTransaction;
+pub use user_events::{EventLoopProxy, UserEvent};
+pub use utility::WindowRole;
+pub use watchdog::{LoopStats, SlowFrameCause};
+
+use activation::ActivationState;
+use alpha_modifier::AlphaModifierState;
+use backpressure::BackpressureState;
+use buffer_allocator::SharedAllocator;
+use buffer_layout::BufferLayout;
+use button_repeat::ButtonRepeatState;
+use canvas::MappedFile;
+use content_type::ContentTypeState;
+#[cfg(feature = "diagnostic-overlay")]
+use diagnostic_overlay::DiagnosticOverlayState;
+use diagnostics::DiagnosticsState;
+use dialog::DialogState;
+use flush::FlushState;
+use foreign::ForeignState;
+use frame_skip::FrameSkipState;
+use geometry::GeometryState;
+use gestures::GestureState;
+use globals::GlobalsState;
+use hidden_buffers::HiddenBuffersState;
+use hit_test::HitTestState;
+use icon::IconState;
+use idle::IdleState;
+use input_region::InputRegionState;
+use input_routing::InputRoutingState;
+use key_bindings::KeyBindings;
+use lifecycle::{LifecycleState, SurfaceRequest};
+use mapped_pool::MappedPool;
+use motion::MotionState;
+use output::OutputsState;
+use pointer::PointerState;
+use preferred::PreferredState;
+#[cfg(feature = "protocol-log")]
+use protocol_log::FrameSpan;
+use protocol_log::protocol_log;
+use refresh::RefreshState;
+use relative_pointer::RelativePointerState;
+use render_thread::RenderThreadState;
+use renderer::RendererState;
+use resize_preview::ResizePreviewState;
+use rows::RowsState;
+use scroll::ScrollState;
+use seat::SeatsState;
+use serials::SerialsState;
+use side_queue::SideQueueState;
+use size_persistence::SizePersistenceState;
+use sizing::SizingState;
+use solid_color::SolidColorState;
+use startup::StartupState;
+use swapchain::SwapchainState;
+use title::wire_string;
+use transaction::StagedCommit;
+use user_data::UserData;
+use user_events::UserEvents;
+use utility::UtilityState;
+use viewport::ViewportState;
+use watchdog::WatchdogState;
+
+//Events the window hands back to the application on every pump_events call.
+//The Dispatch impls push into AppState::events and the application drains them,
+//so the protocol details stay in here and the application only sees what happened.
+//
+//Input events say which seat they came from (its name, see Window::seats): with several seats,
+//each has its own keyboard focus and pointer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowEvent {
+    //`code` is the evdev keycode `key` was made from, for keys without a name. `layout` is the
+    //keymap group it was pressed with, a release has its press's (see layout.rs). `key` is the
+    //physical key, `logical_key` what it means with NumLock and Shift, again the press's for a
+    //release (see numpad.rs).
+    //
+    //Both key events gain fields as keys learn more (logical_key broke every exhaustive match of
+    //them): they're non_exhaustive, match them with `..`.
+    #[non_exhaustive]
+    Key {
+        seat: Arc<str>,
+        key: Key,
+        logical_key: LogicalKey,
+        code: u32,
+        state: KeyState,
+        layout: u32,
+        time: u32,
+        serial: u32,
+    },
+    //A held key repeating. Only the EventLoop produces these, pump_events has no timers to
+    //drive them.
+    #[non_exhaustive]
+    KeyRepeat {
+        seat: Arc<str>,
+        key: Key,
+        logical_key: LogicalKey,
+    },
+    PointerEntered {
+        seat: Arc<str>,
+        x: f64,
+        y: f64,
+    },
+    PointerLeft {
+        seat: Arc<str>,
+    },
+    //Surface-local coordinates. These stop arriving while the pointer is locked. `dx`, `dy` are
+    //how far it went since the seat's previous position, over `samples` motion events merged
+    //into this one, see motion.rs.
+    PointerMoved {
+        seat: Arc<str>,
+        x: f64,
+        y: f64,
+        dx: f64,
+        dy: f64,
+        samples: u32,
+    },
+    PointerButton {
+        seat: Arc<str>,
+        button: u32,
+        pressed: bool,
+    },
+    //A button held in one of the window's repeat regions, where the pointer is now. EventLoop
+    //only, see button_repeat.rs.
+    PointerButtonRepeat {
+        seat: Arc<str>,
+        button: u32,
+        x: f64,
+        y: f64,
+    },
+    //Pixels to scroll by, positive is down and right (the content moves up and left). Wheels are
+    //turned into pixels through the ScrollConfig, see scroll.rs.
+    Scroll {
+        seat: Arc<str>,
+        dx: f64,
+        dy: f64,
+        source: ScrollSource,
+    },
+    //Unaccelerated deltas are what cameras and games want. `utime` is in microseconds.
+    //`synthetic` is set when the compositor has no relative pointer support and the deltas were
+    //derived from absolute motion instead (accelerated, and they stop while locked). The deltas
+    //of `samples` events added up, `utime` the newest one's, see motion.rs.
+    RelativeMotion {
+        seat: Arc<str>,
+        dx: f64,
+        dy: f64,
+        dx_unaccel: f64,
+        dy_unaccel: f64,
+        utime: u64,
+        synthetic: bool,
+        samples: u32,
+    },
+    Gesture {
+        seat: Arc<str>,
+        gesture: GestureEvent,
+    },
+    //The window size changed, after a configure. Shm windows get a new buffer with the gradient
+    //in it, anything drawn with Window::draw has to be drawn again.
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    //The compositor's dmabuf preferences changed (e.g. it now composites on another GPU).
+    //Buffers should be re-allocated for the new main device.
+    #[cfg(feature = "dmabuf")]
+    DmabufFeedbackChanged {
+        main_device: Option<u64>,
+    },
+    //The compositor's answer to Window::create_dmabuf_buffer.
+    #[cfg(feature = "dmabuf")]
+    DmabufBufferCreated {
+        request: DmabufRequest,
+        result: Result<wl_buffer::WlBuffer, WindowError>,
+    },
+    //The compositor's preferred color description for the surface changed (e.g. it moved to an
+    //HDR output). See Window::preferred_color.
+    #[cfg(feature = "color-management")]
+    PreferredColorChanged(ColorInfo),
+    //The compositor wants the next frame (see request_frame). `time` is in milliseconds.
+    Frame {
+        time: u32,
+    },
+    //Sent from another thread through an EventLoopProxy.
+    User(UserEvent),
+    PointerLocked,
+    PointerUnlocked,
+    PointerConfined,
+    PointerUnconfined,
+    //Keyboard focus. `pressed_keys` are the keys already held when it arrived (a modifier held
+    //while alt-tabbing in, for one), they won't get their own press event nor fire a binding,
+    //their release is an event as usual.
+    FocusGained {
+        seat: Arc<str>,
+        pressed_keys: Vec<Key>,
+    },
+    //Comes after a Released Key event (time 0) for every key still held, the compositor doesn't
+    //send those releases to a window without focus.
+    FocusLost {
+        seat: Arc<str>,
+    },
+    //The seat's active keyboard layout changed, see Window::current_layout.
+    KeyboardLayoutChanged {
+        seat: Arc<str>,
+        layout: LayoutInfo,
+    },
+    //The compositor shows the window as the active one (or not anymore). Usually follows the
+    //keyboard focus, but it's also there without a keyboard: what a title bar should dim with.
+    Activated,
+    Deactivated,
+    //Nobody used the seat for `timeout`, one registered with Window::on_idle, and then somebody
+    //did again.
+    UserIdle {
+        timeout: Duration,
+    },
+    UserActive {
+        timeout: Duration,
+    },
+    //Nothing of the window is visible (minimized, other workspace, screen locked...), frame
+    //callbacks won't come. Stop animating until Resumed, then redraw everything: what was shown
+    //before may be stale by now. Needs xdg_toplevel v6.
+    Suspended,
+    Resumed,
+    //The window went `busy` without reading the compositor's events, pings included, mostly
+    //because of `cause`. Only with Window::set_watchdog, see watchdog.rs.
+    SlowFrame {
+        busy: Duration,
+        cause: SlowFrameCause,
+    },
+    //The buffer scale followed the compositor's preferred one, a Resized comes with it. See
+    //preferred.rs.
+    ScaleChanged {
+        old: i32,
+        new: i32,
+    },
+    //The buffer transform followed the preferred one, with Window::set_follow_preferred_transform.
+    TransformChanged {
+        old: Transform,
+        new: Transform,
+    },
+    //The compositor went away (crashed, quit) or dropped us over a protocol error. The window
+    //stops running; Window::reconnect brings it back on a new connection.
+    ConnectionLost,
+    //More events waited than Window::set_event_capacity allows, `count` motion, scroll and
+    //similar events were dropped where this is. See backpressure.rs.
+    EventsDropped {
+        count: u64,
+    },
+}
+
+//Key repeat is done client side: the compositor only tells the rate (keys per second, 0 disables
+//it) and the delay before the first repeat (ms), the EventLoop's timer does the repeating.
+pub(crate) struct KeyRepeat {
+    rate: i32,
+    delay: i32,
+    //With the seat it's held on.
+    held: Option<(u32, Arc<str>)>,
+}
+
+impl Default for KeyRepeat {
+    //wl_keyboard before version 4 has no RepeatInfo, these are the usual desktop defaults.
+    fn default() -> Self {
+        KeyRepeat {
+            rate: 25,
+            delay: 600,
+            held: None,
+        }
+    }
+}
+
+impl KeyRepeat {
+    //Only the last pressed key repeats, as everywhere else, whatever seat it's on.
+    fn track(&mut self, key: u32, pressed: bool, seat: &Arc<str>) {
+        if pressed && self.rate > 0 {
+            self.held = Some((key, seat.clone()));
+        } else if self.held.as_ref() == Some(&(key, seat.clone())) {
+            self.held = None;
+        }
+    }
+
+    fn is_held_by(&self, seat: &str) -> bool {
+        self.held.as_ref().is_some_and(|(_, held)| &**held == seat)
+    }
+}
+
+//Application State
+//Quoting wayland_client documentation:
+//"The core event dispatching logic provided by this crate is built around the EventQueue struct.
+//In this paradigm, receiving and processing events is a two-step process:
+//
+//  - First, events are read from the Wayland socket. For each event, the backend figures out
+//    which EventQueue manages it, and enqueues the event in an internal buffer of that queue.
+//  - Then, the EventQueue empties its internal buffer by sequentially invoking the appropriate
+//    Dispatch::event() method on the State value that was provided to it.
+//
+//The main goal of this structure is to make your State accessible without synchronization to most
+//of your event-processing logic, to reduce the plumbing costs.
+//
+pub struct AppState {
+    running: bool,
+    compositor: Option<wl_compositor::WlCompositor>,
+    base_surface: Option<wl_surface::WlSurface>,
+    shm: Option<wl_shm::WlShm>,
+    buffer: Option<wl_buffer::WlBuffer>,
+    //The file backing the pool, kept so the gradient can be drawn again into the same memory,
+    //and how the buffer's rows are laid out in it.
+    shm_pixels: Option<(MappedPool, BufferLayout)>,
+    //The buffer of the last commit that attached one: what the window shows, see capture.rs.
+    attached: Option<wl_buffer::WlBuffer>,
+    //The connection's pool for icons and other small buffers, see buffer_allocator.rs.
+    buffer_allocator: SharedAllocator,
+    //Whether the buffer above goes away while the window is hidden, see hidden_buffers.rs.
+    hidden_buffers: HiddenBuffersState,
+    //Spare buffers for when the compositor releases late, see swapchain.rs.
+    swapchain: SwapchainState,
+    //A frame drawn with draw_rows and not presented yet, see rows.rs.
+    rows: RowsState,
+    //The shm buffer holds a picture from Window::draw, not the gradient we can redraw ourselves.
+    drawn_by_app: bool,
+    //What a new buffer starts with after a resize, see resize_content.rs.
+    resize_content: ResizeContent,
+    resize_preview: ResizePreviewState,
+    //How the buffer content is turned, see buffer_transform.rs. buffer_size stays the buffer's.
+    buffer_transform: Transform,
+    //The compositor's preferred scale and transform, and the scale buffers are at, see
+    //preferred.rs.
+    preferred: PreferredState,
+    buffer_size: (u32, u32),
+    //The last gradient view, what Window::gradient_view reports.
+    view: GradientView,
+    //What goes into the shm buffer when the application doesn't draw, see renderer.rs.
+    renderer: RendererState,
+    wm_base: Option<xdg_wm_base::XdgWmBase>,
+    //Ours, kept for wl_fixes.destroy_registry, see cleanup.rs.
+    registry: Option<wl_registry::WlRegistry>,
+    fixes: Option<cleanup::protocol::wl_fixes::WlFixes>,
+    xdg_surface: Option<(xdg_surface::XdgSurface, xdg_toplevel::XdgToplevel)>,
+    //Where the surface is in the role, initial commit, configure, attach order.
+    lifecycle: LifecycleState,
+    //Set once a dispatch failed, see WindowEvent::ConnectionLost.
+    connection_lost: bool,
+    //Newest xdg_surface Configure serial not acked yet.
+    pending_configure: Option<u32>,
+    configure_stats: ConfigureStats,
+    #[cfg(feature = "protocol-log")]
+    frame_span: FrameSpan,
+    //Size proposed by the latest xdg_toplevel Configure.
+    configure_size: (i32, i32),
+    //States (fullscreen, maximized, activated...) of the same Configure.
+    configure_states: Vec<xdg_toplevel::State>,
+    //Largest size worth being, from ConfigureBounds (xdg_toplevel v4). None when unknown.
+    configure_bounds: Option<(u32, u32)>,
+    render_mode: RenderMode,
+    //Already cut to what fits on the wire, see wire_string.
+    title: String,
+    app_id: String,
+    format: BufferFormat,
+    maximized: bool,
+    fullscreen: bool,
+    //WindowOptions::fullscreen_output, for the initial commit.
+    fullscreen_output: Option<String>,
+    key_repeat: KeyRepeat,
+    seats: SeatsState,
+    key_bindings: KeyBindings,
+    //Activated and Suspended states of the last applied configure.
+    activated: bool,
+    suspended: bool,
+    //What the compositor can do with the toplevel, None until it says (xdg_toplevel v5).
+    wm_capabilities: Option<Vec<xdg_toplevel::WmCapabilities>>,
+    #[cfg(feature = "egl")]
+    egl: Option<egl::EglState>,
+    pointer: PointerState,
+    relative_pointer: RelativePointerState,
+    //How motion events are merged, see motion.rs.
+    motion: MotionState,
+    //Where relative motion goes when it's isolated, see side_queue.rs.
+    side_queue: Option<SideQueueState>,
+    gestures: GestureState,
+    //Axis events until their frame, and how they become Scroll events, see scroll.rs.
+    scroll: ScrollState,
+    //Whether Window::draw commits frames identical to the last one, see frame_skip.rs.
+    frame_skip: FrameSkipState,
+    hit_test: HitTestState,
+    //When requests are sent, see flush.rs.
+    flush: FlushState,
+    //Held buttons repeating, see button_repeat.rs.
+    button_repeat: ButtonRepeatState,
+    //Input during the compositor's grabs, see input_routing.rs.
+    routing: InputRoutingState,
+    #[cfg(feature = "diagnostic-overlay")]
+    diagnostic_overlay: DiagnosticOverlayState,
+    serials: SerialsState,
+    globals: GlobalsState,
+    content_type: ContentTypeState,
+    alpha_modifier: AlphaModifierState,
+    input_region: InputRegionState,
+    geometry: GeometryState,
+    sizing: SizingState,
+    dialog: DialogState,
+    //Utility windows and ours, see utility.rs.
+    utility: UtilityState,
+    foreign: ForeignState,
+    activation: ActivationState,
+    icon: IconState,
+    viewport: ViewportState,
+    solid_color: SolidColorState,
+    outputs: OutputsState,
+    refresh: RefreshState,
+    idle: IdleState,
+    //Ping answers and the time between dispatches, see watchdog.rs.
+    watchdog: WatchdogState,
+    //The application's, see user_data.rs.
+    user_data: UserData,
+    //Only there once the window was split, see render_thread.rs.
+    render_thread: Option<RenderThreadState>,
+    #[cfg(feature = "dmabuf")]
+    dmabuf: dmabuf::DmabufState,
+    #[cfg(feature = "explicit-sync")]
+    explicit_sync: explicit_sync::ExplicitSyncState,
+    #[cfg(feature = "color-management")]
+    color: color_management::ColorState,
+    //Only there while recording, see record.rs.
+    #[cfg(feature = "record")]
+    recording: Option<record::RecordingState>,
+    //Taken after every dispatch, see Window::state.
+    snapshot: WindowStateSnapshot,
+    events: Vec<WindowEvent>,
+    //How many of them may wait, see backpressure.rs.
+    backpressure: BackpressureState,
+    size_persistence: SizePersistenceState,
+    //Timings and the first frame drawn ahead, see startup.rs.
+    startup: StartupState,
+    //Roles and recent requests of our objects, see diagnostics.rs.
+    diagnostics: DiagnosticsState,
+}
+
+impl AppState {
+    fn init_xdg_surface(&mut self, queue_handle: &QueueHandle<AppState>) {
+        //wm_base: Global object that enables clients to turn wl_surfaces into windows
+        //in the Desktop Environemnt
+        if let Err(err) = self.lifecycle.transition(SurfaceRequest::AssignRole) {
+            log::warn!("no xdg_surface: {err}");
+            return;
+        }
+        let wm_base = self.wm_base.as_ref().unwrap();
+
+        //base_surface here refers to the wl_surface
+        //WlSurfaces are a rectangle area that allows to receive user input, show
+        //wl_buffers and have local coordinate systems
+        let base_surface = self.base_surface.as_ref().unwrap();
+
+        //XdgSurfaces is an interface that may be implemented by a wl_surface
+        //if the implementation needs to provide a desktop-style user interface.
+        //
+        //Creating an XdgSurface requires you to set up your role-specific object
+        //by sending the application info (title, id, size, parent, etc) then
+        //performing an initial commit. This initial commit CANNOT have a buffer attached.
+        let xdg_surface = wm_base.get_xdg_surface(base_surface, queue_handle, ());
+        let toplevel = xdg_surface.get_toplevel(queue_handle, ());
+
+        toplevel.set_title(self.title.clone());
+        toplevel.set_app_id(self.app_id.clone());
+        self.set_role(&xdg_surface, "main window");
+        self.note_request(&xdg_surface, "get_toplevel");
+        self.set_role(&toplevel, "main window");
+        self.note_request(&toplevel, "set_title");
+        self.note_request(&toplevel, "set_app_id");
+
+        self.xdg_surface = Some((xdg_surface, toplevel));
+        //The icon and size limits are toplevel state too, better there from the start.
+        self.apply_icon(queue_handle);
+        self.apply_size_limits();
+        self.apply_parent();
+        self.apply_utility_parent(queue_handle);
+        self.apply_dialog(queue_handle);
+
+        //Outputs later in the registry than xdg_wm_base aren't bound yet, the end of the dispatch
+        //has them all.
+        if self.fullscreen_output.is_none() {
+            self.initial_commit();
+        }
+    }
+
+    //Maximized and fullscreen are asked right before the initial commit, so the first configure
+    //already has the final size and the first buffer is made at it. Compositors that ignore them
+    //(some tiling ones) leave the size to us with a 0, that's WindowOptions::preferred_size.
+    //
+    //A fullscreen output given by name waits for the outputs to say theirs: a dispatch after
+    //they're bound, Window::apply_configure tries again after each one.
+    pub(crate) fn initial_commit(&mut self) {
+        if self.lifecycle.stage() != Lifecycle::Created {
+            return;
+        }
+        let Some((_, ref toplevel)) = self.xdg_surface else {
+            return;
+        };
+        let output = match self.fullscreen_output {
+            Some(ref name) if self.fullscreen => {
+                if !self.outputs_known() {
+                    return;
+                }
+                let output = self.output_named(name);
+                if output.is_none() {
+                    log::warn!("no output named {name:?}, fullscreen where the compositor likes");
+                }
+                output
+            }
+            _ => None,
+        };
+        if self.maximized {
+            toplevel.set_maximized();
+        }
+        if self.fullscreen {
+            toplevel.set_fullscreen(output.as_ref());
+        }
+        if self
+            .lifecycle
+            .transition(SurfaceRequest::InitialCommit)
+            .is_ok()
+        {
+            let surface = self.base_surface.clone().unwrap();
+            surface.commit();
+            self.note_request(&surface, "commit (initial, no buffer)");
+            self.committed();
+        }
+    }
+
+    //Configure handling when EGL or an external renderer owns the buffers: take the size the
+    //configure asks for and tell whoever renders. They use the new size from their next frame on.
+    fn configure_client_rendered(&mut self, first_configure: bool) {
+        let size = self.configure_target_size();
+        self.resize_client_rendered(size, first_configure);
+    }
+
+    //The configure size is the window geometry, the buffer also has room for the shadow around
+    //it. Configure sizes are the compositor's, kept to what a buffer can be made with. At a
+    //buffer scale (see preferred.rs) it's that many times larger, and kept a multiple of it.
+    fn buffer_size_for(&self, size: (u32, u32)) -> (u32, u32) {
+        let margins = self.shadow_margins();
+        let scale = self.scale_math();
+        let size = BufferLayout::clamp_size(
+            scale.size_to_physical((
+                size.0
+                    .saturating_add(margins.left.saturating_add(margins.right)),
+                size.1
+                    .saturating_add(margins.top.saturating_add(margins.bottom)),
+            )),
+        );
+        self.buffer_transform
+            .apply_to_size(scale.divisible_size(size))
+    }
+
+    //Resized reports the window size, the renderer can get the buffer size from the window.
+    fn resize_client_rendered(&mut self, size: (u32, u32), force: bool) {
+        let buffer_size = self.buffer_size_for(size);
+        if buffer_size == self.buffer_size && !force {
+            return;
+        }
+
+        self.buffer_size = buffer_size;
+        #[cfg(feature = "egl")]
+        if let Some(ref egl) = self.egl {
+            egl.resize(buffer_size.0 as i32, buffer_size.1 as i32);
+        }
+        self.events.push(WindowEvent::Resized {
+            width: size.0,
+            height: size.1,
+        });
+    }
+
+    //The shm side of a configure, after its ack: a new buffer when there's none yet or the size
+    //changed, the gradient drawn into it. Quoting documentation: "The client must acknowledge it
+    //and is then allowed to attach a buffer to map the surface." Making the buffer here, rather
+    //than when wl_shm is bound, keeps that true whatever order things arrive in.
+    //
+    //A picture from Window::draw doesn't survive a new buffer, the application draws it again on
+    //Resized, which the first configure always sends. Until then the buffer has the gradient, or
+    //the old picture copied over with ResizeContent.
+    fn resize_shm(
+        &mut self,
+        size: (u32, u32),
+        first_configure: bool,
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        let buffer_size = self.buffer_size_for(size);
+        let resized = buffer_size != self.buffer_size;
+        //Hidden with its memory released, the buffer waits until the window shows again.
+        if (resized || self.buffer.is_none()) && !self.hidden_buffers.released {
+            self.buffer_size = buffer_size;
+            let old = self.shm_pixels.take();
+            self.create_main_buffer(queue_handle);
+            self.drawn_by_app = self.keep_resized_content(old);
+            self.render_thread_resized();
+        } else if resized {
+            self.buffer_size = buffer_size;
+            self.render_thread_resized();
+        }
+        if resized || first_configure {
+            self.events.push(WindowEvent::Resized {
+                width: size.0,
+                height: size.1,
+            });
+        }
+    }
+
+    //The main buffer, at buffer_size, with the renderer's content (see renderer.rs). Always a valid
+    //layout: buffer_size only ever comes from clamp_size.
+    fn create_main_buffer(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let (width, height) = self.buffer_size;
+        let layout = BufferLayout::new(width, height, self.format.into()).unwrap();
+        //The first one may be drawn already, see startup.rs.
+        let prerendered = self.take_prerendered(layout);
+        let drawn = prerendered.is_some();
+        let Some((buffer, pixels)) =
+            self.new_shm_buffer(layout, prerendered, "main buffer", queue_handle)
+        else {
+            return;
+        };
+
+        //The old buffer may still be on screen, until the commit with the new one. Its memory
+        //stays with the compositor as long as it needs it.
+        if let Some(old) = self.buffer.replace(buffer) {
+            old.destroy();
+        }
+        self.drop_spare_buffers();
+        self.shm_pixels = Some((pixels, layout));
+        if !drawn {
+            self.render_background();
+        }
+    }
+
+    //A buffer laid out as `layout`, in a file and pool of its own: `pixels` when given, a new
+    //file otherwise. `role` is what it's for, see diagnostics.rs.
+    fn new_shm_buffer(
+        &mut self,
+        layout: BufferLayout,
+        pixels: Option<MappedPool>,
+        role: &str,
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Option<(wl_buffer::WlBuffer, MappedPool)> {
+        let shm = self.shm.clone()?;
+        let pixels =
+            pixels.unwrap_or_else(|| MappedPool::new(MappedFile::new(layout.len()).unwrap()));
+
+        //wl_shm_pool: this object encapsulates a piece of memory shared between the compositor and
+        //client.
+        //
+        //With wl_shm_pool, the client can allocate shared memory wl_buffer objects.
+        //If you create an object through the same pool it will share the same mapped memory.
+        //As per documentation: "Reusing the mapped memory avoids the setup/teardown overhead and is
+        //useful when: interactively resizing a surface OR when using many small buffers."
+        let pool = shm.create_pool(pixels.file().as_fd(), layout.len() as i32, queue_handle, ());
+        self.note_request(&shm, format!("create_pool(size {})", layout.len()));
+        self.set_role(&pool, format!("{role} pool"));
+
+        //Quoting documentation: "A buffer provides the content for a wl_surface.
+        //Buffers are created through factory interfaces such as wl_shm, wp_linux_buffer_params
+        //(from the linux-dmabuf protocol extension) or similar. It has a width and a height
+        //and can be attached to a wl_surface, but the mechanism by which a client provides and
+        //updates the contents is defined by the buffer factory interface."
+        let (width, height, stride) = layout.protocol_size();
+        let buffer = pool.create_buffer(0, width, height, stride, layout.format, queue_handle, ());
+        self.note_request(
+            &pool,
+            format!(
+                "create_buffer({width}x{height}, stride {stride}, {:?})",
+                layout.format
+            ),
+        );
+        self.set_role(&buffer, format!("{role} {width}x{height}"));
+        //Quoting documentation: "The mmapped memory will be released when all buffers that have
+        //been created from this pool are gone."
+        pool.destroy();
+        Some((buffer, pixels))
+    }
+
+    //Attaches the gradient buffer and commits it. The viewport destination is reset first, in case
+    //a solid color (which is stretched with it) was shown before, or kept for a crop.
+    fn present_gradient(&mut self, queue_handle: &QueueHandle<AppState>) {
+        //When EGL or an external renderer owns the surface's buffers, attaching ours would fight
+        //with them.
+        if self.render_mode != RenderMode::Shm || !self.may_attach() {
+            return;
+        }
+
+        let Some(buffer) = self.buffer.clone() else {
+            return;
+        };
+        let (width, height) = self.buffer_size;
+        let staged = StagedCommit {
+            buffer: Some((buffer, width, height)),
+            damage: self.main_buffer_presented(),
+            ..StagedCommit::default()
+        };
+        self.commit_staged(staged, queue_handle, "shm buffer");
+        self.main_buffer_attached();
+        self.frame_skip.force_next();
+        self.frame_skip.frame_requested = false;
+    }
+
+    //Acks the newest configure of the dispatch batch and acts on it: one redraw, however many
+    //configures came in. Older serials are never acked after it, acking the newest one covers
+    //them all.
+    fn apply_pending_configure(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let (Some(serial), Some((xdg_surface, _))) =
+            (self.pending_configure.take(), self.xdg_surface.as_ref())
+        else {
+            return;
+        };
+        let first_configure = !self.configured();
+        if let Err(err) = self
+            .lifecycle
+            .transition(SurfaceRequest::AckConfigure(serial))
+        {
+            log::warn!("configure {serial} not acked: {err}");
+            return;
+        }
+        xdg_surface.ack_configure(serial);
+        let xdg_surface = xdg_surface.clone();
+        self.note_request(&xdg_surface, format!("ack_configure({serial})"));
+        self.configure_stats.applied += 1;
+        #[cfg(feature = "record")]
+        self.record(record::Recorded::AckConfigure);
+        protocol_log!(
+            "configure {serial} acked ({} of {} received applied)",
+            self.configure_stats.applied,
+            self.configure_stats.received
+        );
+        self.update_toplevel_states();
+
+        //When someone else renders there's nothing of ours to attach, just the size to pass on.
+        //The geometry then goes out with their next frame's commit.
+        if self.render_mode != RenderMode::Shm {
+            self.configure_client_rendered(first_configure);
+            self.apply_window_geometry();
+            self.apply_input_region(queue_handle);
+            return;
+        }
+        self.update_hidden_buffers();
+        let size = self.configure_target_size();
+        if self.preview_resize(size, queue_handle) {
+            return;
+        }
+        self.apply_shm_size(size, first_configure, queue_handle);
+    }
+
+    //The buffer for a `size` window, drawn and committed with the geometry.
+    fn apply_shm_size(
+        &mut self,
+        size: (u32, u32),
+        first_configure: bool,
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        self.resize_preview_done();
+        self.resize_shm(size, first_configure, queue_handle);
+        self.apply_window_geometry();
+        self.apply_input_region(queue_handle);
+
+        //If the gradient isn't drawn yet, a cheap solid color maps the window meanwhile. Same
+        //while hidden with the buffer released.
+        if self.buffer.is_some() {
+            self.present_gradient(queue_handle);
+        } else {
+            self.show_placeholder(queue_handle);
+        }
+    }
+
+    //The states only count once the xdg_surface Configure arrives, same as the size.
+    fn update_toplevel_states(&mut self) {
+        let activated = self
+            .configure_states
+            .contains(&xdg_toplevel::State::Activated);
+        if activated != self.activated {
+            self.activated = activated;
+            self.utility_activated(activated);
+            self.events.push(if activated {
+                WindowEvent::Activated
+            } else {
+                WindowEvent::Deactivated
+            });
+        }
+
+        let suspended = self
+            .configure_states
+            .contains(&xdg_toplevel::State::Suspended);
+        if suspended != self.suspended {
+            self.suspended = suspended;
+            //Redrawing after a Resumed is for things that may be stale, it has to show.
+            if !suspended {
+                self.frame_skip.force_next();
+            }
+            self.events.push(if suspended {
+                WindowEvent::Suspended
+            } else {
+                WindowEvent::Resumed
+            });
+        }
+    }
+
+    //Commits double-buffered surface state (content type, cursor hint...) set outside of a redraw.
+    //Before the initial commit there is nothing to do: that commit picks it up.
+    fn commit_state(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if self.lifecycle.stage() != Lifecycle::Created {
+            self.commit_staged(StagedCommit::default(), queue_handle, "state");
+        }
+    }
+}
+
+//The window owns the connection, the event queue and the state the queue dispatches into.
+//Everything the application can ask for goes through here; the feature modules add their
+//own `impl Window` blocks next to the Dispatch impls they drive.
+pub struct Window {
+    connection: Connection,
+    event_queue: EventQueue<AppState>,
+    state: AppState,
+    //Read prepared by prepare_read, waiting for the application's poll to say the socket is
+    //readable.
+    read_guard: Option<ReadEventsGuard>,
+    //Only there once a proxy was created.
+    user_events: Option<UserEvents>,
+    //The socket given to Window::connect, None for the environment's.
+    socket: Option<PathBuf>,
+}
+
+//How the window content gets to the screen: our own shm buffers (the gradient), or an OpenGL ES
+//context through EGL, in which case EGL owns the surface's buffers and we never attach any.
+//External is the same deal for renderers we don't know about (wgpu, Vulkan...) that get the
+//surface through the raw window handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    #[default]
+    Shm,
+    #[cfg(feature = "egl")]
+    Egl,
+    #[cfg(feature = "raw-window-handle")]
+    External,
+}
+
+//What the window starts with. Everything here can also be changed later through the Window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowOptions {
+    pub title: String,
+    //Usually the basename of the .desktop file, compositors use it to group windows and pick the
+    //icon. Made from the executable's name by default, see app_id.rs.
+    pub app_id: String,
+    pub render_mode: RenderMode,
+    //Size of the buffers we draw, in pixels, until the first configure. Both must be non-zero.
+    pub size: (u32, u32),
+    //Window size (without the shadow) to take when the compositor leaves it to us, with a 0 in
+    //its configure (the first one usually). None keeps the size we have.
+    pub preferred_size: Option<(u32, u32)>,
+    pub format: BufferFormat,
+    //Asked for before the window is first shown, so it comes up at that size without a jump.
+    //The compositor has the last word, see WindowStateSnapshot::maximized and fullscreen.
+    pub maximized: bool,
+    pub fullscreen: bool,
+    //With fullscreen, the output to be fullscreen on, by its name (OutputInfo::name, "DP-1").
+    //None, or a name no output has, leaves it to the compositor.
+    pub fullscreen_output: Option<String>,
+    pub decorations: Decorations,
+    pub resize_content: ResizeContent,
+    //Some(interval): an interactive resize stretches the last frame to the new size, with a real
+    //redraw at the end and at most `interval` apart meanwhile, see resize_preview.rs.
+    pub resize_preview: Option<Duration>,
+    //What the window draws when the application doesn't, see renderer.rs. Window::set_renderer
+    //takes renderers of your own.
+    pub background: Background,
+    //An app id to save the window's size under and start from it the next run, see
+    //size_persistence.rs. None (the default) writes nothing.
+    pub size_persistence: Option<String>,
+    //A tool palette with Window::create_child_window, see utility.rs.
+    pub role: WindowRole,
+    //Window::startup_timing gets a time_to_first_frame_callback, for a frame callback of its own
+    //on the first frame. Off by default.
+    pub startup_timing: bool,
+}
+
+//Pixel formats of our shm buffers. Every compositor supports these two. With Xrgb8888 the alpha
+//byte is ignored and the window is always opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferFormat {
+    #[default]
+    Argb8888,
+    Xrgb8888,
+}
+
+impl From<BufferFormat> for wl_shm::Format {
+    fn from(format: BufferFormat) -> Self {
+        match format {
+            BufferFormat::Argb8888 => wl_shm::Format::Argb8888,
+            BufferFormat::Xrgb8888 => wl_shm::Format::Xrgb8888,
+        }
+    }
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        WindowOptions {
+            title: "receba".into(),
+            app_id: default_app_id(),
+            render_mode: RenderMode::default(),
+            size: (320, 240),
+            preferred_size: None,
+            format: BufferFormat::default(),
+            maximized: false,
+            fullscreen: false,
+            fullscreen_output: None,
+            decorations: Decorations::default(),
+            resize_content: ResizeContent::default(),
+            resize_preview: None,
+            background: Background::default(),
+            size_persistence: None,
+            role: WindowRole::default(),
+            startup_timing: false,
+        }
+    }
+}
+
+impl Window {
+    pub fn new() -> Window {
+        Self::with_options(WindowOptions::default())
+    }
+
+    pub fn with_render_mode(render_mode: RenderMode) -> Window {
+        Self::with_options(WindowOptions {
+            render_mode,
+            ..WindowOptions::default()
+        })
+    }
+
+    //Connects through the environment. Panics when there's no compositor, or one the window
+    //can't work with (see Window::connect for the Result).
+    pub fn with_options(options: WindowOptions) -> Window {
+        Self::connect(ConnectOptions::default(), options).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    //Every window has its own event queue and registry, but several can share one connection
+    //(see create_child_dialog). Also for connections that don't come from the environment: a
+    //socket handed down by a parent process, or the test compositor.
+    pub fn with_connection(connection: Connection, options: WindowOptions) -> Window {
+        //A display is the starting point of any Wayland program.
+        //All other objects are created from it.
+        let display = connection.display();
+
+        //An event_queue is needed for event processing.
+        let event_queue = connection.new_event_queue();
+
+        //Its handle is needed to associate objects to the it.
+        let queue_handle = event_queue.handle();
+
+        //A registry allows the client to list and bind the global objects
+        //available from the compositor.
+        //
+        //Following the logic, we associate the registry we created to our queue_handle.
+        let registry = display.get_registry(&queue_handle, ());
+
+        //A 0 wide or 100000 pixel tall buffer can't be made, see buffer_layout.rs.
+        let buffer_size = BufferLayout::clamp_size(options.size);
+        if buffer_size != options.size {
+            log::warn!(
+                "buffer size {:?} out of range, using {buffer_size:?}",
+                options.size
+            );
+        }
+
+        //Create our Application State.
+        let mut state = AppState {
+            running: true,
+            compositor: None,
+            base_surface: None,
+            shm: None,
+            buffer: None,
+            shm_pixels: None,
+            attached: None,
+            buffer_allocator: SharedAllocator::for_connection(&connection),
+            hidden_buffers: HiddenBuffersState::default(),
+            swapchain: SwapchainState::default(),
+            rows: RowsState::default(),
+            drawn_by_app: false,
+            resize_content: options.resize_content,
+            resize_preview: ResizePreviewState::new(options.resize_preview),
+            buffer_transform: Transform::Normal,
+            preferred: PreferredState::default(),
+            buffer_size,
+            view: match options.background {
+                Background::Gradient(view) => view,
+                _ => GradientView::default(),
+            },
+            renderer: RendererState::new(options.background),
+            wm_base: None,
+            registry: Some(registry),
+            fixes: None,
+            xdg_surface: None,
+            lifecycle: LifecycleState::default(),
+            connection_lost: false,
+            pending_configure: None,
+            configure_stats: ConfigureStats::default(),
+            #[cfg(feature = "protocol-log")]
+            frame_span: FrameSpan::default(),
+            configure_size: (0, 0),
+            configure_states: Vec::new(),
+            configure_bounds: None,
+            render_mode: options.render_mode,
+            title: wire_string(&options.title),
+            app_id: wire_string(&options.app_id),
+            format: options.format,
+            maximized: options.maximized,
+            fullscreen: options.fullscreen,
+            fullscreen_output: options.fullscreen_output,
+            key_repeat: KeyRepeat::default(),
+            seats: SeatsState::default(),
+            key_bindings: KeyBindings::default(),
+            activated: false,
+            suspended: false,
+            wm_capabilities: None,
+            #[cfg(feature = "egl")]
+            egl: None,
+            pointer: PointerState::default(),
+            relative_pointer: RelativePointerState::default(),
+            motion: MotionState::default(),
+            side_queue: None,
+            gestures: GestureState::default(),
+            scroll: ScrollState::default(),
+            frame_skip: FrameSkipState::default(),
+            hit_test: HitTestState::default(),
+            flush: FlushState::new(connection.clone()),
+            button_repeat: ButtonRepeatState::default(),
+            routing: InputRoutingState::default(),
+            #[cfg(feature = "diagnostic-overlay")]
+            diagnostic_overlay: DiagnosticOverlayState::default(),
+            serials: SerialsState::default(),
+            globals: GlobalsState::default(),
+            content_type: ContentTypeState::default(),
+            alpha_modifier: AlphaModifierState::default(),
+            input_region: InputRegionState::default(),
+            geometry: GeometryState::new(options.decorations),
+            sizing: SizingState::default(),
+            dialog: DialogState::default(),
+            utility: UtilityState::new(options.role),
+            foreign: ForeignState::default(),
+            activation: ActivationState::default(),
+            icon: IconState::default(),
+            viewport: ViewportState::default(),
+            solid_color: SolidColorState::default(),
+            outputs: OutputsState::default(),
+            refresh: RefreshState::default(),
+            idle: IdleState::default(),
+            watchdog: WatchdogState::default(),
+            user_data: UserData::default(),
+            render_thread: None,
+            #[cfg(feature = "dmabuf")]
+            dmabuf: dmabuf::DmabufState::default(),
+            #[cfg(feature = "explicit-sync")]
+            explicit_sync: explicit_sync::ExplicitSyncState::default(),
+            #[cfg(feature = "color-management")]
+            color: color_management::ColorState::default(),
+            #[cfg(feature = "record")]
+            recording: None,
+            snapshot: WindowStateSnapshot::default(),
+            events: Vec::new(),
+            backpressure: BackpressureState::default(),
+            size_persistence: SizePersistenceState::default(),
+            diagnostics: DiagnosticsState::default(),
+            startup: StartupState::new(options.startup_timing),
+        };
+        state.sizing.preferred_size = options.preferred_size;
+        state.apply_role();
+        state.restore_size(options.size_persistence.as_deref());
+        state.check_app_id();
+        state.refresh_snapshot();
+
+        Window {
+            connection,
+            event_queue,
+            state,
+            read_guard: None,
+            user_events: None,
+            socket: None,
+        }
+    }
+
+    //Attaches any buffer (shm, single pixel, dmabuf...) of the given size and commits it, at the
+    //window's buffer scale when the size is a multiple of it (buffer_size() is). The buffer must
+    //stay alive until the compositor releases it. Err(InvalidState) before the
+    //first configure and after close, see Lifecycle.
+    pub fn present_buffer(
+        &mut self,
+        buffer: &wl_buffer::WlBuffer,
+        width: i32,
+        height: i32,
+    ) -> Result<(), WindowError> {
+        //A transaction of one attach, see transaction.rs.
+        let mut frame = self.begin_frame();
+        frame.attach(buffer, width as u32, height as u32);
+        frame.commit()
+    }
+
+    //Whether the first configure was acked. Nothing may be attached to the surface before that,
+    //which includes whatever a renderer using the raw handles presents.
+    pub fn is_configured(&self) -> bool {
+        self.state.configured()
+    }
+
+    //Whether any seat's keyboard focus is on the window.
+    pub fn has_keyboard_focus(&self) -> bool {
+        self.state.any_keyboard_focus()
+    }
+
+    //Whether the compositor shows the window as the active one.
+    pub fn is_activated(&self) -> bool {
+        self.state.activated
+    }
+
+    //The largest size the compositor recommends (the work area, a tile...), None if it didn't
+    //say. Windows bigger than this may end up partly off screen. A configure leaving the size to
+    //us gets one within it, see WindowOptions::preferred_size.
+    pub fn recommended_bounds(&self) -> Option<(u32, u32)> {
+        self.state.configure_bounds
+    }
+
+    //Whether the window is hidden away, see WindowEvent::Suspended.
+    pub fn is_suspended(&self) -> bool {
+        self.state.suspended
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.running
+    }
+
+    //Stops the window: is_running turns false, and nothing is attached or acked anymore (see
+    //Lifecycle::Closed).
+    pub fn close(&mut self) {
+        self.state.persist_size_now();
+        self.state.running = false;
+        let _ = self.state.lifecycle.transition(SurfaceRequest::Close);
+        self.close_child_windows();
+    }
+
+    //Block waiting for events, dispatch them and hand back whatever the Dispatch impls produced.
+    //Quoting documentation: "This method is similar to dispatch_pending(), but if there are no
+    //pending events it will also flush the connection and block waiting for the Wayland server to
+    //send an event."
+    pub fn pump_events(&mut self) -> Vec<WindowEvent> {
+        self.cancel_read();
+        self.state.watchdog.round_started();
+        //Before blocking_dispatch, whose own flush fails the dispatch on a full socket.
+        self.send_requests();
+        if self.user_events.is_some()
+            || self.state.flush.blocked.get()
+            || self.wakeup_deadline().is_some()
+        {
+            self.blocking_dispatch_with_wakeups();
+        } else {
+            self.state.wait_started();
+            let result = self.event_queue.blocking_dispatch(&mut self.state);
+            self.state.watchdog.wait_ended();
+            self.state.watchdog.woke(false);
+            if let Err(err) = result {
+                self.connection_failed(err);
+            }
+        }
+        self.apply_configure();
+        std::mem::take(&mut self.state.events)
+    }
+
+    //Same as pump_events, but never blocks: sends our requests, reads whatever already arrived and
+    //returns. For loops that render continuously (e.g. GL paced by eglSwapBuffers).
+    pub fn poll_events(&mut self) -> Vec<WindowEvent> {
+        self.cancel_read();
+        self.state.watchdog.round_started();
+        self.dispatch_queued();
+        self.send_requests();
+
+        //read() doesn't block either, it returns WouldBlock when the socket is empty.
+        self.state.wait_started();
+        if let Some(guard) = self.event_queue.prepare_read() {
+            let _ = guard.read();
+        }
+        self.state.watchdog.wait_ended();
+        self.dispatch_queued();
+        self.deliver_user_events();
+        self.apply_configure();
+        std::mem::take(&mut self.state.events)
+    }
+
+    //Every way of dispatching calls this once its batch is done, before handing out the events.
+    //Key bindings that fired run here too, they need the Window, the side queue's motion comes
+    //in, then the render thread's frame goes out, and the state snapshot is taken last.
+    pub(crate) fn apply_configure(&mut self) {
+        self.run_key_bindings();
+        self.run_idle_callbacks();
+        self.run_activation_callbacks();
+        self.follow_parent();
+        self.state.dispatch_side_queue();
+        self.state.initial_commit();
+        let queue_handle = self.event_queue.handle();
+        self.state.prepare_first_frame(&queue_handle);
+        self.state.apply_preferred(&queue_handle);
+        self.state.apply_pending_configure(&queue_handle);
+        self.state.settle_resize_preview(&queue_handle);
+        self.state.present_rendered_frame(&queue_handle);
+        self.state.refresh_snapshot();
+        self.state.coalesce_motion();
+        self.state.bound_events();
+        #[cfg(feature = "diagnostic-overlay")]
+        {
+            self.state.redraw_diagnostic_overlay(&queue_handle);
+            let overlay = &mut self.state.diagnostic_overlay;
+            overlay.note_inputs(&self.state.events);
+        }
+        self.state.persist_size();
+        self.state.watchdog.round_ended();
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//With its own connection, a window disappears when the connection closes. Sharing one with other
+//windows (dialogs), it has to take its surface down by itself: role objects first, then the
+//surface.
+impl Drop for Window {
+    fn drop(&mut self) {
+        self.state.persist_size_now();
+        self.state.detach_dialogs();
+        self.close_child_windows();
+        self.state.detach_utility();
+        self.state.revoke_foreign();
+        //The EGL window wraps the wl_surface, it can't outlive it.
+        #[cfg(feature = "egl")]
+        {
+            self.state.egl = None;
+        }
+        self.state.release_surface_objects();
+        if let Some((xdg_surface, toplevel)) = self.state.xdg_surface.take() {
+            toplevel.destroy();
+            xdg_surface.destroy();
+        }
+        if let Some(surface) = self.state.base_surface.take() {
+            surface.destroy();
+        }
+        self.state.release_objects();
+        let _ = self.flush();
+    }
+}
+
+//The registry provides a list of global objects (protocols/interfaces) exposed by the compositor.
+//Here, we handle each advertised global and bind to the ones we need (e.g., wl_compositor, wl_shm).
+//Binding gives us a client-side handle to interact with that global object.
+//Each interface's events are handled in their respective `Dispatch` impls.
+//We need to implement Dispatch<O, _> to each O wayland object that needs to have their events
+//processed.
+impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            protocol_log!("global {interface} v{version} (name {name})");
+            state.globals.added(name, &interface, version);
+            match &interface[..] {
+                "wl_compositor" => {
+                    //wl_compositor: the compositor, responsible for creating the displayable
+                    //output of multiple surfaces.
+                    let compositor = registry.bind::<wl_compositor::WlCompositor, _, _>(
+                        name,
+                        version,
+                        queue_handle,
+                        (),
+                    );
+
+                    let surface = compositor.create_surface(queue_handle, ());
+                    state.set_role(&surface, "main window surface");
+                    state.base_surface = Some(surface);
+
+                    //Per-surface extension objects belong to the old surface (if any), set
+                    //them up again for this one.
+                    state.forget_content_type_object();
+                    state.apply_content_type(queue_handle);
+                    state.forget_alpha_modifier_object();
+                    state.apply_alpha_modifier(queue_handle);
+                    #[cfg(feature = "color-management")]
+                    {
+                        state.forget_color_objects();
+                        state.init_color_feedback(queue_handle);
+                        state.apply_color_description(queue_handle);
+                    }
+
+                    //Kept around since regions (for pointer confinement, input regions...)
+                    //are created through the compositor too.
+                    state.compositor = Some(compositor);
+
+                    if state.wm_base.is_some() && state.xdg_surface.is_none() {
+                        state.init_xdg_surface(queue_handle);
+                    }
+                }
+                "wl_shm" => {
+                    //shm: this singleton provides support for shared memory. Clients are able to
+                    //create wl_shm_pools using the create_pool request.
+                    //
+                    //Only noted here, whatever order the globals and the first configure come in:
+                    //the buffer is made by the configure, after its ack (see resize_shm).
+                    let shm = registry.bind::<wl_shm::WlShm, _, _>(name, version, queue_handle, ());
+                    state.set_role(&shm, "wl_shm global");
+                    state.shm = Some(shm);
+
+                    //Configured without it, the placeholder is up: replace it.
+                    if state.configured() {
+                        state.create_main_buffer(queue_handle);
+                        state.present_gradient(queue_handle);
+                    }
+                }
+                "wl_seat" => {
+                    //wl_seat: A seat is a greoup of input devices (mouse, keyboard, touch).
+                    //Quoting documentation: "A seat is published during start up, or when a
+                    //device is hot plugged. A seat typically has a pointer and maintains a
+                    //keyboard focus and a pointer focus"
+                    let seat =
+                        registry.bind::<wl_seat::WlSeat, _, _>(name, version, queue_handle, ());
+                    state.add_seat(name, seat);
+                    state.init_idle_notifications(queue_handle);
+                }
+                "wl_output" => {
+                    //wl_output: a monitor. Only what the state snapshot reports is kept: its name
+                    //and scale, and whether the surface is on it. v4 has the name.
+                    let output = registry.bind::<wl_output::WlOutput, _, _>(
+                        name,
+                        version.min(4),
+                        queue_handle,
+                        (),
+                    );
+                    state.add_output(name, output);
+                }
+                "xdg_wm_base" => {
+                    //Quoting documentation: The xdg_wm_base interface is exposed as a global
+                    //object enabling clients to turn their wl_surfaces into windows in a desktop
+                    //environment. It defines the basic functionality needed for clients and the
+                    //compositor to create windows that can be dragged, resized, maximized, etc,
+                    //as well as creating transient windows such as popup menus.
+                    //
+                    let wm_base = registry.bind::<xdg_wm_base::XdgWmBase, _, _>(
+                        name,
+                        version,
+                        queue_handle,
+                        (),
+                    );
+
+                    state.set_role(&wm_base, "xdg_wm_base global");
+                    state.wm_base = Some(wm_base);
+
+                    if state.base_surface.is_some() && state.xdg_surface.is_none() {
+                        state.init_xdg_surface(queue_handle);
+                    }
+                }
+                "wl_fixes" => {
+                    //wl_fixes: requests the core interfaces can't have, destroy_registry for the
+                    //window's drop (see cleanup.rs).
+                    let fixes = registry.bind::<cleanup::protocol::wl_fixes::WlFixes, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    );
+                    state.fixes = Some(fixes);
+                }
+                "xdg_wm_dialog_v1" => {
+                    //xdg_wm_dialog_v1: marks toplevels as dialogs of their parent. Only dialog
+                    //windows use it, and the toplevel may already be there.
+                    let manager = registry.bind::<XdgWmDialogV1, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    );
+                    state.dialog.manager = Some(manager);
+                    state.apply_dialog(queue_handle);
+                }
+                //xdg_activation_v1: tokens for the programs we start, see activation.rs.
+                "xdg_activation_v1" => {
+                    state.activation.manager = Some(registry.bind::<XdgActivationV1, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    ));
+                }
+                //Subsurfaces, for the diagnostic overlay.
+                #[cfg(feature = "diagnostic-overlay")]
+                "wl_subcompositor" => {
+                    state.diagnostic_overlay.subcompositor =
+                        Some(registry.bind::<WlSubcompositor, _, _>(
+                            name,
+                            version.min(1),
+                            queue_handle,
+                            (),
+                        ));
+                }
+                //xdg-foreign: toplevel handles shared with other clients. Bound up front so
+                //export_handle can say right away when there's no support.
+                "zxdg_exporter_v2" => {
+                    state.foreign.exporter = Some(registry.bind::<ZxdgExporterV2, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    ));
+                }
+                "zxdg_importer_v2" => {
+                    state.foreign.importer = Some(registry.bind::<ZxdgImporterV2, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    ));
+                }
+                "zwp_pointer_constraints_v1" => {
+                    //zwp_pointer_constraints_v1: lets us lock the pointer in place or confine it to
+                    //a region of our surface. Only needed when the application asks for it, but
+                    //binding it up front is cheap.
+                    let constraints = registry
+                        .bind::<zwp_pointer_constraints_v1::ZwpPointerConstraintsV1, _, _>(
+                            name,
+                            version.min(1),
+                            queue_handle,
+                            (),
+                        );
+
+                    state.pointer.constraints = Some(constraints);
+                }
+                "zwp_relative_pointer_manager_v1" => {
+                    //zwp_relative_pointer_manager_v1: gives unaccelerated pointer deltas that
+                    //keep coming even when the pointer is locked or stuck at a screen edge.
+                    let manager = registry
+                        .bind::<zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    );
+
+                    state.relative_pointer.manager = Some(manager);
+                    state.init_relative_pointer(queue_handle);
+                }
+                "wp_content_type_manager_v1" => {
+                    //wp_content_type_manager_v1: hints about what the surface shows (photo,
+                    //video, game) so the compositor can tune latency vs quality for it.
+                    let manager = registry
+                        .bind::<wp_content_type_manager_v1::WpContentTypeManagerV1, _, _>(
+                            name,
+                            version.min(1),
+                            queue_handle,
+                            (),
+                        );
+
+                    state.content_type.manager = Some(manager);
+                    state.apply_content_type(queue_handle);
+                }
+                "wp_alpha_modifier_v1" => {
+                    //wp_alpha_modifier_v1: the compositor multiplies the surface's alpha for us,
+                    //window fades without redrawing.
+                    let manager = registry.bind::<wp_alpha_modifier_v1::WpAlphaModifierV1, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    );
+
+                    state.alpha_modifier.manager = Some(manager);
+                    state.apply_alpha_modifier(queue_handle);
+                }
+                "xdg_toplevel_icon_manager_v1" => {
+                    //xdg_toplevel_icon_manager_v1: icons for the task bar, from pixels or a name.
+                    let manager = registry
+                        .bind::<xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1, _, _>(
+                            name,
+                            version.min(1),
+                            queue_handle,
+                            (),
+                        );
+
+                    state.icon.manager = Some(manager);
+                }
+                #[cfg(feature = "color-management")]
+                "wp_color_manager_v1" => {
+                    //wp_color_manager_v1: color spaces and HDR. The supported features follow
+                    //the bind, see color_management.rs.
+                    let manager = registry.bind::<WpColorManagerV1, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    );
+                    state.color.manager = Some(manager);
+                }
+                "ext_idle_notifier_v1" => {
+                    //ext_idle_notifier_v1: tells when the user has been away for a while. Version
+                    //2 only adds notifications that ignore idle inhibitors, see idle.rs.
+                    let notifier = registry.bind::<ExtIdleNotifierV1, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    );
+                    state.idle.notifier = Some(notifier);
+                    state.init_idle_notifications(queue_handle);
+                }
+                "wp_cursor_shape_manager_v1" => {
+                    //wp_cursor_shape_manager_v1: cursors by name (resize arrows and such) instead
+                    //of drawing them, for client side decorations, see hit_test.rs. Version 2
+                    //only adds shapes.
+                    let manager = registry.bind::<WpCursorShapeManagerV1, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    );
+                    state.hit_test.cursor_shape = Some(manager);
+                }
+                "wp_presentation" => {
+                    //wp_presentation: when our commits make it to the screen, and the refresh of
+                    //the screen they made it to. Only that refresh is kept, see refresh.rs.
+                    let presentation = registry.bind::<WpPresentation, _, _>(
+                        name,
+                        version.min(2),
+                        queue_handle,
+                        (),
+                    );
+                    state.refresh.presentation = Some(presentation);
+                }
+                "wp_viewporter" => {
+                    //wp_viewporter: lets the compositor scale/crop our buffer to a surface size.
+                    let viewporter = registry.bind::<wp_viewporter::WpViewporter, _, _>(
+                        name,
+                        version.min(1),
+                        queue_handle,
+                        (),
+                    );
+
+                    state.viewport.viewporter = Some(viewporter);
+                }
+                "wp_single_pixel_buffer_manager_v1" => {
+                    //wp_single_pixel_buffer_manager_v1: 1x1 buffers of a solid color that need
+                    //no shared memory at all. Stretched with the viewport they fill a surface.
+                    let manager: wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1 =
+                        registry.bind(name, version.min(1), queue_handle, ());
+
+                    state.solid_color.manager = Some(manager);
+                }
+                #[cfg(feature = "dmabuf")]
+                "zwp_linux_dmabuf_v1" => {
+                    //zwp_linux_dmabuf_v1: buffers living in GPU memory, shared through dmabuf fds.
+                    //Version 4 is enough for the feedback objects, 5 adds nothing we use.
+                    let dmabuf = registry.bind::<zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1, _, _>(
+                        name,
+                        version.min(4),
+                        queue_handle,
+                        (),
+                    );
+
+                    state.dmabuf.dmabuf = Some(dmabuf);
+                    state.init_dmabuf_feedback(queue_handle);
+                }
+                #[cfg(feature = "explicit-sync")]
+                "wp_linux_drm_syncobj_manager_v1" => {
+                    //wp_linux_drm_syncobj_manager_v1: acquire and release fences for dmabuf
+                    //buffers, see explicit_sync.rs.
+                    let manager: wp_linux_drm_syncobj_manager_v1::WpLinuxDrmSyncobjManagerV1 =
+                        registry.bind(name, version.min(1), queue_handle, ());
+                    state.explicit_sync.manager = Some(manager);
+                }
+                "zwp_pointer_gestures_v1" => {
+                    //zwp_pointer_gestures_v1: touchpad swipes, pinches and holds. We only know
+                    //up to version 3, anything newer could send events we can't parse.
+                    let manager = registry
+                        .bind::<zwp_pointer_gestures_v1::ZwpPointerGesturesV1, _, _>(
+                            name,
+                            version.min(3),
+                            queue_handle,
+                            (),
+                        );
+
+                    state.gestures.manager = Some(manager);
+                    state.init_gestures(queue_handle);
+                }
+
+                //No need to bind other protocols so we just don't bind them.
+                _ => {}
+            }
+        } else if let wl_registry::Event::GlobalRemove { name } = event {
+            //Seats and outputs can be unplugged. The other globals we use don't go away in
+            //practice.
+            state.globals.removed(name);
+            state.remove_seat(name, queue_handle);
+            state.remove_output(name);
+        }
+    }
+}
+
+impl Dispatch<xdg_wm_base::XdgWmBase, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        wm_base: &xdg_wm_base::XdgWmBase,
+        event: xdg_wm_base::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<AppState>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+            state.note_request(wm_base, format!("pong({serial})"));
+            state.watchdog.ping_answered();
+        }
+    }
+}
+
+impl Dispatch<xdg_surface::XdgSurface, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &xdg_surface::XdgSurface,
+        event: xdg_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<AppState>,
+    ) {
+        //During an interactive resize configures come in bursts, several per frame. Acking and
+        //redrawing each one would fall behind, so only the newest is kept (serials only grow) and
+        //apply_pending_configure handles it once the batch is dispatched.
+        if let xdg_surface::Event::Configure { serial } = event {
+            protocol_log!("xdg_surface configure {serial} received");
+            state.pending_configure = Some(serial);
+            state.lifecycle.configure_received(serial);
+            state.configure_stats.received += 1;
+            #[cfg(feature = "record")]
+            state.record(record::Recorded::Configure {
+                width: state.configure_size.0,
+                height: state.configure_size.1,
+                states: state.configure_states.iter().map(|&s| s as u32).collect(),
+            });
+        }
+    }
+}
+
+impl Dispatch<xdg_toplevel::XdgToplevel, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &XdgToplevel,
+        event: xdg_toplevel::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<AppState>,
+    ) {
+        match event {
+            //The size the compositor would like us to be. 0 means "up to you". It only counts
+            //once the xdg_surface Configure that follows arrives.
+            xdg_toplevel::Event::Configure {
+                width,
+                height,
+                states,
+            } => {
+                state.configure_states = toplevel_states(&states);
+                state.propose_configure_size((width, height));
+                protocol_log!(
+                    "toplevel configure {width}x{height}, states {:?}",
+                    state.configure_states
+                );
+            }
+            //Quoting documentation: "The bounds can for example correspond to the size of a
+            //monitor excluding any panels or other shell components, so that a surface isn't
+            //created in a way that it cannot fit." Sent before the Configure it goes with, 0x0
+            //meaning unknown.
+            xdg_toplevel::Event::ConfigureBounds { width, height } => {
+                state.configure_bounds =
+                    (width > 0 && height > 0).then_some((width as u32, height as u32));
+            }
+            xdg_toplevel::Event::WmCapabilities { capabilities } => {
+                state.wm_capabilities = Some(
+                    capabilities
+                        .chunks_exact(4)
+                        .filter_map(|capability| {
+                            let capability = u32::from_ne_bytes(capability.try_into().unwrap());
+                            xdg_toplevel::WmCapabilities::try_from(capability).ok()
+                        })
+                        .collect(),
+                );
+            }
+            xdg_toplevel::Event::Close => {
+                #[cfg(feature = "record")]
+                state.record(record::Recorded::Close);
+                state.running = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+//The states come as an array of u32 in native byte order. Values newer than our protocol version
+//are skipped.
+fn toplevel_states(states: &[u8]) -> Vec<xdg_toplevel::State> {
+    states
+        .chunks_exact(4)
+        .filter_map(|state| {
+            let state = u32::from_ne_bytes(state.try_into().unwrap());
+            xdg_toplevel::State::try_from(state).ok()
+        })
+        .collect()
+}
+
+//The user data is the seat the keyboard belongs to, serials are per seat.
+impl Dispatch<wl_keyboard::WlKeyboard, wl_seat::WlSeat> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        seat: &wl_seat::WlSeat,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_keyboard::Event::Keymap { format, fd, size } => {
+                state.update_keymap(seat, format, fd, size);
+            }
+            wl_keyboard::Event::Modifiers {
+                mods_locked, group, ..
+            } => {
+                state.update_locks(seat, mods_locked);
+                state.update_layout(seat, group);
+            }
+            wl_keyboard::Event::RepeatInfo { rate, delay } => {
+                state.key_repeat.rate = rate;
+                state.key_repeat.delay = delay;
+                //Turned off while a key is held: its timer goes too.
+                if rate <= 0 {
+                    state.key_repeat.held = None;
+                }
+            }
+            wl_keyboard::Event::Key {
+                serial,
+                time,
+                key,
+                state: key_state,
+            } => {
+                let key_state = match key_state {
+                    WEnum::Value(wl_keyboard::KeyState::Pressed) => KeyState::Pressed,
+                    _ => KeyState::Released,
+                };
+                let pressed = key_state.is_pressed();
+                if pressed {
+                    state.record_serial(seat, SerialKind::KeyPress, serial);
+                }
+                #[cfg(feature = "record")]
+                state.record(record::Recorded::Key { key, pressed });
+                let fresh = state.track_key(seat, key, pressed);
+                let layout = state.key_layout(seat, key, pressed);
+                let logical_key = state.key_logical(seat, key, pressed, layout);
+                let name = state.seat_name(seat);
+                state.key_repeat.track(key, pressed && fresh, &name);
+
+                //A locked/confined pointer can't leave the window, so Escape's press releases it
+                //first, whatever it's bound to; otherwise users could get stuck. Its release is
+                //swallowed with it, nothing quits on it.
+                let bound = if pressed
+                    && Key::from_evdev(key) == Key::Escape
+                    && state.pointer.is_constrained()
+                {
+                    state.release_pointer_constraint();
+                    state.hold_key(key);
+                    true
+                } else if pressed && fresh {
+                    state.fire_key_binding(&name, key, false)
+                } else if pressed {
+                    //Already down at Enter, see track_key.
+                    true
+                } else {
+                    state.release_key_binding(key)
+                };
+                if !bound {
+                    state.events.push(WindowEvent::Key {
+                        seat: name,
+                        key: Key::from_evdev(key),
+                        logical_key,
+                        code: key,
+                        state: key_state,
+                        layout,
+                        time,
+                        serial,
+                    });
+                }
+            }
+            //`keys` is an array of u32 in native byte order, like the toplevel states.
+            wl_keyboard::Event::Enter {
+                serial,
+                surface,
+                keys,
+            } => {
+                state.record_serial(seat, SerialKind::KeyboardEnter, serial);
+                state.keyboard_entered(&surface);
+                #[cfg(feature = "record")]
+                state.record(record::Recorded::KeyboardEnter);
+                let pressed_keys: Vec<u32> = keys
+                    .chunks_exact(4)
+                    .map(|key| u32::from_ne_bytes(key.try_into().unwrap()))
+                    .collect();
+                protocol_log!(
+                    "keyboard focus gained (serial {serial}), keys held {pressed_keys:?}"
+                );
+                if let Some(entry) = state.seat_mut(seat) {
+                    entry.keyboard_focus = true;
+                    entry.pressed_keys = pressed_keys.clone();
+                    entry.held_at_enter = pressed_keys.clone();
+                }
+                state.events.push(WindowEvent::FocusGained {
+                    seat: state.seat_name(seat),
+                    pressed_keys: pressed_keys.into_iter().map(Key::from_evdev).collect(),
+                });
+            }
+            //Keys held while the focus leaves don't send a release to us: they stop repeating
+            //and get a made up release each (release_held_keys), before FocusLost.
+            wl_keyboard::Event::Leave { serial, .. } => {
+                protocol_log!("keyboard focus lost (serial {serial})");
+                #[cfg(feature = "record")]
+                state.record(record::Recorded::KeyboardLeave);
+                let name = state.seat_name(seat);
+                if let Some(index) = state.seat_index(seat) {
+                    state.release_held_keys(index, serial);
+                }
+                if state.key_repeat.is_held_by(&name) {
+                    state.key_repeat.held = None;
+                }
+                state.forget_held_bindings();
+                state.events.push(WindowEvent::FocusLost { seat: name });
+            }
+            _ => {}
+        }
+    }
+}
+
+//Which part of the gradient is on screen. zoom 1.0 and no pan is the plain gradient; the
+//gesture demo drives this with pinches (zoom) and swipes (pan).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientView {
+    pub zoom: f64,
+    pub pan: (f64, f64),
+}
+
+impl Default for GradientView {
+    fn default() -> Self {
+        GradientView {
+            zoom: 1.0,
+            pan: (0.0, 0.0),
+        }
+    }
+}
+
+impl Window {
+    pub fn gradient_view(&self) -> GradientView {
+        self.state.view
+    }
+
+    //Switches to the gradient (whatever the renderer was) with the new view, and shows it.
+    pub fn set_gradient_view(&mut self, view: GradientView) {
+        self.state.view = view;
+        self.set_renderer(Background::Gradient(view));
+    }
+}
+
+//These protocols events are being ignored since we don't care about them in the scope our
+//application.
+delegate_noop!(AppState: ignore wl_shm::WlShm);
+delegate_noop!(AppState: ignore wl_shm_pool::WlShmPool);
+delegate_noop!(AppState: ignore wl_compositor::WlCompositor);
+delegate_noop!(AppState: ignore wl_region::WlRegion);
+delegate_noop!(AppState: cleanup::protocol::wl_fixes::WlFixes);
+delegate_noop!(AppState: zwp_pointer_constraints_v1::ZwpPointerConstraintsV1);
+delegate_noop!(AppState: zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1);
+delegate_noop!(AppState: zwp_pointer_gestures_v1::ZwpPointerGesturesV1);
+delegate_noop!(AppState: wp_content_type_manager_v1::WpContentTypeManagerV1);
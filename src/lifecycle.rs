@@ -0,0 +1,254 @@
+//The window surface's life, as the protocol orders it. A wl_surface gets one role (xdg_surface
+//plus xdg_toplevel), then an initial commit without a buffer, then waits for a configure, acks
+//it, and only then may a buffer be attached:
+//
+//Quoting documentation: "After creating a role-specific object and setting it up (e.g. by sending
+//the title, app ID, size constraints, parent, etc), the client must perform an initial commit
+//without any buffer attached. The compositor will reply with initial wl_surface state such as
+//wl_surface.preferred_buffer_scale followed by an xdg_surface.configure event. The client must
+//acknowledge it and is then allowed to attach a buffer to map the surface."
+//
+//Getting it wrong is a protocol error, which the compositor answers by dropping the connection
+//with a message that names an object id, not the line that did it. So every request of that kind
+//on the window's surface asks LifecycleState first, and an out of order one is an
+//Err(WindowError::InvalidState) saying what was wrong, with nothing sent.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::{AppState, Window, WindowError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Lifecycle {
+    //The wl_surface exists, maybe with its role already.
+    #[default]
+    Created,
+    //Committed without a buffer, waiting for the first configure.
+    InitialCommitDone,
+    //A configure was acked, buffers can go on.
+    Configured,
+    //A buffer was committed, the window is (or can be) on screen.
+    Mapped,
+    //Window::close was called. Nothing is attached or acked anymore.
+    Closed,
+}
+
+//The requests whose order the protocol cares about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SurfaceRequest {
+    //get_xdg_surface and get_toplevel.
+    AssignRole,
+    InitialCommit,
+    AckConfigure(u32),
+    //wl_surface.attach of a buffer, with the commit right after.
+    Attach,
+    Close,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct LifecycleState {
+    stage: Lifecycle,
+    role: bool,
+    //Configure serials: the newest received and the last acked. Serials only grow, so anything
+    //in between can be acked and the rest can't.
+    newest_configure: Option<u32>,
+    last_acked: Option<u32>,
+    //Set by the main window of a utility window when it closes, having destroyed our toplevel
+    //already: Closed from then on, see utility.rs.
+    closed_by_parent: Arc<AtomicBool>,
+}
+
+impl LifecycleState {
+    pub(crate) fn stage(&self) -> Lifecycle {
+        if self.closed_by_parent.load(Ordering::Acquire) {
+            return Lifecycle::Closed;
+        }
+        self.stage
+    }
+
+    pub(crate) fn closed_by_parent(&self) -> Arc<AtomicBool> {
+        self.closed_by_parent.clone()
+    }
+
+    pub(crate) fn configure_received(&mut self, serial: u32) {
+        self.newest_configure = Some(serial);
+    }
+
+    //Checks `request` against where the surface is and moves it along. Nothing changes on Err.
+    pub(crate) fn transition(&mut self, request: SurfaceRequest) -> Result<(), WindowError> {
+        let invalid = |reason| Err(WindowError::InvalidState(reason));
+        if self.stage() == Lifecycle::Closed {
+            return invalid("the window was closed");
+        }
+        match request {
+            SurfaceRequest::AssignRole if self.role => {
+                return invalid("the surface already has a role");
+            }
+            SurfaceRequest::AssignRole => self.role = true,
+            SurfaceRequest::InitialCommit if !self.role => {
+                return invalid("initial commit before the surface has a role");
+            }
+            SurfaceRequest::InitialCommit if self.stage != Lifecycle::Created => {
+                return invalid("initial commit done already");
+            }
+            SurfaceRequest::InitialCommit => self.stage = Lifecycle::InitialCommitDone,
+            SurfaceRequest::AckConfigure(_) if self.stage == Lifecycle::Created => {
+                return invalid("configure acked before the initial commit");
+            }
+            SurfaceRequest::AckConfigure(serial) => {
+                if self.newest_configure.is_none_or(|newest| serial > newest) {
+                    return invalid("acked a configure that never came");
+                }
+                if self.last_acked.is_some_and(|acked| serial <= acked) {
+                    return invalid("configure acked twice");
+                }
+                self.last_acked = Some(serial);
+                self.stage = self.stage.max(Lifecycle::Configured);
+            }
+            SurfaceRequest::Attach if self.stage < Lifecycle::Configured => {
+                return invalid("buffer attached before the first configure was acked");
+            }
+            SurfaceRequest::Attach => self.stage = Lifecycle::Mapped,
+            SurfaceRequest::Close => self.stage = Lifecycle::Closed,
+        }
+        Ok(())
+    }
+}
+
+impl AppState {
+    pub(crate) fn configured(&self) -> bool {
+        matches!(
+            self.lifecycle.stage(),
+            Lifecycle::Configured | Lifecycle::Mapped
+        )
+    }
+
+    //For the attaches the window makes by itself, which only happen once configured: a refusal
+    //there means the window was closed, or a bug of ours that's better logged than sent.
+    pub(crate) fn may_attach(&mut self) -> bool {
+        match self.lifecycle.transition(SurfaceRequest::Attach) {
+            Ok(()) => true,
+            Err(err) => {
+                log::debug!("not attaching: {err}");
+                false
+            }
+        }
+    }
+}
+
+impl Window {
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.state.lifecycle.stage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(reason: &'static str) -> Result<(), WindowError> {
+        Err(WindowError::InvalidState(reason))
+    }
+
+    //Role, initial commit and a configure acked: what apply_pending_configure starts from.
+    fn configured() -> LifecycleState {
+        let mut lifecycle = LifecycleState::default();
+        lifecycle.transition(SurfaceRequest::AssignRole).unwrap();
+        lifecycle.transition(SurfaceRequest::InitialCommit).unwrap();
+        lifecycle.configure_received(5);
+        lifecycle
+            .transition(SurfaceRequest::AckConfigure(5))
+            .unwrap();
+        lifecycle
+    }
+
+    #[test]
+    fn the_protocol_order_goes_through() {
+        let mut lifecycle = configured();
+        assert_eq!(lifecycle.stage(), Lifecycle::Configured);
+        lifecycle.transition(SurfaceRequest::Attach).unwrap();
+        assert_eq!(lifecycle.stage(), Lifecycle::Mapped);
+        //Later configures keep it mapped.
+        lifecycle.configure_received(9);
+        lifecycle
+            .transition(SurfaceRequest::AckConfigure(9))
+            .unwrap();
+        assert_eq!(lifecycle.stage(), Lifecycle::Mapped);
+    }
+
+    #[test]
+    fn attach_before_configure_is_refused() {
+        let mut lifecycle = LifecycleState::default();
+        let error = invalid("buffer attached before the first configure was acked");
+        assert_eq!(lifecycle.transition(SurfaceRequest::Attach), error);
+        lifecycle.transition(SurfaceRequest::AssignRole).unwrap();
+        lifecycle.transition(SurfaceRequest::InitialCommit).unwrap();
+        assert_eq!(lifecycle.transition(SurfaceRequest::Attach), error);
+        assert_eq!(lifecycle.stage(), Lifecycle::InitialCommitDone);
+    }
+
+    #[test]
+    fn a_second_role_is_refused() {
+        let mut lifecycle = LifecycleState::default();
+        lifecycle.transition(SurfaceRequest::AssignRole).unwrap();
+        assert_eq!(
+            lifecycle.transition(SurfaceRequest::AssignRole),
+            invalid("the surface already has a role")
+        );
+    }
+
+    #[test]
+    fn initial_commit_needs_a_role_and_happens_once() {
+        let mut lifecycle = LifecycleState::default();
+        assert_eq!(
+            lifecycle.transition(SurfaceRequest::InitialCommit),
+            invalid("initial commit before the surface has a role")
+        );
+        let mut lifecycle = configured();
+        assert_eq!(
+            lifecycle.transition(SurfaceRequest::InitialCommit),
+            invalid("initial commit done already")
+        );
+    }
+
+    #[test]
+    fn configures_are_acked_once_and_only_when_received() {
+        let mut lifecycle = LifecycleState::default();
+        lifecycle.transition(SurfaceRequest::AssignRole).unwrap();
+        lifecycle.configure_received(5);
+        assert_eq!(
+            lifecycle.transition(SurfaceRequest::AckConfigure(5)),
+            invalid("configure acked before the initial commit")
+        );
+
+        let mut lifecycle = configured();
+        assert_eq!(
+            lifecycle.transition(SurfaceRequest::AckConfigure(5)),
+            invalid("configure acked twice")
+        );
+        assert_eq!(
+            lifecycle.transition(SurfaceRequest::AckConfigure(6)),
+            invalid("acked a configure that never came")
+        );
+    }
+
+    #[test]
+    fn nothing_goes_after_close() {
+        let mut lifecycle = configured();
+        lifecycle.transition(SurfaceRequest::Close).unwrap();
+        lifecycle.configure_received(9);
+        for request in [
+            SurfaceRequest::Attach,
+            SurfaceRequest::AckConfigure(9),
+            SurfaceRequest::Close,
+        ] {
+            assert_eq!(
+                lifecycle.transition(request),
+                invalid("the window was closed")
+            );
+        }
+        assert_eq!(lifecycle.stage(), Lifecycle::Closed);
+    }
+}
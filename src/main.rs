@@ -1,342 +1,1300 @@
-use std::{fs::File, os::fd::AsFd};
-
-use tempfile::tempfile;
-use wayland_client::{
-    Connection, Dispatch, QueueHandle, WEnum, delegate_noop,
-    protocol::{
-        wl_buffer, wl_compositor, wl_keyboard, wl_registry,
-        wl_seat::{self},
-        wl_shm, wl_shm_pool, wl_surface,
-    },
-};
-use wayland_protocols::xdg::shell::client::{
-    xdg_surface,
-    xdg_toplevel::{self, XdgToplevel},
-    xdg_wm_base,
+use std::time::{Duration, Instant};
+
+use simple_wayland_window::{
+    Action, Capabilities, Color, ConnectOptions, GestureEvent, GradientView, IconData, Key,
+    KeyState, Mods, Rect, ScaleMath, SideDispatch, Transform, Window, WindowEvent, WindowOptions,
 };
 
-//Application State
-//Quoting wayland_client documentation:
-//"The core event dispatching logic provided by this crate is built around the EventQueue struct. In this paradigm, receiving and processing events is a two-step process:
-//
-//  - First, events are read from the Wayland socket. For each event, the backend figures out which EventQueue manages it, and enqueues the event in an internal buffer of that queue.
-//  - Then, the EventQueue empties its internal buffer by sequentially invoking the appropriate Dispatch::event() method on the State value that was provided to it.
-//
-//The main goal of this structure is to make your State accessible without synchronization to most of your event-processing logic, to reduce the plumbing costs.
-//
-struct AppState {
-    running: bool,
-    base_surface: Option<wl_surface::WlSurface>,
-    buffer: Option<wl_buffer::WlBuffer>,
-    wm_base: Option<xdg_wm_base::XdgWmBase>,
-    xdg_surface: Option<(xdg_surface::XdgSurface, xdg_toplevel::XdgToplevel)>,
-    configured: bool,
-}
+//linux/input-event-codes.h
+#[cfg(feature = "calloop")]
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
 
-impl AppState {
-    fn init_xdg_surface(&mut self, queue_handle: &QueueHandle<AppState>) {
-        //wm_base: Global object that enables clients to turn wl_surfaces into windows
-        //in the Desktop Environemnt
-        let wm_base = self.wm_base.as_ref().unwrap();
+mod args;
 
-        //base_surface here refers to the wl_surface
-        //WlSurfaces are a rectangle area that allows to receive user input, show
-        //wl_buffers and have local coordinate systems
-        let base_surface = self.base_surface.as_ref().unwrap();
+use args::{Args, Mode, USAGE};
 
-        //XdgSurfaces is an interface that may be implemented by a wl_surface
-        //if the implementation needs to provide a desktop-style user interface.
-        //
-        //Creating an XdgSurface requires you to set up your role-specific object
-        //by sending the application info (title, id, size, parent, etc) then
-        //performing an initial commit. This initial commit CANNOT have a buffer attached.
-        let xdg_surface = wm_base.get_xdg_surface(base_surface, queue_handle, ());
-        let toplevel = xdg_surface.get_toplevel(queue_handle, ());
+fn main() {
+    //Library warnings (stale input serials and such) show with RUST_LOG=warn.
+    env_logger::init();
 
-        toplevel.set_title("receba".into());
-        toplevel.set_app_id("EstamosAquiDaSilva.org".into());
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        //--help comes back as an empty error.
+        Err(err) if err.is_empty() => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
 
-        base_surface.commit();
+    match args.mode {
+        Mode::Demo => demo(args.options, args.frames, args.side_queue),
+        #[cfg(feature = "calloop")]
+        Mode::Loop => event_loop_example(args.options),
+        Mode::Epoll => epoll_example(args.options),
+        Mode::Transparent => transparent_example(args.options),
+        Mode::Dialog => dialog_example(args.options),
+        Mode::Transform => transform_example(args.options),
+        Mode::Overlay => overlay_example(args.options),
+        Mode::Threaded => threaded_example(args.options),
+        Mode::Animate => animate_example(args.options),
+        Mode::Blink => blink_example(args.options),
+        Mode::Fractional => fractional_example(args.options),
+        #[cfg(feature = "async")]
+        Mode::Async => async_example(args.options),
+        #[cfg(feature = "egl")]
+        Mode::Egl => egl_example(args.options),
+        #[cfg(feature = "raw-window-handle")]
+        Mode::Handles => handles_example(args.options),
+        #[cfg(feature = "image")]
+        Mode::Image => image_example(args.options, &args.image.unwrap()),
+        #[cfg(feature = "color-management")]
+        Mode::Hdr => hdr_example(args.options),
+        Mode::Probe => probe(),
+        Mode::ProbeStartup => probe_startup(args.options),
+    }
+}
 
-        self.xdg_surface = Some((xdg_surface, toplevel));
+//--probe: the compositor's protocols and versions, no window made.
+fn probe() {
+    match Capabilities::probe(ConnectOptions::default()) {
+        Ok(capabilities) => {
+            print!("{capabilities}");
+            if !capabilities.has_required() {
+                println!(
+                    "\nA window needs wl_compositor and xdg_wm_base, this compositor can't show \
+                     one."
+                );
+            }
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
     }
 }
 
-//The registry provides a list of global objects (protocols/interfaces) exposed by the compositor.
-//Here, we handle each advertised global and bind to the ones we need (e.g., wl_compositor, wl_shm).
-//Binding gives us a client-side handle to interact with that global object.
-//Each interface's events are handled in their respective `Dispatch` impls.
-//We need to implement Dispatch<O, _> to each O wayland object that needs to have their events processed.
-impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
-    fn event(
-        state: &mut Self,
-        registry: &wl_registry::WlRegistry,
-        event: wl_registry::Event,
-        _: &(),
-        _: &Connection,
-        queue_handle: &QueueHandle<AppState>,
-    ) {
-        if let wl_registry::Event::Global {
-            name,
-            interface,
-            version,
-        } = event
+//--probe-startup: from Window::with_options to the first frame on screen, to compare
+//compositors. Frames are redrawn until one is presented, the first one's feedback may say
+//discarded.
+fn probe_startup(options: WindowOptions) {
+    //Give up on presentation feedback after this many frames.
+    const FRAMES: u32 = 60;
+
+    let start = Instant::now();
+    let mut window = Window::with_options(WindowOptions {
+        startup_timing: true,
+        ..options
+    });
+    let connected = start.elapsed();
+    let presentation = window.capabilities().presentation.is_some();
+    let mut frames = 0;
+
+    while window.is_running() {
+        let timing = window.startup_timing();
+        if timing.time_to_first_frame_callback.is_some()
+            && (!presentation || timing.time_to_first_presented.is_some() || frames >= FRAMES)
+        {
+            break;
+        }
+        for event in window.pump_events() {
+            if let WindowEvent::Frame { .. } = event {
+                frames += 1;
+            }
+        }
+        //Every commit asks for presentation feedback, a frame callback keeps them coming.
+        let timing = window.startup_timing();
+        if timing.time_to_first_frame_callback.is_some() && timing.time_to_first_presented.is_none()
         {
-            match &interface[..] {
-                "wl_compositor" => {
-                    //wl_compositor: the compositor, responsible for creating the displayable
-                    //output of multiple surfaces.
-                    let compositor = registry.bind::<wl_compositor::WlCompositor, _, _>(
-                        name,
-                        version,
-                        queue_handle,
-                        (),
-                    );
-
-                    let surface = compositor.create_surface(queue_handle, ());
-                    state.base_surface = Some(surface);
-
-                    if state.wm_base.is_some() && state.xdg_surface.is_none() {
-                        state.init_xdg_surface(queue_handle);
+            window.request_frame();
+            window.redraw_background();
+        }
+    }
+
+    let timing = window.startup_timing();
+    let show = |time: Option<Duration>| match time {
+        Some(time) => format!("{:.2} ms", time.as_secs_f64() * 1000.0),
+        None => "-".into(),
+    };
+    println!("connect                       {}", show(Some(connected)));
+    println!(
+        "time_to_first_commit          {}",
+        show(timing.time_to_first_commit)
+    );
+    println!(
+        "time_to_first_frame_callback  {}",
+        show(timing.time_to_first_frame_callback)
+    );
+    println!(
+        "time_to_first_presented       {}{}",
+        show(timing.time_to_first_presented),
+        if presentation {
+            ""
+        } else {
+            " (no wp_presentation)"
+        }
+    );
+    println!(
+        "first frame drawn ahead       {}",
+        if timing.prerendered { "yes" } else { "no" }
+    );
+}
+
+//The interactive demo: keys, pointer constraints, gestures...
+fn demo(options: WindowOptions, frames: Option<u32>, side_queue: Option<SideDispatch>) {
+    //The window connects, binds the globals and sets up the surface for us.
+    let mut window = Window::with_options(options);
+    //--side-queue: relative motion added up on a queue of its own, see side_queue.rs.
+    if let Some(dispatch) = side_queue
+        && let Err(err) = window.isolate_relative_pointer(dispatch)
+    {
+        println!("Relative motion stays on the main queue: {err}");
+    }
+    //Escape still quits, Ctrl+Q too. F11 goes fullscreen and back.
+    window.bind_key_with_mods(Mods::CTRL, Key::Q, Action::Quit);
+    window.bind_key(Key::F11, Action::ToggleFullscreen);
+
+    //--frames: how many frame callbacks are left, and when the first one was asked for.
+    let mut frames_left = frames;
+    let mut frame_in_flight = false;
+    let mut frames_start = None;
+
+    //Zoom when the current pinch started, the pinch scale is relative to it.
+    let mut zoom_at_pinch_begin = 1.0;
+
+    //Where the pointer is, for the window menu.
+    let mut pointer_position = (0.0, 0.0);
+
+    //Lets worker threads hand results back to this loop.
+    let proxy = window.create_proxy::<GradientView>().unwrap();
+
+    //Application loop
+    while window.is_running() {
+        //--frames: keep one frame callback in flight. Each one comes with a redraw, a compositor
+        //may not bother repainting (and calling back) an unchanged surface.
+        if frames_left.is_some() && !frame_in_flight && window.is_configured() {
+            frames_start.get_or_insert_with(Instant::now);
+            window.request_frame();
+            window.set_gradient_view(window.gradient_view());
+            frame_in_flight = true;
+        }
+
+        for event in window.pump_events() {
+            match event {
+                WindowEvent::Frame { .. } => {
+                    frame_in_flight = false;
+                    if let (Some(left), Some(total), Some(start)) =
+                        (frames_left.as_mut(), frames, frames_start)
+                    {
+                        *left -= 1;
+                        if *left == 0 {
+                            let elapsed = start.elapsed();
+                            let fps = f64::from(total) / elapsed.as_secs_f64();
+                            let configures = window.configure_stats();
+                            println!(
+                                "{total} frames in {elapsed:.2?} ({fps:.1} fps), {} of {} \
+                                 configures drawn",
+                                configures.applied, configures.received
+                            );
+                            window.close();
+                        }
+                    }
+                }
+                WindowEvent::Key {
+                    seat,
+                    key,
+                    time,
+                    serial,
+                    state: KeyState::Pressed,
+                    ..
+                } => {
+                    println!("Key {key:?} did smth! on {seat}, time: {time}. Serial: {serial}");
+
+                    //L locks the pointer (first-person-camera style), C confines it to the window.
+                    //Escape releases either of them before it quits.
+                    //F fills the window with a solid color, G brings the gradient back.
+                    //W has a worker thread "compute" a new view and send it back.
+                    //I sets a generated task bar icon.
+                    let result = match key {
+                        Key::L => window.lock_pointer(),
+                        Key::C => window.confine_pointer(None),
+                        Key::F => window.fill_color(0, 0x4000_0000, 0x8000_0000, u32::MAX),
+                        Key::G => {
+                            window.set_gradient_view(window.gradient_view());
+                            Ok(())
+                        }
+                        Key::W => {
+                            let proxy = proxy.clone();
+                            let mut view = window.gradient_view();
+                            std::thread::spawn(move || {
+                                std::thread::sleep(Duration::from_millis(500));
+                                view.zoom = if view.zoom < 4.0 {
+                                    view.zoom * 2.0
+                                } else {
+                                    1.0
+                                };
+                                let _ = proxy.send_event(view);
+                            });
+                            Ok(())
+                        }
+                        Key::I => window.set_icon(gradient_icon(64)),
+                        _ => Ok(()),
+                    };
+                    if let Err(err) = result {
+                        println!("Couldn't do that: {err}");
+                    }
+                }
+                WindowEvent::PointerEntered { x, y, .. }
+                | WindowEvent::PointerMoved { x, y, .. } => {
+                    pointer_position = (x, y);
+                }
+                //There's no title bar to click, so the whole window acts as one: right-click
+                //opens the compositor's window menu.
+                WindowEvent::PointerButton {
+                    button: BTN_RIGHT,
+                    pressed: true,
+                    ..
+                } => {
+                    let (x, y) = pointer_position;
+                    if let Err(err) = window.show_window_menu(x, y, None) {
+                        println!("Couldn't show the window menu: {err}");
+                    }
+                }
+                WindowEvent::PointerLocked => println!("Pointer locked, press Escape to unlock"),
+                //While locked this is the only motion we get, what a camera would turn with.
+                WindowEvent::RelativeMotion {
+                    dx_unaccel,
+                    dy_unaccel,
+                    synthetic,
+                    ..
+                } if window.is_pointer_locked() => {
+                    println!("Look by ({dx_unaccel:.1}, {dy_unaccel:.1}), synthetic: {synthetic}")
+                }
+                //Pinch zooms the gradient, swipe pans it.
+                WindowEvent::Gesture { gesture, .. } => {
+                    let mut view = window.gradient_view();
+                    match gesture {
+                        GestureEvent::PinchBegin { .. } => zoom_at_pinch_begin = view.zoom,
+                        GestureEvent::PinchUpdate { scale, dx, dy, .. } => {
+                            view.zoom = (zoom_at_pinch_begin * scale).clamp(0.25, 16.0);
+                            view.pan.0 += dx / view.zoom;
+                            view.pan.1 += dy / view.zoom;
+                        }
+                        GestureEvent::SwipeUpdate { dx, dy } => {
+                            view.pan.0 += dx / view.zoom;
+                            view.pan.1 += dy / view.zoom;
+                        }
+                        _ => {}
                     }
+                    if view != window.gradient_view() {
+                        window.set_gradient_view(view);
+                    }
+                }
+                //Scrolling zooms into the gradient around the pointer, sideways scrolling pans.
+                WindowEvent::Scroll { dx, dy, .. } => {
+                    scroll_zoom(&mut window, pointer_position, dx, dy)
+                }
+                WindowEvent::FocusGained { seat, pressed_keys } => {
+                    println!("{seat} got keyboard focus, keys already down: {pressed_keys:?}")
+                }
+                WindowEvent::FocusLost { seat } => println!("{seat} lost keyboard focus"),
+                WindowEvent::KeyboardLayoutChanged { seat, layout } => println!(
+                    "{seat} switched to layout {} ({})",
+                    layout.index,
+                    layout.name.as_deref().unwrap_or("unnamed")
+                ),
+                WindowEvent::Activated => println!("Window activated"),
+                WindowEvent::Deactivated => println!("Window deactivated"),
+                WindowEvent::PointerConfined => {
+                    println!("Pointer confined, press Escape to release")
                 }
-                "wl_shm" => {
-                    //shm: this singleton provides support for shared memory. Clients are able to
-                    //create wl_shm_pools using the create_pool request.
-                    let shm = registry.bind::<wl_shm::WlShm, _, _>(name, version, queue_handle, ());
-
-                    let (initial_width, initial_height) = (320, 240);
-
-                    let mut file = tempfile().unwrap();
-
-                    draw(&mut file, (initial_width, initial_height));
-
-                    //wl_shm_pool: this object encapsulates a piece of memory shared between the compositor and
-                    //client.
-                    //
-                    //With wl_shm_pool, the client can allocate shared memory wl_buffer objects.
-                    //If you create an object through the same pool it will share the same mapped memory.
-                    //As per documentation: "Reusing the mapped memory avoids the setup/teardown overhead and is
-                    //useful when: interactively resizing a surface OR when using many small buffers."
-                    let pool = shm.create_pool(
-                        file.as_fd(),
-                        (initial_width * initial_height * 4) as i32,
-                        queue_handle,
-                        (),
-                    );
-
-                    //Quoting documentation: "A buffer provides the content for a wl_surface.
-                    //Buffers are created through factory interfaces such as wl_shm, wp_linux_buffer_params
-                    //(from the linux-dmabuf protocol extension) or similar. It has a width and a height
-                    //and can be attached to a wl_surface, but the mechanism by which a client provides and
-                    //updates the contents is defined by the buffer factory interface."
-                    let buffer = pool.create_buffer(
-                        0,
-                        initial_width as i32,
-                        initial_height as i32,
-                        (initial_width * 4) as i32,
-                        wl_shm::Format::Argb8888,
-                        queue_handle,
-                        (),
-                    );
-
-                    state.buffer = Some(buffer.clone());
-
-                    if state.configured {
-                        let surface = state.base_surface.as_ref().unwrap();
-                        surface.attach(Some(&buffer), 0, 0);
-                        surface.commit();
+                WindowEvent::User(event) => {
+                    if let Some(view) = event.take::<GradientView>() {
+                        window.set_gradient_view(view);
+                    }
+                }
+                //Restart the compositor (or a nested one) to see this: the window comes back
+                //with its title, size and view if a compositor is there again.
+                WindowEvent::ConnectionLost => {
+                    match window.protocol_error() {
+                        Some(error) => println!("Lost the compositor over {error}, reconnecting"),
+                        None => println!("Lost the compositor, reconnecting"),
+                    }
+                    if let Err(err) = window.reconnect() {
+                        println!("Couldn't reconnect: {err}");
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    //--frames measures, a fade would only add to the time.
+    if frames.is_none() {
+        fade_out(&mut window);
+    }
+}
+
+//Zoom and pan with the viewport's crop alone, the gradient is never redrawn: a detent (48
+//pixels with the default ScrollConfig) zooms by about a fifth, keeping what's under the pointer
+//under it. Back at 1:1 the crop goes.
+fn scroll_zoom(window: &mut Window, (px, py): (f64, f64), dx: f64, dy: f64) {
+    let (width, height) = window.surface_size();
+    let (width, height) = (f64::from(width), f64::from(height));
+    let (x, y, w, h) = window
+        .viewport_source()
+        .unwrap_or((0.0, 0.0, width, height));
+    //The point of the buffer under the pointer.
+    let (ax, ay) = (x + px / width * w, y + py / height * h);
+    let zoom = (width / w * (-dy / 240.0).exp()).clamp(1.0, 16.0);
+    let (w, h) = (width / zoom, height / zoom);
+    let x = (ax - px / width * w + dx / zoom).clamp(0.0, width - w);
+    let y = (ay - py / height * h).clamp(0.0, height - h);
+    if let Err(err) = window.set_viewport_source(x, y, w, h) {
+        println!("Couldn't zoom: {err}");
+    }
+}
+
+//A quarter second fade before the window goes. With wp_alpha_modifier_v1 every step is a commit,
+//without it the gradient is redrawn each time (the slow path). Paced by a sleep, not frame
+//callbacks: a hidden window gets none and would never finish fading.
+fn fade_out(window: &mut Window) {
+    const FADE: Duration = Duration::from_millis(250);
+    if !window.is_configured() {
+        return;
+    }
+    let start = Instant::now();
+    while start.elapsed() < FADE {
+        window.set_opacity(1.0 - start.elapsed().as_secs_f32() / FADE.as_secs_f32());
+        window.poll_events();
+        std::thread::sleep(Duration::from_millis(16));
+    }
+}
+
+//A round, gradient-filled icon. The corners are transparent, so the compositor's background shows.
+fn gradient_icon(size: u32) -> IconData {
+    let radius = size as f32 / 2.0;
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let (dx, dy) = (x as f32 + 0.5 - radius, y as f32 + 0.5 - radius);
+            let inside = dx * dx + dy * dy <= radius * radius;
+            let alpha = if inside { 0xFF } else { 0 };
+            rgba.extend([(x * 255 / size) as u8, (y * 255 / size) as u8, 0xC0, alpha]);
+        }
+    }
+    IconData {
+        width: size,
+        height: size,
+        rgba,
+    }
+}
+
+//cargo run -- --transparent
+//A 50% transparent rounded rectangle on a fully transparent window. Over a checkerboard (or any
+//busy) wallpaper the squares should show through evenly dimmed, with no bright halo around the
+//soft edge: that halo is what straight alpha in a premultiplied buffer looks like.
+fn transparent_example(options: WindowOptions) {
+    let mut window = Window::with_options(options);
+    while window.is_running() {
+        //The buffer comes with the first configure, and a new one with every new size.
+        let resized = window
+            .pump_events()
+            .iter()
+            .any(|event| matches!(event, WindowEvent::Resized { .. }));
+        if resized {
+            draw_rounded_rectangle(&mut window);
+        }
+    }
+}
+
+fn draw_rounded_rectangle(window: &mut Window) {
+    const INSET: f32 = 20.0;
+    const RADIUS: f32 = 24.0;
+
+    window.draw(|canvas| {
+        let (width, height) = (canvas.width() as f32, canvas.height() as f32);
+        for y in 0..canvas.height() {
+            for x in 0..canvas.width() {
+                //Distance from the pixel center to the rounded rectangle, negative inside.
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let dx = (px - width / 2.0).abs() - (width / 2.0 - INSET - RADIUS);
+                let dy = (py - height / 2.0).abs() - (height / 2.0 - INSET - RADIUS);
+                let outside = dx.max(0.0).hypot(dy.max(0.0)) + dx.max(dy).min(0.0);
+                let distance = outside - RADIUS;
+
+                //One pixel wide antialiased edge, on top of the 50% the whole shape has.
+                let coverage = (0.5 - distance).clamp(0.0, 1.0);
+                let alpha = (coverage * 128.0).round() as u8;
+                canvas.put_pixel_unpremultiplied(x, y, 0x30, 0x90, 0xF0, alpha);
+            }
+        }
+    });
+}
+
+//cargo run -- --transform
+//An F drawn into the buffer turned the way the buffer transform says, so the compositor turning it
+//back shows it upright and unmirrored under all eight. T goes to the next transform. A sideways
+//or mirrored F is a transform someone got wrong.
+fn transform_example(options: WindowOptions) {
+    let mut window = Window::with_options(options);
+    let mut index = 0;
+
+    while window.is_running() {
+        for event in window.pump_events() {
+            if let WindowEvent::Resized { .. } = event {
+                draw_f(&mut window);
+            } else if let WindowEvent::Key {
+                key: Key::T,
+                state: KeyState::Pressed,
+                ..
+            } = event
+            {
+                index = (index + 1) % Transform::ALL.len();
+                let transform = Transform::ALL[index];
+                match window.set_buffer_transform(transform) {
+                    Ok(()) => {
+                        println!("{transform:?}, window {:?}", window.size());
+                        draw_f(&mut window);
                     }
+                    Err(err) => println!("Couldn't set {transform:?}: {err}"),
                 }
-                "wl_seat" => {
-                    //wl_seat: A seat is a greoup of input devices (mouse, keyboard, touch).
-                    //Quoting documentation: "A seat is published during start up, or when a device is hot plugged. A seat
-                    //typically has a pointer and maintains a keyboard focus and a pointer focus"
-                    registry.bind::<wl_seat::WlSeat, _, _>(name, version, queue_handle, ());
+            }
+        }
+    }
+}
+
+//A dark F on white, laid out in surface coordinates: a bar on the left, a long arm at the top and
+//a shorter one in the middle. Each buffer pixel gets what the surface shows at its place.
+fn draw_f(window: &mut Window) {
+    let transform = window.buffer_transform();
+    window.draw(|canvas| {
+        let buffer_size = (canvas.width(), canvas.height());
+        let (width, height) = transform.apply_to_size(buffer_size);
+        for y in 0..canvas.height() {
+            for x in 0..canvas.width() {
+                let (sx, sy) = transform.buffer_to_surface((x, y), buffer_size);
+                let (fx, fy) = (sx as f32 / width as f32, sy as f32 / height as f32);
+                let bar = (0.3..0.4).contains(&fx) && (0.2..0.8).contains(&fy);
+                let top = (0.3..0.7).contains(&fx) && (0.2..0.3).contains(&fy);
+                let middle = (0.3..0.6).contains(&fx) && (0.45..0.55).contains(&fy);
+                let color = if bar || top || middle {
+                    Color::opaque(0x20, 0x20, 0x20)
+                } else {
+                    Color::opaque(0xF0, 0xF0, 0xF0)
+                };
+                canvas.put_pixel(x, y, color);
+            }
+        }
+    });
+}
+
+//cargo run -- --overlay
+//A fully transparent window with a button in it. Only the button takes clicks, the rest of the
+//window lets them through to what's below. Clicking the button makes the whole window
+//click-through, C (the window keeps the keyboard focus) brings the button back.
+fn overlay_example(options: WindowOptions) {
+    const BUTTON: Rect = Rect {
+        x: 20,
+        y: 20,
+        width: 120,
+        height: 40,
+    };
+
+    let mut window = Window::with_options(options);
+    window.set_input_region(Some(&[BUTTON]));
+
+    while window.is_running() {
+        for event in window.pump_events() {
+            match event {
+                WindowEvent::Resized { .. } => window.draw(|canvas| {
+                    canvas.fill_rect(BUTTON, Color::opaque(0xE0, 0x40, 0x40));
+                }),
+                WindowEvent::PointerButton { pressed: true, .. } => {
+                    println!("Button clicked, the window is click-through now. C to undo.");
+                    window.set_input_region(Some(&[]));
+                }
+                WindowEvent::Key {
+                    key: Key::C,
+                    state: KeyState::Pressed,
+                    ..
+                } => {
+                    println!("The button takes clicks again.");
+                    window.set_input_region(Some(&[BUTTON]));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+//cargo run -- --threaded
+//A bar sweeping across the window, each frame taking a 10 ms busy loop on a thread of its own.
+//Keys pressed meanwhile are printed as soon as they come in, not after the frame.
+fn threaded_example(options: WindowOptions) {
+    let window = Window::with_options(options);
+    let (mut window, mut render) = match window.split() {
+        Ok(halves) => halves,
+        Err(err) => {
+            eprintln!("Can't split the window: {err}");
+            return;
+        }
+    };
+
+    let renderer = std::thread::spawn(move || {
+        let (width, height) = render.size();
+        let mut frames = 0u32;
+        loop {
+            let x = frames * 4 % width;
+            let drawn = render.render(|canvas| {
+                let busy = Instant::now();
+                while busy.elapsed() < Duration::from_millis(10) {
+                    std::hint::spin_loop();
                 }
-                "xdg_wm_base" => {
-                    //Quoting documentation: The xdg_wm_base interface is exposed as a global object enabling clients
-                    //to turn their wl_surfaces into windows in a desktop environment. It defines the basic functionality
-                    //needed for clients and the compositor to create windows that can be dragged, resized, maximized,
-                    //etc, as well as creating transient windows such as popup menus.
-                    //
-                    let wm_base = registry.bind::<xdg_wm_base::XdgWmBase, _, _>(
-                        name,
-                        version,
-                        queue_handle,
-                        (),
-                    );
-
-                    state.wm_base = Some(wm_base);
-
-                    if state.base_surface.is_some() && state.xdg_surface.is_none() {
-                        state.init_xdg_surface(queue_handle);
+                canvas.clear(Color::opaque(0x20, 0x20, 0x30));
+                let bar = Rect {
+                    x: x as i32,
+                    y: 0,
+                    width: 40.min(width - x) as i32,
+                    height: height as i32,
+                };
+                canvas.fill_rect(bar, Color::opaque(0x40, 0xA0, 0xE0));
+            });
+            //Err once the window is gone.
+            if drawn.is_err() {
+                return frames;
+            }
+            frames += 1;
+        }
+    });
+
+    let start = Instant::now();
+    while window.is_running() {
+        for event in window.pump_events() {
+            if let WindowEvent::Key {
+                key,
+                state: KeyState::Pressed,
+                ..
+            } = event
+            {
+                println!("{key:?} at {:?}", start.elapsed());
+            }
+        }
+    }
+
+    drop(window);
+    let frames = renderer.join().unwrap_or(0);
+    println!("{frames} frames in {:?}", start.elapsed());
+}
+
+//cargo run -- --animate
+//The gradient's hues turn with the frame callbacks' timestamps. Each callback asks for the next
+//one and draws, so it runs at the compositor's pace and never faster. A hidden window gets no
+//callbacks and a suspended one stops asking, either way it sits at zero CPU until it's back.
+//Prints the frame rate every 5 seconds.
+//
+//The whole buffer is damaged: every pixel changes hue each frame.
+fn animate_example(options: WindowOptions) {
+    let mut window = Window::with_options(options);
+    let mut frame_in_flight = false;
+    //Timestamp of the latest callback, in milliseconds.
+    let mut time = 0u32;
+    //Frames since the last print and the timestamp they started at.
+    let mut frames = 0u32;
+    let mut since = None;
+
+    while window.is_running() {
+        if !frame_in_flight && window.is_configured() && !window.is_suspended() {
+            window.request_frame();
+            draw_hues(&mut window, time);
+            frame_in_flight = true;
+        }
+
+        for event in window.pump_events() {
+            match event {
+                WindowEvent::Frame { time: now } => {
+                    frame_in_flight = false;
+                    time = now;
+                    let start = *since.get_or_insert(now);
+                    frames += 1;
+                    //Milliseconds wrap around every 49 days.
+                    let elapsed = now.wrapping_sub(start);
+                    if elapsed >= 5000 {
+                        let fps = f64::from(frames - 1) * 1000.0 / f64::from(elapsed);
+                        println!("{fps:.1} fps");
+                        (frames, since) = (1, Some(now));
                     }
                 }
+                //The new buffer starts out as the plain gradient.
+                WindowEvent::Resized { .. } => draw_hues(&mut window, time),
+                WindowEvent::Suspended => println!("Suspended, no more frames"),
+                //The time away doesn't count towards the frame rate.
+                WindowEvent::Resumed => {
+                    println!("Resumed");
+                    (frames, since) = (0, None);
+                }
+                _ => {}
+            }
+        }
+    }
+}
 
-                //No need to bind other protocols so we just don't bind them.
+//A text cursor blinking every half second, moved right by any key and left by Backspace. Only
+//where it was and where it is are damaged, draw_damaged adds what the buffer drawn into missed:
+//with the buffers taking turns there would be cursors left behind otherwise. Prints how old the
+//buffers drawn into were when it quits.
+fn blink_example(options: WindowOptions) {
+    const BACKGROUND: Color = Color::opaque(0xF0, 0xF0, 0xE8);
+    const CURSOR: Color = Color::opaque(0x20, 0x20, 0x20);
+    let mut window = Window::with_options(options);
+    let mut frame_in_flight = false;
+    let mut column = 0i32;
+    //Where the cursor was last drawn and whether it showed.
+    let mut drawn: Option<(Rect, bool)> = None;
+    let mut visible = true;
+    let mut ages = std::collections::BTreeMap::new();
+
+    while window.is_running() {
+        if !frame_in_flight && window.is_configured() && !window.is_suspended() {
+            window.request_frame();
+            frame_in_flight = true;
+        }
+        for event in window.pump_events() {
+            match event {
+                WindowEvent::Frame { time } => {
+                    frame_in_flight = false;
+                    visible = (time / 500) % 2 == 0;
+                }
+                WindowEvent::Key {
+                    key,
+                    state: KeyState::Pressed,
+                    ..
+                } => {
+                    column = if key == Key::Backspace {
+                        (column - 1).max(0)
+                    } else {
+                        (column + 1) % 40
+                    };
+                    visible = true;
+                }
+                //A new buffer, everything is drawn again.
+                WindowEvent::Resized { .. } => drawn = None,
                 _ => {}
             }
         }
+        if !window.is_configured() {
+            continue;
+        }
+        let cursor = window
+            .scale_math()
+            .rect_to_physical(Rect::new(8 + column * 10, 8, 2, 20));
+        if drawn == Some((cursor, visible)) {
+            continue;
+        }
+        let damage: Vec<Rect> = drawn
+            .map(|(old, _)| old)
+            .into_iter()
+            .chain([cursor])
+            .collect();
+        window.draw_damaged(&damage, |canvas, age, repaint| {
+            *ages.entry(age).or_insert(0u32) += 1;
+            for rect in repaint {
+                canvas.fill_rect(*rect, BACKGROUND);
+            }
+            if visible {
+                canvas.fill_rect(cursor, CURSOR);
+            }
+        });
+        drawn = Some((cursor, visible));
+    }
+    for (age, count) in ages {
+        println!("{count} draws into a buffer of age {age}");
     }
 }
 
-impl Dispatch<xdg_wm_base::XdgWmBase, ()> for AppState {
-    fn event(
-        _: &mut Self,
-        wm_base: &xdg_wm_base::XdgWmBase,
-        event: xdg_wm_base::Event,
-        _: &(),
-        _: &Connection,
-        _: &QueueHandle<AppState>,
-    ) {
-        if let xdg_wm_base::Event::Ping { serial } = event {
-            wm_base.pong(serial);
+//Full saturation hues across, darker towards the bottom, turned by a full circle every 10 seconds.
+fn draw_hues(window: &mut Window, time: u32) {
+    window.draw(|canvas| {
+        let (width, height) = (canvas.width(), canvas.height());
+        let turn = f64::from(time % 10_000) / 10_000.0;
+        let hues: Vec<_> = (0..width)
+            .map(|x| hue(f64::from(x) / f64::from(width) + turn))
+            .collect();
+        let mut row = vec![0; width as usize * 4];
+        for y in 0..height {
+            let value = 1.0 - 0.5 * f64::from(y) / f64::from(height);
+            for (pixel, (r, g, b)) in row.chunks_exact_mut(4).zip(&hues) {
+                let channel = |c: f64| (c * value * 255.0) as u8;
+                pixel.copy_from_slice(
+                    &Color::opaque(channel(*r), channel(*g), channel(*b)).to_argb8888_bytes(),
+                );
+            }
+            canvas.copy_from_slice(y, &row);
         }
+    });
+}
+
+//HSV to RGB with saturation and value 1, `turns` being the hue (1 is all the way around).
+fn hue(turns: f64) -> (f64, f64, f64) {
+    let h = turns.rem_euclid(1.0) * 6.0;
+    let channel = |n: f64| {
+        let k = (n + h) % 6.0;
+        1.0 - k.min(4.0 - k).clamp(0.0, 1.0)
+    };
+    (channel(5.0), channel(3.0), channel(1.0))
+}
+
+//cargo run -- --fractional
+//wp_fractional_scale_v1 isn't wrapped by the library, so it's bound on an event queue of our own
+//with our own Dispatch impls, next to the window's queue (see protocol_objects.rs).
+fn fractional_example(options: WindowOptions) {
+    use wayland_client::{
+        Connection, Dispatch, QueueHandle,
+        globals::{GlobalListContents, registry_queue_init},
+        protocol::wl_registry::{self, WlRegistry},
+    };
+    use wayland_protocols::wp::fractional_scale::v1::client::{
+        wp_fractional_scale_manager_v1::{self, WpFractionalScaleManagerV1},
+        wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+    };
+
+    #[derive(Default)]
+    struct Extra {
+        //In 120ths, as the protocol sends it.
+        scale: Option<u32>,
     }
+
+    //Globals coming and going later are of no interest here.
+    impl Dispatch<WlRegistry, GlobalListContents> for Extra {
+        fn event(
+            _: &mut Self,
+            _: &WlRegistry,
+            _: wl_registry::Event,
+            _: &GlobalListContents,
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<WpFractionalScaleManagerV1, ()> for Extra {
+        fn event(
+            _: &mut Self,
+            _: &WpFractionalScaleManagerV1,
+            _: wp_fractional_scale_manager_v1::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<WpFractionalScaleV1, ()> for Extra {
+        fn event(
+            state: &mut Self,
+            _: &WpFractionalScaleV1,
+            event: wp_fractional_scale_v1::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+            if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+                state.scale = Some(scale);
+            }
+        }
+    }
+
+    let mut window = Window::with_options(options);
+    //Fractional scaling takes the place of the integer one, buffers stay at scale 1.
+    window.set_apply_preferred_scale(false);
+    //The wl_surface is there after the first dispatch.
+    window.pump_events();
+
+    let (globals, mut queue) = match registry_queue_init::<Extra>(window.connection()) {
+        Ok(init) => init,
+        Err(err) => {
+            eprintln!("Registry: {err}");
+            return;
+        }
+    };
+    let manager: WpFractionalScaleManagerV1 = match globals.bind(&queue.handle(), 1..=1, ()) {
+        Ok(manager) => manager,
+        Err(err) => {
+            eprintln!("No fractional scale: {err}");
+            return;
+        }
+    };
+    let surface = window.wl_surface().expect("bound in the first dispatch");
+    let fractional = manager.get_fractional_scale(surface, &queue.handle(), ());
+
+    let mut extra = Extra::default();
+    let mut printed = None;
+    while window.is_running() {
+        window.pump_events();
+        if let Err(err) = queue.dispatch_pending(&mut extra) {
+            eprintln!("Dispatch: {err}");
+            break;
+        }
+        if extra.scale != printed {
+            printed = extra.scale;
+            if let Some(scale) = extra.scale {
+                let scale = ScaleMath::fractional(scale);
+                let (width, height) = scale.size_to_physical(window.size());
+                println!(
+                    "Preferred scale {:.3}, a {width}x{height} buffer for the window",
+                    scale.factor()
+                );
+            }
+        }
+    }
+    fractional.destroy();
+    manager.destroy();
 }
 
-impl Dispatch<xdg_surface::XdgSurface, ()> for AppState {
-    fn event(
-        state: &mut Self,
-        surface_xdg: &xdg_surface::XdgSurface,
-        event: xdg_surface::Event,
-        _: &(),
-        _: &Connection,
-        _: &QueueHandle<AppState>,
-    ) {
-        if let xdg_surface::Event::Configure { serial } = event {
-            surface_xdg.ack_configure(serial);
-            state.configured = true;
-
-            let base_surface = state.base_surface.as_ref().unwrap();
-            if let Some(ref buffer) = state.buffer {
-                base_surface.attach(Some(buffer), 0, 0);
-                base_surface.commit();
+//cargo run --features image -- --image picture.png
+//The picture fitted into the window, on a transparent background.
+#[cfg(feature = "image")]
+fn image_example(options: WindowOptions, path: &std::path::Path) {
+    let mut window = Window::with_options(options);
+    while window.is_running() {
+        for event in window.pump_events() {
+            if let WindowEvent::Resized { .. } = event
+                && let Err(err) = window.show_image(path)
+            {
+                eprintln!("{err}");
+                std::process::exit(1);
             }
         }
     }
 }
 
-impl Dispatch<xdg_toplevel::XdgToplevel, ()> for AppState {
-    fn event(
-        state: &mut Self,
-        _: &XdgToplevel,
-        event: xdg_toplevel::Event,
-        _: &(),
-        _: &Connection,
-        _: &QueueHandle<AppState>,
-    ) {
-        if let xdg_toplevel::Event::Close = event {
-            state.running = false;
+//cargo run --features color-management -- --hdr
+//For compositor developers: the surface tagged BT.2020/PQ, showing ramps of white, red, green and
+//blue from 0 to the full PQ signal (10000 cd/m²) left to right. 8 bits per channel, so the steps
+//are coarse. What the compositor supports and would prefer are printed as they come.
+#[cfg(feature = "color-management")]
+fn hdr_example(options: WindowOptions) {
+    use simple_wayland_window::{ColorDescription, RenderIntent};
+
+    let mut window = Window::with_options(options);
+    let mut tagged = false;
+    while window.is_running() {
+        for event in window.pump_events() {
+            match event {
+                WindowEvent::Resized { .. } => draw_pq_ramps(&mut window),
+                WindowEvent::PreferredColorChanged(info) => println!("Preferred: {info:?}"),
+                _ => {}
+            }
+        }
+        //The capabilities come a roundtrip after the bind.
+        if !tagged && let Some(capabilities) = window.color_capabilities() {
+            println!("Supported: {capabilities:?}");
+            tagged = true;
+            if let Err(err) = window
+                .set_color_description(Some(ColorDescription::BT2100_PQ), RenderIntent::Perceptual)
+            {
+                eprintln!("{err}, the ramps show untagged");
+            }
+        }
+        if !tagged && window.is_configured() && !window.color_management_supported() {
+            eprintln!("no wp_color_manager_v1, the ramps show untagged");
+            tagged = true;
         }
     }
 }
 
-impl Dispatch<wl_seat::WlSeat, ()> for AppState {
-    fn event(
-        _: &mut Self,
-        seat: &wl_seat::WlSeat,
-        event: <wl_seat::WlSeat as wayland_client::Proxy>::Event,
-        _: &(),
-        _: &Connection,
-        queue_handle: &QueueHandle<Self>,
-    ) {
-        if let wl_seat::Event::Capabilities {
-            capabilities: WEnum::Value(capabilities),
-        } = event
-        {
-            if capabilities.contains(wl_seat::Capability::Keyboard) {
-                seat.get_keyboard(queue_handle, ());
+#[cfg(feature = "color-management")]
+fn draw_pq_ramps(window: &mut Window) {
+    window.draw(|canvas| {
+        let (width, height) = (canvas.width(), canvas.height());
+        for y in 0..height {
+            let band = y * 4 / height;
+            for x in 0..width {
+                //The PQ signal itself, the compositor decodes it.
+                let signal = (x * 255 / width.saturating_sub(1).max(1)).min(255) as u8;
+                let (r, g, b) = match band {
+                    0 => (signal, signal, signal),
+                    1 => (signal, 0, 0),
+                    2 => (0, signal, 0),
+                    _ => (0, 0, signal),
+                };
+                canvas.put_pixel_unpremultiplied(x, y, r, g, b, 0xFF);
+            }
+        }
+    });
+}
+
+//cargo run --features calloop -- --loop
+//The EventLoop: a timer slowly pans the gradient, once per screen refresh, while keys repeat when
+//held, and so does the left button in the top left corner, like a scrollbar arrow. The pan stops
+//while the window is suspended (e.g. minimized), and steps once a second after 10 seconds without
+//input.
+#[cfg(feature = "calloop")]
+fn event_loop_example(options: WindowOptions) {
+    use simple_wayland_window::TimeoutAction;
+
+    let mut event_loop = match Window::with_options(options).into_event_loop() {
+        Ok(event_loop) => event_loop,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    let handle = event_loop.handle();
+    handle.set_idle_multiplier(60);
+    //Half a second without dispatching is well before any compositor's ping timeout.
+    event_loop
+        .window_mut()
+        .set_watchdog(Some(Duration::from_millis(500)));
+    let window = event_loop.window_mut();
+    window.pointer_button_repeat(Duration::from_millis(400), Duration::from_millis(50));
+    window.set_repeat_regions(&[(BTN_LEFT, Rect::new(0, 0, 64, 64))]);
+    event_loop
+        .window_mut()
+        .on_idle(Duration::from_secs(10), |_, idle| {
+            println!(
+                "{}",
+                if idle {
+                    "Idle, the pan slows down"
+                } else {
+                    "Active again"
+                }
+            );
+        });
+
+    let mut last = Instant::now();
+    handle.insert_animation_timer(Duration::ZERO, move |window| {
+        //40 pixels a second, however often this runs.
+        let now = Instant::now();
+        if window.is_configured() {
+            let mut view = window.gradient_view();
+            view.pan.0 += 40.0 * (now - last).as_secs_f64();
+            window.set_gradient_view(view);
+        }
+        last = now;
+        TimeoutAction::NextFrame
+    });
+
+    event_loop.run(|event, _| match event {
+        WindowEvent::Key {
+            key,
+            state: KeyState::Pressed,
+            ..
+        } => println!("Key {key:?} pressed"),
+        WindowEvent::KeyRepeat { key, .. } => println!("Key {key:?} repeated"),
+        WindowEvent::PointerButtonRepeat { x, y, .. } => println!("Button repeated at {x},{y}"),
+        WindowEvent::Suspended => println!("Suspended, the pan waits"),
+        WindowEvent::Resumed => println!("Resumed"),
+        WindowEvent::SlowFrame { busy, cause } => {
+            println!("{busy:?} without dispatching, mostly {cause:?}")
+        }
+        _ => {}
+    });
+}
+
+//cargo run --features async -- --async
+//The event stream polled by the smallest executor there is: park the thread until woken.
+#[cfg(feature = "async")]
+fn async_example(options: WindowOptions) {
+    use std::{
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        thread::Thread,
+    };
+
+    struct Unpark(Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
             }
         }
     }
+
+    let mut window = Window::with_options(options);
+    block_on(async {
+        let mut events = window.events().unwrap();
+        while let Some(event) = events.next_event().await {
+            if let WindowEvent::Key {
+                key,
+                state: KeyState::Pressed,
+                ..
+            } = event
+            {
+                println!("Key {key:?} pressed");
+            }
+        }
+    });
 }
 
-impl Dispatch<wl_keyboard::WlKeyboard, ()> for AppState {
-    fn event(
-        state: &mut Self,
-        _: &wl_keyboard::WlKeyboard,
-        event: wl_keyboard::Event,
-        _: &(),
-        _: &Connection,
-        _: &QueueHandle<Self>,
-    ) {
-        if let wl_keyboard::Event::Key {
-            serial, time, key, ..
-        } = event
-        {
-            println!("Key {key} did smth!, time: {time}. Serial: {serial}");
+//cargo run -- --dialog
+//Q asks before quitting, with a modal dialog: click the red half to quit, the grey one to go back.
+fn dialog_example(options: WindowOptions) {
+    use rustix::event::{PollFd, PollFlags, Timespec, poll};
 
-            if key == 1 {
-                //esc is version
-                state.running = false;
+    let mut window = Window::with_options(options);
+    //The dialog, whether its buttons are drawn, and where the pointer is on it.
+    let mut dialog: Option<(Window, bool)> = None;
+    let mut dialog_pointer_x = 0.0;
+
+    while window.is_running() {
+        for event in window.poll_events() {
+            //While the dialog is up the parent ignores input, the compositor doesn't do it for us.
+            if let WindowEvent::Key {
+                key: Key::Q,
+                state: KeyState::Pressed,
+                ..
+            } = event
+                && dialog.is_none()
+            {
+                let dialog_options = WindowOptions {
+                    title: "Quit?".into(),
+                    size: (240, 120),
+                    ..WindowOptions::default()
+                };
+                match Window::create_child_dialog(&window, dialog_options) {
+                    Ok(child) => dialog = Some((child, false)),
+                    Err(err) => println!("No dialog: {err}"),
+                }
             }
         }
+
+        if let Some((ref mut child, ref mut drawn)) = dialog {
+            for event in child.poll_events() {
+                match event {
+                    WindowEvent::PointerEntered { x, .. } | WindowEvent::PointerMoved { x, .. } => {
+                        dialog_pointer_x = x
+                    }
+                    WindowEvent::PointerButton { pressed: true, .. } => {
+                        if dialog_pointer_x < f64::from(child.size().0) / 2.0 {
+                            window.close();
+                        }
+                        child.close();
+                    }
+                    _ => {}
+                }
+            }
+
+            //Drawing needs the dialog's buffer, which comes with its first dispatches.
+            if child.is_configured() && !*drawn {
+                child.draw(|canvas| {
+                    let (width, height) = (canvas.width(), canvas.height());
+                    for y in 8..height.saturating_sub(8) {
+                        for x in 8..width.saturating_sub(8) {
+                            let color = match x {
+                                x if x < width / 2 - 4 => Color::opaque(0xC0, 0x30, 0x30),
+                                x if x >= width / 2 + 4 => Color::opaque(0x60, 0x60, 0x60),
+                                _ => continue,
+                            };
+                            canvas.put_pixel(x, y, color);
+                        }
+                    }
+                });
+                *drawn = true;
+            }
+            if !child.is_running() {
+                dialog = None;
+            }
+        }
+
+        //Both windows read from the same socket, so one may read what's for the other, and those
+        //events wait in its queue where poll can't see them. Waking up now and then picks them
+        //up.
+        let timeout = Timespec::try_from(Duration::from_millis(50)).unwrap();
+        let mut fds = [PollFd::from_borrowed_fd(
+            window.connection_fd(),
+            PollFlags::IN,
+        )];
+        let _ = poll(&mut fds, Some(&timeout));
     }
 }
 
-//Function to draw the image. idk what they doing here idc for now
-//TODO: learn this later
-fn draw(tmp: &mut File, (buf_x, buf_y): (u32, u32)) {
-    use std::{cmp::min, io::Write};
-    let mut buf = std::io::BufWriter::new(tmp);
-    for y in 0..buf_y {
-        for x in 0..buf_x {
-            let a = 0xFF;
-            let r = min(((buf_x - x) * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
-            let g = min((x * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
-            let b = min(((buf_x - x) * 0xFF) / buf_x, (y * 0xFF) / buf_y);
-            buf.write_all(&[b as u8, g as u8, r as u8, a as u8])
-                .unwrap();
-        }
-    }
-    buf.flush().unwrap();
+//cargo run -- --epoll
+//The window driven from an epoll loop we own, next to stdin: type a line in the terminal and it's
+//echoed while the window keeps working.
+fn epoll_example(options: WindowOptions) {
+    use rustix::event::epoll;
+    use std::io::BufRead;
+
+    const WAYLAND: u64 = 0;
+    const STDIN: u64 = 1;
+
+    let mut window = Window::with_options(options);
+    let epoll_fd = epoll::create(epoll::CreateFlags::CLOEXEC).unwrap();
+    epoll::add(
+        &epoll_fd,
+        window.connection_fd(),
+        epoll::EventData::new_u64(WAYLAND),
+        epoll::EventFlags::IN,
+    )
+    .unwrap();
+    epoll::add(
+        &epoll_fd,
+        std::io::stdin(),
+        epoll::EventData::new_u64(STDIN),
+        epoll::EventFlags::IN,
+    )
+    .unwrap();
+
+    let mut ready = Vec::with_capacity(2);
+    while window.is_running() {
+        //Prepare and flush before sleeping, or we could wait for an answer to a request that
+        //never left.
+        window.prepare_read().unwrap();
+        window.flush().unwrap();
+
+        ready.clear();
+        epoll::wait(&epoll_fd, rustix::buffer::spare_capacity(&mut ready), None).unwrap();
+
+        for event in &ready {
+            match event.data.u64() {
+                WAYLAND => {
+                    window.read_and_dispatch().unwrap();
+                }
+                _ => {
+                    let mut line = String::new();
+                    //EOF (nothing attached to stdin) would wake us up forever.
+                    match std::io::stdin().lock().read_line(&mut line) {
+                        Ok(0) | Err(_) => epoll::delete(&epoll_fd, std::io::stdin()).unwrap(),
+                        Ok(_) => println!("stdin: {}", line.trim_end()),
+                    }
+                }
+            }
+        }
+
+        for event in window.take_events() {
+            if let WindowEvent::Key {
+                key,
+                state: KeyState::Pressed,
+                ..
+            } = event
+            {
+                println!("Key {key:?} pressed");
+            }
+        }
+    }
 }
 
-//These protocols events are being ignored since we don't care about them in the scope our
-//application.
-delegate_noop!(AppState: ignore wl_shm::WlShm);
-delegate_noop!(AppState: ignore wl_shm_pool::WlShmPool);
-delegate_noop!(AppState: ignore wl_buffer::WlBuffer);
-delegate_noop!(AppState: ignore wl_compositor::WlCompositor);
-delegate_noop!(AppState: ignore wl_surface::WlSurface);
+//cargo run --features egl -- --egl
+//Clears the window with GL to a color that changes over time. No GL bindings crate, the two
+//functions needed are looked up by hand.
+#[cfg(feature = "egl")]
+fn egl_example(options: WindowOptions) {
+    use simple_wayland_window::{RenderMode, WindowError};
+    use std::{ffi::c_void, time::Instant};
 
-fn main() {
-    //Connect to the wayland server through the configuration provided by the environment.
-    let connection = Connection::connect_to_env().unwrap();
-
-    //A display is the starting point of any Wayland program.
-    //All other objects are created from it.
-    let display = connection.display();
-
-    //An event_queue is needed for event processing.
-    let mut event_queue = connection.new_event_queue();
-
-    //Its handle is needed to associate objects to the it.
-    let queue_handle = event_queue.handle();
-
-    //A registry allows the client to list and bind the global objects
-    //available from the compositor.
-    //
-    //Following the logic, we associate the registry we created to our queue_handle.
-    display.get_registry(&queue_handle, ());
-
-    //Create our Application State.
-    let mut app_state = AppState {
-        running: true,
-        base_surface: None,
-        buffer: None,
-        wm_base: None,
-        xdg_surface: None,
-        configured: false,
+    type ClearColor = unsafe extern "C" fn(f32, f32, f32, f32);
+    type Clear = unsafe extern "C" fn(u32);
+    type Scissor = unsafe extern "C" fn(i32, i32, i32, i32);
+    type Enable = unsafe extern "C" fn(u32);
+    const GL_COLOR_BUFFER_BIT: u32 = 0x4000;
+    const GL_SCISSOR_TEST: u32 = 0x0C11;
+
+    let mut window = Window::with_options(WindowOptions {
+        render_mode: RenderMode::Egl,
+        ..options
+    });
+    if let Err(err) = window.make_current() {
+        println!("No GL for us: {err}");
+        return;
+    }
+
+    let procs = [
+        window.gl_proc_address("glClearColor"),
+        window.gl_proc_address("glClear"),
+        window.gl_proc_address("glScissor"),
+        window.gl_proc_address("glEnable"),
+    ];
+    if procs.iter().any(|proc| proc.is_null()) {
+        println!("glClear isn't there?");
+        return;
+    }
+    //SAFETY: these are the GLES2 signatures of the functions.
+    let (clear_color, clear, scissor, enable) = unsafe {
+        (
+            std::mem::transmute::<*const c_void, ClearColor>(procs[0]),
+            std::mem::transmute::<*const c_void, Clear>(procs[1]),
+            std::mem::transmute::<*const c_void, Scissor>(procs[2]),
+            std::mem::transmute::<*const c_void, Enable>(procs[3]),
+        )
     };
 
-    //Application loop
-    while app_state.running {
-        //Block waiting for events and dispatch them.
-        //Quoting documentation: "This method is similar to dispatch_pending(), but if there are no pending events it will also flush the connection
-        //and block waiting for the Wayland server to send an event."
-        event_queue.blocking_dispatch(&mut app_state).unwrap();
+    //16:9, with black bars when maximized or tiled into another shape.
+    let _ = window.set_aspect_ratio(Some((16, 9)));
+
+    let start = Instant::now();
+    while window.is_running() {
+        for event in window.poll_events() {
+            if let WindowEvent::Resized { width, height } = event {
+                println!("Now {width}x{height}");
+            }
+        }
+
+        let t = start.elapsed().as_secs_f32();
+        //GL counts y from the bottom.
+        let (_, buffer_height) = window.buffer_size();
+        let content = window.content_rect();
+        //SAFETY: the context is current on this thread.
+        unsafe {
+            clear_color(0.0, 0.0, 0.0, 1.0);
+            clear(GL_COLOR_BUFFER_BIT);
+            enable(GL_SCISSOR_TEST);
+            scissor(
+                content.x,
+                buffer_height as i32 - content.y - content.height,
+                content.width,
+                content.height,
+            );
+            clear_color(t.sin() * 0.5 + 0.5, 0.2, t.cos() * 0.5 + 0.5, 1.0);
+            clear(GL_COLOR_BUFFER_BIT);
+            scissor(0, 0, i32::MAX, i32::MAX);
+        }
+
+        //Before the first configure there's nowhere to present to yet. Once it's there, the swap
+        //interval makes this wait for the compositor, so the loop doesn't spin.
+        match window.swap_buffers() {
+            Ok(()) | Err(WindowError::NotConfigured) => {}
+            Err(err) => {
+                println!("Swap failed: {err}");
+                return;
+            }
+        }
+    }
+}
+
+//cargo run --features raw-window-handle -- --handles
+//What a wgpu/Vulkan integration does before creating its surface: wait for the first configure,
+//then take the raw handles. (No wgpu here, it would drag a whole GPU stack into the example.)
+#[cfg(feature = "raw-window-handle")]
+fn handles_example(options: WindowOptions) {
+    use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+    use simple_wayland_window::RenderMode;
+
+    let mut window = Window::with_options(WindowOptions {
+        render_mode: RenderMode::External,
+        ..options
+    });
+    while window.is_running() && !window.is_configured() {
+        window.pump_events();
+    }
+
+    match (window.display_handle(), window.window_handle()) {
+        (Ok(display), Ok(surface)) => println!("{:?}\n{:?}", display.as_raw(), surface.as_raw()),
+        (Err(err), _) | (_, Err(err)) => println!("No handles: {err}"),
     }
 }
@@ -0,0 +1,238 @@
+//The shared pool's memory (buffer_allocator.rs): one mapping, several buffers in it, each a slice
+//the compositor reads whenever it likes between the attach and the release. Writing into a slice
+//then shows torn pixels at best, so slices to write are only handed out as a SliceGuard, and
+//only while the compositor can't be reading any byte of them. The guard borrows the pool: growing
+//it (which remaps, the old addresses go away) can't happen while one is alive, the borrow checker
+//says so.
+//
+//About aliasing, since the same pages are mapped in the compositor too. The compositor only reads
+//them (wl_shm mappings are read-only on its side, or should be), so nothing in this process ever
+//sees them change behind a reference: a &[u8] of the pool is as good as any other. What the
+//compositor reads while we write somewhere else in the pool isn't ours to care about, slices
+//don't overlap. A compositor that writes anyway breaks that, and the pixels we read back (frame
+//skipping, captures) are then whatever it wrote, nothing worse: bytes have no invalid values.
+//
+//The window's own buffers (swapchain.rs) are MappedPools too, each of one slice filling a file of
+//its own. Whether the compositor may read one is the busy state here, so drawing into it goes
+//through a guard like any slice. The swapchain's timeout, after which a draw goes into a busy
+//buffer anyway, is spelled out: it calls released itself, as if the compositor had.
+//
+//The pool's memory is anything PoolMemory, a MappedFile for real and a Vec in the tests, which
+//miri can run (mmap it can't).
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{buffer_allocator::Move, canvas::MappedFile};
+
+pub(crate) trait PoolMemory {
+    fn bytes(&self) -> &[u8];
+    fn bytes_mut(&mut self) -> &mut [u8];
+    //Bigger, keeping the content. Whatever pointed into the old memory is gone.
+    fn grow(&mut self, len: usize) -> io::Result<()>;
+}
+
+impl PoolMemory for MappedFile {
+    fn bytes(&self) -> &[u8] {
+        MappedFile::bytes(self)
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        MappedFile::bytes_mut(self)
+    }
+
+    fn grow(&mut self, len: usize) -> io::Result<()> {
+        MappedFile::grow(self, len)
+    }
+}
+
+pub(crate) struct MappedPool<M: PoolMemory = MappedFile> {
+    memory: M,
+    //Offset to length of the slices the compositor may be reading: attached, not released yet.
+    //They never overlap.
+    busy: BTreeMap<usize, usize>,
+}
+
+//A slice of the pool nobody else reads, to write into. Done when dropped.
+pub(crate) struct SliceGuard<'a> {
+    bytes: &'a mut [u8],
+}
+
+impl Deref for SliceGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.bytes
+    }
+}
+
+impl DerefMut for SliceGuard<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.bytes
+    }
+}
+
+impl<M: PoolMemory> MappedPool<M> {
+    pub(crate) fn new(memory: M) -> MappedPool<M> {
+        MappedPool {
+            memory,
+            busy: BTreeMap::new(),
+        }
+    }
+
+    //Whether some busy slice has a byte in offset..end. Busy slices don't overlap, so only the
+    //last one starting before the end can.
+    fn overlaps_busy(&self, offset: usize, end: usize) -> bool {
+        self.busy
+            .range(..end)
+            .next_back()
+            .is_some_and(|(&busy, &busy_len)| busy + busy_len > offset)
+    }
+
+    //The bytes offset..offset + len to write into. None while the compositor may read any of
+    //them, or when they're not in the pool.
+    pub(crate) fn slice_mut(&mut self, offset: usize, len: usize) -> Option<SliceGuard<'_>> {
+        let end = offset.checked_add(len)?;
+        if len == 0 || self.overlaps_busy(offset, end) {
+            return None;
+        }
+        let bytes = self.memory.bytes_mut().get_mut(offset..end)?;
+        Some(SliceGuard { bytes })
+    }
+
+    //The whole pool to write into, for pools of one buffer. None while it's busy.
+    pub(crate) fn whole_mut(&mut self) -> Option<SliceGuard<'_>> {
+        let len = self.memory.bytes().len();
+        self.slice_mut(0, len)
+    }
+
+    //All of it, to read. Reading what the compositor reads too is fine, see the top.
+    pub(crate) fn bytes(&self) -> &[u8] {
+        self.memory.bytes()
+    }
+
+    //The slice at `offset` was attached: no guard for it until released.
+    pub(crate) fn attached(&mut self, offset: usize, len: usize) {
+        self.busy.insert(offset, len);
+    }
+
+    pub(crate) fn released(&mut self, offset: usize) {
+        self.busy.remove(&offset);
+    }
+
+    pub(crate) fn is_busy(&self, offset: usize) -> bool {
+        self.busy.contains_key(&offset)
+    }
+
+    //Compaction's copy, which can overlap its own source. False, and nothing copied, when either
+    //side may be read by the compositor.
+    pub(crate) fn move_slice(&mut self, Move { from, to, len }: Move) -> bool {
+        if self.overlaps_busy(from, from + len) || self.overlaps_busy(to, to + len) {
+            return false;
+        }
+        self.memory.bytes_mut().copy_within(from..from + len, to);
+        true
+    }
+
+    //Remaps: &mut self, so no guard is left pointing into the old mapping.
+    pub(crate) fn grow(&mut self, len: usize) -> io::Result<()> {
+        self.memory.grow(len)
+    }
+}
+
+impl MappedPool<MappedFile> {
+    //For the wl_shm_pool, which maps it on the compositor's side.
+    pub(crate) fn file(&self) -> &File {
+        self.memory.file()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //The fake pool: a Vec, growing like the file does.
+    impl PoolMemory for Vec<u8> {
+        fn bytes(&self) -> &[u8] {
+            self
+        }
+
+        fn bytes_mut(&mut self) -> &mut [u8] {
+            self
+        }
+
+        fn grow(&mut self, len: usize) -> io::Result<()> {
+            if len > self.len() {
+                self.resize(len, 0);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn busy_slices_are_not_handed_out() {
+        let mut pool = MappedPool::new(vec![0; 256]);
+        pool.slice_mut(64, 64).unwrap().fill(7);
+        pool.attached(64, 64);
+
+        assert!(pool.slice_mut(64, 64).is_none());
+        //Any overlap counts, from either side.
+        assert!(pool.slice_mut(0, 65).is_none());
+        assert!(pool.slice_mut(127, 64).is_none());
+        assert!(pool.slice_mut(0, 64).is_some());
+        assert!(pool.slice_mut(128, 128).is_some());
+
+        pool.released(64);
+        assert_eq!(&*pool.slice_mut(64, 64).unwrap(), [7; 64]);
+    }
+
+    #[test]
+    fn slices_stay_inside_the_pool() {
+        let mut pool = MappedPool::new(vec![0; 128]);
+        assert!(pool.slice_mut(64, 65).is_none());
+        assert!(pool.slice_mut(usize::MAX, 2).is_none());
+        assert!(pool.slice_mut(0, 0).is_none());
+
+        pool.grow(256).unwrap();
+        assert_eq!(pool.slice_mut(64, 192).unwrap().len(), 192);
+        assert!(pool.slice_mut(64, 193).is_none());
+    }
+
+    #[test]
+    fn moves_leave_busy_slices_alone() {
+        let mut pool = MappedPool::new(vec![0; 256]);
+        pool.slice_mut(128, 64).unwrap().fill(1);
+        pool.attached(0, 64);
+        let blocked = Move {
+            from: 128,
+            to: 32,
+            len: 64,
+        };
+        assert!(!pool.move_slice(blocked));
+
+        let ok = Move {
+            from: 128,
+            to: 64,
+            len: 64,
+        };
+        assert!(pool.move_slice(ok));
+        assert_eq!(&*pool.slice_mut(64, 64).unwrap(), [1; 64]);
+    }
+
+    #[test]
+    fn a_buffer_of_its_own_is_written_only_while_free() {
+        let mut pool = MappedPool::new(vec![0; 64]);
+        pool.whole_mut().unwrap().fill(3);
+        pool.attached(0, 64);
+        assert!(pool.whole_mut().is_none());
+        //Reading stays possible.
+        assert_eq!(pool.bytes(), [3; 64]);
+
+        pool.released(0);
+        assert_eq!(pool.whole_mut().unwrap().len(), 64);
+    }
+}
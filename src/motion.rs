@@ -0,0 +1,321 @@
+//Pointer motion, merged. A 1000 Hz mouse sends a motion (and a relative motion) every
+//millisecond, a dispatch at 60 Hz would hand out a few dozen PointerMoved the application only
+//wants the last of.
+//
+//At the end of every dispatch round the new events go through coalesce: consecutive motion of a
+//seat becomes one event, with the newest position, the deltas added up and how many samples it
+//stands for. PointerMoved with PointerMoved, RelativeMotion with RelativeMotion (synthetic ones
+//apart). Consecutive means with nothing but motion in between: a button, a scroll, a leave or
+//anything else ends the run, and motion after it is a new event. A drag threshold sees the
+//press at the position it happened.
+//
+//MotionCoalescing::Frame also holds the last run back while a frame callback is pending, to
+//deliver it with the Frame: one PointerMoved per frame at most. Whatever comes after it
+//releases it, in order. Drawing tablets and handwriting want every sample: MotionCoalescing::Off.
+//
+//PointerMoved's deltas are computed here too, from the seat's previous position (the enter, or
+//the last motion), so they're there with coalescing off as well.
+
+use std::sync::Arc;
+
+use crate::{AppState, Window, WindowEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MotionCoalescing {
+    //Every motion event is delivered.
+    Off,
+    //Merged within a dispatch batch.
+    #[default]
+    Dispatch,
+    //Merged until the next frame callback, when one is pending.
+    Frame,
+}
+
+#[derive(Default)]
+pub(crate) struct MotionState {
+    mode: MotionCoalescing,
+    //Of each seat's pointer on the surface, for the deltas.
+    positions: Vec<(Arc<str>, (f64, f64))>,
+    //Motion held back for the frame, already coalesced.
+    held: Vec<WindowEvent>,
+    //wl_surface.frame requests not answered yet.
+    pub(crate) frames_pending: u32,
+}
+
+impl MotionState {
+    pub(crate) fn for_reconnect(&self) -> MotionState {
+        MotionState {
+            mode: self.mode,
+            ..MotionState::default()
+        }
+    }
+
+    fn set_position(&mut self, seat: &Arc<str>, position: Option<(f64, f64)>) -> (f64, f64) {
+        let index = self.positions.iter().position(|(name, _)| name == seat);
+        let old = index.map(|index| self.positions[index].1);
+        match (index, position) {
+            (Some(index), Some(position)) => self.positions[index].1 = position,
+            (Some(index), None) => {
+                self.positions.swap_remove(index);
+            }
+            (None, Some(position)) => self.positions.push((seat.clone(), position)),
+            (None, None) => {}
+        }
+        //The first motion without an enter moved by nothing we know of.
+        old.or(position).unwrap_or_default()
+    }
+}
+
+//Merges `event` into the run's event of the same kind and seat, if there's one. Returns it back
+//otherwise.
+fn merge(run: &mut [WindowEvent], event: WindowEvent) -> Option<WindowEvent> {
+    for earlier in run.iter_mut().rev() {
+        match (earlier, &event) {
+            (
+                WindowEvent::PointerMoved {
+                    seat,
+                    x,
+                    y,
+                    dx,
+                    dy,
+                    samples,
+                },
+                WindowEvent::PointerMoved {
+                    seat: new_seat,
+                    x: new_x,
+                    y: new_y,
+                    dx: new_dx,
+                    dy: new_dy,
+                    samples: new_samples,
+                },
+            ) if seat == new_seat => {
+                (*x, *y) = (*new_x, *new_y);
+                *dx += new_dx;
+                *dy += new_dy;
+                *samples += new_samples;
+                return None;
+            }
+            (
+                WindowEvent::RelativeMotion {
+                    seat,
+                    dx,
+                    dy,
+                    dx_unaccel,
+                    dy_unaccel,
+                    utime,
+                    synthetic,
+                    samples,
+                },
+                WindowEvent::RelativeMotion {
+                    seat: new_seat,
+                    dx: new_dx,
+                    dy: new_dy,
+                    dx_unaccel: new_dx_unaccel,
+                    dy_unaccel: new_dy_unaccel,
+                    utime: new_utime,
+                    synthetic: new_synthetic,
+                    samples: new_samples,
+                },
+            ) if seat == new_seat && synthetic == new_synthetic => {
+                *dx += new_dx;
+                *dy += new_dy;
+                *dx_unaccel += new_dx_unaccel;
+                *dy_unaccel += new_dy_unaccel;
+                *utime = (*utime).max(*new_utime);
+                *samples += new_samples;
+                return None;
+            }
+            _ => {}
+        }
+    }
+    Some(event)
+}
+
+fn is_motion(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::PointerMoved { .. } | WindowEvent::RelativeMotion { .. }
+    )
+}
+
+//`held` and then `events`, with PointerMoved deltas filled in and, unless `merge_motion` is
+//false, motion runs merged. Returns where the last run starts.
+fn coalesce(
+    state: &mut MotionState,
+    events: Vec<WindowEvent>,
+    merge_motion: bool,
+) -> (Vec<WindowEvent>, usize) {
+    let mut out = std::mem::take(&mut state.held);
+    let mut run_start = 0;
+    for mut event in events {
+        match event {
+            WindowEvent::PointerMoved {
+                ref seat,
+                x,
+                y,
+                ref mut dx,
+                ref mut dy,
+                ..
+            } => {
+                let (old_x, old_y) = state.set_position(seat, Some((x, y)));
+                (*dx, *dy) = (x - old_x, y - old_y);
+            }
+            WindowEvent::PointerEntered { ref seat, x, y } => {
+                state.set_position(seat, Some((x, y)));
+            }
+            WindowEvent::PointerLeft { ref seat } => {
+                state.set_position(seat, None);
+            }
+            _ => {}
+        }
+        if !is_motion(&event) {
+            out.push(event);
+            run_start = out.len();
+            continue;
+        }
+        if merge_motion {
+            if let Some(event) = merge(&mut out[run_start..], event) {
+                out.push(event);
+            }
+        } else {
+            out.push(event);
+        }
+    }
+    (out, run_start)
+}
+
+impl AppState {
+    //The end of every dispatch round, see motion.rs.
+    pub(crate) fn coalesce_motion(&mut self) {
+        let mode = self.motion.mode;
+        let events = std::mem::take(&mut self.events);
+        let framed = events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::Frame { .. }));
+        let (mut events, run_start) =
+            coalesce(&mut self.motion, events, mode != MotionCoalescing::Off);
+        if mode == MotionCoalescing::Frame && self.motion.frames_pending > 0 && !framed {
+            self.motion.held = events.split_off(run_start);
+        }
+        self.events = events;
+    }
+}
+
+impl Window {
+    //MotionCoalescing::Dispatch by default, see motion.rs. Motion held for a frame goes out
+    //with the next dispatch when switching.
+    pub fn set_motion_coalescing(&mut self, mode: MotionCoalescing) {
+        self.state.motion.mode = mode;
+    }
+
+    pub fn motion_coalescing(&self) -> MotionCoalescing {
+        self.state.motion.mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moved(seat: &str, x: f64) -> WindowEvent {
+        WindowEvent::PointerMoved {
+            seat: seat.into(),
+            x,
+            y: 0.0,
+            dx: 0.0,
+            dy: 0.0,
+            samples: 1,
+        }
+    }
+
+    fn relative(seat: &str, dx: f64, utime: u64) -> WindowEvent {
+        WindowEvent::RelativeMotion {
+            seat: seat.into(),
+            dx,
+            dy: 0.0,
+            dx_unaccel: dx,
+            dy_unaccel: 0.0,
+            utime,
+            synthetic: false,
+            samples: 1,
+        }
+    }
+
+    fn with_delta(seat: &str, x: f64, dx: f64, samples: u32) -> WindowEvent {
+        WindowEvent::PointerMoved {
+            seat: seat.into(),
+            x,
+            y: 0.0,
+            dx,
+            dy: 0.0,
+            samples,
+        }
+    }
+
+    #[test]
+    fn runs_merge_per_seat_and_kind() {
+        let mut state = MotionState::default();
+        let events = vec![
+            WindowEvent::PointerEntered {
+                seat: "a".into(),
+                x: 1.0,
+                y: 0.0,
+            },
+            moved("a", 2.0),
+            relative("a", 1.0, 10),
+            moved("b", 7.0),
+            moved("a", 4.0),
+            relative("a", 2.0, 20),
+        ];
+        let (events, run_start) = coalesce(&mut state, events, true);
+        assert_eq!(run_start, 1);
+        let mut relative = relative("a", 3.0, 20);
+        if let WindowEvent::RelativeMotion {
+            ref mut samples, ..
+        } = relative
+        {
+            *samples = 2;
+        }
+        assert_eq!(
+            events[1..],
+            [
+                with_delta("a", 4.0, 3.0, 2),
+                relative,
+                with_delta("b", 7.0, 0.0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn buttons_end_the_run() {
+        let mut state = MotionState::default();
+        let button = WindowEvent::PointerButton {
+            seat: "a".into(),
+            button: 0x110,
+            pressed: true,
+        };
+        let events = vec![
+            moved("a", 1.0),
+            moved("a", 2.0),
+            button.clone(),
+            moved("a", 3.0),
+        ];
+        let (events, run_start) = coalesce(&mut state, events, true);
+        assert_eq!(run_start, 2);
+        assert_eq!(
+            events,
+            [
+                with_delta("a", 2.0, 1.0, 2),
+                button,
+                with_delta("a", 3.0, 1.0, 1),
+            ]
+        );
+
+        //Off, every sample with its own delta.
+        let (events, _) = coalesce(&mut state, vec![moved("a", 5.0), moved("a", 6.0)], false);
+        assert_eq!(
+            events,
+            [with_delta("a", 5.0, 2.0, 1), with_delta("a", 6.0, 1.0, 1)]
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//The numpad means two things. Its keys have XKB's KEYPAD type, where NumLock switches between
+//the navigation keys and the digits: Numpad7 is Home with NumLock off and 7 with it on, and
+//Shift turns it the other way around for as long as it's held. Games want the key where it is,
+//whatever NumLock says; text fields want what it types. Key events have both, like winit has
+//them:
+//
+//  key          the physical key, Key::Numpad7 whatever the state (Key is always physical).
+//  logical_key  what it means now: LogicalKey::Character('7') or LogicalKey::Named(Key::Home).
+//
+//The logical key is the keymap's keysym for the key's group and level, see keysyms.rs, for every
+//key: Q on a French layout is LogicalKey::Character('a'). Without a keymap (or for keysyms it
+//can't name) it's the physical key.
+//
+//Key bindings target either, see BindingKey. A key keeps the meaning it was pressed with until
+//its release, repeats included: toggling NumLock while Numpad7 is held doesn't turn its repeats
+//(or its release) into Homes.
+//
+//NumLock is a locked modifier of wl_keyboard.modifiers, the real modifier the keymap maps the
+//NumLock key to (`modifier_map Mod2 { <NMLK> };`, Mod2 everywhere in practice and without a
+//keymap). CapsLock is always Lock.
+
+use wayland_client::protocol::wl_seat::WlSeat;
+
+use crate::{AppState, Key, Mods, keysyms::Levels};
+
+//What a key means with the modifiers it was pressed with, see numpad.rs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogicalKey {
+    //A key that types this: letters, digits, the numpad's digits with NumLock on...
+    Character(char),
+    //A key that does something: Enter, F1, the numpad's navigation with NumLock off (Home for
+    //Numpad7). Keys the keymap says nothing about are here too, by their physical name.
+    Named(Key),
+}
+
+impl LogicalKey {
+    //The text the key types, for keys that type any.
+    pub fn text(self) -> Option<char> {
+        match self {
+            LogicalKey::Character(c) => Some(c),
+            LogicalKey::Named(_) => None,
+        }
+    }
+}
+
+//The real modifiers of wl_keyboard.modifiers, by bit.
+const REAL_MODIFIERS: [&str; 8] = [
+    "Shift", "Lock", "Control", "Mod1", "Mod2", "Mod3", "Mod4", "Mod5",
+];
+//Mod2, where every keymap puts NumLock.
+const DEFAULT_NUMLOCK: u32 = 1 << 4;
+//Lock, CapsLock's.
+const CAPS_LOCK: u32 = 1 << 1;
+
+//The mask NumLock is locked with, from the keymap's `modifier_map Mod2 { <NMLK> };`.
+pub(crate) fn numlock_mask(text: &str) -> u32 {
+    let mut rest = text;
+    while let Some(at) = rest.find("modifier_map") {
+        rest = &rest[at + "modifier_map".len()..];
+        let Some((modifier, keys)) = rest.split_once('{') else {
+            break;
+        };
+        let keys = keys.split('}').next().unwrap_or_default();
+        let numlock = keys
+            .split(',')
+            .map(str::trim)
+            .any(|key| key == "<NMLK>" || key == "Num_Lock");
+        if let Some(bit) = REAL_MODIFIERS
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(modifier.trim()))
+            .filter(|_| numlock)
+        {
+            return 1 << bit;
+        }
+    }
+    DEFAULT_NUMLOCK
+}
+
+impl AppState {
+    //wl_keyboard.modifiers, for its locked ones.
+    pub(crate) fn update_locks(&mut self, seat: &WlSeat, locked: u32) {
+        let Some(entry) = self.seat_mut(seat) else {
+            return;
+        };
+        let mask = entry
+            .keymap
+            .as_ref()
+            .map_or(DEFAULT_NUMLOCK, |keymap| keymap.numlock);
+        entry.numlock = locked & mask != 0;
+        entry.caps_lock = locked & CAPS_LOCK != 0;
+    }
+
+    //The logical key of a Key event: worked out for a press in the `group` it's pressed with,
+    //the press's for a release.
+    pub(crate) fn key_logical(
+        &mut self,
+        seat: &WlSeat,
+        code: u32,
+        pressed: bool,
+        group: u32,
+    ) -> LogicalKey {
+        let key = Key::from_evdev(code);
+        let Some(entry) = self.seat_mut(seat) else {
+            return LogicalKey::Named(key);
+        };
+        let at_press = entry
+            .key_logical
+            .iter()
+            .position(|&(held, _)| held == code)
+            .map(|index| entry.key_logical.swap_remove(index).1);
+        if !pressed {
+            return at_press.unwrap_or(LogicalKey::Named(key));
+        }
+        let shift = Mods::held(
+            entry
+                .pressed_keys
+                .iter()
+                .copied()
+                .filter(|&held| held != code),
+        )
+        .contains(Mods::SHIFT);
+        let levels = Levels {
+            shift,
+            caps_lock: entry.caps_lock,
+            num_lock: entry.numlock,
+        };
+        let logical = entry
+            .keymap
+            .as_ref()
+            .map_or(LogicalKey::Named(key), |keymap| {
+                keymap.symbols.logical_key(code, group, levels)
+            });
+        entry.key_logical.push((code, logical));
+        logical
+    }
+
+    //What a held key was pressed as, for its repeats.
+    pub(crate) fn held_logical_key(&self, seat: &str, code: u32) -> LogicalKey {
+        self.seat_named(seat)
+            .and_then(|entry| entry.key_logical.iter().find(|&&(held, _)| held == code))
+            .map_or(LogicalKey::Named(Key::from_evdev(code)), |&(_, logical)| {
+                logical
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keysyms::Symbols;
+
+    //The numpad and NumLock of what xkbcommon writes for "us".
+    const KEYMAP: &str = r#"xkb_keymap {
+xkb_keycodes "evdev" {
+	<NMLK> = 77;
+	<KP7> = 79;
+	<KP8> = 80;
+	<KP9> = 81;
+	<KPSU> = 82;
+	<KP4> = 83;
+	<KP5> = 84;
+	<KP6> = 85;
+	<KPAD> = 86;
+	<KP1> = 87;
+	<KP2> = 88;
+	<KP3> = 89;
+	<KP0> = 90;
+	<KPDL> = 91;
+	<KPEN> = 104;
+	<KPDV> = 106;
+	<KPMU> = 63;
+};
+xkb_compat "complete" {
+	interpret Num_Lock { action= LockMods(modifiers=NumLock); };
+};
+xkb_symbols "pc+us+inet(evdev)" {
+	name[Group1]="English (US)";
+	key <KPMU> {
+		type= "CTRL+ALT",
+		symbols[Group1]= [ KP_Multiply, KP_Multiply, KP_Multiply, KP_Multiply, XF86ClearGrab ]
+	};
+	key <NMLK> { [ Num_Lock ] };
+	key <KP7> { [ KP_Home, KP_7 ] };
+	key <KP8> { [ KP_Up, KP_8 ] };
+	key <KP9> { [ KP_Prior, KP_9 ] };
+	key <KPSU> {
+		type= "CTRL+ALT",
+		symbols[Group1]= [ KP_Subtract, KP_Subtract, KP_Subtract, KP_Subtract, XF86Prev_VMode ]
+	};
+	key <KP4> { [ KP_Left, KP_4 ] };
+	key <KP5> { [ KP_Begin, KP_5 ] };
+	key <KP6> { [ KP_Right, KP_6 ] };
+	key <KPAD> {
+		type= "CTRL+ALT",
+		symbols[Group1]= [ KP_Add, KP_Add, KP_Add, KP_Add, XF86Next_VMode ]
+	};
+	key <KP1> { [ KP_End, KP_1 ] };
+	key <KP2> { [ KP_Down, KP_2 ] };
+	key <KP3> { [ KP_Next, KP_3 ] };
+	key <KP0> { [ KP_Insert, KP_0 ] };
+	key <KPDL> { type= "KEYPAD", symbols[Group1]= [ KP_Delete, KP_Decimal ] };
+	key <KPEN> { [ KP_Enter ] };
+	key <KPDV> {
+		type= "CTRL+ALT",
+		symbols[Group1]= [ KP_Divide, KP_Divide, KP_Divide, KP_Divide, XF86Ungrab ]
+	};
+	modifier_map Control { <LCTL> };
+	modifier_map Mod2 { <NMLK> };
+	modifier_map Mod4 { <LWIN>, <RWIN> };
+};
+};
+"#;
+
+    const NUMPAD: [(Key, char, Key); 11] = [
+        (Key::Numpad0, '0', Key::Insert),
+        (Key::Numpad1, '1', Key::End),
+        (Key::Numpad2, '2', Key::Down),
+        (Key::Numpad3, '3', Key::PageDown),
+        (Key::Numpad4, '4', Key::Left),
+        //KP_Begin, which no keyboard has elsewhere: the key itself.
+        (Key::Numpad5, '5', Key::Numpad5),
+        (Key::Numpad6, '6', Key::Right),
+        (Key::Numpad7, '7', Key::Home),
+        (Key::Numpad8, '8', Key::Up),
+        (Key::Numpad9, '9', Key::PageUp),
+        (Key::NumpadDecimal, '.', Key::Delete),
+    ];
+
+    #[test]
+    fn numlock_comes_from_the_modifier_map() {
+        assert_eq!(numlock_mask(KEYMAP), 1 << 4);
+        assert_eq!(
+            numlock_mask("xkb_symbols { modifier_map Mod3 { Num_Lock }; };"),
+            1 << 5
+        );
+        assert_eq!(numlock_mask("xkb_keymap { };"), DEFAULT_NUMLOCK);
+    }
+
+    #[test]
+    fn the_whole_numpad_under_both_numlock_states() {
+        let symbols = Symbols::parse(KEYMAP);
+        let logical_key = |key: Key, num_lock, shift| {
+            let levels = Levels {
+                shift,
+                num_lock,
+                caps_lock: false,
+            };
+            symbols.logical_key(key.evdev(), 0, levels)
+        };
+        for (key, character, navigation) in NUMPAD {
+            assert_eq!(
+                logical_key(key, true, false),
+                LogicalKey::Character(character)
+            );
+            assert_eq!(
+                logical_key(key, false, false),
+                LogicalKey::Named(navigation)
+            );
+            //Shift the other way around.
+            assert_eq!(logical_key(key, true, true), LogicalKey::Named(navigation));
+            assert_eq!(
+                logical_key(key, false, true),
+                LogicalKey::Character(character)
+            );
+        }
+        for numlock in [false, true] {
+            for (key, character) in [
+                (Key::NumpadDivide, '/'),
+                (Key::NumpadMultiply, '*'),
+                (Key::NumpadSubtract, '-'),
+                (Key::NumpadAdd, '+'),
+            ] {
+                assert_eq!(
+                    logical_key(key, numlock, false),
+                    LogicalKey::Character(character)
+                );
+            }
+            assert_eq!(
+                logical_key(Key::NumpadEnter, numlock, false),
+                LogicalKey::Named(Key::Enter)
+            );
+            //Not in this keymap.
+            assert_eq!(
+                logical_key(Key::Q, numlock, false),
+                LogicalKey::Named(Key::Q)
+            );
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use wayland_client::{
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+    protocol::{
+        wl_output::{self, WlOutput},
+        wl_surface::{self, WlSurface},
+    },
+};
+
+use crate::{AppState, protocol_log::protocol_log};
+
+//What's known of a monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputInfo {
+    //"DP-1" and such, the same across reconnects. wl_output v4, None before that.
+    pub name: Option<Arc<str>>,
+    //For humans, e.g. "Dell Inc. U2720Q". v4 too.
+    pub description: Option<Arc<str>>,
+    //Integer scale the compositor shows client buffers at: 2 on most HiDPI screens.
+    pub scale: i32,
+    //Refresh rate of the current mode in mHz (60 Hz is 60000). None when the compositor says 0,
+    //as for virtual outputs.
+    pub refresh_mhz: Option<u32>,
+}
+
+impl Default for OutputInfo {
+    fn default() -> Self {
+        //Quoting documentation: "If it is not sent, the client should assume a scale of 1."
+        OutputInfo {
+            name: None,
+            description: None,
+            scale: 1,
+            refresh_mhz: None,
+        }
+    }
+}
+
+struct Output {
+    global: u32,
+    output: WlOutput,
+    info: OutputInfo,
+    //Changes waiting for Done.
+    pending: OutputInfo,
+    //The first Done came, info has the name (v1 has no Done, nor a name).
+    done: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct OutputsState {
+    outputs: Vec<Output>,
+    //The ones the surface is on, in the order it entered them.
+    entered: Vec<WlOutput>,
+    //wl_surface.preferred_buffer_scale (wl_surface v6), better than guessing from the outputs.
+    pub(crate) preferred_scale: Option<i32>,
+}
+
+impl AppState {
+    pub(crate) fn add_output(&mut self, global: u32, output: WlOutput) {
+        self.outputs.outputs.push(Output {
+            global,
+            done: output.version() < 2,
+            output,
+            info: OutputInfo::default(),
+            pending: OutputInfo::default(),
+        });
+    }
+
+    //Monitors get unplugged. The surface gets no leave for them, it's just off them.
+    pub(crate) fn remove_output(&mut self, global: u32) {
+        let Some(index) = self.outputs.outputs.iter().position(|o| o.global == global) else {
+            return;
+        };
+        let output = self.outputs.outputs.remove(index).output;
+        self.outputs.entered.retain(|entered| *entered != output);
+        if output.version() >= 3 {
+            output.release();
+        }
+    }
+
+    //The window is going, see cleanup.rs.
+    pub(crate) fn release_outputs(&mut self) {
+        self.outputs.entered.clear();
+        for output in self.outputs.outputs.drain(..) {
+            if output.output.version() >= 3 {
+                output.output.release();
+            }
+        }
+    }
+
+    //The outputs the surface is on, as of the last Done of each.
+    pub(crate) fn surface_outputs(&self) -> Vec<OutputInfo> {
+        let outputs = &self.outputs;
+        outputs
+            .entered
+            .iter()
+            .filter_map(|entered| outputs.outputs.iter().find(|o| o.output == *entered))
+            .map(|output| output.info.clone())
+            .collect()
+    }
+
+    //Every output bound so far told what it is, names included.
+    pub(crate) fn outputs_known(&self) -> bool {
+        self.outputs.outputs.iter().all(|output| output.done)
+    }
+
+    pub(crate) fn output_named(&self, name: &str) -> Option<WlOutput> {
+        self.outputs
+            .outputs
+            .iter()
+            .find(|output| output.info.name.as_deref() == Some(name))
+            .map(|output| output.output.clone())
+    }
+
+    //What the buffer would be scaled by to be sharp: the compositor's preference if it says,
+    //otherwise the largest scale of the outputs the surface is on, 1 when on none.
+    pub(crate) fn scale_factor(&self) -> i32 {
+        self.outputs.preferred_scale.unwrap_or_else(|| {
+            self.surface_outputs()
+                .iter()
+                .map(|output| output.scale)
+                .max()
+                .unwrap_or(1)
+        })
+    }
+}
+
+//Quoting documentation: "This event is sent after all other properties have been sent after
+//binding to the output object and after any other property changes done after that. This allows
+//changes to the output properties to be seen as atomic, even if they happen via multiple
+//events." Geometry isn't kept, and of the modes only the current one's refresh.
+impl Dispatch<WlOutput, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        output: &WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        #[cfg(feature = "record")]
+        if let wl_output::Event::Scale { factor } = event {
+            state.record(crate::Recorded::OutputScale(factor));
+        }
+        let Some(entry) = state
+            .outputs
+            .outputs
+            .iter_mut()
+            .find(|entry| entry.output == *output)
+        else {
+            return;
+        };
+        match event {
+            wl_output::Event::Scale { factor } => entry.pending.scale = factor.max(1),
+            //Quoting documentation: "the current mode is always the last mode that was received
+            //with the current flag set."
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                refresh,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => {
+                entry.pending.refresh_mhz = u32::try_from(refresh).ok().filter(|&mhz| mhz > 0);
+            }
+            wl_output::Event::Name { name } => entry.pending.name = Some(name.into()),
+            wl_output::Event::Description { description } => {
+                entry.pending.description = Some(description.into());
+            }
+            wl_output::Event::Done => {
+                protocol_log!("output {:?}", entry.pending);
+                entry.info = entry.pending.clone();
+                entry.done = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlSurface, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &WlSurface,
+        event: wl_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        #[cfg(feature = "record")]
+        state.record_surface_event(&event);
+        let outputs = &mut state.outputs;
+        match event {
+            wl_surface::Event::Enter { output } if !outputs.entered.contains(&output) => {
+                outputs.entered.push(output);
+                state.forget_presented_refresh();
+            }
+            wl_surface::Event::Leave { output } => {
+                outputs.entered.retain(|entered| *entered != output);
+                state.forget_presented_refresh();
+            }
+            //Quoting documentation: "The compositor shall emit a scale value greater than 0."
+            wl_surface::Event::PreferredBufferScale { factor } => {
+                outputs.preferred_scale = Some(factor.max(1));
+                //Drawn at the new scale, a frame may come out the same bytes and still needs
+                //committing.
+                state.frame_skip.force_next();
+                state.preferred.pending = true;
+            }
+            wl_surface::Event::PreferredBufferTransform {
+                transform: WEnum::Value(transform),
+            } => {
+                state.preferred.transform = transform.into();
+                state.preferred.pending = true;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//RGBA pixels (image crates, GPU readbacks, anything that writes R first) into what wl_shm
+//buffers hold. wl_shm formats are named by their channels in a little endian word from the
+//highest bit down: Argb8888 is B, G, R, A in memory, Abgr8888 is R, G, B, A, the same as RGBA.
+//
+//Each pixel is read as one little endian u32 (0xAABBGGRR for RGBA) and the channels moved with
+//masks and shifts, a word at a time. Premultiplying does the red and blue lanes with a single
+//multiply, they're 16 bits apart and a channel times alpha fits in 16 bits.
+
+use wayland_client::protocol::wl_shm;
+
+use crate::{Canvas, WindowError};
+
+//Bytes a pixel takes in `format`, None for the formats this doesn't convert to.
+fn bytes_per_pixel(format: wl_shm::Format) -> Option<usize> {
+    match format {
+        wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 | wl_shm::Format::Abgr8888 => Some(4),
+        wl_shm::Format::Rgb565 => Some(2),
+        _ => None,
+    }
+}
+
+//Rounded channel * alpha / 255, like Color::from_unpremultiplied, for R, G and B of an RGBA word.
+//(x + 128 + ((x + 128) >> 8)) >> 8 is x / 255 rounded, for any x up to 255 * 255.
+fn premultiply(rgba: u32) -> u32 {
+    let a = rgba >> 24;
+    let rb = (rgba & 0x00FF_00FF) * a + 0x0080_0080;
+    let rb = ((rb + ((rb >> 8) & 0x00FF_00FF)) >> 8) & 0x00FF_00FF;
+    let g = ((rgba >> 8) & 0xFF) * a + 0x80;
+    let g = ((g + (g >> 8)) >> 8) & 0xFF;
+    (a << 24) | (g << 8) | rb
+}
+
+//0xAABBGGRR to 0xAARRGGBB: red and blue trade places, green and alpha stay.
+fn swap_red_blue(rgba: u32) -> u32 {
+    (rgba & 0xFF00_FF00) | ((rgba & 0xFF) << 16) | ((rgba >> 16) & 0xFF)
+}
+
+//The top 5, 6 and 5 bits of red, green and blue. Alpha is dropped.
+fn to_rgb565(rgba: u32) -> u16 {
+    let (r, g, b) = (rgba & 0xFF, (rgba >> 8) & 0xFF, (rgba >> 16) & 0xFF);
+    ((r >> 3) << 11 | (g >> 2) << 5 | b >> 3) as u16
+}
+
+//Pixel words to u32 or u16 ones, whichever `convert` makes.
+trait Word: Copy {
+    const LEN: usize;
+    fn write(self, out: &mut [u8]);
+}
+
+impl Word for u32 {
+    const LEN: usize = 4;
+    fn write(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Word for u16 {
+    const LEN: usize = 2;
+    fn write(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes());
+    }
+}
+
+fn convert_words<W: Word>(src: &[u8], dst: &mut [u8], convert: impl Fn(u32) -> W) {
+    for (pixel, out) in src.chunks_exact(4).zip(dst.chunks_exact_mut(W::LEN)) {
+        convert(u32::from_le_bytes(pixel.try_into().unwrap())).write(out);
+    }
+}
+
+//Converts the RGBA pixels of `src` (4 bytes each, straight or premultiplied alpha, as many as
+//there are) into `dst` in `format`: Argb8888, Xrgb8888, Abgr8888 or Rgb565. `premultiply`
+//multiplies the colors by alpha on the way, for straight alpha sources: every wl_shm format with
+//alpha is premultiplied. For the formats without, that's the picture over black.
+//Extra room in `dst` is left alone.
+pub fn convert_rgba_to(
+    format: wl_shm::Format,
+    src: &[u8],
+    dst: &mut [u8],
+    premultiply: bool,
+) -> Result<(), WindowError> {
+    let Some(bytes_per_pixel) = bytes_per_pixel(format) else {
+        return Err(WindowError::InvalidArgument("unsupported shm format"));
+    };
+    if !src.len().is_multiple_of(4) {
+        return Err(WindowError::InvalidArgument(
+            "RGBA source of partial pixels",
+        ));
+    }
+    let pixels = src.len() / 4;
+    if dst.len() < pixels * bytes_per_pixel {
+        return Err(WindowError::InvalidArgument("destination too small"));
+    }
+
+    //One loop per format and premultiply, each with its conversion inlined: through a function
+    //pointer, the per-byte shuffle was faster than this.
+    match (format, premultiply) {
+        (wl_shm::Format::Rgb565, false) => convert_words(src, dst, to_rgb565),
+        (wl_shm::Format::Rgb565, true) => {
+            convert_words(src, dst, |word| to_rgb565(self::premultiply(word)))
+        }
+        (wl_shm::Format::Argb8888, false) => convert_words(src, dst, swap_red_blue),
+        (wl_shm::Format::Argb8888, true) => {
+            convert_words(src, dst, |word| swap_red_blue(self::premultiply(word)))
+        }
+        (wl_shm::Format::Xrgb8888, false) => {
+            convert_words(src, dst, |word| swap_red_blue(word) | 0xFF00_0000)
+        }
+        (wl_shm::Format::Xrgb8888, true) => convert_words(src, dst, |word| {
+            swap_red_blue(self::premultiply(word)) | 0xFF00_0000
+        }),
+        (_, false) => convert_words(src, dst, |word| word),
+        (_, true) => convert_words(src, dst, self::premultiply),
+    }
+    Ok(())
+}
+
+impl Canvas<'_> {
+    //Copies a width x height RGBA picture with straight alpha (what the image crate decodes to)
+    //to the canvas' top left, premultiplied. Whatever doesn't fit is cut off. Err when `pixels`
+    //is smaller than width x height.
+    pub fn copy_from_rgba(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), WindowError> {
+        let row_len = width as usize * 4;
+        if pixels.len() < row_len * height as usize {
+            return Err(WindowError::InvalidArgument(
+                "RGBA picture smaller than its size",
+            ));
+        }
+        let len = row_len.min(self.width() as usize * 4);
+        for (y, src) in (0..height.min(self.height())).zip(pixels.chunks_exact(row_len.max(1))) {
+            if let Some(row) = self.row_mut(y) {
+                convert_rgba_to(wl_shm::Format::Argb8888, &src[..len], row, true)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    //A distinct value per channel: any two swapped show.
+    const RGBA: [u8; 4] = [0x11, 0x22, 0x33, 0xFF];
+
+    fn convert(format: wl_shm::Format, src: &[u8], premultiply: bool) -> Vec<u8> {
+        let mut dst = vec![0; src.len() / 4 * bytes_per_pixel(format).unwrap()];
+        convert_rgba_to(format, src, &mut dst, premultiply).unwrap();
+        dst
+    }
+
+    #[test]
+    fn channels_land_in_format_order() {
+        assert_eq!(
+            convert(wl_shm::Format::Argb8888, &RGBA, false),
+            [0x33, 0x22, 0x11, 0xFF]
+        );
+        assert_eq!(
+            convert(wl_shm::Format::Abgr8888, &RGBA, false),
+            [0x11, 0x22, 0x33, 0xFF]
+        );
+        //X is ignored, but filled all the same.
+        assert_eq!(
+            convert(wl_shm::Format::Xrgb8888, &[0x11, 0x22, 0x33, 0x44], false),
+            [0x33, 0x22, 0x11, 0xFF]
+        );
+        //Red in the top 5 bits, blue in the bottom ones.
+        let rgb565 = convert(wl_shm::Format::Rgb565, &[0xF8, 0x00, 0x00, 0xFF], false);
+        assert_eq!(u16::from_le_bytes([rgb565[0], rgb565[1]]), 0xF800);
+        let rgb565 = convert(wl_shm::Format::Rgb565, &[0x00, 0xFC, 0x00, 0xFF], false);
+        assert_eq!(u16::from_le_bytes([rgb565[0], rgb565[1]]), 0x07E0);
+        let rgb565 = convert(wl_shm::Format::Rgb565, &[0x00, 0x00, 0xF8, 0xFF], false);
+        assert_eq!(u16::from_le_bytes([rgb565[0], rgb565[1]]), 0x001F);
+    }
+
+    #[test]
+    fn every_pixel_is_converted() {
+        let src: Vec<u8> = (0..3)
+            .flat_map(|i| RGBA.map(|c| c.wrapping_add(i)))
+            .collect();
+        let dst = convert(wl_shm::Format::Argb8888, &src, false);
+        assert_eq!(dst[8..], [0x35, 0x24, 0x13, 0x01]);
+    }
+
+    #[test]
+    fn premultiply_matches_color() {
+        for a in 0..=255 {
+            for c in 0..=255 {
+                let word = premultiply(u32::from_le_bytes([c, 255 - c, c / 2, a]));
+                let expected = Color::from_unpremultiplied(c, 255 - c, c / 2, a);
+                assert_eq!(
+                    swap_red_blue(word).to_le_bytes(),
+                    expected.to_argb8888_bytes(),
+                    "channel {c}, alpha {a}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn odd_sizes_are_refused() {
+        let mut dst = [0; 4];
+        let short = convert_rgba_to(wl_shm::Format::Argb8888, &[0; 8], &mut dst, false);
+        assert_eq!(
+            short,
+            Err(WindowError::InvalidArgument("destination too small"))
+        );
+        let partial = convert_rgba_to(wl_shm::Format::Argb8888, &[0; 3], &mut dst, false);
+        assert!(partial.is_err());
+        let format = convert_rgba_to(wl_shm::Format::Yuyv, &[0; 4], &mut dst, false);
+        assert!(format.is_err());
+    }
+
+    #[test]
+    fn rgba_pictures_are_clipped_to_the_canvas() {
+        let mut bytes = vec![0; 2 * 4 * 2];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 2, 2, 2 * 4).unwrap();
+        //3 x 1, straight alpha: half transparent red, then opaque green and blue.
+        let picture = [0xFF, 0, 0, 0x80, 0, 0xFF, 0, 0xFF, 0, 0, 0xFF, 0xFF];
+        canvas.copy_from_rgba(&picture, 3, 1).unwrap();
+        assert_eq!(bytes[..8], [0, 0, 0x80, 0x80, 0, 0xFF, 0, 0xFF]);
+        assert_eq!(bytes[8..], [0; 8]);
+    }
+}
@@ -0,0 +1,393 @@
+use wayland_client::{
+    Connection, Dispatch, QueueHandle, WEnum,
+    protocol::{
+        wl_pointer::{self, WlPointer},
+        wl_seat::WlSeat,
+    },
+};
+use wayland_protocols::wp::pointer_constraints::zv1::client::{
+    zwp_confined_pointer_v1::{self, ZwpConfinedPointerV1},
+    zwp_locked_pointer_v1::{self, ZwpLockedPointerV1},
+    zwp_pointer_constraints_v1::{Lifetime, ZwpPointerConstraintsV1},
+};
+
+#[cfg(feature = "record")]
+use crate::Recorded;
+use crate::{
+    AppState, Rect, SerialKind, Window, WindowError, WindowEvent, protocol_log::protocol_log,
+    region::create_region,
+};
+
+//What the application asked for. Kept apart from the protocol object so the constraint
+//can be created again if the object goes away (e.g. the seat's pointer was recreated).
+#[derive(Debug, Clone)]
+enum WantedConstraint {
+    Lock,
+    Confine(Option<Vec<Rect>>),
+}
+
+enum Constraint {
+    Locked(ZwpLockedPointerV1),
+    Confined(ZwpConfinedPointerV1),
+}
+
+#[derive(Default)]
+pub(crate) struct PointerState {
+    pub(crate) pointer: Option<WlPointer>,
+    pub(crate) constraints: Option<ZwpPointerConstraintsV1>,
+    wanted: Option<WantedConstraint>,
+    constraint: Option<Constraint>,
+    //Whether the compositor told us the constraint is in effect right now (Locked/Confined).
+    active: bool,
+    cursor_hint: Option<(f64, f64)>,
+}
+
+impl PointerState {
+    pub(crate) fn is_constrained(&self) -> bool {
+        self.wanted.is_some()
+    }
+
+    fn destroy_constraint(&mut self) {
+        match self.constraint.take() {
+            Some(Constraint::Locked(locked)) => locked.destroy(),
+            Some(Constraint::Confined(confined)) => confined.destroy(),
+            None => {}
+        }
+    }
+}
+
+impl AppState {
+    //The pointer went away with its seat (or the seat's pointer capability): what hangs off it
+    //goes too. A wanted constraint stays wanted, for the pointer that takes over.
+    pub(crate) fn forget_pointer(&mut self) {
+        if self.pointer.active {
+            match self.pointer.constraint {
+                Some(Constraint::Locked(_)) => self.events.push(WindowEvent::PointerUnlocked),
+                Some(Constraint::Confined(_)) => self.events.push(WindowEvent::PointerUnconfined),
+                None => {}
+            }
+        }
+        self.pointer.destroy_constraint();
+        self.pointer.active = false;
+        self.pointer.pointer = None;
+        self.relative_pointer.forget();
+        self.gestures.forget();
+    }
+
+    //Creates the constraint object again if one is wanted and there's none.
+    pub(crate) fn reapply_pointer_constraint(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if self.pointer.wanted.is_some() && self.pointer.constraint.is_none() {
+            self.apply_pointer_constraint(queue_handle);
+        }
+    }
+}
+
+impl AppState {
+    //Creates the protocol object for whatever constraint the application wants.
+    //
+    //Quoting documentation: "The intersection of the region passed with this request and the input
+    //region of the surface is used to determine where the pointer must be in order for the
+    //constraint to take effect." Using a persistent lifetime means the compositor re-activates the
+    //constraint by itself whenever the pointer comes back, instead of us recreating it every time.
+    fn apply_pointer_constraint(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let (Some(wanted), Some(constraints), Some(pointer), Some(surface)) = (
+            self.pointer.wanted.clone(),
+            self.pointer.constraints.as_ref(),
+            self.pointer.pointer.as_ref(),
+            self.base_surface.as_ref(),
+        ) else {
+            return;
+        };
+
+        let constraint = match wanted {
+            WantedConstraint::Lock => {
+                let locked = constraints.lock_pointer(
+                    surface,
+                    pointer,
+                    None,
+                    Lifetime::Persistent,
+                    queue_handle,
+                    (),
+                );
+                if let Some((x, y)) = self.pointer.cursor_hint {
+                    locked.set_cursor_position_hint(x, y);
+                }
+                Constraint::Locked(locked)
+            }
+            WantedConstraint::Confine(rects) => {
+                let region = rects.as_deref().and_then(|rects| {
+                    self.compositor
+                        .as_ref()
+                        .map(|compositor| create_region(compositor, rects, queue_handle))
+                });
+                let confined = constraints.confine_pointer(
+                    surface,
+                    pointer,
+                    region.as_ref(),
+                    Lifetime::Persistent,
+                    queue_handle,
+                    (),
+                );
+                //The region is copied by the request, we don't need ours anymore.
+                if let Some(region) = region {
+                    region.destroy();
+                }
+                Constraint::Confined(confined)
+            }
+        };
+
+        self.pointer.constraint = Some(constraint);
+    }
+
+    //Drops the constraint. Destroying the object doesn't make the compositor send
+    //Unlocked/Unconfined, so the application is told here instead.
+    pub(crate) fn release_pointer_constraint(&mut self) {
+        if self.pointer.active {
+            match self.pointer.constraint {
+                Some(Constraint::Locked(_)) => self.events.push(WindowEvent::PointerUnlocked),
+                Some(Constraint::Confined(_)) => self.events.push(WindowEvent::PointerUnconfined),
+                None => {}
+            }
+        }
+
+        self.pointer.destroy_constraint();
+        self.pointer.wanted = None;
+        self.pointer.active = false;
+    }
+
+    fn set_pointer_constraint(
+        &mut self,
+        wanted: WantedConstraint,
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Result<(), WindowError> {
+        if self.pointer.constraints.is_none() {
+            return Err(WindowError::Unsupported("zwp_pointer_constraints_v1"));
+        }
+        if self.pointer.pointer.is_none() {
+            return Err(WindowError::NoPointer);
+        }
+
+        //Only one constraint per surface/pointer pair is allowed, asking for a second one is a
+        //protocol error (already_constrained), so the old one goes first.
+        self.release_pointer_constraint();
+        self.pointer.wanted = Some(wanted);
+        self.apply_pointer_constraint(queue_handle);
+        Ok(())
+    }
+}
+
+impl Window {
+    //Locks the pointer in place. While locked no absolute motion is delivered; pair it with
+    //relative pointer events to get the deltas.
+    pub fn lock_pointer(&mut self) -> Result<(), WindowError> {
+        let queue_handle = self.event_queue.handle();
+        self.state
+            .set_pointer_constraint(WantedConstraint::Lock, &queue_handle)
+    }
+
+    //Keeps the pointer inside the given rectangles of the surface (or the whole surface with None).
+    pub fn confine_pointer(&mut self, region: Option<&[Rect]>) -> Result<(), WindowError> {
+        let queue_handle = self.event_queue.handle();
+        self.state.set_pointer_constraint(
+            WantedConstraint::Confine(region.map(<[Rect]>::to_vec)),
+            &queue_handle,
+        )
+    }
+
+    //Releases a lock or confinement. Does nothing if there is none.
+    pub fn unlock(&mut self) {
+        self.state.release_pointer_constraint();
+    }
+
+    pub fn is_pointer_locked(&self) -> bool {
+        self.state.pointer.active
+            && matches!(self.state.pointer.constraint, Some(Constraint::Locked(_)))
+    }
+
+    //Where the cursor should show up once the lock is released, in surface-local coordinates.
+    //This is double-buffered state, so it only takes effect on the next surface commit.
+    pub fn set_cursor_position_hint(&mut self, x: f64, y: f64) {
+        self.state.pointer.cursor_hint = Some((x, y));
+
+        if let Some(Constraint::Locked(ref locked)) = self.state.pointer.constraint {
+            locked.set_cursor_position_hint(x, y);
+            let queue_handle = self.event_queue.handle();
+            self.state.commit_state(&queue_handle);
+        }
+    }
+}
+
+//wl_pointer: the pointer of the seat. Coordinates come as surface-local fixed point numbers
+//(wayland-client already converts them to f64 for us). The user data is the pointer's seat.
+impl Dispatch<WlPointer, WlSeat> for AppState {
+    fn event(
+        state: &mut Self,
+        pointer: &WlPointer,
+        event: wl_pointer::Event,
+        seat: &WlSeat,
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+    ) {
+        //Relative motion is only derived from the pointer the constraints follow, two pointers'
+        //positions would make a mess of the deltas.
+        let main_pointer = state.pointer.pointer.as_ref() == Some(pointer);
+        if state.scroll_event(pointer, state.seat_name(seat), &event) {
+            return;
+        }
+        match event {
+            wl_pointer::Event::Enter {
+                serial,
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                state.record_serial(seat, SerialKind::PointerEnter, serial);
+                #[cfg(feature = "record")]
+                state.record(Recorded::PointerEnter {
+                    x: surface_x,
+                    y: surface_y,
+                });
+                protocol_log!("pointer entered at {surface_x},{surface_y} (serial {serial})");
+                state.pointer_grab_ended(pointer);
+                //The surface regained pointer focus: if a constraint is wanted but its object is
+                //gone, establish it again.
+                state.reapply_pointer_constraint(queue_handle);
+                state.pointer_hit_moved(
+                    pointer,
+                    (surface_x, surface_y),
+                    Some(serial),
+                    queue_handle,
+                );
+                if main_pointer {
+                    state.reset_relative_motion(Some((surface_x, surface_y)));
+                }
+                state.events.push(WindowEvent::PointerEntered {
+                    seat: state.seat_name(seat),
+                    x: surface_x,
+                    y: surface_y,
+                });
+            }
+            wl_pointer::Event::Leave { serial, .. } => {
+                protocol_log!("pointer left (serial {serial})");
+                #[cfg(feature = "record")]
+                state.record(Recorded::PointerLeave);
+                if main_pointer {
+                    state.reset_relative_motion(None);
+                }
+                state.pointer_hit_left(pointer);
+                state.stop_button_repeat(Some(pointer));
+                state.events.push(WindowEvent::PointerLeft {
+                    seat: state.seat_name(seat),
+                });
+            }
+            wl_pointer::Event::Motion {
+                time,
+                surface_x,
+                surface_y,
+            } => {
+                #[cfg(feature = "record")]
+                state.record(Recorded::PointerMotion {
+                    x: surface_x,
+                    y: surface_y,
+                });
+                state.pointer_grab_ended(pointer);
+                state.pointer_hit_moved(pointer, (surface_x, surface_y), None, queue_handle);
+                state.button_repeat_moved(pointer, (surface_x, surface_y));
+                let seat = state.seat_name(seat);
+                //The deltas come with coalesce_motion.
+                state.events.push(WindowEvent::PointerMoved {
+                    seat: seat.clone(),
+                    x: surface_x,
+                    y: surface_y,
+                    dx: 0.0,
+                    dy: 0.0,
+                    samples: 1,
+                });
+                if main_pointer {
+                    state.synthesize_relative_motion(surface_x, surface_y, time, seat);
+                }
+            }
+            wl_pointer::Event::Button {
+                serial,
+                button,
+                state: button_state,
+                ..
+            } => {
+                let pressed = button_state == WEnum::Value(wl_pointer::ButtonState::Pressed);
+                #[cfg(feature = "record")]
+                state.record(Recorded::PointerButton { button, pressed });
+                //Only presses: moves, resizes and menus are started by a press, and that's the
+                //serial compositors compare with.
+                if pressed {
+                    state.record_serial(seat, SerialKind::PointerButton, serial);
+                }
+                //The button that ends a move or resize, see input_routing.rs.
+                if state.pointer_grab_ended(pointer) {
+                    return;
+                }
+                //Presses on the decorations move, resize or close, see hit_test.rs.
+                if state.decoration_button(pointer, seat, serial, button, pressed) {
+                    return;
+                }
+                let seat = state.seat_name(seat);
+                if !state.route_button(pointer, seat.clone(), button, pressed) {
+                    return;
+                }
+                //Buttons held in a repeat region, see button_repeat.rs.
+                state.track_button_repeat(pointer, seat.clone(), serial, button, pressed);
+                state.events.push(WindowEvent::PointerButton {
+                    seat,
+                    button,
+                    pressed,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwpLockedPointerV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpLockedPointerV1,
+        event: zwp_locked_pointer_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwp_locked_pointer_v1::Event::Locked => {
+                state.pointer.active = true;
+                state.events.push(WindowEvent::PointerLocked);
+            }
+            zwp_locked_pointer_v1::Event::Unlocked => {
+                state.pointer.active = false;
+                state.events.push(WindowEvent::PointerUnlocked);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwpConfinedPointerV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpConfinedPointerV1,
+        event: zwp_confined_pointer_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwp_confined_pointer_v1::Event::Confined => {
+                state.pointer.active = true;
+                state.events.push(WindowEvent::PointerConfined);
+            }
+            zwp_confined_pointer_v1::Event::Unconfined => {
+                state.pointer.active = false;
+                state.events.push(WindowEvent::PointerUnconfined);
+            }
+            _ => {}
+        }
+    }
+}
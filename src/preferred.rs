@@ -0,0 +1,186 @@
+//wl_surface v6 says which buffer scale and transform the compositor would like, instead of
+//leaving it to guess from the outputs the surface entered:
+//
+//Quoting documentation: "It is intended that scaling aware clients use this event to scale their
+//content and use wl_surface.set_buffer_scale to indicate the scale they have rendered with. This
+//allows clients to supply a higher detail buffer."
+//
+//The preferred scale is applied by default: buffers are made at the window size times the scale,
+//canvases get that many more pixels (Canvas::scale goes back to 1), and the window keeps its size
+//in surface coordinates. Applications doing fractional scaling themselves (wp_fractional_scale_v1
+//and a viewport, see the --fractional example) turn it off, theirs takes precedence.
+//
+//The preferred transform is only followed on request. Quoting documentation: "Applying this
+//transformation to the surface buffer contents and using wl_surface.set_buffer_transform might
+//allow the compositor to use the surface buffer more efficiently." That means drawing the content
+//turned, which Canvas doesn't do by itself (see Transform::buffer_to_surface): following it
+//without that shows the content turned on rotated outputs.
+//
+//Both come before the first configure, which then makes the first buffer with them. Later ones are
+//applied after the dispatch: a new buffer at the new size, a Resized to draw it, and ScaleChanged
+//or TransformChanged with the old and new values.
+
+use wayland_client::{Proxy, QueueHandle};
+
+use crate::{AppState, RenderMode, ScaleMath, Transform, Window, WindowEvent};
+
+pub(crate) struct PreferredState {
+    apply_scale: bool,
+    follow_transform: bool,
+    //The compositor's, Normal until it says.
+    pub(crate) transform: Transform,
+    //The scale the window's buffers are made at.
+    pub(crate) scale: i32,
+    //The last set_buffer_scale sent, 1 before any.
+    pub(crate) sent_scale: i32,
+    //A preference came in, applied by apply_preferred.
+    pub(crate) pending: bool,
+}
+
+impl Default for PreferredState {
+    fn default() -> Self {
+        PreferredState {
+            apply_scale: true,
+            follow_transform: false,
+            transform: Transform::Normal,
+            scale: 1,
+            sent_scale: 1,
+            pending: false,
+        }
+    }
+}
+
+impl PreferredState {
+    //The new surface hears the preferences again.
+    pub(crate) fn for_reconnect(&self) -> PreferredState {
+        PreferredState {
+            apply_scale: self.apply_scale,
+            follow_transform: self.follow_transform,
+            ..PreferredState::default()
+        }
+    }
+}
+
+impl AppState {
+    pub(crate) fn scale_math(&self) -> ScaleMath {
+        ScaleMath::integer(self.preferred.scale)
+    }
+
+    //Before every attach of a buffer of `size`: the window's buffer scale, or 1 for buffers it
+    //doesn't divide (a single pixel), which would be an invalid_size error otherwise.
+    pub(crate) fn set_surface_scale(&mut self, size: (u32, u32)) {
+        let scale = self.scale_math();
+        let scale = if scale.divides(size) {
+            scale.buffer_scale()
+        } else {
+            1
+        };
+        if scale != self.preferred.sent_scale
+            && let Some(ref surface) = self.base_surface
+        {
+            surface.set_buffer_scale(scale);
+            self.preferred.sent_scale = scale;
+        }
+    }
+
+    //After the dispatch that brought a preference, before its configure (if one came along) is
+    //applied.
+    pub(crate) fn apply_preferred(&mut self, queue_handle: &QueueHandle<Self>) {
+        if !std::mem::take(&mut self.preferred.pending) {
+            return;
+        }
+        let Some(version) = self.base_surface.as_ref().map(|surface| surface.version()) else {
+            return;
+        };
+        let scale = match self.outputs.preferred_scale {
+            Some(scale) if self.preferred.apply_scale && version >= 3 => scale,
+            _ => 1,
+        };
+        let transform = if self.preferred.follow_transform {
+            self.preferred.transform
+        } else {
+            self.buffer_transform
+        };
+        if scale == self.preferred.scale && transform == self.buffer_transform {
+            return;
+        }
+
+        //The window's size, in surface coordinates, from before the change.
+        let geometry = self.window_geometry();
+        let size = (geometry.width as u32, geometry.height as u32);
+        if scale != self.preferred.scale {
+            self.events.push(WindowEvent::ScaleChanged {
+                old: self.preferred.scale,
+                new: scale,
+            });
+            self.preferred.scale = scale;
+        }
+        if transform != self.buffer_transform {
+            self.base_surface
+                .as_ref()
+                .unwrap()
+                .set_buffer_transform(transform.into());
+            self.events.push(WindowEvent::TransformChanged {
+                old: self.buffer_transform,
+                new: transform,
+            });
+            self.buffer_transform = transform;
+        }
+
+        if self.render_mode != RenderMode::Shm {
+            //Their next commit has the buffer at the new size, the scale has to go with it.
+            self.set_surface_scale(self.buffer_size_for(size));
+        }
+        if !self.configured() {
+            //The first configure makes the buffer, this only keeps the sizes consistent.
+            self.buffer_size = self.buffer_size_for(size);
+            return;
+        }
+        if self.render_mode != RenderMode::Shm {
+            self.resize_client_rendered(size, true);
+            self.apply_window_geometry();
+            self.apply_input_region(queue_handle);
+            return;
+        }
+        self.resize_shm(size, false, queue_handle);
+        self.apply_window_geometry();
+        self.apply_input_region(queue_handle);
+        if self.buffer.is_some() {
+            self.present_gradient(queue_handle);
+        } else {
+            self.show_placeholder(queue_handle);
+        }
+    }
+}
+
+impl Window {
+    //On by default, see preferred.rs. Turning it off (for fractional scaling done by the
+    //application) goes back to scale 1 after the next dispatch.
+    pub fn set_apply_preferred_scale(&mut self, apply: bool) {
+        self.state.preferred.apply_scale = apply;
+        self.state.preferred.pending = true;
+    }
+
+    //Off by default, see preferred.rs: only for applications drawing their content turned.
+    //Takes effect after the next dispatch. Turning it off keeps the transform it had, see
+    //Window::set_buffer_transform.
+    pub fn set_follow_preferred_transform(&mut self, follow: bool) {
+        self.state.preferred.follow_transform = follow;
+        self.state.preferred.pending = true;
+    }
+
+    //The scale buffers are made at: the compositor's preferred one, or 1.
+    pub fn buffer_scale(&self) -> i32 {
+        self.state.preferred.scale
+    }
+
+    //The buffer scale's conversions between surface coordinates and buffer pixels, see
+    //scale_math.rs.
+    pub fn scale_math(&self) -> ScaleMath {
+        self.state.scale_math()
+    }
+
+    pub fn preferred_transform(&self) -> Transform {
+        self.state.preferred.transform
+    }
+}
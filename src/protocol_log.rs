@@ -0,0 +1,66 @@
+#[cfg(feature = "protocol-log")]
+use std::time::Instant;
+
+use crate::AppState;
+
+//Debug lines for the milestones that decide whether anything shows up: globals, configures,
+//buffers, frame callbacks, seat capabilities and focus. A much shorter read than WAYLAND_DEBUG=1
+//when a buffer doesn't show. Built with the protocol-log feature, shown with
+//RUST_LOG=simple_wayland_window::protocol=debug.
+//
+//Without the feature the condition is a constant false and the whole line (arguments included)
+//is compiled out, while the arguments still count as used.
+macro_rules! protocol_log {
+    ($($arg:tt)+) => {
+        if cfg!(feature = "protocol-log") {
+            log::debug!(target: "simple_wayland_window::protocol", $($arg)+);
+        }
+    };
+}
+pub(crate) use protocol_log;
+
+//One frame, from the commit of its buffer to the compositor's frame done (the closest we get to
+//"presented" without wp_presentation). Numbered, so the lines of a frame can be told apart and
+//the time in between shows where the latency is.
+#[cfg(feature = "protocol-log")]
+#[derive(Default)]
+pub(crate) struct FrameSpan {
+    frame: u64,
+    committed: Option<Instant>,
+}
+
+impl AppState {
+    //Called right after a commit with a new buffer. `buffer` says which kind it was.
+    #[cfg_attr(not(feature = "protocol-log"), allow(unused_variables))]
+    pub(crate) fn log_commit(&mut self, buffer: &str) {
+        #[cfg(feature = "record")]
+        self.record(crate::Recorded::Commit {
+            width: self.buffer_size.0,
+            height: self.buffer_size.1,
+        });
+        #[cfg(feature = "protocol-log")]
+        {
+            self.frame_span.frame += 1;
+            self.frame_span.committed = Some(Instant::now());
+            protocol_log!(
+                "frame {}: {buffer} attached, buffer size {:?}, committed",
+                self.frame_span.frame,
+                self.buffer_size
+            );
+        }
+    }
+
+    //Called from the frame callback.
+    #[cfg_attr(not(feature = "protocol-log"), allow(unused_variables))]
+    pub(crate) fn log_frame_done(&mut self, time: u32) {
+        #[cfg(feature = "protocol-log")]
+        match self.frame_span.committed.take() {
+            Some(committed) => protocol_log!(
+                "frame {}: done at {time}ms, {:.1?} after its commit",
+                self.frame_span.frame,
+                committed.elapsed()
+            ),
+            None => protocol_log!("frame done at {time}ms, nothing committed since the last one"),
+        }
+    }
+}
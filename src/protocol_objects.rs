@@ -0,0 +1,64 @@
+//The wayland-client objects behind the window, for protocols this crate doesn't wrap.
+//
+//Our Dispatch impls are all on AppState, and the orphan rule keeps anyone else from adding theirs
+//to it. Rather than making AppState generic over a user slot (every Dispatch impl in the crate
+//would carry the parameter), extra protocols go on an event queue of their own, on the same
+//connection, with the application's own state type:
+//
+//  let (globals, mut queue) = registry_queue_init::<MyState>(window.connection())?;
+//  let manager: WpFractionalScaleManagerV1 = globals.bind(&queue.handle(), 1..=1, ())?;
+//  manager.get_fractional_scale(window.wl_surface().unwrap(), &queue.handle(), ());
+//  while window.is_running() {
+//      for event in window.pump_events() { ... }
+//      queue.dispatch_pending(&mut my_state)?;
+//  }
+//
+//Reading the socket is shared: whichever queue reads, events land in the queue their object
+//belongs to. pump_events wakes up for the other queue's events too and just returns nothing, so
+//dispatch_pending right after it sees them. EventLoop and the event stream only dispatch ours.
+//
+//Proxies from one queue can be passed to requests on another, the surface above is. What's
+//created from ours keeps going to ours, so don't make objects through the proxies returned here
+//(get_popup on our xdg_surface, say) expecting their events on your queue: make them through an
+//object of your queue instead.
+//
+//Everything here is made anew by reconnect, and the wl_surface only exists after the first
+//dispatch (when the compositor global was bound).
+
+use wayland_client::{
+    Connection,
+    protocol::{wl_compositor::WlCompositor, wl_shm::WlShm, wl_surface::WlSurface},
+};
+use wayland_protocols::xdg::shell::client::{xdg_surface::XdgSurface, xdg_toplevel::XdgToplevel};
+
+use crate::Window;
+
+impl Window {
+    //The connection the window lives on. Cloning it is cheap and keeps the connection open.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn wl_compositor(&self) -> Option<&WlCompositor> {
+        self.state.compositor.as_ref()
+    }
+
+    pub fn wl_surface(&self) -> Option<&WlSurface> {
+        self.state.base_surface.as_ref()
+    }
+
+    pub fn xdg_surface(&self) -> Option<&XdgSurface> {
+        self.state.xdg_surface.as_ref().map(|(surface, _)| surface)
+    }
+
+    pub fn xdg_toplevel(&self) -> Option<&XdgToplevel> {
+        self.state
+            .xdg_surface
+            .as_ref()
+            .map(|(_, toplevel)| toplevel)
+    }
+
+    pub fn wl_shm(&self) -> Option<&WlShm> {
+        self.state.shm.as_ref()
+    }
+}
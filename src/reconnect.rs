@@ -0,0 +1,145 @@
+use std::fmt::Display;
+
+use wayland_protocols::xdg::shell::client::xdg_toplevel;
+
+use crate::{
+    AppState, Background, Window, WindowError, WindowEvent, WindowOptions,
+    render_thread::RenderThreadState,
+};
+
+impl AppState {
+    //Once a dispatch fails nothing more will come from this connection: the compositor crashed,
+    //quit, or disconnected us over a protocol error. Reported once, and the window stops running
+    //so loops end by themselves unless the application reconnects.
+    pub(crate) fn connection_lost(&mut self, reason: impl Display) {
+        if self.connection_lost {
+            return;
+        }
+        log::warn!("lost the connection to the compositor: {reason}");
+        self.connection_lost = true;
+        self.running = false;
+        self.events.push(WindowEvent::ConnectionLost);
+    }
+
+    //What the window looks like to the application right now, to be created again just like it.
+    //The toplevel states are the compositor's word, our options only count until it gave it.
+    fn current_options(&self) -> WindowOptions {
+        let maximized = self.has_state(xdg_toplevel::State::Maximized);
+        let fullscreen = self.has_state(xdg_toplevel::State::Fullscreen);
+        WindowOptions {
+            title: self.title.clone(),
+            app_id: self.app_id.clone(),
+            render_mode: self.render_mode,
+            //The new connection starts at scale 1, until the compositor says again.
+            size: self.scale_math().size_to_logical(self.buffer_size),
+            preferred_size: self.sizing.preferred_size,
+            format: self.format,
+            maximized,
+            fullscreen,
+            fullscreen_output: self.fullscreen_output.clone(),
+            decorations: self.geometry.decorations,
+            resize_content: self.resize_content,
+            resize_preview: self.resize_preview.interval,
+            //The renderer itself goes over in reconnect.
+            background: Background::default(),
+            //The state itself goes over in reconnect, the file isn't read again.
+            size_persistence: None,
+            role: self.utility.role,
+            //The first frame is long gone.
+            startup_timing: false,
+        }
+    }
+}
+
+impl Window {
+    //dispatch_pending, a failure meaning the connection is gone. How many events it dispatched,
+    //none then.
+    pub(crate) fn dispatch_queued(&mut self) -> usize {
+        let dispatched = self
+            .event_queue
+            .dispatch_pending(&mut self.state)
+            .unwrap_or_else(|err| {
+                self.connection_failed(err);
+                0
+            });
+        self.state.bound_events();
+        dispatched
+    }
+
+    //False once WindowEvent::ConnectionLost was sent, until reconnect.
+    pub fn is_connected(&self) -> bool {
+        !self.state.connection_lost
+    }
+
+    //Connects again (to the socket given to Window::connect, else through the environment) and
+    //creates the window anew with what it had: title, app id, size, maximized and fullscreen,
+    //decorations and their hit-testing, scroll configuration, motion coalescing, frame skipping,
+    //buffer release when hidden, the swapchain configuration, the watchdog threshold, the event
+    //capacity, the flush policy, size persistence, whether preferred scale and transform are
+    //followed, the renderer, relative pointer isolation, size limits, aspect ratio, content type,
+    //color description, icon, key bindings, idle timeouts and the user data.
+    //Proxies keep working. The window is running again and goes through a first configure, like
+    //a new one.
+    //
+    //Anything made from the old surface has to be made again: EGL contexts (make_current), buffers
+    //from present_buffer, pointer constraints, foreign handles. A dialog (or utility window) comes
+    //back as a normal window, its parent lived on the old connection.
+    pub fn reconnect(&mut self) -> Result<(), WindowError> {
+        let connection = self.connect_again()?;
+
+        let mut window = Window::with_connection(connection, self.state.current_options());
+        let (old, new) = (&mut self.state, &mut window.state);
+        new.view = old.view;
+        new.sizing = std::mem::take(&mut old.sizing);
+        new.key_bindings = std::mem::take(&mut old.key_bindings);
+        new.content_type = old.content_type.for_reconnect();
+        new.icon = old.icon.for_reconnect();
+        new.input_region = old.input_region.for_reconnect();
+        new.idle = old.idle.for_reconnect();
+        new.hit_test = old.hit_test.for_reconnect();
+        new.button_repeat = old.button_repeat.for_reconnect();
+        new.flush.policy = old.flush.policy;
+        new.scroll = old.scroll.for_reconnect();
+        new.motion = old.motion.for_reconnect();
+        new.frame_skip = old.frame_skip.for_reconnect();
+        new.hidden_buffers = old.hidden_buffers.for_reconnect();
+        new.swapchain = old.swapchain.for_reconnect();
+        new.watchdog = old.watchdog.for_reconnect();
+        new.backpressure = old.backpressure.for_reconnect();
+        new.size_persistence = std::mem::take(&mut old.size_persistence);
+        new.preferred = old.preferred.for_reconnect();
+        new.user_data = std::mem::take(&mut old.user_data);
+        new.renderer = old.renderer.for_reconnect();
+        #[cfg(feature = "color-management")]
+        {
+            new.color = old.color.for_reconnect();
+        }
+        new.render_thread = old
+            .render_thread
+            .take()
+            .map(RenderThreadState::for_reconnect);
+        window.user_events = self.user_events.take();
+        window.socket = self.socket.take();
+
+        //Dropping the old window sends its teardown into the dead connection, where it goes
+        //nowhere.
+        //Opacity and buffer transform are applied once the new window has its globals: the
+        //opacity then goes through the protocol if it's there instead of fading the new gradient.
+        let opacity = self.opacity();
+        let transform = self.buffer_transform();
+        let isolation = self.relative_pointer_isolation();
+        *self = window;
+        if let Some(dispatch) = isolation
+            && let Err(err) = self.isolate_relative_pointer(dispatch)
+        {
+            log::warn!("relative pointer not isolated again: {err}");
+        }
+        if opacity != 1.0 {
+            self.set_opacity(opacity);
+        }
+        if let Err(err) = self.set_buffer_transform(transform) {
+            log::warn!("buffer transform not restored: {err}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+//What the compositor told the window and what the window did about it, written down to turn a
+//bug seen on someone else's compositor into a test that runs without it: the recording's inputs
+//are fed to the test compositor (tests/compositor) and the window has to ack and commit the same
+//way again.
+//
+//Serials and object ids are left out, a replay gets its own. So is which output or seat
+//something came from: the test compositor has one of each. Times are kept for reading, a replay
+//only keeps the order (and which inputs came before which ack or commit, so configures that came
+//in one batch come in one again).
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use wayland_client::protocol::wl_surface;
+
+use crate::{AppState, Window, WindowError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recorded {
+    //Inputs. A configure is the xdg_toplevel one with the xdg_surface one that applied it, the
+    //states being xdg_toplevel.state values.
+    Configure {
+        width: i32,
+        height: i32,
+        states: Vec<u32>,
+    },
+    Close,
+    SurfaceEnter,
+    SurfaceLeave,
+    OutputScale(i32),
+    PreferredScale(i32),
+    KeyboardEnter,
+    KeyboardLeave,
+    //Evdev key code.
+    Key {
+        key: u32,
+        pressed: bool,
+    },
+    PointerEnter {
+        x: f64,
+        y: f64,
+    },
+    PointerLeave,
+    PointerMotion {
+        x: f64,
+        y: f64,
+    },
+    PointerButton {
+        button: u32,
+        pressed: bool,
+    },
+    //What the window did.
+    AckConfigure,
+    //A commit with a new buffer, of that size.
+    Commit {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl Recorded {
+    //Whether it came from the compositor, as opposed to being the window's doing.
+    pub fn is_input(&self) -> bool {
+        !matches!(self, Recorded::AckConfigure | Recorded::Commit { .. })
+    }
+}
+
+//Entries with the time since the recording started, in whole milliseconds. Display writes one
+//line per entry, which FromStr reads back.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+    pub entries: Vec<(Duration, Recorded)>,
+}
+
+impl Recording {
+    //Acks and commits, what a replay has to come up with again.
+    pub fn effects(&self) -> impl Iterator<Item = &Recorded> {
+        self.entries
+            .iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| !entry.is_input())
+    }
+}
+
+const HEADER: &str = "#simple-wayland-window recording 1";
+
+fn pressed(pressed: bool) -> &'static str {
+    if pressed { "down" } else { "up" }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for (time, entry) in &self.entries {
+            write!(f, "{} ", time.as_millis())?;
+            match entry {
+                Recorded::Configure {
+                    width,
+                    height,
+                    states,
+                } => {
+                    let states: Vec<_> = states.iter().map(u32::to_string).collect();
+                    let states = if states.is_empty() {
+                        "-".into()
+                    } else {
+                        states.join(",")
+                    };
+                    writeln!(f, "configure {width} {height} {states}")
+                }
+                Recorded::Close => writeln!(f, "close"),
+                Recorded::SurfaceEnter => writeln!(f, "surface-enter"),
+                Recorded::SurfaceLeave => writeln!(f, "surface-leave"),
+                Recorded::OutputScale(scale) => writeln!(f, "output-scale {scale}"),
+                Recorded::PreferredScale(scale) => writeln!(f, "preferred-scale {scale}"),
+                Recorded::KeyboardEnter => writeln!(f, "keyboard-enter"),
+                Recorded::KeyboardLeave => writeln!(f, "keyboard-leave"),
+                Recorded::Key { key, pressed: down } => {
+                    writeln!(f, "key {key} {}", pressed(*down))
+                }
+                Recorded::PointerEnter { x, y } => writeln!(f, "pointer-enter {x} {y}"),
+                Recorded::PointerLeave => writeln!(f, "pointer-leave"),
+                Recorded::PointerMotion { x, y } => writeln!(f, "pointer-motion {x} {y}"),
+                Recorded::PointerButton {
+                    button,
+                    pressed: down,
+                } => writeln!(f, "button {button} {}", pressed(*down)),
+                Recorded::AckConfigure => writeln!(f, "ack"),
+                Recorded::Commit { width, height } => writeln!(f, "commit {width} {height}"),
+            }?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Option<(Duration, Recorded)> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    let (time, words) = words.split_first()?;
+    let time = Duration::from_millis(time.parse().ok()?);
+    let down = |word: &str| match word {
+        "down" => Some(true),
+        "up" => Some(false),
+        _ => None,
+    };
+    let entry = match *words {
+        ["configure", width, height, states] => Recorded::Configure {
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+            states: match states {
+                "-" => Vec::new(),
+                states => states
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .ok()?,
+            },
+        },
+        ["close"] => Recorded::Close,
+        ["surface-enter"] => Recorded::SurfaceEnter,
+        ["surface-leave"] => Recorded::SurfaceLeave,
+        ["output-scale", scale] => Recorded::OutputScale(scale.parse().ok()?),
+        ["preferred-scale", scale] => Recorded::PreferredScale(scale.parse().ok()?),
+        ["keyboard-enter"] => Recorded::KeyboardEnter,
+        ["keyboard-leave"] => Recorded::KeyboardLeave,
+        ["key", key, state] => Recorded::Key {
+            key: key.parse().ok()?,
+            pressed: down(state)?,
+        },
+        ["pointer-enter", x, y] => Recorded::PointerEnter {
+            x: x.parse().ok()?,
+            y: y.parse().ok()?,
+        },
+        ["pointer-leave"] => Recorded::PointerLeave,
+        ["pointer-motion", x, y] => Recorded::PointerMotion {
+            x: x.parse().ok()?,
+            y: y.parse().ok()?,
+        },
+        ["button", button, state] => Recorded::PointerButton {
+            button: button.parse().ok()?,
+            pressed: down(state)?,
+        },
+        ["ack"] => Recorded::AckConfigure,
+        ["commit", width, height] => Recorded::Commit {
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+        },
+        _ => return None,
+    };
+    Some((time, entry))
+}
+
+impl FromStr for Recording {
+    type Err = WindowError;
+
+    fn from_str(text: &str) -> Result<Recording, WindowError> {
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let entry = parse_line(line).ok_or_else(|| {
+                WindowError::Recording(format!("line {}: can't read {line:?}", number + 1))
+            })?;
+            entries.push(entry);
+        }
+        Ok(Recording { entries })
+    }
+}
+
+pub(crate) struct RecordingState {
+    start: Instant,
+    recording: Recording,
+}
+
+impl AppState {
+    //Enters and leaves keep no output, the test compositor has one.
+    pub(crate) fn record_surface_event(&mut self, event: &wl_surface::Event) {
+        let entry = match *event {
+            wl_surface::Event::Enter { .. } => Recorded::SurfaceEnter,
+            wl_surface::Event::Leave { .. } => Recorded::SurfaceLeave,
+            wl_surface::Event::PreferredBufferScale { factor } => Recorded::PreferredScale(factor),
+            _ => return,
+        };
+        self.record(entry);
+    }
+
+    pub(crate) fn record(&mut self, entry: Recorded) {
+        if let Some(ref mut state) = self.recording {
+            let time = Duration::from_millis(state.start.elapsed().as_millis() as u64);
+            state.recording.entries.push((time, entry));
+        }
+    }
+}
+
+impl Window {
+    //Starts writing down configures, input, scale and focus changes, and the acks and commits
+    //that follow. Right after the window is made, to have it all from the first configure on.
+    //A recording already going starts over.
+    pub fn start_recording(&mut self) {
+        self.state.recording = Some(RecordingState {
+            start: Instant::now(),
+            recording: Recording::default(),
+        });
+    }
+
+    //What was recorded so far, None when not recording.
+    pub fn recording(&self) -> Option<&Recording> {
+        self.state.recording.as_ref().map(|state| &state.recording)
+    }
+
+    //Stops recording and hands it over (empty if there was none).
+    pub fn stop_recording(&mut self) -> Recording {
+        self.state
+            .recording
+            .take()
+            .map(|state| state.recording)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_what_it_writes() {
+        let text = "#simple-wayland-window recording 1\n\
+                    0 configure 0 0 -\n\
+                    4 ack\n\
+                    5 commit 320 240\n\
+                    1200 configure 640 480 1,4\n\
+                    1250 pointer-motion 10.5 -3.25\n\
+                    1300 key 30 down\n";
+        let recording: Recording = text.parse().unwrap();
+        assert_eq!(recording.entries.len(), 6);
+        assert_eq!(
+            recording.entries[3],
+            (
+                Duration::from_millis(1200),
+                Recorded::Configure {
+                    width: 640,
+                    height: 480,
+                    states: vec![1, 4],
+                }
+            )
+        );
+        assert_eq!(recording.to_string(), text);
+    }
+
+    #[test]
+    fn bad_lines_are_named() {
+        let err = "0 ack\n12 key 30 sideways\n"
+            .parse::<Recording>()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            WindowError::Recording("line 2: can't read \"12 key 30 sideways\"".into())
+        );
+    }
+}
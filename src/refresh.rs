@@ -0,0 +1,107 @@
+//How often the screen the window is on refreshes, for animations that tick on a timer rather than
+//on frame callbacks (before the first frame, or while nothing throttles them). Two sources:
+//presentation-time feedback on our own commits, the compositor's own prediction for the output
+//that shows the surface, and otherwise the current mode of the outputs the surface entered.
+//
+//Quoting documentation (wl_output.mode): "Clients should not use the refresh rate to schedule
+//frames. Instead, they should use the wl_surface.frame event or the presentation-time protocol."
+//Which is why this only fills in for the frame callback, and why presentation feedback wins.
+
+use std::time::Duration;
+
+use wayland_client::{Connection, Dispatch, QueueHandle, delegate_noop};
+use wayland_protocols::wp::presentation_time::client::{
+    wp_presentation::WpPresentation,
+    wp_presentation_feedback::{self, WpPresentationFeedback},
+};
+
+use crate::{AppState, Window, protocol_log::protocol_log};
+
+//Where current_refresh_interval got its value, for debugging a stuttering animation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshSource {
+    //wp_presentation_feedback.presented of one of our commits.
+    Presentation,
+    //The current mode of the outputs the surface is on.
+    Output,
+}
+
+#[derive(Default)]
+pub(crate) struct RefreshState {
+    pub(crate) presentation: Option<WpPresentation>,
+    //Refresh of the last presented commit, None when unknown or since the surface moved.
+    presented: Option<Duration>,
+}
+
+impl AppState {
+    //Asks for feedback on the commit about to be made. Feedback objects are one-shot, the
+    //compositor destroys them with presented or discarded.
+    pub(crate) fn request_presentation_feedback(&self, queue_handle: &QueueHandle<AppState>) {
+        if let (Some(presentation), Some(surface)) = (
+            self.refresh.presentation.as_ref(),
+            self.base_surface.as_ref(),
+        ) {
+            presentation.feedback(surface, queue_handle, ());
+        }
+    }
+
+    //The surface entered or left an output: what was presented where it was says nothing about
+    //where it is now.
+    pub(crate) fn forget_presented_refresh(&mut self) {
+        self.refresh.presented = None;
+    }
+
+    //The fastest of the outputs the surface is on: compositors showing a window on two outputs
+    //usually pace it to the faster one.
+    pub(crate) fn refresh_interval(&self) -> Option<(Duration, RefreshSource)> {
+        if let Some(interval) = self.refresh.presented {
+            return Some((interval, RefreshSource::Presentation));
+        }
+        let fastest = self
+            .surface_outputs()
+            .iter()
+            .filter_map(|output| output.refresh_mhz)
+            .max()?;
+        //mHz: 60 Hz is 60000, one refresh 10^12 / 60000 ns.
+        let interval = Duration::from_nanos(1_000_000_000_000 / u64::from(fastest));
+        Some((interval, RefreshSource::Output))
+    }
+}
+
+impl Window {
+    //Time between two refreshes of the screen the window is on, None until known (no output
+    //entered yet, or one with a refresh of 0, like virtual outputs). Follows the window to other
+    //outputs. See RefreshSource for where it came from.
+    pub fn current_refresh_interval(&self) -> Option<Duration> {
+        self.state.refresh_interval().map(|(interval, _)| interval)
+    }
+
+    pub fn refresh_source(&self) -> Option<RefreshSource> {
+        self.state.refresh_interval().map(|(_, source)| source)
+    }
+}
+
+impl Dispatch<WpPresentationFeedback, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &WpPresentationFeedback,
+        event: wp_presentation_feedback::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        //Quoting documentation: "If such prediction cannot usefully be done, the argument is
+        //zero." The outputs' modes are all we have then.
+        if let wp_presentation_feedback::Event::Presented { refresh, .. } = event {
+            state.startup_presented();
+            let presented = (refresh > 0).then(|| Duration::from_nanos(refresh.into()));
+            if presented != state.refresh.presented {
+                protocol_log!("presentation refresh {presented:?}");
+            }
+            state.refresh.presented = presented;
+        }
+    }
+}
+
+//clock_id only matters for the timestamps, which aren't kept.
+delegate_noop!(AppState: ignore WpPresentation);
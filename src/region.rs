@@ -0,0 +1,41 @@
+use wayland_client::{
+    QueueHandle,
+    protocol::{wl_compositor, wl_region},
+};
+
+use crate::AppState;
+
+//A rectangle in surface-local coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+//wl_region: a region is just a set of rectangles, built by adding (and subtracting) them.
+//Requests that take a region (confine_pointer, set_input_region, set_opaque_region...) copy
+//it, so the region can be destroyed right after it is used.
+pub(crate) fn create_region(
+    compositor: &wl_compositor::WlCompositor,
+    rects: &[Rect],
+    queue_handle: &QueueHandle<AppState>,
+) -> wl_region::WlRegion {
+    let region = compositor.create_region(queue_handle, ());
+    for rect in rects {
+        region.add(rect.x, rect.y, rect.width, rect.height);
+    }
+    region
+}
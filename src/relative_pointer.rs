@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, protocol::wl_seat::WlSeat};
+use wayland_protocols::wp::relative_pointer::zv1::client::{
+    zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1,
+    zwp_relative_pointer_v1::{self, ZwpRelativePointerV1},
+};
+
+use crate::{AppState, WindowEvent};
+
+#[derive(Default)]
+pub(crate) struct RelativePointerState {
+    pub(crate) manager: Option<ZwpRelativePointerManagerV1>,
+    relative_pointer: Option<ZwpRelativePointerV1>,
+    //Last absolute position, used to derive deltas when the compositor has no relative pointer.
+    last_position: Option<(f64, f64)>,
+}
+
+impl RelativePointerState {
+    pub(crate) fn forget(&mut self) {
+        if let Some(relative_pointer) = self.relative_pointer.take() {
+            relative_pointer.destroy();
+        }
+        self.last_position = None;
+    }
+}
+
+impl AppState {
+    //A relative pointer is an extension of one wl_pointer, so it can only be created once both
+    //the manager global and the seat's pointer are around. Whichever arrives last calls this.
+    //It goes on the side queue when there is one (see side_queue.rs).
+    pub(crate) fn init_relative_pointer(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if self.relative_pointer.relative_pointer.is_some() {
+            return;
+        }
+
+        if let (Some(manager), Some(pointer)) = (
+            self.relative_pointer.manager.as_ref(),
+            self.pointer.pointer.as_ref(),
+        ) {
+            //Its events are tagged with the pointer's seat.
+            let seat = pointer.data::<WlSeat>().unwrap().clone();
+            let relative_pointer = match self.side_queue {
+                //The seat's name as it is now, the side queue can't look it up later.
+                Some(ref side_queue) => manager.get_relative_pointer(
+                    pointer,
+                    &side_queue.handle(),
+                    self.seat_name(&seat),
+                ),
+                None => manager.get_relative_pointer(pointer, queue_handle, seat),
+            };
+            self.relative_pointer.relative_pointer = Some(relative_pointer);
+        }
+    }
+
+    //Fallback for compositors without zwp_relative_pointer_manager_v1: the delta between two
+    //absolute motion events. These deltas are accelerated and stop at the edges of the surface
+    //(and entirely while the pointer is locked), so they're flagged as synthetic.
+    pub(crate) fn synthesize_relative_motion(&mut self, x: f64, y: f64, time: u32, seat: Arc<str>) {
+        if self.relative_pointer.relative_pointer.is_some() {
+            return;
+        }
+
+        if let Some((last_x, last_y)) = self.relative_pointer.last_position {
+            let (dx, dy) = (x - last_x, y - last_y);
+            self.events.push(WindowEvent::RelativeMotion {
+                seat,
+                dx,
+                dy,
+                dx_unaccel: dx,
+                dy_unaccel: dy,
+                utime: u64::from(time) * 1000,
+                synthetic: true,
+                samples: 1,
+            });
+        }
+        self.relative_pointer.last_position = Some((x, y));
+    }
+
+    //Enter/Leave break the chain of absolute positions, the next delta starts from scratch.
+    pub(crate) fn reset_relative_motion(&mut self, position: Option<(f64, f64)>) {
+        self.relative_pointer.last_position = position;
+    }
+}
+
+//Quoting documentation: "Relative x/y pointer motion from the pointer of the seat associated with
+//this object. A relative motion is in the same dimension as regular wl_pointer motion events,
+//except they do not represent an absolute position."
+//
+//The timestamp comes split in two u32 halves with microsecond granularity.
+impl Dispatch<ZwpRelativePointerV1, WlSeat> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpRelativePointerV1,
+        event: zwp_relative_pointer_v1::Event,
+        seat: &WlSeat,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwp_relative_pointer_v1::Event::RelativeMotion {
+            utime_hi,
+            utime_lo,
+            dx,
+            dy,
+            dx_unaccel,
+            dy_unaccel,
+        } = event
+        {
+            state.events.push(WindowEvent::RelativeMotion {
+                seat: state.seat_name(seat),
+                dx,
+                dy,
+                dx_unaccel,
+                dy_unaccel,
+                utime: (u64::from(utime_hi) << 32) | u64::from(utime_lo),
+                synthetic: false,
+                samples: 1,
+            });
+        }
+    }
+}
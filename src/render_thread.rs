@@ -0,0 +1,334 @@
+//Rendering on a thread of its own. Everything Wayland stays on the thread dispatching: the event
+//queue and AppState aren't Send, and a surface committed from two threads gets its state mixed
+//up. So the render thread only ever draws into plain memory, and hands each finished frame over
+//through a channel; the dispatch side copies it into the shm buffer and commits it after its next
+//dispatch, woken through the same pipe as user events.
+//
+//Two frames go around: one can be drawn while the other waits to be shown or is on screen. A
+//frame comes back to the render thread when the compositor's frame callback for its commit
+//fires, which is what paces rendering to the screen instead of letting it run as fast as it can.
+//That's PresentMode::Fifo. In PresentMode::Mailbox a frame comes back as soon as it's copied into
+//the shm buffer and each one is committed as it comes, so the render thread only waits for the
+//dispatching thread: the newest frame replaces the one committed before it if the compositor
+//hadn't used that yet, and the picture on screen is never older than the last one drawn.
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, Sender, SyncSender},
+    },
+};
+
+use wayland_client::{Connection, Dispatch, QueueHandle, protocol::wl_callback};
+
+use crate::{
+    AppState, Canvas, Color, RenderMode, Window, WindowError, protocol_log::protocol_log,
+    user_events::Waker,
+};
+
+const FRAMES: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PresentMode {
+    //A commit per frame callback: the render thread is at most one frame ahead of the screen.
+    #[default]
+    Fifo,
+    //A commit per frame, as fast as they're drawn. Less latency from input to screen, at the
+    //price of frames drawn for nothing.
+    Mailbox,
+}
+
+//What became of the render thread's frames. `replaced` are the ones that were committed (or
+//waiting to be) when a newer one was, before their frame callback: shown briefly at best, more
+//likely never. Only Mailbox replaces frames.
+//`skipped` counts Window::draw's instead, the ones not committed for being identical to the
+//frame on screen (see frame_skip.rs), split window or not.
+//`buffers` is how many shm buffers the window has now, and `stalls` how many times a frame found
+//them all unreleased (see swapchain.rs), split window or not too.
+//`previewed` is how many configures were shown by stretching the last frame, full redraws a
+//resize preview saved (see resize_preview.rs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameStats {
+    pub presented: u64,
+    pub replaced: u64,
+    pub skipped: u64,
+    pub buffers: usize,
+    pub stalls: u64,
+    pub previewed: u64,
+}
+
+//The dispatch side's end of the handover.
+pub(crate) struct RenderThreadState {
+    frames: Receiver<Vec<u8>>,
+    free: Sender<Vec<u8>>,
+    //The commit waiting for its frame callback, numbered like its callback's RenderedFrame.
+    in_flight: Option<u64>,
+    //Its frame, which Fifo only gives back with the callback.
+    held: Option<Vec<u8>>,
+    //Buffer size, which the RenderSide draws its next frames at.
+    size: Arc<Mutex<(u32, u32)>>,
+    mode: PresentMode,
+    stats: FrameStats,
+    commits: u64,
+}
+
+impl RenderThreadState {
+    //The old surface's frame callback never comes, its frame goes back right away.
+    pub(crate) fn for_reconnect(mut self) -> RenderThreadState {
+        self.in_flight = None;
+        self.release_held();
+        self
+    }
+
+    fn release_held(&mut self) {
+        if let Some(frame) = self.held.take() {
+            let _ = self.free.send(frame);
+        }
+    }
+
+    //The next frame to commit, None for none. Fifo takes them in order, Mailbox the newest,
+    //giving the others back.
+    fn next_frame(&mut self) -> Option<Vec<u8>> {
+        match self.mode {
+            PresentMode::Fifo => self.frames.try_recv().ok(),
+            PresentMode::Mailbox => {
+                let mut newest = self.frames.try_recv().ok()?;
+                while let Ok(frame) = self.frames.try_recv() {
+                    let _ = self.free.send(std::mem::replace(&mut newest, frame));
+                    self.stats.replaced += 1;
+                }
+                Some(newest)
+            }
+        }
+    }
+}
+
+impl AppState {
+    //After a resize of the shm buffer. Frames drawn at the old size are skipped when they come.
+    pub(crate) fn render_thread_resized(&mut self) {
+        if let Some(ref render_thread) = self.render_thread {
+            *render_thread
+                .size
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()) = self.buffer_size;
+        }
+    }
+}
+
+//Marks the frame callbacks of rendered frames, apart from request_frame's, with the number of
+//their commit. Callbacks of replaced commits can come after a newer commit was made.
+pub(crate) struct RenderedFrame(u64);
+
+impl AppState {
+    //Shows the next frame from the render thread: in Fifo, unless the last one is still waiting
+    //for its frame callback. Called after every dispatch.
+    pub(crate) fn present_rendered_frame(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if !self.configured() {
+            return;
+        }
+        let Some(render_thread) = self.render_thread.as_mut() else {
+            return;
+        };
+        if render_thread.mode == PresentMode::Fifo && render_thread.in_flight.is_some() {
+            return;
+        }
+        if self.shm_pixels.is_none() || self.base_surface.is_none() {
+            return;
+        }
+        let Some(frame) = render_thread.next_frame() else {
+            return;
+        };
+        self.next_buffer_without_waiting(queue_handle);
+        let (Some(render_thread), Some((pixels, layout)), Some(surface)) = (
+            self.render_thread.as_mut(),
+            self.shm_pixels.as_mut(),
+            self.base_surface.as_ref(),
+        ) else {
+            return;
+        };
+        //Drawn before a resize or a reconnect to another buffer size, those frames are skipped.
+        if frame.len() != layout.row_len() * layout.height as usize {
+            let _ = render_thread.free.send(frame);
+            return;
+        }
+        //next_buffer_without_waiting made it free, or took it back.
+        let Some(mut bytes) = pixels.whole_mut() else {
+            let _ = render_thread.free.send(frame);
+            return;
+        };
+        layout.copy_rows_in(&frame, &mut bytes);
+        if render_thread.in_flight.is_some() {
+            render_thread.stats.replaced += 1;
+        }
+        //A frame held back from Fifo before a switch to Mailbox is free now too.
+        render_thread.release_held();
+        match render_thread.mode {
+            PresentMode::Fifo => render_thread.held = Some(frame),
+            PresentMode::Mailbox => {
+                let _ = render_thread.free.send(frame);
+            }
+        }
+        //Quoting documentation: "The frame request will take effect on the next wl_surface.commit."
+        //Its callback says the frame is off our hands, present_gradient commits.
+        render_thread.commits += 1;
+        surface.frame(queue_handle, RenderedFrame(render_thread.commits));
+        render_thread.in_flight = Some(render_thread.commits);
+        render_thread.stats.presented += 1;
+        self.drawn_by_app = true;
+        self.fade_shm_buffer();
+        self.present_gradient(queue_handle);
+        protocol_log!("rendered frame presented");
+    }
+}
+
+//The window, on the thread dispatching its events. Everything of Window works through it, except
+//that drawing now comes from the RenderSide: a Window::draw here is shown until the next frame.
+pub struct EventSide {
+    window: Window,
+}
+
+impl Deref for EventSide {
+    type Target = Window;
+
+    fn deref(&self) -> &Window {
+        &self.window
+    }
+}
+
+impl DerefMut for EventSide {
+    fn deref_mut(&mut self) -> &mut Window {
+        &mut self.window
+    }
+}
+
+impl Window {
+    //Fifo by default, see PresentMode. Takes effect with the next frame, once the window is
+    //split (before, there's no render thread to pace).
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        if let Some(ref mut render_thread) = self.state.render_thread {
+            render_thread.mode = mode;
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.state
+            .render_thread
+            .as_ref()
+            .map_or(PresentMode::default(), |render_thread| render_thread.mode)
+    }
+
+    //Presented and replaced are zero until the window is split.
+    pub fn frame_stats(&self) -> FrameStats {
+        let stats = self
+            .state
+            .render_thread
+            .as_ref()
+            .map_or(FrameStats::default(), |render_thread| render_thread.stats);
+        FrameStats {
+            skipped: self.state.frame_skip.skipped,
+            buffers: self.state.swapchain.buffers(),
+            stalls: self.state.swapchain.stalls,
+            previewed: self.state.resize_preview.previewed,
+            ..stats
+        }
+    }
+}
+
+//The drawing half, to be moved to another thread. Frames are drawn at the buffer size, which
+//follows the window's configures.
+pub struct RenderSide {
+    //Mutex only to make RenderSide Sync, render takes &mut self and never locks it.
+    free: Mutex<Receiver<Vec<u8>>>,
+    frames: SyncSender<Vec<u8>>,
+    waker: Waker,
+    size: Arc<Mutex<(u32, u32)>>,
+}
+
+impl RenderSide {
+    //Width and height the next frame is drawn at, shadow included.
+    pub fn size(&self) -> (u32, u32) {
+        *self.size.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    //Draws a frame and hands it to the event side, like Window::draw: the canvas starts fully
+    //transparent. In Fifo, blocks while both frames are taken, until the compositor showed the
+    //older one, so a loop calling it runs at the screen's pace (and stops while the window is
+    //hidden). In Mailbox, only until the event side dispatches.
+    //Err(Closed) once the EventSide is gone.
+    pub fn render(&mut self, draw: impl FnOnce(&mut Canvas)) -> Result<(), WindowError> {
+        let (width, height) = self.size();
+        let free = self.free.get_mut().unwrap_or_else(|err| err.into_inner());
+        let mut frame = free.recv().map_err(|_| WindowError::Closed)?;
+        frame.resize(width as usize * height as usize * 4, 0);
+        if let Some(mut canvas) = Canvas::from_bytes(&mut frame, width, height, width as usize * 4)
+        {
+            canvas.clear(Color::TRANSPARENT);
+            draw(&mut canvas);
+        }
+        //Waits here while the previous frame wasn't taken yet.
+        self.frames.send(frame).map_err(|_| WindowError::Closed)?;
+        self.waker.wake()
+    }
+}
+
+impl Window {
+    //Splits the window in two, so a slow renderer can't hold up events: the EventSide stays on
+    //this thread and keeps dispatching, the RenderSide goes to another one and draws. Shm
+    //rendering only, EGL contexts and external renderers have their own ways to do that.
+    pub fn split(mut self) -> Result<(EventSide, RenderSide), WindowError> {
+        if self.state.render_mode != RenderMode::Shm {
+            return Err(WindowError::Unsupported(
+                "render thread needs RenderMode::Shm",
+            ));
+        }
+        let waker = self.waker()?;
+        let (width, height) = self.state.buffer_size;
+        let size = Arc::new(Mutex::new((width, height)));
+        //Room for every frame, the free ones are what limits the render thread.
+        let (frames_sender, frames) = mpsc::sync_channel(FRAMES);
+        let (free, free_receiver) = mpsc::channel();
+        for _ in 0..FRAMES {
+            let _ = free.send(vec![0; width as usize * height as usize * 4]);
+        }
+        self.state.render_thread = Some(RenderThreadState {
+            frames,
+            free,
+            in_flight: None,
+            held: None,
+            size: size.clone(),
+            mode: PresentMode::default(),
+            stats: FrameStats::default(),
+            commits: 0,
+        });
+        Ok((
+            EventSide { window: self },
+            RenderSide {
+                free: Mutex::new(free_receiver),
+                frames: frames_sender,
+                waker,
+                size,
+            },
+        ))
+    }
+}
+
+impl Dispatch<wl_callback::WlCallback, RenderedFrame> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        RenderedFrame(commit): &RenderedFrame,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        //A replaced commit's callback frees nothing, the newer one is still waiting.
+        if let (wl_callback::Event::Done { .. }, Some(render_thread)) =
+            (event, state.render_thread.as_mut())
+            && render_thread.in_flight == Some(*commit)
+        {
+            render_thread.in_flight = None;
+            render_thread.release_held();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//What the window draws into its shm buffer by itself: at every new buffer (first configure,
+//resizes) and whenever asked to again. The gradient used to be the only thing; now it's one of
+//the Background built-ins and anything implementing Renderer can take its place.
+//
+//Window::draw is the one-shot version: a picture from the application, over the renderer's
+//until the next resize or redraw_background.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    AppState, Canvas, Color, GradientView, ScaleMath, Window, buffer_layout::BufferLayout,
+};
+
+//Draws a whole canvas. `time` is how long the window has existed, for content that moves:
+//call Window::redraw_background (on frame callbacks, say) to have it drawn again. The canvas
+//holds whatever was there before, draw every pixel.
+pub trait Renderer: Send {
+    fn render(&mut self, canvas: &mut Canvas, time: Duration);
+}
+
+impl<F: FnMut(&mut Canvas, Duration) + Send> Renderer for F {
+    fn render(&mut self, canvas: &mut Canvas, time: Duration) {
+        self(canvas, time)
+    }
+}
+
+//The built-in renderers, the ones WindowOptions can name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    //See Canvas::gradient.
+    Gradient(GradientView),
+    Solid(Color),
+    //Black and white checks, each this many physical pixels wide and high. The compositor scales
+    //buffers up by what the buffer scale leaves of the output's scale (Canvas::scale), so a check
+    //is size / scale buffer pixels: exactly size physical pixels when the scale divides it. A
+    //blurry or uneven board means the buffer was scaled some other way than the window thinks.
+    Checkerboard(u32),
+    //x ^ y in every channel, in buffer pixels. Any off by one in a transform or a stride shows
+    //as a broken pattern.
+    Xor,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient(GradientView::default())
+    }
+}
+
+impl Renderer for Background {
+    fn render(&mut self, canvas: &mut Canvas, _time: Duration) {
+        match *self {
+            Background::Gradient(view) => canvas.gradient(&view),
+            Background::Solid(color) => canvas.clear(color),
+            Background::Checkerboard(size) => checkerboard(canvas, size),
+            Background::Xor => xor_pattern(canvas),
+        }
+    }
+}
+
+const CHECK_COLORS: [Color; 2] = [Color::opaque(0, 0, 0), Color::opaque(0xFF, 0xFF, 0xFF)];
+
+fn checkerboard(canvas: &mut Canvas, size: u32) {
+    let check = ScaleMath::integer(canvas.scale()).to_logical(size).max(1);
+    for y in 0..canvas.height() {
+        let Some(row) = canvas.row_mut(y) else {
+            return;
+        };
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let color = CHECK_COLORS[((x as u32 / check + y / check) % 2) as usize];
+            pixel.copy_from_slice(&color.to_argb8888_bytes());
+        }
+    }
+}
+
+fn xor_pattern(canvas: &mut Canvas) {
+    for y in 0..canvas.height() {
+        let Some(row) = canvas.row_mut(y) else {
+            return;
+        };
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let value = (x as u32 ^ y) as u8;
+            pixel.copy_from_slice(&Color::opaque(value, value, value).to_argb8888_bytes());
+        }
+    }
+}
+
+pub(crate) struct RendererState {
+    pub(crate) renderer: Box<dyn Renderer>,
+    //What `time` counts from.
+    start: Instant,
+}
+
+impl RendererState {
+    pub(crate) fn new(background: Background) -> RendererState {
+        RendererState {
+            renderer: Box::new(background),
+            start: Instant::now(),
+        }
+    }
+
+    //The renderer goes along, a custom one couldn't be made again from options. The time starts
+    //over with the new window.
+    pub(crate) fn for_reconnect(&mut self) -> RendererState {
+        RendererState {
+            renderer: std::mem::replace(&mut self.renderer, Box::new(Background::default())),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl AppState {
+    //Runs `draw` on the shm buffer as a canvas that knows the surface's scale, what's left of it
+    //once the buffer scale took its part. Nothing before the first buffer.
+    pub(crate) fn paint(&mut self, draw: impl FnOnce(&mut Canvas)) {
+        let scale = (self.scale_factor() / self.scale_math().buffer_scale()).max(1);
+        let Some((pixels, layout)) = self.shm_pixels.as_mut() else {
+            return;
+        };
+        let BufferLayout {
+            width,
+            height,
+            stride,
+            ..
+        } = *layout;
+        //Made free by the caller, see swapchain.rs.
+        let Some(mut bytes) = pixels.whole_mut() else {
+            return;
+        };
+        if let Some(canvas) = Canvas::from_bytes(&mut bytes, width, height, stride as usize) {
+            draw(&mut canvas.with_scale(scale));
+        }
+    }
+
+    //The renderer into the shm buffer, faded. Not presented, see present_gradient.
+    //The renderer is taken out for the call, paint borrows the rest of the state.
+    pub(crate) fn render_background(&mut self) {
+        let time = self.renderer.start.elapsed();
+        let mut renderer =
+            std::mem::replace(&mut self.renderer.renderer, Box::new(Background::default()));
+        self.paint(|canvas| renderer.render(canvas, time));
+        self.renderer.renderer = renderer;
+        self.fade_shm_buffer();
+    }
+}
+
+impl Window {
+    //Replaces what the window draws by itself and shows it, unless there's no buffer yet (it's
+    //used for the first one then). A picture from Window::draw is replaced too.
+    pub fn set_renderer(&mut self, renderer: impl Renderer + 'static) {
+        self.state.renderer.renderer = Box::new(renderer);
+        self.redraw_background();
+    }
+
+    //Draws the renderer again, with the time now, into a free buffer (see swapchain.rs) and
+    //commits it.
+    pub fn redraw_background(&mut self) {
+        //Before the first buffer, a frame drawn ahead of it is stale now.
+        self.state.discard_prerendered();
+        if self.state.shm_pixels.is_none() {
+            return;
+        }
+        self.wait_for_free_buffer();
+        self.state.render_background();
+        self.state.drawn_by_app = false;
+
+        //Same as the initial attach, no buffer before the first configure.
+        if self.state.configured() {
+            let queue_handle = self.event_queue.handle();
+            self.state.present_gradient(&queue_handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(mut background: Background, scale: i32) -> Vec<u8> {
+        let mut bytes = vec![0; 8 * 4 * 8];
+        let canvas = Canvas::from_bytes(&mut bytes, 8, 8, 8 * 4).unwrap();
+        background.render(&mut canvas.with_scale(scale), Duration::ZERO);
+        bytes
+    }
+
+    //Whether the pixel at (x, 0) is white.
+    fn white(bytes: &[u8], x: usize) -> bool {
+        bytes[x * 4] == 0xFF
+    }
+
+    #[test]
+    fn checks_are_physical_pixels() {
+        let board = pixels(Background::Checkerboard(4), 1);
+        let at_1: Vec<_> = (0..8).map(|x| white(&board, x)).collect();
+        assert_eq!(at_1, [false, false, false, false, true, true, true, true]);
+        //At scale 2 the compositor doubles every buffer pixel, so a check is 2 of them.
+        let board = pixels(Background::Checkerboard(4), 2);
+        let at_2: Vec<_> = (0..8).map(|x| white(&board, x)).collect();
+        assert_eq!(at_2, [false, false, true, true, false, false, true, true]);
+    }
+
+    #[test]
+    fn closures_are_renderers() {
+        let mut bytes = vec![0; 2 * 4];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 2, 1, 2 * 4).unwrap();
+        let mut renderer = |canvas: &mut Canvas, time: Duration| {
+            canvas.clear(Color::opaque(time.as_secs() as u8, 0, 0));
+        };
+        renderer.render(&mut canvas, Duration::from_secs(3));
+        assert_eq!(bytes[..4], [0, 0, 3, 0xFF]);
+    }
+}
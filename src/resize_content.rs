@@ -0,0 +1,165 @@
+//What a new shm buffer starts out with when the size changes. A new buffer is the gradient (or
+//transparent) until the application draws again on Resized, and if that takes a while the
+//window flashes it at every step of an interactive resize. Copying the old picture over hides
+//that: stale, but where it was, until the real one comes.
+//
+//The main buffer has a file of its own (buffer_allocator.rs is for icons and other small ones),
+//so the old mapping is simply kept until the copy is done and dropped after.
+
+use crate::{AppState, Window, buffer_layout::BufferLayout, mapped_pool::MappedPool};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeContent {
+    //The gradient, until the application draws again.
+    #[default]
+    Discard,
+    //The old picture at the top left, cut off when the window got smaller and the gradient
+    //around it when it got bigger. What most toolkits show while resizing.
+    Anchored,
+    //The old picture stretched to the new size, nearest neighbour: blocky, but a fair preview
+    //of a layout that scales with the window.
+    Scaled,
+}
+
+//Copies `old` into `new` the ResizeContent way. Both are 4 bytes a pixel, rows `stride` apart.
+pub(crate) fn copy_content(
+    mode: ResizeContent,
+    old: &[u8],
+    old_layout: &BufferLayout,
+    new: &mut [u8],
+    new_layout: &BufferLayout,
+) {
+    let (old_stride, new_stride) = (old_layout.stride as usize, new_layout.stride as usize);
+    match mode {
+        ResizeContent::Discard => {}
+        ResizeContent::Anchored => {
+            let width = old_layout.width.min(new_layout.width) as usize * 4;
+            let height = old_layout.height.min(new_layout.height) as usize;
+            for y in 0..height {
+                new[y * new_stride..][..width].copy_from_slice(&old[y * old_stride..][..width]);
+            }
+        }
+        ResizeContent::Scaled => {
+            //Which old column each new one takes, worked out once for all rows.
+            let columns: Vec<usize> = (0..new_layout.width)
+                .map(|x| {
+                    (u64::from(x) * u64::from(old_layout.width) / u64::from(new_layout.width))
+                        as usize
+                        * 4
+                })
+                .collect();
+            for y in 0..new_layout.height {
+                let old_y =
+                    u64::from(y) * u64::from(old_layout.height) / u64::from(new_layout.height);
+                let old_row = &old[old_y as usize * old_stride..];
+                let new_row = &mut new[y as usize * new_stride..];
+                for (pixel, &from) in new_row.chunks_exact_mut(4).zip(&columns) {
+                    pixel.copy_from_slice(&old_row[from..from + 4]);
+                }
+            }
+        }
+    }
+}
+
+impl AppState {
+    //Right after create_main_buffer, with what it replaced. Only a picture from the application
+    //is worth keeping, the gradient is already drawn at the new size. The old pixels are faded
+    //already (fade_shm_buffer), so they go over the faded gradient as they are.
+    //Returns whether anything was copied.
+    pub(crate) fn keep_resized_content(&mut self, old: Option<(MappedPool, BufferLayout)>) -> bool {
+        let (Some((old, old_layout)), Some((new, new_layout))) = (old, self.shm_pixels.as_mut())
+        else {
+            return false;
+        };
+        if !self.drawn_by_app || self.resize_content == ResizeContent::Discard {
+            return false;
+        }
+        //A new buffer, never attached.
+        let Some(mut new) = new.whole_mut() else {
+            return false;
+        };
+        //`old` is unmapped once this returns, the compositor has its own mapping of it for as
+        //long as the old buffer is on screen.
+        copy_content(
+            self.resize_content,
+            old.bytes(),
+            &old_layout,
+            &mut new,
+            new_layout,
+        );
+        true
+    }
+}
+
+impl Window {
+    //What a new buffer shows after a resize until the next draw, see ResizeContent. Only
+    //matters for pictures from Window::draw (the gradient is always drawn at the new size) and
+    //only in RenderMode::Shm.
+    pub fn set_resize_content(&mut self, resize_content: ResizeContent) {
+        self.state.resize_content = resize_content;
+    }
+
+    pub fn resize_content(&self) -> ResizeContent {
+        self.state.resize_content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wayland_client::protocol::wl_shm;
+
+    use super::*;
+
+    //Pixels numbered by position, (x, y) being [x, y, 0, 0xFF].
+    fn numbered(layout: &BufferLayout) -> Vec<u8> {
+        let mut bytes = vec![0; layout.len()];
+        for y in 0..layout.height {
+            for x in 0..layout.width {
+                let at = (y * layout.stride + x * 4) as usize;
+                bytes[at..at + 4].copy_from_slice(&[x as u8, y as u8, 0, 0xFF]);
+            }
+        }
+        bytes
+    }
+
+    fn pixel(bytes: &[u8], layout: &BufferLayout, x: u32, y: u32) -> [u8; 4] {
+        let at = (y * layout.stride + x * 4) as usize;
+        bytes[at..at + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn anchored_keeps_the_top_left() {
+        let old_layout = BufferLayout::new(4, 3, wl_shm::Format::Argb8888).unwrap();
+        let new_layout = BufferLayout::new(2, 5, wl_shm::Format::Argb8888).unwrap();
+        let old = numbered(&old_layout);
+        let mut new = vec![0xAA; new_layout.len()];
+        copy_content(
+            ResizeContent::Anchored,
+            &old,
+            &old_layout,
+            &mut new,
+            &new_layout,
+        );
+        assert_eq!(pixel(&new, &new_layout, 1, 2), [1, 2, 0, 0xFF]);
+        //Below the old picture the new buffer is left as it was.
+        assert_eq!(pixel(&new, &new_layout, 0, 3), [0xAA; 4]);
+    }
+
+    #[test]
+    fn scaled_picks_the_nearest_pixel() {
+        let old_layout = BufferLayout::new(2, 2, wl_shm::Format::Argb8888).unwrap();
+        let new_layout = BufferLayout::new(4, 3, wl_shm::Format::Argb8888).unwrap();
+        let old = numbered(&old_layout);
+        let mut new = vec![0; new_layout.len()];
+        copy_content(
+            ResizeContent::Scaled,
+            &old,
+            &old_layout,
+            &mut new,
+            &new_layout,
+        );
+        assert_eq!(pixel(&new, &new_layout, 1, 1), [0, 0, 0, 0xFF]);
+        assert_eq!(pixel(&new, &new_layout, 2, 1), [1, 0, 0, 0xFF]);
+        assert_eq!(pixel(&new, &new_layout, 3, 2), [1, 1, 0, 0xFF]);
+    }
+}
@@ -0,0 +1,137 @@
+//Fast resize preview, WindowOptions::resize_preview. An interactive resize is a configure for
+//about every pointer motion, and each one a new buffer and a redraw, which a heavy application
+//can't keep up with: the window lags behind the pointer. With a preview interval, a configure in
+//the Resizing state only moves the viewport destination (viewport.rs) to the new size, and the
+//compositor stretches the frame we have over it. The ack, the window geometry and the input
+//region still follow every configure, so the resize itself feels as quick as ever.
+//
+//The real thing (a buffer of the new size and a Resized) comes when the resize ends, or once the
+//interval went by since the last one, whichever is first. When no configure comes by then, the
+//wakeup deadline (user_events.rs) has it done anyway.
+//
+//Only for our own shm buffers, with wp_viewporter, and neither with a solid color shown nor while
+//set_viewport_source crops: the destination is theirs then.
+
+use std::time::{Duration, Instant};
+
+use wayland_client::QueueHandle;
+use wayland_protocols::xdg::shell::client::xdg_toplevel;
+
+use crate::{AppState, RenderMode, Window, protocol_log::protocol_log, transaction::StagedCommit};
+
+#[derive(Default)]
+pub(crate) struct ResizePreviewState {
+    pub(crate) interval: Option<Duration>,
+    //The surface size shown over the older buffer. None when the buffer has the window's size.
+    pub(crate) surface: Option<(u32, u32)>,
+    //When the buffer last got the size of a configure.
+    full_at: Option<Instant>,
+    //Configures shown stretched instead of redrawn, see FrameStats::previewed.
+    pub(crate) previewed: u64,
+}
+
+impl ResizePreviewState {
+    pub(crate) fn new(interval: Option<Duration>) -> ResizePreviewState {
+        ResizePreviewState {
+            interval,
+            ..ResizePreviewState::default()
+        }
+    }
+
+    //While a preview shows, when it has to make way for a real frame.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.surface?;
+        Some(self.full_at? + self.interval.unwrap_or_default())
+    }
+}
+
+impl AppState {
+    //From apply_pending_configure, in place of the new buffer: true when the configure of a
+    //`size` window was shown stretched, geometry and all.
+    pub(crate) fn preview_resize(
+        &mut self,
+        size: (u32, u32),
+        queue_handle: &QueueHandle<AppState>,
+    ) -> bool {
+        let Some(interval) = self.resize_preview.interval else {
+            return false;
+        };
+        let resizing = self
+            .configure_states
+            .contains(&xdg_toplevel::State::Resizing);
+        let due = self
+            .resize_preview
+            .full_at
+            .is_none_or(|at| at.elapsed() >= interval);
+        let unchanged =
+            self.resize_preview.surface.is_none() && self.buffer_size_for(size) == self.buffer_size;
+        if !resizing
+            || due
+            || unchanged
+            || self.render_mode != RenderMode::Shm
+            || self.render_thread.is_some()
+            || self.buffer.is_none()
+            || !self.has_viewporter()
+            || self.has_viewport_source()
+            || self.shows_solid_color()
+            || !self.may_attach()
+        {
+            return false;
+        }
+
+        let margins = self.shadow_margins();
+        let surface_size = (
+            size.0
+                .saturating_add(margins.left.saturating_add(margins.right)),
+            size.1
+                .saturating_add(margins.top.saturating_add(margins.bottom)),
+        );
+        //The geometry and input region follow the preview's size, commit_staged sends them with
+        //the destination.
+        self.resize_preview.surface = Some(surface_size);
+        let staged = StagedCommit {
+            viewport_destination: Some(Some((surface_size.0 as i32, surface_size.1 as i32))),
+            ..StagedCommit::default()
+        };
+        self.commit_staged(staged, queue_handle, "state");
+        self.resize_preview.previewed += 1;
+        protocol_log!(
+            "resize previewed at {}x{}, the buffer stays {}x{}",
+            size.0,
+            size.1,
+            self.buffer_size.0,
+            self.buffer_size.1
+        );
+        true
+    }
+
+    //The buffer is about to get the configure's size: no preview anymore.
+    pub(crate) fn resize_preview_done(&mut self) {
+        self.resize_preview.surface = None;
+        self.resize_preview.full_at = Some(Instant::now());
+    }
+
+    //After the configures of a dispatch: a preview shown for the whole interval gets its real
+    //frame, configure or not.
+    pub(crate) fn settle_resize_preview(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if self
+            .resize_preview
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            let size = self.configure_target_size();
+            self.apply_shm_size(size, false, queue_handle);
+        }
+    }
+}
+
+impl Window {
+    //Changes WindowOptions::resize_preview. With None, a preview shown ends at the next dispatch.
+    pub fn set_resize_preview(&mut self, interval: Option<Duration>) {
+        self.state.resize_preview.interval = interval;
+    }
+
+    pub fn resize_preview(&self) -> Option<Duration> {
+        self.state.resize_preview.interval
+    }
+}
@@ -0,0 +1,189 @@
+//Drawing a few rows at a time. Window::draw hands out the whole canvas cleared, so every frame
+//touches every byte of the buffer: 132 MB at 8K. An application streaming its content (an image
+//decoded progressively, a log view appending lines) only changes some rows, and draw_rows gives
+//it just those, as many calls as it likes, until present commits them all at once with the rows
+//drawn as damage.
+//
+//The rows not drawn keep what's on screen. With more than one buffer (swapchain.rs) the one drawn
+//into may be frames behind, so the first draw_rows of a frame brings it up to date: what it
+//missed (see damage.rs) is copied over from the buffer on screen, which is as much as the frames
+//in between changed, not the whole buffer. A buffer of unknown content takes all of it, or is
+//cleared to transparent when nothing of ours is on screen; the whole buffer is damaged then.
+//
+//Rows are buffer rows, in the buffer's format (B, G, R, A with Argb8888), like Canvas::row_mut.
+
+use std::ops::Range;
+
+use wayland_client::protocol::wl_buffer::WlBuffer;
+
+use crate::{Color, Rect, Window};
+
+//A frame drawn row by row and not presented yet.
+pub(crate) struct RowFrame {
+    //The window's buffer it's in. A new size or a buffer swap makes another one, this frame is
+    //then over.
+    buffer: WlBuffer,
+    //Drawn so far, in draw_rows order.
+    rows: Vec<Range<u32>>,
+    //Brought up to date by clearing: the compositor needs all of it.
+    full_damage: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct RowsState {
+    //Ended by present, or by any other present of the window's buffer.
+    pub(crate) frame: Option<RowFrame>,
+}
+
+//`rows` merged, sorted and clipped to `height`, as whole-width damage.
+fn row_damage(rows: &[Range<u32>], (width, height): (u32, u32)) -> Vec<Rect> {
+    let mut sorted: Vec<_> = rows
+        .iter()
+        .map(|rows| rows.start.min(height)..rows.end.min(height))
+        .filter(|rows| !rows.is_empty())
+        .collect();
+    sorted.sort_by_key(|rows| rows.start);
+    let mut merged: Vec<Range<u32>> = Vec::new();
+    for rows in sorted {
+        match merged.last_mut() {
+            Some(last) if rows.start <= last.end => last.end = last.end.max(rows.end),
+            _ => merged.push(rows),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|rows| Rect::new(0, rows.start as i32, width as i32, rows.len() as i32))
+        .collect()
+}
+
+impl Window {
+    //Calls `draw` with each row of `rows` (clipped to the buffer) and its pixels, nothing else of
+    //the buffer is touched. Several calls make one frame, shown by present. See rows.rs.
+    pub fn draw_rows(&mut self, rows: Range<u32>, mut draw: impl FnMut(u32, &mut [u8])) {
+        let Some((_, layout)) = self.state.shm_pixels.as_ref() else {
+            return;
+        };
+        let rows = rows.start..rows.end.min(layout.height);
+        if rows.is_empty() {
+            return;
+        }
+        let current = self.state.rows.frame.as_ref().map(|frame| &frame.buffer);
+        if current.is_none() || current != self.state.buffer.as_ref() {
+            self.start_row_frame();
+        }
+
+        let Some((pixels, layout)) = self.state.shm_pixels.as_mut() else {
+            return;
+        };
+        let (stride, row_len) = (layout.stride as usize, layout.row_len());
+        //start_row_frame made it free, and it stays so until present.
+        let Some(mut bytes) = pixels.whole_mut() else {
+            return;
+        };
+        for y in rows.clone() {
+            let start = y as usize * stride;
+            draw(y, &mut bytes[start..start + row_len]);
+        }
+        self.state.drawn_by_app = true;
+        if let Some(ref mut frame) = self.state.rows.frame {
+            frame.rows.push(rows);
+        }
+    }
+
+    //Commits the rows drawn since the last present, damaging only them. Nothing without any.
+    pub fn present(&mut self) {
+        let Some(frame) = self.state.rows.frame.take() else {
+            return;
+        };
+        let Some((_, layout)) = self.state.shm_pixels.as_ref() else {
+            return;
+        };
+        let size = (layout.width, layout.height);
+        //A resize in between, whatever was drawn went with the old buffer.
+        let same_buffer = self.state.buffer.as_ref() == Some(&frame.buffer);
+        let damage = (same_buffer && !frame.full_damage).then(|| row_damage(&frame.rows, size));
+        //The slow fade, only where it isn't done yet: the rows kept are faded already.
+        if same_buffer {
+            self.state.fade_shm_rows(&frame.rows);
+        } else {
+            self.state.fade_shm_buffer();
+        }
+
+        let presented = self.state.swapchain.frame;
+        if self.state.configured() {
+            self.state.swapchain.frame_damage = damage;
+            let queue_handle = self.event_queue.handle();
+            self.state.present_gradient(&queue_handle);
+        }
+        if self.state.swapchain.frame == presented {
+            //Not presented, like draw_damaged.
+            self.state.swapchain.frame_damage = None;
+            self.state.swapchain.drawn_at = None;
+        }
+    }
+
+    //The first draw_rows of a frame: a free buffer, holding what's on screen.
+    fn start_row_frame(&mut self) {
+        self.wait_for_free_buffer();
+        let missed = self.state.missed_damage();
+        let full_damage = !self.state.copy_from_shown(&missed);
+        if full_damage {
+            self.state.paint(|canvas| canvas.clear(Color::TRANSPARENT));
+        }
+        //Half drawn, its age means nothing until presented.
+        self.state.swapchain.drawn_at = None;
+        self.state.rows.frame = self.state.buffer.clone().map(|buffer| RowFrame {
+            buffer,
+            rows: Vec::new(),
+            full_damage,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_merge_into_bands() {
+        let rows = [10..20, 0..5, 15..30, 30..32, 90..200, 40..40];
+        assert_eq!(
+            row_damage(&rows, (64, 100)),
+            [
+                Rect::new(0, 0, 64, 5),
+                Rect::new(0, 10, 64, 22),
+                Rect::new(0, 90, 64, 10),
+            ]
+        );
+    }
+
+    //One more row each frame, the buffers taking turns: each shows every row drawn so far, the
+    //one a frame behind gets the row it missed from the other.
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn rows_stay_across_buffers() {
+        use crate::{TestWindow, WindowOptions};
+
+        let mut window = TestWindow::new(WindowOptions::default());
+        window.inject_configure(16, 8, &[]);
+        window.dispatch();
+        window.draw(|canvas| canvas.clear(Color::opaque(0, 0, 0)));
+        window.dispatch();
+        window.take_frames();
+        for step in 0..6 {
+            window.draw_rows(step..step + 1, |_, row| row.fill(0xFF));
+            window.present();
+            window.dispatch();
+
+            let frame = window.take_frames().pop().unwrap();
+            for (y, row) in frame.rgba.chunks(16 * 4).enumerate() {
+                let expected = if y as u32 <= step { 0xFF } else { 0 };
+                assert!(
+                    row.chunks(4)
+                        .all(|pixel| pixel == [expected, expected, expected, 0xFF]),
+                    "step {step}, row {y}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//Surface (logical) coordinates to buffer (physical) pixels and back, for every scale a window can
+//be at: the buffer scale of wl_surface.set_buffer_scale, or a fractional one from
+//wp_fractional_scale_v1 (120ths, drawn at scale 1 with a viewport, see the --fractional
+//example). Every conversion between the two in the library goes through here: buffer sizes and
+//what set_buffer_scale can take of them, the surface size, viewport destinations, damage, the
+//overlay's layout. Applications doing their own fractional scaling should use it too, their
+//buffer and damage only line up with ours when they round the same way. Pointer positions stay in
+//surface coordinates, as the compositor sends them, and resampling an icon, an image or a glyph
+//goes by the ratio of its own sizes, not the window's scale.
+//
+//Quoting documentation: "the client should use a buffer of size 126×126 (i.e. 101*1.25 =
+//126.25, rounded)", round half up being what compositors do. Sizes and edges are rounded, never
+//truncated: a rectangle's physical edges are its logical edges converted one by one, so
+//rectangles that touch on the surface touch in the buffer, without a seam or an overlap, and the
+//whole surface is the whole buffer. Integer scales come out exact, as before.
+//
+//The arithmetic is integer, on the 120ths, so a scale of 1.25 is exactly 1.25.
+
+use crate::Rect;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScaleMath {
+    //The scale in 120ths, at least 1.
+    numerator: u32,
+}
+
+const DENOMINATOR: u64 = 120;
+
+//`numerator / denominator` rounded half up, for both signs.
+fn round_half_up(numerator: i64, denominator: i64) -> i64 {
+    (2 * numerator + denominator).div_euclid(2 * denominator)
+}
+
+fn saturate(value: i64) -> u32 {
+    value.clamp(0, u32::MAX as i64) as u32
+}
+
+impl ScaleMath {
+    //An integer scale, like wl_surface.set_buffer_scale's. Below 1 is taken as 1.
+    pub fn integer(scale: i32) -> ScaleMath {
+        ScaleMath {
+            numerator: scale.max(1) as u32 * DENOMINATOR as u32,
+        }
+    }
+
+    //wp_fractional_scale_v1's preferred_scale, in 120ths. 0 is taken as 1/120.
+    pub fn fractional(numerator: u32) -> ScaleMath {
+        ScaleMath {
+            numerator: numerator.max(1),
+        }
+    }
+
+    pub fn factor(self) -> f64 {
+        f64::from(self.numerator) / DENOMINATOR as f64
+    }
+
+    //The 120ths, as wp_fractional_scale_v1 has it.
+    pub fn numerator(self) -> u32 {
+        self.numerator
+    }
+
+    pub fn is_integer(self) -> bool {
+        self.numerator.is_multiple_of(DENOMINATOR as u32)
+    }
+
+    //What wl_surface.set_buffer_scale can take of it: all of an integer scale, 1 for a fractional
+    //one (drawn at 1 and scaled down with a viewport).
+    pub fn buffer_scale(self) -> i32 {
+        if self.is_integer() {
+            (self.numerator / DENOMINATOR as u32) as i32
+        } else {
+            1
+        }
+    }
+
+    //Whether a buffer of `size` can go with buffer_scale(): quoting documentation, "at commit
+    //time the supplied buffer size must be an integer multiple of the buffer_scale".
+    pub fn divides(self, (width, height): (u32, u32)) -> bool {
+        let scale = self.buffer_scale() as u32;
+        width.is_multiple_of(scale) && height.is_multiple_of(scale)
+    }
+
+    //`size` rounded down to a buffer that divides, never below one surface pixel.
+    pub fn divisible_size(self, (width, height): (u32, u32)) -> (u32, u32) {
+        let scale = self.buffer_scale() as u32;
+        (
+            (width / scale).max(1) * scale,
+            (height / scale).max(1) * scale,
+        )
+    }
+
+    //A length or an edge in surface coordinates, in buffer pixels.
+    pub fn to_physical(self, logical: u32) -> u32 {
+        saturate(self.edge_to_physical(i64::from(logical)))
+    }
+
+    pub fn to_logical(self, physical: u32) -> u32 {
+        saturate(round_half_up(
+            i64::from(physical) * DENOMINATOR as i64,
+            i64::from(self.numerator),
+        ))
+    }
+
+    //The buffer for a surface of `size`.
+    pub fn size_to_physical(self, (width, height): (u32, u32)) -> (u32, u32) {
+        (self.to_physical(width), self.to_physical(height))
+    }
+
+    //The surface a buffer of `size` makes.
+    pub fn size_to_logical(self, (width, height): (u32, u32)) -> (u32, u32) {
+        (self.to_logical(width), self.to_logical(height))
+    }
+
+    fn edge_to_physical(self, logical: i64) -> i64 {
+        round_half_up(logical * i64::from(self.numerator), DENOMINATOR as i64)
+    }
+
+    //Damage, regions, anything rectangular: each edge on its own, see scale_math.rs.
+    pub fn rect_to_physical(self, rect: Rect) -> Rect {
+        let edge = |logical: i32| self.edge_to_physical(i64::from(logical));
+        let (left, top) = (edge(rect.x), edge(rect.y));
+        let right = edge(rect.x.saturating_add(rect.width));
+        let bottom = edge(rect.y.saturating_add(rect.height));
+        let clamp = |value: i64| value.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        Rect::new(
+            clamp(left),
+            clamp(top),
+            clamp(right - left),
+            clamp(bottom - top),
+        )
+    }
+}
+
+impl Default for ScaleMath {
+    fn default() -> ScaleMath {
+        ScaleMath::integer(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //1.0 to 3.0 in 120ths.
+    fn scales() -> impl Iterator<Item = ScaleMath> {
+        (120..=360).map(ScaleMath::fractional)
+    }
+
+    #[test]
+    fn rounds_half_up_like_the_protocol() {
+        let scale = ScaleMath::fractional(150);
+        assert_eq!(scale.to_physical(101), 126);
+        assert_eq!(scale.to_physical(641), 801);
+        assert_eq!(scale.to_physical(2), 3, "2.5 goes up");
+        assert_eq!(ScaleMath::integer(2).to_physical(641), 1282);
+        assert_eq!(ScaleMath::integer(2).to_logical(1282), 641);
+        assert_eq!(
+            scale.rect_to_physical(Rect::new(-2, 0, 2, 4)),
+            Rect::new(-2, 0, 2, 5)
+        );
+    }
+
+    #[test]
+    fn sizes_round_trip() {
+        for scale in scales() {
+            for logical in 1..=4096 {
+                let physical = scale.to_physical(logical);
+                assert_eq!(scale.to_logical(physical), logical, "{scale:?} {logical}");
+                //The buffer made for the surface is the one its size says, whichever way it's
+                //computed.
+                let rect = scale.rect_to_physical(Rect::new(0, 0, logical as i32, 1));
+                assert_eq!(rect.width as u32, physical, "{scale:?} {logical}");
+                assert!(physical >= logical);
+            }
+        }
+    }
+
+    #[test]
+    fn touching_rects_stay_touching() {
+        for scale in scales() {
+            for x in (0..4096).step_by(7) {
+                let left = scale.rect_to_physical(Rect::new(0, 0, x, 1));
+                let right = scale.rect_to_physical(Rect::new(x, 0, 4096 - x, 1));
+                assert_eq!(left.x + left.width, right.x, "{scale:?} {x}");
+                assert_eq!(right.x + right.width, scale.to_physical(4096) as i32);
+            }
+        }
+    }
+
+    #[test]
+    fn surface_sizes_round_half_up() {
+        //126.25 made a 126 buffer (the protocol's example), which is 101 again. Flooring 100.8
+        //would lose a surface pixel.
+        let scale = ScaleMath::fractional(150);
+        assert_eq!(scale.size_to_logical((126, 126)), (101, 101));
+        assert_eq!(scale.size_to_logical((801, 3)), (641, 2));
+        assert_eq!(ScaleMath::integer(2).size_to_logical((641, 1)), (321, 1));
+        //A fractional scale's buffers are at 1 as far as set_buffer_scale goes.
+        assert_eq!(scale.buffer_scale(), 1);
+        assert!(scale.divides((801, 3)));
+    }
+
+    #[test]
+    fn divisible_sizes_round_down() {
+        let scale = ScaleMath::integer(3);
+        assert_eq!(scale.divisible_size((640, 2)), (639, 3));
+        assert!(scale.divides(scale.divisible_size((640, 2))));
+        assert!(!scale.divides((640, 639)));
+        assert_eq!(ScaleMath::integer(1).divisible_size((641, 1)), (641, 1));
+    }
+}
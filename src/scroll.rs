@@ -0,0 +1,249 @@
+//Scrolling, from wl_pointer's axis events to WindowEvent::Scroll.
+//
+//A single scroll is several events: the source (wheel, finger...), the value of each axis, and
+//for wheels the number of detents, in one wl_pointer.frame (version 5 and later). They're
+//gathered here until the frame and turned into one Scroll with pixel deltas, whatever the device:
+//
+//- wheels count detents. Quoting documentation: "with each multiple of 120 representing one
+//  logical scroll step (a wheel detent)". A detent scrolls ScrollConfig::discrete_multiplier
+//  lines of line_height_px pixels. The axis value that comes along is in some unit of the
+//  compositor's (10 or 15 a detent, depending) and isn't used then.
+//- fingers and everything continuous send surface coordinates, used as pixels as they are.
+//
+//Pointers before version 5 have no frames and no sources: each axis event is a Scroll of its
+//own, of ScrollSource::Unknown, its value taken as pixels.
+
+use std::sync::Arc;
+
+use wayland_client::{
+    Proxy, WEnum,
+    protocol::wl_pointer::{self, WlPointer},
+};
+
+use crate::{AppState, Window, WindowEvent};
+
+//Where a scroll comes from, for applications that treat them differently (a wheel scrolling by
+//lines, kinetic scrolling for fingers...).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollSource {
+    Wheel,
+    Finger,
+    //Continuous, but not a finger: button scrolling, a trackpoint...
+    Continuous,
+    //A wheel pushed sideways.
+    WheelTilt,
+    //The compositor didn't say (wl_pointer before version 5).
+    Unknown,
+}
+
+impl ScrollSource {
+    fn from_protocol(source: wl_pointer::AxisSource) -> ScrollSource {
+        match source {
+            wl_pointer::AxisSource::Wheel => ScrollSource::Wheel,
+            wl_pointer::AxisSource::Finger => ScrollSource::Finger,
+            wl_pointer::AxisSource::Continuous => ScrollSource::Continuous,
+            wl_pointer::AxisSource::WheelTilt => ScrollSource::WheelTilt,
+            _ => ScrollSource::Unknown,
+        }
+    }
+}
+
+//How axis events become Scroll deltas, per window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollConfig {
+    //Flip the direction, on top of whatever the compositor does (its natural scrolling setting
+    //already applies to the values we get).
+    pub invert_vertical: bool,
+    pub invert_horizontal: bool,
+    //Pixels a line is, what a wheel detent scrolls by (times discrete_multiplier).
+    pub line_height_px: f64,
+    //Lines per wheel detent.
+    pub discrete_multiplier: f64,
+}
+
+impl Default for ScrollConfig {
+    //Three lines of 16 pixels a detent, roughly what toolkits do.
+    fn default() -> Self {
+        ScrollConfig {
+            invert_vertical: false,
+            invert_horizontal: false,
+            line_height_px: 16.0,
+            discrete_multiplier: 3.0,
+        }
+    }
+}
+
+//One frame's axis events, [horizontal, vertical].
+#[derive(Debug, Default)]
+struct PendingScroll {
+    source: Option<wl_pointer::AxisSource>,
+    value: [f64; 2],
+    //Detents in 120ths, from axis_value120 or axis_discrete (times 120).
+    value120: [i32; 2],
+    any: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct ScrollState {
+    config: ScrollConfig,
+    //Per seat, frames of two pointers can interleave.
+    pending: Vec<(Arc<str>, PendingScroll)>,
+}
+
+impl ScrollState {
+    pub(crate) fn for_reconnect(&self) -> ScrollState {
+        ScrollState {
+            config: self.config,
+            pending: Vec::new(),
+        }
+    }
+
+    fn pending(&mut self, seat: &Arc<str>) -> &mut PendingScroll {
+        let index = match self.pending.iter().position(|(s, _)| s == seat) {
+            Some(index) => index,
+            None => {
+                self.pending.push((seat.clone(), PendingScroll::default()));
+                self.pending.len() - 1
+            }
+        };
+        &mut self.pending[index].1
+    }
+}
+
+fn axis_index(axis: WEnum<wl_pointer::Axis>) -> Option<usize> {
+    match axis {
+        WEnum::Value(wl_pointer::Axis::HorizontalScroll) => Some(0),
+        WEnum::Value(wl_pointer::Axis::VerticalScroll) => Some(1),
+        _ => None,
+    }
+}
+
+//A frame's deltas in pixels, [dx, dy].
+fn normalize(pending: &PendingScroll, config: &ScrollConfig) -> [f64; 2] {
+    let invert = [config.invert_horizontal, config.invert_vertical];
+    let mut delta = [0.0; 2];
+    for axis in 0..2 {
+        delta[axis] = if pending.value120[axis] != 0 {
+            f64::from(pending.value120[axis]) / 120.0
+                * config.discrete_multiplier
+                * config.line_height_px
+        } else {
+            pending.value[axis]
+        };
+        if invert[axis] {
+            delta[axis] = -delta[axis];
+        }
+    }
+    delta
+}
+
+impl AppState {
+    //Everything scroll of a wl_pointer event, false for the others.
+    pub(crate) fn scroll_event(
+        &mut self,
+        pointer: &WlPointer,
+        seat: Arc<str>,
+        event: &wl_pointer::Event,
+    ) -> bool {
+        let pending = self.scroll.pending(&seat);
+        match *event {
+            wl_pointer::Event::AxisSource { axis_source } => {
+                pending.source = axis_source.into_result().ok();
+            }
+            wl_pointer::Event::Axis { axis, value, .. } => {
+                if let Some(axis) = axis_index(axis) {
+                    pending.value[axis] += value;
+                    pending.any = true;
+                }
+                //No frame will come.
+                if pointer.version() < 5 {
+                    self.scroll_frame(seat);
+                }
+            }
+            wl_pointer::Event::AxisDiscrete { axis, discrete } => {
+                if let Some(axis) = axis_index(axis) {
+                    pending.value120[axis] += discrete * 120;
+                    pending.any = true;
+                }
+            }
+            wl_pointer::Event::AxisValue120 { axis, value120 } => {
+                if let Some(axis) = axis_index(axis) {
+                    pending.value120[axis] += value120;
+                    pending.any = true;
+                }
+            }
+            //A finger lifted, a kinetic scroll could start here. Nothing to scroll by itself.
+            wl_pointer::Event::AxisStop { .. } => {}
+            wl_pointer::Event::Frame => self.scroll_frame(seat),
+            _ => return false,
+        }
+        true
+    }
+
+    fn scroll_frame(&mut self, seat: Arc<str>) {
+        let pending = std::mem::take(self.scroll.pending(&seat));
+        if !pending.any {
+            return;
+        }
+        let [dx, dy] = normalize(&pending, &self.scroll.config);
+        self.events.push(WindowEvent::Scroll {
+            seat,
+            dx,
+            dy,
+            source: pending
+                .source
+                .map_or(ScrollSource::Unknown, ScrollSource::from_protocol),
+        });
+    }
+}
+
+impl Window {
+    pub fn set_scroll_config(&mut self, config: ScrollConfig) {
+        self.state.scroll.config = config;
+    }
+
+    pub fn scroll_config(&self) -> ScrollConfig {
+        self.state.scroll.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wheel(value: f64, detents: i32) -> PendingScroll {
+        PendingScroll {
+            source: Some(wl_pointer::AxisSource::Wheel),
+            value: [0.0, value],
+            value120: [0, detents * 120],
+            any: true,
+        }
+    }
+
+    #[test]
+    fn wheels_scroll_by_lines_and_fingers_by_pixels() {
+        let config = ScrollConfig::default();
+        //Whatever the compositor's unit, a detent is 3 lines of 16 pixels.
+        assert_eq!(normalize(&wheel(15.0, 1), &config), [0.0, 48.0]);
+        assert_eq!(normalize(&wheel(10.0, 1), &config), [0.0, 48.0]);
+        let finger = PendingScroll {
+            source: Some(wl_pointer::AxisSource::Finger),
+            value: [-2.5, 7.0],
+            ..PendingScroll::default()
+        };
+        assert_eq!(normalize(&finger, &config), [-2.5, 7.0]);
+    }
+
+    #[test]
+    fn inversion_is_per_axis() {
+        let config = ScrollConfig {
+            invert_vertical: true,
+            line_height_px: 20.0,
+            discrete_multiplier: 1.0,
+            ..ScrollConfig::default()
+        };
+        let mut pending = wheel(15.0, -2);
+        pending.value = [4.0, -30.0];
+        assert_eq!(normalize(&pending, &config), [4.0, 40.0]);
+    }
+}
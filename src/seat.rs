@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use wayland_client::{
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+    protocol::{
+        wl_keyboard::WlKeyboard,
+        wl_pointer::WlPointer,
+        wl_seat::{self, WlSeat},
+    },
+};
+
+use crate::{
+    AppState, Key, KeyState, LogicalKey, Window, WindowEvent, keymap::Keymap,
+    protocol_log::protocol_log,
+};
+
+//One wl_seat global: a group of input devices with its own keyboard focus and pointer. Most
+//setups have one, but nothing stops a compositor from exposing more (multi-seat, or a virtual
+//seat for remote input), and each keeps its own focus, held keys and serials.
+pub(crate) struct Seat {
+    //Registry name of the global, what GlobalRemove talks about.
+    global: u32,
+    seat: WlSeat,
+    //From the Name event (wl_seat v2). Until it comes, a made up one.
+    name: Arc<str>,
+    keyboard: Option<WlKeyboard>,
+    pointer: Option<WlPointer>,
+    //Keys down while this seat's keyboard has our focus.
+    pub(crate) pressed_keys: Vec<u32>,
+    //Those of them that were already down at Enter and haven't been released since. Their
+    //presses happened elsewhere, see AppState::is_fresh_press.
+    pub(crate) held_at_enter: Vec<u32>,
+    pub(crate) keyboard_focus: bool,
+    pub(crate) keymap: Option<Keymap>,
+    //The active XKB group, and the group each held key was pressed with (layout.rs).
+    pub(crate) layout: u32,
+    pub(crate) key_layouts: Vec<(u32, u32)>,
+    //NumLock and CapsLock, and the logical key each held key was pressed as (numpad.rs).
+    pub(crate) numlock: bool,
+    pub(crate) caps_lock: bool,
+    pub(crate) key_logical: Vec<(u32, LogicalKey)>,
+}
+
+#[derive(Default)]
+pub(crate) struct SeatsState {
+    seats: Vec<Seat>,
+}
+
+impl SeatsState {
+    //The seat things that want just one go on.
+    pub(crate) fn first_seat(&self) -> Option<&WlSeat> {
+        self.seats.first().map(|seat| &seat.seat)
+    }
+}
+
+impl AppState {
+    //Called when the wl_seat global is bound. Its devices come with the Capabilities event.
+    pub(crate) fn add_seat(&mut self, global: u32, seat: WlSeat) {
+        self.seats.seats.push(Seat {
+            global,
+            seat,
+            name: format!("seat-{global}").into(),
+            keyboard: None,
+            pointer: None,
+            pressed_keys: Vec::new(),
+            held_at_enter: Vec::new(),
+            keyboard_focus: false,
+            keymap: None,
+            layout: 0,
+            key_layouts: Vec::new(),
+            numlock: false,
+            caps_lock: false,
+            key_logical: Vec::new(),
+        });
+    }
+
+    pub(crate) fn seat_mut(&mut self, seat: &WlSeat) -> Option<&mut Seat> {
+        self.seats.seats.iter_mut().find(|s| &s.seat == seat)
+    }
+
+    pub(crate) fn seat_named(&self, name: &str) -> Option<&Seat> {
+        self.seats.seats.iter().find(|s| &*s.name == name)
+    }
+
+    //What input events are tagged with.
+    pub(crate) fn seat_name(&self, seat: &WlSeat) -> Arc<str> {
+        self.seats
+            .seats
+            .iter()
+            .find(|s| &s.seat == seat)
+            .map_or_else(|| "".into(), |s| s.name.clone())
+    }
+
+    pub(crate) fn any_keyboard_focus(&self) -> bool {
+        self.seats.seats.iter().any(|seat| seat.keyboard_focus)
+    }
+
+    //Devices come and go with the capabilities (a keyboard unplugged, a tablet mode switch...).
+    //A gone device's state goes with it: its focus, held keys and, for the pointer the
+    //constraints hang off, those too.
+    fn update_devices(
+        &mut self,
+        seat: &WlSeat,
+        capabilities: wl_seat::Capability,
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        let Some(index) = self.seat_index(seat) else {
+            return;
+        };
+
+        let entry = &mut self.seats.seats[index];
+        match (
+            capabilities.contains(wl_seat::Capability::Keyboard),
+            entry.keyboard.is_some(),
+        ) {
+            (true, false) => entry.keyboard = Some(seat.get_keyboard(queue_handle, seat.clone())),
+            (false, true) => self.remove_keyboard(index),
+            _ => {}
+        }
+
+        let entry = &mut self.seats.seats[index];
+        match (
+            capabilities.contains(wl_seat::Capability::Pointer),
+            entry.pointer.is_some(),
+        ) {
+            (true, false) => {
+                entry.pointer = Some(seat.get_pointer(queue_handle, seat.clone()));
+                self.adopt_pointer(queue_handle);
+            }
+            (false, true) => self.remove_pointer(index, queue_handle),
+            _ => {}
+        }
+    }
+
+    fn remove_keyboard(&mut self, index: usize) {
+        let seat = &mut self.seats.seats[index];
+        let Some(keyboard) = seat.keyboard.take() else {
+            return;
+        };
+        //release came with version 3, before it the object just stays around.
+        if keyboard.version() >= 3 {
+            keyboard.release();
+        }
+        seat.keymap = None;
+        let name = seat.name.clone();
+        if self.key_repeat.is_held_by(&name) {
+            self.key_repeat.held = None;
+        }
+        self.release_held_keys(index, 0);
+    }
+
+    //Brings the seat's held keys up to date with a Key event. False for a press of a key that
+    //was already down at Enter: the Enter listed it, so the press happened while another window
+    //had the focus, and some compositors send it again anyway. Such a press fires no binding (an
+    //Escape held while alt-tabbing in mustn't quit) and isn't an event; once the key is
+    //released, its next press is a fresh one.
+    pub(crate) fn track_key(&mut self, seat: &WlSeat, key: u32, pressed: bool) -> bool {
+        let Some(entry) = self.seat_mut(seat) else {
+            return true;
+        };
+        entry.pressed_keys.retain(|&held| held != key);
+        if !pressed {
+            entry.held_at_enter.retain(|&held| held != key);
+            return true;
+        }
+        entry.pressed_keys.push(key);
+        !entry.held_at_enter.contains(&key)
+    }
+
+    //The focus left, or the keyboard with it: keys still down won't send us their releases, so
+    //they're made up here, with time 0 and the Leave's serial. Those of bindings stay quiet, as
+    //their real releases would have.
+    pub(crate) fn release_held_keys(&mut self, index: usize, serial: u32) {
+        let seat = &mut self.seats.seats[index];
+        seat.keyboard_focus = false;
+        let pressed_keys = std::mem::take(&mut seat.pressed_keys);
+        seat.held_at_enter.clear();
+        let key_layouts = std::mem::take(&mut seat.key_layouts);
+        let key_logical = std::mem::take(&mut seat.key_logical);
+        let current = seat.layout;
+        let name = seat.name.clone();
+        for code in pressed_keys {
+            let layout = key_layouts
+                .iter()
+                .find(|&&(held, _)| held == code)
+                .map_or(current, |&(_, layout)| layout);
+            let logical_key = key_logical
+                .iter()
+                .find(|&&(held, _)| held == code)
+                .map_or(LogicalKey::Named(Key::from_evdev(code)), |&(_, logical)| {
+                    logical
+                });
+            if self.release_key_binding(code) {
+                continue;
+            }
+            self.events.push(WindowEvent::Key {
+                seat: name.clone(),
+                key: Key::from_evdev(code),
+                logical_key,
+                code,
+                state: KeyState::Released,
+                layout,
+                time: 0,
+                serial,
+            });
+        }
+    }
+
+    pub(crate) fn seat_index(&self, seat: &WlSeat) -> Option<usize> {
+        self.seats.seats.iter().position(|s| &s.seat == seat)
+    }
+
+    fn remove_pointer(&mut self, index: usize, queue_handle: &QueueHandle<AppState>) {
+        let Some(pointer) = self.seats.seats[index].pointer.take() else {
+            return;
+        };
+        if self.pointer.pointer.as_ref() == Some(&pointer) {
+            self.forget_pointer();
+        }
+        self.forget_hit_pointer(&pointer);
+        self.stop_button_repeat(Some(&pointer));
+        self.forget_routed_pointer(&pointer);
+        if pointer.version() >= 3 {
+            pointer.release();
+        }
+        self.adopt_pointer(queue_handle);
+    }
+
+    //Pointer constraints, relative motion and gestures follow one pointer, the first seat's that
+    //has one. When it goes away the next one takes over.
+    fn adopt_pointer(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if self.pointer.pointer.is_some() {
+            return;
+        }
+        let Some(pointer) = self.seats.seats.iter().find_map(|s| s.pointer.clone()) else {
+            return;
+        };
+        self.pointer.pointer = Some(pointer);
+        self.init_relative_pointer(queue_handle);
+        self.init_gestures(queue_handle);
+        self.reapply_pointer_constraint(queue_handle);
+    }
+
+    //GlobalRemove: the seat and everything of it goes, serials included.
+    pub(crate) fn remove_seat(&mut self, global: u32, queue_handle: &QueueHandle<AppState>) {
+        let Some(index) = self.seats.seats.iter().position(|s| s.global == global) else {
+            return;
+        };
+        self.remove_keyboard(index);
+        self.remove_pointer(index, queue_handle);
+
+        let seat = self.seats.seats.remove(index);
+        protocol_log!("seat {} removed", seat.name);
+        self.forget_seat_serials(&seat.seat);
+        self.idle_seat_removed(&seat.seat, queue_handle);
+        if seat.seat.version() >= 5 {
+            seat.seat.release();
+        }
+    }
+
+    //The window is going: every seat lets go of its devices, and nothing takes over.
+    pub(crate) fn release_seats(&mut self) {
+        self.forget_pointer();
+        for seat in std::mem::take(&mut self.seats.seats) {
+            if let Some(keyboard) = seat.keyboard
+                && keyboard.version() >= 3
+            {
+                keyboard.release();
+            }
+            if let Some(pointer) = seat.pointer {
+                self.forget_hit_pointer(&pointer);
+                if pointer.version() >= 3 {
+                    pointer.release();
+                }
+            }
+            self.forget_seat_serials(&seat.seat);
+            if seat.seat.version() >= 5 {
+                seat.seat.release();
+            }
+        }
+    }
+}
+
+impl Window {
+    //Names of the seats around ("seat0" and such), what input events are tagged with.
+    pub fn seats(&self) -> Vec<Arc<str>> {
+        self.state
+            .seats
+            .seats
+            .iter()
+            .map(|seat| seat.name.clone())
+            .collect()
+    }
+
+    //The wl_seat of a seat from seats(), see protocol_objects.rs.
+    pub fn wl_seat(&self, name: &str) -> Option<&WlSeat> {
+        self.state.seat_named(name).map(|seat| &seat.seat)
+    }
+}
+
+impl Dispatch<WlSeat, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        seat: &WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_seat::Event::Capabilities {
+                capabilities: WEnum::Value(capabilities),
+            } => {
+                protocol_log!("{} capabilities {capabilities:?}", seat.id());
+                state.update_devices(seat, capabilities, queue_handle);
+            }
+            //Quoting documentation: "The seat name is a UTF-8 string with no convention defined
+            //for its contents. Each name is unique among all wl_seat globals."
+            wl_seat::Event::Name { name } => {
+                if let Some(entry) = state.seat_mut(seat) {
+                    entry.name = name.into();
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use wayland_client::protocol::wl_seat::WlSeat;
+
+use crate::{AppState, Window};
+
+//Requests like move, resize, show_window_menu or set_selection must name the input event that
+//triggered them through its serial, so a client can't pop menus or start drags on its own.
+//Compositors check it against their recent events and quietly drop the request when it doesn't
+//match (or is too old), so the one we pass has to be fresh.
+//
+//Serials older than this get a warning when used: nothing breaks on our side, but a request that
+//does nothing is easy to chase for hours.
+const STALE_AFTER: Duration = Duration::from_secs(5);
+
+//Which input event a serial came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialKind {
+    KeyboardEnter,
+    KeyPress,
+    PointerEnter,
+    PointerButton,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Serial {
+    serial: u32,
+    received: Instant,
+}
+
+//The latest serial of each kind one seat gave us.
+#[derive(Debug, Default)]
+pub(crate) struct SeatSerials {
+    keyboard_enter: Option<Serial>,
+    key_press: Option<Serial>,
+    pointer_enter: Option<Serial>,
+    pointer_button: Option<Serial>,
+}
+
+impl SeatSerials {
+    fn slot(&mut self, kind: SerialKind) -> &mut Option<Serial> {
+        match kind {
+            SerialKind::KeyboardEnter => &mut self.keyboard_enter,
+            SerialKind::KeyPress => &mut self.key_press,
+            SerialKind::PointerEnter => &mut self.pointer_enter,
+            SerialKind::PointerButton => &mut self.pointer_button,
+        }
+    }
+
+    fn get(&self, kind: SerialKind) -> Option<Serial> {
+        match kind {
+            SerialKind::KeyboardEnter => self.keyboard_enter,
+            SerialKind::KeyPress => self.key_press,
+            SerialKind::PointerEnter => self.pointer_enter,
+            SerialKind::PointerButton => self.pointer_button,
+        }
+    }
+}
+
+//Per seat, since a serial only means something to the seat it came from.
+#[derive(Default)]
+pub(crate) struct SerialsState {
+    seats: Vec<(WlSeat, SeatSerials)>,
+}
+
+impl AppState {
+    //Called from the keyboard and pointer Dispatch impls.
+    pub(crate) fn record_serial(&mut self, seat: &WlSeat, kind: SerialKind, serial: u32) {
+        let index = match self.serials.seats.iter().position(|(s, _)| s == seat) {
+            Some(index) => index,
+            None => {
+                self.serials
+                    .seats
+                    .push((seat.clone(), SeatSerials::default()));
+                self.serials.seats.len() - 1
+            }
+        };
+        *self.serials.seats[index].1.slot(kind) = Some(Serial {
+            serial,
+            received: Instant::now(),
+        });
+    }
+
+    //The seat went away, its serials can't be used anymore.
+    pub(crate) fn forget_seat_serials(&mut self, seat: &WlSeat) {
+        self.serials.seats.retain(|(s, _)| s != seat);
+    }
+
+    //The newest serial of any of `kinds`, over all seats, with the seat it belongs to (requests
+    //taking a serial take the seat too). `request` is only there for the stale serial warning.
+    pub(crate) fn latest_serial(
+        &self,
+        kinds: &[SerialKind],
+        request: &str,
+    ) -> Option<(WlSeat, u32)> {
+        let (seat, serial) = self
+            .serials
+            .seats
+            .iter()
+            .flat_map(|(seat, serials)| {
+                kinds
+                    .iter()
+                    .filter_map(move |&kind| Some((seat, serials.get(kind)?)))
+            })
+            .max_by_key(|(_, serial)| serial.received)?;
+
+        let age = serial.received.elapsed();
+        if age > STALE_AFTER {
+            log::warn!(
+                "{request} uses an input serial from {age:.1?} ago, the compositor will probably \
+                 ignore it"
+            );
+        }
+        Some((seat.clone(), serial.serial))
+    }
+
+    //The seat a serial the application kept came from. Serials are unique per connection, so at
+    //most one seat has it.
+    pub(crate) fn seat_of_serial(&self, serial: u32) -> Option<WlSeat> {
+        let kinds = [
+            SerialKind::KeyboardEnter,
+            SerialKind::KeyPress,
+            SerialKind::PointerEnter,
+            SerialKind::PointerButton,
+        ];
+        self.serials
+            .seats
+            .iter()
+            .find(|(_, serials)| {
+                kinds
+                    .iter()
+                    .any(|&kind| serials.get(kind).is_some_and(|s| s.serial == serial))
+            })
+            .map(|(seat, _)| seat.clone())
+    }
+}
+
+impl Window {
+    //The newest serial of that kind, for requests made on the protocol objects directly.
+    pub fn latest_serial(&self, kind: SerialKind) -> Option<u32> {
+        self.state
+            .latest_serial(&[kind], "Window::latest_serial")
+            .map(|(_, serial)| serial)
+    }
+}
@@ -0,0 +1,321 @@
+//A second event queue, for protocols that send a lot. Everything else is dispatched on the
+//window's queue into AppState, one event at a time behind the same &mut: a gaming mouse's
+//relative motion at 1000 Hz means a thousand Dispatch calls (and a thousand WindowEvents) between
+//two configures. On a queue of its own with a small state of its own, those events are added up
+//instead, and the window gets one RelativeMotion per seat per dispatch: the sum of the deltas
+//since the last one, with the newest time.
+//
+//libwayland sorts events into queues by the object they're for as it reads them, whoever reads.
+//So an object goes on the side queue by being created through its QueueHandle, and the side
+//queue is either dispatched right after the window's (SideDispatch::AfterMainQueue) or by a
+//thread of its own (SideDispatch::Thread), which hands its sums over through a bounded channel
+//and wakes the window through the user events pipe, like the render thread does.
+//
+//The socket is still only read by the window's dispatching, which kicks the thread after each
+//one. A thread reading too would have to prepare a read and sleep on the socket, and
+//libwayland's read makes every prepared reader wait for all the others: poll_events, which reads
+//without sleeping first, would hang until the thread woke up.
+//
+//What can go there: objects whose events need nothing but their own data to digest, and that
+//nothing in AppState waits on. zwp_relative_pointer_v1 is one (its Dispatch only needs the seat
+//name), presentation feedback and frame callbacks of a renderer of your own would be others.
+//What can't: wl_pointer, wl_keyboard and wl_seat (focus, serials, held keys and bindings live in
+//AppState), wl_surface, wl_output and the xdg objects (configures, scale), and anything else the
+//registry handler binds.
+
+use std::{
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread::{self, JoinHandle},
+};
+
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle};
+use wayland_protocols::wp::relative_pointer::zv1::client::zwp_relative_pointer_v1::{
+    self, ZwpRelativePointerV1,
+};
+
+use crate::{AppState, Window, WindowError, WindowEvent, user_events::Waker};
+
+//Sums waiting for the window. When it's full, the thread keeps adding to the sums it has.
+const DIGESTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SideDispatch {
+    //Dispatched after each dispatch of the window's queue, on the same thread. Nothing to
+    //synchronize, the motion just doesn't wake the window by itself.
+    AfterMainQueue,
+    //Dispatched on a thread of its own, which wakes the window when it has something.
+    Thread,
+}
+
+//Relative motion of one seat, added up.
+#[derive(Debug, Clone)]
+pub(crate) struct MotionSum {
+    seat: Arc<str>,
+    dx: f64,
+    dy: f64,
+    dx_unaccel: f64,
+    dy_unaccel: f64,
+    utime: u64,
+    samples: u32,
+}
+
+impl MotionSum {
+    fn into_event(self) -> WindowEvent {
+        WindowEvent::RelativeMotion {
+            seat: self.seat,
+            dx: self.dx,
+            dy: self.dy,
+            dx_unaccel: self.dx_unaccel,
+            dy_unaccel: self.dy_unaccel,
+            utime: self.utime,
+            synthetic: false,
+            samples: self.samples,
+        }
+    }
+}
+
+//What the side queue dispatches into.
+#[derive(Default)]
+pub(crate) struct SideState {
+    motion: Vec<MotionSum>,
+}
+
+impl SideState {
+    fn add(&mut self, motion: MotionSum) {
+        match self.motion.iter_mut().find(|sum| sum.seat == motion.seat) {
+            Some(sum) => {
+                sum.dx += motion.dx;
+                sum.dy += motion.dy;
+                sum.dx_unaccel += motion.dx_unaccel;
+                sum.dy_unaccel += motion.dy_unaccel;
+                sum.utime = sum.utime.max(motion.utime);
+                sum.samples += motion.samples;
+            }
+            None => self.motion.push(motion),
+        }
+    }
+}
+
+pub(crate) enum SideQueueState {
+    AfterMainQueue {
+        queue: EventQueue<SideState>,
+        state: SideState,
+    },
+    Thread {
+        queue_handle: QueueHandle<SideState>,
+        digests: Receiver<Vec<MotionSum>>,
+        //The thread dispatches once for each, and ends when it's dropped.
+        kick: Option<SyncSender<()>>,
+        thread: Option<JoinHandle<()>>,
+    },
+}
+
+impl SideQueueState {
+    pub(crate) fn handle(&self) -> QueueHandle<SideState> {
+        match self {
+            SideQueueState::AfterMainQueue { queue, .. } => queue.handle(),
+            SideQueueState::Thread { queue_handle, .. } => queue_handle.clone(),
+        }
+    }
+
+    pub(crate) fn dispatch(&self) -> SideDispatch {
+        match self {
+            SideQueueState::AfterMainQueue { .. } => SideDispatch::AfterMainQueue,
+            SideQueueState::Thread { .. } => SideDispatch::Thread,
+        }
+    }
+}
+
+//Without kicks the thread has nothing left to wait for.
+impl Drop for SideQueueState {
+    fn drop(&mut self) {
+        if let SideQueueState::Thread { kick, thread, .. } = self {
+            kick.take();
+            if let Some(thread) = thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+//The thread's loop: a dispatch per kick, and the sums handed over if there's room.
+fn run(
+    mut queue: EventQueue<SideState>,
+    kicks: Receiver<()>,
+    digests: SyncSender<Vec<MotionSum>>,
+    waker: Waker,
+) {
+    let mut state = SideState::default();
+    while kicks.recv().is_ok() {
+        if queue.dispatch_pending(&mut state).is_err() {
+            return;
+        }
+        if state.motion.is_empty() {
+            continue;
+        }
+        match digests.try_send(std::mem::take(&mut state.motion)) {
+            //Delivered on the window's next dispatch, which this starts if it's asleep.
+            Ok(()) => {
+                if waker.wake().is_err() {
+                    return;
+                }
+            }
+            Err(TrySendError::Full(motion)) => {
+                for sum in motion {
+                    state.add(sum);
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => return,
+        }
+    }
+}
+
+impl AppState {
+    //After every dispatch of the window's queue: the side queue's sums become WindowEvents.
+    pub(crate) fn dispatch_side_queue(&mut self) {
+        let motion = match self.side_queue.as_mut() {
+            None => return,
+            Some(SideQueueState::AfterMainQueue { queue, state }) => {
+                if let Err(err) = queue.dispatch_pending(state) {
+                    log::warn!("side queue: {err}");
+                }
+                std::mem::take(&mut state.motion)
+            }
+            Some(SideQueueState::Thread { digests, kick, .. }) => {
+                //Full means a kick is waiting already.
+                if let Some(kick) = kick {
+                    let _ = kick.try_send(());
+                }
+                let mut side = SideState::default();
+                for sum in digests.try_iter().flatten() {
+                    side.add(sum);
+                }
+                side.motion
+            }
+        };
+        self.events
+            .extend(motion.into_iter().map(MotionSum::into_event));
+    }
+}
+
+impl Window {
+    //Moves relative pointer motion to a queue of its own (see side_queue.rs), which hands it over
+    //added up, one WindowEvent::RelativeMotion per seat per dispatch. Calling it again moves it
+    //to a new one. The synthetic motion of compositors without the protocol stays where it is.
+    pub fn isolate_relative_pointer(&mut self, dispatch: SideDispatch) -> Result<(), WindowError> {
+        let waker = match dispatch {
+            SideDispatch::AfterMainQueue => None,
+            SideDispatch::Thread => Some(self.waker()?),
+        };
+        //No events for the old queue's objects once they're destroyed, then it can go.
+        self.state.relative_pointer.forget();
+        self.state.side_queue = None;
+
+        let queue = self.connection.new_event_queue();
+        self.state.side_queue = Some(match waker {
+            None => SideQueueState::AfterMainQueue {
+                queue,
+                state: SideState::default(),
+            },
+            Some(waker) => {
+                let queue_handle = queue.handle();
+                let (sender, digests) = mpsc::sync_channel(DIGESTS);
+                let (kick, kicks) = mpsc::sync_channel(1);
+                let thread = thread::Builder::new()
+                    .name("wayland side queue".into())
+                    .spawn(move || run(queue, kicks, sender, waker))
+                    .map_err(|err| WindowError::Connection(err.to_string()))?;
+                SideQueueState::Thread {
+                    queue_handle,
+                    digests,
+                    kick: Some(kick),
+                    thread: Some(thread),
+                }
+            }
+        });
+        let queue_handle = self.event_queue.handle();
+        self.state.init_relative_pointer(&queue_handle);
+        Ok(())
+    }
+
+    //Where relative pointer motion is dispatched, None for the window's own queue.
+    pub fn relative_pointer_isolation(&self) -> Option<SideDispatch> {
+        self.state.side_queue.as_ref().map(SideQueueState::dispatch)
+    }
+}
+
+//The same as AppState's (relative_pointer.rs), added up. The seat's name comes along as the
+//object's data, the side queue has no seats to look it up in.
+impl Dispatch<ZwpRelativePointerV1, Arc<str>> for SideState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpRelativePointerV1,
+        event: zwp_relative_pointer_v1::Event,
+        seat: &Arc<str>,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwp_relative_pointer_v1::Event::RelativeMotion {
+            utime_hi,
+            utime_lo,
+            dx,
+            dy,
+            dx_unaccel,
+            dy_unaccel,
+        } = event
+        {
+            state.add(MotionSum {
+                seat: seat.clone(),
+                dx,
+                dy,
+                dx_unaccel,
+                dy_unaccel,
+                utime: (u64::from(utime_hi) << 32) | u64::from(utime_lo),
+                samples: 1,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motion(seat: &str, dx: f64, utime: u64) -> MotionSum {
+        MotionSum {
+            seat: seat.into(),
+            dx,
+            dy: -dx,
+            dx_unaccel: dx * 2.0,
+            dy_unaccel: 0.0,
+            utime,
+            samples: 1,
+        }
+    }
+
+    #[test]
+    fn motion_adds_up_per_seat() {
+        let mut state = SideState::default();
+        state.add(motion("seat0", 1.5, 10));
+        state.add(motion("seat1", 4.0, 11));
+        state.add(motion("seat0", 2.0, 12));
+        let sums: Vec<_> = state
+            .motion
+            .iter()
+            .map(|sum| {
+                let dx = (sum.dx, sum.dy, sum.dx_unaccel);
+                (&*sum.seat, dx, sum.utime, sum.samples)
+            })
+            .collect();
+        assert_eq!(
+            sums,
+            [
+                ("seat0", (3.5, -3.5, 7.0), 12, 2),
+                ("seat1", (4.0, -4.0, 8.0), 11, 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//Windows coming back at the size they were closed with. Wayland leaves positions to the
+//compositor, sizes are the client's: a configure with a 0 (usually the first one) lets it pick.
+//With WindowOptions::size_persistence set to an app id, the window's size and whether it was
+//maximized or fullscreen go to $XDG_STATE_HOME/<app id>/window.ron (~/.local/state without
+//XDG_STATE_HOME), and the next window with that id starts from them: the size as its preferred
+//size, the flags as WindowOptions::maximized and fullscreen.
+//
+//Off by default, it writes files. The size saved is the last one the window had while nothing
+//forced it (maximized, fullscreen, tiled): unmaximizing on the next run goes back to it. It's
+//written once the window kept the same state for DEBOUNCE, checked when a dispatch ends, so an
+//interactive resize is one write; and when the window is closed or dropped.
+//
+//The file is a RON tuple struct written and read by hand, `(width: 800, height: 600, ...)`.
+//Anything missing, unreadable or out of range means no saved size, and nothing is said: the
+//window starts as if it was the first run. Failing to write is a debug log, nothing more.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use wayland_protocols::xdg::shell::client::xdg_toplevel;
+
+use crate::{AppState, Window};
+
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+//Bigger than any screen, smaller than any buffer that can't be made.
+const MAX_SIDE: u32 = 1 << 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SavedSize {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) maximized: bool,
+    pub(crate) fullscreen: bool,
+}
+
+impl SavedSize {
+    fn to_ron(self) -> String {
+        format!(
+            "(\n    width: {},\n    height: {},\n    maximized: {},\n    fullscreen: {},\n)\n",
+            self.width, self.height, self.maximized, self.fullscreen
+        )
+    }
+
+    //Fields in any order, unknown ones skipped. width and height must be there.
+    fn from_ron(text: &str) -> Option<SavedSize> {
+        let fields = text.trim().strip_prefix('(')?.strip_suffix(')')?;
+        let (mut width, mut height) = (None, None);
+        let (mut maximized, mut fullscreen) = (false, false);
+        for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (name, value) = field.split_once(':')?;
+            let value = value.trim();
+            match name.trim() {
+                "width" => width = Some(value.parse().ok()?),
+                "height" => height = Some(value.parse().ok()?),
+                "maximized" => maximized = value.parse().ok()?,
+                "fullscreen" => fullscreen = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        let side = |side: Option<u32>| side.filter(|side| (1..=MAX_SIDE).contains(side));
+        Some(SavedSize {
+            width: side(width)?,
+            height: side(height)?,
+            maximized,
+            fullscreen,
+        })
+    }
+}
+
+//Where the file of `app_id` goes. None for ids that would make a path of their own ("../x").
+fn state_file(app_id: &str) -> Option<PathBuf> {
+    if app_id.is_empty() || app_id.contains('/') || app_id.starts_with('.') {
+        log::warn!("app id {app_id:?} can't be a directory name, the size isn't persisted");
+        return None;
+    }
+    let non_empty = |name| env::var_os(name).filter(|value| !value.is_empty());
+    let state_home = non_empty("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| Some(PathBuf::from(non_empty("HOME")?).join(".local/state")))?;
+    Some(state_home.join(app_id).join("window.ron"))
+}
+
+fn load(path: &Path) -> Option<SavedSize> {
+    SavedSize::from_ron(&fs::read_to_string(path).ok()?)
+}
+
+//Through a temporary file, so a crash halfway leaves the old file.
+fn save(path: &Path, size: SavedSize) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension("ron.tmp");
+    fs::write(&temporary, size.to_ron())?;
+    fs::rename(temporary, path)
+}
+
+#[derive(Default)]
+pub(crate) struct SizePersistenceState {
+    //None when not enabled.
+    path: Option<PathBuf>,
+    //What the file has.
+    saved: Option<SavedSize>,
+    //The last size nothing forced, in surface pixels without the shadow.
+    normal_size: Option<(u32, u32)>,
+    //A state differing from the file's, and since when it's the same.
+    pending: Option<(SavedSize, Instant)>,
+}
+
+impl AppState {
+    //WindowOptions::size_persistence, when the window is made.
+    pub(crate) fn restore_size(&mut self, app_id: Option<&str>) {
+        let Some(path) = app_id.and_then(state_file) else {
+            return;
+        };
+        let saved = load(&path);
+        if let Some(saved) = saved {
+            log::debug!("restoring {saved:?} from {}", path.display());
+            self.sizing.preferred_size = Some((saved.width, saved.height));
+            self.maximized |= saved.maximized;
+            self.fullscreen |= saved.fullscreen;
+        }
+        self.size_persistence = SizePersistenceState {
+            path: Some(path),
+            saved,
+            normal_size: saved.map(|saved| (saved.width, saved.height)),
+            pending: None,
+        };
+    }
+
+    fn current_saved_size(&mut self) -> Option<SavedSize> {
+        self.size_persistence.path.as_ref()?;
+        if !self.configured() {
+            return None;
+        }
+        if !self.size_is_forced() {
+            let geometry = self.window_geometry();
+            self.size_persistence.normal_size =
+                Some((geometry.width as u32, geometry.height as u32));
+        }
+        let (width, height) = self.size_persistence.normal_size?;
+        Some(SavedSize {
+            width,
+            height,
+            maximized: self.has_state(xdg_toplevel::State::Maximized),
+            fullscreen: self.has_state(xdg_toplevel::State::Fullscreen),
+        })
+    }
+
+    fn write_saved_size(&mut self, size: SavedSize) {
+        let persistence = &mut self.size_persistence;
+        persistence.pending = None;
+        let Some(ref path) = persistence.path else {
+            return;
+        };
+        match save(path, size) {
+            Ok(()) => persistence.saved = Some(size),
+            Err(err) => log::debug!("window size not saved to {}: {err}", path.display()),
+        }
+    }
+
+    //When a dispatch ends: saved once the state stayed the same for DEBOUNCE.
+    pub(crate) fn persist_size(&mut self) {
+        let Some(current) = self.current_saved_size() else {
+            return;
+        };
+        let now = Instant::now();
+        let persistence = &mut self.size_persistence;
+        if persistence.saved == Some(current) {
+            persistence.pending = None;
+            return;
+        }
+        match persistence.pending {
+            Some((pending, since)) if pending == current => {
+                if now.duration_since(since) >= DEBOUNCE {
+                    self.write_saved_size(current);
+                }
+            }
+            _ => persistence.pending = Some((current, now)),
+        }
+    }
+
+    //Closing or dropping the window: saved right away.
+    pub(crate) fn persist_size_now(&mut self) {
+        if let Some(current) = self.current_saved_size()
+            && self.size_persistence.saved != Some(current)
+        {
+            self.write_saved_size(current);
+        }
+    }
+}
+
+impl Window {
+    //The file the size goes to, None when WindowOptions::size_persistence isn't set.
+    pub fn size_persistence_file(&self) -> Option<&Path> {
+        self.state.size_persistence.path.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: SavedSize = SavedSize {
+        width: 800,
+        height: 600,
+        maximized: true,
+        fullscreen: false,
+    };
+
+    #[test]
+    fn sizes_go_through_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("org.example.App/window.ron");
+        assert_eq!(load(&path), None);
+        save(&path, SIZE).unwrap();
+        assert_eq!(load(&path), Some(SIZE));
+        assert!(!path.with_extension("ron.tmp").exists());
+    }
+
+    #[test]
+    fn broken_files_are_no_size() {
+        assert_eq!(
+            SavedSize::from_ron("(height: 600,width:800 , maximized: true, x: \"y\")"),
+            Some(SIZE)
+        );
+        assert_eq!(
+            SavedSize::from_ron("(width: 800, height: 600)").map(|s| s.maximized),
+            Some(false)
+        );
+        for broken in [
+            "",
+            "(width: 800)",
+            "(width: 0, height: 600)",
+            "(width: 800, height: 99999999)",
+            "(width: -1, height: 600)",
+            "(width: 800, height: 600, maximized: yes)",
+            "width: 800, height: 600",
+            "\u{0}\u{1}garbage",
+        ] {
+            assert_eq!(SavedSize::from_ron(broken), None, "{broken:?}");
+        }
+    }
+}
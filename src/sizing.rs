@@ -0,0 +1,236 @@
+use wayland_protocols::xdg::shell::client::xdg_toplevel;
+
+use crate::{
+    AppState, Rect, RenderMode, Window, WindowError, buffer_layout::MAX_DIMENSION,
+    protocol_log::protocol_log,
+};
+
+//A proposed side beyond this is garbage rather than a window: the buffer couldn't be that big
+//anyway.
+const CONFIGURE_CAP: i32 = MAX_DIMENSION as i32;
+//Nor is a side below this a window, 0 aside (ours to pick).
+const DEGENERATE_BELOW: i32 = 4;
+
+#[derive(Default)]
+pub(crate) struct SizingState {
+    min_size: Option<(u32, u32)>,
+    max_size: Option<(u32, u32)>,
+    aspect_ratio: Option<(u32, u32)>,
+    //WindowOptions::preferred_size.
+    pub(crate) preferred_size: Option<(u32, u32)>,
+    //The latest sane size a configure proposed, standing in for the transient ones.
+    proposed: Option<(i32, i32)>,
+}
+
+impl AppState {
+    //min/max size are double-buffered toplevel state, sent again when the toplevel gets created
+    //(before its initial commit). The caller commits.
+    pub(crate) fn apply_size_limits(&self) {
+        let Some((_, ref toplevel)) = self.xdg_surface else {
+            return;
+        };
+        //0 is "no limit" on the wire.
+        let (min_width, min_height) = self.sizing.min_size.unwrap_or((0, 0));
+        let (max_width, max_height) = self.sizing.max_size.unwrap_or((0, 0));
+        toplevel.set_min_size(min_width as i32, min_height as i32);
+        toplevel.set_max_size(max_width as i32, max_height as i32);
+    }
+
+    //Maximized, fullscreen and tiled windows have to take the size they're given: a smaller
+    //buffer would leave a hole in the layout.
+    pub(crate) fn size_is_forced(&self) -> bool {
+        self.configure_states.iter().any(|state| {
+            matches!(
+                state,
+                xdg_toplevel::State::Maximized
+                    | xdg_toplevel::State::Fullscreen
+                    | xdg_toplevel::State::TiledLeft
+                    | xdg_toplevel::State::TiledRight
+                    | xdg_toplevel::State::TiledTop
+                    | xdg_toplevel::State::TiledBottom
+            )
+        })
+    }
+
+    //From the xdg_toplevel Configure: its size, once through sane_configure_size. The serial is
+    //acked all the same, whatever we make of the size (a client skipping acks looks stuck to
+    //some compositors).
+    pub(crate) fn propose_configure_size(&mut self, proposed: (i32, i32)) {
+        let sane = sane_configure_size(proposed, self.sizing.proposed);
+        if sane != proposed {
+            protocol_log!(
+                "configure size {}x{} taken as {}x{}",
+                proposed.0,
+                proposed.1,
+                sane.0,
+                sane.1
+            );
+        }
+        if sane.0 > 0 && sane.1 > 0 {
+            self.sizing.proposed = Some(sane);
+        }
+        self.configure_size = sane;
+    }
+
+    //The window size a configure asks for. Quoting documentation: "If the width or height
+    //arguments are zero, it means the client should decide its own window dimension." We decide
+    //for the preferred size, or the current one without, kept within ConfigureBounds when the
+    //compositor sent them. Then fitted to our limits.
+    pub(crate) fn configure_target_size(&self) -> (u32, u32) {
+        let (width, height) = self.configure_size;
+        let preferred = self
+            .sizing
+            .preferred_size
+            .unwrap_or_else(|| self.sent_window_size());
+        let (max_width, max_height) = self.configure_bounds.unwrap_or((u32::MAX, u32::MAX));
+        let pick = |proposed: i32, preferred: u32, bound: u32| {
+            if proposed > 0 {
+                proposed as u32
+            } else {
+                preferred.min(bound).max(1)
+            }
+        };
+        self.constrain_size((
+            pick(width, preferred.0, max_width),
+            pick(height, preferred.1, max_height),
+        ))
+    }
+
+    //The size we answer a configure with: the proposed one within our limits and, if there is
+    //one, shrunk to the aspect ratio. Forced sizes are taken as they are, content_rect then says
+    //where the picture goes.
+    pub(crate) fn constrain_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        if self.size_is_forced() {
+            return (width, height);
+        }
+
+        let (min_width, min_height) = self.sizing.min_size.unwrap_or((1, 1));
+        let (max_width, max_height) = self.sizing.max_size.unwrap_or((u32::MAX, u32::MAX));
+        let size = (
+            width.clamp(min_width, max_width.max(min_width)),
+            height.clamp(min_height, max_height.max(min_height)),
+        );
+        match self.sizing.aspect_ratio {
+            Some(ratio) => fit_aspect_ratio(size, ratio),
+            None => size,
+        }
+    }
+}
+
+//A configure's proposed size, with the garbage out. Mutter sends a transient 1x1 or 65535x65535
+//during monitor changes, compositors in the making send anything (negative sizes too): with a
+//side below DEGENERATE_BELOW or beyond CONFIGURE_CAP, the latest sane size stands in when there
+//is one. Without, the sides are clamped, a negative one being ours to pick like a 0. The min and
+//max size come after, in constrain_size.
+fn sane_configure_size((width, height): (i32, i32), latest: Option<(i32, i32)>) -> (i32, i32) {
+    let degenerate =
+        |side: i32| side < 0 || (1..DEGENERATE_BELOW).contains(&side) || side > CONFIGURE_CAP;
+    if !degenerate(width) && !degenerate(height) {
+        return (width, height);
+    }
+    latest.unwrap_or((
+        width.clamp(0, CONFIGURE_CAP),
+        height.clamp(0, CONFIGURE_CAP),
+    ))
+}
+
+//The biggest size of that ratio that fits in `size`: the smaller side decides, the other one
+//gets shortened. Never 0.
+fn fit_aspect_ratio((width, height): (u32, u32), (ratio_x, ratio_y): (u32, u32)) -> (u32, u32) {
+    let (width, height, ratio_x, ratio_y) = (
+        u64::from(width),
+        u64::from(height),
+        u64::from(ratio_x),
+        u64::from(ratio_y),
+    );
+    let fitted = if width * ratio_y <= height * ratio_x {
+        (width, width * ratio_y / ratio_x)
+    } else {
+        (height * ratio_x / ratio_y, height)
+    };
+    (fitted.0.max(1) as u32, fitted.1.max(1) as u32)
+}
+
+impl Window {
+    //Keeps the window at width:height when the compositor lets us pick the size (floating
+    //windows, interactive resizes). When it doesn't (maximized, fullscreen, tiled) the window takes
+    //the forced size and the picture belongs centered in content_rect, with black bars around
+    //it: draw those rather than stretching the picture.
+    //
+    //Client rendered windows (EGL, external) follow it right away, the shm buffer from the next
+    //configure.
+    pub fn set_aspect_ratio(&mut self, ratio: Option<(u32, u32)>) -> Result<(), WindowError> {
+        if ratio.is_some_and(|(x, y)| x == 0 || y == 0) {
+            return Err(WindowError::InvalidArgument("aspect ratio with a 0 side"));
+        }
+
+        let size = self.size();
+        self.state.sizing.aspect_ratio = ratio;
+        if self.state.render_mode != RenderMode::Shm {
+            let size = self.state.constrain_size(size);
+            self.state.resize_client_rendered(size, false);
+        }
+        Ok(())
+    }
+
+    //Window (not buffer) size limits, enforced by the compositor during interactive resizes. The
+    //same value for both gives a fixed-size window. None removes the limit.
+    pub fn set_size_limits(
+        &mut self,
+        min: Option<(u32, u32)>,
+        max: Option<(u32, u32)>,
+    ) -> Result<(), WindowError> {
+        //Quoting documentation: "Requesting a maximum size to be smaller than the minimum size of
+        //a surface is illegal and will result in an invalid_size error."
+        if let (Some(min), Some(max)) = (min, max)
+            && (max.0 < min.0 || max.1 < min.1)
+        {
+            return Err(WindowError::InvalidArgument(
+                "maximum size below the minimum",
+            ));
+        }
+
+        self.state.sizing.min_size = min;
+        self.state.sizing.max_size = max;
+        self.state.apply_size_limits();
+        let queue_handle = self.event_queue.handle();
+        self.state.commit_state(&queue_handle);
+        Ok(())
+    }
+
+    //Where the picture goes in the buffer: the window geometry, or the part of it with the
+    //aspect ratio when the size was forced on us.
+    pub fn content_rect(&self) -> Rect {
+        let geometry = self.state.window_geometry();
+        let Some(ratio) = self.state.sizing.aspect_ratio else {
+            return geometry;
+        };
+
+        let (width, height) =
+            fit_aspect_ratio((geometry.width as u32, geometry.height as u32), ratio);
+        Rect::new(
+            geometry.x + (geometry.width - width as i32) / 2,
+            geometry.y + (geometry.height - height as i32) / 2,
+            width as i32,
+            height as i32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transient_configure_sizes_keep_the_latest_sane_one() {
+        let latest = Some((800, 600));
+        assert_eq!(sane_configure_size((1024, 768), latest), (1024, 768));
+        assert_eq!(sane_configure_size((0, 0), latest), (0, 0));
+        assert_eq!(sane_configure_size((1, 1), latest), (800, 600));
+        assert_eq!(sane_configure_size((65535, 65535), latest), (800, 600));
+        assert_eq!(sane_configure_size((-3, 600), latest), (800, 600));
+
+        assert_eq!(sane_configure_size((1, 1), None), (1, 1));
+        assert_eq!(sane_configure_size((65535, -3), None), (CONFIGURE_CAP, 0));
+    }
+}
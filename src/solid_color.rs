@@ -0,0 +1,118 @@
+use wayland_client::{
+    QueueHandle, delegate_noop,
+    protocol::{wl_buffer::WlBuffer, wl_shm},
+};
+use wayland_protocols::wp::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1;
+
+use crate::{
+    AppState, RenderMode, Window, WindowError, buffer_layout::BufferLayout,
+    transaction::StagedCommit,
+};
+
+//Shown between the first configure and the first real draw, so the window maps right away.
+const PLACEHOLDER_COLOR: [u32; 4] = [0, 0, 0, u32::MAX];
+
+#[derive(Default)]
+pub(crate) struct SolidColorState {
+    pub(crate) manager: Option<wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1>,
+    buffer: Option<WlBuffer>,
+}
+
+impl SolidColorState {
+    //Compaction recreated the shm fallback's buffer elsewhere in the pool.
+    pub(crate) fn replace_buffer(&mut self, old: &WlBuffer, new: WlBuffer) {
+        if self.buffer.as_ref() == Some(old) {
+            self.buffer = Some(new);
+        }
+    }
+}
+
+impl AppState {
+    //A 1x1 buffer holding the color. Channels go from 0 to u32::MAX and, like everything
+    //on Wayland, are premultiplied by alpha.
+    //
+    //wp_single_pixel_buffer_manager_v1 makes it without any memory on our side. Without it, a
+    //1x1 shm buffer from the shared pool does the same job.
+    fn create_solid_buffer(
+        &mut self,
+        [r, g, b, a]: [u32; 4],
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Option<WlBuffer> {
+        if let Some(ref manager) = self.solid_color.manager {
+            return Some(manager.create_u32_rgba_buffer(r, g, b, a, queue_handle, ()));
+        }
+
+        //Argb8888 is little endian, so the bytes are B, G, R, A. Only the top 8 bits survive.
+        let pixel = [
+            (b >> 24) as u8,
+            (g >> 24) as u8,
+            (r >> 24) as u8,
+            (a >> 24) as u8,
+        ];
+        let layout = BufferLayout::new(1, 1, wl_shm::Format::Argb8888).ok()?;
+        self.create_pool_buffer(layout, &pixel, false, queue_handle)
+    }
+
+    //Attaches a solid color stretched (with the viewport) over the whole surface.
+    pub(crate) fn show_solid_color(
+        &mut self,
+        color: [u32; 4],
+        queue_handle: &QueueHandle<AppState>,
+    ) -> Result<(), WindowError> {
+        if !self.has_viewporter() {
+            return Err(WindowError::Unsupported("wp_viewporter"));
+        }
+        let Some(buffer) = self.create_solid_buffer(color, queue_handle) else {
+            return Err(WindowError::Unsupported(
+                "wp_single_pixel_buffer_manager_v1",
+            ));
+        };
+
+        //Same rule as every other attach: no buffer before the first configure.
+        if self.configured() && self.may_attach() {
+            //The destination is in surface coordinates, it turns with the buffer transform. A
+            //crop would be outside the single pixel. The buffer scale comes out 1, a single
+            //pixel isn't a multiple of anything else.
+            let (width, height) = self.surface_size();
+            self.clear_viewport_source();
+            let staged = StagedCommit {
+                buffer: Some((buffer.clone(), 1, 1)),
+                viewport_destination: Some(Some((width as i32, height as i32))),
+                ..StagedCommit::default()
+            };
+            self.commit_staged(staged, queue_handle, "single pixel buffer");
+            self.pool_buffer_attached(&buffer);
+        }
+
+        //The previous solid buffer isn't attached anymore.
+        if let Some(old) = self.solid_color.buffer.replace(buffer) {
+            self.drop_buffer(old);
+        }
+        Ok(())
+    }
+
+    //Whether what the window shows is a solid color, see set_viewport_source.
+    pub(crate) fn shows_solid_color(&self) -> bool {
+        self.attached.is_some() && self.attached == self.solid_color.buffer
+    }
+
+    pub(crate) fn show_placeholder(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if self.render_mode != RenderMode::Shm {
+            return;
+        }
+        //Not having a placeholder is fine, the window just maps with the first real draw.
+        let _ = self.show_solid_color(PLACEHOLDER_COLOR, queue_handle);
+    }
+}
+
+impl Window {
+    //Fills the whole window with one color, without drawing anything. Handy for splash screens
+    //and letterbox bars. Channels go from 0 to u32::MAX, premultiplied by alpha. The color stays
+    //until the next draw.
+    pub fn fill_color(&mut self, r: u32, g: u32, b: u32, a: u32) -> Result<(), WindowError> {
+        let queue_handle = self.event_queue.handle();
+        self.state.show_solid_color([r, g, b, a], &queue_handle)
+    }
+}
+
+delegate_noop!(AppState: wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1);
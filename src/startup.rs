@@ -0,0 +1,174 @@
+//Startup, from Window::with_connection to the first frame on screen. Nothing of it waits on a
+//roundtrip: the surface and its xdg objects are made as the registry announces the globals (see
+//the Dispatch<WlRegistry> impl), and the initial commit leaves with that same dispatch. What the
+//first configure used to wait for is the first frame: a new file, the renderer over the whole
+//buffer, and only then the attach.
+//
+//So the file is made and the frame drawn right after the initial commit, which is flushed by
+//then: the compositor works out its configure while we draw. The configure only wraps the file in
+//a pool and a buffer, right after its ack. A frame drawn for another size or scale than the
+//configure's is dropped and drawn again as before, as is one a set_renderer or set_opacity made
+//stale meanwhile.
+//
+//Unlike the surface, the pool is not made ahead of the configure, only its file. A wl_shm_pool
+//takes its size when created and the configure may change the buffer's, making it then would
+//mean a second pool for most first configures. Wrapping the file costs no roundtrip either way.
+//
+//Window::startup_timing says how long it all took, see --probe-startup. The first frame callback
+//is only asked for with WindowOptions::startup_timing.
+
+use std::time::{Duration, Instant};
+
+use wayland_client::{
+    Connection, Dispatch, QueueHandle,
+    protocol::wl_callback::{self, WlCallback},
+};
+
+use crate::{
+    AppState, Lifecycle, RenderMode, Window, buffer_layout::BufferLayout, canvas::MappedFile,
+    mapped_pool::MappedPool, protocol_log::protocol_log,
+};
+
+//How long startup took, counted from Window::with_connection. None for what didn't happen yet,
+//or never will: frame callbacks and presentation feedback are the compositor's to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StartupTiming {
+    //The first commit with a buffer.
+    pub time_to_first_commit: Option<Duration>,
+    //The done of a frame callback asked for right after the initial commit, so it goes with the
+    //next one (the first frame's): the compositor showed it, or thinks it's time for the next.
+    //Only with WindowOptions::startup_timing.
+    pub time_to_first_frame_callback: Option<Duration>,
+    //wp_presentation_feedback.presented of one of our commits, with wp_presentation only.
+    pub time_to_first_presented: Option<Duration>,
+    //Whether the first frame was the one drawn ahead of the first configure.
+    pub prerendered: bool,
+}
+
+//User data of the window's own frame callback, see StartupTiming::time_to_first_frame_callback.
+pub(crate) struct FirstFrame;
+
+pub(crate) struct StartupState {
+    started: Instant,
+    //WindowOptions::startup_timing.
+    timed: bool,
+    pub(crate) timing: StartupTiming,
+    //The first frame with its layout and the scale it was drawn at, until the first configure.
+    prerendered: Option<(MappedPool, BufferLayout, i32)>,
+    //Tried already, it's once after the initial commit.
+    prepared: bool,
+}
+
+impl StartupState {
+    pub(crate) fn new(timed: bool) -> StartupState {
+        StartupState {
+            started: Instant::now(),
+            timed,
+            timing: StartupTiming::default(),
+            prerendered: None,
+            prepared: false,
+        }
+    }
+
+    //`when` is unset: now.
+    fn stamp(started: Instant, when: &mut Option<Duration>) {
+        when.get_or_insert_with(|| started.elapsed());
+    }
+}
+
+impl AppState {
+    //From Window::apply_configure, after the initial commit: the first frame, drawn while the
+    //compositor works out its configure.
+    pub(crate) fn prepare_first_frame(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if self.startup.prepared || self.lifecycle.stage() != Lifecycle::InitialCommitDone {
+            return;
+        }
+        self.startup.prepared = true;
+        if self.startup.timed
+            && let Some(ref surface) = self.base_surface
+        {
+            surface.frame(queue_handle, FirstFrame);
+        }
+
+        if self.render_mode != RenderMode::Shm
+            || self.render_thread.is_some()
+            || self.shm.is_none()
+            || self.buffer.is_some()
+        {
+            return;
+        }
+        let (width, height) = self.buffer_size_for(self.configure_target_size());
+        let Ok(layout) = BufferLayout::new(width, height, self.format.into()) else {
+            return;
+        };
+        let Ok(pixels) = MappedFile::new(layout.len()) else {
+            return;
+        };
+        //Drawn as the main buffer would be, the renderer only knows to paint that one.
+        self.shm_pixels = Some((MappedPool::new(pixels), layout));
+        self.render_background();
+        self.startup.prerendered = self
+            .shm_pixels
+            .take()
+            .map(|(pixels, layout)| (pixels, layout, self.scale_factor()));
+        protocol_log!("first frame drawn ahead, {width}x{height}");
+    }
+
+    //The first frame's memory for a main buffer laid out as `layout`, when it was drawn for it.
+    //Gone either way: there's only one first configure.
+    pub(crate) fn take_prerendered(&mut self, layout: BufferLayout) -> Option<MappedPool> {
+        let (pixels, drawn_for, scale) = self.startup.prerendered.take()?;
+        if drawn_for != layout || scale != self.scale_factor() {
+            protocol_log!("first frame drawn ahead for another size, drawn again");
+            return None;
+        }
+        self.startup.timing.prerendered = true;
+        Some(pixels)
+    }
+
+    //What the renderer draws changed, the first frame drawn ahead shows something stale.
+    pub(crate) fn discard_prerendered(&mut self) {
+        self.startup.prerendered = None;
+    }
+
+    //From the commits with a buffer: commit_staged and Window::swap_buffers.
+    pub(crate) fn startup_committed(&mut self) {
+        StartupState::stamp(
+            self.startup.started,
+            &mut self.startup.timing.time_to_first_commit,
+        );
+    }
+
+    //From wp_presentation_feedback.presented.
+    pub(crate) fn startup_presented(&mut self) {
+        StartupState::stamp(
+            self.startup.started,
+            &mut self.startup.timing.time_to_first_presented,
+        );
+    }
+}
+
+impl Window {
+    //See StartupTiming.
+    pub fn startup_timing(&self) -> StartupTiming {
+        self.state.startup.timing
+    }
+}
+
+impl Dispatch<WlCallback, FirstFrame> for AppState {
+    fn event(
+        state: &mut Self,
+        _: &WlCallback,
+        event: wl_callback::Event,
+        _: &FirstFrame,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event {
+            StartupState::stamp(
+                state.startup.started,
+                &mut state.startup.timing.time_to_first_frame_callback,
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use wayland_protocols::xdg::shell::client::xdg_toplevel;
+
+use crate::{AppState, Decorations, OutputInfo, TiledEdges, Window};
+
+//Everything an application drawing its own UI looks at every frame, in one value. Taken once per
+//dispatch, after the batch's events are handled and before they're handed out, so all of it
+//agrees with itself and with those events. Between two dispatches it doesn't change, not even for
+//the application's own set_decorations and such (the getters like size() see those right away).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WindowStateSnapshot {
+    //Window size without the shadow, what Resized reports.
+    pub size: (u32, u32),
+    //Buffer size, shadow included: what a renderer draws.
+    pub buffer_size: (u32, u32),
+    //Integer scale the compositor would like the buffer at, see Window::scale_factor.
+    pub scale: i32,
+    pub configured: bool,
+    pub fullscreen: bool,
+    pub maximized: bool,
+    pub activated: bool,
+    pub suspended: bool,
+    pub decorations: Decorations,
+    //Where to leave the shadow out and draw square corners, see TiledEdges.
+    pub tiled: TiledEdges,
+    //Whether any seat's keyboard focus is on the window.
+    pub keyboard_focus: bool,
+    //The outputs the surface is on, in the order it entered them.
+    pub outputs: Arc<[OutputInfo]>,
+}
+
+impl AppState {
+    //Toplevel states are the compositor's word once configured, what was asked for before that.
+    pub(crate) fn has_state(&self, state: xdg_toplevel::State) -> bool {
+        if self.configured() {
+            return self.configure_states.contains(&state);
+        }
+        match state {
+            xdg_toplevel::State::Maximized => self.maximized,
+            xdg_toplevel::State::Fullscreen => self.fullscreen,
+            _ => false,
+        }
+    }
+
+    //Takes the snapshot again. The output list is only rebuilt when it changed, so cloning the
+    //snapshot stays a handful of copies and one reference count.
+    pub(crate) fn refresh_snapshot(&mut self) {
+        let geometry = self.window_geometry();
+        let outputs = self.surface_outputs();
+        let outputs = if *self.snapshot.outputs == *outputs {
+            self.snapshot.outputs.clone()
+        } else {
+            outputs.into()
+        };
+        self.snapshot = WindowStateSnapshot {
+            size: (geometry.width as u32, geometry.height as u32),
+            buffer_size: self.buffer_size,
+            scale: self.scale_factor(),
+            configured: self.configured(),
+            fullscreen: self.has_state(xdg_toplevel::State::Fullscreen),
+            maximized: self.has_state(xdg_toplevel::State::Maximized),
+            activated: self.activated,
+            suspended: self.suspended,
+            decorations: self.geometry.decorations,
+            tiled: self.tiled_edges(),
+            keyboard_focus: self.any_keyboard_focus(),
+            outputs,
+        };
+    }
+}
+
+impl Window {
+    //The window's state as of the last dispatch, see WindowStateSnapshot.
+    pub fn state(&self) -> WindowStateSnapshot {
+        self.state.snapshot.clone()
+    }
+
+    //Integer scale the compositor would like the buffer drawn at: wl_surface's preferred buffer
+    //scale when it sends one (v6), otherwise the largest scale of the outputs the window is on.
+    //Buffers follow the preferred one by themselves, see Window::buffer_scale.
+    pub fn scale_factor(&self) -> i32 {
+        self.state.scale_factor()
+    }
+}
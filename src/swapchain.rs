@@ -0,0 +1,435 @@
+//More than one shm buffer for the window, as many as the compositor's releases ask for. Drawing
+//into a buffer the compositor still reads shows half drawn frames, so a draw first makes sure the
+//window's buffer isn't attached and unreleased: it swaps it with a free spare, makes a spare if
+//there are fewer buffers than wanted, and only then waits for a release.
+//
+//Quoting documentation: "Sent when this wl_buffer is no longer used by the compositor. The client
+//is now free to reuse or destroy this buffer and its backing storage."
+//
+//Two buffers are enough when releases come right after the next commit (Sway copies shm buffers
+//on commit). Some compositors, and buffers scanned out on an overlay plane, release them a frame
+//later: with two, every other draw waits. More than `grow_after` waits within a second make one
+//more buffer, up to `max_buffers`. After `shrink_after` without a wait, one goes away again, down
+//to two.
+//
+//Every buffer has a file and a pool of its own, like the window's buffer always had: a
+//wl_shm_pool can grow but not shrink, a buffer going away gives its memory back by being
+//unmapped. Spares go away with the window's buffer (a new size, released while hidden).
+//
+//Each one's memory is a MappedPool (mapped_pool.rs) of one slice, and a buffer is busy when that
+//slice is: between its attach and its release, nothing gets a guard to write into it.
+//
+//A wait is bounded by `wait_timeout`: a compositor keeping every buffer (the window hidden, or
+//frozen) would stop the draw otherwise. It then draws into the busy buffer, as with one buffer:
+//the buffer is taken back as if released.
+//The render thread's frames never wait, they're copied on the dispatching thread: a frame that
+//finds every buffer busy counts as a wait and is copied anyway.
+//
+//Waiting dispatches the window's events, WindowEvents among them come with the next dispatch.
+//
+//Each buffer also remembers which frame it holds, for Window::draw_damaged (damage.rs): a
+//buffer coming back after a rotation is that many frames behind.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use rustix::event::{PollFd, Timespec, poll};
+use wayland_client::{
+    Connection, Dispatch, Proxy, QueueHandle,
+    protocol::wl_buffer::{self, WlBuffer},
+};
+
+use crate::{AppState, Rect, Window, mapped_pool::MappedPool, protocol_log::protocol_log};
+
+//The window's buffer and one spare.
+const MIN_BUFFERS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwapchainConfig {
+    //At least 2.
+    pub max_buffers: usize,
+    //Waits for a release within a second above which one more buffer is made.
+    pub grow_after: u32,
+    //Time without waits after which one buffer goes away.
+    pub shrink_after: Duration,
+    //Longest a draw waits for a release.
+    pub wait_timeout: Duration,
+}
+
+impl Default for SwapchainConfig {
+    //Triple buffering at most, once the compositor is a frame late more than twice a second.
+    fn default() -> Self {
+        SwapchainConfig {
+            max_buffers: 3,
+            grow_after: 2,
+            shrink_after: Duration::from_secs(5),
+            wait_timeout: Duration::from_millis(50),
+        }
+    }
+}
+
+//Same layout as the window's buffer.
+struct Spare {
+    buffer: WlBuffer,
+    //Busy from the attach to the release.
+    pixels: MappedPool,
+    drawn_at: Option<u64>,
+}
+
+pub(crate) struct SwapchainState {
+    config: SwapchainConfig,
+    spares: Vec<Spare>,
+    //Buffers wanted, the window's included.
+    target: usize,
+    //Waits of the last second.
+    recent: VecDeque<Instant>,
+    //The last wait, or growing or shrinking: what shrink_after counts from.
+    contended: Instant,
+    pub(crate) stalls: u64,
+    //Frames of the window's buffers presented, the frame the window's buffer holds (None when
+    //unknown: new, or drawn over without being presented), and the damage of the latest frames,
+    //newest last, None for a whole frame. See damage.rs.
+    pub(crate) frame: u64,
+    pub(crate) drawn_at: Option<u64>,
+    pub(crate) history: VecDeque<Option<Vec<Rect>>>,
+    //What the next present damages, None for everything.
+    pub(crate) frame_damage: Option<Vec<Rect>>,
+}
+
+impl Default for SwapchainState {
+    fn default() -> Self {
+        SwapchainState {
+            config: SwapchainConfig::default(),
+            spares: Vec::new(),
+            target: MIN_BUFFERS,
+            recent: VecDeque::new(),
+            contended: Instant::now(),
+            stalls: 0,
+            frame: 0,
+            drawn_at: None,
+            history: VecDeque::new(),
+            frame_damage: None,
+        }
+    }
+}
+
+impl SwapchainState {
+    pub(crate) fn for_reconnect(&self) -> SwapchainState {
+        SwapchainState {
+            config: self.config,
+            ..SwapchainState::default()
+        }
+    }
+
+    //The window's buffer and the spares.
+    pub(crate) fn buffers(&self) -> usize {
+        1 + self.spares.len()
+    }
+
+    //A draw found every buffer busy.
+    fn stalled(&mut self, now: Instant) {
+        self.stalls += 1;
+        self.contended = now;
+        self.recent.push_back(now);
+        while let Some(&first) = self.recent.front()
+            && now.duration_since(first) > Duration::from_secs(1)
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() > self.config.grow_after as usize
+            && self.target < self.config.max_buffers.max(MIN_BUFFERS)
+        {
+            self.target += 1;
+            self.recent.clear();
+            log::debug!("buffers released late, going to {}", self.target);
+        }
+    }
+
+    //One buffer less wanted when there was no wait for long enough, or the maximum went down.
+    fn shrink(&mut self, now: Instant) {
+        let max = self.config.max_buffers.max(MIN_BUFFERS);
+        if self.target > max {
+            self.target = max;
+        } else if self.target > MIN_BUFFERS
+            && now.duration_since(self.contended) >= self.config.shrink_after
+        {
+            self.target -= 1;
+            self.contended = now;
+            log::debug!("no late releases, going back to {}", self.target);
+        }
+    }
+
+    //Spares beyond the target that nobody reads, destroyed. Busy ones go with their release.
+    fn trim(&mut self) {
+        while self.buffers() > self.target {
+            let Some(index) = self
+                .spares
+                .iter()
+                .position(|spare| !spare.pixels.is_busy(0))
+            else {
+                return;
+            };
+            self.spares.swap_remove(index).buffer.destroy();
+        }
+    }
+}
+
+impl AppState {
+    //The window's buffer was attached: no drawing into it until it's released.
+    pub(crate) fn main_buffer_attached(&mut self) {
+        if let Some((pixels, layout)) = self.shm_pixels.as_mut() {
+            pixels.attached(0, layout.len());
+        }
+    }
+
+    //Whether the compositor may be reading the window's buffer.
+    fn main_buffer_busy(&self) -> bool {
+        self.shm_pixels
+            .as_ref()
+            .is_some_and(|(pixels, _)| pixels.is_busy(0))
+    }
+
+    //wait_timeout went by without a release: the window's buffer is drawn into anyway.
+    fn reclaim_main_buffer(&mut self) {
+        if let Some((pixels, _)) = self.shm_pixels.as_mut() {
+            pixels.released(0);
+        }
+    }
+
+    //wl_buffer.release of the window's buffer or a spare. False for other buffers.
+    pub(crate) fn swapchain_released(&mut self, buffer: &WlBuffer) -> bool {
+        if self.buffer.as_ref() == Some(buffer) {
+            if let Some((pixels, _)) = self.shm_pixels.as_mut() {
+                pixels.released(0);
+            }
+            return true;
+        }
+        let swapchain = &mut self.swapchain;
+        let Some(spare) = swapchain.spares.iter_mut().find(|s| s.buffer == *buffer) else {
+            return false;
+        };
+        spare.pixels.released(0);
+        swapchain.trim();
+        true
+    }
+
+    //With the window's buffer: a new size, or released while hidden. Destroying spares the
+    //compositor still reads is fine, their storage is unmapped and never re-used.
+    pub(crate) fn drop_spare_buffers(&mut self) {
+        for spare in self.swapchain.spares.drain(..) {
+            spare.buffer.destroy();
+        }
+        self.swapchain.drawn_at = None;
+    }
+
+    //Whether what the compositor shows is the window's buffer or a spare, both with what was
+    //drawn last (a spare shows when the frame drawn after it was skipped as identical).
+    pub(crate) fn main_buffer_shown(&self) -> bool {
+        self.shown_pixels().is_some()
+    }
+
+    //The memory of the buffer on screen, when it's one of ours. Same layout as shm_pixels.
+    pub(crate) fn shown_pixels(&self) -> Option<&MappedPool> {
+        let attached = self.attached.as_ref()?;
+        if self.buffer.as_ref() == Some(attached) {
+            return self.shm_pixels.as_ref().map(|(pixels, _)| pixels);
+        }
+        self.swapchain
+            .spares
+            .iter()
+            .find(|spare| spare.buffer == *attached)
+            .map(|spare| &spare.pixels)
+    }
+
+    //Copies `rects` of the buffer on screen into the window's buffer, see rows.rs. False when
+    //what's on screen isn't one of ours.
+    pub(crate) fn copy_from_shown(&mut self, rects: &[Rect]) -> bool {
+        let (Some(attached), Some((pixels, layout))) =
+            (self.attached.as_ref(), self.shm_pixels.as_mut())
+        else {
+            return false;
+        };
+        if self.buffer.as_ref() == Some(attached) {
+            return true;
+        }
+        let Some(spare) = self.swapchain.spares.iter().find(|s| s.buffer == *attached) else {
+            return false;
+        };
+        //The window's buffer was made free to draw into first.
+        let Some(mut target) = pixels.whole_mut() else {
+            return false;
+        };
+        let source = spare.pixels.bytes();
+        let stride = layout.stride as usize;
+        for rect in rects {
+            for y in rect.y..rect.y + rect.height {
+                let start = y as usize * stride + rect.x as usize * 4;
+                let end = start + rect.width as usize * 4;
+                target[start..end].copy_from_slice(&source[start..end]);
+            }
+        }
+        true
+    }
+
+    //Makes the window's buffer free to draw into, if it can without waiting: a free spare takes
+    //its place, or a new one when there are fewer buffers than wanted. False when they're all
+    //busy.
+    pub(crate) fn next_free_buffer(&mut self, queue_handle: &QueueHandle<AppState>) -> bool {
+        let Some((_, layout)) = self.shm_pixels.as_ref() else {
+            return true;
+        };
+        let layout = *layout;
+        self.swapchain.shrink(Instant::now());
+        self.swapchain.trim();
+        if !self.main_buffer_busy() {
+            return true;
+        }
+        let spares = &self.swapchain.spares;
+        let index = match spares.iter().position(|spare| !spare.pixels.is_busy(0)) {
+            Some(index) => index,
+            None if self.swapchain.buffers() < self.swapchain.target => {
+                let Some((buffer, pixels)) =
+                    self.new_shm_buffer(layout, None, "spare buffer", queue_handle)
+                else {
+                    return false;
+                };
+                self.swapchain.spares.push(Spare {
+                    buffer,
+                    pixels,
+                    drawn_at: None,
+                });
+                self.swapchain.spares.len() - 1
+            }
+            None => return false,
+        };
+        let spare = &mut self.swapchain.spares[index];
+        std::mem::swap(self.buffer.as_mut().unwrap(), &mut spare.buffer);
+        std::mem::swap(&mut self.shm_pixels.as_mut().unwrap().0, &mut spare.pixels);
+        std::mem::swap(&mut self.swapchain.drawn_at, &mut spare.drawn_at);
+        true
+    }
+
+    //For the render thread's frames, which are copied whatever happens.
+    pub(crate) fn next_buffer_without_waiting(&mut self, queue_handle: &QueueHandle<AppState>) {
+        if !self.next_free_buffer(queue_handle) {
+            self.swapchain.stalled(Instant::now());
+            //Growing makes a buffer right away.
+            if !self.next_free_buffer(queue_handle) {
+                self.reclaim_main_buffer();
+            }
+        }
+    }
+}
+
+impl Window {
+    //Before drawing into the window's buffer, see swapchain.rs.
+    pub(crate) fn wait_for_free_buffer(&mut self) {
+        let queue_handle = self.event_queue.handle();
+        if self.state.next_free_buffer(&queue_handle) {
+            return;
+        }
+        self.state.swapchain.stalled(Instant::now());
+        let deadline = Instant::now() + self.state.swapchain.config.wait_timeout;
+        loop {
+            self.dispatch_queued();
+            if self.state.next_free_buffer(&queue_handle) {
+                return;
+            }
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            if self.state.connection_lost {
+                break;
+            }
+            self.send_requests();
+            if let Some(guard) = self.event_queue.prepare_read() {
+                let mut fds = [PollFd::from_borrowed_fd(
+                    guard.connection_fd(),
+                    self.connection_interest(),
+                )];
+                let timeout = Timespec::try_from(left).ok();
+                //EINTR (a signal) is just an early wakeup.
+                let _ = poll(&mut fds, timeout.as_ref());
+                if !fds[0].revents().is_empty() {
+                    let _ = guard.read();
+                }
+            }
+        }
+        log::debug!("no buffer released in time, drawing into one the compositor may read");
+        self.state.reclaim_main_buffer();
+    }
+
+    pub fn set_swapchain_config(&mut self, config: SwapchainConfig) {
+        self.state.swapchain.config = config;
+    }
+
+    pub fn swapchain_config(&self) -> SwapchainConfig {
+        self.state.swapchain.config
+    }
+}
+
+//wl_buffer.release (quoted at the top), for the window's buffer and its spares. Pool buffers
+//given back while attached wait for it too, see buffer_allocator.rs.
+impl Dispatch<WlBuffer, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        buffer: &WlBuffer,
+        event: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            protocol_log!("{} released", buffer.id());
+            state.buffer_released(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_grow_and_quiet_shrinks() {
+        let mut state = SwapchainState::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        //Two waits in a second are still fine, the third isn't.
+        state.stalled(at(0));
+        state.stalled(at(400));
+        assert_eq!(state.target, 2);
+        state.stalled(at(800));
+        assert_eq!(state.target, 3);
+        //Spread out, they never add up.
+        for ms in [2000, 3100, 4200, 5300] {
+            state.stalled(at(ms));
+        }
+        assert_eq!((state.target, state.stalls), (3, 7));
+
+        state.shrink(at(5300 + 4999));
+        assert_eq!(state.target, 3);
+        state.shrink(at(5300 + 5000));
+        assert_eq!(state.target, 2);
+        //Never below two.
+        state.shrink(at(60_000));
+        assert_eq!(state.target, 2);
+    }
+
+    #[test]
+    fn a_lower_maximum_applies_right_away() {
+        let mut state = SwapchainState::default();
+        state.config.max_buffers = 4;
+        let now = Instant::now();
+        for _ in 0..6 {
+            state.stalled(now);
+        }
+        assert_eq!(state.target, 4);
+        state.config.max_buffers = 0;
+        state.shrink(now);
+        assert_eq!(state.target, MIN_BUFFERS);
+    }
+}
@@ -0,0 +1,568 @@
+//A Window for the application's own tests, without a compositor. It is a real Window, connected
+//through a socket pair to a small compositor living in the same TestWindow: configures, keys and
+//pointer input go out as protocol events, and the window takes them through the same Dispatch
+//impls, acks, lifecycle checks and buffer handling as on a desktop. Only the transport differs.
+//
+//Everything runs on the calling thread. Injected input waits in the socket until
+//TestWindow::dispatch, which goes back and forth between the two sides until both are quiet and
+//hands out the window's events. Every commit with an shm buffer is read back into a
+//CapturedFrame, straight alpha RGBA like Window::capture_to_vec.
+//
+//The compositor advertises wl_compositor, wl_shm, a seat with a pointer and a keyboard, and
+//xdg_wm_base. Everything else is missing, as on a minimal compositor. TestWindow derefs to the
+//Window, drawing and the rest of the API are the window's own.
+
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    ops::{Deref, DerefMut},
+    os::{
+        fd::{OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+    sync::Arc,
+};
+
+use wayland_backend::{
+    protocol::{Argument, Message},
+    server::{
+        Backend, ClientData, ClientId, GlobalHandler, GlobalId, Handle, ObjectData, ObjectId,
+    },
+};
+use wayland_client::{
+    Connection, Proxy,
+    protocol::{wl_compositor::WlCompositor, wl_seat::WlSeat, wl_shm::WlShm},
+};
+use wayland_protocols::xdg::shell::client::{xdg_toplevel, xdg_wm_base::XdgWmBase};
+
+use crate::{BufferFormat, Key, KeyState, Window, WindowEvent, WindowOptions, capture::to_rgba};
+
+//Back and forth at most this many times per dispatch, a client that keeps sending is a bug.
+const ROUNDS: usize = 16;
+
+//The seat's name, as in WindowEvent::Key and friends.
+pub const TEST_SEAT: &str = "test-seat";
+
+//One commit with an shm buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    //Row by row, R G B A with straight alpha.
+    pub rgba: Vec<u8>,
+}
+
+struct ShmBuffer {
+    fd: Arc<OwnedFd>,
+    offset: u64,
+    width: u32,
+    height: u32,
+    stride: u32,
+    format: BufferFormat,
+}
+
+impl ShmBuffer {
+    fn read(&self) -> Option<CapturedFrame> {
+        let row_len = self.width as usize * 4;
+        let mut rgba = Vec::with_capacity(row_len * self.height as usize);
+        let mut row = vec![0; row_len];
+        for y in 0..u64::from(self.height) {
+            let offset = self.offset + y * u64::from(self.stride);
+            if rustix::io::pread(&*self.fd, &mut row[..], offset).ok()? != row_len {
+                return None;
+            }
+            rgba.extend(to_rgba(&row, self.format));
+        }
+        Some(CapturedFrame {
+            width: self.width,
+            height: self.height,
+            rgba,
+        })
+    }
+}
+
+//The compositor's side.
+#[derive(Default)]
+struct Server {
+    //The newest object of each interface, where events go.
+    objects: HashMap<&'static str, ObjectId>,
+    //The surface with the xdg_surface role, cursor surfaces aside.
+    window_surface: Option<ObjectId>,
+    pools: HashMap<ObjectId, Arc<OwnedFd>>,
+    buffers: HashMap<ObjectId, ShmBuffer>,
+    //Attached since the last commit: Some(None) for a null buffer.
+    pending_buffer: Option<Option<ObjectId>>,
+    committed_buffer: Option<ObjectId>,
+    pending_callbacks: Vec<ObjectId>,
+    callbacks: Vec<ObjectId>,
+    frames: Vec<CapturedFrame>,
+    last_ack: Option<u32>,
+}
+
+impl Server {
+    fn request(&mut self, handle: &Handle, message: Message<ObjectId, OwnedFd>) {
+        let interface = message.sender_id.interface().name;
+        let name = message.sender_id.interface().requests[message.opcode as usize].name;
+        let mut args = message.args.into_iter();
+        match (interface, name) {
+            ("wl_shm", "create_pool") => {
+                if let (Some(Argument::NewId(pool)), Some(Argument::Fd(fd))) =
+                    (args.next(), args.next())
+                {
+                    self.pools.insert(pool, Arc::new(fd));
+                }
+            }
+            ("wl_shm_pool", "create_buffer") => {
+                let args: Vec<_> = args.collect();
+                if let (
+                    [
+                        Argument::NewId(buffer),
+                        Argument::Int(offset),
+                        Argument::Int(width),
+                        Argument::Int(height),
+                        Argument::Int(stride),
+                        Argument::Uint(format),
+                    ],
+                    Some(fd),
+                ) = (&args[..], self.pools.get(&message.sender_id))
+                {
+                    //wl_shm.format: 0 is argb8888, 1 xrgb8888.
+                    let format = if *format == 1 {
+                        BufferFormat::Xrgb8888
+                    } else {
+                        BufferFormat::Argb8888
+                    };
+                    self.buffers.insert(
+                        buffer.clone(),
+                        ShmBuffer {
+                            fd: fd.clone(),
+                            offset: *offset as u64,
+                            width: *width as u32,
+                            height: *height as u32,
+                            stride: *stride as u32,
+                            format,
+                        },
+                    );
+                }
+            }
+            ("wl_shm_pool", "destroy") => {
+                self.pools.remove(&message.sender_id);
+            }
+            ("wl_buffer", "destroy") => {
+                self.buffers.remove(&message.sender_id);
+            }
+            ("xdg_wm_base", "get_xdg_surface") => {
+                if let (Some(Argument::NewId(_)), Some(Argument::Object(surface))) =
+                    (args.next(), args.next())
+                {
+                    self.window_surface = Some(surface);
+                }
+            }
+            ("xdg_surface", "ack_configure") => {
+                if let Some(Argument::Uint(serial)) = args.next() {
+                    self.last_ack = Some(serial);
+                }
+            }
+            _ if Some(&message.sender_id) != self.window_surface.as_ref() => {}
+            ("wl_surface", "attach") => {
+                if let Some(Argument::Object(buffer)) = args.next() {
+                    self.pending_buffer = Some((!buffer.is_null()).then_some(buffer));
+                }
+            }
+            ("wl_surface", "frame") => {
+                if let Some(Argument::NewId(callback)) = args.next() {
+                    self.pending_callbacks.push(callback);
+                }
+            }
+            ("wl_surface", "commit") => self.commit(handle),
+            _ => {}
+        }
+    }
+
+    fn commit(&mut self, handle: &Handle) {
+        self.callbacks.append(&mut self.pending_callbacks);
+        let Some(attached) = self.pending_buffer.take() else {
+            return;
+        };
+        //Read right away, so the previous one can go back to the client.
+        if let Some(previous) = self.committed_buffer.take()
+            && Some(&previous) != attached.as_ref()
+        {
+            let _ = send(handle, &previous, 0, vec![]);
+        }
+        if let Some(frame) = attached
+            .as_ref()
+            .and_then(|buffer| self.buffers.get(buffer))
+            .and_then(ShmBuffer::read)
+        {
+            self.frames.push(frame);
+        }
+        self.committed_buffer = attached;
+    }
+}
+
+fn send(
+    handle: &Handle,
+    object: &ObjectId,
+    opcode: u16,
+    args: Vec<Argument<ObjectId, RawFd>>,
+) -> Option<()> {
+    handle
+        .send_event(Message {
+            sender_id: object.clone(),
+            opcode,
+            args: args.into_iter().collect(),
+        })
+        .ok()
+}
+
+//wl_fixed_t, 24.8 fixed point.
+fn fixed(value: f64) -> Argument<ObjectId, RawFd> {
+    Argument::Fixed((value * 256.0).round() as i32)
+}
+
+struct Client;
+
+impl ClientData for Client {}
+
+struct Global;
+
+impl GlobalHandler<Server> for Global {
+    fn bind(
+        self: Arc<Self>,
+        handle: &Handle,
+        server: &mut Server,
+        _: ClientId,
+        _: GlobalId,
+        object: ObjectId,
+    ) -> Arc<dyn ObjectData<Server>> {
+        //Pointer and keyboard, and the name.
+        if object.interface().name == "wl_seat" {
+            let name = CString::new(TEST_SEAT).unwrap();
+            send(handle, &object, 0, vec![Argument::Uint(3)]);
+            send(
+                handle,
+                &object,
+                1,
+                vec![Argument::Str(Some(Box::new(name)))],
+            );
+        }
+        server.objects.insert(object.interface().name, object);
+        Arc::new(Objects)
+    }
+}
+
+//The data of every object: hands the requests to Server and keeps the objects created.
+struct Objects;
+
+impl ObjectData<Server> for Objects {
+    fn request(
+        self: Arc<Self>,
+        handle: &Handle,
+        server: &mut Server,
+        _: ClientId,
+        message: Message<ObjectId, OwnedFd>,
+    ) -> Option<Arc<dyn ObjectData<Server>>> {
+        let mut created = false;
+        for argument in &message.args {
+            if let Argument::NewId(id) = argument {
+                server.objects.insert(id.interface().name, id.clone());
+                created = true;
+            }
+        }
+        server.request(handle, message);
+        created.then_some(self as Arc<dyn ObjectData<Server>>)
+    }
+
+    fn destroyed(self: Arc<Self>, _: &Handle, _: &mut Server, _: ClientId, _: ObjectId) {}
+}
+
+pub struct TestWindow {
+    window: Window,
+    backend: Backend<Server>,
+    server: Server,
+    serial: u32,
+    keyboard_focus: bool,
+    pointer_focus: bool,
+}
+
+impl TestWindow {
+    //The window with its initial commit done, waiting for inject_configure.
+    pub fn new(options: WindowOptions) -> TestWindow {
+        let backend = Backend::<Server>::new().unwrap();
+        let mut handle = backend.handle();
+        for (interface, version) in [
+            (WlCompositor::interface(), 6),
+            (WlShm::interface(), 1),
+            (WlSeat::interface(), 7),
+            (XdgWmBase::interface(), 6),
+        ] {
+            handle.create_global::<Server>(interface, version, Arc::new(Global));
+        }
+        let (client, server) = UnixStream::pair().unwrap();
+        handle.insert_client(server, Arc::new(Client)).unwrap();
+        let connection = Connection::from_socket(client).unwrap();
+
+        let mut test_window = TestWindow {
+            window: Window::with_connection(connection, options),
+            backend,
+            server: Server::default(),
+            serial: 0,
+            keyboard_focus: false,
+            pointer_focus: false,
+        };
+        test_window.dispatch();
+        test_window
+    }
+
+    //Both sides take what the other sent, until neither has anything left. The window's events
+    //of all of it, in order.
+    pub fn dispatch(&mut self) -> Vec<WindowEvent> {
+        let mut events = Vec::new();
+        for round in 0..ROUNDS {
+            let _ = self.window.flush();
+            let dispatched = self.backend.dispatch_all_clients(&mut self.server);
+            let _ = self.backend.flush(None);
+            events.extend(self.window.poll_events());
+            //The first round only brings the window what was injected, its answers come with the
+            //second.
+            if round > 0 && matches!(dispatched, Ok(0)) {
+                break;
+            }
+        }
+        events
+    }
+
+    //An event for the application as it is, with the next dispatch. For what has no protocol
+    //event behind it (user events...): a Resized injected this way changes no buffer,
+    //inject_configure does.
+    pub fn inject(&mut self, event: WindowEvent) {
+        self.window.state.events.push(event);
+    }
+
+    //An xdg_toplevel configure with its xdg_surface configure. 0 leaves the size to the window.
+    //Returns the serial to find in last_ack.
+    pub fn inject_configure(
+        &mut self,
+        width: i32,
+        height: i32,
+        states: &[xdg_toplevel::State],
+    ) -> u32 {
+        let states: Vec<u8> = states
+            .iter()
+            .flat_map(|&state| (state as u32).to_ne_bytes())
+            .collect();
+        self.send(
+            "xdg_toplevel",
+            0,
+            vec![
+                Argument::Int(width),
+                Argument::Int(height),
+                Argument::Array(Box::new(states)),
+            ],
+        );
+        let serial = self.next_serial();
+        self.send("xdg_surface", 0, vec![Argument::Uint(serial)]);
+        serial
+    }
+
+    //The compositor asking the window to close.
+    pub fn inject_close(&mut self) {
+        self.send("xdg_toplevel", 1, vec![]);
+    }
+
+    //A key on the seat's keyboard, which gets the focus first if it hasn't it.
+    pub fn inject_key(&mut self, key: Key, state: KeyState) {
+        if !std::mem::replace(&mut self.keyboard_focus, true) {
+            let (serial, surface) = (self.next_serial(), self.surface());
+            self.send(
+                "wl_keyboard",
+                1,
+                vec![
+                    Argument::Uint(serial),
+                    Argument::Object(surface),
+                    Argument::Array(Box::default()),
+                ],
+            );
+        }
+        let serial = self.next_serial();
+        self.send(
+            "wl_keyboard",
+            3,
+            vec![
+                Argument::Uint(serial),
+                Argument::Uint(0),
+                Argument::Uint(key.evdev()),
+                Argument::Uint(state.is_pressed() as u32),
+            ],
+        );
+    }
+
+    //The pointer to surface-local x, y. It enters there the first time.
+    pub fn inject_pointer_motion(&mut self, x: f64, y: f64) {
+        if std::mem::replace(&mut self.pointer_focus, true) {
+            self.send("wl_pointer", 2, vec![Argument::Uint(0), fixed(x), fixed(y)]);
+        } else {
+            let (serial, surface) = (self.next_serial(), self.surface());
+            self.send(
+                "wl_pointer",
+                0,
+                vec![
+                    Argument::Uint(serial),
+                    Argument::Object(surface),
+                    fixed(x),
+                    fixed(y),
+                ],
+            );
+        }
+        self.send("wl_pointer", 5, vec![]);
+    }
+
+    //A button (evdev code, 0x110 is the left one) where the pointer is.
+    pub fn inject_button(&mut self, button: u32, state: KeyState) {
+        let serial = self.next_serial();
+        self.send(
+            "wl_pointer",
+            3,
+            vec![
+                Argument::Uint(serial),
+                Argument::Uint(0),
+                Argument::Uint(button),
+                Argument::Uint(state.is_pressed() as u32),
+            ],
+        );
+        self.send("wl_pointer", 5, vec![]);
+    }
+
+    //Answers the frame callbacks of the commits so far, as the compositor does once it showed
+    //them.
+    pub fn frame_done(&mut self, time: u32) {
+        for callback in std::mem::take(&mut self.server.callbacks) {
+            send(
+                &self.backend.handle(),
+                &callback,
+                0,
+                vec![Argument::Uint(time)],
+            );
+        }
+    }
+
+    //Every commit with an shm buffer so far, oldest first.
+    pub fn frames(&self) -> &[CapturedFrame] {
+        &self.server.frames
+    }
+
+    pub fn take_frames(&mut self) -> Vec<CapturedFrame> {
+        std::mem::take(&mut self.server.frames)
+    }
+
+    //The newest configure serial the window acked.
+    pub fn last_ack(&self) -> Option<u32> {
+        self.server.last_ack
+    }
+
+    fn next_serial(&mut self) -> u32 {
+        self.serial += 1;
+        self.serial
+    }
+
+    fn surface(&self) -> ObjectId {
+        self.server
+            .window_surface
+            .clone()
+            .expect("no window surface yet")
+    }
+
+    fn send(&mut self, interface: &str, opcode: u16, args: Vec<Argument<ObjectId, RawFd>>) {
+        let object = match self.server.objects.get(interface) {
+            Some(object) => object.clone(),
+            None => panic!("the window has no {interface}"),
+        };
+        send(&self.backend.handle(), &object, opcode, args);
+    }
+}
+
+impl Deref for TestWindow {
+    type Target = Window;
+
+    fn deref(&self) -> &Window {
+        &self.window
+    }
+}
+
+impl DerefMut for TestWindow {
+    fn deref_mut(&mut self) -> &mut Window {
+        &mut self.window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Canvas, Color};
+
+    #[test]
+    fn configures_are_acked_and_frames_captured() {
+        let mut window = TestWindow::new(WindowOptions::default());
+        assert!(!window.is_configured());
+        let serial = window.inject_configure(40, 30, &[xdg_toplevel::State::Activated]);
+        let events = window.dispatch();
+        assert!(events.contains(&WindowEvent::Resized {
+            width: 40,
+            height: 30
+        }));
+        assert_eq!(window.last_ack(), Some(serial));
+
+        window.take_frames();
+        window.draw(|canvas: &mut Canvas| canvas.clear(Color::opaque(0xFF, 0, 0)));
+        window.dispatch();
+        let frames = window.take_frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].width, frames[0].height), (40, 30));
+        assert_eq!(frames[0].rgba[..4], [0xFF, 0, 0, 0xFF]);
+
+        window.inject_close();
+        window.dispatch();
+        assert!(!window.is_running());
+    }
+
+    #[test]
+    fn input_goes_through_the_protocol() {
+        let mut window = TestWindow::new(WindowOptions::default());
+        window.inject_configure(0, 0, &[]);
+        window.dispatch();
+
+        window.inject_key(Key::A, KeyState::Pressed);
+        window.inject_pointer_motion(5.0, 6.0);
+        window.inject_button(0x110, KeyState::Pressed);
+        window.inject(WindowEvent::Activated);
+        let events = window.dispatch();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            WindowEvent::Key {
+                key: Key::A,
+                state: KeyState::Pressed,
+                ..
+            }
+        )));
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, WindowEvent::PointerEntered { x: 5.0, y: 6.0, .. }))
+        );
+        assert!(events.iter().any(|event| matches!(
+            event,
+            WindowEvent::PointerButton {
+                button: 0x110,
+                pressed: true,
+                ..
+            }
+        )));
+        assert!(events.contains(&WindowEvent::Activated));
+
+        //Through the key bindings as well, Escape quits by default.
+        window.inject_key(Key::Escape, KeyState::Pressed);
+        window.dispatch();
+        assert!(!window.is_running());
+    }
+}
@@ -0,0 +1,460 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    process::Command,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use fontdue::FontSettings;
+
+use crate::{Canvas, Color, WindowError};
+
+//A font to draw text with. Cheap to clone, clones share the parsed file and the glyph cache.
+#[derive(Clone)]
+pub struct Font {
+    //Tells fonts apart in the glyph cache, which outlives any one Font value.
+    id: u64,
+    font: Arc<fontdue::Font>,
+}
+
+//0 is the built in font.
+static NEXT_FONT_ID: AtomicU64 = AtomicU64::new(1);
+
+//DejaVu Sans Mono, license in fonts/LICENSE-DejaVu: for when the system has no font to offer.
+const FALLBACK_FONT: &[u8] = include_bytes!("../fonts/DejaVuSansMono.ttf");
+
+//Sans fonts found on most distributions, in case fontconfig isn't there to ask.
+const SYSTEM_FONTS: [&str; 8] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu-sans-fonts/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/liberation-sans/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+];
+
+fn parse(data: &[u8]) -> Result<fontdue::Font, WindowError> {
+    fontdue::Font::from_bytes(data, FontSettings::default())
+        .map_err(|reason| WindowError::Font(reason.to_string()))
+}
+
+impl Font {
+    //A .ttf or .otf, or the first font of a collection.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Font, WindowError> {
+        Ok(Font {
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
+            font: Arc::new(parse(&data)?),
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Font, WindowError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .map_err(|err| WindowError::Font(format!("{}: {err}", path.display())))?;
+        Font::from_bytes(data).map_err(|err| match err {
+            WindowError::Font(reason) => WindowError::Font(format!("{}: {reason}", path.display())),
+            err => err,
+        })
+    }
+
+    //The built in DejaVu Sans Mono: not the desktop's font, but always there.
+    pub fn fallback() -> Font {
+        static FALLBACK: OnceLock<Font> = OnceLock::new();
+        FALLBACK
+            .get_or_init(|| Font {
+                id: 0,
+                font: Arc::new(parse(FALLBACK_FONT).expect("the built in font parses")),
+            })
+            .clone()
+    }
+
+    //The desktop's sans font: what fontconfig picks for sans-serif, else a few well known paths,
+    //else the fallback. Looked up once.
+    pub fn system() -> Font {
+        static SYSTEM: OnceLock<Font> = OnceLock::new();
+        SYSTEM
+            .get_or_init(|| {
+                let fontconfig = Command::new("fc-match")
+                    .args(["--format=%{file}", "sans-serif"])
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())
+                    .and_then(|output| String::from_utf8(output.stdout).ok());
+                for path in fontconfig.iter().map(String::as_str).chain(SYSTEM_FONTS) {
+                    match Font::from_file(path) {
+                        Ok(font) => return font,
+                        Err(err) => log::debug!("{err}"),
+                    }
+                }
+                log::warn!("no usable system font, drawing text with the built in one");
+                Font::fallback()
+            })
+            .clone()
+    }
+}
+
+//One rasterized glyph: its coverage in the atlas, and where it goes relative to the pen.
+struct Glyph {
+    offset: usize,
+    width: usize,
+    height: usize,
+    left: i32,
+    top: i32,
+    advance: f32,
+}
+
+//The glyphs of one font at one size, rasterized by fontdue the first time they're drawn. Their
+//coverage masks are packed one after the other in one buffer.
+struct Atlas {
+    coverage: Vec<u8>,
+    glyphs: HashMap<char, Glyph>,
+    ascent: f32,
+    line_height: f32,
+}
+
+//Sizes are rarely more than a handful (title, overlay), but an animated size would make a new
+//atlas every frame: past this many, they're all dropped and rebuilt as needed.
+const MAX_ATLASES: usize = 32;
+
+//A glyph's mask is about size squared bytes, kept in the atlas: more than enough for text, not
+//enough for an accidental f32::MAX to eat the memory.
+const MAX_SIZE: f32 = 1024.0;
+
+//Shared by every canvas, so a glyph is rasterized once and not once per frame.
+fn atlases() -> &'static Mutex<HashMap<(u64, u32), Atlas>> {
+    static ATLASES: OnceLock<Mutex<HashMap<(u64, u32), Atlas>>> = OnceLock::new();
+    ATLASES.get_or_init(Default::default)
+}
+
+impl Atlas {
+    fn new(font: &fontdue::Font, size: f32) -> Atlas {
+        //Fonts with vertical metrics only: the em box then.
+        let (ascent, line_height) = font
+            .horizontal_line_metrics(size)
+            .map_or((size, size), |metrics| {
+                (metrics.ascent, metrics.new_line_size)
+            });
+        Atlas {
+            coverage: Vec::new(),
+            glyphs: HashMap::new(),
+            ascent,
+            line_height,
+        }
+    }
+
+    fn glyph(&mut self, font: &fontdue::Font, size: f32, c: char) -> &Glyph {
+        if !self.glyphs.contains_key(&c) {
+            let (metrics, coverage) = font.rasterize(c, size);
+            let offset = self.coverage.len();
+            self.coverage.extend(coverage);
+            //fontdue's ymin is the bottom edge, up from the baseline; top is down from it.
+            let glyph = Glyph {
+                offset,
+                width: metrics.width,
+                height: metrics.height,
+                left: metrics.xmin,
+                top: -(metrics.ymin + metrics.height as i32),
+                advance: metrics.advance_width,
+            };
+            self.glyphs.insert(c, glyph);
+        }
+        &self.glyphs[&c]
+    }
+}
+
+//Rounded x * y / 255.
+fn multiply(x: u8, y: u8) -> u8 {
+    ((u16::from(x) * u16::from(y) + 127) / 255) as u8
+}
+
+impl Canvas<'_> {
+    //`text` in the system font (see Font::system), `size` pixels per em, top left corner of the
+    //first line at (x, y). '\n' starts a new line under it, back at x. Glyphs are blended over
+    //what's there with their antialiased coverage, and cut off at the canvas edges. Sizes outside
+    //(0, 1024] draw nothing.
+    pub fn draw_text(&mut self, text: &str, x: i32, y: i32, size: f32, color: Color) {
+        self.draw_text_with_font(&Font::system(), text, x, y, size, color);
+    }
+
+    pub fn draw_text_with_font(
+        &mut self,
+        font: &Font,
+        text: &str,
+        x: i32,
+        y: i32,
+        size: f32,
+        color: Color,
+    ) {
+        if size.is_nan() || size <= 0.0 || size > MAX_SIZE {
+            return;
+        }
+        let mut atlases = atlases().lock().unwrap();
+        if atlases.len() >= MAX_ATLASES && !atlases.contains_key(&(font.id, size.to_bits())) {
+            atlases.clear();
+        }
+        let atlas = atlases
+            .entry((font.id, size.to_bits()))
+            .or_insert_with(|| Atlas::new(&font.font, size));
+
+        let (mut pen_x, mut baseline) = (x as f32, y as f32 + atlas.ascent.round());
+        for c in text.chars() {
+            if c == '\n' {
+                pen_x = x as f32;
+                baseline += atlas.line_height.round();
+                continue;
+            }
+            if c.is_control() {
+                continue;
+            }
+            let glyph = atlas.glyph(&font.font, size, c);
+            let (left, top) = (
+                pen_x.round() as i64 + i64::from(glyph.left),
+                baseline as i64 + i64::from(glyph.top),
+            );
+            let (offset, width, height, advance) =
+                (glyph.offset, glyph.width, glyph.height, glyph.advance);
+            let mask = &atlas.coverage[offset..offset + width * height];
+            self.blend_mask(mask, width, left, top, color);
+            pen_x += advance;
+        }
+    }
+
+    //Premultiplied "over": the color scaled by coverage, plus what's there scaled by what the
+    //color leaves uncovered.
+    fn blend_mask(&mut self, mask: &[u8], width: usize, left: i64, top: i64, color: Color) {
+        if width == 0 {
+            return;
+        }
+        let [b, g, r, a] = color.to_argb8888_bytes();
+        for (mask_y, mask_row) in mask.chunks_exact(width).enumerate() {
+            let y = top + mask_y as i64;
+            if y < 0 || y >= i64::from(self.height()) {
+                continue;
+            }
+            let canvas_width = i64::from(self.width());
+            let row = self.row_mut(y as u32).unwrap();
+            for (mask_x, &coverage) in mask_row.iter().enumerate() {
+                let x = left + mask_x as i64;
+                if coverage == 0 || x < 0 || x >= canvas_width {
+                    continue;
+                }
+                let source = [b, g, r, a].map(|channel| multiply(channel, coverage));
+                let pixel = &mut row[x as usize * 4..x as usize * 4 + 4];
+                let keep = 255 - source[3];
+                for (destination, source) in pixel.iter_mut().zip(source) {
+                    *destination = source.saturating_add(multiply(*destination, keep));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A minimal font: 1000 units per em, glyph 1 a 500x500 square on the baseline mapped to 'A',
+    //glyph 2 that square moved by a composite, mapped to 'B'.
+    fn square_font() -> Vec<u8> {
+        fn be16(out: &mut Vec<u8>, value: u16) {
+            out.extend(value.to_be_bytes());
+        }
+
+        //Versions 1.0, maxp's 0.5: the one without TrueType limits.
+        let mut head = vec![0; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut maxp = vec![0; 6];
+        maxp[0..4].copy_from_slice(&0x0000_5000u32.to_be_bytes());
+        maxp[4..6].copy_from_slice(&3u16.to_be_bytes());
+        let mut hhea = vec![0; 36];
+        hhea[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[34..36].copy_from_slice(&3u16.to_be_bytes());
+        let mut hmtx = Vec::new();
+        for _ in 0..3 {
+            be16(&mut hmtx, 600);
+            be16(&mut hmtx, 0);
+        }
+
+        //One contour of four on curve points, coordinates as words.
+        let mut square = Vec::new();
+        for value in [1u16, 0, 0, 500, 500, 3, 0] {
+            be16(&mut square, value);
+        }
+        square.extend([0x01; 4]);
+        for delta in [0i16, 500, 0, -500] {
+            square.extend(delta.to_be_bytes());
+        }
+        for delta in [0i16, 0, 500, 0] {
+            square.extend(delta.to_be_bytes());
+        }
+        let mut composite = Vec::new();
+        for value in [0xFFFFu16, 0, 0, 0, 0] {
+            be16(&mut composite, value);
+        }
+        //Words, xy values, glyph 1 moved right by 100.
+        for value in [0x0003u16, 1, 100, 0] {
+            be16(&mut composite, value);
+        }
+        let glyf = [square.clone(), composite.clone()].concat();
+        let mut loca = Vec::new();
+        for offset in [0, 0, square.len(), glyf.len()] {
+            be16(&mut loca, (offset / 2) as u16);
+        }
+
+        //Format 4: 'A'..'B' to glyphs 1..2 by delta, and the closing 0xFFFF segment.
+        let mut cmap = Vec::new();
+        for value in [0u16, 1, 3, 1, 0, 12] {
+            be16(&mut cmap, value);
+        }
+        for value in [
+            4u16,
+            32,
+            0,
+            4,
+            4,
+            0,
+            0,
+            b'B' as u16,
+            0xFFFF,
+            0,
+            b'A' as u16,
+            0xFFFF,
+        ] {
+            be16(&mut cmap, value);
+        }
+        for value in [1u16.wrapping_sub(b'A' as u16), 1, 0, 0] {
+            be16(&mut cmap, value);
+        }
+
+        let tables: [(&[u8; 4], Vec<u8>); 8] = [
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+            (b"name", Vec::new()),
+        ];
+        let mut font = vec![0, 1, 0, 0];
+        be16(&mut font, tables.len() as u16);
+        font.extend([0; 6]);
+        let mut offset = 12 + tables.len() * 16;
+        let mut bodies = Vec::new();
+        for (tag, body) in &tables {
+            font.extend(*tag);
+            font.extend([0; 4]);
+            font.extend((offset as u32).to_be_bytes());
+            font.extend((body.len() as u32).to_be_bytes());
+            let padded = body.len().next_multiple_of(4);
+            bodies.extend(body);
+            bodies.resize(bodies.len() + padded - body.len(), 0);
+            offset += padded;
+        }
+        font.extend(bodies);
+        font
+    }
+
+    fn pixel(bytes: &[u8], stride: usize, x: usize, y: usize) -> [u8; 4] {
+        bytes[y * stride + x * 4..][..4].try_into().unwrap()
+    }
+
+    //'A' of the test font is a 5x5 square at 10 pixels per em, with an ascent of 8: it lands on
+    //rows 3 to 7, blended over what's there.
+    #[test]
+    fn glyphs_blend_premultiplied() {
+        let font = Font::from_bytes(square_font()).unwrap();
+        let mut bytes = vec![0; 8 * 10 * 4];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 8, 10, 32).unwrap();
+        canvas.clear(Color::opaque(0xFF, 0, 0));
+        canvas.draw_text_with_font(
+            &font,
+            "A",
+            1,
+            0,
+            10.0,
+            Color::premultiplied(0, 0, 0x80, 0x80),
+        );
+
+        assert_eq!(pixel(&bytes, 32, 1, 3), [0x80, 0, 0x7F, 0xFF]);
+        assert_eq!(pixel(&bytes, 32, 5, 7), [0x80, 0, 0x7F, 0xFF]);
+        for (x, y) in [(0, 3), (6, 3), (1, 2), (1, 8)] {
+            assert_eq!(pixel(&bytes, 32, x, y), [0, 0, 0xFF, 0xFF], "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn newlines_go_back_under_the_start() {
+        let font = Font::from_bytes(square_font()).unwrap();
+        let mut bytes = vec![0; 16 * 20 * 4];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 16, 20, 64).unwrap();
+        canvas.draw_text_with_font(&font, "AA\nA", 0, 0, 10.0, Color::opaque(0xFF, 0xFF, 0xFF));
+
+        //Lines are 10 pixels apart (ascent 8, descent 2), glyphs 6 apart.
+        for (x, y) in [(0, 3), (6, 3), (0, 13)] {
+            assert_eq!(pixel(&bytes, 64, x, y), [0xFF; 4], "({x}, {y})");
+        }
+        assert_eq!(pixel(&bytes, 64, 6, 13), [0; 4]);
+    }
+
+    #[test]
+    fn text_is_clipped_at_the_edges() {
+        let mut bytes = vec![0; 10 * 10 * 4];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 10, 10, 40).unwrap();
+        let white = Color::opaque(0xFF, 0xFF, 0xFF);
+        for (x, y) in [(-20, -20), (-5, 3), (5, -8), (8, 8), (i32::MAX, i32::MAX)] {
+            canvas.draw_text_with_font(&Font::fallback(), "Wg\n#", x, y, 26.0, white);
+        }
+        assert!(bytes.contains(&0xFF));
+    }
+
+    #[test]
+    fn glyphs_are_rasterized_once() {
+        let font = Font::fallback();
+        let mut bytes = vec![0; 64 * 16 * 4];
+        let size = 13.5;
+        let atlas_size = || {
+            let atlases = atlases().lock().unwrap();
+            let atlas = &atlases[&(font.id, f32::to_bits(size))];
+            (atlas.glyphs.len(), atlas.coverage.len())
+        };
+
+        let mut canvas = Canvas::from_bytes(&mut bytes, 64, 16, 256).unwrap();
+        canvas.draw_text_with_font(&font, "abcabc", 0, 0, size, Color::opaque(0, 0, 0));
+        let first = atlas_size();
+        assert_eq!(first.0, 3);
+        canvas.draw_text_with_font(&font, "cab", 0, 0, size, Color::opaque(0, 0, 0));
+        assert_eq!(atlas_size(), first);
+    }
+
+    //'B' is 'A' moved right by 100 units by a composite, a pixel at 10 pixels per em.
+    #[test]
+    fn composite_parts_are_moved() {
+        let font = Font::from_bytes(square_font()).unwrap();
+        let mut bytes = vec![0; 8 * 10 * 4];
+        let mut canvas = Canvas::from_bytes(&mut bytes, 8, 10, 32).unwrap();
+        canvas.draw_text_with_font(&font, "B", 0, 0, 10.0, Color::opaque(0xFF, 0xFF, 0xFF));
+
+        for (x, y) in [(1, 3), (5, 7)] {
+            assert_eq!(pixel(&bytes, 32, x, y), [0xFF; 4], "({x}, {y})");
+        }
+        assert_eq!(pixel(&bytes, 32, 0, 3), [0; 4]);
+    }
+
+    #[test]
+    fn garbage_is_refused() {
+        assert!(Font::from_bytes(Vec::new()).is_err());
+        assert!(Font::from_bytes(b"OTTO and then some".to_vec()).is_err());
+        let mut truncated = square_font();
+        truncated.truncate(100);
+        assert!(Font::from_bytes(truncated).is_err());
+    }
+}
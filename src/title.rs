@@ -0,0 +1,97 @@
+use crate::{AppState, Window, app_id};
+
+//libwayland refuses to send messages over 4096 bytes, and a request it can't send kills the
+//connection. set_title/set_app_id carry one string: 8 bytes of header, 4 of length, then the
+//string with its NUL padded to 4 bytes. 4083 bytes of text is the most that fits.
+const MAX_STRING_LEN: usize = 4096 - 8 - 4 - 1;
+
+//Makes a string safe to send: wayland strings end at the first NUL (the C side would cut it there
+//anyway, and wayland-client refuses to send one), and long ones are cut to fit the message,
+//on a char boundary so the result stays valid UTF-8.
+pub(crate) fn wire_string(string: &str) -> String {
+    let string = string.split('\0').next().unwrap_or_default();
+    if string.len() <= MAX_STRING_LEN {
+        return string.to_string();
+    }
+
+    let mut end = MAX_STRING_LEN;
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+    string[..end].to_string()
+}
+
+impl AppState {
+    //For a new window and every set_app_id.
+    pub(crate) fn check_app_id(&self) {
+        app_id::warn_invalid(&self.app_id);
+        #[cfg(feature = "desktop-file-check")]
+        app_id::check_desktop_file(&self.app_id);
+    }
+}
+
+impl Window {
+    //Titles too long for the wire are truncated (see wire_string). The title isn't
+    //double-buffered, so it's flushed right away for task bars to pick it up.
+    pub fn set_title(&mut self, title: &str) {
+        self.state.title = wire_string(title);
+        if let Some((_, ref toplevel)) = self.state.xdg_surface {
+            toplevel.set_title(self.state.title.clone());
+            let _ = self.flush();
+        }
+    }
+
+    //Same rules as set_title. An id that isn't a valid desktop file id is sent all the same,
+    //with a warning, see app_id.rs.
+    pub fn set_app_id(&mut self, app_id: &str) {
+        self.state.app_id = wire_string(app_id);
+        self.state.check_app_id();
+        if let Some((_, ref toplevel)) = self.state.xdg_surface {
+            toplevel.set_app_id(self.state.app_id.clone());
+            let _ = self.flush();
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.state.title
+    }
+
+    pub fn app_id(&self) -> &str {
+        &self.state.app_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_strings_are_untouched() {
+        assert_eq!(wire_string("receba"), "receba");
+    }
+
+    #[test]
+    fn multi_kilobyte_title_fits_the_wire() {
+        let title = "a".repeat(10 * 1024);
+        let sent = wire_string(&title);
+        assert_eq!(sent.len(), MAX_STRING_LEN);
+
+        //Header, length, string, NUL and padding together must fit libwayland's limit.
+        let message_len = 8 + 4 + (sent.len() + 1).next_multiple_of(4);
+        assert!(message_len <= 4096);
+    }
+
+    #[test]
+    fn truncation_keeps_utf8_valid() {
+        //3 byte chars, MAX_STRING_LEN isn't a multiple of 3 so the cut falls mid-char.
+        let title = "ç".repeat(1000) + &"€".repeat(2000);
+        let sent = wire_string(&title);
+        assert!(sent.len() <= MAX_STRING_LEN);
+        assert!(title.starts_with(&sent));
+    }
+
+    #[test]
+    fn strings_stop_at_nul() {
+        assert_eq!(wire_string("before\0after"), "before");
+    }
+}
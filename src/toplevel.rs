@@ -0,0 +1,113 @@
+use wayland_protocols::xdg::shell::client::xdg_toplevel;
+
+use crate::{SerialKind, Window, WindowError};
+
+//Window management actions the compositor supports, to leave out buttons and menu entries that
+//would do nothing (a minimize button on a compositor without minimizing, for one).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WmCapabilities {
+    pub window_menu: bool,
+    pub maximize: bool,
+    pub fullscreen: bool,
+    pub minimize: bool,
+}
+
+impl Window {
+    //Quoting documentation: "Compositors must send this event before the first configure. [...]
+    //If the compositor doesn't support this event (version < 5), the client should assume all
+    //capabilities are supported."
+    pub fn wm_capabilities(&self) -> WmCapabilities {
+        let Some(ref capabilities) = self.state.wm_capabilities else {
+            return WmCapabilities {
+                window_menu: true,
+                maximize: true,
+                fullscreen: true,
+                minimize: true,
+            };
+        };
+        let has = |capability| capabilities.contains(&capability);
+        WmCapabilities {
+            window_menu: has(xdg_toplevel::WmCapabilities::WindowMenu),
+            maximize: has(xdg_toplevel::WmCapabilities::Maximize),
+            fullscreen: has(xdg_toplevel::WmCapabilities::Fullscreen),
+            minimize: has(xdg_toplevel::WmCapabilities::Minimize),
+        }
+    }
+
+    //There's no way back from code: the user restores the window through the compositor, and
+    //the window gets Suspended meanwhile (on compositors that tell).
+    pub fn minimize(&mut self) -> Result<(), WindowError> {
+        let Some((_, ref toplevel)) = self.state.xdg_surface else {
+            return Err(WindowError::NotConfigured);
+        };
+        if !self.wm_capabilities().minimize {
+            return Err(WindowError::Unsupported("xdg_toplevel minimize"));
+        }
+        toplevel.set_minimized();
+        let _ = self.flush();
+        Ok(())
+    }
+
+    //The compositor's word once configured, what was asked for before that.
+    pub fn is_fullscreen(&self) -> bool {
+        self.state.has_state(xdg_toplevel::State::Fullscreen)
+    }
+
+    //A request, the compositor decides: the next configure says whether it happened (see
+    //is_fullscreen). Before the toplevel exists it's kept for the initial commit, like
+    //WindowOptions::fullscreen, whose output it keeps.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) -> Result<(), WindowError> {
+        self.state.fullscreen = fullscreen;
+        let Some((_, ref toplevel)) = self.state.xdg_surface else {
+            return Ok(());
+        };
+        if !self.wm_capabilities().fullscreen {
+            return Err(WindowError::Unsupported("xdg_toplevel fullscreen"));
+        }
+        if fullscreen {
+            toplevel.set_fullscreen(None);
+        } else {
+            toplevel.unset_fullscreen();
+        }
+        let _ = self.flush();
+        Ok(())
+    }
+
+    //Pops up the compositor's own window menu (move, maximize, close...), what a right-click on
+    //a client side title bar is expected to do. x and y are where the pointer is, surface-local
+    //like every pointer event. The compositor wants them relative to the window geometry, so the
+    //shadow margin is taken off here.
+    //
+    //`serial` is the button press that asked for the menu, None takes the latest one. It has to
+    //be a recent press: compositors ignore the request otherwise, and there is no menu without any
+    //error either.
+    pub fn show_window_menu(
+        &mut self,
+        x: f64,
+        y: f64,
+        serial: Option<u32>,
+    ) -> Result<(), WindowError> {
+        if !self.wm_capabilities().window_menu {
+            return Err(WindowError::Unsupported("xdg_toplevel window menu"));
+        }
+        let Some((_, ref toplevel)) = self.state.xdg_surface else {
+            return Err(WindowError::NotConfigured);
+        };
+
+        let trigger = match serial {
+            Some(serial) => self.state.seat_of_serial(serial).map(|seat| (seat, serial)),
+            None => self
+                .state
+                .latest_serial(&[SerialKind::PointerButton], "show_window_menu"),
+        };
+        let Some((seat, serial)) = trigger else {
+            return Err(WindowError::NoPointer);
+        };
+
+        let geometry = self.state.window_geometry();
+        toplevel.show_window_menu(&seat, serial, x as i32 - geometry.x, y as i32 - geometry.y);
+        //Nothing else is coming to push it out, and a menu should show right away.
+        let _ = self.flush();
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//Everything on a wl_surface waits for the next commit (quoting documentation: "Surface state
+//(input, opaque, and damage regions, attached buffers, etc.) is double-buffered"), so a commit
+//in the middle of setting things up shows half of it. A SurfaceTransaction stages what the
+//application wants changed and sends all of it with one commit:
+//
+//  let mut frame = window.begin_frame();
+//  frame.attach(&buffer, width, height);
+//  frame.damage(rect);
+//  frame.set_opaque(Some(&[rect]));
+//  frame.commit()?;
+//
+//What the window does by itself on a new buffer joins that commit rather than making its own:
+//the buffer scale, the viewport destination, the window geometry of client side decorations and
+//the input region worked out for the new size (see present_buffer, which is a transaction of one
+//attach). It borrows the window, so nothing else commits while it's open.
+//
+//Without a buffer it's a commit of the rest only, fine once the initial commit is done.
+//Dropped without commit, nothing of it is sent.
+//
+//The window's own commits go the same way: the gradient, the solid color, the resize preview, a
+//state-only commit all fill a StagedCommit and send it with AppState::commit_staged. The
+//initial commit (nothing to stage yet) and the diagnostic overlay don't: the overlay is a
+//desynchronized subsurface, its commits are its own surface's and don't wait for the window's.
+
+use wayland_client::{Proxy, QueueHandle, protocol::wl_buffer::WlBuffer};
+
+use crate::{
+    AppState, Lifecycle, Rect, Window, WindowError, lifecycle::SurfaceRequest,
+    protocol_log::protocol_log, region::create_region,
+};
+
+//One commit's worth of the window surface's state, nothing sent until commit_staged.
+#[derive(Default)]
+pub(crate) struct StagedCommit {
+    pub(crate) buffer: Option<(WlBuffer, u32, u32)>,
+    //Buffer pixels. None with a buffer is all of it.
+    pub(crate) damage: Option<Vec<Rect>>,
+    //Some(None) back to none, like the protocol's null region.
+    pub(crate) opaque: Option<Option<Vec<Rect>>>,
+    pub(crate) input: Option<Option<Vec<Rect>>>,
+    //Instead of the viewport destination worked out for the buffer: Some(None) unsets it. A
+    //buffer stretched this way is damaged all over the destination.
+    pub(crate) viewport_destination: Option<Option<(i32, i32)>>,
+    //The buffer's acquire and release points are set already, see explicit_sync.rs.
+    #[cfg(feature = "explicit-sync")]
+    pub(crate) explicitly_synced: bool,
+}
+
+#[must_use = "nothing is sent until commit"]
+pub struct SurfaceTransaction<'a> {
+    window: &'a mut Window,
+    staged: StagedCommit,
+    committed: bool,
+}
+
+impl Window {
+    //See transaction.rs.
+    pub fn begin_frame(&mut self) -> SurfaceTransaction<'_> {
+        SurfaceTransaction {
+            window: self,
+            staged: StagedCommit::default(),
+            committed: false,
+        }
+    }
+}
+
+impl SurfaceTransaction<'_> {
+    //Any buffer (shm, single pixel, dmabuf...) of the given size, in buffer pixels. It must stay
+    //alive until the compositor releases it.
+    pub fn attach(&mut self, buffer: &WlBuffer, width: u32, height: u32) -> &mut Self {
+        self.staged.buffer = Some((buffer.clone(), width, height));
+        self
+    }
+
+    //A part of the buffer that changed, in buffer pixels. A new buffer without any is damaged
+    //all over.
+    pub fn damage(&mut self, rect: Rect) -> &mut Self {
+        self.staged.damage.get_or_insert_with(Vec::new).push(rect);
+        self
+    }
+
+    //Where the surface is opaque (surface coordinates), so the compositor can skip drawing what's
+    //under it. None is none of it, the default.
+    pub fn set_opaque(&mut self, rects: Option<&[Rect]>) -> &mut Self {
+        self.staged.opaque = Some(rects.map(<[Rect]>::to_vec));
+        self
+    }
+
+    //Window::set_input_region, in this commit.
+    pub fn set_input_region(&mut self, rects: Option<&[Rect]>) -> &mut Self {
+        self.staged.input = Some(rects.map(<[Rect]>::to_vec));
+        self
+    }
+
+    //Sends it all with one wl_surface.commit. Err(InvalidState), with nothing sent, for a buffer
+    //before the first configure, anything before the initial commit, or after close.
+    pub fn commit(mut self) -> Result<(), WindowError> {
+        self.committed = true;
+        let window = &mut *self.window;
+        let state = &mut window.state;
+        match self.staged.buffer {
+            Some(_) => state.lifecycle.transition(SurfaceRequest::Attach)?,
+            None => match state.lifecycle.stage() {
+                Lifecycle::Created => {
+                    return Err(WindowError::InvalidState(
+                        "committed before the initial commit",
+                    ));
+                }
+                Lifecycle::Closed => {
+                    return Err(WindowError::InvalidState("the window was closed"));
+                }
+                _ => {}
+            },
+        }
+        if state.base_surface.is_none() || state.compositor.is_none() {
+            return Err(WindowError::NotConfigured);
+        }
+
+        let queue_handle = window.event_queue.handle();
+        let staged = std::mem::take(&mut self.staged);
+        state.commit_staged(staged, &queue_handle, "application buffer");
+        Ok(())
+    }
+}
+
+impl AppState {
+    //Sends `staged` with one commit, along with what the window adds by itself (see the top of
+    //this file). The lifecycle is the caller's to check. `what` is the buffer, for the log.
+    pub(crate) fn commit_staged(
+        &mut self,
+        staged: StagedCommit,
+        queue_handle: &QueueHandle<AppState>,
+        what: &str,
+    ) {
+        let (Some(surface), Some(compositor)) =
+            (self.base_surface.clone(), self.compositor.clone())
+        else {
+            return;
+        };
+        if let Some(opaque) = staged.opaque {
+            let region = opaque.map(|rects| create_region(&compositor, &rects, queue_handle));
+            surface.set_opaque_region(region.as_ref());
+            if let Some(region) = region {
+                region.destroy();
+            }
+        }
+        if let Some(input) = staged.input {
+            self.input_region.want(input.as_deref());
+        }
+
+        if let Some((ref buffer, width, height)) = staged.buffer {
+            self.request_presentation_feedback(queue_handle);
+            //Relying on implicit sync again, see explicit_sync.rs.
+            #[cfg(feature = "explicit-sync")]
+            if !staged.explicitly_synced {
+                self.end_explicit_sync();
+            }
+            self.set_surface_scale((width, height));
+            if staged.viewport_destination.is_none() {
+                self.viewport_for_buffer((width, height), queue_handle);
+            }
+            surface.attach(Some(buffer), 0, 0);
+            self.note_request(&surface, format!("attach({})", buffer.id()));
+        }
+        if let Some(destination) = staged.viewport_destination {
+            self.set_viewport_destination(destination, queue_handle);
+        }
+
+        let (width, height) = staged
+            .buffer
+            .as_ref()
+            .map_or(self.buffer_size, |&(_, width, height)| (width, height));
+        if let Some(ref damage) = staged.damage {
+            self.damage_buffer_rects(damage, (width as i32, height as i32));
+            self.note_request(&surface, "damage");
+        } else if staged.buffer.is_some() {
+            match staged.viewport_destination {
+                Some(Some((width, height))) => surface.damage(0, 0, width, height),
+                _ => self.damage_buffer((width as i32, height as i32)),
+            }
+            self.note_request(&surface, "damage");
+        }
+        if self.configured() {
+            self.apply_window_geometry();
+        }
+        self.apply_input_region(queue_handle);
+
+        surface.commit();
+        self.note_request(&surface, "commit");
+        self.committed();
+        match staged.buffer {
+            Some((buffer, ..)) => {
+                self.attached = Some(buffer);
+                self.startup_committed();
+                self.log_commit(what);
+            }
+            None => protocol_log!("state committed"),
+        }
+    }
+}
+
+impl Drop for SurfaceTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            log::debug!("surface transaction dropped without commit, nothing of it sent");
+        }
+    }
+}
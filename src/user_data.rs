@@ -0,0 +1,77 @@
+//Application state hung on the window. Everything that gets the window (event handlers, key
+//bindings, idle and timer callbacks) gets at its data too, so with several windows each handler
+//updates its own window's model, no globals or mutexes. The same idea as the user data of the
+//Dispatch impls, one level up.
+//
+//One value per window, of any type. Asking for another type than the one stored gives None. The
+//value is dropped with the window, and survives Window::reconnect.
+
+use std::any::Any;
+
+use crate::Window;
+
+#[derive(Default)]
+pub(crate) struct UserData(Option<Box<dyn Any>>);
+
+impl UserData {
+    fn get<T: Any>(&self) -> Option<&T> {
+        self.0.as_ref()?.downcast_ref()
+    }
+
+    fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.0.as_mut()?.downcast_mut()
+    }
+
+    fn take<T: Any>(&mut self) -> Option<T> {
+        if !self.0.as_ref()?.is::<T>() {
+            return None;
+        }
+        self.0.take()?.downcast().ok().map(|value| *value)
+    }
+}
+
+impl Window {
+    //Replaces whatever was stored, of whatever type.
+    pub fn set_user_data<T: Any>(&mut self, value: T) {
+        self.state.user_data.0 = Some(Box::new(value));
+    }
+
+    pub fn user_data<T: Any>(&self) -> Option<&T> {
+        self.state.user_data.get()
+    }
+
+    pub fn user_data_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.state.user_data.get_mut()
+    }
+
+    //Takes the value out, leaving the slot empty. Left in place if it's of another type.
+    pub fn take_user_data<T: Any>(&mut self) -> Option<T> {
+        self.state.user_data.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn the_wrong_type_is_none() {
+        let mut data = UserData(Some(Box::new(5u32)));
+        assert_eq!(data.get::<i32>(), None);
+        assert_eq!(data.take::<String>(), None);
+        *data.get_mut::<u32>().unwrap() += 1;
+        assert_eq!(data.take::<u32>(), Some(6));
+        assert_eq!(data.get::<u32>(), None);
+    }
+
+    #[test]
+    fn the_value_is_dropped_with_the_slot() {
+        let value = Rc::new(());
+        let data = UserData(Some(Box::new(value.clone())));
+        assert_eq!(Rc::strong_count(&value), 2);
+        drop(data);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+}
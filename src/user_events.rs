@@ -0,0 +1,208 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt,
+    marker::PhantomData,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    sync::{Arc, Mutex, Weak},
+    time::Instant,
+};
+
+use rustix::{
+    event::{PollFd, PollFlags, Timespec, poll},
+    io::{read, write},
+    pipe::{PipeFlags, pipe_with},
+};
+
+use crate::{Window, WindowError, WindowEvent};
+
+//Something another thread sent through an EventLoopProxy, handed out as WindowEvent::User.
+//The value can be taken out once (it may be big, e.g. freshly computed content); clones of the
+//event share it.
+#[derive(Clone)]
+pub struct UserEvent(Arc<Mutex<Option<Box<dyn Any + Send>>>>);
+
+impl UserEvent {
+    //None if it was already taken or is of another type.
+    pub fn take<T: 'static>(&self) -> Option<T> {
+        let mut value = self.0.lock().unwrap();
+        if !value.as_ref()?.is::<T>() {
+            return None;
+        }
+        value.take()?.downcast().ok().map(|value| *value)
+    }
+}
+
+impl fmt::Debug for UserEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UserEvent")
+    }
+}
+
+impl PartialEq for UserEvent {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+//Shared between the window and its proxies. Sending queues the event and writes a byte to the
+//pipe, whose read end sits in the window's poll so a sleeping dispatch wakes up.
+struct Shared {
+    queue: Mutex<VecDeque<UserEvent>>,
+    wake: OwnedFd,
+}
+
+pub(crate) struct UserEvents {
+    shared: Arc<Shared>,
+    wake_read: OwnedFd,
+}
+
+//Cloneable and Send: lets other threads (a worker computing something, a network thread...)
+//wake the window's loop with a value. Once the window is dropped, sending fails instead of
+//blocking.
+pub struct EventLoopProxy<T> {
+    shared: Weak<Shared>,
+    _event: PhantomData<fn(T)>,
+}
+
+impl<T> Clone for EventLoopProxy<T> {
+    fn clone(&self) -> Self {
+        EventLoopProxy {
+            shared: self.shared.clone(),
+            _event: PhantomData,
+        }
+    }
+}
+
+impl<T: Send + 'static> EventLoopProxy<T> {
+    pub fn send_event(&self, event: T) -> Result<(), WindowError> {
+        let shared = self.shared.upgrade().ok_or(WindowError::Closed)?;
+        shared
+            .queue
+            .lock()
+            .unwrap()
+            .push_back(UserEvent(Arc::new(Mutex::new(Some(Box::new(event))))));
+
+        //A full pipe (EAGAIN) is fine: it's readable already, which is all the byte is for.
+        let _ = write(&shared.wake, &[0]);
+        Ok(())
+    }
+}
+
+//Wakes the window's loop without an event, for handovers with a channel of their own (see
+//render_thread.rs).
+pub(crate) struct Waker(Weak<Shared>);
+
+impl Waker {
+    pub(crate) fn wake(&self) -> Result<(), WindowError> {
+        let shared = self.0.upgrade().ok_or(WindowError::Closed)?;
+        let _ = write(&shared.wake, &[0]);
+        Ok(())
+    }
+}
+
+impl Window {
+    pub fn create_proxy<T: Send + 'static>(&mut self) -> Result<EventLoopProxy<T>, WindowError> {
+        Ok(EventLoopProxy {
+            shared: self.user_events_shared()?,
+            _event: PhantomData,
+        })
+    }
+
+    pub(crate) fn waker(&mut self) -> Result<Waker, WindowError> {
+        Ok(Waker(self.user_events_shared()?))
+    }
+
+    //The pipe and queue are only made once something can send.
+    fn user_events_shared(&mut self) -> Result<Weak<Shared>, WindowError> {
+        if self.user_events.is_none() {
+            let (wake_read, wake) = pipe_with(PipeFlags::NONBLOCK | PipeFlags::CLOEXEC)
+                .map_err(|err| WindowError::Connection(err.to_string()))?;
+            self.user_events = Some(UserEvents {
+                shared: Arc::new(Shared {
+                    queue: Mutex::default(),
+                    wake,
+                }),
+                wake_read,
+            });
+        }
+        Ok(Arc::downgrade(&self.user_events.as_ref().unwrap().shared))
+    }
+
+    //Fd that becomes readable when a proxy sent something, for loops that poll themselves.
+    pub(crate) fn user_event_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.user_events
+            .as_ref()
+            .map(|user_events| user_events.wake_read.as_fd())
+    }
+
+    //Moves what the proxies sent into the window's events.
+    pub(crate) fn deliver_user_events(&mut self) {
+        let Some(ref user_events) = self.user_events else {
+            return;
+        };
+
+        let mut bytes = [0u8; 64];
+        while matches!(read(&user_events.wake_read, &mut bytes), Ok(n) if n > 0) {}
+
+        let mut queue = user_events.shared.queue.lock().unwrap();
+        self.state
+            .events
+            .extend(queue.drain(..).map(WindowEvent::User));
+    }
+
+    //When a wait has to end even if nothing comes: the real frame after a resize preview, and
+    //the diagnostic overlay's next redraw unless the window is suspended (nobody would see it,
+    //it's redrawn when resumed).
+    pub(crate) fn wakeup_deadline(&self) -> Option<Instant> {
+        let preview = self.state.resize_preview.deadline();
+        #[cfg(feature = "diagnostic-overlay")]
+        if !self.state.suspended
+            && let Some(redraw) = self.state.diagnostic_overlay.next_redraw()
+        {
+            return Some(preview.map_or(redraw, |preview| preview.min(redraw)));
+        }
+        preview
+    }
+
+    //blocking_dispatch only sleeps on the wayland socket, until something comes. With proxies
+    //around, sleep on their pipe too, and only until the wakeup deadline (a resize preview's
+    //real frame, the diagnostic overlay's next redraw).
+    pub(crate) fn blocking_dispatch_with_wakeups(&mut self) {
+        self.dispatch_queued();
+        self.deliver_user_events();
+        if !self.state.events.is_empty() {
+            return;
+        }
+
+        self.send_requests();
+        self.state.wait_started();
+        if let Some(guard) = self.event_queue.prepare_read() {
+            let mut fds = vec![PollFd::from_borrowed_fd(
+                guard.connection_fd(),
+                self.connection_interest(),
+            )];
+            if let Some(wake) = self.user_event_fd() {
+                fds.push(PollFd::from_borrowed_fd(wake, PollFlags::IN));
+            }
+            let timeout = self
+                .wakeup_deadline()
+                .map(|next| next.saturating_duration_since(Instant::now()))
+                .and_then(|left| Timespec::try_from(left).ok());
+            //EINTR (a signal) is just an early wakeup.
+            let timed_out = poll(&mut fds, timeout.as_ref()) == Ok(0);
+            let ready = fds[0].revents();
+            if ready.contains(PollFlags::OUT) {
+                self.send_requests();
+            }
+            if ready.intersects(PollFlags::IN | PollFlags::ERR | PollFlags::HUP) {
+                let _ = guard.read();
+            }
+            self.state.watchdog.woke(timed_out);
+        }
+        self.state.watchdog.wait_ended();
+
+        self.dispatch_queued();
+        self.deliver_user_events();
+    }
+}
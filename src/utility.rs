@@ -0,0 +1,281 @@
+//Utility windows: tool palettes and the like, small toplevels that belong to a main window. One
+//made with WindowRole::Utility through Window::create_child_window gets
+//
+//  - the main window as its parent (set_parent). That's all Wayland has for "keep it above its
+//    window and out of the task bar", compositors that do either go by it.
+//  - a smaller size when the compositor leaves it to us (WindowOptions::preferred_size wins).
+//  - no ToggleFullscreen key binding, a palette is never fullscreen or maximized on purpose.
+//  - raised with its main window: when the main window gets activated, it asks xdg-activation to
+//    activate its utility windows too, with a token of its own. Not when the focus came from one
+//    of them (the user clicked the main window), raising it would take the focus right back.
+//  - closed with it: Window::close (or dropping) the main window destroys its utility windows'
+//    toplevels before its own, so none points at a destroyed parent. They're closed right away
+//    (nothing is committed on them anymore) and is_running turns false the next time they
+//    dispatch. Other child windows are only unparented.
+//
+//Like dialogs, a utility window shares the main window's connection with its own event queue:
+//both need their events pumped. The main window wakes its utility windows' queues when it closes,
+//pump_events doesn't sleep through it.
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use wayland_client::{
+    Connection, Dispatch, Proxy, QueueHandle,
+    protocol::{wl_callback::WlCallback, wl_surface::WlSurface},
+};
+use wayland_protocols::xdg::shell::client::xdg_toplevel::XdgToplevel;
+
+use crate::{AppState, Window, WindowError, WindowOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WindowRole {
+    #[default]
+    Normal,
+    //A tool palette of the window given to Window::create_child_window, see utility.rs.
+    Utility,
+}
+
+//The window size a utility window takes when the compositor leaves it to us.
+const UTILITY_SIZE: (u32, u32) = (240, 320);
+//The main window activated this soon after one of its utility windows lost the focus got it from
+//that window.
+const HANDOVER: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Focus {
+    Never,
+    Activated,
+    Left(Instant),
+}
+
+impl Focus {
+    //Whether the main window getting activated at `now` should raise this utility window.
+    fn raise(self, now: Instant) -> bool {
+        match self {
+            Focus::Never => true,
+            //Its Deactivated may still wait in its own queue, nothing to raise anyway.
+            Focus::Activated => false,
+            Focus::Left(at) => now.saturating_duration_since(at) > HANDOVER,
+        }
+    }
+}
+
+//What a main window and one of its utility windows share.
+struct Link {
+    role: WindowRole,
+    toplevel: XdgToplevel,
+    surface: WlSurface,
+    //The utility window's queue, woken up when the main window closes.
+    queue_handle: QueueHandle<AppState>,
+    //The utility window's LifecycleState::closed_by_parent.
+    parent_closed: Arc<AtomicBool>,
+    focus: Mutex<Focus>,
+}
+
+//The main window's utility windows, shared with them so each side can let go of the other
+//whichever goes first.
+type Links = Arc<Mutex<Vec<Arc<Link>>>>;
+
+#[derive(Default)]
+pub(crate) struct UtilityState {
+    pub(crate) role: WindowRole,
+    //The main window's toplevel and its list, which we join once our toplevel exists.
+    parent: Option<(XdgToplevel, Links)>,
+    //Ours, once we joined.
+    link: Option<Arc<Link>>,
+    children: Links,
+    //Activated since the last dispatch, the utility windows are raised after it.
+    raise: bool,
+}
+
+impl UtilityState {
+    pub(crate) fn new(role: WindowRole) -> UtilityState {
+        UtilityState {
+            role,
+            ..UtilityState::default()
+        }
+    }
+}
+
+impl AppState {
+    //From with_connection, after WindowOptions::preferred_size.
+    pub(crate) fn apply_role(&mut self) {
+        if self.utility.role == WindowRole::Utility {
+            self.sizing.preferred_size.get_or_insert(UTILITY_SIZE);
+        }
+    }
+
+    //Called when the toplevel gets created, before its initial commit. The main window may be
+    //gone already, the utility window is then a normal one.
+    pub(crate) fn apply_utility_parent(&mut self, queue_handle: &QueueHandle<AppState>) {
+        let (Some((_, toplevel)), Some(surface), Some((parent, siblings))) = (
+            self.xdg_surface.as_ref(),
+            self.base_surface.as_ref(),
+            self.utility.parent.as_ref(),
+        ) else {
+            return;
+        };
+        if !parent.is_alive() {
+            return;
+        }
+        toplevel.set_parent(Some(parent));
+        let link = Arc::new(Link {
+            role: self.utility.role,
+            toplevel: toplevel.clone(),
+            surface: surface.clone(),
+            queue_handle: queue_handle.clone(),
+            parent_closed: self.lifecycle.closed_by_parent(),
+            focus: Mutex::new(Focus::Never),
+        });
+        siblings.lock().unwrap().push(link.clone());
+        self.utility.link = Some(link);
+    }
+
+    //From update_toplevel_states.
+    pub(crate) fn utility_activated(&mut self, activated: bool) {
+        if let Some(ref link) = self.utility.link {
+            *link.focus.lock().unwrap() = if activated {
+                Focus::Activated
+            } else {
+                Focus::Left(Instant::now())
+            };
+        }
+        if activated && !self.utility.children.lock().unwrap().is_empty() {
+            self.utility.raise = true;
+        }
+    }
+
+    //Part of dropping the window: the main window forgets about us.
+    pub(crate) fn detach_utility(&mut self) {
+        if let (Some((_, siblings)), Some(link)) =
+            (self.utility.parent.as_ref(), self.utility.link.take())
+        {
+            siblings
+                .lock()
+                .unwrap()
+                .retain(|sibling| !Arc::ptr_eq(sibling, &link));
+        }
+    }
+}
+
+impl Window {
+    //A second window on this one's connection, `parent` being its parent. With
+    //WindowRole::Utility in `options`, a tool palette of it, see utility.rs; a normal window
+    //otherwise, only kept above the parent.
+    pub fn create_child_window(
+        parent: &Window,
+        options: WindowOptions,
+    ) -> Result<Window, WindowError> {
+        let Some((_, ref toplevel)) = parent.state.xdg_surface else {
+            return Err(WindowError::NotConfigured);
+        };
+
+        let mut child = Window::with_connection(parent.connection.clone(), options);
+        child.state.utility.parent =
+            Some((toplevel.clone(), parent.state.utility.children.clone()));
+        Ok(child)
+    }
+
+    //Whether some utility or child window created from this one is still open.
+    pub fn has_child_windows(&self) -> bool {
+        !self.state.utility.children.lock().unwrap().is_empty()
+    }
+
+    //From close and drop, before our toplevel goes: the utility windows lose theirs, the other
+    //child windows their parent. The utility windows learn it with a sync on their queue, which
+    //wakes them up to it.
+    pub(crate) fn close_child_windows(&mut self) {
+        let children = std::mem::take(&mut *self.state.utility.children.lock().unwrap());
+        for link in children {
+            if link.role != WindowRole::Utility {
+                if link.toplevel.is_alive() {
+                    link.toplevel.set_parent(None);
+                }
+                continue;
+            }
+            //Before the destroy, nothing of theirs may go out on a surface without its role.
+            link.parent_closed.store(true, Ordering::Release);
+            link.toplevel.destroy();
+            self.connection
+                .display()
+                .sync(&link.queue_handle, ParentClosed);
+        }
+    }
+
+    //After every dispatch: a utility window closes after its main window, and a main window that
+    //got activated raises its utility windows.
+    pub(crate) fn follow_parent(&mut self) {
+        if self.state.utility.role == WindowRole::Utility
+            && self
+                .state
+                .utility
+                .link
+                .as_ref()
+                .is_some_and(|link| link.parent_closed.load(Ordering::Acquire))
+        {
+            self.state.utility.link = None;
+            self.close();
+        }
+
+        if !std::mem::take(&mut self.state.utility.raise) {
+            return;
+        }
+        let now = Instant::now();
+        let surfaces: Vec<_> = self
+            .state
+            .utility
+            .children
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|link| {
+                link.role == WindowRole::Utility
+                    && link.surface.is_alive()
+                    && link.focus.lock().unwrap().raise(now)
+            })
+            .map(|link| link.surface.clone())
+            .collect();
+        if surfaces.is_empty() {
+            return;
+        }
+        let queue_handle = self.event_queue.handle();
+        if self.state.activate_surfaces(surfaces, &queue_handle) {
+            let _ = self.flush();
+        }
+    }
+}
+
+//The sync that wakes a utility window up when its main window closes, follow_parent does the rest.
+pub(crate) struct ParentClosed;
+
+impl Dispatch<WlCallback, ParentClosed> for AppState {
+    fn event(
+        _: &mut Self,
+        _: &WlCallback,
+        _: <WlCallback as Proxy>::Event,
+        _: &ParentClosed,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn focus_handed_over_by_a_utility_window_raises_nothing() {
+        let now = Instant::now();
+        assert!(Focus::Never.raise(now));
+        assert!(!Focus::Activated.raise(now));
+        assert!(!Focus::Left(now).raise(now + HANDOVER));
+        assert!(Focus::Left(now).raise(now + HANDOVER * 2));
+    }
+}
@@ -0,0 +1,241 @@
+use wayland_client::{QueueHandle, delegate_noop};
+use wayland_protocols::wp::viewporter::client::{
+    wp_viewport::WpViewport, wp_viewporter::WpViewporter,
+};
+
+use crate::{AppState, ScaleMath, Window, WindowError};
+
+//wp_viewporter: decouples the surface size from the buffer size. The compositor scales
+//(destination) and crops (source) the attached buffer for us, for free.
+#[derive(Default)]
+pub(crate) struct ViewportState {
+    pub(crate) viewporter: Option<WpViewporter>,
+    viewport: Option<WpViewport>,
+    //The crop of set_viewport_source as sent, stretched over the whole surface. None when the
+    //whole buffer shows.
+    source: Option<SourceRect>,
+}
+
+//x, y, width, height in surface coordinates, each a multiple of 1/256 like the wl_fixed it's
+//sent as.
+type SourceRect = (f64, f64, f64, f64);
+
+impl AppState {
+    //The surface is going, see cleanup.rs.
+    pub(crate) fn forget_viewport(&mut self) {
+        if let Some(viewport) = self.viewport.viewport.take() {
+            viewport.destroy();
+        }
+    }
+
+    //After a buffer of `size` (buffer pixels) got its scale with set_surface_scale, before it's
+    //attached. The crop stays while it's inside the buffer, stretched over it, and goes when the
+    //buffer got too small for it.
+    pub(crate) fn viewport_for_buffer(
+        &mut self,
+        size: (u32, u32),
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        let size = self.buffer_transform.apply_to_size(size);
+        let surface_size = ScaleMath::integer(self.preferred.sent_scale).size_to_logical(size);
+        if let Some(source) = self.viewport.source
+            && !fits(source, surface_size)
+        {
+            log::debug!(
+                "viewport source {source:?} left for a {}x{} surface",
+                surface_size.0,
+                surface_size.1
+            );
+            self.clear_viewport_source();
+        }
+        //A frame drawn during a resize preview is stretched like the one before it.
+        let destination = match self.resize_preview.surface {
+            Some(preview) if self.viewport.source.is_none() => {
+                Some((preview.0 as i32, preview.1 as i32))
+            }
+            _ => self
+                .viewport
+                .source
+                .map(|_| (surface_size.0 as i32, surface_size.1 as i32)),
+        };
+        self.set_viewport_destination(destination, queue_handle);
+    }
+
+    //Back to the whole buffer. The destination is the caller's.
+    pub(crate) fn clear_viewport_source(&mut self) {
+        if self.viewport.source.take().is_some()
+            && let Some(ref viewport) = self.viewport.viewport
+        {
+            //All -1 unsets the source.
+            viewport.set_source(-1.0, -1.0, -1.0, -1.0);
+        }
+    }
+
+    pub(crate) fn has_viewporter(&self) -> bool {
+        self.viewport.viewporter.is_some()
+    }
+
+    pub(crate) fn has_viewport_source(&self) -> bool {
+        self.viewport.source.is_some()
+    }
+
+    //Sets the size the surface is shown at, whatever the buffer size is. None goes back to
+    //"surface size = buffer size". Double-buffered, applied on the next commit.
+    //
+    //The per-surface viewport is only created the first time it's needed; a surface can
+    //have only one (Quoting documentation: "If the given wl_surface already has a wp_viewport
+    //object associated, the viewport_exists protocol error is raised.").
+    pub(crate) fn set_viewport_destination(
+        &mut self,
+        size: Option<(i32, i32)>,
+        queue_handle: &QueueHandle<AppState>,
+    ) {
+        if self.viewport.viewport.is_none() && size.is_none() {
+            return;
+        }
+
+        let (Some(viewporter), Some(surface)) = (
+            self.viewport.viewporter.as_ref(),
+            self.base_surface.as_ref(),
+        ) else {
+            return;
+        };
+
+        let viewport = self
+            .viewport
+            .viewport
+            .get_or_insert_with(|| viewporter.get_viewport(surface, queue_handle, ()));
+
+        //-1, -1 unsets the destination.
+        let (width, height) = size.unwrap_or((-1, -1));
+        viewport.set_destination(width, height);
+    }
+}
+
+impl Window {
+    //Shows only the rectangle (x, y, width, height) of the window's buffer, stretched over the
+    //whole window: zoom and pan with a commit, nothing redrawn. In surface coordinates, those of
+    //pointer events, fractions included (rounded down to wl_fixed, 1/256 of a pixel). The whole
+    //surface is the same as reset_viewport_source.
+    //
+    //The rectangle has to lie within the buffer, the compositor's out_of_buffer protocol error
+    //otherwise, so one that doesn't is an InvalidArgument here. It stays over redraws, and goes
+    //when a resize leaves it outside the new buffer. A solid color (fill_color) has no pixels to
+    //crop, InvalidState while one is shown.
+    pub fn set_viewport_source(
+        &mut self,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<(), WindowError> {
+        if !self.state.has_viewporter() {
+            return Err(WindowError::Unsupported("wp_viewporter"));
+        }
+        if !self.state.configured() {
+            return Err(WindowError::NotConfigured);
+        }
+        if self.state.shows_solid_color() {
+            return Err(WindowError::InvalidState(
+                "a solid color is shown, there's nothing to crop",
+            ));
+        }
+        let source = to_fixed((x, y, width, height)).ok_or(WindowError::InvalidArgument(
+            "the viewport source must be finite, with a positive size",
+        ))?;
+        let surface_size = self.state.surface_size();
+        if !fits(source, surface_size) {
+            return Err(WindowError::InvalidArgument(
+                "the viewport source is outside the buffer",
+            ));
+        }
+        if source == (0.0, 0.0, surface_size.0 as f64, surface_size.1 as f64) {
+            self.reset_viewport_source();
+            return Ok(());
+        }
+        if self.state.viewport.source == Some(source) {
+            return Ok(());
+        }
+
+        let queue_handle = self.event_queue.handle();
+        //Creates the viewport when there's none yet.
+        let (width, height) = surface_size;
+        self.state
+            .set_viewport_destination(Some((width as i32, height as i32)), &queue_handle);
+        let (x, y, width, height) = source;
+        let viewport = self.state.viewport.viewport.as_ref().unwrap();
+        viewport.set_source(x, y, width, height);
+        self.state.viewport.source = Some(source);
+        //Both are double-buffered, the buffer already attached shows them with a commit.
+        self.state.commit_state(&queue_handle);
+        Ok(())
+    }
+
+    //The whole buffer again, at 1:1.
+    pub fn reset_viewport_source(&mut self) {
+        if self.state.viewport.source.is_none() {
+            return;
+        }
+        let queue_handle = self.event_queue.handle();
+        self.state.clear_viewport_source();
+        self.state.set_viewport_destination(None, &queue_handle);
+        self.state.commit_state(&queue_handle);
+    }
+
+    //What set_viewport_source shows, rounded down to wl_fixed. None when it's the whole buffer.
+    pub fn viewport_source(&self) -> Option<(f64, f64, f64, f64)> {
+        self.state.viewport.source
+    }
+}
+
+//Rounded down to what the compositor gets, so a rectangle inside the buffer stays inside. None
+//for what's still invalid then.
+fn to_fixed((x, y, width, height): SourceRect) -> Option<SourceRect> {
+    let round = |value: f64| (value * 256.0).floor() / 256.0;
+    let rect = (round(x), round(y), round(width), round(height));
+    let valid = [rect.0, rect.1, rect.2, rect.3]
+        .iter()
+        .all(|value| value.is_finite() && value.abs() < f64::from(1 << 23))
+        && rect.0 >= 0.0
+        && rect.1 >= 0.0
+        && rect.2 > 0.0
+        && rect.3 > 0.0;
+    valid.then_some(rect)
+}
+
+//Quoting documentation: "If the source rectangle is partially or completely outside of the
+//non-NULL wl_buffer, then the out_of_buffer protocol error is raised when the surface state is
+//applied."
+fn fits((x, y, width, height): SourceRect, (surface_width, surface_height): (u32, u32)) -> bool {
+    x + width <= f64::from(surface_width) && y + height <= f64::from(surface_height)
+}
+
+//Neither interface has events.
+delegate_noop!(AppState: WpViewporter);
+delegate_noop!(AppState: ignore WpViewport);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_is_rounded_down_to_wl_fixed() {
+        assert_eq!(
+            to_fixed((0.1, 10.5, 100.0, 50.25)),
+            Some((0.09765625, 10.5, 100.0, 50.25))
+        );
+        //Too small to survive the rounding.
+        assert_eq!(to_fixed((0.0, 0.0, 0.001, 10.0)), None);
+        assert_eq!(to_fixed((-1.0, 0.0, 10.0, 10.0)), None);
+        assert_eq!(to_fixed((f64::NAN, 0.0, 10.0, 10.0)), None);
+        assert_eq!(to_fixed((0.0, 0.0, f64::INFINITY, 10.0)), None);
+    }
+
+    #[test]
+    fn source_must_lie_within_the_buffer() {
+        assert!(fits((0.0, 0.0, 640.0, 480.0), (640, 480)));
+        assert!(fits((320.5, 240.0, 319.5, 240.0), (640, 480)));
+        assert!(!fits((320.5, 240.0, 320.0, 240.0), (640, 480)));
+        assert!(!fits((0.0, 0.0, 640.0, 480.25), (640, 480)));
+    }
+}
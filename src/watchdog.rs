@@ -0,0 +1,187 @@
+//Pings, and how long the window goes without dispatching.
+//
+//xdg_wm_base pings are answered as soon as they're dispatched. Quoting documentation: "A client
+//must respond to a ping event with a pong request or the client may be deemed unresponsive." So
+//a draw that blocks for seconds leaves pings unanswered, and GNOME asks the user whether to kill
+//the application.
+//
+//The time is cut into waits (blocked on the socket, or polled without blocking) and what's
+//between them, the busy spans. A ping can only wait as long as a busy span lasts. Waiting is
+//idle time: a ping arriving then is read right away. Each busy span is itself cut in two, at
+//the end of every dispatch round (apply_configure):
+//
+//- the application: from the events handed out to the next pump_events, poll_events,
+//  EventLoop::dispatch or prepare_read. Event handlers and drawing, most of the time.
+//- the dispatch: reading and handling the events, key binding and idle callbacks.
+//
+//With Window::set_watchdog, a busy span longer than the threshold is logged and reported as
+//WindowEvent::SlowFrame, with the larger of the two as the cause.
+//
+//The waits are counted too (LoopStats), for checking in the field that an idle window sleeps:
+//with no timer armed, nothing held and the window suspended, nothing should wake it up but the
+//compositor.
+
+use std::time::{Duration, Instant};
+
+use crate::{AppState, Window, WindowEvent};
+
+//Shorter waits didn't block: whatever they read was already in the socket before.
+const BLOCKED: Duration = Duration::from_millis(1);
+
+//What most of a slow busy span went to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowFrameCause {
+    //The application, between dispatches.
+    Application,
+    //Dispatching: the events, key bindings and idle callbacks.
+    Dispatch,
+}
+
+//Dispatch rounds (every pump_events, poll_events, EventLoop::dispatch and external loop
+//dispatch), and how the waits of pump_events and EventLoop::dispatch ended: `wakeups` woken by
+//something to read (the compositor, a proxy, a source), `timeouts` on a deadline (a timer, a
+//repeat, the diagnostic overlay's redraw) or the caller's timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoopStats {
+    pub rounds: u64,
+    pub wakeups: u64,
+    pub timeouts: u64,
+}
+
+pub(crate) struct WatchdogState {
+    threshold: Option<Duration>,
+    //The start of the busy span, the last wait's end.
+    busy_since: Instant,
+    //The application's part of the busy span so far.
+    application: Duration,
+    //The end of the last round, while the application has the events.
+    round_end: Option<Instant>,
+    wait_start: Option<Instant>,
+    //From when a ping read now may have been waiting: the last wait's end if it blocked (the
+    //ping woke it up), else the start of the busy span before it.
+    unread_since: Instant,
+    last_ping_latency: Option<Duration>,
+    pings_answered: u64,
+    stats: LoopStats,
+}
+
+impl Default for WatchdogState {
+    fn default() -> Self {
+        let now = Instant::now();
+        WatchdogState {
+            threshold: None,
+            busy_since: now,
+            application: Duration::ZERO,
+            round_end: None,
+            wait_start: None,
+            unread_since: now,
+            last_ping_latency: None,
+            pings_answered: 0,
+            stats: LoopStats::default(),
+        }
+    }
+}
+
+impl WatchdogState {
+    pub(crate) fn for_reconnect(&self) -> WatchdogState {
+        WatchdogState {
+            threshold: self.threshold,
+            stats: self.stats,
+            ..WatchdogState::default()
+        }
+    }
+
+    //pump_events, poll_events, EventLoop::dispatch and prepare_read, before anything else.
+    pub(crate) fn round_started(&mut self) {
+        if let Some(end) = self.round_end.take() {
+            self.application += end.elapsed();
+        }
+    }
+
+    //The end of apply_configure: the events go to the application.
+    pub(crate) fn round_ended(&mut self) {
+        self.round_end = Some(Instant::now());
+        self.stats.rounds += 1;
+    }
+
+    //A blocking wait returned: `timed_out` with nothing ready.
+    pub(crate) fn woke(&mut self, timed_out: bool) {
+        if timed_out {
+            self.stats.timeouts += 1;
+        } else {
+            self.stats.wakeups += 1;
+        }
+    }
+
+    pub(crate) fn wait_ended(&mut self) {
+        let Some(start) = self.wait_start.take() else {
+            return;
+        };
+        let now = Instant::now();
+        self.unread_since = if now - start >= BLOCKED {
+            now
+        } else {
+            self.busy_since
+        };
+        self.busy_since = now;
+        self.application = Duration::ZERO;
+    }
+
+    pub(crate) fn ping_answered(&mut self) {
+        self.pings_answered += 1;
+        self.last_ping_latency = Some(self.unread_since.elapsed());
+    }
+}
+
+impl AppState {
+    //Right before reading or blocking on the socket: the busy span ends and gets checked.
+    pub(crate) fn wait_started(&mut self) {
+        let watchdog = &mut self.watchdog;
+        if watchdog.wait_start.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        watchdog.wait_start = Some(now);
+        let busy = now - watchdog.busy_since;
+        if watchdog.threshold.is_none_or(|threshold| busy <= threshold) {
+            return;
+        }
+        //The application's part ends here if it still has the events.
+        let application = watchdog.application
+            + watchdog
+                .round_end
+                .map_or(Duration::ZERO, |end| now.saturating_duration_since(end));
+        let cause = if application * 2 >= busy {
+            SlowFrameCause::Application
+        } else {
+            SlowFrameCause::Dispatch
+        };
+        log::warn!(
+            "{busy:?} without reading the socket, {application:?} of it in the application: \
+             pings wait meanwhile, the compositor may call the window unresponsive"
+        );
+        self.events.push(WindowEvent::SlowFrame { busy, cause });
+    }
+}
+
+impl Window {
+    //Off (None) by default. See watchdog.rs: busy spans longer than `threshold` are logged and
+    //sent as WindowEvent::SlowFrame. Compositors usually give pings a few seconds, a threshold
+    //well below that catches the slow frames before the "not responding" dialog does.
+    pub fn set_watchdog(&mut self, threshold: Option<Duration>) {
+        self.state.watchdog.threshold = threshold;
+    }
+
+    //At most how long the last ping waited for its pong, None before the first one.
+    pub fn last_ping_latency(&self) -> Option<Duration> {
+        self.state.watchdog.last_ping_latency
+    }
+
+    pub fn pings_answered(&self) -> u64 {
+        self.state.watchdog.pings_answered
+    }
+
+    pub fn loop_stats(&self) -> LoopStats {
+        self.state.watchdog.stats
+    }
+}
@@ -0,0 +1,44 @@
+use std::{ffi::c_void, ptr::NonNull};
+
+use raw_window_handle::{
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, WaylandDisplayHandle,
+    WaylandWindowHandle, WindowHandle,
+};
+use wayland_client::Proxy;
+
+use crate::Window;
+
+//The handles are plain pointers to the libwayland objects behind our wl_display and wl_surface.
+//They borrow the window, so the surface can't go away while a renderer holds one; a renderer that
+//keeps its own surface (wgpu's Surface<'window>) has to be dropped before the window too.
+//
+//The surface only exists once the compositor global was bound, i.e. after the first
+//pump_events/poll_events. Until then window_handle() says Unavailable.
+//
+//Use RenderMode::External with these, otherwise our gradient gets attached to the same surface.
+impl HasWindowHandle for Window {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let surface = self
+            .state
+            .base_surface
+            .as_ref()
+            .ok_or(HandleError::Unavailable)?;
+        let surface_ptr =
+            NonNull::new(surface.id().as_ptr() as *mut c_void).ok_or(HandleError::Unavailable)?;
+
+        let handle = WaylandWindowHandle::new(surface_ptr);
+        //SAFETY: the wl_surface lives as long as the window, which the handle borrows.
+        Ok(unsafe { WindowHandle::borrow_raw(handle.into()) })
+    }
+}
+
+impl HasDisplayHandle for Window {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        let display_ptr = NonNull::new(self.connection.backend().display_ptr() as *mut c_void)
+            .ok_or(HandleError::Unavailable)?;
+
+        let handle = WaylandDisplayHandle::new(display_ptr);
+        //SAFETY: the connection (and its wl_display) lives as long as the window.
+        Ok(unsafe { DisplayHandle::borrow_raw(handle.into()) })
+    }
+}
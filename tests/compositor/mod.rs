@@ -0,0 +1,1138 @@
+//A tiny compositor running in the test process, on the server half of wayland-backend (the crate
+//the client side runs on too). It advertises wl_compositor, wl_shm, wl_seat, xdg_wm_base,
+//wl_output, wp_color_manager_v1, ext_idle_notifier_v1, wp_cursor_shape_manager_v1,
+//zwp_relative_pointer_manager_v1, zwp_pointer_constraints_v1, wl_subcompositor, wp_viewporter,
+//xdg_activation_v1 and wl_fixes, writes down every request the client makes and sends whatever
+//events a test scripts. Nothing is ever drawn: the tests look at the requests.
+//
+//It runs on its own thread, so a window can block in pump_events while events are on their way.
+//
+//A test runs its window through a Driver: poll_events, what pump_events does without waiting, or
+//rounds of an EventLoop (the calloop feature). window.rs runs every test with the first,
+//event_loop.rs the same tests with the second.
+//
+//Like a real compositor, it kills a client attaching a buffer before acking a configure, and it
+//answers an activation token's commit with ACTIVATION_TOKEN by itself (unless told to withhold
+//tokens): the client may be blocking on it.
+
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    io::Write,
+    ops::{Deref, DerefMut},
+    os::fd::AsRawFd,
+    os::unix::net::UnixStream,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+#[cfg(feature = "calloop")]
+use simple_wayland_window::EventLoop;
+use simple_wayland_window::{EventSide, Key, Window, WindowEvent, WindowOptions};
+#[cfg(feature = "record")]
+use simple_wayland_window::{Recorded, Recording};
+use wayland_backend::{
+    protocol::{Argument, Message},
+    server::{
+        Backend, ClientData, ClientId, GlobalHandler, GlobalId, Handle, ObjectData, ObjectId,
+    },
+};
+use wayland_client::{
+    Connection, Proxy,
+    protocol::{
+        wl_compositor::WlCompositor, wl_output::WlOutput, wl_seat::WlSeat, wl_shm::WlShm,
+        wl_subcompositor::WlSubcompositor,
+    },
+};
+use wayland_protocols::{
+    ext::idle_notify::v1::client::ext_idle_notifier_v1::ExtIdleNotifierV1,
+    wp::{
+        color_management::v1::client::wp_color_manager_v1::WpColorManagerV1,
+        cursor_shape::v1::client::wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+        pointer_constraints::zv1::client::zwp_pointer_constraints_v1::ZwpPointerConstraintsV1,
+        relative_pointer::zv1::client::zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1,
+        viewporter::client::wp_viewporter::WpViewporter,
+    },
+    xdg::{
+        activation::v1::client::xdg_activation_v1::XdgActivationV1,
+        shell::client::xdg_wm_base::XdgWmBase,
+    },
+};
+
+//wl_fixes, newer than wayland-client's own protocol, from the crate's protocols/.
+mod fixes {
+    use wayland_client::protocol::__interfaces::*;
+    wayland_scanner::generate_interfaces!("protocols/wl-fixes.xml");
+}
+
+//How long wait_for gives the client before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+//Name of the seat.
+pub const SEAT: &str = "seat0";
+//Name of the one output, a scale 2 one.
+pub const OUTPUT: &str = "TEST-1";
+//Its refresh rate until output_mode, in mHz.
+pub const OUTPUT_REFRESH: i32 = 60000;
+//The token every xdg_activation_token_v1 gets.
+pub const ACTIVATION_TOKEN: &str = "test-activation-token";
+
+//A request argument, with object ids as their protocol ids.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Uint(u32),
+    Fixed(f64),
+    Str(Option<String>),
+    Object(u32),
+    NewId(u32),
+    Array(Vec<u8>),
+    Fd,
+}
+
+impl Arg {
+    //The object a NewId made, as the requests naming it later carry it.
+    pub fn as_object(&self) -> Arg {
+        match *self {
+            Arg::NewId(id) => Arg::Object(id),
+            ref arg => panic!("not a new id: {arg:?}"),
+        }
+    }
+
+    fn from_argument<F>(argument: Argument<ObjectId, F>) -> Arg {
+        match argument {
+            Argument::Int(value) => Arg::Int(value),
+            Argument::Uint(value) => Arg::Uint(value),
+            Argument::Fixed(value) => Arg::Fixed(f64::from(value) / 256.0),
+            Argument::Str(value) => Arg::Str(value.map(|s| s.to_string_lossy().into_owned())),
+            Argument::Object(id) => Arg::Object(id.protocol_id()),
+            Argument::NewId(id) => Arg::NewId(id.protocol_id()),
+            Argument::Array(value) => Arg::Array(*value),
+            Argument::Fd(_) => Arg::Fd,
+        }
+    }
+}
+
+//One request, as "interface.request" and its arguments. Binds show up as wl_registry.bind with
+//the interface name and version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    //The protocol id of the object it was sent on, of the object bound for binds.
+    pub object: u32,
+    pub interface: &'static str,
+    pub name: &'static str,
+    pub args: Vec<Arg>,
+}
+
+impl Request {
+    pub fn is(&self, interface: &str, name: &str) -> bool {
+        self.interface == interface && self.name == name
+    }
+}
+
+//What runs a window in a test, one round at a time.
+pub trait Driver {
+    fn window(&mut self) -> &mut Window;
+    //The events that came in, without waiting for any.
+    fn round(&mut self) -> Vec<WindowEvent>;
+}
+
+impl Driver for Window {
+    fn window(&mut self) -> &mut Window {
+        self
+    }
+
+    fn round(&mut self) -> Vec<WindowEvent> {
+        self.poll_events()
+    }
+}
+
+#[cfg(feature = "calloop")]
+impl Driver for EventLoop {
+    fn window(&mut self) -> &mut Window {
+        self.window_mut()
+    }
+
+    fn round(&mut self) -> Vec<WindowEvent> {
+        let mut events = Vec::new();
+        self.dispatch(Some(Duration::ZERO), &mut |event, _| events.push(event));
+        events
+    }
+}
+
+//The event side of a split window (render_thread.rs), polled.
+impl Driver for EventSide {
+    fn window(&mut self) -> &mut Window {
+        self
+    }
+
+    fn round(&mut self) -> Vec<WindowEvent> {
+        self.poll_events()
+    }
+}
+
+//A window with the driver the test binary runs it with, and everything else of the Window
+//through Deref.
+//
+//In debug builds its drop is also a leak check. The connection outlives the window (Driven keeps
+//a clone), so what the window didn't destroy is still there in the compositor: once the window
+//is gone, the objects left must be no more than TEARDOWN_BASELINE, plus a WINDOW_BASELINE for
+//each child window made through it and whatever the test said it leaves.
+pub struct Driven {
+    //None once taken, by into_event_loop or the drop.
+    run: Option<Run>,
+    connection: Connection,
+    //What may be left once everything is dropped.
+    expected: Vec<&'static str>,
+    //Weak, the compositor going first has to close the socket like it always did.
+    server: Weak<Mutex<Server>>,
+}
+
+enum Run {
+    Polled(Window),
+    #[cfg(feature = "calloop")]
+    Looped(EventLoop),
+}
+
+//What a window can't take down with it: the globals without a destructor at the versions
+//advertised, and the registry, which wl_fixes.destroy_registry only writes down here. Every
+//window on a connection binds its own.
+pub const WINDOW_BASELINE: [&str; 3] = ["wl_compositor", "wl_registry", "wl_shm"];
+
+//A connection whose one window is gone: that window's WINDOW_BASELINE and the display. Frame
+//callbacks this compositor never answers aren't counted, they're its to destroy.
+pub const TEARDOWN_BASELINE: [&str; 4] = ["wl_compositor", "wl_display", "wl_registry", "wl_shm"];
+
+impl Driven {
+    //`window` on `connection`, one of this compositor's.
+    pub fn new(
+        compositor: &TestCompositor,
+        connection: Connection,
+        window: Window,
+        event_loop: bool,
+    ) -> Driven {
+        let run = match event_loop {
+            #[cfg(feature = "calloop")]
+            true => Run::Looped(window.into_event_loop().unwrap()),
+            _ => Run::Polled(window),
+        };
+        Driven {
+            run: Some(run),
+            connection,
+            expected: TEARDOWN_BASELINE.to_vec(),
+            server: Arc::downgrade(&compositor.server),
+        }
+    }
+
+    //Window::create_child_dialog of ours, its WINDOW_BASELINE expected at the leak check. It has
+    //to be dropped before us.
+    pub fn child_dialog(&mut self, options: WindowOptions) -> Window {
+        let dialog = Window::create_child_dialog(self, options).unwrap();
+        self.expected.extend(WINDOW_BASELINE);
+        dialog
+    }
+
+    //The same with Window::create_child_window.
+    pub fn child_window(&mut self, options: WindowOptions) -> Window {
+        let child = Window::create_child_window(self, options).unwrap();
+        self.expected.extend(WINDOW_BASELINE);
+        child
+    }
+
+    //Objects the test made on our connection itself and can't destroy, like a registry.
+    pub fn leaves(&mut self, interfaces: &[&'static str]) {
+        self.expected.extend(interfaces);
+    }
+
+    //For the tests of the EventLoop itself, whatever the binary. No leak check then.
+    #[cfg(feature = "calloop")]
+    pub fn into_event_loop(mut self) -> EventLoop {
+        match self.run.take().unwrap() {
+            Run::Polled(window) => window.into_event_loop().unwrap(),
+            Run::Looped(event_loop) => event_loop,
+        }
+    }
+}
+
+impl Deref for Driven {
+    type Target = Window;
+
+    fn deref(&self) -> &Window {
+        match self.run.as_ref().unwrap() {
+            Run::Polled(window) => window,
+            #[cfg(feature = "calloop")]
+            Run::Looped(event_loop) => event_loop.window(),
+        }
+    }
+}
+
+impl DerefMut for Driven {
+    fn deref_mut(&mut self) -> &mut Window {
+        match self.run.as_mut().unwrap() {
+            Run::Polled(window) => window,
+            #[cfg(feature = "calloop")]
+            Run::Looped(event_loop) => event_loop.window_mut(),
+        }
+    }
+}
+
+impl Driver for Driven {
+    fn window(&mut self) -> &mut Window {
+        self
+    }
+
+    fn round(&mut self) -> Vec<WindowEvent> {
+        match self.run.as_mut().unwrap() {
+            Run::Polled(window) => window.round(),
+            #[cfg(feature = "calloop")]
+            Run::Looped(event_loop) => event_loop.round(),
+        }
+    }
+}
+
+impl Drop for Driven {
+    fn drop(&mut self) {
+        let Some(run) = self.run.take() else {
+            return;
+        };
+        drop(run);
+        if !cfg!(debug_assertions) || thread::panicking() {
+            return;
+        }
+        //Gone with the compositor, or with a protocol error: nothing to count.
+        let Some(server) = self.server.upgrade() else {
+            return;
+        };
+        //Everything the drop sent is in once the compositor answered after it.
+        if self.connection.roundtrip().is_err() {
+            return;
+        }
+        let mut left = live_objects(&server);
+        left.retain(|interface| *interface != "wl_callback");
+        for interface in self.expected.drain(..) {
+            if let Some(index) = left.iter().position(|left| *left == interface) {
+                left.remove(index);
+            }
+        }
+        assert!(left.is_empty(), "teardown left {left:?} behind");
+    }
+}
+
+#[derive(Default)]
+struct State {
+    requests: Vec<Request>,
+    //The newest object of each interface, for the events sent to them.
+    objects: HashMap<&'static str, ObjectId>,
+    //Activation tokens are never answered, see withhold_activation_tokens.
+    withhold_tokens: bool,
+}
+
+struct Server {
+    backend: Backend<State>,
+    state: State,
+    serial: u32,
+    //For remove_global.
+    globals: HashMap<&'static str, GlobalId>,
+}
+
+pub struct TestCompositor {
+    server: Arc<Mutex<Server>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestCompositor {
+    //The compositor and a client connection to it.
+    pub fn new() -> (TestCompositor, Connection) {
+        let (compositor, socket) = Self::with_socket();
+        (compositor, Connection::from_socket(socket).unwrap())
+    }
+
+    //The same with the client's end of the socket left as it is.
+    pub fn with_socket() -> (TestCompositor, UnixStream) {
+        Self::with_globals(&[], &[])
+    }
+
+    //With the named globals advertised before the others, in that order.
+    pub fn with_global_order(first: &[&str]) -> (TestCompositor, Connection) {
+        let (compositor, socket) = Self::with_globals(first, &[]);
+        (compositor, Connection::from_socket(socket).unwrap())
+    }
+
+    //Without the named globals, as a compositor lacking them. The client's end of the socket.
+    pub fn without_globals(left_out: &[&str]) -> (TestCompositor, UnixStream) {
+        Self::with_globals(&[], left_out)
+    }
+
+    fn with_globals(first: &[&str], left_out: &[&str]) -> (TestCompositor, UnixStream) {
+        let backend = Backend::<State>::new().unwrap();
+        let mut handle = backend.handle();
+        let mut globals = HashMap::new();
+        let mut advertised = [
+            (WlCompositor::interface(), 6),
+            (WlShm::interface(), 1),
+            (WlSeat::interface(), 7),
+            (XdgWmBase::interface(), 6),
+            (WlOutput::interface(), 4),
+            (WpColorManagerV1::interface(), 1),
+            (ExtIdleNotifierV1::interface(), 1),
+            (WpCursorShapeManagerV1::interface(), 1),
+            (ZwpRelativePointerManagerV1::interface(), 1),
+            (ZwpPointerConstraintsV1::interface(), 1),
+            (WlSubcompositor::interface(), 1),
+            (WpViewporter::interface(), 1),
+            (XdgActivationV1::interface(), 1),
+            (&fixes::WL_FIXES_INTERFACE, 1),
+        ];
+        advertised.sort_by_key(|(interface, _)| {
+            first
+                .iter()
+                .position(|name| *name == interface.name)
+                .unwrap_or(first.len())
+        });
+        for (interface, version) in advertised {
+            if left_out.contains(&interface.name) {
+                continue;
+            }
+            let global = handle.create_global::<State>(interface, version, Arc::new(Global));
+            globals.insert(interface.name, global);
+        }
+
+        let (client_socket, server_socket) = UnixStream::pair().unwrap();
+        handle
+            .insert_client(server_socket, Arc::new(Client))
+            .unwrap();
+        let server = Arc::new(Mutex::new(Server {
+            backend,
+            state: State::default(),
+            serial: 0,
+            globals,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let (server, stop) = (server.clone(), stop.clone());
+            move || run(&server, &stop)
+        });
+
+        (
+            TestCompositor {
+                server,
+                stop,
+                thread: Some(thread),
+            },
+            client_socket,
+        )
+    }
+
+    //Every request so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.server.lock().unwrap().state.requests.clone()
+    }
+
+    //The arguments of every `interface.name` request so far.
+    pub fn requests_of(&self, interface: &str, name: &str) -> Vec<Vec<Arg>> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.is(interface, name))
+            .map(|request| request.args)
+            .collect()
+    }
+
+    //Waits until `interface.name` was requested at least `count` times. The client has to flush
+    //its requests first (Window::flush, or a dispatch that does).
+    pub fn wait_for(&self, interface: &str, name: &str, count: usize) {
+        let start = Instant::now();
+        while self.requests_of(interface, name).len() < count {
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "no {interface}.{name} came, requests were: {:#?}",
+                self.requests()
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    //Reads what the client sent so far, right away. A flush is done writing by the time it
+    //returns, so a request missing after this was never flushed.
+    pub fn settle(&self) {
+        let mut server = self.server.lock().unwrap();
+        let Server { backend, state, .. } = &mut *server;
+        let _ = backend.dispatch_all_clients(state);
+    }
+
+    //Runs the window (a round of its driver, then a flush for what it asked meanwhile) until
+    //`done` says so. Returns the window events of the way.
+    pub fn run_until(
+        &self,
+        driver: &mut impl Driver,
+        done: impl Fn(&Window, &[Request]) -> bool,
+    ) -> Vec<WindowEvent> {
+        let start = Instant::now();
+        let mut events = Vec::new();
+        loop {
+            events.extend(driver.round());
+            let window = driver.window();
+            window.flush().unwrap();
+            if done(window, &self.requests()) {
+                return events;
+            }
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "timed out, events were: {events:#?}\nrequests were: {:#?}",
+                self.requests()
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    //An xdg_toplevel configure and the xdg_surface configure that applies it. Returns the serial
+    //to be acked.
+    pub fn configure(&self, width: i32, height: i32, states: &[u32]) -> u32 {
+        let serial = self.next_serial();
+        let states = states
+            .iter()
+            .flat_map(|state| state.to_ne_bytes())
+            .collect();
+        self.send(
+            "xdg_toplevel",
+            0,
+            vec![
+                Argument::Int(width),
+                Argument::Int(height),
+                Argument::Array(Box::new(states)),
+            ],
+        );
+        self.send("xdg_surface", 0, vec![Argument::Uint(serial)]);
+        serial
+    }
+
+    pub fn close(&self) {
+        self.send("xdg_toplevel", 1, vec![]);
+    }
+
+    //A fatal protocol error on the client's newest object of `interface`, the connection goes.
+    pub fn post_error(&self, interface: &str, code: u32, message: &str) {
+        let object = self.object(interface);
+        let mut server = self.server.lock().unwrap();
+        server
+            .backend
+            .handle()
+            .post_error(object, code, CString::new(message).unwrap());
+        server.backend.flush(None).unwrap();
+    }
+
+    //Returns the serial the pong should carry.
+    pub fn ping(&self) -> u32 {
+        let serial = self.next_serial();
+        self.send("xdg_wm_base", 0, vec![Argument::Uint(serial)]);
+        serial
+    }
+
+    //Keyboard focus on our surface, with nothing held.
+    pub fn keyboard_enter(&self) {
+        self.keyboard_enter_holding(&[]);
+    }
+
+    //Keyboard focus arriving with `keys` already down, as after alt-tabbing in while holding
+    //them.
+    pub fn keyboard_enter_holding(&self, keys: &[Key]) {
+        let serial = self.next_serial();
+        let surface = self.object("wl_surface");
+        let keys: Vec<u8> = keys
+            .iter()
+            .flat_map(|key| key.evdev().to_ne_bytes())
+            .collect();
+        self.send(
+            "wl_keyboard",
+            1,
+            vec![
+                Argument::Uint(serial),
+                Argument::Object(surface),
+                Argument::Array(Box::new(keys)),
+            ],
+        );
+    }
+
+    pub fn keyboard_leave(&self) {
+        let serial = self.next_serial();
+        let surface = self.object("wl_surface");
+        self.send(
+            "wl_keyboard",
+            2,
+            vec![Argument::Uint(serial), Argument::Object(surface)],
+        );
+    }
+
+    pub fn key(&self, key: Key, pressed: bool) {
+        let serial = self.next_serial();
+        self.send(
+            "wl_keyboard",
+            3,
+            vec![
+                Argument::Uint(serial),
+                Argument::Uint(0),
+                Argument::Uint(key.evdev()),
+                Argument::Uint(pressed as u32),
+            ],
+        );
+    }
+
+    //Keys repeat `rate` times a second after `delay` ms, 0 turning repeat off. Only the EventLoop
+    //repeats them.
+    #[cfg(feature = "calloop")]
+    pub fn repeat_info(&self, rate: i32, delay: i32) {
+        self.send(
+            "wl_keyboard",
+            5,
+            vec![Argument::Int(rate), Argument::Int(delay)],
+        );
+    }
+
+    //An XKB keymap, from a file like compositors send it.
+    pub fn keymap(&self, text: &str) {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        self.send(
+            "wl_keyboard",
+            0,
+            vec![
+                Argument::Uint(1),
+                Argument::Fd(file.as_raw_fd()),
+                Argument::Uint(text.len() as u32),
+            ],
+        );
+    }
+
+    //No modifiers down, layout `group` active.
+    pub fn layout_group(&self, group: u32) {
+        let serial = self.next_serial();
+        self.send(
+            "wl_keyboard",
+            4,
+            vec![
+                Argument::Uint(serial),
+                Argument::Uint(0),
+                Argument::Uint(0),
+                Argument::Uint(0),
+                Argument::Uint(group),
+            ],
+        );
+    }
+
+    //`locked` (a real modifier mask, Mod2 = 1 << 4 is NumLock) locked, nothing down, layout 0.
+    pub fn locked_modifiers(&self, locked: u32) {
+        let serial = self.next_serial();
+        self.send(
+            "wl_keyboard",
+            4,
+            vec![
+                Argument::Uint(serial),
+                Argument::Uint(0),
+                Argument::Uint(0),
+                Argument::Uint(locked),
+                Argument::Uint(0),
+            ],
+        );
+    }
+
+    //Relative motion on the newest relative pointer, unaccelerated the same.
+    pub fn relative_motion(&self, dx: f64, dy: f64) {
+        self.send(
+            "zwp_relative_pointer_v1",
+            0,
+            vec![
+                Argument::Uint(0),
+                Argument::Uint(self.next_serial()),
+                fixed(dx),
+                fixed(dy),
+                fixed(dx),
+                fixed(dy),
+            ],
+        );
+    }
+
+    //The newest locked pointer is in effect.
+    pub fn pointer_locked(&self) {
+        self.send("zwp_locked_pointer_v1", 0, Vec::new());
+    }
+
+    //The pointer comes onto our surface at x, y (surface-local).
+    pub fn pointer_enter(&self, x: f64, y: f64) {
+        let serial = self.next_serial();
+        let surface = self.object("wl_surface");
+        self.send(
+            "wl_pointer",
+            0,
+            vec![
+                Argument::Uint(serial),
+                Argument::Object(surface),
+                fixed(x),
+                fixed(y),
+            ],
+        );
+        self.send("wl_pointer", 5, vec![]);
+    }
+
+    pub fn pointer_leave(&self) {
+        let serial = self.next_serial();
+        let surface = self.object("wl_surface");
+        self.send(
+            "wl_pointer",
+            1,
+            vec![Argument::Uint(serial), Argument::Object(surface)],
+        );
+        self.send("wl_pointer", 5, vec![]);
+    }
+
+    pub fn pointer_motion(&self, x: f64, y: f64) {
+        self.send("wl_pointer", 2, vec![Argument::Uint(0), fixed(x), fixed(y)]);
+        self.send("wl_pointer", 5, vec![]);
+    }
+
+    //A button (BTN_LEFT is 0x110) goes down or up, returns its serial.
+    pub fn pointer_button(&self, button: u32, pressed: bool) -> u32 {
+        let serial = self.next_serial();
+        self.send(
+            "wl_pointer",
+            3,
+            vec![
+                Argument::Uint(serial),
+                Argument::Uint(0),
+                Argument::Uint(button),
+                Argument::Uint(pressed as u32),
+            ],
+        );
+        self.send("wl_pointer", 5, vec![]);
+        serial
+    }
+
+    //One wheel detent on the vertical axis, the way version 7 pointers send it: source, discrete
+    //steps, value and a frame.
+    pub fn wheel(&self, detents: i32) {
+        self.send("wl_pointer", 6, vec![Argument::Uint(0)]);
+        self.send(
+            "wl_pointer",
+            8,
+            vec![Argument::Uint(0), Argument::Int(detents)],
+        );
+        self.send(
+            "wl_pointer",
+            4,
+            vec![
+                Argument::Uint(0),
+                Argument::Uint(0),
+                fixed(15.0 * f64::from(detents)),
+            ],
+        );
+        self.send("wl_pointer", 5, vec![]);
+    }
+
+    //Finger scrolling, both axes in one frame.
+    pub fn finger_scroll(&self, dx: f64, dy: f64) {
+        self.send("wl_pointer", 6, vec![Argument::Uint(1)]);
+        self.send(
+            "wl_pointer",
+            4,
+            vec![Argument::Uint(0), Argument::Uint(1), fixed(dx)],
+        );
+        self.send(
+            "wl_pointer",
+            4,
+            vec![Argument::Uint(0), Argument::Uint(0), fixed(dy)],
+        );
+        self.send("wl_pointer", 5, vec![]);
+    }
+
+    //Feeds a recording's inputs to the window, running it wherever the recording has an ack or
+    //a commit until it did as many, so what came in one dispatch comes in one again. Returns
+    //the window's own recording of it, to compare the effects with.
+    #[cfg(feature = "record")]
+    pub fn replay(&self, driver: &mut impl Driver, recording: &Recording) -> Recording {
+        driver.window().start_recording();
+        let mut effects = 0;
+        for (_, entry) in &recording.entries {
+            if !entry.is_input() {
+                effects += 1;
+                self.run_until(driver, |window, _| {
+                    window
+                        .recording()
+                        .is_some_and(|recording| recording.effects().count() >= effects)
+                });
+                continue;
+            }
+            match *entry {
+                Recorded::Configure {
+                    width,
+                    height,
+                    ref states,
+                } => {
+                    self.configure(width, height, states);
+                }
+                Recorded::Close => self.close(),
+                Recorded::SurfaceEnter => self.surface_enter(),
+                Recorded::SurfaceLeave => {
+                    let output = self.object("wl_output");
+                    self.send("wl_surface", 1, vec![Argument::Object(output)]);
+                }
+                Recorded::OutputScale(scale) => {
+                    self.send("wl_output", 3, vec![Argument::Int(scale)]);
+                    self.send("wl_output", 2, vec![]);
+                }
+                Recorded::PreferredScale(scale) => self.preferred_scale(scale),
+                Recorded::KeyboardEnter => self.keyboard_enter(),
+                Recorded::KeyboardLeave => self.keyboard_leave(),
+                Recorded::Key { key, pressed } => self.key(Key::from_evdev(key), pressed),
+                Recorded::PointerEnter { x, y } => self.pointer_enter(x, y),
+                Recorded::PointerLeave => {
+                    let serial = self.next_serial();
+                    let surface = self.object("wl_surface");
+                    self.send(
+                        "wl_pointer",
+                        1,
+                        vec![Argument::Uint(serial), Argument::Object(surface)],
+                    );
+                    self.send("wl_pointer", 5, vec![]);
+                }
+                Recorded::PointerMotion { x, y } => self.pointer_motion(x, y),
+                Recorded::PointerButton { button, pressed } => {
+                    self.pointer_button(button, pressed);
+                }
+                Recorded::AckConfigure | Recorded::Commit { .. } => unreachable!(),
+            }
+        }
+        //Whatever came after the last effect.
+        driver.round();
+        driver.window().stop_recording()
+    }
+
+    //The surface is on the output now.
+    pub fn surface_enter(&self) {
+        let output = self.object("wl_output");
+        self.send("wl_surface", 0, vec![Argument::Object(output)]);
+    }
+
+    //wl_surface.preferred_buffer_scale and preferred_buffer_transform (v6), the transform being a
+    //wl_output.transform value.
+    pub fn preferred_scale(&self, scale: i32) {
+        self.send("wl_surface", 2, vec![Argument::Int(scale)]);
+    }
+
+    pub fn preferred_transform(&self, transform: u32) {
+        self.send("wl_surface", 3, vec![Argument::Uint(transform)]);
+    }
+
+    //The output switches to a mode with another refresh rate.
+    pub fn output_mode(&self, refresh_mhz: i32) {
+        self.send("wl_output", 1, mode_args(refresh_mhz));
+        self.send("wl_output", 2, vec![]);
+    }
+
+    //The newest image description is ready, under that identity.
+    #[cfg(feature = "color-management")]
+    pub fn image_description_ready(&self, identity: u32) {
+        self.send("wp_image_description_v1", 1, vec![Argument::Uint(identity)]);
+    }
+
+    //The surface's preferred image description changed.
+    #[cfg(feature = "color-management")]
+    pub fn preferred_changed(&self, identity: u32) {
+        self.send(
+            "wp_color_management_surface_feedback_v1",
+            0,
+            vec![Argument::Uint(identity)],
+        );
+    }
+
+    //The information about the newest image description: BT.2020 and PQ, for a display of
+    //0.005 to 1000 cd/m².
+    #[cfg(feature = "color-management")]
+    pub fn hdr_image_description_info(&self) {
+        let uint = |values: &[u32]| values.iter().map(|value| Argument::Uint(*value)).collect();
+        for (opcode, args) in [
+            (3, uint(&[6])),
+            (5, uint(&[11])),
+            (6, uint(&[50, 10000, 203])),
+            (8, uint(&[50, 1000])),
+            (0, vec![]),
+        ] {
+            self.send("wp_image_description_info_v1", opcode, args);
+        }
+    }
+
+    //The newest idle notification goes idle, or comes back.
+    pub fn idle(&self, idle: bool) {
+        self.send("ext_idle_notification_v1", if idle { 0 } else { 1 }, vec![]);
+    }
+
+    //Activation tokens committed from now on never get their done, as with a compositor that
+    //doesn't answer.
+    pub fn withhold_activation_tokens(&self) {
+        self.server.lock().unwrap().state.withhold_tokens = true;
+    }
+
+    //The global goes away, as a seat does when its devices are unplugged.
+    pub fn remove_global(&self, interface: &str) {
+        let mut server = self.server.lock().unwrap();
+        let global = server.globals.remove(interface).unwrap();
+        server.backend.handle().remove_global::<State>(global);
+        server.backend.flush(None).unwrap();
+    }
+
+    //Interfaces of the objects the client still has here, sorted. wl_fixes.destroy_registry is
+    //only written down: wayland-backend's server can't destroy an object for the client.
+    pub fn live_objects(&self) -> Vec<&'static str> {
+        live_objects(&self.server)
+    }
+
+    fn next_serial(&self) -> u32 {
+        let mut server = self.server.lock().unwrap();
+        server.serial += 1;
+        server.serial
+    }
+
+    fn object(&self, interface: &str) -> ObjectId {
+        let server = self.server.lock().unwrap();
+        match server.state.objects.get(interface) {
+            Some(object) => object.clone(),
+            None => panic!("the client has no {interface} yet"),
+        }
+    }
+
+    //Sends an event and flushes it right away.
+    fn send(&self, interface: &str, opcode: u16, args: Vec<Argument<ObjectId, i32>>) {
+        let sender_id = self.object(interface);
+        let mut server = self.server.lock().unwrap();
+        server
+            .backend
+            .handle()
+            .send_event(Message {
+                sender_id,
+                opcode,
+                args: args.into_iter().collect(),
+            })
+            .unwrap();
+        server.backend.flush(None).unwrap();
+    }
+}
+
+impl Drop for TestCompositor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn live_objects(server: &Mutex<Server>) -> Vec<&'static str> {
+    let server = server.lock().unwrap();
+    let handle = server.backend.handle();
+    let mut clients = Vec::new();
+    handle.with_all_clients(|client| clients.push(client));
+    let mut live = Vec::new();
+    for client in clients {
+        let _ = handle.with_all_objects_for(client, |object| {
+            live.push(object.interface().name);
+        });
+    }
+    live.sort_unstable();
+    live
+}
+
+//Dispatches the client's requests as they come. The poll timeout is there to notice `stop`.
+fn run(server: &Mutex<Server>, stop: &AtomicBool) {
+    let poll_fd = server
+        .lock()
+        .unwrap()
+        .backend
+        .poll_fd()
+        .try_clone_to_owned()
+        .unwrap();
+    let timeout = Timespec::try_from(Duration::from_millis(5)).unwrap();
+
+    while !stop.load(Ordering::Relaxed) {
+        let mut fds = [PollFd::new(&poll_fd, PollFlags::IN)];
+        let _ = poll(&mut fds, Some(&timeout));
+
+        let mut server = server.lock().unwrap();
+        let Server { backend, state, .. } = &mut *server;
+        //Errors here are the client going away, which the teardown tests do on purpose.
+        let _ = backend.dispatch_all_clients(state);
+        let _ = backend.flush(None);
+    }
+}
+
+struct Client;
+
+impl ClientData for Client {}
+
+struct Global;
+
+impl GlobalHandler<State> for Global {
+    fn bind(
+        self: Arc<Self>,
+        handle: &Handle,
+        state: &mut State,
+        _: ClientId,
+        _: GlobalId,
+        object: ObjectId,
+    ) -> Arc<dyn ObjectData<State>> {
+        let interface = object.interface();
+        let version = handle.object_info(object.clone()).unwrap().version;
+        state.requests.push(Request {
+            object: object.protocol_id(),
+            interface: "wl_registry",
+            name: "bind",
+            args: vec![Arg::Str(Some(interface.name.into())), Arg::Uint(version)],
+        });
+
+        //A seat called SEAT with a pointer and a keyboard.
+        if interface.name == "wl_seat" {
+            handle
+                .send_event(Message {
+                    sender_id: object.clone(),
+                    opcode: 0,
+                    args: [Argument::Uint(3)].into_iter().collect(),
+                })
+                .unwrap();
+            let name = CString::new(SEAT).unwrap();
+            handle
+                .send_event(Message {
+                    sender_id: object.clone(),
+                    opcode: 1,
+                    args: [Argument::Str(Some(Box::new(name)))].into_iter().collect(),
+                })
+                .unwrap();
+        }
+        //Its current mode, scale and name, then done.
+        if interface.name == "wl_output" {
+            let name = CString::new(OUTPUT).unwrap();
+            for (opcode, args) in [
+                (1, mode_args(OUTPUT_REFRESH)),
+                (3, vec![Argument::Int(2)]),
+                (4, vec![Argument::Str(Some(Box::new(name)))]),
+                (2, vec![]),
+            ] {
+                handle
+                    .send_event(Message {
+                        sender_id: object.clone(),
+                        opcode,
+                        args: args.into_iter().collect(),
+                    })
+                    .unwrap();
+            }
+        }
+        //Parametric descriptions of sRGB or BT.2020 primaries, sRGB or PQ encoded, perceptual
+        //intent only.
+        if interface.name == "wp_color_manager_v1" {
+            for (opcode, value) in [(0, 0), (1, 1), (2, 9), (2, 11), (3, 1), (3, 6)] {
+                handle
+                    .send_event(Message {
+                        sender_id: object.clone(),
+                        opcode,
+                        args: [Argument::Uint(value)].into_iter().collect(),
+                    })
+                    .unwrap();
+            }
+            handle
+                .send_event(Message {
+                    sender_id: object.clone(),
+                    opcode: 4,
+                    args: Default::default(),
+                })
+                .unwrap();
+        }
+        state.objects.insert(interface.name, object);
+        Arc::new(Recorder)
+    }
+}
+
+//Object data of every object: writes the request down and remembers the objects created.
+struct Recorder;
+
+impl ObjectData<State> for Recorder {
+    fn request(
+        self: Arc<Self>,
+        handle: &Handle,
+        state: &mut State,
+        _: ClientId,
+        message: Message<ObjectId, std::os::fd::OwnedFd>,
+    ) -> Option<Arc<dyn ObjectData<State>>> {
+        let interface = message.sender_id.interface();
+        let name = interface.requests[message.opcode as usize].name;
+
+        //xdg_surface's unconfigured_buffer error, for any buffer before the first ack.
+        if (interface.name, name) == ("wl_surface", "attach")
+            && matches!(&message.args[0], Argument::Object(buffer) if !buffer.is_null())
+            && !state
+                .requests
+                .iter()
+                .any(|request| request.is("xdg_surface", "ack_configure"))
+            && let Some(xdg_surface) = state.objects.get("xdg_surface")
+        {
+            handle.post_error(
+                xdg_surface.clone(),
+                3,
+                CString::new("buffer attached before the first configure").unwrap(),
+            );
+        }
+
+        if (interface.name, name) == ("xdg_activation_token_v1", "commit") && !state.withhold_tokens
+        {
+            let token = CString::new(ACTIVATION_TOKEN).unwrap();
+            handle
+                .send_event(Message {
+                    sender_id: message.sender_id.clone(),
+                    opcode: 0,
+                    args: [Argument::Str(Some(Box::new(token)))].into_iter().collect(),
+                })
+                .unwrap();
+        }
+
+        let mut created = false;
+        for argument in &message.args {
+            if let Argument::NewId(id) = argument {
+                state.objects.insert(id.interface().name, id.clone());
+                created = true;
+            }
+        }
+
+        state.requests.push(Request {
+            object: message.sender_id.protocol_id(),
+            interface: interface.name,
+            name,
+            args: message.args.into_iter().map(Arg::from_argument).collect(),
+        });
+        created.then_some(self as Arc<dyn ObjectData<State>>)
+    }
+
+    fn destroyed(self: Arc<Self>, _: &Handle, _: &mut State, _: ClientId, _: ObjectId) {}
+}
+
+//wl_output.mode of a current 1920x1080 mode.
+fn mode_args(refresh_mhz: i32) -> Vec<Argument<ObjectId, i32>> {
+    vec![
+        Argument::Uint(1),
+        Argument::Int(1920),
+        Argument::Int(1080),
+        Argument::Int(refresh_mhz),
+    ]
+}
+
+//wl_fixed_t, 24.8 fixed point.
+fn fixed(value: f64) -> Argument<ObjectId, i32> {
+    Argument::Fixed((value * 256.0) as i32)
+}
+
+//Makes sure set_title and friends made it through as the strings they were.
+pub fn string(args: &[Arg]) -> &str {
+    match args {
+        [Arg::Str(Some(value)), ..] => value,
+        _ => panic!("not a string request: {args:?}"),
+    }
+}
@@ -0,0 +1,6 @@
+//The tests of window.rs again, every window they start run by an EventLoop instead of
+//poll_events: both loops answer the compositor the same way. Only with the calloop feature.
+#![cfg(feature = "calloop")]
+
+#[path = "window.rs"]
+mod window;
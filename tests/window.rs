@@ -0,0 +1,2641 @@
+//The window against the test compositor in tests/compositor: what it asks for at startup, how it
+//answers configures, keys and close, and the order it takes things down in.
+//
+//A test binary of its own, running the windows with poll_events, and a module of event_loop.rs,
+//which runs the very same tests with an EventLoop (the calloop feature, like the tests of the
+//EventLoop itself here).
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+mod compositor;
+
+use compositor::{
+    ACTIVATION_TOKEN, Arg, Driven, Driver, OUTPUT, Request, SEAT, TEARDOWN_BASELINE,
+    TestCompositor, string,
+};
+#[cfg(feature = "calloop")]
+use simple_wayland_window::TimeoutAction;
+use simple_wayland_window::{
+    Action, Canvas, Capabilities, Color, ConnectOptions, Decorations, FlushPolicy, HitRegion,
+    InputRouting, Key, KeyState, LayoutInfo, Lifecycle, LogicalKey, Margins, Mods,
+    MotionCoalescing, PresentMode, Rect, RefreshSource, ScrollConfig, ScrollSource, SerialKind,
+    SideDispatch, SlowFrameCause, SwapchainConfig, TiledEdges, Transform, Window, WindowError,
+    WindowEvent, WindowOptions, WindowRole,
+};
+
+fn count(requests: &[Request], interface: &str, name: &str) -> usize {
+    requests
+        .iter()
+        .filter(|request| request.is(interface, name))
+        .count()
+}
+
+fn position(requests: &[Request], interface: &str, name: &str) -> usize {
+    requests
+        .iter()
+        .position(|request| request.is(interface, name))
+        .unwrap_or_else(|| panic!("no {interface}.{name} in {requests:#?}"))
+}
+
+//The first `name` request on the object `object`, whose first argument is `first` if given.
+fn position_on(requests: &[Request], object: u32, name: &str, first: Option<&Arg>) -> usize {
+    requests
+        .iter()
+        .position(|request| {
+            request.object == object
+                && request.name == name
+                && first.is_none_or(|first| request.args.first() == Some(first))
+        })
+        .unwrap_or_else(|| panic!("no {name} on {object} in {requests:#?}"))
+}
+
+//Whether this is event_loop.rs running the tests.
+fn event_loop() -> bool {
+    module_path!().starts_with("event_loop::")
+}
+
+//A window past its initial commit, waiting for the first configure.
+fn start(options: WindowOptions) -> (TestCompositor, Driven) {
+    let (compositor, connection) = TestCompositor::new();
+    let window = Window::with_connection(connection.clone(), options);
+    let mut window = Driven::new(&compositor, connection, window, event_loop());
+    run_to_initial_commit(&compositor, &mut window);
+    (compositor, window)
+}
+
+//The same, as a plain Window, for split windows: their EventSide dispatches, whatever the binary.
+fn start_window(options: WindowOptions) -> (TestCompositor, Window) {
+    let (compositor, connection) = TestCompositor::new();
+    let mut window = Window::with_connection(connection, options);
+    run_to_initial_commit(&compositor, &mut window);
+    (compositor, window)
+}
+
+fn run_to_initial_commit(compositor: &TestCompositor, driver: &mut impl Driver) {
+    compositor.run_until(driver, |_, requests| {
+        count(requests, "wl_surface", "commit") >= 1
+            && count(requests, "wl_seat", "get_keyboard") >= 1
+    });
+}
+
+#[test]
+fn init_sequence() {
+    let options = WindowOptions {
+        title: "init sequence".into(),
+        app_id: "org.example.Test".into(),
+        size: (64, 48),
+        ..WindowOptions::default()
+    };
+    let (compositor, window) = start(options);
+    let requests = compositor.requests();
+
+    let bound: Vec<_> = compositor
+        .requests_of("wl_registry", "bind")
+        .iter()
+        .map(|args| string(args).to_owned())
+        .collect();
+    for interface in ["wl_compositor", "wl_shm", "wl_seat", "xdg_wm_base"] {
+        assert!(
+            bound.iter().any(|b| b == interface),
+            "{interface} not bound"
+        );
+    }
+
+    assert_eq!(
+        string(&compositor.requests_of("xdg_toplevel", "set_title")[0]),
+        "init sequence"
+    );
+    assert_eq!(
+        string(&compositor.requests_of("xdg_toplevel", "set_app_id")[0]),
+        "org.example.Test"
+    );
+
+    //No buffer before the first configure, see shm_buffer_waits_for_the_first_configure.
+    assert_eq!(count(&requests, "wl_shm_pool", "create_buffer"), 0);
+
+    //The toplevel exists before the initial commit, and that commit carries no buffer.
+    assert!(
+        position(&requests, "xdg_surface", "get_toplevel")
+            < position(&requests, "wl_surface", "commit")
+    );
+    assert_eq!(count(&requests, "wl_surface", "attach"), 0);
+    assert!(!window.is_configured());
+}
+
+//Maximized and fullscreen are asked before the initial commit, so the first configure comes
+//with the size.
+#[test]
+fn start_states_come_before_the_initial_commit() {
+    let options = WindowOptions {
+        maximized: true,
+        fullscreen: true,
+        ..WindowOptions::default()
+    };
+    let (compositor, _window) = start(options);
+    let requests = compositor.requests();
+    let commit = position(&requests, "wl_surface", "commit");
+    assert!(position(&requests, "xdg_toplevel", "set_maximized") < commit);
+    assert!(position(&requests, "xdg_toplevel", "set_fullscreen") < commit);
+    assert_eq!(
+        compositor.requests_of("xdg_toplevel", "set_fullscreen"),
+        [vec![Arg::Object(0)]]
+    );
+}
+
+//A fullscreen output by name holds the initial commit until the outputs said their names.
+#[test]
+fn fullscreen_output_is_found_by_name() {
+    let options = WindowOptions {
+        fullscreen: true,
+        fullscreen_output: Some(OUTPUT.into()),
+        ..WindowOptions::default()
+    };
+    let (compositor, _window) = start(options);
+    let requests = compositor.requests();
+    assert!(
+        position(&requests, "xdg_toplevel", "set_fullscreen")
+            < position(&requests, "wl_surface", "commit")
+    );
+    //The test compositor's only output, not the null object.
+    let fullscreen = compositor.requests_of("xdg_toplevel", "set_fullscreen");
+    assert_eq!(fullscreen.len(), 1);
+    assert!(matches!(fullscreen[0][..], [Arg::Object(output)] if output != 0));
+}
+
+#[test]
+fn configure_is_acked_then_drawn() {
+    let (compositor, mut window) = start(WindowOptions::default());
+
+    let serial = compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    assert!(window.is_configured());
+
+    let requests = compositor.requests();
+    assert_eq!(
+        compositor.requests_of("xdg_surface", "ack_configure"),
+        [vec![Arg::Uint(serial)]]
+    );
+    let attach = position(&requests, "wl_surface", "attach");
+    assert!(position(&requests, "xdg_surface", "ack_configure") < attach);
+    assert!(
+        requests[attach..]
+            .iter()
+            .any(|r| r.is("wl_surface", "commit"))
+    );
+
+    //The gradient buffer, not a stand-in.
+    let Arg::NewId(buffer) = compositor.requests_of("wl_shm_pool", "create_buffer")[0][0] else {
+        panic!("create_buffer without a new id");
+    };
+    assert_eq!(requests[attach].args[0], Arg::Object(buffer));
+}
+
+//The window goes through the protocol's order, and once closed nothing more is attached.
+#[test]
+fn lifecycle_follows_the_surface() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    assert_eq!(window.lifecycle(), Lifecycle::InitialCommitDone);
+
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    assert_eq!(window.lifecycle(), Lifecycle::Mapped);
+
+    window.close();
+    assert_eq!(window.lifecycle(), Lifecycle::Closed);
+    window.draw(|canvas| canvas.clear(Color::opaque(0, 0, 0)));
+    window.flush().unwrap();
+    compositor.run_until(&mut window, |_, _| true);
+    assert_eq!(count(&compositor.requests(), "wl_surface", "attach"), 1);
+}
+
+#[test]
+fn capture_reads_back_the_last_commit() {
+    let options = WindowOptions {
+        size: (4, 3),
+        ..WindowOptions::default()
+    };
+    let (compositor, mut window) = start(options);
+    assert_eq!(window.capture_to_vec(), Err(WindowError::NotConfigured));
+
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    window.draw(|canvas| canvas.clear(Color::premultiplied(0x20, 0x40, 0x60, 0x80)));
+
+    //Un-premultiplied, R G B A.
+    assert_eq!(
+        window.capture_to_vec().unwrap(),
+        [0x40, 0x80, 0xBF, 0x80].repeat(4 * 3)
+    );
+
+    #[cfg(feature = "image")]
+    {
+        let path = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        window.capture_to_png(path.path()).unwrap();
+        let png = image::open(path.path()).unwrap().into_rgba8();
+        assert_eq!(png.dimensions(), (4, 3));
+        assert_eq!(png.into_raw(), window.capture_to_vec().unwrap());
+    }
+}
+
+//An identical draw attaches nothing, and only commits when a frame callback waits on it.
+#[test]
+fn identical_frames_are_skipped() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    window.set_skip_identical_frames(true);
+    let red = |canvas: &mut Canvas| canvas.clear(Color::opaque(0xFF, 0, 0));
+
+    window.draw(red);
+    window.draw(red);
+    window.request_frame();
+    window.draw(red);
+    window.draw(|canvas| canvas.clear(Color::opaque(0, 0, 0xFF)));
+    window.flush().unwrap();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 3
+    });
+    let requests = compositor.requests();
+    //The initial commit, the configure's, the first red, the frame callback's and the blue.
+    assert_eq!(count(&requests, "wl_surface", "commit"), 5);
+    assert_eq!(count(&requests, "wl_surface", "attach"), 3);
+    assert_eq!(window.frame_stats().skipped, 2);
+}
+
+//A commit leaves right away, without a dispatch or a flush, unless the policy waits for them.
+#[test]
+fn commits_are_flushed_by_default() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+
+    window.draw(|canvas| canvas.clear(Color::opaque(0xFF, 0, 0)));
+    compositor.wait_for("wl_surface", "attach", 2);
+
+    window.set_flush_policy(FlushPolicy::OnDispatch);
+    //The wait for a free buffer flushes before drawing, the commit is after.
+    window.draw(|canvas| canvas.clear(Color::opaque(0, 0, 0xFF)));
+    compositor.settle();
+    assert_eq!(count(&compositor.requests(), "wl_surface", "attach"), 2);
+    window.flush().unwrap();
+    compositor.wait_for("wl_surface", "attach", 3);
+}
+
+//The test compositor never releases buffers: the first draw gets a second buffer, the second
+//waits, the third waits and makes a third buffer, the fourth waits again.
+#[test]
+fn late_releases_grow_the_swapchain() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    window.set_swapchain_config(SwapchainConfig {
+        grow_after: 1,
+        wait_timeout: Duration::from_millis(1),
+        ..SwapchainConfig::default()
+    });
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+
+    for blue in 1..=4 {
+        window.draw(|canvas| canvas.clear(Color::opaque(0, 0, blue)));
+    }
+    window.flush().unwrap();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 5
+    });
+    let stats = window.frame_stats();
+    assert_eq!((stats.buffers, stats.stalls), (3, 3));
+    let buffers: Vec<_> = compositor
+        .requests_of("wl_shm_pool", "create_buffer")
+        .into_iter()
+        .map(|args| match args[0] {
+            Arg::NewId(id) => Arg::Object(id),
+            _ => panic!("create_buffer without a new id"),
+        })
+        .collect();
+    assert_eq!(buffers.len(), 3);
+    //A draw that waited in vain goes into the buffer on screen.
+    let attached: Vec<_> = compositor
+        .requests_of("wl_surface", "attach")
+        .into_iter()
+        .map(|args| args[0].clone())
+        .collect();
+    let [b0, b1, b2] = [0, 1, 2].map(|i| buffers[i].clone());
+    assert_eq!(attached, [b0, b1.clone(), b1, b2.clone(), b2]);
+}
+
+//A preferred scale of 2 makes a buffer twice the size at buffer scale 2, the window keeps its
+//size. A preferred transform is only followed when asked.
+#[test]
+fn preferred_scale_and_transform_are_applied() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(300, 200, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+
+    compositor.preferred_scale(2);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 2
+    });
+    assert!(events.contains(&WindowEvent::ScaleChanged { old: 1, new: 2 }));
+    let requests = compositor.requests();
+    assert!(
+        position(&requests, "wl_surface", "set_buffer_scale")
+            < requests
+                .iter()
+                .rposition(|request| request.is("wl_surface", "attach"))
+                .unwrap()
+    );
+    assert_eq!(
+        compositor.requests_of("wl_surface", "set_buffer_scale"),
+        [vec![Arg::Int(2)]]
+    );
+    let buffer = &compositor.requests_of("wl_shm_pool", "create_buffer")[1];
+    assert_eq!(buffer[2..4], [Arg::Int(600), Arg::Int(400)]);
+    assert_eq!(
+        (window.size(), window.buffer_size()),
+        ((300, 200), (600, 400))
+    );
+    assert_eq!(window.buffer_scale(), 2);
+
+    //wl_output.transform 90.
+    compositor.preferred_transform(1);
+    compositor.run_until(&mut window, |window, _| {
+        window.preferred_transform() == Transform::Rotate90
+    });
+    assert_eq!(
+        count(&compositor.requests(), "wl_surface", "set_buffer_transform"),
+        0
+    );
+    window.set_follow_preferred_transform(true);
+    compositor.preferred_transform(1);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "set_buffer_transform") >= 1
+    });
+    assert!(events.contains(&WindowEvent::TransformChanged {
+        old: Transform::Normal,
+        new: Transform::Rotate90,
+    }));
+    assert_eq!(
+        (window.size(), window.buffer_size()),
+        ((300, 200), (400, 600))
+    );
+}
+
+//Suspended, the buffer is destroyed and the placeholder goes on, a 1x1 buffer from the small-buffer
+//pool stretched by the viewport; shown again, a new buffer comes from a new pool.
+#[test]
+fn hidden_windows_release_their_buffer() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    window.set_release_buffers_when_hidden(true);
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    let pools = count(&compositor.requests(), "wl_shm", "create_pool");
+    assert_eq!(pools, 1);
+
+    //Suspended is state 9, xdg_toplevel v6.
+    compositor.configure(0, 0, &[9]);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_buffer", "destroy") >= 1 && count(requests, "wl_surface", "attach") >= 2
+    });
+    assert!(events.contains(&WindowEvent::Suspended));
+    let requests = compositor.requests();
+    let buffers = compositor.requests_of("wl_shm_pool", "create_buffer");
+    let placeholder = buffers.last().unwrap();
+    assert_eq!(placeholder[2..4], [Arg::Int(1), Arg::Int(1)]);
+    let attach = requests.iter().rposition(|r| r.is("wl_surface", "attach"));
+    assert_eq!(
+        requests[attach.unwrap()].args[0],
+        placeholder[0].as_object()
+    );
+    //The placeholder's pool, none for the main buffer.
+    assert_eq!(count(&requests, "wl_shm", "create_pool"), pools + 1);
+    //Nothing to draw into.
+    window.draw(|canvas| canvas.clear(Color::opaque(0xFF, 0, 0)));
+
+    compositor.configure(0, 0, &[]);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 3
+    });
+    assert!(events.contains(&WindowEvent::Resumed));
+    let requests = compositor.requests();
+    assert_eq!(count(&requests, "wl_shm", "create_pool"), pools + 2);
+    let buffers = compositor.requests_of("wl_shm_pool", "create_buffer");
+    let buffer = buffers.last().unwrap();
+    assert_ne!(buffer[2..4], [Arg::Int(1), Arg::Int(1)]);
+    let attach = requests.iter().rposition(|r| r.is("wl_surface", "attach"));
+    assert_eq!(requests[attach.unwrap()].args[0], buffer[0].as_object());
+}
+
+//A dialog takes its small buffers from the pool its parent made, not one of its own.
+#[test]
+fn windows_of_a_connection_share_the_buffer_pool() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    window.fill_color(0, 0, 0, u32::MAX).unwrap();
+    window.flush().unwrap();
+    compositor.wait_for("wl_shm_pool", "create_buffer", 2);
+    let pools = count(&compositor.requests(), "wl_shm", "create_pool");
+
+    let mut dialog = window.child_dialog(WindowOptions::default());
+    compositor.run_until(&mut dialog, |_, requests| {
+        count(requests, "xdg_toplevel", "set_parent") >= 1
+    });
+    dialog.fill_color(u32::MAX, 0, 0, u32::MAX).unwrap();
+    dialog.flush().unwrap();
+    compositor.wait_for("wl_shm_pool", "create_buffer", 3);
+    assert_eq!(
+        count(&compositor.requests(), "wl_shm", "create_pool"),
+        pools
+    );
+}
+
+//Suspended, with only an animation timer armed, the EventLoop sleeps through 5 idle seconds in a
+//single wait: no tick, and the timer doesn't run.
+#[test]
+#[cfg(feature = "calloop")]
+fn idle_suspended_loop_sleeps_through() {
+    const IDLE: Duration = Duration::from_secs(5);
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[9]);
+    compositor.run_until(&mut window, |window, _| window.is_suspended());
+
+    let mut event_loop = window.into_event_loop();
+    let ticked = Arc::new(AtomicBool::new(false));
+    let timer_ticked = ticked.clone();
+    event_loop
+        .handle()
+        .insert_animation_timer(Duration::ZERO, move |_| {
+            timer_ticked.store(true, Ordering::Relaxed);
+            TimeoutAction::NextFrame
+        });
+
+    let before = event_loop.window().loop_stats();
+    let start = Instant::now();
+    while start.elapsed() < IDLE {
+        let left = IDLE.saturating_sub(start.elapsed());
+        event_loop.dispatch(Some(left), &mut |_: WindowEvent, _: &mut Window| {});
+    }
+    let stats = event_loop.window().loop_stats();
+    assert_eq!(stats.rounds - before.rounds, 1, "{stats:?}");
+    assert_eq!(
+        (
+            stats.wakeups - before.wakeups,
+            stats.timeouts - before.timeouts
+        ),
+        (0, 1)
+    );
+    assert!(!ticked.load(Ordering::Relaxed));
+}
+
+//A button held in a repeat region repeats through the EventLoop until it goes up, at the
+//interval: no burst for the rounds spent waiting on the compositor.
+#[test]
+#[cfg(feature = "calloop")]
+fn held_buttons_repeat_in_their_region() {
+    const BTN_LEFT: u32 = 0x110;
+    const INTERVAL: Duration = Duration::from_millis(20);
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, requests| {
+        window.is_configured() && count(requests, "wl_seat", "get_pointer") >= 1
+    });
+    window.pointer_button_repeat(Duration::from_millis(50), INTERVAL);
+    window.set_repeat_regions(&[(BTN_LEFT, Rect::new(0, 0, 40, 40))]);
+    compositor.pointer_enter(10.0, 12.0);
+    compositor.pointer_button(BTN_LEFT, true);
+
+    let mut event_loop = window.into_event_loop();
+    let mut repeats = Vec::new();
+    let pressed = Instant::now();
+    while repeats.len() < 3 {
+        assert!(pressed.elapsed() < Duration::from_secs(5), "{repeats:?}");
+        event_loop.dispatch(
+            Some(Duration::from_millis(10)),
+            &mut |event: WindowEvent, _: &mut Window| {
+                if let WindowEvent::PointerButtonRepeat { button, x, y, .. } = event {
+                    repeats.push((Instant::now(), button, x, y));
+                }
+            },
+        );
+    }
+    //The delay, then one interval each: never sooner, whatever the rounds did.
+    for (n, &(at, ..)) in repeats.iter().enumerate() {
+        assert!(
+            at - pressed >= Duration::from_millis(50) + INTERVAL * n as u32,
+            "repeat {n} came early: {repeats:?}"
+        );
+    }
+    assert!(
+        repeats
+            .iter()
+            .all(|&(_, button, x, y)| (button, x, y) == (BTN_LEFT, 10.0, 12.0))
+    );
+
+    compositor.pointer_button(BTN_LEFT, false);
+    let mut released = false;
+    let mut after = 0;
+    let start = Instant::now();
+    while start.elapsed() < INTERVAL * 5 || !released {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        event_loop.dispatch(
+            Some(Duration::from_millis(10)),
+            &mut |event: WindowEvent, _: &mut Window| match event {
+                WindowEvent::PointerButton { pressed: false, .. } => released = true,
+                WindowEvent::PointerButtonRepeat { .. } if released => after += 1,
+                _ => {}
+            },
+        );
+    }
+    assert_eq!(after, 0, "repeats after the release");
+}
+
+//Any calloop source goes on the EventLoop through its handle, with the Window in its callback.
+#[test]
+#[cfg(feature = "calloop")]
+fn calloop_sources_run_with_the_window() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| window.is_configured());
+
+    let mut event_loop = window.into_event_loop();
+    let (sender, channel) = calloop::channel::channel();
+    let titled = Arc::new(AtomicBool::new(false));
+    let channel_titled = titled.clone();
+    event_loop
+        .handle()
+        .calloop()
+        .insert_source(channel, move |event, _, window: &mut Window| {
+            if let calloop::channel::Event::Msg(title) = event {
+                window.set_title(title);
+                channel_titled.store(true, Ordering::Relaxed);
+            }
+        })
+        .unwrap();
+    std::thread::spawn(move || sender.send("From a thread").unwrap());
+
+    let start = Instant::now();
+    while !titled.load(Ordering::Relaxed) {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        event_loop.dispatch(Some(Duration::from_millis(100)), &mut |_, _| {});
+    }
+    compositor.run_until(&mut event_loop, |_, _| {
+        compositor
+            .requests_of("xdg_toplevel", "set_title")
+            .iter()
+            .any(|request| string(request) == "From a thread")
+    });
+}
+
+//A held key repeats on the EventLoop's timer after the delay, at the rate, until it goes up.
+#[test]
+#[cfg(feature = "calloop")]
+fn held_keys_repeat() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, requests| {
+        window.is_configured() && count(requests, "wl_seat", "get_keyboard") >= 1
+    });
+    compositor.keyboard_enter();
+    compositor.repeat_info(50, 40);
+    compositor.key(Key::A, true);
+
+    let mut event_loop = window.into_event_loop();
+    let mut repeats = Vec::new();
+    let mut pressed = None;
+    let start = Instant::now();
+    while repeats.len() < 3 {
+        assert!(start.elapsed() < Duration::from_secs(5), "{repeats:?}");
+        event_loop.dispatch(
+            Some(Duration::from_millis(10)),
+            &mut |event: WindowEvent, _: &mut Window| match event {
+                WindowEvent::Key {
+                    state: KeyState::Pressed,
+                    ..
+                } => pressed = Some(Instant::now()),
+                WindowEvent::KeyRepeat { key, .. } => repeats.push((Instant::now(), key)),
+                _ => {}
+            },
+        );
+    }
+    //The delay, then one interval each.
+    let pressed = pressed.unwrap();
+    for (n, &(at, key)) in repeats.iter().enumerate() {
+        assert_eq!(key, Key::A);
+        assert!(
+            at - pressed >= Duration::from_millis(40 + 20 * n as u64),
+            "repeat {n} came early: {repeats:?}"
+        );
+    }
+
+    compositor.key(Key::A, false);
+    let mut released = false;
+    let mut after = 0;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(100) || !released {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        event_loop.dispatch(
+            Some(Duration::from_millis(10)),
+            &mut |event: WindowEvent, _: &mut Window| match event {
+                WindowEvent::Key {
+                    state: KeyState::Released,
+                    ..
+                } => released = true,
+                WindowEvent::KeyRepeat { .. } if released => after += 1,
+                _ => {}
+            },
+        );
+    }
+    assert_eq!(after, 0, "repeats after the release");
+}
+
+//The test compositor has no wp_alpha_modifier_v1: the slow path fades the buffer itself.
+#[test]
+fn opacity_falls_back_to_fading_the_buffer() {
+    let options = WindowOptions {
+        size: (4, 3),
+        ..WindowOptions::default()
+    };
+    let (compositor, mut window) = start(options);
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    assert!(!window.opacity_supported());
+    let alphas = |window: &Window| -> Vec<u8> {
+        let pixels = window.capture_to_vec().unwrap();
+        pixels.chunks(4).map(|pixel| pixel[3]).collect()
+    };
+
+    //The gradient is redrawn faded right away.
+    window.set_opacity(0.5);
+    assert_eq!(alphas(&window), [0x80; 4 * 3]);
+
+    //A picture of the application's only at its next draw.
+    window.draw(|canvas| canvas.clear(Color::opaque(0xFF, 0xFF, 0xFF)));
+    assert_eq!(alphas(&window), [0x80; 4 * 3]);
+    window.set_opacity(7.0);
+    assert_eq!(window.opacity(), 1.0);
+    assert_eq!(alphas(&window), [0x80; 4 * 3]);
+    window.draw(|canvas| canvas.clear(Color::opaque(0xFF, 0xFF, 0xFF)));
+    assert_eq!(alphas(&window), [0xFF; 4 * 3]);
+}
+
+//A quarter turn swaps the window's size around the same buffer, and damage stays in buffer
+//coordinates.
+#[test]
+fn buffer_transform_turns_the_window() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+
+    window.set_buffer_transform(Transform::Rotate90).unwrap();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 2
+    });
+    assert_eq!(window.size(), (240, 320));
+    assert_eq!(window.buffer_size(), (320, 240));
+    assert_eq!(
+        compositor.requests_of("wl_surface", "set_buffer_transform"),
+        [vec![Arg::Int(1)]]
+    );
+    assert_eq!(
+        compositor.requests_of("xdg_surface", "set_window_geometry")[1],
+        [Arg::Int(0), Arg::Int(0), Arg::Int(240), Arg::Int(320)]
+    );
+    assert_eq!(
+        compositor.requests_of("wl_surface", "damage_buffer")[1],
+        [Arg::Int(0), Arg::Int(0), Arg::Int(320), Arg::Int(240)]
+    );
+}
+
+//The resize path: several configures in one batch get one ack (the newest) and one redraw.
+#[test]
+fn configures_in_one_batch_are_coalesced() {
+    let (compositor, mut window) = start(WindowOptions::default());
+
+    compositor.configure(400, 300, &[]);
+    let newest = compositor.configure(500, 400, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+
+    assert_eq!(
+        compositor.requests_of("xdg_surface", "ack_configure"),
+        [vec![Arg::Uint(newest)]]
+    );
+    let stats = window.configure_stats();
+    assert_eq!((stats.received, stats.applied), (2, 1));
+    //Only the newest size gets a buffer, and the window geometry goes with it.
+    assert_eq!(window.size(), (500, 400));
+    assert_eq!(
+        compositor.requests_of("xdg_surface", "set_window_geometry"),
+        [vec![Arg::Int(0), Arg::Int(0), Arg::Int(500), Arg::Int(400)]]
+    );
+    let sizes: Vec<_> = compositor
+        .requests_of("wl_shm_pool", "create_buffer")
+        .into_iter()
+        .map(|args| [args[2].clone(), args[3].clone()])
+        .collect();
+    assert_eq!(sizes, [[Arg::Int(500), Arg::Int(400)]]);
+}
+
+//Tiled on the left, the shadow there goes away and the window geometry starts at the buffer's
+//edge; floating again, the next configure brings it back at the same window size.
+#[test]
+fn tiled_edges_drop_their_shadow() {
+    let options = WindowOptions {
+        decorations: Decorations::ClientSide {
+            shadow: Margins::uniform(10),
+        },
+        ..WindowOptions::default()
+    };
+    let (compositor, mut window) = start(options);
+    compositor.configure(400, 300, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+
+    //xdg_toplevel.State::TiledLeft
+    compositor.configure(400, 300, &[5]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 2
+    });
+    assert_eq!(
+        window.tiled_edges(),
+        TiledEdges {
+            left: true,
+            ..TiledEdges::NONE
+        }
+    );
+    assert!(window.state().tiled.left);
+    assert_eq!(
+        (window.size(), window.buffer_size()),
+        ((400, 300), (410, 320))
+    );
+
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 3
+    });
+    assert!(!window.tiled_edges().any());
+    assert_eq!(
+        (window.size(), window.buffer_size()),
+        ((400, 300), (420, 320))
+    );
+    assert_eq!(
+        compositor.requests_of("xdg_surface", "set_window_geometry"),
+        [
+            vec![Arg::Int(10), Arg::Int(10), Arg::Int(400), Arg::Int(300)],
+            vec![Arg::Int(0), Arg::Int(10), Arg::Int(400), Arg::Int(300)],
+            vec![Arg::Int(10), Arg::Int(10), Arg::Int(400), Arg::Int(300)],
+        ]
+    );
+}
+
+//With a resize preview, configures while resizing move the viewport destination and the window
+//geometry, the buffer stays. The end of the resize makes the real one.
+#[test]
+fn resize_preview_stretches_until_the_resize_ends() {
+    let options = WindowOptions {
+        decorations: Decorations::ClientSide {
+            shadow: Margins::uniform(10),
+        },
+        resize_preview: Some(Duration::from_secs(60)),
+        ..WindowOptions::default()
+    };
+    let (compositor, mut window) = start(options);
+    compositor.configure(400, 300, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+
+    //xdg_toplevel.State::Resizing
+    compositor.configure(500, 400, &[3]);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_surface", "ack_configure") >= 2
+    });
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::Resized { .. }))
+    );
+    assert_eq!(
+        (window.buffer_size(), window.surface_size()),
+        ((420, 320), (520, 420))
+    );
+    assert_eq!(window.frame_stats().previewed, 1);
+    assert_eq!(
+        compositor.requests_of("wp_viewport", "set_destination"),
+        [vec![Arg::Int(520), Arg::Int(420)]]
+    );
+    assert_eq!(
+        compositor
+            .requests_of("xdg_surface", "set_window_geometry")
+            .last(),
+        Some(&vec![
+            Arg::Int(10),
+            Arg::Int(10),
+            Arg::Int(500),
+            Arg::Int(400)
+        ])
+    );
+    assert_eq!(compositor.requests_of("wl_surface", "attach").len(), 1);
+
+    compositor.configure(520, 410, &[]);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 2
+    });
+    assert!(events.contains(&WindowEvent::Resized {
+        width: 520,
+        height: 410
+    }));
+    assert_eq!(window.buffer_size(), (540, 430));
+    assert_eq!(
+        compositor
+            .requests_of("wp_viewport", "set_destination")
+            .last(),
+        Some(&vec![Arg::Int(-1), Arg::Int(-1)])
+    );
+    assert_eq!(window.frame_stats().previewed, 1);
+}
+
+//Garbage configure sizes (a transient 1x1, 65535, negative) keep the window at its last sane
+//size, and each of their serials is acked all the same.
+#[test]
+fn absurd_configure_sizes_are_acked_and_ignored() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    let mut serials = vec![compositor.configure(400, 300, &[])];
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+
+    for (width, height) in [(1, 1), (65535, 65535), (-20, 300), (400, 2)] {
+        serials.push(compositor.configure(width, height, &[]));
+        let acked = serials.len();
+        let events = compositor.run_until(&mut window, |_, requests| {
+            count(requests, "xdg_surface", "ack_configure") >= acked
+        });
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, WindowEvent::Resized { .. })),
+            "{width}x{height}"
+        );
+        assert_eq!(window.buffer_size(), (400, 300), "{width}x{height}");
+    }
+
+    serials.push(compositor.configure(500, 350, &[]));
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_surface", "ack_configure") >= 6
+    });
+    assert!(events.contains(&WindowEvent::Resized {
+        width: 500,
+        height: 350
+    }));
+    let acked: Vec<_> = serials
+        .iter()
+        .map(|&serial| vec![Arg::Uint(serial)])
+        .collect();
+    assert_eq!(
+        compositor.requests_of("xdg_surface", "ack_configure"),
+        acked
+    );
+}
+
+//wl_shm first of all globals, long before the configure: the buffer is still only made and
+//attached after the ack. The test compositor kills the client otherwise.
+#[test]
+fn shm_buffer_waits_for_the_first_configure() {
+    let (compositor, connection) = TestCompositor::with_global_order(&["wl_shm"]);
+    let options = WindowOptions {
+        size: (64, 48),
+        ..WindowOptions::default()
+    };
+    let mut window = Window::with_connection(connection, options);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "commit") >= 1
+    });
+    let bound = compositor.requests_of("wl_registry", "bind");
+    assert_eq!(string(&bound[0]), "wl_shm");
+    assert_eq!(
+        count(&compositor.requests(), "wl_shm_pool", "create_buffer"),
+        0
+    );
+
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    let requests = compositor.requests();
+    let ack = position(&requests, "xdg_surface", "ack_configure");
+    assert!(ack < position(&requests, "wl_shm", "create_pool"));
+    assert!(ack < position(&requests, "wl_surface", "attach"));
+    //new_id, offset, width, height, stride, format.
+    let buffer = &compositor.requests_of("wl_shm_pool", "create_buffer")[0];
+    assert_eq!(buffer[2..5], [Arg::Int(64), Arg::Int(48), Arg::Int(64 * 4)]);
+    assert!(window.is_connected());
+}
+
+//The first frame is drawn while the configure is on its way, and only wrapped in a pool and a
+//buffer after the ack. Stale or drawn for another size, it's drawn again.
+#[test]
+fn first_frame_is_drawn_ahead_of_the_configure() {
+    let options = WindowOptions {
+        size: (64, 48),
+        startup_timing: true,
+        ..WindowOptions::default()
+    };
+    let (compositor, mut window) = start(options);
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    let requests = compositor.requests();
+    assert!(
+        position(&requests, "xdg_surface", "ack_configure")
+            < position(&requests, "wl_shm", "create_pool")
+    );
+    //The window's own frame callback, for time_to_first_frame_callback.
+    assert_eq!(count(&requests, "wl_surface", "frame"), 1);
+    assert!(
+        position(&requests, "wl_surface", "frame") < position(&requests, "wl_surface", "attach")
+    );
+    let timing = window.startup_timing();
+    assert!(timing.prerendered);
+    assert!(timing.time_to_first_commit.is_some());
+    assert_eq!(
+        timing.time_to_first_frame_callback, None,
+        "this compositor never answers"
+    );
+
+    //A renderer set meanwhile is what shows.
+    let (compositor, mut window) = start(WindowOptions {
+        size: (4, 3),
+        ..WindowOptions::default()
+    });
+    window.set_renderer(|canvas: &mut Canvas, _: Duration| canvas.clear(Color::opaque(0xFF, 0, 0)));
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    assert!(!window.startup_timing().prerendered);
+    //Nobody asked for startup timing, no frame callback of the window's own.
+    assert_eq!(count(&compositor.requests(), "wl_surface", "frame"), 0);
+    assert_eq!(
+        window.capture_to_vec().unwrap(),
+        [0xFF, 0, 0, 0xFF].repeat(4 * 3)
+    );
+
+    //So is the size of the configure.
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(100, 80, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    assert!(!window.startup_timing().prerendered);
+    let buffer = &compositor.requests_of("wl_shm_pool", "create_buffer")[0];
+    assert_eq!(buffer[2..4], [Arg::Int(100), Arg::Int(80)]);
+}
+
+//The usual start: a 0x0 configure leaves the size to us, then the compositor picks one. Every
+//buffer committed in between has a real size.
+#[test]
+fn zero_size_configure_takes_the_preferred_size() {
+    let (compositor, mut window) = start(WindowOptions {
+        preferred_size: Some((400, 300)),
+        ..WindowOptions::default()
+    });
+    //Committed buffer sizes, from the attach and the create_buffer of the buffer attached.
+    let committed = |compositor: &compositor::TestCompositor| -> Vec<(i32, i32)> {
+        let buffers = compositor.requests_of("wl_shm_pool", "create_buffer");
+        compositor
+            .requests_of("wl_surface", "attach")
+            .iter()
+            .map(|attach| {
+                let Arg::Object(id) = attach[0] else {
+                    panic!("attach without a buffer");
+                };
+                let created = buffers
+                    .iter()
+                    .find(|args| args[0] == Arg::NewId(id))
+                    .expect("attached an shm buffer");
+                match created[2..4] {
+                    [Arg::Int(width), Arg::Int(height)] => (width, height),
+                    _ => panic!("create_buffer size {created:?}"),
+                }
+            })
+            .collect()
+    };
+
+    compositor.configure(0, 0, &[]);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    assert!(events.contains(&WindowEvent::Resized {
+        width: 400,
+        height: 300
+    }));
+    assert_eq!(committed(&compositor), [(400, 300)]);
+
+    compositor.configure(800, 600, &[]);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 2
+    });
+    assert!(events.contains(&WindowEvent::Resized {
+        width: 800,
+        height: 600
+    }));
+    assert_eq!(committed(&compositor), [(400, 300), (800, 600)]);
+    assert_eq!(window.buffer_size(), (800, 600));
+
+    //Nothing was ever made with a 0 side.
+    for args in compositor.requests_of("wl_shm_pool", "create_buffer") {
+        assert!(matches!(args[2..4], [Arg::Int(w), Arg::Int(h)] if w > 0 && h > 0));
+    }
+}
+
+//Client rendered windows follow the configure size.
+#[cfg(feature = "raw-window-handle")]
+#[test]
+fn client_rendered_window_resizes() {
+    use simple_wayland_window::RenderMode;
+
+    let (compositor, mut window) = start(WindowOptions {
+        render_mode: RenderMode::External,
+        ..WindowOptions::default()
+    });
+
+    compositor.configure(640, 480, &[]);
+    let events = compositor.run_until(&mut window, |window, _| window.is_configured());
+    assert!(events.contains(&WindowEvent::Resized {
+        width: 640,
+        height: 480
+    }));
+    assert_eq!(window.buffer_size(), (640, 480));
+    //Nothing of ours gets attached, the renderer does that.
+    assert_eq!(count(&compositor.requests(), "wl_surface", "attach"), 0);
+}
+
+#[test]
+fn escape_quits() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.keyboard_enter();
+    compositor.key(Key::Escape, true);
+
+    let events = compositor.run_until(&mut window, |window, _| !window.is_running());
+    assert!(events.contains(&WindowEvent::FocusGained {
+        seat: SEAT.into(),
+        pressed_keys: Vec::new()
+    }));
+    //Bound keys are the binding's, not events.
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::Key { .. }))
+    );
+}
+
+//With the pointer locked, Escape unlocks it instead of quitting. Neither its press nor its
+//release are events, and the release doesn't quit either.
+#[test]
+fn escape_releases_the_pointer_lock() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, requests| {
+        window.is_configured() && count(requests, "wl_seat", "get_pointer") >= 1
+    });
+    compositor.pointer_enter(10.0, 10.0);
+    compositor.keyboard_enter();
+    compositor.run_until(&mut window, |window, _| window.has_keyboard_focus());
+    window.lock_pointer().unwrap();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "zwp_pointer_constraints_v1", "lock_pointer") >= 1
+    });
+    compositor.pointer_locked();
+    compositor.run_until(&mut window, |window, _| window.is_pointer_locked());
+
+    compositor.key(Key::Escape, true);
+    compositor.key(Key::Escape, false);
+    //Everything before it has been handled once the focus is gone.
+    compositor.keyboard_leave();
+    let events = compositor.run_until(&mut window, |window, _| !window.has_keyboard_focus());
+    assert!(window.is_running());
+    assert!(!window.is_pointer_locked());
+    assert!(events.contains(&WindowEvent::PointerUnlocked));
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::Key { .. }))
+    );
+    assert_eq!(
+        count(&compositor.requests(), "zwp_locked_pointer_v1", "destroy"),
+        1
+    );
+}
+
+//Alt-tabbing in while holding Escape: the Enter lists it, some compositors send its press
+//again, and neither may quit. Only a press that happens while we have the focus does.
+#[test]
+fn keys_held_at_enter_fire_no_bindings() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    let marker = Arc::new(AtomicBool::new(false));
+    window.bind_key(
+        Key::A,
+        Action::Custom(Box::new({
+            let marker = marker.clone();
+            move |_| marker.store(true, Ordering::Relaxed)
+        })),
+    );
+    compositor.configure(0, 0, &[]);
+    compositor.keyboard_enter_holding(&[Key::Escape]);
+    compositor.key(Key::Escape, true);
+    compositor.key(Key::Escape, false);
+    //Everything before it has been handled once the binding ran.
+    compositor.key(Key::A, true);
+
+    let events = compositor.run_until(&mut window, |_, _| marker.load(Ordering::Relaxed));
+    assert!(window.is_running());
+    assert!(events.contains(&WindowEvent::FocusGained {
+        seat: SEAT.into(),
+        pressed_keys: vec![Key::Escape]
+    }));
+    let keys: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            WindowEvent::Key { key, state, .. } => Some((*key, *state)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(keys, [(Key::Escape, KeyState::Released)]);
+
+    compositor.key(Key::Escape, true);
+    compositor.run_until(&mut window, |window, _| !window.is_running());
+}
+
+//Keys held when the focus leaves get their releases, made up, before FocusLost.
+#[test]
+fn leave_releases_held_keys() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    window.bind_key(Key::Q, Action::Custom(Box::new(|_| {})));
+    compositor.configure(0, 0, &[]);
+    compositor.keyboard_enter();
+    compositor.run_until(&mut window, |window, _| window.has_keyboard_focus());
+    compositor.key(Key::A, true);
+    compositor.key(Key::Q, true);
+    compositor.keyboard_leave();
+
+    let events = compositor.run_until(&mut window, |window, _| !window.has_keyboard_focus());
+    let keys: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            WindowEvent::Key { key, state, .. } => Some((*key, *state)),
+            _ => None,
+        })
+        .collect();
+    //Q's release is its binding's, like its press.
+    assert_eq!(
+        keys,
+        [(Key::A, KeyState::Pressed), (Key::A, KeyState::Released)]
+    );
+    assert!(matches!(events.last(), Some(WindowEvent::FocusLost { .. })));
+}
+
+#[test]
+fn key_bindings_replace_escape() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    let pressed = Arc::new(AtomicBool::new(false));
+    window.unbind_key(Mods::NONE, Key::Escape);
+    window.bind_key(
+        Key::Q,
+        Action::Custom(Box::new({
+            let pressed = pressed.clone();
+            move |_| pressed.store(true, Ordering::Relaxed)
+        })),
+    );
+    compositor.configure(0, 0, &[]);
+    compositor.keyboard_enter();
+    compositor.key(Key::Escape, true);
+    compositor.key(Key::Q, true);
+    compositor.key(Key::Q, false);
+
+    let events = compositor.run_until(&mut window, |_, _| pressed.load(Ordering::Relaxed));
+    assert!(window.is_running());
+    //Escape is an event again, Q's press and release aren't.
+    let keys: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            WindowEvent::Key {
+                seat, key, state, ..
+            } if &**seat == SEAT => Some((*key, *state)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(keys, [(Key::Escape, KeyState::Pressed)]);
+}
+
+//The layout switching while A is down: its release still has the group it was pressed with,
+//the next press has the new one.
+#[test]
+fn layout_switches_keep_key_pairs() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.keymap(
+        "xkb_keymap {\nxkb_symbols \"pc+us+ru:2\" {\n\tname[Group1]=\"English (US)\";\n\
+         \tname[Group2]=\"Russian\";\n};\n};\n",
+    );
+    compositor.keyboard_enter();
+    compositor.key(Key::A, true);
+    compositor.layout_group(1);
+    compositor.key(Key::A, false);
+    compositor.key(Key::A, true);
+
+    let mut events = Vec::new();
+    while events
+        .iter()
+        .filter(|event| matches!(event, WindowEvent::Key { .. }))
+        .count()
+        < 3
+    {
+        events.extend(compositor.run_until(&mut window, |_, _| true));
+    }
+    let russian = LayoutInfo {
+        index: 1,
+        name: Some("Russian".into()),
+    };
+    assert_eq!(window.current_layout(SEAT), Some(russian.clone()));
+    assert_eq!(window.layouts(SEAT).len(), 2);
+    let keys: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            WindowEvent::Key { state, layout, .. } => Some(format!("{state:?} {layout}")),
+            WindowEvent::KeyboardLayoutChanged { seat, layout } => {
+                assert_eq!(&**seat, SEAT);
+                assert_eq!(*layout, russian);
+                Some("changed".into())
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(keys, ["Pressed 0", "changed", "Released 0", "Pressed 1"]);
+}
+
+//A keymap with the numpad's KEYPAD keys and NumLock on Mod2, trimmed from what xkbcommon writes
+//for "fr": the key where Q is on a US layout types an a.
+const NUMPAD_KEYMAP: &str = "xkb_keymap {
+xkb_keycodes \"evdev\" {
+\t<AD01> = 24;
+\t<NMLK> = 77;
+\t<KP7> = 79;
+\t<KP8> = 80;
+\t<KPDL> = 91;
+\t<HOME> = 110;
+};
+xkb_symbols \"pc+fr+inet(evdev)\" {
+\tname[Group1]=\"French\";
+\tkey <AD01> { [ a, A ] };
+\tkey <HOME> { [ Home ] };
+\tkey <NMLK> { [ Num_Lock ] };
+\tkey <KP7> { type= \"KEYPAD\", [ KP_Home, KP_7 ] };
+\tkey <KP8> { type= \"KEYPAD\", [ KP_Up, KP_8 ] };
+\tkey <KPDL> { type= \"KEYPAD\", [ KP_Delete, KP_Decimal ] };
+\tmodifier_map Mod2 { <NMLK> };
+};
+};
+";
+
+//Numpad7 is 7 with NumLock on and Home with it off, and a key stays what it was pressed as until
+//its release, NumLock toggled in between or not. Logical bindings go by the same.
+#[test]
+fn numpad_keys_follow_numlock() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.keymap(NUMPAD_KEYMAP);
+    compositor.keyboard_enter();
+    compositor.locked_modifiers(1 << 4);
+    compositor.key(Key::Numpad7, true);
+    compositor.locked_modifiers(0);
+    compositor.key(Key::Numpad7, false);
+    compositor.key(Key::NumpadDecimal, true);
+    compositor.key(Key::NumpadDecimal, false);
+    compositor.key(Key::Q, true);
+    compositor.key(Key::Q, false);
+
+    let key_events = |events: &[WindowEvent]| -> Vec<_> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                WindowEvent::Key {
+                    key,
+                    logical_key,
+                    state,
+                    ..
+                } => Some((*key, *logical_key, *state)),
+                _ => None,
+            })
+            .collect()
+    };
+    let mut events = Vec::new();
+    while key_events(&events).len() < 6 {
+        events.extend(compositor.run_until(&mut window, |_, _| true));
+    }
+    assert_eq!(
+        key_events(&events),
+        [
+            (Key::Numpad7, LogicalKey::Character('7'), KeyState::Pressed),
+            (Key::Numpad7, LogicalKey::Character('7'), KeyState::Released),
+            (
+                Key::NumpadDecimal,
+                LogicalKey::Named(Key::Delete),
+                KeyState::Pressed
+            ),
+            (
+                Key::NumpadDecimal,
+                LogicalKey::Named(Key::Delete),
+                KeyState::Released
+            ),
+            (Key::Q, LogicalKey::Character('a'), KeyState::Pressed),
+            (Key::Q, LogicalKey::Character('a'), KeyState::Released),
+        ]
+    );
+
+    let homes = Arc::new(Mutex::new(0));
+    window.bind_key(
+        LogicalKey::Named(Key::Home),
+        Action::Custom(Box::new({
+            let homes = homes.clone();
+            move |_| *homes.lock().unwrap() += 1
+        })),
+    );
+    compositor.key(Key::Numpad7, true);
+    compositor.key(Key::Numpad7, false);
+    compositor.key(Key::Home, true);
+    compositor.key(Key::Home, false);
+    compositor.locked_modifiers(1 << 4);
+    compositor.key(Key::Numpad7, true);
+    let mut events = Vec::new();
+    while key_events(&events).is_empty() {
+        events.extend(compositor.run_until(&mut window, |_, _| true));
+    }
+    assert_eq!(*homes.lock().unwrap(), 2);
+    assert_eq!(
+        key_events(&events),
+        [(Key::Numpad7, LogicalKey::Character('7'), KeyState::Pressed)]
+    );
+}
+
+//A seat going away takes its keyboard, and the focus it had, with it.
+#[test]
+fn seat_removal_releases_its_devices() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.keyboard_enter();
+    compositor.run_until(&mut window, |window, _| window.has_keyboard_focus());
+    assert_eq!(window.seats(), [SEAT.into()]);
+
+    compositor.remove_global("wl_seat");
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_seat", "release") == 1
+    });
+    assert_eq!(count(&compositor.requests(), "wl_keyboard", "release"), 1);
+    assert!(!window.has_keyboard_focus());
+    assert!(window.seats().is_empty());
+}
+
+#[test]
+fn close_stops_the_window() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.close();
+    compositor.run_until(&mut window, |window, _| !window.is_running());
+}
+
+//Role objects go before the surface they belong to.
+#[test]
+fn teardown_order() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| window.is_configured());
+
+    drop(window);
+    compositor.wait_for("wl_surface", "destroy", 1);
+
+    let requests = compositor.requests();
+    let toplevel = position(&requests, "xdg_toplevel", "destroy");
+    let xdg_surface = position(&requests, "xdg_surface", "destroy");
+    let surface = position(&requests, "wl_surface", "destroy");
+    assert!(toplevel < xdg_surface && xdg_surface < surface);
+}
+
+//Child windows get their parent before their initial commit, and lose it before the parent's
+//toplevel goes: a utility window its whole toplevel, a normal child window only the parent.
+#[test]
+fn child_windows_follow_their_parent_in_order() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| window.is_configured());
+
+    let utility_options = WindowOptions {
+        role: WindowRole::Utility,
+        ..WindowOptions::default()
+    };
+    let mut utility = window.child_window(utility_options);
+    let mut child = window.child_window(WindowOptions::default());
+    for driver in [&mut utility, &mut child] {
+        let commits = count(&compositor.requests(), "wl_surface", "commit");
+        compositor.run_until(driver, |_, requests| {
+            count(requests, "wl_surface", "commit") > commits
+        });
+    }
+
+    //In the order the windows were made: the parent, the utility window, the child window.
+    let surfaces: Vec<_> = compositor
+        .requests_of("xdg_wm_base", "get_xdg_surface")
+        .into_iter()
+        .map(|args| args[1].clone())
+        .collect();
+    let toplevels: Vec<_> = compositor
+        .requests_of("xdg_surface", "get_toplevel")
+        .into_iter()
+        .map(|args| match args[0] {
+            Arg::NewId(id) => id,
+            ref arg => panic!("get_toplevel with {arg:?}"),
+        })
+        .collect();
+    let requests = compositor.requests();
+    for (surface, toplevel) in surfaces[1..].iter().zip(&toplevels[1..]) {
+        let Arg::Object(surface) = *surface else {
+            panic!("get_xdg_surface with {surface:?}");
+        };
+        let parent = Arg::Object(toplevels[0]);
+        assert!(
+            position_on(&requests, *toplevel, "set_parent", Some(&parent))
+                < position_on(&requests, surface, "commit", None)
+        );
+    }
+
+    window.close();
+    assert_eq!(utility.lifecycle(), Lifecycle::Closed);
+    assert!(child.is_running());
+    compositor.run_until(&mut utility, |window, _| !window.is_running());
+    drop(utility);
+    drop(child);
+    drop(window);
+    compositor.settle();
+
+    let requests = compositor.requests();
+    let parent_gone = position_on(&requests, toplevels[0], "destroy", None);
+    assert!(position_on(&requests, toplevels[1], "destroy", None) < parent_gone);
+    let unparented = position_on(&requests, toplevels[2], "set_parent", Some(&Arg::Object(0)));
+    assert!(unparented < parent_gone);
+}
+
+//A window dropped from a connection that stays leaves nothing of its own behind: the globals
+//without a destructor and those shared by nothing else, that's all.
+#[test]
+fn a_dropped_window_leaves_no_objects() {
+    let (compositor, connection) = TestCompositor::new();
+    let mut window = Window::with_connection(connection.clone(), WindowOptions::default());
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "commit") >= 1
+            && count(requests, "wl_seat", "get_pointer") >= 1
+    });
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| window.is_configured());
+    compositor.keyboard_enter();
+    compositor.pointer_enter(10.0, 10.0);
+    window.poll_events();
+
+    drop(window);
+    compositor.wait_for("wl_fixes", "destroy", 1);
+    assert_eq!(
+        compositor.requests_of("wl_fixes", "destroy_registry").len(),
+        1
+    );
+    //Frame callbacks this compositor never answers stay too, they're its to destroy.
+    let mut live = compositor.live_objects();
+    live.retain(|interface| *interface != "wl_callback");
+    assert_eq!(live, TEARDOWN_BASELINE, "left behind by the window");
+    drop(connection);
+}
+
+#[test]
+fn compositor_going_away_is_reported() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    drop(compositor);
+
+    let mut events = Vec::new();
+    while window.is_running() {
+        events.extend(window.pump_events());
+    }
+    assert_eq!(events, [WindowEvent::ConnectionLost]);
+    assert!(!window.is_connected());
+    //Reported once, later dispatches don't block on the dead socket either.
+    assert_eq!(window.pump_events(), []);
+}
+
+//A protocol error comes back decoded, with what the object was and what we last sent on it.
+#[test]
+fn protocol_errors_are_decoded() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    let serial = compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| window.is_configured());
+    compositor.post_error("xdg_surface", 4, "no such serial");
+
+    //Through the external loop, which returns it.
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let err = loop {
+        assert!(std::time::Instant::now() < deadline, "the error never came");
+        if let Err(err) = window
+            .prepare_read()
+            .and_then(|()| window.read_and_dispatch())
+        {
+            break err;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    assert_eq!(window.take_events(), [WindowEvent::ConnectionLost]);
+    let info = window.protocol_error().unwrap().clone();
+    assert_eq!(err, WindowError::Protocol(info.clone()));
+    assert_eq!((info.interface.as_str(), info.code), ("xdg_surface", 4));
+    assert_eq!(
+        info.meaning,
+        Some("Invalid serial number when acking a configure event")
+    );
+    assert_eq!(info.role.as_deref(), Some("main window"));
+    //Empty through libwayland, which only prints it.
+    assert!(["", "no such serial"].contains(&info.message.as_str()));
+    assert_eq!(
+        info.recent_requests.last().unwrap(),
+        &format!("ack_configure({serial})")
+    );
+    assert!(
+        WindowError::Protocol(info)
+            .to_string()
+            .contains("(object: main window, xdg_surface@")
+    );
+}
+
+//WAYLAND_SOCKET style: the window gets an already connected fd.
+//A ping that arrives while the application is busy waits for it, and the watchdog says so.
+#[test]
+fn slow_frames_delay_pongs() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    window.set_watchdog(Some(Duration::from_millis(50)));
+    window.poll_events();
+
+    let serial = compositor.ping();
+    //A draw taking too long.
+    std::thread::sleep(Duration::from_millis(100));
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_wm_base", "pong") >= 1
+    });
+    assert_eq!(
+        compositor.requests_of("xdg_wm_base", "pong"),
+        [vec![Arg::Uint(serial)]]
+    );
+    assert!(events.iter().any(|event| matches!(
+        event,
+        WindowEvent::SlowFrame { busy, cause: SlowFrameCause::Application }
+            if *busy >= Duration::from_millis(100)
+    )));
+    assert_eq!(window.pings_answered(), 1);
+    assert!(window.last_ping_latency().unwrap() >= Duration::from_millis(100));
+}
+
+#[test]
+fn connect_through_fd() {
+    let (compositor, socket) = TestCompositor::with_socket();
+    let mut window = Window::connect(
+        ConnectOptions::socket_fd(socket.into()),
+        WindowOptions::default(),
+    )
+    .unwrap();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "commit") >= 1
+    });
+}
+
+//Without xdg_wm_base there will never be a configure: connect says so instead of handing out a
+//window that waits forever, after giving the compositor a few roundtrips.
+#[test]
+fn connect_names_missing_globals() {
+    let (_compositor, socket) = TestCompositor::without_globals(&["xdg_wm_base"]);
+    let Err(err) = Window::connect(
+        ConnectOptions::socket_fd(socket.into()),
+        WindowOptions::default(),
+    ) else {
+        panic!("connected to a compositor without xdg_wm_base");
+    };
+    let WindowError::MissingGlobals {
+        ref missing,
+        ref found,
+    } = err
+    else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(*missing, ["xdg_wm_base"]);
+    assert!(found.iter().any(|interface| interface == "wl_compositor"));
+    assert!(err.to_string().contains("xdg-shell"), "{err}");
+}
+
+#[test]
+fn connect_errors_name_the_socket() {
+    let Err(err) = ConnectOptions::socket_path("/nonexistent/wayland-9").connect() else {
+        panic!("connected to a socket that doesn't exist");
+    };
+    assert!(err.to_string().contains("/nonexistent/wayland-9"), "{err}");
+}
+
+//The input region is sent with the surface size it was worked out for, again when that changes,
+//and a null region puts the whole window back.
+#[test]
+fn input_region_follows_the_surface_size() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    //Nothing to send for the default, all of the surface.
+    assert!(
+        compositor
+            .requests_of("wl_surface", "set_input_region")
+            .is_empty()
+    );
+
+    window.set_input_region_with(|width, height| {
+        vec![Rect::new(0, height as i32 - 10, width as i32, 10)]
+    });
+    window.set_buffer_transform(Transform::Rotate90).unwrap();
+    window.set_input_region(None);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "set_input_region") >= 3
+    });
+
+    assert_eq!(
+        compositor.requests_of("wl_region", "add"),
+        [
+            vec![Arg::Int(0), Arg::Int(230), Arg::Int(320), Arg::Int(10)],
+            vec![Arg::Int(0), Arg::Int(310), Arg::Int(240), Arg::Int(10)],
+        ]
+    );
+    let regions = compositor.requests_of("wl_surface", "set_input_region");
+    assert!(matches!(regions[0][..], [Arg::Object(id)] if id != 0));
+    assert_eq!(regions[2], [Arg::Object(0)]);
+    assert_eq!(count(&compositor.requests(), "wl_region", "destroy"), 2);
+}
+
+//A transaction without a buffer sends its regions with one commit after them; a dropped one
+//sends nothing.
+#[test]
+fn surface_transactions_commit_once() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    let commits = count(&compositor.requests(), "wl_surface", "commit");
+
+    let mut dropped = window.begin_frame();
+    dropped.set_opaque(Some(&[Rect::new(0, 0, 4, 4)]));
+    drop(dropped);
+
+    let mut frame = window.begin_frame();
+    frame
+        .set_opaque(Some(&[Rect::new(0, 0, 320, 240)]))
+        .set_input_region(Some(&[Rect::new(0, 0, 320, 32)]));
+    frame.commit().unwrap();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "commit") > commits
+    });
+    let requests = compositor.requests();
+    assert_eq!(count(&requests, "wl_surface", "commit"), commits + 1);
+    assert_eq!(count(&requests, "wl_surface", "set_opaque_region"), 1);
+    assert_eq!(
+        compositor.requests_of("wl_region", "add"),
+        [
+            vec![Arg::Int(0), Arg::Int(0), Arg::Int(320), Arg::Int(240)],
+            vec![Arg::Int(0), Arg::Int(0), Arg::Int(320), Arg::Int(32)],
+        ]
+    );
+    let commit = requests.iter().rposition(|r| r.is("wl_surface", "commit"));
+    assert!(position(&requests, "wl_surface", "set_input_region") < commit.unwrap());
+    assert_eq!(count(&requests, "wl_surface", "attach"), 1);
+}
+
+//The snapshot is taken with each dispatch: the configure's states, the keyboard focus and the
+//output the surface entered all show up together.
+#[test]
+fn state_snapshot_follows_the_compositor() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    let state = window.state();
+    assert!(!state.configured && !state.maximized && !state.keyboard_focus);
+    assert_eq!((state.size, state.scale), ((320, 240), 1));
+    assert!(state.outputs.is_empty());
+
+    //Maximized and activated.
+    compositor.configure(0, 0, &[1, 4]);
+    compositor.keyboard_enter();
+    compositor.surface_enter();
+    compositor.run_until(&mut window, |window, _| {
+        let state = window.state();
+        state.configured && state.keyboard_focus && !state.outputs.is_empty()
+    });
+
+    let state = window.state();
+    assert!(state.maximized && state.activated && !state.fullscreen && !state.suspended);
+    assert_eq!(state.decorations, Decorations::ServerSide);
+    assert_eq!(state.scale, 2);
+    assert_eq!(state.outputs.len(), 1);
+    assert_eq!(state.outputs[0].name.as_deref(), Some(OUTPUT));
+    assert_eq!(window.scale_factor(), 2);
+
+    //Until the next dispatch it stays what it was.
+    window
+        .set_decorations(Decorations::ClientSide {
+            shadow: Margins::uniform(10),
+        })
+        .unwrap();
+    assert_eq!(window.state(), state);
+    assert_eq!(window.size(), (300, 220));
+    window.poll_events();
+    assert_eq!(window.state().size, (300, 220));
+}
+
+//Frames drawn on another thread are committed by the dispatching one. The test compositor never
+//fires frame callbacks, so after the first frame is shown and a second one waits, the render
+//thread stays blocked until the window goes away.
+#[test]
+fn render_thread_frames_are_paced_by_frame_callbacks() {
+    let options = WindowOptions {
+        size: (4, 3),
+        ..WindowOptions::default()
+    };
+    let (compositor, window) = start_window(options);
+    let (mut window, mut render) = window.split().unwrap();
+    assert_eq!(render.size(), (4, 3));
+    let renderer = std::thread::spawn(move || {
+        let mut frames = 0;
+        while render
+            .render(|canvas| canvas.clear(Color::premultiplied(0x20, 0x40, 0x60, 0x80)))
+            .is_ok()
+        {
+            frames += 1;
+        }
+        frames
+    });
+
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, requests| {
+        count(requests, "wl_surface", "frame") >= 1
+            && window.capture_to_vec().unwrap() == [0x40, 0x80, 0xBF, 0x80].repeat(4 * 3)
+    });
+    assert_eq!(
+        count(&compositor.requests(), "wl_surface", "attach"),
+        2,
+        "the gradient of the first configure, then the frame"
+    );
+
+    drop(window);
+    assert_eq!(renderer.join().unwrap(), 2);
+}
+
+//In Mailbox every frame is committed as it comes, callback or not: without any from the test
+//compositor, each frame but the last is replaced, by a newer commit or before it's committed.
+#[test]
+fn mailbox_frames_replace_each_other() {
+    let options = WindowOptions {
+        size: (4, 3),
+        ..WindowOptions::default()
+    };
+    let (compositor, window) = start_window(options);
+    let (mut window, mut render) = window.split().unwrap();
+    window.set_present_mode(PresentMode::Mailbox);
+    let renderer = std::thread::spawn(move || {
+        for red in 1..=5 {
+            render
+                .render(|canvas| canvas.clear(Color::opaque(red, 0, 0)))
+                .unwrap();
+        }
+    });
+
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| {
+        window.frame_stats().replaced == 4
+            && window.capture_to_vec().unwrap() == [5, 0, 0, 0xFF].repeat(4 * 3)
+    });
+    let stats = window.frame_stats();
+    assert!((1..=5).contains(&stats.presented), "{stats:?}");
+    renderer.join().unwrap();
+}
+
+//A wheel detent and a finger scroll come out as pixels, each as one event for its frame.
+#[test]
+fn scrolling_is_in_pixels() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.pointer_enter(10.0, 10.0);
+    compositor.wheel(1);
+    compositor.finger_scroll(-1.5, 4.0);
+    let scrolls = |events: Vec<WindowEvent>| -> Vec<_> {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                WindowEvent::Scroll { dx, dy, source, .. } => Some((dx, dy, source)),
+                _ => None,
+            })
+            .collect()
+    };
+    let events = compositor.run_until(&mut window, |_, _| true);
+    assert_eq!(
+        scrolls(events),
+        [
+            (0.0, 48.0, ScrollSource::Wheel),
+            (-1.5, 4.0, ScrollSource::Finger)
+        ]
+    );
+
+    window.set_scroll_config(ScrollConfig {
+        invert_vertical: true,
+        line_height_px: 10.0,
+        discrete_multiplier: 1.0,
+        ..ScrollConfig::default()
+    });
+    compositor.wheel(2);
+    let events = compositor.run_until(&mut window, |_, _| true);
+    assert_eq!(scrolls(events), [(0.0, -20.0, ScrollSource::Wheel)]);
+}
+
+//Motion in one batch comes out as one PointerMoved, but never across a button.
+#[test]
+fn motion_is_coalesced_between_buttons() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.pointer_enter(10.0, 10.0);
+    for x in 11..=15 {
+        compositor.pointer_motion(f64::from(x), 10.0);
+    }
+    compositor.pointer_button(0x110, true);
+    for x in 16..=18 {
+        compositor.pointer_motion(f64::from(x), 10.0);
+    }
+    let pointer = |events: Vec<WindowEvent>| -> Vec<_> {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                WindowEvent::PointerMoved { x, dx, samples, .. } => Some(Some((x, dx, samples))),
+                WindowEvent::PointerButton { .. } => Some(None),
+                _ => None,
+            })
+            .collect()
+    };
+    let events = compositor.run_until(&mut window, |_, _| true);
+    assert_eq!(
+        pointer(events),
+        [Some((15.0, 5.0, 5)), None, Some((18.0, 3.0, 3))]
+    );
+
+    window.set_motion_coalescing(MotionCoalescing::Off);
+    compositor.pointer_motion(20.0, 10.0);
+    compositor.pointer_motion(21.0, 10.0);
+    let events = compositor.run_until(&mut window, |_, _| true);
+    assert_eq!(
+        pointer(events),
+        [Some((20.0, 2.0, 1)), Some((21.0, 1.0, 1))]
+    );
+}
+
+//An external loop that dispatches but never takes its events: past the capacity the oldest
+//motion goes, the enter and the focus stay, with one marker for the dropped.
+#[test]
+fn untaken_events_are_bounded() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    window.set_event_capacity(16);
+    compositor.pointer_enter(0.0, 0.0);
+    for x in 1..=200 {
+        compositor.pointer_motion(f64::from(x), 0.0);
+    }
+    compositor.keyboard_enter();
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while window.events_dropped() < 187 {
+        assert!(std::time::Instant::now() < deadline, "events never came");
+        window.prepare_read().unwrap();
+        window.flush().unwrap();
+        window.read_and_dispatch().unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    //The 13 motions left are coalesced into one.
+    let events = window.take_events();
+    assert_eq!(window.events_dropped(), 187);
+    assert!(matches!(events[0], WindowEvent::PointerEntered { .. }));
+    assert_eq!(events[1], WindowEvent::EventsDropped { count: 187 });
+    assert!(matches!(
+        events[2],
+        WindowEvent::PointerMoved {
+            x: 200.0,
+            samples: 13,
+            ..
+        }
+    ));
+    assert!(matches!(events[3], WindowEvent::FocusGained { .. }));
+}
+
+//A probe sees the test compositor's globals without making a window, the window the same.
+#[test]
+fn capabilities_list_the_globals() {
+    let (_compositor, socket) = TestCompositor::with_socket();
+    let probed = Capabilities::probe(ConnectOptions::socket_fd(socket.into())).unwrap();
+    assert_eq!(probed.compositor, Some(6));
+    assert!(probed.has_required() && probed.shm.is_some() && probed.seat.is_some());
+    assert_eq!(probed.layer_shell, None);
+    assert!(probed.others.is_empty(), "{:?}", probed.others);
+
+    let (_compositor, window) = start(WindowOptions::default());
+    assert_eq!(window.capabilities(), probed);
+}
+
+//A protocol the window doesn't wrap, on a queue of the test's own: here wl_output, which the
+//window binds too, bound a second time with Dispatch impls on the test's state.
+#[test]
+fn extra_protocols_dispatch_on_their_own_queue() {
+    use wayland_client::{
+        Connection, Dispatch, Proxy, QueueHandle,
+        globals::{GlobalListContents, registry_queue_init},
+        protocol::{
+            wl_output::{self, WlOutput},
+            wl_registry::{self, WlRegistry},
+        },
+    };
+
+    #[derive(Default)]
+    struct Extra {
+        name: Option<String>,
+    }
+
+    impl Dispatch<WlRegistry, GlobalListContents> for Extra {
+        fn event(
+            _: &mut Self,
+            _: &WlRegistry,
+            _: wl_registry::Event,
+            _: &GlobalListContents,
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<WlOutput, ()> for Extra {
+        fn event(
+            state: &mut Self,
+            _: &WlOutput,
+            event: wl_output::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+            if let wl_output::Event::Name { name } = event {
+                state.name = Some(name);
+            }
+        }
+    }
+
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "attach") >= 1
+    });
+    assert!(window.wl_compositor().is_some() && window.wl_shm().is_some());
+    assert!(window.xdg_surface().is_some() && window.xdg_toplevel().is_some());
+    assert_eq!(
+        window.wl_surface().unwrap().id().interface().name,
+        "wl_surface"
+    );
+    let seat = window.seats()[0].clone();
+    assert!(window.wl_seat(&seat).is_some());
+    assert!(window.wl_seat("no such seat").is_none());
+
+    let (globals, queue) = registry_queue_init::<Extra>(window.connection()).unwrap();
+    let output: WlOutput = globals.bind(&queue.handle(), 4..=4, ()).unwrap();
+    //wayland-client's GlobalList can't destroy its registry.
+    window.leaves(&["wl_registry"]);
+    //The window's dispatch reads the socket, the events wait in our queue.
+    let extra = std::cell::RefCell::new((queue, Extra::default()));
+    compositor.run_until(&mut window, |_, _| {
+        let (queue, extra) = &mut *extra.borrow_mut();
+        queue.dispatch_pending(extra).unwrap();
+        extra.name.is_some()
+    });
+    assert_eq!(extra.borrow().1.name.as_deref(), Some(OUTPUT));
+    output.release();
+}
+
+//The refresh interval comes from the mode of the output the surface is on, and follows it when
+//the mode changes. The test compositor has no wp_presentation to take precedence.
+#[test]
+fn refresh_interval_follows_the_output_mode() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| window.is_configured());
+    assert_eq!(window.current_refresh_interval(), None);
+    assert_eq!(window.refresh_source(), None);
+
+    compositor.surface_enter();
+    compositor.run_until(&mut window, |window, _| {
+        window.current_refresh_interval().is_some()
+    });
+    assert_eq!(
+        window.current_refresh_interval(),
+        Some(Duration::from_nanos(16_666_666))
+    );
+    assert_eq!(window.refresh_source(), Some(RefreshSource::Output));
+    assert_eq!(window.state().outputs[0].refresh_mhz, Some(60000));
+
+    compositor.output_mode(144000);
+    compositor.run_until(&mut window, |window, _| {
+        window.current_refresh_interval() == Some(Duration::from_nanos(6_944_444))
+    });
+
+    //A virtual output without a refresh rate.
+    compositor.output_mode(0);
+    compositor.run_until(&mut window, |window, _| {
+        window.current_refresh_interval().is_none()
+    });
+}
+
+//The HDR description goes out through the parametric creator and only reaches the surface once
+//the compositor made it. The compositor's own preference comes through the feedback object.
+#[cfg(feature = "color-management")]
+#[test]
+fn color_description_is_set_once_ready() {
+    use simple_wayland_window::{ColorDescription, Primaries, RenderIntent, TransferFunction};
+
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.run_until(&mut window, |_, requests| {
+        count(
+            requests,
+            "wp_color_management_surface_feedback_v1",
+            "get_preferred_parametric",
+        ) == 1
+    });
+    let capabilities = window.color_capabilities().unwrap();
+    assert!(
+        capabilities
+            .transfer_functions
+            .contains(&TransferFunction::St2084Pq)
+    );
+
+    let p3 = ColorDescription::named(Primaries::DisplayP3, TransferFunction::Srgb);
+    assert_eq!(
+        window.set_color_description(Some(p3), RenderIntent::Perceptual),
+        Err(WindowError::Unsupported("these named primaries"))
+    );
+    window
+        .set_color_description(Some(ColorDescription::BT2100_PQ), RenderIntent::Perceptual)
+        .unwrap();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wp_image_description_creator_params_v1", "create") == 1
+    });
+    let creator = |name| compositor.requests_of("wp_image_description_creator_params_v1", name);
+    assert_eq!(creator("set_tf_named"), [vec![Arg::Uint(11)]]);
+    assert_eq!(creator("set_primaries_named"), [vec![Arg::Uint(6)]]);
+    //Not ready yet, setting it would be a protocol error.
+    assert_eq!(
+        count(
+            &compositor.requests(),
+            "wp_color_management_surface_v1",
+            "set_image_description"
+        ),
+        0
+    );
+
+    compositor.image_description_ready(1);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wl_surface", "commit") >= 2
+            && count(
+                requests,
+                "wp_color_management_surface_v1",
+                "set_image_description",
+            ) == 1
+    });
+    let requests = compositor.requests();
+    let set = position(
+        &requests,
+        "wp_color_management_surface_v1",
+        "set_image_description",
+    );
+    assert!(requests[set..].iter().any(|r| r.is("wl_surface", "commit")));
+
+    //The window moved to an HDR output, say.
+    compositor.preferred_changed(2);
+    compositor.run_until(&mut window, |_, requests| {
+        count(
+            requests,
+            "wp_color_management_surface_feedback_v1",
+            "get_preferred_parametric",
+        ) == 2
+    });
+    compositor.image_description_ready(2);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wp_image_description_v1", "get_information") == 1
+    });
+    compositor.hdr_image_description_info();
+    let events = compositor.run_until(&mut window, |window, _| window.preferred_color().is_some());
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::PreferredColorChanged(_)))
+    );
+    let preferred = window.preferred_color().unwrap();
+    assert_eq!(preferred.named_primaries, Some(Primaries::Bt2020));
+    assert_eq!(
+        preferred.transfer_function,
+        Some(TransferFunction::St2084Pq)
+    );
+    assert_eq!(preferred.luminances.unwrap().min, 0.005);
+    assert_eq!(preferred.target_luminance, Some((0.005, 1000)));
+}
+
+//Every on_idle timeout is a notification of its own. Idled and resumed come back as events and
+//callback calls, and a cancelled timeout's notification is destroyed.
+#[test]
+fn idle_timeouts_notify_and_cancel() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    assert!(window.idle_notify_supported());
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let short = window.on_idle(Duration::from_secs(5), |_, _| {});
+    let long = window.on_idle(Duration::from_secs(300), {
+        let calls = calls.clone();
+        move |_, idle| calls.lock().unwrap().push(idle)
+    });
+    assert_ne!(short, long);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "ext_idle_notifier_v1", "get_idle_notification") == 2
+    });
+    let timeouts: Vec<_> = compositor
+        .requests_of("ext_idle_notifier_v1", "get_idle_notification")
+        .into_iter()
+        .map(|args| args[1].clone())
+        .collect();
+    assert_eq!(timeouts, [Arg::Uint(5000), Arg::Uint(300_000)]);
+
+    //The newest notification is the long timeout's.
+    compositor.idle(true);
+    let events = compositor.run_until(&mut window, |window, _| window.is_user_idle());
+    assert!(events.contains(&WindowEvent::UserIdle {
+        timeout: Duration::from_secs(300)
+    }));
+    assert_eq!(*calls.lock().unwrap(), [true]);
+
+    window.cancel_idle(short);
+    compositor.idle(false);
+    let events = compositor.run_until(&mut window, |window, requests| {
+        !window.is_user_idle() && count(requests, "ext_idle_notification_v1", "destroy") == 1
+    });
+    assert!(events.contains(&WindowEvent::UserActive {
+        timeout: Duration::from_secs(300)
+    }));
+    assert_eq!(*calls.lock().unwrap(), [true, false]);
+}
+
+//With client side decorations a press on the title bar moves the window once dragged and one on
+//the border resizes it, neither reaching the application, while the client area gets its presses
+//as usual. The cursor follows the region under the pointer.
+#[test]
+fn decorations_take_their_presses() {
+    const BTN_LEFT: u32 = 0x110;
+    let options = WindowOptions {
+        decorations: Decorations::ClientSide {
+            shadow: Margins::uniform(10),
+        },
+        ..WindowOptions::default()
+    };
+    let (compositor, mut window) = start(options);
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_surface", "ack_configure") == 1
+    });
+    assert!(window.cursor_shape_supported());
+    assert_eq!(window.hit_test(110.0, 20.0), HitRegion::TitleBar);
+
+    compositor.pointer_enter(110.0, 150.0);
+    compositor.pointer_button(BTN_LEFT, true);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "wp_cursor_shape_device_v1", "set_shape") == 1
+    });
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::PointerButton { pressed: true, .. }))
+    );
+    compositor.pointer_button(BTN_LEFT, false);
+
+    compositor.pointer_motion(110.0, 20.0);
+    let serial = compositor.pointer_button(BTN_LEFT, true);
+    //Within the drag threshold it could still be a click.
+    compositor.pointer_motion(114.0, 22.0);
+    //The pong says the motion was dispatched.
+    compositor.ping();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_wm_base", "pong") == 1
+    });
+    assert_eq!(count(&compositor.requests(), "xdg_toplevel", "move"), 0);
+    compositor.pointer_motion(130.0, 22.0);
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_toplevel", "move") == 1
+    });
+    //The client area's release comes through, the title bar's press doesn't.
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::PointerButton { pressed: true, .. }))
+    );
+    let moves = compositor.requests_of("xdg_toplevel", "move");
+    assert_eq!(moves[0][1], Arg::Uint(serial));
+    compositor.pointer_button(BTN_LEFT, false);
+
+    //5 pixels left of the window, in the shadow: the left border.
+    compositor.pointer_motion(5.0, 150.0);
+    compositor.pointer_button(BTN_LEFT, true);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_toplevel", "resize") == 1
+    });
+    let shapes = compositor.requests_of("wp_cursor_shape_device_v1", "set_shape");
+    //default, then w_resize (the title bar has the default cursor as well).
+    assert_eq!(shapes.len(), 2);
+    assert_eq!(shapes[1][1], Arg::Uint(25));
+    let resizes = compositor.requests_of("xdg_toplevel", "resize");
+    assert_eq!(resizes[0][2], Arg::Uint(4));
+}
+
+//The release of a button that started a move happens wherever the pointer is then, usually not on
+//our surface. The application still sees its press released, and the next click whole.
+#[test]
+fn release_after_begin_move_happens_outside() {
+    const BTN_LEFT: u32 = 0x110;
+    let options = WindowOptions {
+        decorations: Decorations::ClientSide {
+            shadow: Margins::uniform(0),
+        },
+        ..WindowOptions::default()
+    };
+    let (compositor, mut window) = start(options);
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_surface", "ack_configure") == 1
+    });
+    let buttons = |events: &[WindowEvent]| -> Vec<bool> {
+        events
+            .iter()
+            .filter_map(|event| match *event {
+                WindowEvent::PointerButton { pressed, .. } => Some(pressed),
+                _ => None,
+            })
+            .collect()
+    };
+
+    //A title bar of the application's own, in the client area.
+    compositor.pointer_enter(100.0, 150.0);
+    let serial = compositor.pointer_button(BTN_LEFT, true);
+    let events = compositor.run_until(&mut window, |window, _| {
+        window.latest_serial(SerialKind::PointerButton) == Some(serial)
+    });
+    assert_eq!(buttons(&events), [true]);
+    window.begin_move().unwrap();
+    assert_eq!(window.input_routing(), InputRouting::MovingOrResizing);
+    compositor.wait_for("xdg_toplevel", "move", 1);
+    let moves = compositor.requests_of("xdg_toplevel", "move");
+    assert_eq!(moves[0][1], Arg::Uint(serial));
+
+    //Escape doesn't quit mid-drag (the compositor may cancel the move with it).
+    compositor.keyboard_enter();
+    compositor.key(Key::Escape, true);
+    compositor.key(Key::Escape, false);
+    //The pointer leaves with the move, the release happens elsewhere, it comes back.
+    compositor.pointer_leave();
+    compositor.pointer_enter(100.0, 150.0);
+    compositor.pointer_button(BTN_LEFT, true);
+    compositor.pointer_button(BTN_LEFT, false);
+    //The pong says everything before it was dispatched.
+    compositor.ping();
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_wm_base", "pong") == 1
+    });
+    assert_eq!(window.input_routing(), InputRouting::Normal);
+    assert!(window.is_running());
+    //The made up release, then the new click.
+    assert_eq!(buttons(&events), [false, true, false]);
+
+    //The same from the decorations, on a compositor that sends neither Leave nor the release:
+    //the title bar's press is forgotten with the next motion, the click after it isn't eaten.
+    compositor.pointer_motion(100.0, 10.0);
+    compositor.pointer_button(BTN_LEFT, true);
+    compositor.pointer_motion(140.0, 10.0);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_toplevel", "move") == 2
+    });
+    compositor.pointer_motion(100.0, 150.0);
+    compositor.pointer_button(BTN_LEFT, true);
+    compositor.pointer_button(BTN_LEFT, false);
+    compositor.ping();
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_wm_base", "pong") == 2
+    });
+    assert_eq!(buttons(&events), [true, false]);
+}
+
+//Two clicks on the title bar maximize the window, two more unmaximize it.
+#[test]
+fn title_bar_double_click_maximizes() {
+    const BTN_LEFT: u32 = 0x110;
+    let options = WindowOptions {
+        decorations: Decorations::ClientSide {
+            shadow: Margins::uniform(0),
+        },
+        ..WindowOptions::default()
+    };
+    let (compositor, mut window) = start(options);
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_surface", "ack_configure") == 1
+    });
+    let double_click = || {
+        compositor.pointer_button(BTN_LEFT, true);
+        compositor.pointer_button(BTN_LEFT, false);
+        compositor.pointer_button(BTN_LEFT, true);
+        compositor.pointer_button(BTN_LEFT, false);
+    };
+    compositor.pointer_enter(100.0, 10.0);
+    double_click();
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_toplevel", "set_maximized") == 1
+    });
+    //xdg_toplevel.State::Maximized
+    compositor.configure(800, 600, &[1]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_surface", "ack_configure") == 2
+    });
+    double_click();
+    let events = compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_toplevel", "unset_maximized") == 1
+    });
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::PointerButton { .. }))
+    );
+    assert_eq!(count(&compositor.requests(), "xdg_toplevel", "move"), 0);
+}
+
+//A recording reads back from its text, and its inputs fed to a new window get the same acks and
+//commits out of it, two configures of one dispatch included.
+#[cfg(feature = "record")]
+#[test]
+fn recordings_replay_to_the_same_commits() {
+    use simple_wayland_window::{Recorded, Recording};
+
+    let (compositor, mut window) = start(WindowOptions::default());
+    window.start_recording();
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_surface", "ack_configure") == 1
+    });
+    compositor.keyboard_enter();
+    compositor.key(Key::A, true);
+    compositor.configure(400, 300, &[]);
+    compositor.configure(500, 350, &[1]);
+    compositor.run_until(&mut window, |_, requests| {
+        count(requests, "xdg_surface", "ack_configure") == 2
+    });
+    let recording = window.stop_recording();
+    let acks = recording
+        .effects()
+        .filter(|&effect| *effect == Recorded::AckConfigure)
+        .count();
+    assert_eq!(acks, 2);
+    assert!(recording.effects().any(|effect| *effect
+        == Recorded::Commit {
+            width: 500,
+            height: 350
+        }));
+
+    let read: Recording = recording.to_string().parse().unwrap();
+    assert_eq!(read, recording);
+
+    let (compositor, mut window) = start(WindowOptions::default());
+    let replayed = compositor.replay(&mut window, &read);
+    assert!(replayed.effects().eq(read.effects()));
+}
+
+//Relative motion on a side queue comes added up, whichever way it's dispatched, and on a
+//relative pointer made anew on that queue.
+#[test]
+fn isolated_relative_motion_adds_up() {
+    for dispatch in [SideDispatch::AfterMainQueue, SideDispatch::Thread] {
+        let (compositor, mut window) = start(WindowOptions::default());
+        compositor.run_until(&mut window, |_, requests| {
+            count(
+                requests,
+                "zwp_relative_pointer_manager_v1",
+                "get_relative_pointer",
+            ) == 1
+        });
+        window.isolate_relative_pointer(dispatch).unwrap();
+        assert_eq!(window.relative_pointer_isolation(), Some(dispatch));
+        compositor.run_until(&mut window, |_, requests| {
+            count(
+                requests,
+                "zwp_relative_pointer_manager_v1",
+                "get_relative_pointer",
+            ) == 2
+        });
+        assert_eq!(
+            count(&compositor.requests(), "zwp_relative_pointer_v1", "destroy"),
+            1
+        );
+
+        for _ in 0..10 {
+            compositor.relative_motion(1.5, -2.0);
+        }
+        let mut motion = Vec::new();
+        let total = |motion: &[(f64, f64)]| motion.iter().map(|(dx, _)| dx).sum::<f64>();
+        let start = std::time::Instant::now();
+        while total(&motion) < 15.0 {
+            assert!(start.elapsed() < Duration::from_secs(5), "{motion:?}");
+            motion.extend(
+                compositor
+                    .run_until(&mut window, |_, _| true)
+                    .into_iter()
+                    .filter_map(|event| match event {
+                        WindowEvent::RelativeMotion {
+                            seat,
+                            dx,
+                            dy,
+                            synthetic: false,
+                            ..
+                        } if &*seat == SEAT => Some((dx, dy)),
+                        _ => None,
+                    }),
+            );
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(total(&motion), 15.0);
+        assert_eq!(motion.iter().map(|(_, dy)| dy).sum::<f64>(), -20.0);
+        //Sums, not an event per delta.
+        assert!(motion.len() < 10, "{motion:?}");
+    }
+}
+
+//A command checking its XDG_ACTIVATION_TOKEN against `token`, unset for None. The variable we
+//were started with is set too: it must never reach the program.
+fn token_check(token: Option<&str>) -> std::process::Command {
+    let check = match token {
+        Some(token) => format!("test \"$XDG_ACTIVATION_TOKEN\" = {token}"),
+        None => "test -z \"$XDG_ACTIVATION_TOKEN\"".into(),
+    };
+    let mut command = std::process::Command::new("sh");
+    command
+        .args(["-c", &check])
+        .env("XDG_ACTIVATION_TOKEN", "ours, already spent");
+    command
+}
+
+//The token names the input event that asked for the program and our surface, and the program
+//gets it.
+#[test]
+fn spawning_hands_down_an_activation_token() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| window.is_configured());
+    compositor.keyboard_enter();
+    compositor.run_until(&mut window, |window, _| window.has_keyboard_focus());
+
+    let mut spawned = window
+        .spawn_with_activation(token_check(Some(ACTIVATION_TOKEN)))
+        .unwrap();
+    assert_eq!(spawned.token, Ok(ACTIVATION_TOKEN.to_owned()));
+    assert!(spawned.child.wait().unwrap().success());
+
+    let requests = compositor.requests();
+    let commit = position(&requests, "xdg_activation_token_v1", "commit");
+    assert!(position(&requests, "xdg_activation_token_v1", "set_serial") < commit);
+    assert!(position(&requests, "xdg_activation_token_v1", "set_surface") < commit);
+}
+
+//spawn_with_activation_then returns at once, the program starts from the dispatch that brings
+//the token.
+#[test]
+fn spawning_with_a_callback_waits_for_the_token() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    let spawned = Arc::new(Mutex::new(None));
+    let outcome = spawned.clone();
+    window.spawn_with_activation_then(token_check(Some(ACTIVATION_TOKEN)), move |_, result| {
+        *outcome.lock().unwrap() = Some(result);
+    });
+    assert!(spawned.lock().unwrap().is_none());
+
+    compositor.run_until(&mut window, |_, _| spawned.lock().unwrap().is_some());
+    let mut spawned = spawned.lock().unwrap().take().unwrap().unwrap();
+    assert_eq!(spawned.token, Ok(ACTIVATION_TOKEN.to_owned()));
+    assert!(spawned.child.wait().unwrap().success());
+}
+
+//A compositor that never answers: the program starts without a token after half a second.
+#[test]
+fn spawning_gives_up_on_a_late_token() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.withhold_activation_tokens();
+    let started = Instant::now();
+    let mut spawned = window.spawn_with_activation(token_check(None)).unwrap();
+    let waited = started.elapsed();
+    assert!(
+        waited >= Duration::from_millis(500) && waited < Duration::from_secs(2),
+        "{waited:?}"
+    );
+    assert!(
+        matches!(spawned.token, Err(WindowError::Connection(_))),
+        "{:?}",
+        spawned.token
+    );
+    assert!(spawned.child.wait().unwrap().success());
+    //The token object given up on is gone too.
+    window.flush().unwrap();
+    compositor.wait_for("xdg_activation_token_v1", "destroy", 1);
+}
+
+//Without xdg_activation_v1 the program starts anyway, without the variable, and the result says
+//why.
+#[test]
+fn spawning_without_activation_support() {
+    let (compositor, socket) = TestCompositor::without_globals(&["xdg_activation_v1"]);
+    let mut window = Window::connect(
+        ConnectOptions::socket_fd(socket.into()),
+        WindowOptions::default(),
+    )
+    .unwrap();
+    run_to_initial_commit(&compositor, &mut window);
+    let mut spawned = window.spawn_with_activation(token_check(None)).unwrap();
+    assert_eq!(
+        spawned.token,
+        Err(WindowError::Unsupported("xdg_activation_v1"))
+    );
+    assert!(spawned.child.wait().unwrap().success());
+}
+
+//Ctrl+Shift+F12 puts the overlay up, in a subsurface of its own that commits by itself, and takes
+//it down again.
+#[cfg(feature = "diagnostic-overlay")]
+#[test]
+fn diagnostic_overlay_toggles_with_its_binding() {
+    let (compositor, mut window) = start(WindowOptions::default());
+    compositor.configure(0, 0, &[]);
+    compositor.run_until(&mut window, |window, _| window.is_configured());
+    compositor.keyboard_enter();
+    for key in [Key::LeftCtrl, Key::LeftShift, Key::F12] {
+        compositor.key(key, true);
+    }
+    let events = compositor.run_until(&mut window, |window, _| window.diagnostic_overlay_shown());
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::Key { key: Key::F12, .. }))
+    );
+    compositor.wait_for("wl_subsurface", "set_desync", 1);
+    let requests = compositor.requests();
+    assert_eq!(count(&requests, "wl_subcompositor", "get_subsurface"), 1);
+    assert!(
+        position(&requests, "wl_subsurface", "set_desync")
+            < requests
+                .iter()
+                .rposition(|request| request.is("wl_surface", "commit"))
+                .unwrap()
+    );
+
+    compositor.key(Key::F12, false);
+    compositor.key(Key::F12, true);
+    compositor.run_until(&mut window, |window, _| !window.diagnostic_overlay_shown());
+    compositor.wait_for("wl_subsurface", "destroy", 1);
+    assert!(window.is_running());
+}